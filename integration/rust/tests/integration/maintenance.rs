@@ -0,0 +1,67 @@
+use rust::setup::connections_sqlx;
+use sqlx::{Executor, Pool, Postgres, Row};
+
+async fn count(conn: &Pool<Postgres>, shard: usize) -> i64 {
+    conn.fetch_one(
+        format!(
+            "/* pgdog_shard: {} */ SELECT COUNT(*)::BIGINT FROM test_maintenance",
+            shard
+        )
+        .as_str(),
+    )
+    .await
+    .unwrap()
+    .get(0)
+}
+
+#[tokio::test]
+async fn test_truncate_all_shards() {
+    let conn = connections_sqlx().await.into_iter().nth(1).unwrap();
+
+    conn.execute("CREATE TABLE IF NOT EXISTS test_maintenance (customer_id BIGINT, value TEXT)")
+        .await
+        .unwrap();
+
+    for id in 0..25 {
+        sqlx::query("INSERT INTO test_maintenance (customer_id, value) VALUES ($1, 'test')")
+            .bind(id as i64)
+            .execute(&conn)
+            .await
+            .unwrap();
+    }
+
+    // Rows are on both shards.
+    for shard in [0, 1] {
+        assert!(count(&conn, shard).await > 0);
+    }
+
+    let result = conn
+        .execute("TRUNCATE test_maintenance RESTART IDENTITY")
+        .await
+        .unwrap();
+    assert_eq!(result.rows_affected(), 0);
+
+    for shard in [0, 1] {
+        assert_eq!(count(&conn, shard).await, 0);
+    }
+
+    conn.execute("ANALYZE test_maintenance").await.unwrap();
+    conn.execute("VACUUM test_maintenance").await.unwrap();
+
+    // VACUUM can't run inside a transaction, and the transaction is still usable.
+    let mut transaction = conn.begin().await.unwrap();
+    let err = transaction
+        .execute("VACUUM test_maintenance")
+        .await
+        .unwrap_err();
+    let err = err.as_database_error().unwrap();
+    assert_eq!(err.code().unwrap(), "25001");
+    assert_eq!(
+        err.message(),
+        "VACUUM cannot run inside a transaction block"
+    );
+    transaction.rollback().await.unwrap();
+
+    conn.execute("DROP TABLE test_maintenance").await.unwrap();
+    conn.close().await;
+}
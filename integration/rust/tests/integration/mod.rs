@@ -1,7 +1,9 @@
 pub mod auth;
 pub mod ban;
+pub mod cross_shard_limits;
 pub mod distinct;
 pub mod fake_transactions;
+pub mod maintenance;
 pub mod notify;
 pub mod prepared;
 pub mod reload;
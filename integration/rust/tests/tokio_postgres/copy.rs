@@ -30,3 +30,57 @@
 //         }
 //     }
 // }
+
+use futures_util::{TryStreamExt, pin_mut};
+use rust::setup::connections_tokio;
+
+#[tokio::test]
+async fn test_copy_select() {
+    let conns = connections_tokio().await;
+
+    for (i, conn) in conns.into_iter().enumerate() {
+        let sharded = i == 1;
+
+        conn.batch_execute(
+            "CREATE SCHEMA IF NOT EXISTS test_copy_select;
+            CREATE TABLE IF NOT EXISTS test_copy_select.sharded (id BIGINT PRIMARY KEY, value TEXT);
+            TRUNCATE TABLE test_copy_select.sharded;",
+        )
+        .await
+        .unwrap();
+
+        for id in 0..10_i64 {
+            conn.execute(
+                "INSERT INTO test_copy_select.sharded (id, value) VALUES ($1, $2)",
+                &[&id, &format!("value_{}", id)],
+            )
+            .await
+            .unwrap();
+        }
+
+        let stream = conn
+            .copy_out(
+                "COPY (SELECT id, value FROM test_copy_select.sharded WHERE id = 5) TO STDOUT",
+            )
+            .await
+            .unwrap();
+        pin_mut!(stream);
+
+        let mut output = vec![];
+        while let Some(chunk) = stream.try_next().await.unwrap() {
+            output.extend_from_slice(&chunk);
+        }
+
+        assert_eq!(String::from_utf8(output).unwrap(), "5\tvalue_5\n");
+
+        // Cross-shard COPY with a query isn't supported yet.
+        let result = conn
+            .copy_out("COPY (SELECT id, value FROM test_copy_select.sharded) TO STDOUT")
+            .await;
+        if sharded {
+            assert!(result.is_err());
+        } else {
+            assert!(result.is_ok());
+        }
+    }
+}
@@ -4,7 +4,7 @@
 //!
 use proc_macro::TokenStream;
 use quote::quote;
-use syn::{ItemFn, parse_macro_input};
+use syn::{ItemFn, Path, parse_macro_input};
 
 /// Generates required methods for PgDog to run at plugin load time.
 ///
@@ -13,10 +13,49 @@ use syn::{ItemFn, parse_macro_input};
 /// * `pgdog_rustc_version`: Returns the version of the Rust compiler used to build the plugin.
 /// * `pgdog_pg_query_version`: Returns the version of the pg_query library used by the plugin.
 /// * `pgdog_plugin_version`: Returns the version of the plugin itself, taken from Cargo.toml.
+/// * `pgdog_plugin_abi`: Returns the version of the FFI interface the plugin was built with.
+/// * `pgdog_config`: Passes the plugin's configuration from `pgdog.toml`, as JSON, to a function, if one is given,
+///   e.g., `plugin!(configure)`. The function takes `&str` and returns `Result<(), String>`. The exported
+///   function is `pgdog_config(input: PdStr, error: *mut PdStr) -> i32`: it returns `1` and sets `error`
+///   to the function's error, which is logged by PgDog.
 ///
 #[proc_macro]
-pub fn plugin(_input: TokenStream) -> TokenStream {
+pub fn plugin(input: TokenStream) -> TokenStream {
+    let config = if input.is_empty() {
+        None
+    } else {
+        Some(parse_macro_input!(input as Path))
+    };
+
+    let config = config.map(|fn_name| {
+        quote! {
+            #[unsafe(no_mangle)]
+            pub unsafe extern "C" fn pgdog_config(input: pgdog_plugin::PdStr, error: *mut pgdog_plugin::PdStr) -> i32 {
+                thread_local! {
+                    // Error stays valid until the next call, so PgDog can copy it.
+                    static PGDOG_CONFIG_ERROR: std::cell::RefCell<String> = const { std::cell::RefCell::new(String::new()) };
+                }
+
+                let (code, message) = match #fn_name(&input) {
+                    Ok(()) => (0, String::new()),
+                    Err(err) => (1, err),
+                };
+
+                PGDOG_CONFIG_ERROR.with_borrow_mut(|buffer| {
+                    *buffer = message;
+                    unsafe {
+                        *error = buffer.as_str().into();
+                    }
+                });
+
+                code
+            }
+        }
+    });
+
     let expanded = quote! {
+        #config
+
         #[unsafe(no_mangle)]
         pub unsafe extern "C" fn pgdog_rustc_version(output: *mut pgdog_plugin::PdStr) {
             let version = pgdog_plugin::comp::rustc_version();
@@ -42,6 +81,11 @@ pub fn plugin(_input: TokenStream) -> TokenStream {
                 *output = version;
             }
         }
+
+        #[unsafe(no_mangle)]
+        pub extern "C" fn pgdog_plugin_abi() -> u32 {
+            pgdog_plugin::comp::abi_version()
+        }
     };
     TokenStream::from(expanded)
 }
@@ -113,10 +157,56 @@ pub fn route(_attr: TokenStream, item: TokenStream) -> TokenStream {
             #input_fn
 
             let pgdog_context: pgdog_plugin::Context = #first_param_name.into();
-            let route: pgdog_plugin::PdRoute = #fn_name(pgdog_context).into();
-            unsafe {
-                *output = route;
+            let route: pgdog_plugin::Route = #fn_name(pgdog_context).into();
+
+            thread_local! {
+                // Route's message stays valid until the next call, so PgDog can copy it.
+                static PGDOG_ROUTE: std::cell::RefCell<Option<pgdog_plugin::Route>> = const { std::cell::RefCell::new(None) };
             }
+
+            PGDOG_ROUTE.with_borrow_mut(|last| {
+                let route = last.insert(route);
+                unsafe {
+                    *output = **route;
+                }
+            });
+        }
+    };
+
+    TokenStream::from(expanded)
+}
+
+/// Generates the `pgdog_admin` method for plugin commands run from the admin database.
+///
+/// The function takes the command as `&str` and returns `Result<String, String>`.
+#[proc_macro_attribute]
+pub fn admin(_attr: TokenStream, item: TokenStream) -> TokenStream {
+    let input_fn = parse_macro_input!(item as ItemFn);
+    let fn_name = &input_fn.sig.ident;
+
+    let expanded = quote! {
+        #[unsafe(no_mangle)]
+        pub unsafe extern "C" fn pgdog_admin(command: pgdog_plugin::PdStr, output: *mut pgdog_plugin::PdBytes) -> i32 {
+            #input_fn
+
+            thread_local! {
+                // Output stays valid until the next call, so PgDog can copy it.
+                static PGDOG_ADMIN_OUTPUT: std::cell::RefCell<String> = const { std::cell::RefCell::new(String::new()) };
+            }
+
+            let (code, result) = match #fn_name(&command) {
+                Ok(result) => (0, result),
+                Err(err) => (1, err),
+            };
+
+            PGDOG_ADMIN_OUTPUT.with_borrow_mut(|buffer| {
+                *buffer = result;
+                unsafe {
+                    *output = buffer.as_bytes().into();
+                }
+            });
+
+            code
         }
     };
 
@@ -3,13 +3,25 @@
 //! Include this package as a build dependency only.
 //!
 
-use std::{fs::File, io::Read};
+use std::{
+    env, fmt, fs,
+    path::{Path, PathBuf},
+};
 
-/// Extracts the `pg_query` crate version from `Cargo.toml`
+use toml::{Table, Value};
+
+/// Extracts the `pg_query` crate version used by the plugin
 /// and sets it as an environment variable.
 ///
-/// This should be used at build time only. It expects `Cargo.toml` to be present in the same
-/// folder as `build.rs`.
+/// This should be used at build time only. The version is taken from `Cargo.lock`, if
+/// the plugin is in it, or from the `pg_query` dependency in `Cargo.toml` otherwise. All of these
+/// forms are supported:
+///
+/// ```toml
+/// pg_query = "6.1.0"
+/// pg_query = { version = "6.1.0", features = [] }
+/// pg_query.workspace = true
+/// ```
 ///
 /// ### Note
 ///
@@ -21,22 +33,321 @@ use std::{fs::File, io::Read};
 ///
 /// If the version in your plugin doesn't match what PgDog is using, your plugin won't be loaded.
 ///
+/// ### Panics
+///
+/// If the version can't be determined, the build fails.
+///
 pub fn pg_query_version() {
-    let mut contents = String::new();
-    if let Ok(mut file) = File::open("Cargo.toml") {
-        file.read_to_string(&mut contents).ok();
-    } else {
-        panic!("Cargo.toml not found");
+    let manifest_dir = env::var_os("CARGO_MANIFEST_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("."));
+
+    match version(&manifest_dir) {
+        Ok(version) => println!("cargo:rustc-env=PGDOG_PGQUERY_VERSION={}", version),
+        Err(err) => {
+            println!("cargo:warning={}", err);
+            panic!("{}", err);
+        }
     }
+}
+
+/// Why the `pg_query` version couldn't be determined.
+#[derive(Debug)]
+enum Error {
+    Read(PathBuf, std::io::Error),
+    Parse(PathBuf, toml::de::Error),
+    NoDependency(PathBuf),
+    NoVersion(PathBuf),
+    NoWorkspace(PathBuf),
+}
 
-    let contents: Option<toml::Value> = toml::from_str(&contents).ok();
-    if let Some(contents) = contents {
-        if let Some(dependencies) = contents.get("dependencies") {
-            if let Some(pg_query) = dependencies.get("pg_query") {
-                if let Some(version) = pg_query.as_str() {
-                    println!("cargo:rustc-env=PGDOG_PGQUERY_VERSION={}", version);
-                }
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Read(path, err) => write!(f, "failed to read \"{}\": {}", path.display(), err),
+            Self::Parse(path, err) => write!(f, "failed to parse \"{}\": {}", path.display(), err),
+            Self::NoDependency(path) => {
+                write!(f, "pg_query is not a dependency in \"{}\"", path.display())
             }
+            Self::NoVersion(path) => write!(
+                f,
+                "pg_query dependency in \"{}\" doesn't specify a version",
+                path.display()
+            ),
+            Self::NoWorkspace(path) => write!(
+                f,
+                "pg_query in \"{}\" is inherited from the workspace, but the workspace root wasn't found",
+                path.display()
+            ),
+        }
+    }
+}
+
+/// Read and parse a TOML file.
+fn read(path: &Path) -> Result<Table, Error> {
+    let contents = fs::read_to_string(path).map_err(|err| Error::Read(path.to_owned(), err))?;
+    toml::from_str(&contents).map_err(|err| Error::Parse(path.to_owned(), err))
+}
+
+/// Get the `pg_query` version of the package in `manifest_dir`.
+fn version(manifest_dir: &Path) -> Result<String, Error> {
+    let manifest_path = manifest_dir.join("Cargo.toml");
+    let manifest = read(&manifest_path)?;
+    let workspace = workspace_root(manifest_dir, &manifest);
+
+    // Cargo.lock is next to the workspace root manifest.
+    let lock_dir = workspace
+        .as_ref()
+        .map(|(dir, _)| dir.as_path())
+        .unwrap_or(manifest_dir);
+    if let Some(version) = locked_version(&lock_dir.join("Cargo.lock"), &manifest)? {
+        return Ok(version);
+    }
+
+    let dependency = manifest
+        .get("dependencies")
+        .and_then(|dependencies| dependencies.get("pg_query"))
+        .ok_or_else(|| Error::NoDependency(manifest_path.clone()))?;
+
+    let inherited = dependency
+        .get("workspace")
+        .and_then(Value::as_bool)
+        .unwrap_or(false);
+
+    if inherited {
+        let (dir, root) = workspace.ok_or_else(|| Error::NoWorkspace(manifest_path))?;
+        let root_path = dir.join("Cargo.toml");
+        let dependency = root
+            .get("workspace")
+            .and_then(|workspace| workspace.get("dependencies"))
+            .and_then(|dependencies| dependencies.get("pg_query"))
+            .ok_or_else(|| Error::NoDependency(root_path.clone()))?;
+        requirement(dependency).ok_or(Error::NoVersion(root_path))
+    } else {
+        requirement(dependency).ok_or(Error::NoVersion(manifest_path))
+    }
+}
+
+/// Version requirement of a dependency, e.g. `"6.1.0"` or `{ version = "6.1.0" }`.
+fn requirement(dependency: &Value) -> Option<String> {
+    dependency
+        .as_str()
+        .or_else(|| dependency.get("version").and_then(Value::as_str))
+        .map(String::from)
+}
+
+/// Find the workspace root manifest, if the package is in a workspace.
+fn workspace_root(manifest_dir: &Path, manifest: &Table) -> Option<(PathBuf, Table)> {
+    if manifest.contains_key("workspace") {
+        return Some((manifest_dir.to_owned(), manifest.clone()));
+    }
+
+    for dir in manifest_dir.ancestors().skip(1) {
+        let path = dir.join("Cargo.toml");
+        if !path.exists() {
+            continue;
+        }
+        if let Some(root) = read(&path)
+            .ok()
+            .filter(|root| root.contains_key("workspace"))
+        {
+            return Some((dir.to_owned(), root));
+        }
+    }
+
+    None
+}
+
+/// Get the exact `pg_query` version the package resolved to from `Cargo.lock`.
+///
+/// Returns `None` if there is no lock file or the package isn't in it.
+fn locked_version(path: &Path, manifest: &Table) -> Result<Option<String>, Error> {
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let Some(name) = manifest
+        .get("package")
+        .and_then(|package| package.get("name"))
+        .and_then(Value::as_str)
+    else {
+        return Ok(None);
+    };
+
+    let lock = read(path)?;
+    let packages = lock
+        .get("package")
+        .and_then(Value::as_array)
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+
+    // Local packages don't have a source.
+    let Some(package) = packages.iter().find(|package| {
+        package.get("name").and_then(Value::as_str) == Some(name) && package.get("source").is_none()
+    }) else {
+        return Ok(None);
+    };
+
+    // Dependencies are listed as "name", or "name version" if
+    // more than one version of the same crate is in the lock file.
+    let dependency = package
+        .get("dependencies")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+        .map(|dependency| dependency.split_whitespace().collect::<Vec<_>>())
+        .find(|dependency| dependency.first() == Some(&"pg_query"));
+
+    let Some(dependency) = dependency else {
+        return Ok(None);
+    };
+
+    if let Some(version) = dependency.get(1) {
+        return Ok(Some(version.to_string()));
+    }
+
+    Ok(packages
+        .iter()
+        .find(|package| package.get("name").and_then(Value::as_str) == Some("pg_query"))
+        .and_then(|package| package.get("version"))
+        .and_then(Value::as_str)
+        .map(String::from))
+}
+
+#[cfg(test)]
+mod test {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    const WORKSPACE: &str = r#"
+[workspace]
+members = ["plugin"]
+
+[workspace.dependencies]
+pg_query = { version = "6.1.0", default-features = false }
+"#;
+
+    const LOCK: &str = r#"
+version = 4
+
+[[package]]
+name = "pg_query"
+version = "6.0.0"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[[package]]
+name = "pg_query"
+version = "6.1.1"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[[package]]
+name = "plugin"
+version = "0.1.0"
+dependencies = [
+ "pg_query 6.1.1",
+]
+"#;
+
+    /// Write fixture files into a new temporary directory,
+    /// removed when it's dropped.
+    fn fixture(name: &str, files: &[(&str, &str)]) -> TempDir {
+        let dir = TempDir::with_prefix(format!("pgdog-plugin-build-{name}-")).unwrap();
+        for (path, contents) in files {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
         }
+        dir
+    }
+
+    fn plugin(dependency: &str) -> String {
+        format!(
+            "[package]\nname = \"plugin\"\nversion = \"0.1.0\"\n\n[dependencies]\n{dependency}\n"
+        )
+    }
+
+    #[test]
+    fn test_string() {
+        let dir = fixture("string", &[("Cargo.toml", &plugin("pg_query = \"6.1.0\""))]);
+        assert_eq!(version(dir.path()).unwrap(), "6.1.0");
+    }
+
+    #[test]
+    fn test_table() {
+        let inline = fixture(
+            "inline",
+            &[(
+                "Cargo.toml",
+                &plugin("pg_query = { version = \"6.1.0\", features = [\"prost\"] }"),
+            )],
+        );
+        assert_eq!(version(inline.path()).unwrap(), "6.1.0");
+
+        let dotted = fixture(
+            "dotted",
+            &[(
+                "Cargo.toml",
+                &plugin("pg_query.version = \"=6.1.0\"\npg_query.default-features = false"),
+            )],
+        );
+        assert_eq!(version(dotted.path()).unwrap(), "=6.1.0");
+    }
+
+    #[test]
+    fn test_workspace() {
+        let dir = fixture(
+            "workspace",
+            &[
+                ("Cargo.toml", WORKSPACE),
+                ("plugin/Cargo.toml", &plugin("pg_query.workspace = true")),
+            ],
+        );
+        assert_eq!(version(&dir.path().join("plugin")).unwrap(), "6.1.0");
+
+        let dir = fixture(
+            "no-workspace",
+            &[("Cargo.toml", &plugin("pg_query = { workspace = true }"))],
+        );
+        assert!(matches!(version(dir.path()), Err(Error::NoWorkspace(_))));
+    }
+
+    #[test]
+    fn test_lock() {
+        let dir = fixture(
+            "lock",
+            &[
+                ("Cargo.toml", WORKSPACE),
+                ("Cargo.lock", LOCK),
+                ("plugin/Cargo.toml", &plugin("pg_query.workspace = true")),
+            ],
+        );
+        assert_eq!(version(&dir.path().join("plugin")).unwrap(), "6.1.1");
+
+        let lock = "[[package]]\nname = \"pg_query\"\nversion = \"6.1.0\"\n\n[[package]]\nname = \"plugin\"\nversion = \"0.1.0\"\ndependencies = [\"pg_query\"]\n";
+        let dir = fixture(
+            "lock-single",
+            &[
+                ("Cargo.toml", &plugin("pg_query = \"6\"")),
+                ("Cargo.lock", lock),
+            ],
+        );
+        assert_eq!(version(dir.path()).unwrap(), "6.1.0");
+    }
+
+    #[test]
+    fn test_missing() {
+        let dir = fixture("missing", &[("Cargo.toml", &plugin(""))]);
+        assert!(matches!(version(dir.path()), Err(Error::NoDependency(_))));
+
+        let dir = fixture(
+            "no-version",
+            &[(
+                "Cargo.toml",
+                &plugin("pg_query = { git = \"https://github.com/pganalyze/pg_query.rs\" }"),
+            )],
+        );
+        assert!(matches!(version(dir.path()), Err(Error::NoVersion(_))));
     }
 }
@@ -2,6 +2,26 @@
 #include <stddef.h>
 #include <stdint.h>
 
+/**
+ * Version of the FFI interface between PgDog and plugins.
+ * Bump this every time any of the structs below, or the functions plugins export, change.
+ *
+ * Plugins accepting configuration export:
+ *
+ * int32_t pgdog_config(PdStr input, PdStr *error);
+ *
+ * `input` is the plugin's configuration from pgdog.toml, as JSON. If the plugin rejects it,
+ * it returns a non-zero code and sets `error` to its message. The plugin owns the memory,
+ * which stays valid until the plugin is called again on the same thread.
+ */
+#define PDOG_ABI_VERSION 5
+
+/**
+ * Oldest version of the FFI interface PgDog can still load. Fields added since
+ * are appended to the end of structs, and left at their defaults by older plugins.
+ */
+#define PDOG_ABI_MIN_VERSION 3
+
 /**
  * Wrapper around Rust's [`&str`], without allocating memory, unlike [`std::ffi::CString`].
  * The caller must use it as a Rust string. This is not a C-string.
@@ -11,6 +31,15 @@ typedef struct PdStr {
     void *data;
 } RustString;
 
+/**
+ * Bytes returned by a plugin. The plugin owns the memory, which stays valid
+ * until the plugin is called again on the same thread.
+ */
+typedef struct PdBytes {
+    size_t len;
+    void *data;
+} PdBytes;
+
 /**
  * Wrapper around output by pg_query.
  */
@@ -37,6 +66,36 @@ typedef struct PdParameters {
     void *format_codes;
 } PdParameters;
 
+/**
+ * Session parameter set by the client, e.g. with `SET app.tenant_id = '42'`.
+ */
+typedef struct PdSessionParameter {
+    /** Parameter name. */
+    struct PdStr name;
+    /** Parameter value. */
+    struct PdStr value;
+} PdSessionParameter;
+
+/**
+ * Session parameters of the client connection.
+ */
+typedef struct PdSessionParameters {
+    /** Number of parameters. */
+    uint64_t num_params;
+    /** Pointer to an array of parameters. */
+    PdSessionParameter *params;
+} PdSessionParameters;
+
+/**
+ * Compute the shard number for a value, using PgDog's sharding functions.
+ *
+ * Arguments: opaque sharding schema pointer, the value, its format (`0` = text, `1` = binary)
+ * and its data type (`0` = bigint, `1` = uuid, `2` = varchar, `3` = vector).
+ *
+ * Returns the shard number, or `-1` if the value can't be mapped to exactly one shard.
+ */
+typedef int64_t (*PdShardForValue)(const void *sharding_schema, struct PdStr value, uint8_t format, uint8_t data_type);
+
 /**
  * Context on the database cluster configuration and the currently processed
  * PostgreSQL statement.
@@ -59,6 +118,12 @@ typedef struct PdRouterContext {
     PdStatement query;
     /** Bound parameters. */
     PdParameters params;
+    /** Opaque pointer to PgDog's sharding schema. Pass it to `shard_for_value`. */
+    const void *sharding_schema;
+    /** Sharding function provided by PgDog. Can be `NULL`. */
+    PdShardForValue shard_for_value;
+    /** Session parameters set by the client. */
+    PdSessionParameters session_params;
 } PdRouterContext;
 
 /**
@@ -75,4 +140,9 @@ typedef struct PdRouterContext {
       * `1` for `true`, `0` for `false`, `2` for unknown, this setting is ignored.
       */
      uint8_t read_write;
+     /** Error message returned to the client if the statement is blocked. Empty for none.
+      *
+      * The plugin owns the memory, which stays valid until the plugin is called again on the same thread.
+      */
+     struct PdStr message;
  } PdRoute;
@@ -0,0 +1,55 @@
+//! Wrapper around a byte slice returned by a plugin.
+//!
+//! The plugin owns the memory. PgDog copies the bytes before calling
+//! the plugin again.
+//!
+//! ### Example
+//!
+//! ```
+//! use pgdog_plugin::PdBytes;
+//! use std::ops::Deref;
+//!
+//! let bytes = PdBytes::from("hello world".as_bytes());
+//! assert_eq!(bytes.deref(), b"hello world");
+//! ```
+//!
+use crate::bindings::PdBytes;
+use std::{ops::Deref, os::raw::c_void, slice::from_raw_parts};
+
+impl From<&[u8]> for PdBytes {
+    fn from(value: &[u8]) -> Self {
+        PdBytes {
+            data: value.as_ptr() as *mut c_void,
+            len: value.len(),
+        }
+    }
+}
+
+impl Deref for PdBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { from_raw_parts::<u8>(self.data as *mut u8, self.len) }
+    }
+}
+
+impl Default for PdBytes {
+    fn default() -> Self {
+        Self {
+            len: 0,
+            data: [].as_ptr() as *mut c_void,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_pd_bytes() {
+        let bytes = PdBytes::from("one\ttwo".as_bytes());
+        assert_eq!(bytes.deref(), b"one\ttwo");
+        assert!(PdBytes::default().is_empty());
+    }
+}
@@ -6,3 +6,16 @@ use crate::PdStr;
 pub fn rustc_version() -> PdStr {
     env!("RUSTC_VERSION").into()
 }
+
+/// Version of the FFI interface between PgDog and plugins.
+///
+/// Plugins built with a newer version, or a version older than
+/// [`min_abi_version`], are not loaded.
+pub fn abi_version() -> u32 {
+    crate::PDOG_ABI_VERSION
+}
+
+/// Oldest version of the FFI interface plugins can be built with.
+pub fn min_abi_version() -> u32 {
+    crate::PDOG_ABI_MIN_VERSION
+}
@@ -0,0 +1,23 @@
+//! Plugin configuration.
+//!
+//! The `config` of the plugin's `[[plugins]]` entry in `pgdog.toml` is passed to the plugin
+//! as JSON, before `pgdog_init` and again when it changes on reload.
+
+use serde::de::DeserializeOwned;
+
+pub use serde_json::Error;
+
+/// Deserialize the plugin's configuration.
+///
+/// ### Example
+///
+/// ```
+/// use std::collections::HashMap;
+/// use pgdog_plugin::config;
+///
+/// let config: HashMap<String, String> = config::parse(r#"{"endpoint": "http://localhost"}"#).unwrap();
+/// assert_eq!(config["endpoint"], "http://localhost");
+/// ```
+pub fn parse<T: DeserializeOwned>(config: &str) -> Result<T, Error> {
+    serde_json::from_str(config)
+}
@@ -1,9 +1,11 @@
 //! Context passed to and from the plugins.
 
-use std::ops::Deref;
+use std::{ops::Deref, os::raw::c_void, ptr::null_mut, slice::from_raw_parts};
 
 use crate::{
-    bindings::PdRouterContext, parameters::Parameters, PdParameters, PdRoute, PdStatement,
+    bindings::PdRouterContext,
+    parameters::{ParameterFormat, ParameterValue, Parameters},
+    PdParameters, PdRoute, PdSessionParameter, PdSessionParameters, PdStatement, PdStr,
 };
 
 /// PostgreSQL statement, parsed by [`pg_query`].
@@ -238,6 +240,122 @@ impl Context {
     pub fn parameters(&self) -> Parameters {
         self.ffi.params.into()
     }
+
+    /// Returns the shard number the value belongs to, computed by PgDog using the
+    /// sharding configuration of the database cluster, i.e., number of shards, hash function and
+    /// any list or range mappings configured for a sharded table with the same data type.
+    ///
+    /// Returns `None` if the value can't be mapped to exactly one shard, e.g. because it doesn't
+    /// match the data type, or if PgDog didn't provide a sharding function.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pgdog_plugin::prelude::*;
+    /// # let context = unsafe { Context::doc_test() };
+    /// let shard = context.shard_for_value(ParameterValue::Text("1234"), DataType::Bigint);
+    ///
+    /// let route = match shard {
+    ///     Some(shard) => Route::new(Shard::Direct(shard), ReadWrite::Unknown),
+    ///     None => Route::unknown(),
+    /// };
+    /// ```
+    pub fn shard_for_value(&self, value: ParameterValue, data_type: DataType) -> Option<usize> {
+        let shard_for_value = self.ffi.shard_for_value?;
+
+        let binary = |binary: &[u8]| PdStr {
+            data: binary.as_ptr() as *mut c_void,
+            len: binary.len(),
+        };
+        let (data, format) = match &value {
+            ParameterValue::Text(text) | ParameterValue::Json(text) => {
+                (PdStr::from(*text), ParameterFormat::Text)
+            }
+            ParameterValue::Decimal(decimal) => (PdStr::from(decimal), ParameterFormat::Text),
+            ParameterValue::Binary(bytes) => (binary(bytes), ParameterFormat::Binary),
+            ParameterValue::Bytea(bytes) => (binary(bytes), ParameterFormat::Binary),
+        };
+
+        // SAFETY: The function and the sharding schema are provided by PgDog
+        // and are valid for as long as the plugin is executing. The value is borrowed
+        // for the duration of the call only.
+        let shard = unsafe {
+            shard_for_value(
+                self.ffi.sharding_schema,
+                data,
+                format as u8,
+                data_type.into(),
+            )
+        };
+
+        if shard >= 0 {
+            Some(shard as usize)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the value of a session parameter set by the client, e.g. with
+    /// `SET app.tenant_id = '42'`. Parameter names are case-insensitive, like in PostgreSQL.
+    /// Parameters set to a list of values, like `search_path`, are returned comma-separated.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use pgdog_plugin::Context;
+    /// # let context = unsafe { Context::doc_test() };
+    /// if let Some(tenant_id) = context.session_param("app.tenant_id") {
+    ///     println!("tenant: {}", tenant_id);
+    /// }
+    /// ```
+    pub fn session_param(&self, name: &str) -> Option<&str> {
+        self.session_params()
+            .find(|(param, _)| param.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value)
+    }
+
+    /// Returns all session parameters set by the client, as name and value pairs.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use pgdog_plugin::Context;
+    /// # let context = unsafe { Context::doc_test() };
+    /// for (name, value) in context.session_params() {
+    ///     println!("{} = {}", name, value);
+    /// }
+    /// ```
+    pub fn session_params(&self) -> impl Iterator<Item = (&str, &str)> {
+        let params = &self.ffi.session_params;
+        let params: &[PdSessionParameter] = if params.params.is_null() {
+            &[]
+        } else {
+            // SAFETY: PgDog keeps the parameters in memory while the plugin is executing.
+            unsafe { from_raw_parts(params.params, params.num_params as usize) }
+        };
+
+        params
+            .iter()
+            .map(|param| (param.name.deref(), param.value.deref()))
+    }
+}
+
+impl Default for PdSessionParameters {
+    fn default() -> Self {
+        Self {
+            num_params: 0,
+            params: null_mut(),
+        }
+    }
+}
+
+impl From<&[PdSessionParameter]> for PdSessionParameters {
+    fn from(value: &[PdSessionParameter]) -> Self {
+        Self {
+            num_params: value.len() as u64,
+            params: value.as_ptr() as *mut PdSessionParameter,
+        }
+    }
 }
 
 impl Context {
@@ -248,7 +366,7 @@ impl Context {
     /// Not safe, don't use. We use it for doc tests only.
     ///
     pub unsafe fn doc_test() -> Context {
-        use std::{os::raw::c_void, ptr::null};
+        use std::ptr::null;
 
         Context {
             ffi: PdRouterContext {
@@ -263,11 +381,61 @@ impl Context {
                     data: null::<c_void>() as *mut c_void,
                 },
                 params: PdParameters::default(),
+                sharding_schema: null::<c_void>(),
+                shard_for_value: None,
+                session_params: PdSessionParameters::default(),
             },
         }
     }
 }
 
+/// Data type of the value used for sharding.
+///
+/// ### Example
+///
+/// ```
+/// use pgdog_plugin::DataType;
+///
+/// let data_type = DataType::Bigint;
+/// assert_eq!(u8::from(data_type), 0);
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DataType {
+    /// `BIGINT`, `INTEGER` or `SMALLINT`.
+    Bigint,
+    /// `UUID`.
+    Uuid,
+    /// `VARCHAR` or `TEXT`.
+    Varchar,
+    /// `VECTOR` (from pgvector).
+    Vector,
+}
+
+impl From<DataType> for u8 {
+    fn from(value: DataType) -> Self {
+        match value {
+            DataType::Bigint => 0,
+            DataType::Uuid => 1,
+            DataType::Varchar => 2,
+            DataType::Vector => 3,
+        }
+    }
+}
+
+impl TryFrom<u8> for DataType {
+    type Error = ();
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Ok(match value {
+            0 => DataType::Bigint,
+            1 => DataType::Uuid,
+            2 => DataType::Varchar,
+            3 => DataType::Vector,
+            _ => return Err(()),
+        })
+    }
+}
+
 /// What shard, if any, the statement should be sent to.
 ///
 /// ### Example
@@ -381,7 +549,11 @@ impl From<ReadWrite> for u8 {
 
 impl Default for PdRoute {
     fn default() -> Self {
-        Route::unknown().ffi
+        PdRoute {
+            shard: Shard::Unknown.into(),
+            read_write: ReadWrite::Unknown.into(),
+            message: PdStr::default(),
+        }
     }
 }
 
@@ -403,11 +575,18 @@ impl Default for PdRoute {
 /// // of each shard, if any are configured.
 /// let route = Route::new(Shard::All, ReadWrite::Read);
 ///
+/// // Same routes, using the helpers.
+/// let route = Route::write_shard(0);
+/// let route = Route::read_all();
+///
 /// // No routing information is available. PgDog will ignore it
 /// // and make its own decision.
 /// let route = Route::unknown();
+/// ```
 pub struct Route {
     ffi: PdRoute,
+    /// Error message of a blocked statement. `ffi.message` points to it.
+    message: String,
 }
 
 impl Default for Route {
@@ -426,13 +605,24 @@ impl Deref for Route {
 
 impl From<PdRoute> for Route {
     fn from(value: PdRoute) -> Self {
-        Self { ffi: value }
+        let mut route = Self {
+            ffi: value,
+            message: value.message.to_string(),
+        };
+        route.ffi.message = (&route.message).into();
+        route
     }
 }
 
+/// Deprecated: return [`Route`] from the function annotated with `#[route]`
+/// instead. The route's message is owned by [`Route`], so it's not kept.
+// `#[deprecated]` has no effect on trait implementations.
 impl From<Route> for PdRoute {
     fn from(value: Route) -> Self {
-        value.ffi
+        PdRoute {
+            message: PdStr::default(),
+            ..value.ffi
+        }
     }
 }
 
@@ -445,11 +635,17 @@ impl Route {
     /// * `read_write`: Does the statement read or write data. Read statements are sent to a replica. Write statements are sent to the primary.
     ///
     pub fn new(shard: Shard, read_write: ReadWrite) -> Route {
+        Self::with_message(shard, read_write, String::new())
+    }
+
+    fn with_message(shard: Shard, read_write: ReadWrite, message: String) -> Route {
         Self {
             ffi: PdRoute {
                 shard: shard.into(),
                 read_write: read_write.into(),
+                message: (&message).into(),
             },
+            message,
         }
     }
 
@@ -458,22 +654,130 @@ impl Route {
     /// Plugins that do something else with queries, e.g., logging, metrics,
     /// can return this route.
     pub fn unknown() -> Route {
-        Self {
-            ffi: PdRoute {
-                shard: -2,
-                read_write: 2,
-            },
-        }
+        Self::new(Shard::Unknown, ReadWrite::Unknown)
+    }
+
+    /// Send the statement to a replica, letting PgDog pick the shard.
+    pub fn read() -> Route {
+        Self::new(Shard::Unknown, ReadWrite::Read)
+    }
+
+    /// Send the statement to the primary, letting PgDog pick the shard.
+    pub fn write() -> Route {
+        Self::new(Shard::Unknown, ReadWrite::Write)
+    }
+
+    /// Send the statement to a replica of every shard.
+    ///
+    /// ### Example
+    ///
+    /// ```
+    /// use pgdog_plugin::prelude::*;
+    ///
+    /// let route = Route::read_all();
+    /// assert_eq!(route.shard(), Shard::All);
+    /// assert_eq!(route.read_write(), ReadWrite::Read);
+    /// ```
+    pub fn read_all() -> Route {
+        Self::new(Shard::All, ReadWrite::Read)
+    }
+
+    /// Send the statement to the primary of every shard.
+    pub fn write_all() -> Route {
+        Self::new(Shard::All, ReadWrite::Write)
+    }
+
+    /// Send the statement to a replica of the shard.
+    pub fn read_shard(shard: usize) -> Route {
+        Self::new(Shard::Direct(shard), ReadWrite::Read)
+    }
+
+    /// Send the statement to the primary of the shard.
+    ///
+    /// ### Example
+    ///
+    /// ```
+    /// use pgdog_plugin::prelude::*;
+    ///
+    /// let route = Route::write_shard(2);
+    /// assert_eq!(route.shard(), Shard::Direct(2));
+    /// assert_eq!(route.read_write(), ReadWrite::Write);
+    /// ```
+    pub fn write_shard(shard: usize) -> Route {
+        Self::new(Shard::Direct(shard), ReadWrite::Write)
+    }
+
+    /// Send the statement to the shard the sharding key belongs to, computed
+    /// with [`Context::shard_for_value`]. If the shard can't be computed, PgDog picks it.
+    ///
+    /// ### Example
+    ///
+    /// ```
+    /// use pgdog_plugin::prelude::*;
+    /// # let context = unsafe { Context::doc_test() };
+    ///
+    /// let route = Route::sharding_key(
+    ///     &context,
+    ///     ParameterValue::Text("1234"),
+    ///     DataType::Bigint,
+    ///     ReadWrite::Read,
+    /// );
+    ///
+    /// // The doc test context has no sharding function.
+    /// assert_eq!(route.shard(), Shard::Unknown);
+    /// assert_eq!(route.read_write(), ReadWrite::Read);
+    /// ```
+    pub fn sharding_key(
+        context: &Context,
+        value: ParameterValue,
+        data_type: DataType,
+        read_write: ReadWrite,
+    ) -> Route {
+        let shard = match context.shard_for_value(value, data_type) {
+            Some(shard) => Shard::Direct(shard),
+            None => Shard::Unknown,
+        };
+        Self::new(shard, read_write)
     }
 
     /// Block the query from being sent to a database. PgDog will abort the query
     /// and return an error to the client, telling them which plugin blocked it.
     pub fn block() -> Route {
-        Self {
-            ffi: PdRoute {
-                shard: -3,
-                read_write: 2,
-            },
+        Self::new(Shard::Blocked, ReadWrite::Unknown)
+    }
+
+    /// Block the query from being sent to a database, returning the message
+    /// to the client in the error, along with the name of the plugin that blocked it.
+    ///
+    /// ### Example
+    ///
+    /// ```
+    /// use pgdog_plugin::prelude::*;
+    ///
+    /// let route = Route::block_with_message("tenant_id required");
+    /// assert_eq!(route.shard(), Shard::Blocked);
+    /// assert_eq!(route.message(), Some("tenant_id required"));
+    /// ```
+    pub fn block_with_message(message: impl ToString) -> Route {
+        Self::with_message(Shard::Blocked, ReadWrite::Unknown, message.to_string())
+    }
+
+    /// Which shard the statement should be sent to.
+    pub fn shard(&self) -> Shard {
+        self.ffi.shard.try_into().unwrap_or(Shard::Unknown)
+    }
+
+    /// Does the statement read or write data.
+    pub fn read_write(&self) -> ReadWrite {
+        self.ffi.read_write.try_into().unwrap_or(ReadWrite::Unknown)
+    }
+
+    /// Error message returned to the client if the statement is blocked.
+    pub fn message(&self) -> Option<&str> {
+        if self.message.is_empty() {
+            None
+        } else {
+            Some(&self.message)
         }
     }
 }
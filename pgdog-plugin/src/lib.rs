@@ -23,10 +23,11 @@
 //! and make statement routing decisions.
 //!
 //! The AST is computed by PgDog at runtime. It then passes it down to plugins, using a FFI interface. To make this safe, plugins must follow the
-//! following 2 requirements:
+//! following 3 requirements:
 //!
 //! 1. Plugins must be compiled with the **same version of the Rust compiler** as PgDog. This is automatically checked at runtime and plugins that don't do this are not loaded.
 //! 2. Plugins must use the **same version of [`pg_query`] crate** as PgDog. This happens automatically when using `pg_query` structs re-exported by this crate.
+//! 3. Plugins must use a version of this crate with a **compatible FFI interface version** (see [`comp::abi_version`] and [`comp::min_abi_version`]). This is automatically checked at runtime and plugins that don't do this are not loaded.
 //!
 //!
 //! #### Configure dependencies
@@ -109,7 +110,8 @@
 //!
 //! Plugins can block queries from executing. This is useful if you'd like to enforce specific requirements,
 //! like a mandatory `tenant_id` column, for example, or want to block your apps from saving sensitive information,
-//! like credit card numbers or plain text passwords.
+//! like credit card numbers or plain text passwords. Blocked queries fail with SQLSTATE `42501`,
+//! and the message passed to [`Route::block_with_message`], if any, is included in the error.
 //!
 //! #### Example
 //!
@@ -125,7 +127,7 @@
 //!         .flatten();
 //!     if let Some(ParameterValue::Text(password)) = password {
 //!         if !password.starts_with("$bcrypt") {
-//!             return Route::block();
+//!             return Route::block_with_message("passwords must be hashed with bcrypt");
 //!         }
 //!     }
 //!
@@ -133,6 +135,64 @@
 //! }
 //! ```
 //!
+//! # Admin commands
+//!
+//! Plugins can expose commands to the PgDog admin database, e.g., to inspect or refresh their internal state at runtime.
+//! Running `PLUGIN <plugin_name> <command>` in the admin database passes `<command>` to the plugin's function,
+//! annotated with the [`macros::admin`] macro:
+//!
+//! ```
+//! use pgdog_plugin::prelude::*;
+//!
+//! #[admin]
+//! fn admin(command: &str) -> Result<String, String> {
+//!     match command {
+//!         "show tenants" => Ok("tenant\tshard\nacme\t0\nglobex\t1".into()),
+//!         command => Err(format!("unknown command: {}", command)),
+//!     }
+//! }
+//! ```
+//!
+//! The output is sent to the admin client as rows, one for each line. If the first line contains tabs, the output is read as
+//! tab-separated values, with the first line naming the columns. Errors are sent to the client as an error.
+//!
+//! # Configuration
+//!
+//! Plugins can be configured in `pgdog.toml`, using the `config` setting of their `[[plugins]]` entry:
+//!
+//! ```toml
+//! [[plugins]]
+//! name = "my_plugin"
+//! config = { endpoint = "https://tenants.example.com", refresh_interval = 60 }
+//! ```
+//!
+//! PgDog passes it to the plugin as JSON, before calling `pgdog_init` and again when the configuration is reloaded
+//! and it changed. Pass a function taking the JSON as `&str` and returning `Result<(), String>` to the [`macros::plugin`] macro,
+//! and use [`config::parse`] to deserialize it:
+//!
+//! ```ignore
+//! use pgdog_plugin::{config, macros};
+//! use serde::Deserialize;
+//!
+//! #[derive(Deserialize, Default)]
+//! struct Config {
+//!     endpoint: String,
+//!     refresh_interval: u64,
+//! }
+//!
+//! macros::plugin!(configure);
+//!
+//! fn configure(input: &str) -> Result<(), String> {
+//!     // `null` if the configuration was removed from pgdog.toml.
+//!     let config: Option<Config> = config::parse(input).map_err(|err| err.to_string())?;
+//!     let config = config.unwrap_or_default();
+//!     // Save it for later.
+//!     Ok(())
+//! }
+//! ```
+//!
+//! If the plugin returns an error, PgDog logs it and doesn't load the plugin. On reload, the plugin keeps running with its old configuration.
+//!
 //! # Enabling plugins
 //!
 //! Plugins are shared libraries, loaded by PgDog at runtime using `dlopen(3)`. If specifying only its name, make sure to place the plugin's shared library
@@ -165,7 +225,9 @@
 pub mod bindings;
 
 pub mod ast;
+pub mod bytes;
 pub mod comp;
+pub mod config;
 pub mod context;
 pub mod parameters;
 pub mod plugin;
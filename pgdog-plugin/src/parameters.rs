@@ -12,7 +12,7 @@
 //!     let value = param.decode(params.parameter_format(0));
 //! }
 //! ```
-use std::{ops::Deref, os::raw::c_void, ptr::null, str::from_utf8};
+use std::{borrow::Cow, ops::Deref, os::raw::c_void, ptr::null, str::from_utf8};
 
 use crate::PdParameters;
 
@@ -30,6 +30,8 @@ pub enum ParameterFormat {
 
 /// Wrapper around a decoded parameter.
 ///
+/// More data types may be decoded in the future, so matches need a wildcard arm.
+///
 /// # Example
 ///
 /// ```
@@ -38,16 +40,44 @@ pub enum ParameterFormat {
 /// match parameter {
 ///     ParameterValue::Text(text) => assert_eq!(text, "test"),
 ///     ParameterValue::Binary(binary) => println!("{:?}", binary),
+///     _ => (),
 /// }
 /// ```
 #[derive(Debug, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum ParameterValue<'a> {
     /// Parameter is encoded using text (UTF-8).
     Text(&'a str),
     /// Parameter is encoded using binary encoding.
     Binary(&'a [u8]),
+    /// `BYTEA` contents, decoded from hex, escape or binary encoding.
+    Bytea(Cow<'a, [u8]>),
+    /// `JSONB` document.
+    Json(&'a str),
+    /// `NUMERIC` or `MONEY` as a decimal string, e.g. `"-1234.56"`.
+    Decimal(String),
+}
+
+/// Postgres data type of a parameter, used by [`Parameter::decode_as`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ParameterType {
+    /// `BYTEA`.
+    Bytea,
+    /// `JSONB`.
+    Jsonb,
+    /// `NUMERIC`.
+    Numeric,
+    /// `MONEY`.
+    Money,
 }
 
+const NUMERIC_POS: u16 = 0x0000;
+const NUMERIC_NEG: u16 = 0x4000;
+const NUMERIC_NAN: u16 = 0xC000;
+const NUMERIC_PINF: u16 = 0xD000;
+const NUMERIC_NINF: u16 = 0xF000;
+
 /// Prepared statement bound parameter.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Parameter {
@@ -93,12 +123,216 @@ impl Parameter {
         }
     }
 
+    /// Decode parameter of a known data type, given the provided format.
+    ///
+    /// If the parameter is `NULL` or the data isn't valid for the data type, `None` is returned.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pgdog_plugin::prelude::*;
+    ///
+    /// let parameter = Parameter {
+    ///     len: 6,
+    ///     data: "\\xcafe".as_bytes().to_vec(),
+    /// };
+    /// assert_eq!(
+    ///     parameter.decode_as(ParameterFormat::Text, ParameterType::Bytea),
+    ///     Some(ParameterValue::Bytea(vec![0xca, 0xfe].into()))
+    /// );
+    /// ```
+    pub fn decode_as(
+        &self,
+        format: ParameterFormat,
+        data_type: ParameterType,
+    ) -> Option<ParameterValue<'_>> {
+        if self.null() {
+            return None;
+        }
+
+        let data = &self.data[..];
+
+        match (data_type, format) {
+            (ParameterType::Bytea, ParameterFormat::Binary) => {
+                Some(ParameterValue::Bytea(Cow::Borrowed(data)))
+            }
+            (ParameterType::Bytea, ParameterFormat::Text) => {
+                bytea(from_utf8(data).ok()?).map(|bytes| ParameterValue::Bytea(Cow::Owned(bytes)))
+            }
+
+            // Binary JSONB is prefixed with a version number, currently 1.
+            (ParameterType::Jsonb, ParameterFormat::Binary) => match data.split_first() {
+                Some((1, json)) => from_utf8(json).ok().map(ParameterValue::Json),
+                _ => None,
+            },
+            (ParameterType::Jsonb, ParameterFormat::Text) => {
+                from_utf8(data).ok().map(ParameterValue::Json)
+            }
+
+            (ParameterType::Numeric, ParameterFormat::Binary) => {
+                numeric(data).map(ParameterValue::Decimal)
+            }
+            (ParameterType::Numeric, ParameterFormat::Text) => {
+                let text = from_utf8(data).ok()?.trim();
+                if text.is_empty() {
+                    None
+                } else {
+                    Some(ParameterValue::Decimal(text.to_owned()))
+                }
+            }
+
+            // Money is stored as an integer number of cents.
+            (ParameterType::Money, ParameterFormat::Binary) => {
+                let cents = i64::from_be_bytes(data.try_into().ok()?);
+                let sign = if cents < 0 { "-" } else { "" };
+                let cents = cents.unsigned_abs();
+                Some(ParameterValue::Decimal(format!(
+                    "{}{}.{:02}",
+                    sign,
+                    cents / 100,
+                    cents % 100
+                )))
+            }
+            (ParameterType::Money, ParameterFormat::Text) => {
+                money(from_utf8(data).ok()?).map(ParameterValue::Decimal)
+            }
+        }
+    }
+
     /// Returns true if the parameter is `NULL`.
     pub fn null(&self) -> bool {
         self.len == -1
     }
 }
 
+/// Decode `BYTEA` in hex (`\xcafe`) or escape (`caf\351`) text format.
+fn bytea(text: &str) -> Option<Vec<u8>> {
+    if let Some(hex) = text.strip_prefix("\\x") {
+        let hex = hex.as_bytes();
+        if hex.len() % 2 != 0 || !hex.iter().all(u8::is_ascii_hexdigit) {
+            return None;
+        }
+        return hex
+            .chunks_exact(2)
+            .map(|pair| u8::from_str_radix(from_utf8(pair).ok()?, 16).ok())
+            .collect();
+    }
+
+    let text = text.as_bytes();
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut i = 0;
+
+    while i < text.len() {
+        if text[i] != b'\\' {
+            bytes.push(text[i]);
+            i += 1;
+        } else if text.get(i + 1) == Some(&b'\\') {
+            bytes.push(b'\\');
+            i += 2;
+        } else {
+            let octal = text.get(i + 1..i + 4)?;
+            if !octal.iter().all(|c| (b'0'..=b'7').contains(c)) {
+                return None;
+            }
+            bytes.push(u8::from_str_radix(from_utf8(octal).ok()?, 8).ok()?);
+            i += 4;
+        }
+    }
+
+    Some(bytes)
+}
+
+/// Decode binary `NUMERIC` into a decimal string.
+///
+/// The format is a header of 4 16-bit integers (number of digits, weight, sign and display scale),
+/// followed by base 10,000 digits.
+fn numeric(data: &[u8]) -> Option<String> {
+    let header = |i: usize| u16::from_be_bytes([data[i * 2], data[i * 2 + 1]]);
+
+    if data.len() < 8 {
+        return None;
+    }
+
+    let ndigits = header(0) as usize;
+    let weight = header(1) as i16 as i32;
+    let sign = header(2);
+    let dscale = header(3) as usize;
+
+    if data.len() != 8 + ndigits * 2 {
+        return None;
+    }
+
+    let digits = data[8..]
+        .chunks_exact(2)
+        .map(|digit| u16::from_be_bytes([digit[0], digit[1]]))
+        .collect::<Vec<_>>();
+
+    if digits.iter().any(|digit| *digit >= 10_000) {
+        return None;
+    }
+
+    let mut result = String::new();
+
+    match sign {
+        NUMERIC_POS => (),
+        NUMERIC_NEG => result.push('-'),
+        NUMERIC_NAN => return Some("NaN".into()),
+        NUMERIC_PINF => return Some("Infinity".into()),
+        NUMERIC_NINF => return Some("-Infinity".into()),
+        _ => return None,
+    }
+
+    let digit = |i: i32| {
+        usize::try_from(i)
+            .ok()
+            .and_then(|i| digits.get(i).copied())
+            .unwrap_or(0)
+    };
+
+    if weight < 0 {
+        result.push('0');
+    } else {
+        result.push_str(&digit(0).to_string());
+        for i in 1..=weight {
+            result.push_str(&format!("{:04}", digit(i)));
+        }
+    }
+
+    if dscale > 0 {
+        let mut fraction = String::new();
+        let mut i = weight + 1;
+        while fraction.len() < dscale {
+            fraction.push_str(&format!("{:04}", digit(i)));
+            i += 1;
+        }
+        fraction.truncate(dscale);
+        result.push('.');
+        result.push_str(&fraction);
+    }
+
+    Some(result)
+}
+
+/// Convert `MONEY` text output, e.g. `-$1,234.56`, into a decimal string.
+fn money(text: &str) -> Option<String> {
+    let text = text.trim();
+    let negative = text.starts_with('-') || text.starts_with('(');
+    let amount = text
+        .chars()
+        .filter(|c| c.is_ascii_digit() || *c == '.')
+        .collect::<String>();
+
+    if !amount.chars().any(|c| c.is_ascii_digit()) || amount.matches('.').count() > 1 {
+        return None;
+    }
+
+    Some(if negative {
+        format!("-{}", amount)
+    } else {
+        amount
+    })
+}
+
 /// Prepared statement parameters.
 #[derive(Debug, PartialEq, Eq)]
 pub struct Parameters {
@@ -235,6 +469,160 @@ impl Default for PdParameters {
 mod test {
     use super::*;
 
+    fn param(data: &[u8]) -> Parameter {
+        Parameter {
+            len: data.len() as i32,
+            data: data.to_vec(),
+        }
+    }
+
+    fn numeric(ndigits: i16, weight: i16, sign: u16, dscale: u16, digits: &[i16]) -> Vec<u8> {
+        let mut data = vec![];
+        data.extend(ndigits.to_be_bytes());
+        data.extend(weight.to_be_bytes());
+        data.extend(sign.to_be_bytes());
+        data.extend(dscale.to_be_bytes());
+        for digit in digits {
+            data.extend(digit.to_be_bytes());
+        }
+        data
+    }
+
+    #[test]
+    fn test_decode_bytea() {
+        let decode = |data: &[u8], format| {
+            param(data)
+                .decode_as(format, ParameterType::Bytea)
+                .map(|value| match value {
+                    ParameterValue::Bytea(bytes) => bytes.into_owned(),
+                    _ => panic!("not bytea"),
+                })
+        };
+
+        assert_eq!(
+            decode(b"\\xdeadBEEF", ParameterFormat::Text),
+            Some(vec![0xde, 0xad, 0xbe, 0xef])
+        );
+        assert_eq!(decode(b"\\x", ParameterFormat::Text), Some(vec![]));
+        assert_eq!(decode(b"\\xabc", ParameterFormat::Text), None);
+        assert_eq!(decode(b"\\xzz", ParameterFormat::Text), None);
+        assert_eq!(
+            decode(b"ab\\\\c\\001", ParameterFormat::Text),
+            Some(b"ab\\c\x01".to_vec())
+        );
+        assert_eq!(decode(b"\\9", ParameterFormat::Text), None);
+        assert_eq!(
+            decode(&[0, 1, 2], ParameterFormat::Binary),
+            Some(vec![0, 1, 2])
+        );
+    }
+
+    #[test]
+    fn test_decode_jsonb() {
+        let json = r#"{"key": "value"}"#;
+        assert_eq!(
+            param(json.as_bytes()).decode_as(ParameterFormat::Text, ParameterType::Jsonb),
+            Some(ParameterValue::Json(json))
+        );
+
+        let mut binary = vec![1];
+        binary.extend(json.as_bytes());
+        assert_eq!(
+            param(&binary).decode_as(ParameterFormat::Binary, ParameterType::Jsonb),
+            Some(ParameterValue::Json(json))
+        );
+
+        // Unknown version.
+        binary[0] = 2;
+        assert_eq!(
+            param(&binary).decode_as(ParameterFormat::Binary, ParameterType::Jsonb),
+            None
+        );
+    }
+
+    #[test]
+    fn test_decode_numeric() {
+        let decode = |data: &[u8], format| {
+            param(data)
+                .decode_as(format, ParameterType::Numeric)
+                .map(|value| match value {
+                    ParameterValue::Decimal(decimal) => decimal,
+                    _ => panic!("not decimal"),
+                })
+        };
+
+        for (data, expected) in [
+            (numeric(2, 0, NUMERIC_POS, 2, &[1234, 5600]), "1234.56"),
+            (numeric(2, 1, NUMERIC_POS, 0, &[1234, 5678]), "12345678"),
+            (numeric(1, 0, NUMERIC_NEG, 0, &[5]), "-5"),
+            (numeric(1, -1, NUMERIC_POS, 3, &[10]), "0.001"),
+            (numeric(1, 2, NUMERIC_POS, 0, &[1]), "100000000"),
+            (numeric(0, 0, NUMERIC_POS, 2, &[]), "0.00"),
+            (numeric(0, 0, NUMERIC_NAN, 0, &[]), "NaN"),
+            (numeric(0, 0, NUMERIC_NINF, 0, &[]), "-Infinity"),
+        ] {
+            assert_eq!(
+                decode(&data, ParameterFormat::Binary).as_deref(),
+                Some(expected)
+            );
+        }
+
+        assert_eq!(
+            decode(b" 1234.56 ", ParameterFormat::Text).as_deref(),
+            Some("1234.56")
+        );
+        assert_eq!(decode(&[0, 1], ParameterFormat::Binary), None);
+        assert_eq!(
+            decode(
+                &numeric(1, 0, NUMERIC_POS, 0, &[10_000]),
+                ParameterFormat::Binary
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_decode_money() {
+        let decode = |data: &[u8], format| {
+            param(data)
+                .decode_as(format, ParameterType::Money)
+                .map(|value| match value {
+                    ParameterValue::Decimal(decimal) => decimal,
+                    _ => panic!("not decimal"),
+                })
+        };
+
+        assert_eq!(
+            decode(&123456_i64.to_be_bytes(), ParameterFormat::Binary).as_deref(),
+            Some("1234.56")
+        );
+        assert_eq!(
+            decode(&(-5_i64).to_be_bytes(), ParameterFormat::Binary).as_deref(),
+            Some("-0.05")
+        );
+        assert_eq!(
+            decode(b"-$1,234.56", ParameterFormat::Text).as_deref(),
+            Some("-1234.56")
+        );
+        assert_eq!(
+            decode(b"12.50", ParameterFormat::Text).as_deref(),
+            Some("12.50")
+        );
+        assert_eq!(decode(b"$", ParameterFormat::Text), None);
+        assert_eq!(decode(&[1, 2], ParameterFormat::Binary), None);
+    }
+
+    #[test]
+    fn test_decode_as_null() {
+        let null = Parameter {
+            len: -1,
+            data: vec![],
+        };
+        assert!(null
+            .decode_as(ParameterFormat::Binary, ParameterType::Bytea)
+            .is_none());
+    }
+
     #[test]
     fn test_empty_params() {
         let params = PdParameters::default();
@@ -8,7 +8,7 @@ use std::path::Path;
 
 use libloading::{library_filename, Library, Symbol};
 
-use crate::{PdRoute, PdRouterContext, PdStr};
+use crate::{PdBytes, PdRoute, PdRouterContext, PdStr};
 
 /// Plugin interface.
 ///
@@ -28,10 +28,18 @@ pub struct Plugin<'a> {
     fini: Option<Symbol<'a, unsafe extern "C" fn()>>,
     /// Route query.
     route: Option<Symbol<'a, unsafe extern "C" fn(PdRouterContext, *mut PdRoute)>>,
+    /// Admin command.
+    admin: Option<Symbol<'a, unsafe extern "C" fn(PdStr, *mut PdBytes) -> i32>>,
+    /// Configuration from `pgdog.toml`.
+    config: Option<Symbol<'a, unsafe extern "C" fn(PdStr, *mut PdStr) -> i32>>,
     /// Compiler version.
     rustc_version: Option<Symbol<'a, unsafe extern "C" fn(*mut PdStr)>>,
     /// Plugin version.
     plugin_version: Option<Symbol<'a, unsafe extern "C" fn(*mut PdStr)>>,
+    /// pg_query version.
+    pg_query_version: Option<Symbol<'a, unsafe extern "C" fn(*mut PdStr)>>,
+    /// FFI interface version.
+    abi_version: Option<Symbol<'a, unsafe extern "C" fn() -> u32>>,
 }
 
 impl<'a> Plugin<'a> {
@@ -71,16 +79,45 @@ impl<'a> Plugin<'a> {
         let init = unsafe { library.get(b"pgdog_init\0") }.ok();
         let fini = unsafe { library.get(b"pgdog_fini\0") }.ok();
         let route = unsafe { library.get(b"pgdog_route\0") }.ok();
+        let admin = unsafe { library.get(b"pgdog_admin\0") }.ok();
+        let config = unsafe { library.get(b"pgdog_config\0") }.ok();
         let rustc_version = unsafe { library.get(b"pgdog_rustc_version\0") }.ok();
         let plugin_version = unsafe { library.get(b"pgdog_plugin_version\0") }.ok();
+        let pg_query_version = unsafe { library.get(b"pgdog_pg_query_version\0") }.ok();
+        let abi_version = unsafe { library.get(b"pgdog_plugin_abi\0") }.ok();
 
         Self {
             name: name.to_owned(),
             init,
             fini,
             route,
+            admin,
+            config,
             rustc_version,
             plugin_version,
+            pg_query_version,
+            abi_version,
+        }
+    }
+
+    /// Pass the plugin its configuration, encoded as JSON. Runs before [`Plugin::init`]
+    /// and again when the configuration changes. Returns the plugin's error
+    /// if it rejected the configuration.
+    ///
+    /// Plugins that don't accept configuration ignore it.
+    pub fn config(&self, config: &str) -> Result<(), String> {
+        match &self.config {
+            Some(func) => {
+                let mut error = PdStr::default();
+                let code = unsafe { func(config.into(), &mut error as *mut PdStr) };
+                // The plugin owns the error, so copy it right away.
+                match code {
+                    0 => Ok(()),
+                    code if error.is_empty() => Err(format!("error {}", code)),
+                    _ => Err(error.to_string()),
+                }
+            }
+            None => Ok(()),
         }
     }
 
@@ -113,6 +150,7 @@ impl<'a> Plugin<'a> {
     ///
     pub fn route(&self, context: PdRouterContext) -> Option<PdRoute> {
         if let Some(ref route) = &self.route {
+            // Plugins built with an older ABI leave newer fields at their defaults.
             let mut output = PdRoute::default();
             unsafe {
                 route(context, &mut output as *mut PdRoute);
@@ -123,6 +161,27 @@ impl<'a> Plugin<'a> {
         }
     }
 
+    /// Execute plugin's admin command. Returns the command output, or the error
+    /// if the plugin returned one. Returns `None` if the plugin has no admin interface.
+    ///
+    /// ### Arguments
+    ///
+    /// * `command`: Command sent by the admin client, without the plugin name.
+    ///
+    pub fn admin(&self, command: &str) -> Option<Result<String, String>> {
+        self.admin.as_ref().map(|admin| {
+            let mut output = PdBytes::default();
+            let code = unsafe { admin(command.into(), &mut output as *mut PdBytes) };
+            // The plugin owns the output, so copy it right away.
+            let output = String::from_utf8_lossy(&output).into_owned();
+            if code == 0 {
+                Ok(output)
+            } else {
+                Err(output)
+            }
+        })
+    }
+
     /// Returns plugin's name. This  is the same name as what
     /// is passed to [`Plugin::load`] function.
     pub fn name(&self) -> &str {
@@ -140,6 +199,35 @@ impl<'a> Plugin<'a> {
         })
     }
 
+    /// Returns the version of the `pg_query` crate used to build the plugin.
+    /// Empty if the plugin doesn't use `pgdog-plugin-build`.
+    pub fn pg_query_version(&self) -> Option<PdStr> {
+        let mut output = PdStr::default();
+        self.pg_query_version.as_ref().map(|func| unsafe {
+            func(&mut output as *mut PdStr);
+            output
+        })
+    }
+
+    /// Returns the version of the FFI interface used to build the plugin.
+    /// This must be between [`crate::comp::min_abi_version`] and [`crate::comp::abi_version`],
+    /// or the plugin won't be loaded.
+    pub fn abi_version(&self) -> Option<u32> {
+        self.abi_version.as_ref().map(|func| unsafe { func() })
+    }
+
+    /// Check that the plugin can be safely called by this version of PgDog.
+    pub fn check_abi(&self) -> Result<(), AbiError> {
+        let expected = crate::comp::abi_version();
+        match self.abi_version() {
+            None => Err(AbiError::Missing { expected }),
+            Some(found) if found > expected || found < crate::comp::min_abi_version() => {
+                Err(AbiError::Mismatch { expected, found })
+            }
+            Some(_) => Ok(()),
+        }
+    }
+
     /// Get plugin version. It's set in plugin's
     /// `Cargo.toml`.
     pub fn version(&self) -> Option<PdStr> {
@@ -150,3 +238,32 @@ impl<'a> Plugin<'a> {
         })
     }
 }
+
+/// Plugin FFI interface doesn't match PgDog's.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AbiError {
+    /// Plugin doesn't export `pgdog_plugin_abi`, likely because it was
+    /// built with an older version of `pgdog-plugin`.
+    Missing { expected: u32 },
+    /// Plugin was built with a different version of the FFI interface.
+    Mismatch { expected: u32, found: u32 },
+}
+
+impl std::fmt::Display for AbiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Missing { expected } => write!(
+                f,
+                "plugin doesn't export pgdog_plugin_abi, expected ABI version {}",
+                expected
+            ),
+            Self::Mismatch { expected, found } => write!(
+                f,
+                "plugin ABI version is {}, expected {}; rebuild the plugin with a compatible pgdog-plugin crate",
+                found, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for AbiError {}
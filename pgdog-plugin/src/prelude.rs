@@ -2,7 +2,7 @@
 
 pub use crate::pg_query;
 pub use crate::{
-    macros::{fini, init, route},
-    parameters::{Parameter, ParameterFormat, ParameterValue, Parameters},
-    Context, ReadWrite, Route, Shard,
+    macros::{admin, fini, init, route},
+    parameters::{Parameter, ParameterFormat, ParameterType, ParameterValue, Parameters},
+    Context, DataType, ReadWrite, Route, Shard,
 };
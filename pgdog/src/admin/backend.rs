@@ -14,12 +14,31 @@ use crate::net::ToBytes;
 
 use super::parser::Parser;
 use super::prelude::Message;
-use super::Error;
+use super::{Error, Response, Rows};
+
+/// Rows buffered for the client at a time.
+pub const BATCH_SIZE: usize = 1000;
 
 /// Admin backend.
-#[derive(Debug)]
 pub struct Backend {
     messages: VecDeque<Message>,
+    streaming: Option<Streaming>,
+}
+
+/// Command output still being produced.
+struct Streaming {
+    rows: Rows,
+    /// Sent after the last row.
+    complete: Vec<Message>,
+}
+
+impl std::fmt::Debug for Backend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Backend")
+            .field("messages", &self.messages)
+            .field("streaming", &self.streaming.is_some())
+            .finish()
+    }
 }
 
 impl Default for Backend {
@@ -33,6 +52,7 @@ impl Backend {
     pub fn new() -> Self {
         Self {
             messages: VecDeque::new(),
+            streaming: None,
         }
     }
 
@@ -48,24 +68,85 @@ impl Backend {
 
         let query = Query::from_bytes(message.to_bytes()?)?;
 
-        let messages = match Parser::parse(&query.query().to_lowercase()) {
+        let (sql, limit) = Parser::limit(query.query());
+
+        match Parser::parse(&sql) {
             Ok(command) => {
-                let mut messages = command.execute().await?;
-                messages.push(CommandComplete::new(command.name()).message()?);
+                let complete = vec![
+                    CommandComplete::new(command.name()).message()?,
+                    ReadyForQuery::idle().message()?,
+                ];
 
-                messages
+                match command.stream().await {
+                    Ok(Response::Messages(messages)) => {
+                        let mut rows = 0;
+                        self.messages.extend(messages.into_iter().filter(|message| {
+                            if message.code() != 'D' {
+                                return true;
+                            }
+                            rows += 1;
+                            limit.map(|limit| rows <= limit).unwrap_or(true)
+                        }));
+                        self.messages.extend(complete);
+                    }
+
+                    Ok(Response::Stream(rd, rows)) => {
+                        self.messages.push_back(rd);
+                        self.streaming = Some(Streaming {
+                            rows: match limit {
+                                Some(limit) => Box::new(rows.take(limit)),
+                                None => rows,
+                            },
+                            complete,
+                        });
+                    }
+
+                    // Keep the admin session going, e.g. after a plugin error.
+                    Err(err) => {
+                        self.messages
+                            .push_back(ErrorResponse::from_err(&err).message()?);
+                        self.messages.push_back(ReadyForQuery::idle().message()?);
+                    }
+                }
             }
-            Err(err) => vec![ErrorResponse::syntax(err.to_string().as_str()).message()?],
-        };
 
-        self.messages.extend(messages);
-        self.messages.push_back(ReadyForQuery::idle().message()?);
+            Err(err) => {
+                self.messages
+                    .push_back(ErrorResponse::syntax(err.to_string().as_str()).message()?);
+                self.messages.push_back(ReadyForQuery::idle().message()?);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Produce the next batch of rows, if the command is streaming them.
+    /// Called only once the client read everything we had, so a slow
+    /// client doesn't make us buffer the whole result.
+    fn next_batch(&mut self) -> Result<(), Error> {
+        if let Some(streaming) = self.streaming.as_mut() {
+            for _ in 0..BATCH_SIZE {
+                match streaming.rows.next() {
+                    Some(row) => self.messages.push_back(row?),
+                    None => {
+                        if let Some(streaming) = self.streaming.take() {
+                            self.messages.extend(streaming.complete);
+                        }
+                        break;
+                    }
+                }
+            }
+        }
 
         Ok(())
     }
 
     /// Receive command result.
     pub async fn read(&mut self) -> Result<Message, Error> {
+        if self.messages.is_empty() {
+            self.next_batch()?;
+        }
+
         if let Some(message) = self.messages.pop_front() {
             Ok(message)
         } else {
@@ -76,6 +157,158 @@ impl Backend {
     }
 
     pub fn done(&self) -> bool {
-        self.messages.is_empty()
+        self.messages.is_empty() && self.streaming.is_none()
+    }
+
+    /// Number of messages buffered for the client.
+    pub fn buffered(&self) -> usize {
+        self.messages.len()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::SocketAddr;
+
+    use crate::frontend::comms::comms;
+    use crate::net::{BackendKeyData, Parameters};
+
+    use super::*;
+
+    async fn show(backend: &mut Backend, query: &str) -> (usize, usize) {
+        backend
+            .send(&ClientRequest::from(vec![Query::new(query).into()]))
+            .await
+            .unwrap();
+        // Only the RowDescription is ready, rows come later.
+        assert_eq!(backend.buffered(), 1);
+
+        let (mut rows, mut peak) = (0, 0);
+        while !backend.done() {
+            let message = backend.read().await.unwrap();
+            peak = peak.max(backend.buffered());
+            if message.code() == 'D' {
+                rows += 1;
+            }
+        }
+
+        (rows, peak)
+    }
+
+    #[tokio::test]
+    async fn test_show_clients_streaming() {
+        let addr: SocketAddr = "127.0.0.1:6432".parse().unwrap();
+        let mut clients = (0..5_000)
+            .map(|_| comms().connect(&BackendKeyData::new(), addr, &Parameters::default()))
+            .collect::<Vec<_>>();
+
+        let mut backend = Backend::new();
+
+        let (rows, peak) = show(&mut backend, "SHOW CLIENTS").await;
+        assert!(rows >= 5_000);
+        // Never more than a batch of rows (plus CommandComplete and ReadyForQuery) in memory.
+        assert!(peak <= BATCH_SIZE + 2);
+
+        let (rows, peak) = show(&mut backend, "SHOW CLIENTS LIMIT 1500;").await;
+        assert_eq!(rows, 1500);
+        assert!(peak <= BATCH_SIZE + 2);
+
+        for client in clients.iter_mut() {
+            client.disconnect();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_plugin_error() {
+        let mut backend = Backend::new();
+        backend
+            .send(&ClientRequest::from(vec![Query::new(
+                "PLUGIN My_Plugin Show State;",
+            )
+            .into()]))
+            .await
+            .unwrap();
+
+        let error = backend.read().await.unwrap();
+        assert_eq!(error.code(), 'E');
+        let error = ErrorResponse::from_bytes(error.to_bytes().unwrap()).unwrap();
+        // Plugin names aren't lowercased.
+        assert_eq!(error.message, "plugin \"My_Plugin\" is not loaded");
+        assert_eq!(backend.read().await.unwrap().code(), 'Z');
+    }
+
+    #[tokio::test]
+    async fn test_mapping_case_preserved() {
+        use crate::config::FlexibleType;
+        use crate::frontend::router::sharding::{ListMappings, ListShards};
+
+        let list = ListMappings::global().shared(
+            ("Tenant_DB".into(), "Tenant_Id".into(), None),
+            ListShards::new(&[]),
+        );
+        let mut backend = Backend::new();
+
+        for (query, command) in [
+            (
+                "ADD MAPPING Tenant_DB Tenant_Id 'Acme' TO SHARD 1",
+                "ADD MAPPING",
+            ),
+            (
+                "ADD MAPPING Tenant_DB Tenant_Id Globex TO SHARD 0",
+                "ADD MAPPING",
+            ),
+            ("DROP MAPPING Tenant_DB Tenant_Id 'Globex'", "DROP MAPPING"),
+        ] {
+            backend
+                .send(&ClientRequest::from(vec![Query::new(query).into()]))
+                .await
+                .unwrap();
+            let complete = backend.read().await.unwrap();
+            assert_eq!(complete.code(), 'C', "{}", query);
+            let complete = CommandComplete::from_bytes(complete.to_bytes().unwrap()).unwrap();
+            assert_eq!(complete.command(), command);
+            assert_eq!(backend.read().await.unwrap().code(), 'Z');
+        }
+
+        // Database, column and value kept their case.
+        let mapping = list.load();
+        assert_eq!(mapping.len(), 1);
+        assert_eq!(mapping.get(&FlexibleType::String("Acme".into())), Some(&1));
+
+        // Lowercase names don't match.
+        backend
+            .send(&ClientRequest::from(vec![Query::new(
+                "DROP MAPPING tenant_db tenant_id 'Acme'",
+            )
+            .into()]))
+            .await
+            .unwrap();
+        assert_eq!(backend.read().await.unwrap().code(), 'E');
+        assert_eq!(backend.read().await.unwrap().code(), 'Z');
+        assert_eq!(list.load().len(), 1);
+    }
+
+    #[test]
+    fn test_limit() {
+        assert_eq!(
+            Parser::limit("show clients limit 10;"),
+            ("show clients".to_string(), Some(10))
+        );
+        assert_eq!(
+            Parser::limit("SHOW POOLS LIMIT 0"),
+            ("SHOW POOLS".to_string(), Some(0))
+        );
+        assert_eq!(
+            Parser::limit("show clients"),
+            ("show clients".to_string(), None)
+        );
+        assert_eq!(
+            Parser::limit("show limit 5"),
+            ("show limit 5".to_string(), None)
+        );
+        assert_eq!(
+            Parser::limit("set x limit 5"),
+            ("set x limit 5".to_string(), None)
+        );
     }
 }
@@ -36,6 +36,24 @@ pub enum Error {
 
     #[error("address is not valid")]
     InvalidAddress,
+
+    #[error("database \"{0}\" doesn't have a list mapping for column \"{1}\"")]
+    NoListMapping(String, String),
+
+    #[error("database \"{0}\" doesn't have shard {1}")]
+    NoShard(String, usize),
+
+    #[error("plugin \"{0}\" is not loaded")]
+    NoPlugin(String),
+
+    #[error("plugin \"{0}\" has no admin interface")]
+    NoPluginAdmin(String),
+
+    #[error("plugin \"{0}\": {1}")]
+    Plugin(String, String),
+
+    #[error("client {0} isn't connected")]
+    NoClient(i32),
 }
 
 impl From<crate::backend::Error> for Error {
@@ -0,0 +1,302 @@
+//! Admin HTTP API.
+//!
+//! Allows external tools to pause, resume and reconnect pools
+//! without using the admin database.
+//!
+//! - `GET /pools`
+//! - `POST /pools/{database}/pause`
+//! - `POST /pools/{database}/resume`
+//! - `POST /pools/{database}/reconnect`
+//! - `POST /pools/{database}/shrink`
+//!
+//! All requests must include the `Authorization: Bearer <admin_http_token>` header.
+
+use std::convert::Infallible;
+use std::net::SocketAddr;
+
+use http_body_util::Full;
+use hyper::body::Bytes;
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{header, Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use serde::Serialize;
+use serde_json::json;
+use tokio::net::TcpListener;
+use tracing::info;
+
+use crate::backend::databases::databases;
+use crate::backend::pool::stats::Stats;
+use crate::config::config;
+
+use super::{pause::Pause, reconnect::Reconnect, shrink::Shrink};
+
+/// Pool state returned by `GET /pools`.
+#[derive(Serialize, Debug)]
+struct PoolState {
+    id: u64,
+    database: String,
+    user: String,
+    addr: String,
+    port: u16,
+    shard: usize,
+    role: String,
+    cl_waiting: usize,
+    sv_idle: usize,
+    sv_active: usize,
+    sv_total: usize,
+    pool_mode: String,
+    paused: bool,
+    banned: bool,
+    online: bool,
+    errors: usize,
+    stats: Stats,
+}
+
+fn pools() -> Vec<PoolState> {
+    let mut pools = vec![];
+
+    for (user, cluster) in databases().all() {
+        for (shard_num, shard) in cluster.shards().iter().enumerate() {
+            for (role, pool) in shard.pools_with_roles() {
+                let state = pool.state();
+                pools.push(PoolState {
+                    id: pool.id(),
+                    database: user.database.clone(),
+                    user: user.user.clone(),
+                    addr: pool.addr().host.clone(),
+                    port: pool.addr().port,
+                    shard: shard_num,
+                    role: role.to_string(),
+                    cl_waiting: state.waiting,
+                    sv_idle: state.idle,
+                    sv_active: state.checked_out,
+                    sv_total: state.total,
+                    pool_mode: state.pooler_mode.to_string(),
+                    paused: state.paused,
+                    banned: state.banned,
+                    online: state.online,
+                    errors: state.errors,
+                    stats: state.stats,
+                });
+            }
+        }
+    }
+
+    pools
+}
+
+fn response(status: StatusCode, body: serde_json::Value) -> Response<Full<Bytes>> {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Full::new(Bytes::from(body.to_string())))
+        .unwrap_or_else(|_| Response::new(Full::new(Bytes::new())))
+}
+
+fn error(status: StatusCode, message: &str) -> Response<Full<Bytes>> {
+    response(status, json!({ "error": message }))
+}
+
+/// Compare tokens without leaking their contents through timing.
+fn token_matches(expected: &str, provided: &str) -> bool {
+    expected.len() == provided.len()
+        && expected
+            .bytes()
+            .zip(provided.bytes())
+            .fold(0, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+fn authorized<B>(req: &Request<B>, token: &str) -> bool {
+    req.headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(|provided| token_matches(token, provided.trim()))
+        .unwrap_or(false)
+}
+
+/// Is this request for the admin API?
+pub fn is_admin_path(path: &str) -> bool {
+    path == "/pools" || path.starts_with("/pools/")
+}
+
+/// Handle an admin API request.
+pub fn handle<B>(req: &Request<B>) -> Response<Full<Bytes>> {
+    let config = config();
+    let Some(ref token) = config.config.general.admin_http_token else {
+        return error(StatusCode::NOT_FOUND, "admin API is disabled");
+    };
+
+    if !authorized(req, token) {
+        return error(StatusCode::UNAUTHORIZED, "unauthorized");
+    }
+
+    let path = req.uri().path().trim_end_matches('/');
+    let parts = path.split('/').skip(1).collect::<Vec<_>>();
+
+    match (req.method(), parts.as_slice()) {
+        (&Method::GET, ["pools"]) => response(StatusCode::OK, json!({ "pools": pools() })),
+
+        (&Method::POST, ["pools", database, action @ ("pause" | "resume")]) => {
+            let pools = Pause::database(database, *action == "resume").apply();
+            if pools == 0 {
+                error(StatusCode::NOT_FOUND, "database not found")
+            } else {
+                info!(r#"{} database "{}" via admin HTTP API"#, action, database);
+                response(
+                    StatusCode::OK,
+                    json!({ "database": database, "action": action, "pools": pools }),
+                )
+            }
+        }
+
+        (&Method::POST, ["pools", database, "reconnect"]) => {
+            if Reconnect::database(database).apply() {
+                info!(r#"reconnect database "{}" via admin HTTP API"#, database);
+                response(
+                    StatusCode::OK,
+                    json!({ "database": database, "action": "reconnect" }),
+                )
+            } else {
+                error(StatusCode::NOT_FOUND, "database not found")
+            }
+        }
+
+        (&Method::POST, ["pools", database, "shrink"]) => {
+            let pools = Shrink::database(database).apply();
+            if pools.is_empty() {
+                error(StatusCode::NOT_FOUND, "database not found")
+            } else {
+                info!(r#"shrink database "{}" via admin HTTP API"#, database);
+                response(
+                    StatusCode::OK,
+                    json!({ "database": database, "action": "shrink", "pools": pools }),
+                )
+            }
+        }
+
+        (_, ["pools"]) | (_, ["pools", _, "pause" | "resume" | "reconnect" | "shrink"]) => {
+            error(StatusCode::METHOD_NOT_ALLOWED, "method not allowed")
+        }
+
+        _ => error(StatusCode::NOT_FOUND, "not found"),
+    }
+}
+
+async fn service(req: Request<hyper::body::Incoming>) -> Result<Response<Full<Bytes>>, Infallible> {
+    Ok(handle(&req))
+}
+
+/// Run the admin HTTP API on a separate port.
+pub async fn server(port: u16) -> std::io::Result<()> {
+    info!("Admin HTTP API http://0.0.0.0:{}", port);
+    let addr = SocketAddr::from(([0, 0, 0, 0], port));
+    let listener = TcpListener::bind(addr).await?;
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let io = TokioIo::new(stream);
+
+        tokio::task::spawn(async move {
+            if let Err(err) = http1::Builder::new()
+                .serve_connection(io, service_fn(service))
+                .await
+            {
+                eprintln!("Admin HTTP API error: {:?}", err);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::config::{set, test::load_test};
+
+    use super::*;
+
+    fn request(method: Method, path: &str, token: Option<&str>) -> Request<()> {
+        let mut req = Request::builder().method(method).uri(path);
+        if let Some(token) = token {
+            req = req.header(header::AUTHORIZATION, format!("Bearer {}", token));
+        }
+        req.body(()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_admin_http_api() {
+        load_test();
+
+        // Disabled without a token.
+        let res = handle(&request(Method::GET, "/pools", Some("secret")));
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        let mut config = (*config()).clone();
+        config.config.general.admin_http_token = Some("secret".into());
+        set(config).unwrap();
+
+        let res = handle(&request(Method::GET, "/pools", None));
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        let res = handle(&request(Method::GET, "/pools", Some("wrong")));
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        let res = handle(&request(Method::GET, "/pools", Some("secret")));
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(!pools().is_empty());
+        assert!(pools().iter().all(|pool| !pool.paused));
+
+        let res = handle(&request(Method::POST, "/pools/pgdog/pause", Some("secret")));
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(pools().iter().all(|pool| pool.paused));
+
+        let res = handle(&request(
+            Method::POST,
+            "/pools/pgdog/resume",
+            Some("secret"),
+        ));
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(pools().iter().all(|pool| !pool.paused));
+
+        let id = pools()[0].id;
+        let res = handle(&request(
+            Method::POST,
+            "/pools/pgdog/reconnect",
+            Some("secret"),
+        ));
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_ne!(pools()[0].id, id);
+
+        let res = handle(&request(
+            Method::POST,
+            "/pools/pgdog/shrink",
+            Some("secret"),
+        ));
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let res = handle(&request(
+            Method::POST,
+            "/pools/missing/pause",
+            Some("secret"),
+        ));
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        let res = handle(&request(
+            Method::POST,
+            "/pools/missing/reconnect",
+            Some("secret"),
+        ));
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        let res = handle(&request(
+            Method::POST,
+            "/pools/missing/shrink",
+            Some("secret"),
+        ));
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        let res = handle(&request(Method::GET, "/pools/pgdog/pause", Some("secret")));
+        assert_eq!(res.status(), StatusCode::METHOD_NOT_ALLOWED);
+
+        assert!(is_admin_path("/pools"));
+        assert!(is_admin_path("/pools/pgdog/pause"));
+        assert!(!is_admin_path("/metrics"));
+    }
+}
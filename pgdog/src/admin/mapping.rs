@@ -0,0 +1,142 @@
+//! ADD MAPPING and DROP MAPPING.
+use crate::{
+    backend::databases::databases,
+    config::{config, FlexibleType},
+    frontend::router::sharding::ListMappings,
+};
+
+use super::prelude::*;
+
+/// Change a list mapping at runtime.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mapping {
+    database: String,
+    column: String,
+    value: FlexibleType,
+    /// Shard to send the value to, or `None` to drop it.
+    shard: Option<usize>,
+}
+
+#[async_trait]
+impl Command for Mapping {
+    fn name(&self) -> String {
+        if self.shard.is_some() {
+            "ADD MAPPING".into()
+        } else {
+            "DROP MAPPING".into()
+        }
+    }
+
+    fn parse(sql: &str) -> Result<Self, Error> {
+        let parts = sql.split_whitespace().collect::<Vec<_>>();
+        let keywords = parts
+            .iter()
+            .map(|part| part.to_lowercase())
+            .collect::<Vec<_>>();
+        let keywords = keywords.iter().map(|k| k.as_str()).collect::<Vec<_>>();
+
+        match (&keywords[..], &parts[..]) {
+            (
+                ["add", "mapping", _, _, _, "to", "shard", shard],
+                [_, _, database, column, value, ..],
+            ) => Ok(Self {
+                database: database.to_string(),
+                column: column.to_string(),
+                value: Self::value(value),
+                shard: Some(shard.parse()?),
+            }),
+
+            (["drop", "mapping", _, _, _], [_, _, database, column, value]) => Ok(Self {
+                database: database.to_string(),
+                column: column.to_string(),
+                value: Self::value(value),
+                shard: None,
+            }),
+
+            _ => Err(Error::Syntax),
+        }
+    }
+
+    async fn execute(&self) -> Result<Vec<Message>, Error> {
+        let mappings = ListMappings::global();
+
+        let changed = if let Some(shard) = self.shard {
+            let shards = databases()
+                .all()
+                .values()
+                .filter(|cluster| cluster.name() == self.database)
+                .map(|cluster| cluster.shards().len())
+                .max();
+            if shards.is_some_and(|shards| shard >= shards) {
+                return Err(Error::NoShard(self.database.clone(), shard));
+            }
+
+            mappings.add(&self.database, &self.column, self.value.clone(), shard)
+        } else {
+            mappings.remove(&self.database, &self.column, &self.value)
+        };
+
+        if changed.is_empty() {
+            return Err(Error::NoListMapping(
+                self.database.clone(),
+                self.column.clone(),
+            ));
+        }
+
+        if let Some(ref path) = config().config.general.sharded_mappings_path {
+            mappings.save(path, &changed)?;
+        }
+
+        Ok(vec![])
+    }
+}
+
+impl Mapping {
+    /// Quoted values are strings, others are integers or UUIDs if they parse as one.
+    fn value(value: &str) -> FlexibleType {
+        if let Some(value) = value
+            .strip_prefix('\'')
+            .and_then(|value| value.strip_suffix('\''))
+        {
+            FlexibleType::String(value.to_string())
+        } else if let Ok(value) = value.parse::<i64>() {
+            FlexibleType::Integer(value)
+        } else if let Ok(value) = value.parse::<uuid::Uuid>() {
+            FlexibleType::Uuid(value)
+        } else {
+            FlexibleType::String(value.to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_mapping_command() {
+        let cmd = Mapping::parse("ADD MAPPING pgdog tenant_id 1234 TO SHARD 1").unwrap();
+        assert_eq!(
+            cmd,
+            Mapping {
+                database: "pgdog".into(),
+                column: "tenant_id".into(),
+                value: FlexibleType::Integer(1234),
+                shard: Some(1),
+            }
+        );
+        assert_eq!(cmd.name(), "ADD MAPPING");
+
+        let cmd = Mapping::parse("drop mapping pgdog tenant_id 'Acme'").unwrap();
+        assert_eq!(cmd.value, FlexibleType::String("Acme".into()));
+        assert_eq!(cmd.shard, None);
+
+        let cmd =
+            Mapping::parse("DROP MAPPING pgdog tenant_id 11111111-1111-1111-1111-111111111111")
+                .unwrap();
+        assert!(matches!(cmd.value, FlexibleType::Uuid(_)));
+
+        assert!(Mapping::parse("ADD MAPPING pgdog tenant_id 1234").is_err());
+        assert!(Mapping::parse("ADD MAPPING pgdog tenant_id 1234 TO SHARD one").is_err());
+    }
+}
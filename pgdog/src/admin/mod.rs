@@ -7,9 +7,12 @@ use crate::net::messages::Message;
 pub mod backend;
 pub mod ban;
 pub mod error;
+pub mod http;
+pub mod mapping;
 pub mod named_row;
 pub mod parser;
 pub mod pause;
+pub mod plugin;
 pub mod prelude;
 pub mod probe;
 pub mod reconnect;
@@ -19,18 +22,35 @@ pub mod set;
 pub mod setup_schema;
 pub mod show_clients;
 pub mod show_config;
+pub mod show_databases;
+pub mod show_dry_run;
 pub mod show_lists;
+pub mod show_memory;
 pub mod show_peers;
 pub mod show_pools;
 pub mod show_prepared_statements;
 pub mod show_query_cache;
+pub mod show_routing_history;
 pub mod show_servers;
+pub mod show_session_pins;
 pub mod show_stats;
 pub mod show_version;
+pub mod shrink;
 pub mod shutdown;
 
 pub use error::Error;
 
+/// Rows produced on demand, so large outputs aren't built in memory all at once.
+pub type Rows = Box<dyn Iterator<Item = Result<Message, Error>> + Send>;
+
+/// Command output.
+pub enum Response {
+    /// All messages, built up front.
+    Messages(Vec<Message>),
+    /// RowDescription followed by rows produced as the client reads them.
+    Stream(Message, Rows),
+}
+
 /// All pooler commands implement this trait.
 #[async_trait]
 pub trait Command: Sized {
@@ -1,12 +1,15 @@
 //! Admin command parser.
 
 use super::{
-    ban::Ban, pause::Pause, prelude::Message, probe::Probe, reconnect::Reconnect, reload::Reload,
-    reset_query_cache::ResetQueryCache, set::Set, setup_schema::SetupSchema,
-    show_clients::ShowClients, show_config::ShowConfig, show_lists::ShowLists,
-    show_peers::ShowPeers, show_pools::ShowPools, show_prepared_statements::ShowPreparedStatements,
-    show_query_cache::ShowQueryCache, show_servers::ShowServers, show_stats::ShowStats,
-    show_version::ShowVersion, shutdown::Shutdown, Command, Error,
+    ban::Ban, mapping::Mapping, pause::Pause, plugin::PluginAdmin, prelude::Message, probe::Probe,
+    reconnect::Reconnect, reload::Reload, reset_query_cache::ResetQueryCache, set::Set,
+    setup_schema::SetupSchema, show_clients::ShowClients, show_config::ShowConfig,
+    show_databases::ShowDatabases, show_dry_run::ShowDryRun, show_lists::ShowLists,
+    show_memory::ShowMemory, show_peers::ShowPeers, show_pools::ShowPools,
+    show_prepared_statements::ShowPreparedStatements, show_query_cache::ShowQueryCache,
+    show_routing_history::ShowRoutingHistory, show_servers::ShowServers,
+    show_session_pins::ShowSessionPins, show_stats::ShowStats, show_version::ShowVersion,
+    shrink::Shrink, shutdown::Shutdown, Command, Error, Response,
 };
 
 use tracing::debug;
@@ -19,6 +22,8 @@ pub enum ParseResult {
     Reload(Reload),
     ShowPools(ShowPools),
     ShowConfig(ShowConfig),
+    ShowDatabases(ShowDatabases),
+    ShowDryRun(ShowDryRun),
     ShowServers(ShowServers),
     ShowPeers(ShowPeers),
     ShowQueryCache(ShowQueryCache),
@@ -27,11 +32,17 @@ pub enum ParseResult {
     ShowVersion(ShowVersion),
     SetupSchema(SetupSchema),
     Shutdown(Shutdown),
+    Shrink(Shrink),
     ShowLists(ShowLists),
     ShowPrepared(ShowPreparedStatements),
+    ShowMemory(ShowMemory),
+    ShowSessionPins(ShowSessionPins),
+    ShowRoutingHistory(ShowRoutingHistory),
     Set(Set),
     Ban(Ban),
     Probe(Probe),
+    Mapping(Mapping),
+    Plugin(PluginAdmin),
 }
 
 impl ParseResult {
@@ -46,6 +57,8 @@ impl ParseResult {
             Reload(reload) => reload.execute().await,
             ShowPools(show_pools) => show_pools.execute().await,
             ShowConfig(show_config) => show_config.execute().await,
+            ShowDatabases(show_databases) => show_databases.execute().await,
+            ShowDryRun(show_dry_run) => show_dry_run.execute().await,
             ShowServers(show_servers) => show_servers.execute().await,
             ShowPeers(show_peers) => show_peers.execute().await,
             ShowQueryCache(show_query_cache) => show_query_cache.execute().await,
@@ -54,11 +67,25 @@ impl ParseResult {
             ShowVersion(show_version) => show_version.execute().await,
             SetupSchema(setup_schema) => setup_schema.execute().await,
             Shutdown(shutdown) => shutdown.execute().await,
+            Shrink(shrink) => shrink.execute().await,
             ShowLists(show_lists) => show_lists.execute().await,
             ShowPrepared(cmd) => cmd.execute().await,
+            ShowMemory(show_memory) => show_memory.execute().await,
+            ShowSessionPins(show_session_pins) => show_session_pins.execute().await,
+            ShowRoutingHistory(show_routing_history) => show_routing_history.execute().await,
             Set(set) => set.execute().await,
             Ban(ban) => ban.execute().await,
             Probe(probe) => probe.execute().await,
+            Mapping(mapping) => mapping.execute().await,
+            Plugin(plugin) => plugin.execute().await,
+        }
+    }
+
+    /// Execute command, producing rows on demand if the command supports it.
+    pub async fn stream(&self) -> Result<Response, Error> {
+        match self {
+            ParseResult::ShowClients(show_clients) => show_clients.stream(),
+            _ => Ok(Response::Messages(self.execute().await?)),
         }
     }
 
@@ -73,6 +100,8 @@ impl ParseResult {
             Reload(reload) => reload.name(),
             ShowPools(show_pools) => show_pools.name(),
             ShowConfig(show_config) => show_config.name(),
+            ShowDatabases(show_databases) => show_databases.name(),
+            ShowDryRun(show_dry_run) => show_dry_run.name(),
             ShowServers(show_servers) => show_servers.name(),
             ShowPeers(show_peers) => show_peers.name(),
             ShowQueryCache(show_query_cache) => show_query_cache.name(),
@@ -81,11 +110,17 @@ impl ParseResult {
             ShowVersion(show_version) => show_version.name(),
             SetupSchema(setup_schema) => setup_schema.name(),
             Shutdown(shutdown) => shutdown.name(),
+            Shrink(shrink) => shrink.name(),
             ShowLists(show_lists) => show_lists.name(),
             ShowPrepared(show) => show.name(),
+            ShowMemory(show_memory) => show_memory.name(),
+            ShowSessionPins(show_session_pins) => show_session_pins.name(),
+            ShowRoutingHistory(show_routing_history) => show_routing_history.name(),
             Set(set) => set.name(),
             Ban(ban) => ban.name(),
             Probe(probe) => probe.name(),
+            Mapping(mapping) => mapping.name(),
+            Plugin(plugin) => plugin.name(),
         }
     }
 }
@@ -94,21 +129,44 @@ impl ParseResult {
 pub struct Parser;
 
 impl Parser {
+    /// Remove `LIMIT <n>` from the end of a SHOW command, if any.
+    pub fn limit(sql: &str) -> (String, Option<usize>) {
+        let sql = sql.trim().replace(";", "");
+        let parts = sql.split_whitespace().collect::<Vec<_>>();
+
+        if let [show, command @ .., limit, rows] = parts.as_slice() {
+            if show.eq_ignore_ascii_case("show")
+                && !command.is_empty()
+                && limit.eq_ignore_ascii_case("limit")
+            {
+                if let Ok(rows) = rows.parse::<usize>() {
+                    return (format!("{} {}", show, command.join(" ")), Some(rows));
+                }
+            }
+        }
+
+        (sql, None)
+    }
+
     /// Parse the query and return a command we can execute.
     pub fn parse(sql: &str) -> Result<ParseResult, Error> {
-        let sql = sql.trim().replace(";", "").to_lowercase();
+        let original = sql.trim().replace(";", "");
+        let sql = original.to_lowercase();
         let mut iter = sql.split(" ");
 
         Ok(match iter.next().ok_or(Error::Syntax)?.trim() {
             "pause" | "resume" => ParseResult::Pause(Pause::parse(&sql)?),
             "shutdown" => ParseResult::Shutdown(Shutdown::parse(&sql)?),
             "reconnect" => ParseResult::Reconnect(Reconnect::parse(&sql)?),
+            "shrink" => ParseResult::Shrink(Shrink::parse(&sql)?),
             "reload" => ParseResult::Reload(Reload::parse(&sql)?),
             "ban" | "unban" => ParseResult::Ban(Ban::parse(&sql)?),
             "show" => match iter.next().ok_or(Error::Syntax)?.trim() {
                 "clients" => ParseResult::ShowClients(ShowClients::parse(&sql)?),
                 "pools" => ParseResult::ShowPools(ShowPools::parse(&sql)?),
                 "config" => ParseResult::ShowConfig(ShowConfig::parse(&sql)?),
+                "databases" => ParseResult::ShowDatabases(ShowDatabases::parse(&sql)?),
+                "dry_run" => ParseResult::ShowDryRun(ShowDryRun::parse(&sql)?),
                 "servers" => ParseResult::ShowServers(ShowServers::parse(&sql)?),
                 "peers" => ParseResult::ShowPeers(ShowPeers::parse(&sql)?),
                 "query_cache" => ParseResult::ShowQueryCache(ShowQueryCache::parse(&sql)?),
@@ -116,6 +174,9 @@ impl Parser {
                 "version" => ParseResult::ShowVersion(ShowVersion::parse(&sql)?),
                 "lists" => ParseResult::ShowLists(ShowLists::parse(&sql)?),
                 "prepared" => ParseResult::ShowPrepared(ShowPreparedStatements::parse(&sql)?),
+                "memory" => ParseResult::ShowMemory(ShowMemory::parse(&sql)?),
+                "session_pins" => ParseResult::ShowSessionPins(ShowSessionPins::parse(&sql)?),
+                "routing" => ParseResult::ShowRoutingHistory(ShowRoutingHistory::parse(&sql)?),
                 command => {
                     debug!("unknown admin show command: '{}'", command);
                     return Err(Error::Syntax);
@@ -136,6 +197,10 @@ impl Parser {
                 }
             },
             "probe" => ParseResult::Probe(Probe::parse(&sql)?),
+            // Mapped values are case sensitive.
+            "add" | "drop" => ParseResult::Mapping(Mapping::parse(&original)?),
+            // Plugin names and commands are passed as-is.
+            "plugin" => ParseResult::Plugin(PluginAdmin::parse(&original)?),
             // TODO: This is not ready yet. We have a race and
             // also the changed settings need to be propagated
             // into the pools.
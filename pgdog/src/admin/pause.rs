@@ -13,6 +13,47 @@ pub struct Pause {
     resume: bool,
 }
 
+impl Pause {
+    /// Pause or resume all pools for the database.
+    pub fn database(database: &str, resume: bool) -> Self {
+        Self {
+            user: None,
+            database: Some(database.to_owned()),
+            resume,
+        }
+    }
+
+    /// Pause or resume matching pools. Returns the number of pools affected.
+    pub fn apply(&self) -> usize {
+        let mut pools = 0;
+
+        for (name, cluster) in databases().all() {
+            if let Some(ref user) = self.user {
+                if &name.user != user {
+                    continue;
+                }
+            }
+            if let Some(ref database) = self.database {
+                if &name.database != database {
+                    continue;
+                }
+            }
+            for shard in cluster.shards() {
+                for pool in shard.pools() {
+                    if self.resume {
+                        pool.resume();
+                    } else {
+                        pool.pause();
+                    }
+                    pools += 1;
+                }
+            }
+        }
+
+        pools
+    }
+}
+
 #[async_trait]
 impl Command for Pause {
     fn parse(sql: &str) -> Result<Self, Error> {
@@ -43,28 +84,7 @@ impl Command for Pause {
     }
 
     async fn execute(&self) -> Result<Vec<Message>, Error> {
-        for (name, cluster) in databases().all() {
-            if let Some(ref user) = self.user {
-                if &name.user != user {
-                    continue;
-                }
-            }
-            if let Some(ref database) = self.database {
-                if &name.database != database {
-                    continue;
-                }
-            }
-            for shard in cluster.shards() {
-                for pool in shard.pools() {
-                    if self.resume {
-                        pool.resume();
-                    } else {
-                        pool.pause();
-                    }
-                }
-            }
-        }
-
+        self.apply();
         Ok(vec![])
     }
 
@@ -0,0 +1,150 @@
+//! PLUGIN command, forwarded to the plugin's admin interface.
+
+use crate::{net::messages::data_row::Data, plugin::plugins};
+
+use super::prelude::*;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PluginAdmin {
+    plugin: String,
+    command: String,
+}
+
+#[async_trait]
+impl Command for PluginAdmin {
+    fn name(&self) -> String {
+        "PLUGIN".into()
+    }
+
+    fn parse(sql: &str) -> Result<Self, Error> {
+        let mut parts = sql.trim().splitn(3, char::is_whitespace);
+        let (Some(_), Some(plugin), Some(command)) = (parts.next(), parts.next(), parts.next())
+        else {
+            return Err(Error::Syntax);
+        };
+
+        let command = command.trim();
+        if command.is_empty() {
+            return Err(Error::Syntax);
+        }
+
+        Ok(Self {
+            plugin: plugin.to_owned(),
+            command: command.to_owned(),
+        })
+    }
+
+    async fn execute(&self) -> Result<Vec<Message>, Error> {
+        let plugin = plugins()
+            .and_then(|plugins| plugins.iter().find(|plugin| plugin.name() == self.plugin))
+            .ok_or_else(|| Error::NoPlugin(self.plugin.clone()))?;
+
+        match plugin.admin(&self.command) {
+            None => Err(Error::NoPluginAdmin(self.plugin.clone())),
+            Some(Err(err)) => Err(Error::Plugin(self.plugin.clone(), err)),
+            Some(Ok(output)) => rows(&output),
+        }
+    }
+}
+
+/// Convert plugin output into rows. Output with tabs in the first line
+/// is a table, with column names in the first line. Anything else is text,
+/// one row per line.
+fn rows(output: &str) -> Result<Vec<Message>, Error> {
+    let mut lines = output.lines();
+
+    let (columns, lines) = match lines.clone().next() {
+        Some(header) if header.contains('\t') => {
+            lines.next();
+            (header.split('\t').collect::<Vec<_>>(), lines)
+        }
+        _ => (vec!["output"], lines),
+    };
+
+    let fields = columns
+        .iter()
+        .map(|column| Field::text(column))
+        .collect::<Vec<_>>();
+    let mut messages = vec![RowDescription::new(&fields).message()?];
+
+    for line in lines {
+        // Extra values, and tabs in text output, stay in the last column.
+        let mut values = line.splitn(fields.len(), '\t');
+
+        let mut dr = DataRow::new();
+        for _ in &fields {
+            match values.next() {
+                Some(value) => dr.add(value),
+                None => dr.add(Data::null()),
+            };
+        }
+        messages.push(dr.message()?);
+    }
+
+    Ok(messages)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::net::{FromBytes, ToBytes};
+
+    use super::*;
+
+    fn row(values: &[Option<&str>]) -> Message {
+        let mut dr = DataRow::new();
+        for value in values {
+            match value {
+                Some(value) => dr.add(*value),
+                None => dr.add(Data::null()),
+            };
+        }
+        dr.message().unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        let cmd = PluginAdmin::parse("PLUGIN my_plugin Refresh Tenants").unwrap();
+        assert_eq!(
+            cmd,
+            PluginAdmin {
+                plugin: "my_plugin".into(),
+                command: "Refresh Tenants".into(),
+            }
+        );
+        assert!(PluginAdmin::parse("plugin my_plugin").is_err());
+        assert!(PluginAdmin::parse("plugin").is_err());
+    }
+
+    #[test]
+    fn test_text_output() {
+        // Single line with tabs is a table without rows.
+        let messages = rows("one\ttwo words").unwrap();
+        assert_eq!(messages.len(), 1);
+
+        let messages = rows("first\nsecond").unwrap();
+        let rd = RowDescription::from_bytes(messages[0].to_bytes().unwrap()).unwrap();
+        assert_eq!(rd.field(0).unwrap().name, "output");
+        assert_eq!(messages[1], row(&[Some("first")]));
+        assert_eq!(messages[2], row(&[Some("second")]));
+
+        assert_eq!(rows("").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_tsv_output() {
+        let messages = rows("tenant\tshard\nacme\t0\nglobex").unwrap();
+        let rd = RowDescription::from_bytes(messages[0].to_bytes().unwrap()).unwrap();
+        assert_eq!(rd.fields.len(), 2);
+        assert_eq!(rd.field(1).unwrap().name, "shard");
+        assert_eq!(messages[1], row(&[Some("acme"), Some("0")]));
+        // Missing values are NULL.
+        assert_eq!(messages[2], row(&[Some("globex"), None]));
+    }
+
+    #[tokio::test]
+    async fn test_unknown_plugin() {
+        let cmd = PluginAdmin::parse("PLUGIN not_loaded show state").unwrap();
+        let err = cmd.execute().await.unwrap_err();
+        assert_eq!(err.to_string(), "plugin \"not_loaded\" is not loaded");
+    }
+}
@@ -1,4 +1,4 @@
-use tokio::time::{timeout, Duration, Instant};
+use tokio::time::{timeout, Instant};
 use url::Url;
 
 use crate::{
@@ -26,7 +26,7 @@ impl Command for Probe {
 
     async fn execute(&self) -> Result<Vec<Message>, Error> {
         let mut conn = timeout(
-            Duration::from_millis(config().config.general.connect_timeout),
+            config().config.general.connect_timeout(),
             Server::connect(
                 &Address::try_from(self.url.clone()).map_err(|_| Error::InvalidAddress)?,
                 ServerOptions::default(),
@@ -1,11 +1,33 @@
-//! Recreate all connections to all databases.
+//! Recreate all connections to all databases, or just one.
 
-use crate::backend::databases::reconnect;
+use crate::backend::databases::{reconnect, reconnect_database};
 
 use super::prelude::*;
 
 /// Recreate connections.
-pub struct Reconnect;
+#[derive(Default)]
+pub struct Reconnect {
+    database: Option<String>,
+}
+
+impl Reconnect {
+    /// Recreate connections to one database.
+    pub fn database(database: &str) -> Self {
+        Self {
+            database: Some(database.to_owned()),
+        }
+    }
+
+    /// Recreate connections. Returns false if the database doesn't exist.
+    pub fn apply(&self) -> bool {
+        if let Some(ref database) = self.database {
+            reconnect_database(database)
+        } else {
+            reconnect();
+            true
+        }
+    }
+}
 
 #[async_trait]
 impl Command for Reconnect {
@@ -14,14 +36,17 @@ impl Command for Reconnect {
     }
 
     fn parse(sql: &str) -> Result<Self, Error> {
-        match sql {
-            "reconnect" => Ok(Reconnect),
+        let parts = sql.split(" ").collect::<Vec<_>>();
+
+        match parts[..] {
+            ["reconnect"] => Ok(Reconnect::default()),
+            ["reconnect", database] => Ok(Reconnect::database(database)),
             _ => Err(Error::Syntax),
         }
     }
 
     async fn execute(&self) -> Result<Vec<Message>, Error> {
-        reconnect();
+        self.apply();
         Ok(vec![])
     }
 }
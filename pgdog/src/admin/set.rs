@@ -94,6 +94,7 @@ impl Command for Set {
             _ => return Err(Error::Syntax),
         }
 
+        config.config.check_timeouts()?;
         config::set(config)?;
         databases::init();
 
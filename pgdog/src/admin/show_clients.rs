@@ -1,11 +1,12 @@
 //! `SHOW CLIENTS` command implementation.
 
-use std::collections::HashSet;
+use std::collections::{HashSet, VecDeque};
 
 use chrono::DateTime;
 
 use super::prelude::*;
-use crate::frontend::comms::comms;
+use super::{backend::BATCH_SIZE, Response};
+use crate::frontend::{comms::comms, ConnectedClient};
 use crate::net::messages::*;
 use crate::util::format_time;
 
@@ -33,11 +34,13 @@ impl Command for ShowClients {
             Field::numeric("port"),
             Field::text("state"),
             Field::text("replication"),
+            Field::text("confirmed_lsn"),
             Field::text("connect_time"),
             Field::text("last_request"),
             Field::numeric("queries"),
             Field::numeric("transactions"),
             Field::numeric("wait_time"),
+            Field::numeric("total_wait_time"),
             Field::numeric("query_time"),
             Field::numeric("transaction_time"),
             Field::numeric("bytes_received"),
@@ -69,63 +72,130 @@ impl Command for ShowClients {
     }
 
     async fn execute(&self) -> Result<Vec<Message>, Error> {
-        let mut rows = vec![];
-        let clients = comms().clients();
-
-        for client in clients.values() {
-            let user = client.paramters.get_default("user", "postgres");
-            let row = self
-                .filter
-                .clone()
-                .add("user", user)
-                .add("database", client.paramters.get_default("database", user))
-                .add("addr", client.addr.ip().to_string())
-                .add("port", client.addr.port().to_string())
-                .add("state", client.stats.state.to_string())
-                .add(
-                    "replication",
-                    if client.paramters.get("replication").is_some() {
-                        "logical"
-                    } else {
-                        "none"
-                    },
-                )
-                .add("connect_time", format_time(client.connected_at))
-                .add(
-                    "last_request",
-                    format_time(DateTime::from(client.stats.last_request)),
-                )
-                .add("queries", client.stats.queries)
-                .add("transactions", client.stats.transactions)
-                .add("wait_time", client.stats.wait_time().as_secs_f64() * 1000.0)
-                .add(
-                    "query_time",
-                    format!("{:.3}", client.stats.query_time.as_secs_f64() * 1000.0),
-                )
-                .add(
-                    "transaction_time",
-                    format!(
-                        "{:.3}",
-                        client.stats.transaction_time.as_secs_f64() * 1000.0
-                    ),
-                )
-                .add("bytes_received", client.stats.bytes_received)
-                .add("bytes_sent", client.stats.bytes_sent)
-                .add("errors", client.stats.errors)
-                .add(
-                    "application_name",
-                    client.paramters.get_default("application_name", ""),
-                )
-                .add("memory_used", client.stats.memory_used)
-                .add("locked", client.stats.locked)
-                .add("prepared_statements", client.stats.prepared_statements)
-                .data_row();
-            rows.push(row.message()?);
+        match self.stream()? {
+            Response::Stream(rd, rows) => {
+                let mut messages = vec![rd];
+                for row in rows {
+                    messages.push(row?);
+                }
+                Ok(messages)
+            }
+            Response::Messages(messages) => Ok(messages),
         }
+    }
+}
+
+impl ShowClients {
+    /// Produce rows as they are read by the admin client,
+    /// copying only a batch of clients out of the registry at a time.
+    pub fn stream(&self) -> Result<Response, Error> {
+        let rd = self.filter.row_description().message()?;
+        let rows = ClientRows {
+            filter: self.filter.clone(),
+            ids: comms().client_ids().into_iter(),
+            batch: VecDeque::new(),
+        };
+
+        Ok(Response::Stream(rd, Box::new(rows)))
+    }
+}
 
-        let mut messages = vec![self.filter.row_description().message()?];
-        messages.extend(rows);
+/// Clients to show, looked up in batches.
+struct ClientRows {
+    filter: NamedRow,
+    ids: std::vec::IntoIter<BackendKeyData>,
+    batch: VecDeque<ConnectedClient>,
+}
+
+impl ClientRows {
+    fn row(&mut self, client: &ConnectedClient) -> Result<Message, Error> {
+        let user = client.paramters.get_default("user", "postgres");
+        let row = self
+            .filter
+            .add("user", user)
+            .add("database", client.paramters.get_default("database", user))
+            .add("addr", client.addr.ip().to_string())
+            .add("port", client.addr.port().to_string())
+            .add("state", client.stats.current.state.to_string())
+            .add(
+                "replication",
+                if client.paramters.get("replication").is_some() {
+                    "logical"
+                } else {
+                    "none"
+                },
+            )
+            .add(
+                "confirmed_lsn",
+                client
+                    .stats
+                    .current
+                    .confirmed_lsn
+                    .map(|lsn| lsn.to_string())
+                    .unwrap_or_default(),
+            )
+            .add("connect_time", format_time(client.connected_at))
+            .add(
+                "last_request",
+                format_time(DateTime::from(client.stats.current.last_request)),
+            )
+            .add("queries", client.stats.totals.queries)
+            .add("transactions", client.stats.totals.transactions)
+            .add("wait_time", client.stats.wait_time().as_secs_f64() * 1000.0)
+            .add(
+                "total_wait_time",
+                format!(
+                    "{:.3}",
+                    client.stats.totals.wait_time.as_secs_f64() * 1000.0
+                ),
+            )
+            .add(
+                "query_time",
+                format!(
+                    "{:.3}",
+                    client.stats.totals.query_time.as_secs_f64() * 1000.0
+                ),
+            )
+            .add(
+                "transaction_time",
+                format!(
+                    "{:.3}",
+                    client.stats.totals.transaction_time.as_secs_f64() * 1000.0
+                ),
+            )
+            .add("bytes_received", client.stats.totals.bytes_received)
+            .add("bytes_sent", client.stats.totals.bytes_sent)
+            .add("errors", client.stats.totals.errors)
+            .add(
+                "application_name",
+                client.paramters.get_default("application_name", ""),
+            )
+            .add("memory_used", client.stats.current.memory_used)
+            .add("locked", client.stats.current.locked)
+            .add(
+                "prepared_statements",
+                client.stats.current.prepared_statements,
+            )
+            .data_row();
+
+        Ok(row.message()?)
+    }
+}
+
+impl Iterator for ClientRows {
+    type Item = Result<Message, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Clients can disconnect before we get to them.
+        while self.batch.is_empty() {
+            let ids = self.ids.by_ref().take(BATCH_SIZE).collect::<Vec<_>>();
+            if ids.is_empty() {
+                return None;
+            }
+            self.batch.extend(comms().clients_by_id(&ids));
+        }
 
-        Ok(messages)
+        let client = self.batch.pop_front()?;
+        Some(self.row(&client))
     }
 }
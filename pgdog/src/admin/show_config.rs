@@ -46,6 +46,30 @@ impl Command for ShowConfig {
             }
         }
 
+        // Per-user policies, e.g. "policy.analyst@pgdog.blocked_functions".
+        for user in &config.users.users {
+            let policies = [
+                (
+                    "blocked_statements",
+                    serde_json::to_value(&user.blocked_statements)?,
+                ),
+                (
+                    "blocked_functions",
+                    serde_json::to_value(&user.blocked_functions)?,
+                ),
+            ];
+
+            for (policy, value) in policies {
+                if value.as_array().is_some_and(|value| value.is_empty()) {
+                    continue;
+                }
+                let mut dr = DataRow::new();
+                let name = format!("policy.{}@{}.{}", user.name, user.database, policy);
+                dr.add(&name).add(pretty_value(&name, &value)?);
+                messages.push(dr.message()?);
+            }
+        }
+
         Ok(messages)
     }
 }
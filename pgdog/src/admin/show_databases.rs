@@ -0,0 +1,70 @@
+//! SHOW DATABASES command.
+
+use crate::backend::databases::databases;
+
+use super::prelude::*;
+
+pub struct ShowDatabases;
+
+#[async_trait]
+impl Command for ShowDatabases {
+    fn name(&self) -> String {
+        "SHOW DATABASES".into()
+    }
+
+    fn parse(_: &str) -> Result<Self, Error> {
+        Ok(ShowDatabases)
+    }
+
+    async fn execute(&self) -> Result<Vec<Message>, Error> {
+        let mut messages = vec![RowDescription::new(&[
+            Field::text("database"),
+            Field::text("user"),
+            Field::text("database_name"),
+            Field::numeric("shards"),
+            Field::text("pool_mode"),
+            Field::text("template"),
+            Field::text("user_pattern"),
+        ])
+        .message()?];
+
+        let databases = databases();
+        let mut clusters = databases.all().iter().collect::<Vec<_>>();
+        clusters.sort_by(|(a, _), (b, _)| (&a.database, &a.user).cmp(&(&b.database, &b.user)));
+
+        for (user, cluster) in clusters {
+            let database_name = cluster
+                .shards()
+                .first()
+                .and_then(|shard| {
+                    shard
+                        .pools()
+                        .first()
+                        .map(|pool| pool.addr().database_name.clone())
+                })
+                .unwrap_or_default();
+            // Databases and users created from templates.
+            let instance = databases.instances().get(user);
+
+            let mut dr = DataRow::new();
+            dr.add(user.database.as_str())
+                .add(user.user.as_str())
+                .add(database_name)
+                .add(cluster.shards().len() as i64)
+                .add(cluster.pooler_mode().to_string())
+                .add(
+                    instance
+                        .and_then(|instance| instance.database.clone())
+                        .unwrap_or_default(),
+                )
+                .add(
+                    instance
+                        .and_then(|instance| instance.user_pattern.clone())
+                        .unwrap_or_default(),
+                );
+            messages.push(dr.message()?);
+        }
+
+        Ok(messages)
+    }
+}
@@ -0,0 +1,83 @@
+//! SHOW DRY_RUN;
+//!
+//! Number of queries by computed shard, followed by
+//! the most frequent queries that would have been cross-shard.
+
+use crate::frontend::router::parser::dry_run;
+
+use super::prelude::*;
+
+pub struct ShowDryRun;
+
+#[async_trait]
+impl Command for ShowDryRun {
+    fn name(&self) -> String {
+        "SHOW DRY_RUN".into()
+    }
+
+    fn parse(_: &str) -> Result<Self, Error> {
+        Ok(ShowDryRun)
+    }
+
+    async fn execute(&self) -> Result<Vec<Message>, Error> {
+        let summary = dry_run::summary();
+        let mut messages = vec![RowDescription::new(&[
+            Field::text("shard"),
+            Field::numeric("queries"),
+            Field::text("fingerprint"),
+            Field::text("query"),
+        ])
+        .message()?];
+
+        for (shard, queries) in summary.shards() {
+            let mut data_row = DataRow::new();
+            data_row.add(shard).add(queries).add("").add("");
+            messages.push(data_row.message()?);
+        }
+
+        for query in summary.cross_shard() {
+            let mut data_row = DataRow::new();
+            data_row
+                .add(query.shard.to_string())
+                .add(query.count)
+                .add(query.fingerprint)
+                .add(query.query);
+            messages.push(data_row.message()?);
+        }
+
+        Ok(messages)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::frontend::router::parser::Shard;
+    use crate::net::{FromBytes, ToBytes};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_show_dry_run() {
+        dry_run::record(
+            "test_show_dry_run",
+            "SELECT * FROM sharded",
+            &Shard::All,
+            &Shard::Direct(0),
+        );
+
+        let messages = ShowDryRun.execute().await.unwrap();
+        let rows = messages
+            .into_iter()
+            .filter(|message| message.code() == 'D')
+            .map(|message| DataRow::from_bytes(message.to_bytes().unwrap()).unwrap())
+            .collect::<Vec<_>>();
+
+        let row = rows
+            .iter()
+            .find(|row| row.get_text(2).as_deref() == Some("test_show_dry_run"))
+            .unwrap();
+        assert_eq!(row.get_text(0).as_deref(), Some("all"));
+        assert_eq!(row.get_int(1, true), Some(1));
+        assert_eq!(row.get_text(3).as_deref(), Some("SELECT * FROM sharded"));
+    }
+}
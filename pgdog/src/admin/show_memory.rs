@@ -0,0 +1,101 @@
+//! SHOW MEMORY command.
+
+use crate::{stats::MemoryReport, util::human_bytes};
+
+use super::prelude::*;
+
+#[derive(Debug, Clone)]
+pub struct ShowMemory;
+
+#[async_trait]
+impl Command for ShowMemory {
+    fn name(&self) -> String {
+        "SHOW MEMORY".into()
+    }
+
+    fn parse(_: &str) -> Result<Self, Error> {
+        Ok(Self)
+    }
+
+    async fn execute(&self) -> Result<Vec<Message>, Error> {
+        let report = MemoryReport::load();
+
+        let mut messages = vec![RowDescription::new(&[
+            Field::text("category"),
+            Field::text("client_id"),
+            Field::text("addr"),
+            Field::numeric("bytes"),
+            Field::text("memory"),
+        ])
+        .message()?];
+
+        let mut rows = report
+            .categories()
+            .into_iter()
+            .map(|(category, bytes)| (category, String::new(), String::new(), bytes))
+            .collect::<Vec<_>>();
+        rows.push(("total", String::new(), String::new(), report.total()));
+        rows.extend(report.top_clients.iter().map(|client| {
+            (
+                "client",
+                client.id.pid.to_string(),
+                client.addr.to_string(),
+                client.bytes,
+            )
+        }));
+
+        for (category, client_id, addr, bytes) in rows {
+            let mut dr = DataRow::new();
+            dr.add(category)
+                .add(client_id)
+                .add(addr)
+                .add(bytes)
+                .add(human_bytes(bytes));
+            messages.push(dr.message()?);
+        }
+
+        Ok(messages)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::net::{FromBytes, ToBytes};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_show_memory() {
+        let messages = ShowMemory.execute().await.unwrap();
+
+        let rows = messages
+            .into_iter()
+            .filter(|message| message.code() == 'D')
+            .map(|message| DataRow::from_bytes(message.to_bytes().unwrap()).unwrap())
+            .collect::<Vec<_>>();
+
+        let categories = rows
+            .iter()
+            .map(|row| row.get_text(0).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            &categories[..6],
+            &[
+                "clients",
+                "prepared_statements",
+                "query_cache",
+                "mirror_queues",
+                "pools",
+                "total"
+            ]
+        );
+
+        let total = rows[5].get_int(3, true).unwrap();
+        let sum = rows[..5]
+            .iter()
+            .map(|row| row.get_int(3, true).unwrap())
+            .sum::<i64>();
+        assert_eq!(total, sum);
+        assert_eq!(rows[5].get_text(4).unwrap(), human_bytes(total as usize));
+    }
+}
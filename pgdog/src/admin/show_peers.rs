@@ -37,6 +37,10 @@ impl Command for ShowPeers {
             Field::text("addr"),
             Field::text("last_seen"),
             Field::numeric("clients"),
+            Field::text("cluster_name"),
+            Field::text("version"),
+            Field::text("databases"),
+            Field::numeric("port"),
         ])
         .message()?];
 
@@ -50,7 +54,11 @@ impl Command for ShowPeers {
                     now.duration_since(state.last_message)
                         .unwrap_or(Duration::from_secs(0))
                 ))
-                .add(state.clients);
+                .add(state.clients)
+                .add(state.cluster_name.unwrap_or_default())
+                .add(state.version)
+                .add(state.databases.join(","))
+                .add(state.port as i64);
             rows.push(row.message()?);
         }
 
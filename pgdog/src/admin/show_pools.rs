@@ -1,5 +1,6 @@
 use crate::{
     backend::databases::databases,
+    config::config,
     net::messages::{DataRow, Field, Protocol, RowDescription},
 };
 
@@ -41,8 +42,15 @@ impl Command for ShowPools {
             Field::numeric("out_of_sync"),
             Field::bool("online"),
             Field::text("replica_lag"),
+            Field::numeric("slow_start_remaining"),
+            Field::numeric("avg_prepared_statements"),
+            Field::bool("require_tls"),
+            Field::text("last_failure_reason"),
+            Field::numeric("sv_locked"),
+            Field::numeric("sv_draining"),
         ]);
         let mut messages = vec![rd.message()?];
+        let config = config();
         for (user, cluster) in databases().all() {
             for (shard_num, shard) in cluster.shards().iter().enumerate() {
                 for (role, pool) in shard.pools_with_roles() {
@@ -71,7 +79,18 @@ impl Command for ShowPools {
                         .add(state.re_synced)
                         .add(state.out_of_sync)
                         .add(state.online)
-                        .add(state.replica_lag.simple_display());
+                        .add(state.replica_lag.simple_display())
+                        .add(state.slow_start_remaining.as_millis() as i64)
+                        .add(state.avg_prepared_statements)
+                        .add(config.require_tls(&user.user, &user.database))
+                        .add(
+                            state
+                                .last_failure_reason
+                                .map(|reason| reason.to_string())
+                                .unwrap_or_default(),
+                        )
+                        .add(state.locked)
+                        .add(state.draining);
 
                     messages.push(row.message()?);
                 }
@@ -22,6 +22,8 @@ impl Command for ShowPreparedStatements {
             Field::text("statement"),
             Field::numeric("used_by"),
             Field::numeric("memory_used"),
+            Field::text("database"),
+            Field::text("user"),
         ])
         .message()?];
         for (key, stmt) in statements.statements() {
@@ -30,11 +32,18 @@ impl Command for ShowPreparedStatements {
                 .get(&stmt.name())
                 .map(|s| s.memory_usage())
                 .unwrap_or(0);
+            let (database, user) = key
+                .cluster
+                .as_deref()
+                .map(|cluster| (cluster.database.as_str(), cluster.user.as_str()))
+                .unwrap_or_default();
             let mut dr = DataRow::new();
             dr.add(stmt.name())
                 .add(key.query()?)
                 .add(stmt.used)
-                .add(name_memory);
+                .add(name_memory)
+                .add(database)
+                .add(user);
             messages.push(dr.message()?);
         }
         Ok(messages)
@@ -0,0 +1,113 @@
+//! SHOW ROUTING HISTORY <client_pid>;
+//!
+//! Last routing decisions made for a client, oldest first.
+
+use crate::frontend::comms::comms;
+
+use super::prelude::*;
+
+#[derive(Debug, Clone)]
+pub struct ShowRoutingHistory {
+    pid: i32,
+}
+
+#[async_trait]
+impl Command for ShowRoutingHistory {
+    fn name(&self) -> String {
+        "SHOW ROUTING HISTORY".into()
+    }
+
+    fn parse(sql: &str) -> Result<Self, Error> {
+        let parts = sql.split_whitespace().collect::<Vec<_>>();
+
+        match parts[..] {
+            ["show", "routing", "history", pid] => Ok(Self { pid: pid.parse()? }),
+            _ => Err(Error::Syntax),
+        }
+    }
+
+    async fn execute(&self) -> Result<Vec<Message>, Error> {
+        let client = comms()
+            .client_by_pid(self.pid)
+            .ok_or(Error::NoClient(self.pid))?;
+
+        let mut messages = vec![RowDescription::new(&[
+            Field::text("timestamp"),
+            Field::text("fingerprint"),
+            Field::text("command"),
+            Field::text("shard"),
+            Field::text("role"),
+            Field::text("sharding_values"),
+            Field::text("comment_shard"),
+            Field::text("session_shard"),
+            Field::bool("plugin_override"),
+            Field::bool("manual_query"),
+            Field::text("error"),
+        ])
+        .message()?];
+
+        for decision in client.routing_history.decisions() {
+            let command = serde_json::to_value(&decision.command)?;
+            let (shard, role) = match decision.route {
+                Some(ref route) => (
+                    route.shard().to_string(),
+                    if route.is_read() {
+                        "replica"
+                    } else {
+                        "primary"
+                    },
+                ),
+                None => (String::new(), ""),
+            };
+            let inputs = &decision.inputs;
+
+            let mut dr = DataRow::new();
+            dr.add(decision.timestamp.as_str())
+                .add(decision.fingerprint.as_str())
+                .add(command["command"].as_str().unwrap_or_default())
+                .add(shard)
+                .add(role)
+                .add(inputs.sharding_values.join(", "))
+                .add(
+                    inputs
+                        .comment_shard
+                        .as_ref()
+                        .map(|shard| shard.to_string())
+                        .unwrap_or_default(),
+                )
+                .add(
+                    inputs
+                        .session_shard
+                        .as_ref()
+                        .map(|shard| shard.to_string())
+                        .unwrap_or_default(),
+                )
+                .add(inputs.plugin_override)
+                .add(inputs.manual_query)
+                .add(decision.error.as_deref().unwrap_or_default());
+            messages.push(dr.message()?);
+        }
+
+        Ok(messages)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_show_routing_history() {
+        let command = ShowRoutingHistory::parse("show routing history 1234").unwrap();
+        assert_eq!(command.pid, 1234);
+
+        assert!(ShowRoutingHistory::parse("show routing history").is_err());
+        assert!(ShowRoutingHistory::parse("show routing history abc").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_show_routing_history_no_client() {
+        let command = ShowRoutingHistory { pid: -1 };
+        assert!(matches!(command.execute().await, Err(Error::NoClient(-1))));
+    }
+}
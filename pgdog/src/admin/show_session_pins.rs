@@ -0,0 +1,50 @@
+//! SHOW SESSION_PINS command.
+
+use std::time::Instant;
+
+use crate::frontend::SessionPins;
+
+use super::prelude::*;
+
+#[derive(Debug, Clone)]
+pub struct ShowSessionPins;
+
+#[async_trait]
+impl Command for ShowSessionPins {
+    fn name(&self) -> String {
+        "SHOW SESSION_PINS".into()
+    }
+
+    fn parse(_: &str) -> Result<Self, Error> {
+        Ok(Self)
+    }
+
+    async fn execute(&self) -> Result<Vec<Message>, Error> {
+        let mut messages = vec![RowDescription::new(&[
+            Field::text("user"),
+            Field::text("database"),
+            Field::text("application_name"),
+            Field::text("session_tag"),
+            Field::numeric("shard"),
+            Field::numeric("expires_in"),
+        ])
+        .message()?];
+
+        let now = Instant::now();
+        let mut pins = SessionPins::global().pins();
+        pins.sort_by_key(|(_, pin)| pin.expires_at);
+
+        for (key, pin) in pins {
+            let mut dr = DataRow::new();
+            dr.add(key.user)
+                .add(key.database)
+                .add(key.application_name)
+                .add(key.tag)
+                .add(pin.shard as i64)
+                .add(pin.expires_at.saturating_duration_since(now).as_millis() as i64);
+            messages.push(dr.message()?);
+        }
+
+        Ok(messages)
+    }
+}
@@ -41,6 +41,8 @@ impl Command for ShowStats {
                         Field::numeric(&format!("{}_server_parse_count", prefix)),
                         Field::numeric(&format!("{}_bind_count", prefix)),
                         Field::numeric(&format!("{}_close_count", prefix)),
+                        Field::numeric(&format!("{}_rollback_count", prefix)),
+                        Field::numeric(&format!("{}_prewarm_count", prefix)),
                     ]
                 })
                 .collect::<Vec<Field>>(),
@@ -80,7 +82,9 @@ impl Command for ShowStats {
                             .add(stat.wait_time.as_millis() as u64)
                             .add(stat.parse_count)
                             .add(stat.bind_count)
-                            .add(stat.close);
+                            .add(stat.close)
+                            .add(stat.rollbacks)
+                            .add(stat.prewarm_count);
                     }
 
                     messages.push(dr.message()?);
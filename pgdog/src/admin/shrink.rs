@@ -0,0 +1,108 @@
+//! SHRINK [database]: close idle connections above
+//! `min_pool_size` right away, without waiting for `idle_timeout`.
+
+use serde::Serialize;
+
+use crate::backend::databases::databases;
+
+use super::prelude::*;
+
+/// Shrink pool(s).
+#[derive(Default)]
+pub struct Shrink {
+    database: Option<String>,
+}
+
+/// Connections closed in one pool.
+#[derive(Serialize, Debug)]
+pub struct Shrunk {
+    pub database: String,
+    pub user: String,
+    pub addr: String,
+    pub port: u16,
+    pub shard: usize,
+    pub role: String,
+    pub closed: usize,
+}
+
+impl Shrink {
+    /// Shrink all pools for the database.
+    pub fn database(database: &str) -> Self {
+        Self {
+            database: Some(database.to_owned()),
+        }
+    }
+
+    /// Shrink matching pools. Returns how many connections were closed in each.
+    pub fn apply(&self) -> Vec<Shrunk> {
+        let mut shrunk = vec![];
+
+        for (user, cluster) in databases().all() {
+            if let Some(ref database) = self.database {
+                if &user.database != database {
+                    continue;
+                }
+            }
+
+            for (shard, pools) in cluster.shards().iter().enumerate() {
+                for (role, pool) in pools.pools_with_roles() {
+                    shrunk.push(Shrunk {
+                        database: user.database.clone(),
+                        user: user.user.clone(),
+                        addr: pool.addr().host.clone(),
+                        port: pool.addr().port,
+                        shard,
+                        role: role.to_string(),
+                        closed: pool.shrink(),
+                    });
+                }
+            }
+        }
+
+        shrunk
+    }
+}
+
+#[async_trait]
+impl Command for Shrink {
+    fn name(&self) -> String {
+        "SHRINK".into()
+    }
+
+    fn parse(sql: &str) -> Result<Self, Error> {
+        let parts = sql.split(" ").collect::<Vec<_>>();
+
+        match parts[..] {
+            ["shrink"] => Ok(Self::default()),
+            ["shrink", database] => Ok(Self::database(database)),
+            _ => Err(Error::Syntax),
+        }
+    }
+
+    async fn execute(&self) -> Result<Vec<Message>, Error> {
+        let mut messages = vec![RowDescription::new(&[
+            Field::text("database"),
+            Field::text("user"),
+            Field::text("addr"),
+            Field::numeric("port"),
+            Field::numeric("shard"),
+            Field::text("role"),
+            Field::numeric("closed"),
+        ])
+        .message()?];
+
+        for shrunk in self.apply() {
+            let mut dr = DataRow::new();
+            dr.add(shrunk.database)
+                .add(shrunk.user)
+                .add(shrunk.addr)
+                .add(shrunk.port as i64)
+                .add(shrunk.shard)
+                .add(shrunk.role)
+                .add(shrunk.closed);
+            messages.push(dr.message()?);
+        }
+
+        Ok(messages)
+    }
+}
@@ -3,28 +3,33 @@
 use std::collections::BTreeSet;
 use std::collections::{hash_map::Entry, HashMap};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use arc_swap::ArcSwap;
 use once_cell::sync::Lazy;
 use parking_lot::lock_api::MutexGuard;
 use parking_lot::{Mutex, RawMutex};
+use tokio::{spawn, time::sleep};
 use tracing::{debug, info, warn};
 
 use crate::config::PoolerMode;
-use crate::frontend::router::parser::Cache;
-use crate::frontend::router::sharding::Mapping;
-use crate::frontend::PreparedStatements;
+use crate::frontend::router::parser::{Cache, FunctionLists};
+use crate::frontend::router::sharding::{ListMappings, Mapping};
+use crate::frontend::{comms::comms, PreparedStatements};
 use crate::{
-    backend::pool::PoolConfig,
-    config::{config, load, ConfigAndUsers, ManualQuery, Role},
-    net::messages::BackendKeyData,
+    backend::pool::{PoolConfig, SessionSetup},
+    config::{
+        config, load, ConfigAndUsers, Database, ManualQuery, MirrorStrategy, Role, Template,
+        ValidateShardingSchema,
+    },
+    net::{messages::BackendKeyData, tls},
+    plugin,
+    util::human_duration,
 };
 
 use super::{
-    pool::{Address, ClusterConfig, Config},
-    reload_notify,
-    replication::ReplicationConfig,
-    Cluster, ClusterShardConfig, Error, ShardedTables,
+    pool::ClusterConfig, reload_notify, replication::ReplicationConfig,
+    schema::sharding::Validation, Cluster, ClusterShardConfig, Error, ShardedTables,
 };
 
 static DATABASES: Lazy<ArcSwap<Databases>> =
@@ -65,6 +70,34 @@ pub fn reconnect() {
     replace_databases(databases().duplicate(), false);
 }
 
+/// Re-create all connections to one database.
+///
+/// Returns false if the database doesn't exist.
+pub fn reconnect_database(database: &str) -> bool {
+    let old_databases = databases();
+    let Some(new_databases) = old_databases.duplicate_database(database) else {
+        return false;
+    };
+
+    reload_notify::started();
+    let new_clusters = new_databases
+        .databases
+        .iter()
+        .filter(|(user, _)| user.database == database);
+    for (_, cluster) in new_clusters {
+        cluster.launch();
+    }
+    DATABASES.store(Arc::new(new_databases));
+    for (user, cluster) in old_databases.all() {
+        if user.database == database {
+            cluster.shutdown();
+        }
+    }
+    reload_notify::done();
+
+    true
+}
+
 /// Initialize the databases for the first time.
 pub fn init() {
     let config = config();
@@ -72,6 +105,27 @@ pub fn init() {
 
     // Resize query cache
     Cache::resize(config.config.general.query_cache_limit);
+    Cache::set_max_age(config.config.general.query_cache_max_age());
+}
+
+/// Refuse to start if sharded tables don't match the schema,
+/// with `validate_sharding_schema = "strict"`.
+///
+/// Databases that are down are checked in the background once they are up.
+pub async fn validate_sharding_schema() -> Result<(), Error> {
+    let databases = databases();
+
+    for cluster in databases.all().values() {
+        if cluster.validate_sharding_schema_mode() != ValidateShardingSchema::Strict {
+            continue;
+        }
+
+        if cluster.sharding_schema_validation().await == Validation::Invalid {
+            return Err(Error::ShardingSchema(cluster.name().to_owned()));
+        }
+    }
+
+    Ok(())
 }
 
 /// Shutdown all databases.
@@ -83,7 +137,9 @@ pub fn shutdown() {
 pub fn reload() -> Result<(), Error> {
     let old_config = config();
     let new_config = load(&old_config.config_path, &old_config.users_path)?;
-    let databases = from_config(&new_config);
+    let mut databases = from_config(&new_config);
+    // Keep databases created from templates, if they still match one.
+    databases.instantiate_from(&self::databases(), &new_config);
 
     replace_databases(databases, true);
 
@@ -94,31 +150,128 @@ pub fn reload() -> Result<(), Error> {
 
     // Resize query cache
     Cache::resize(new_config.config.general.query_cache_limit);
+    Cache::set_max_age(new_config.config.general.query_cache_max_age());
+
+    // Pick up rotated TLS certificates.
+    tls::reload(&new_config.config.general);
+
+    plugin::reload(&new_config.config.plugins);
 
     Ok(())
 }
 
-/// Add new user to pool.
-pub(crate) fn add(mut user: crate::config::User) {
+/// How long a failed passthrough pool creation is remembered,
+/// so clients retrying it don't create pools in a loop.
+const PASSTHROUGH_FAILURE_TTL: Duration = Duration::from_secs(5);
+
+/// Most passthrough failures remembered at once, so a storm of
+/// bad logins with different user names can't grow the list forever.
+const PASSTHROUGH_FAILURES_LIMIT: usize = 10_000;
+
+/// User/database pairs we couldn't create pools for, or whose clients
+/// sent the wrong password, and when that happened.
+static PASSTHROUGH_FAILURES: Lazy<Mutex<HashMap<User, Instant>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Remember a passthrough failure. Pools for this user and database
+/// aren't created or replaced until it expires.
+pub(crate) fn passthrough_failed(user: &str, database: &str) {
+    record_failure(
+        &mut PASSTHROUGH_FAILURES.lock(),
+        User {
+            user: user.to_owned(),
+            database: database.to_owned(),
+        },
+        PASSTHROUGH_FAILURES_LIMIT,
+    );
+}
+
+/// Expired failures are dropped first; if there are still `limit` failures,
+/// the oldest one is.
+fn record_failure(failures: &mut HashMap<User, Instant>, key: User, limit: usize) {
+    failures.retain(|_, failed_at| failed_at.elapsed() < PASSTHROUGH_FAILURE_TTL);
+
+    if failures.len() >= limit {
+        let oldest = failures
+            .iter()
+            .min_by_key(|(_, failed_at)| **failed_at)
+            .map(|(key, _)| key.clone());
+        if let Some(oldest) = oldest {
+            failures.remove(&oldest);
+        }
+    }
+
+    failures.insert(key, Instant::now());
+}
+
+/// Add new user to pool. Returns true if a new pool was created.
+///
+/// Clients racing to add the same user wait for the first one
+/// and use the pool it created.
+pub(crate) fn add(mut user: crate::config::User) -> bool {
     // One user at a time.
     let _lock = lock();
 
+    let key = User {
+        user: user.name.clone(),
+        database: user.database.clone(),
+    };
+
+    if let Some(failed_at) = PASSTHROUGH_FAILURES.lock().get(&key) {
+        if failed_at.elapsed() < PASSTHROUGH_FAILURE_TTL {
+            return false;
+        }
+    }
+
+    // Created by another client while we were waiting for the lock.
+    // Pools without a password are replaced once we get one.
+    let existing = databases().databases.get(&key).cloned();
+    if let Some(ref existing) = existing {
+        if !existing.password().is_empty() || user.password().is_empty() {
+            return false;
+        }
+    }
+
     debug!(
         "adding user \"{}\" for database \"{}\" via auth passthrough",
         user.name, user.database
     );
 
     let config = config();
-    for existing in &config.users.users {
-        if existing.name == user.name && existing.database == user.database {
-            let mut existing = existing.clone();
-            existing.password = user.password.clone();
-            user = existing;
+    let template = config.template(&user.name, &user.database);
+    let pool_config = match template {
+        Some(ref template) => {
+            if existing.is_none() && !databases().has_room(&config) {
+                return false;
+            }
+            if let Some(ref entry) = template.user {
+                user = crate::config::User {
+                    password: user.password.clone(),
+                    ..entry.clone()
+                };
+            }
+            &template.config
         }
-    }
-    let pool = new_pool(&user, &config.config);
+        None => {
+            for existing in &config.users.users {
+                if existing.name == user.name && existing.database == user.database {
+                    let mut existing = existing.clone();
+                    existing.password = user.password.clone();
+                    user = existing;
+                }
+            }
+            &config.config
+        }
+    };
+    let pool = new_pool(&user, pool_config);
     if let Some((user, cluster)) = pool {
-        let databases = (*databases()).clone();
+        PASSTHROUGH_FAILURES.lock().remove(&user);
+        let mut databases = (*databases()).clone();
+        if let Some(ref template) = template {
+            databases
+                .instances
+                .insert(user.clone(), Instance::new(template));
+        }
         let (added, databases) = databases.add(user, cluster);
         if added {
             // Launch the new pool (idempotent).
@@ -126,7 +279,175 @@ pub(crate) fn add(mut user: crate::config::User) {
             // Don't use replace_databases because Arc refers to the same DBs,
             // and we'll shut them down.
             DATABASES.store(Arc::new(databases));
+            if let Some(existing) = existing {
+                existing.shutdown();
+            }
+        }
+        added
+    } else {
+        warn!(
+            "can't add user \"{}\" for database \"{}\" via auth passthrough",
+            key.user, key.database
+        );
+        passthrough_failed(&key.user, &key.database);
+        false
+    }
+}
+
+/// Password a client needs to create pools from a template, if the database or user
+/// matches one with a users.toml entry and the pools don't exist yet.
+pub(crate) fn template_password(user: &str, database: &str) -> Option<String> {
+    if databases()
+        .databases
+        .contains_key(&(user, database).to_user())
+    {
+        return None;
+    }
+
+    config()
+        .template(user, database)?
+        .user
+        .map(|entry| entry.password().to_owned())
+}
+
+/// Create pools for a database or user matching a template, unless they exist already.
+/// Returns true if they were created.
+pub(crate) fn instantiate(user: &str, database: &str) -> bool {
+    let key = (user, database).to_user();
+    if databases().used(&key) {
+        return false;
+    }
+
+    let config = config();
+    let Some(template) = config.template(user, database) else {
+        return false;
+    };
+    // Without a user entry, passthrough auth creates the pools.
+    let Some(ref entry) = template.user else {
+        return false;
+    };
+
+    // One database at a time.
+    let _lock = lock();
+
+    // Created by another client while we were waiting for the lock.
+    let databases = databases();
+    if databases.used(&key) || !databases.has_room(&config) {
+        return false;
+    }
+
+    let Some((key, cluster)) = new_pool(entry, &template.config) else {
+        return false;
+    };
+
+    info!(
+        r#"creating pools for user "{}" and database "{}" from template"#,
+        key.user, key.database
+    );
+
+    let mut databases = (*databases).clone();
+    databases
+        .instances
+        .insert(key.clone(), Instance::new(&template));
+    databases.databases.insert(key, cluster.clone());
+    cluster.launch();
+    DATABASES.store(Arc::new(databases));
+
+    true
+}
+
+/// Close pools created from templates that clients haven't used for `idle_timeout`.
+/// Returns how many were closed.
+pub(crate) fn evict(idle_timeout: Duration) -> usize {
+    let _lock = lock();
+
+    let databases = databases();
+    if databases.instances.is_empty() {
+        return 0;
+    }
+
+    let connected = comms().users();
+    let idle = databases
+        .instances
+        .iter()
+        .filter(|(user, instance)| {
+            if connected.contains(*user) {
+                instance.used();
+                false
+            } else {
+                instance.idle() >= idle_timeout
+            }
+        })
+        .map(|(user, _)| user.clone())
+        .collect::<Vec<_>>();
+
+    if idle.is_empty() {
+        return 0;
+    }
+
+    let mut databases = (*databases).clone();
+    let mut closed = vec![];
+    for user in &idle {
+        databases.instances.remove(user);
+        if let Some(cluster) = databases.databases.remove(user) {
+            closed.push(cluster);
         }
+        info!(
+            r#"closing pools for user "{}" and database "{}", unused for {}"#,
+            user.user,
+            user.database,
+            human_duration(idle_timeout)
+        );
+    }
+    DATABASES.store(Arc::new(databases));
+
+    for cluster in closed {
+        cluster.shutdown();
+    }
+
+    idle.len()
+}
+
+/// Close idle pools created from templates in the background.
+pub fn evict_idle() {
+    spawn(async {
+        loop {
+            let idle_timeout = config().config.general.template_idle_timeout();
+            // Check often enough to close pools soon after they become idle.
+            sleep((idle_timeout / 10).clamp(Duration::from_secs(1), Duration::from_secs(60))).await;
+            evict(idle_timeout);
+        }
+    });
+}
+
+/// Pools created on demand from a template.
+#[derive(Debug, Clone)]
+pub struct Instance {
+    /// Database entry the database was created from, if it matched its `name_pattern`.
+    pub database: Option<String>,
+    /// `name_pattern` of the users.toml entry the user matched, if any.
+    pub user_pattern: Option<String>,
+    /// When a client last used the pools.
+    last_used: Arc<Mutex<Instant>>,
+}
+
+impl Instance {
+    fn new(template: &Template) -> Self {
+        Self {
+            database: template.database.clone(),
+            user_pattern: template.user_pattern.clone(),
+            last_used: Arc::new(Mutex::new(Instant::now())),
+        }
+    }
+
+    /// A client is using the pools.
+    fn used(&self) {
+        *self.last_used.lock() = Instant::now();
+    }
+
+    /// How long the pools haven't been used for.
+    pub fn idle(&self) -> Duration {
+        self.last_used.lock().elapsed()
     }
 }
 
@@ -175,6 +496,10 @@ pub struct Databases {
     databases: HashMap<User, Cluster>,
     manual_queries: HashMap<String, ManualQuery>,
     mirrors: HashMap<String, Vec<Cluster>>,
+    /// Users for clients missing from users.toml, keyed by database name.
+    default_users: HashMap<String, String>,
+    /// Pools created from templates.
+    instances: HashMap<User, Instance>,
 }
 
 impl Databases {
@@ -196,6 +521,63 @@ impl Databases {
         }
     }
 
+    /// Pools exist for the user/database pair. Marks them used
+    /// if they were created from a template.
+    fn used(&self, user: &User) -> bool {
+        if let Some(instance) = self.instances.get(user) {
+            instance.used();
+        }
+
+        self.databases.contains_key(user)
+    }
+
+    /// More databases can be created from templates, up to `template_limit`.
+    fn has_room(&self, config: &ConfigAndUsers) -> bool {
+        let limit = config.config.general.template_limit;
+        if self.instances.len() < limit {
+            true
+        } else {
+            warn!(
+                "can't create more than {} databases from templates, see \"template_limit\"",
+                limit
+            );
+            false
+        }
+    }
+
+    /// Pools created from templates.
+    pub fn instances(&self) -> &HashMap<User, Instance> {
+        &self.instances
+    }
+
+    /// Create pools for users and databases that were created from templates
+    /// in `other`, if they still match one.
+    fn instantiate_from(&mut self, other: &Databases, config: &ConfigAndUsers) {
+        for (user, instance) in &other.instances {
+            let Some(template) = config.template(&user.user, &user.database) else {
+                continue;
+            };
+            // Created with passthrough auth, clients add them again.
+            let Some(ref entry) = template.user else {
+                continue;
+            };
+            if !self.has_room(config) {
+                break;
+            }
+
+            if let Some((user, cluster)) = new_pool(entry, &template.config) {
+                self.databases.insert(user.clone(), cluster);
+                self.instances.insert(
+                    user,
+                    Instance {
+                        last_used: instance.last_used.clone(),
+                        ..Instance::new(&template)
+                    },
+                );
+            }
+        }
+    }
+
     /// Check if a cluster exists, quickly.
     pub fn exists(&self, user: impl ToUser) -> bool {
         if let Some(cluster) = self.databases.get(&user.to_user()) {
@@ -205,10 +587,22 @@ impl Databases {
         }
     }
 
+    /// Find the cluster for the user/database pair. Users not in users.toml
+    /// use the database's `default_user`, if it has one.
+    fn get(&self, user: &User) -> Option<&Cluster> {
+        self.databases.get(user).or_else(|| {
+            let default_user = self.default_users.get(&user.database)?;
+            self.databases.get(&User {
+                user: default_user.clone(),
+                database: user.database.clone(),
+            })
+        })
+    }
+
     /// Get a cluster for the user/database pair if it's configured.
     pub fn cluster(&self, user: impl ToUser) -> Result<Cluster, Error> {
         let user = user.to_user();
-        if let Some(cluster) = self.databases.get(&user) {
+        if let Some(cluster) = self.get(&user) {
             Ok(cluster.clone())
         } else {
             Err(Error::NoDatabase(user.clone()))
@@ -217,7 +611,7 @@ impl Databases {
 
     pub fn mirrors(&self, user: impl ToUser) -> Result<Option<&[Cluster]>, Error> {
         let user = user.to_user();
-        if let Some(cluster) = self.databases.get(&user) {
+        if let Some(cluster) = self.get(&user) {
             let name = cluster.name();
             Ok(self.mirrors.get(name).map(|m| m.as_slice()))
         } else {
@@ -291,7 +685,54 @@ impl Databases {
                 .collect(),
             manual_queries: self.manual_queries.clone(),
             mirrors: self.mirrors.clone(),
+            default_users: self.default_users.clone(),
+            instances: self.instances.clone(),
+        }
+    }
+
+    /// Create new pools for one database, keeping all others.
+    fn duplicate_database(&self, database: &str) -> Option<Databases> {
+        if !self.databases.keys().any(|user| user.database == database) {
+            return None;
         }
+
+        let databases: HashMap<User, Cluster> = self
+            .databases
+            .iter()
+            .map(|(user, cluster)| {
+                if user.database == database {
+                    (user.clone(), cluster.duplicate())
+                } else {
+                    (user.clone(), cluster.clone())
+                }
+            })
+            .collect();
+
+        // Mirrors should point to the new pools.
+        let mirrors = self
+            .mirrors
+            .iter()
+            .map(|(name, clusters)| {
+                let clusters = clusters
+                    .iter()
+                    .map(|cluster| {
+                        databases
+                            .get(&(cluster.user(), cluster.name()).to_user())
+                            .cloned()
+                            .unwrap_or_else(|| cluster.clone())
+                    })
+                    .collect();
+                (name.clone(), clusters)
+            })
+            .collect();
+
+        Some(Self {
+            databases,
+            manual_queries: self.manual_queries.clone(),
+            mirrors,
+            default_users: self.default_users.clone(),
+            instances: self.instances.clone(),
+        })
     }
 
     /// Shutdown all pools.
@@ -345,20 +786,14 @@ pub(crate) fn new_pool(
                 .find(|d| d.role == Role::Primary)
                 .map(|primary| {
                     mirrors_of.insert(primary.mirror_of.clone());
-                    PoolConfig {
-                        address: Address::new(primary, user),
-                        config: Config::new(general, primary, user),
-                    }
+                    PoolConfig::new(config, primary, user)
                 });
             let replicas = user_databases
                 .iter()
                 .filter(|d| d.role == Role::Replica)
                 .map(|replica| {
                     mirrors_of.insert(replica.mirror_of.clone());
-                    PoolConfig {
-                        address: Address::new(replica, user),
-                        config: Config::new(general, replica, user),
-                    }
+                    PoolConfig::new(config, replica, user)
                 })
                 .collect::<Vec<_>>();
 
@@ -371,14 +806,20 @@ pub(crate) fn new_pool(
             .unwrap_or(vec![]);
 
         for sharded_table in &mut sharded_tables {
-            let mappings = sharded_mappings.get(&(
+            let key = (
                 sharded_table.database.clone(),
                 sharded_table.column.clone(),
                 sharded_table.name.clone(),
-            ));
+            );
 
-            if let Some(mappings) = mappings {
-                sharded_table.mapping = Mapping::new(mappings);
+            if let Some(mappings) = sharded_mappings.get(&key) {
+                // List mappings can be changed at runtime, so all clusters share them.
+                sharded_table.mapping = match Mapping::new(mappings) {
+                    Some(Mapping::List(list)) => {
+                        Some(Mapping::List(ListMappings::global().shared(key, list)))
+                    }
+                    mapping => mapping,
+                };
 
                 if let Some(ref mapping) = sharded_table.mapping {
                     if !mapping.valid() {
@@ -412,15 +853,68 @@ pub(crate) fn new_pool(
             }
         };
 
-        let cluster_config = ClusterConfig::new(
-            general,
-            user,
-            &shard_configs,
-            sharded_tables,
-            mirror_of,
-            config.multi_tenant(),
+        let mirror_strategies = shards
+            .iter()
+            .flatten()
+            .map(|database| database.mirror_strategy)
+            .collect::<BTreeSet<_>>();
+        let mirror_strategy = match mirror_strategies.len() {
+            0 | 1 => mirror_strategies.first().copied().unwrap_or_default(),
+            _ => {
+                warn!(
+                    "database \"{}\" has different \"mirror_strategy\" settings, using default",
+                    user.database
+                );
+                MirrorStrategy::default()
+            }
+        };
+
+        // Database statements run first, then the user's.
+        let mut database_setups = shards
+            .iter()
+            .flatten()
+            .map(|database| &database.session_setup)
+            .filter(|setup| !setup.is_empty());
+        let database_setup = database_setups.next();
+        if database_setups.any(|setup| Some(setup) != database_setup) {
+            warn!(
+                "database \"{}\" has different \"session_setup\" settings, using the first one",
+                user.database
+            );
+        }
+        let session_setup = SessionSetup::new(
+            database_setup
+                .into_iter()
+                .flatten()
+                .chain(&user.session_setup),
         );
 
+        // Databases can override the global function lists.
+        let function_allowlist =
+            database_setting(shards, &user.database, "function_allowlist", |database| {
+                database.function_allowlist.as_ref()
+            })
+            .unwrap_or(&general.function_allowlist);
+        let function_denylist =
+            database_setting(shards, &user.database, "function_denylist", |database| {
+                database.function_denylist.as_ref()
+            })
+            .unwrap_or(&general.function_denylist);
+
+        let cluster_config = ClusterConfig {
+            session_setup,
+            function_lists: FunctionLists::new(function_allowlist, function_denylist),
+            ..ClusterConfig::new(
+                general,
+                user,
+                &shard_configs,
+                sharded_tables,
+                mirror_of,
+                mirror_strategy,
+                config.multi_tenant(),
+            )
+        };
+
         Some((
             User {
                 user: user.name.clone(),
@@ -433,11 +927,38 @@ pub(crate) fn new_pool(
     }
 }
 
+/// Setting of a database, if any of its entries set it.
+/// All entries should agree, otherwise the first one is used.
+fn database_setting<'a, T: PartialEq>(
+    shards: &'a [Vec<Database>],
+    name: &str,
+    setting: &str,
+    get: impl Fn(&'a Database) -> Option<&'a T>,
+) -> Option<&'a T> {
+    let mut values = shards.iter().flatten().filter_map(get);
+    let first = values.next();
+    if values.any(|value| Some(value) != first) {
+        warn!(
+            "database \"{}\" has different \"{}\" settings, using the first one",
+            name, setting
+        );
+    }
+    first
+}
+
 /// Load databases from config.
 pub fn from_config(config: &ConfigAndUsers) -> Databases {
     let mut databases = HashMap::new();
 
+    // Apply mapping changes to clusters still in use, too.
+    ListMappings::global().configure(&config.config);
+
     for user in &config.users.users {
+        // Created when clients connect, see `instantiate`.
+        if user.name_pattern.is_some() || config.config.is_template(&user.database) {
+            continue;
+        }
+
         if let Some((user, cluster)) = new_pool(user, &config.config) {
             databases.insert(user, cluster);
         }
@@ -455,9 +976,330 @@ pub fn from_config(config: &ConfigAndUsers) -> Databases {
         mirrors.insert(cluster.name().to_owned(), mirror_clusters);
     }
 
+    let default_users = config
+        .config
+        .databases
+        .iter()
+        .filter_map(|database| {
+            database
+                .default_user
+                .clone()
+                .map(|user| (database.name.clone(), user))
+        })
+        .collect();
+
     Databases {
         databases,
         manual_queries: config.config.manual_queries(),
         mirrors,
+        default_users,
+        instances: HashMap::new(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::admin::{show_databases::ShowDatabases, Command};
+    use crate::backend::pool::Request;
+    use crate::config::{DataType, Database, HumanDuration, ShardedTable, User as ConfigUser};
+    use crate::net::{messages::DataRow, FromBytes, Protocol, ToBytes};
+
+    use super::*;
+
+    #[test]
+    fn test_default_user() {
+        let mut config = ConfigAndUsers::default();
+        config.config.databases = vec![Database {
+            name: "pgdog".into(),
+            host: "127.0.0.1".into(),
+            default_user: Some("analyst".into()),
+            ..Default::default()
+        }];
+        config.users.users = vec![
+            ConfigUser {
+                name: "pgdog".into(),
+                database: "pgdog".into(),
+                password: Some("pgdog".into()),
+                ..Default::default()
+            },
+            ConfigUser {
+                name: "analyst".into(),
+                database: "pgdog".into(),
+                password: Some("analyst".into()),
+                wildcard_password: Some("shared".into()),
+                ..Default::default()
+            },
+        ];
+
+        let databases = from_config(&config);
+
+        // Exact match beats default_user.
+        let cluster = databases.cluster(("pgdog", "pgdog")).unwrap();
+        assert_eq!(cluster.user(), "pgdog");
+        assert_eq!(cluster.client_password("pgdog"), Some("pgdog"));
+
+        // Users missing from users.toml share the default user's pool
+        // and authenticate with its wildcard password.
+        let cluster = databases.cluster(("alice", "pgdog")).unwrap();
+        assert_eq!(cluster.user(), "analyst");
+        assert_eq!(cluster.client_password("alice"), Some("shared"));
+        assert!(databases.mirrors(("alice", "pgdog")).is_ok());
+        // Passthrough auth still sees them as missing.
+        assert!(!databases.exists(("alice", "pgdog")));
+
+        // Only for the database that has a default user.
+        assert!(databases.cluster(("alice", "other")).is_err());
+
+        config.users.users[1].wildcard_password = None;
+        let databases = from_config(&config);
+        let cluster = databases.cluster(("alice", "pgdog")).unwrap();
+        assert_eq!(cluster.client_password("alice"), None);
+
+        config.config.databases[0].default_user = None;
+        let databases = from_config(&config);
+        assert!(databases.cluster(("alice", "pgdog")).is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_passthrough_add_concurrent() {
+        let mut config = ConfigAndUsers::default();
+        config.config.databases = vec![Database {
+            name: "passthrough_add".into(),
+            host: "127.0.0.1".into(),
+            ..Default::default()
+        }];
+        crate::config::set(config).unwrap();
+
+        let clients = (0..32)
+            .map(|_| {
+                tokio::spawn(async {
+                    add(ConfigUser::new(
+                        "passthrough_add",
+                        "secret",
+                        "passthrough_add",
+                    ))
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let mut created = 0;
+        for client in clients {
+            if client.await.unwrap() {
+                created += 1;
+            }
+        }
+
+        assert_eq!(created, 1);
+        assert!(databases().exists(("passthrough_add", "passthrough_add")));
+
+        // Pool exists, nothing to do.
+        assert!(!add(ConfigUser::new(
+            "passthrough_add",
+            "secret",
+            "passthrough_add"
+        )));
+
+        databases()
+            .cluster(("passthrough_add", "passthrough_add"))
+            .unwrap()
+            .shutdown();
+    }
+
+    #[tokio::test]
+    async fn test_validate_sharding_schema() {
+        crate::config::test::load_test_sharded();
+        let mut config = (*config()).clone();
+        config.config.general.validate_sharding_schema = ValidateShardingSchema::Strict;
+        config.config.general.checkout_timeout = HumanDuration::from_millis(100);
+        config.config.sharded_tables = vec![ShardedTable {
+            database: "pgdog".into(),
+            name: Some("sharded".into()),
+            column: "id".into(),
+            data_type: DataType::Uuid,
+            ..Default::default()
+        }];
+        crate::config::set(config.clone()).unwrap();
+        init();
+
+        assert!(matches!(
+            validate_sharding_schema().await,
+            Err(Error::ShardingSchema(database)) if database == "pgdog"
+        ));
+
+        config.config.sharded_tables[0].data_type = DataType::Bigint;
+        crate::config::set(config.clone()).unwrap();
+        init();
+        assert!(validate_sharding_schema().await.is_ok());
+
+        // Checked in the background once the database is up.
+        config.config.sharded_tables[0].data_type = DataType::Uuid;
+        for database in config.config.databases.iter_mut() {
+            database.port = 1;
+        }
+        crate::config::set(config).unwrap();
+        init();
+        assert!(validate_sharding_schema().await.is_ok());
+        let cluster = databases().cluster(("pgdog", "pgdog")).unwrap();
+        assert_eq!(
+            cluster.sharding_schema_validation().await,
+            Validation::Unavailable
+        );
+        cluster.shutdown();
+    }
+
+    #[tokio::test]
+    async fn test_template() {
+        let mut config = ConfigAndUsers::default();
+        config.config.databases = vec![Database {
+            name: "shards".into(),
+            name_pattern: Some("shard_*".into()),
+            host: "127.0.0.1".into(),
+            pool_size: Some(3),
+            ..Default::default()
+        }];
+        config.users.users = vec![ConfigUser {
+            name: "pgdog".into(),
+            database: "shards".into(),
+            password: Some("pgdog".into()),
+            ..Default::default()
+        }];
+        crate::config::set(config).unwrap();
+        init();
+
+        // Templates aren't databases.
+        assert!(databases().cluster(("pgdog", "shards")).is_err());
+        assert!(!instantiate("pgdog", "shards"));
+        assert!(!instantiate("pgdog", "other"));
+
+        let mut pools = vec![];
+        for database in ["shard_0", "shard_1"] {
+            assert_eq!(
+                template_password("pgdog", database).as_deref(),
+                Some("pgdog")
+            );
+            assert!(instantiate("pgdog", database));
+            assert!(!instantiate("pgdog", database));
+            assert!(template_password("pgdog", database).is_none());
+
+            let cluster = databases().cluster(("pgdog", database)).unwrap();
+            assert_eq!(cluster.name(), database);
+            let mut server = cluster.primary(0, &Request::default()).await.unwrap();
+            let current = server
+                .fetch_all::<String>("SELECT current_database()")
+                .await
+                .unwrap();
+            assert_eq!(current, vec![database.to_string()]);
+
+            let instance = databases().instances()[&("pgdog", database).to_user()].clone();
+            assert_eq!(instance.database.as_deref(), Some("shards"));
+            pools.push(cluster.shards()[0].pools()[0].clone());
+        }
+
+        // Independent pools with the template's settings.
+        assert_ne!(pools[0].id(), pools[1].id());
+        assert!(pools.iter().all(|pool| pool.config().max == 3));
+
+        // Listed with their template.
+        let rows = ShowDatabases
+            .execute()
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|message| message.code() == 'D')
+            .map(|message| DataRow::from_bytes(message.to_bytes().unwrap()).unwrap())
+            .map(|row| {
+                (0..7)
+                    .map(|column| row.get_text(column).unwrap())
+                    .collect::<Vec<_>>()
+            })
+            .filter(|row| row[0].starts_with("shard_"))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            rows,
+            ["shard_0", "shard_1"].map(|database| {
+                [
+                    database,
+                    "pgdog",
+                    database,
+                    "1",
+                    "transaction",
+                    "shards",
+                    "",
+                ]
+                .map(String::from)
+                .to_vec()
+            })
+        );
+
+        // Closed once idle.
+        assert_eq!(evict(Duration::from_secs(60)), 0);
+        assert_eq!(evict(Duration::ZERO), 2);
+        assert!(databases().cluster(("pgdog", "shard_0")).is_err());
+        assert!(databases().instances().is_empty());
+
+        // Up to template_limit.
+        let mut config = (*crate::config::config()).clone();
+        config.config.general.template_limit = 1;
+        crate::config::set(config).unwrap();
+        assert!(instantiate("pgdog", "shard_0"));
+        assert!(!instantiate("pgdog", "shard_1"));
+        assert_eq!(evict(Duration::ZERO), 1);
+    }
+
+    #[test]
+    fn test_passthrough_add_failure_cached() {
+        let key = User {
+            user: "passthrough_missing".into(),
+            database: "passthrough_missing".into(),
+        };
+        let user = || ConfigUser::new("passthrough_missing", "secret", "passthrough_missing");
+
+        // Database isn't configured.
+        assert!(!add(user()));
+        let failed_at = *PASSTHROUGH_FAILURES.lock().get(&key).unwrap();
+
+        // Not retried until the failure expires.
+        assert!(!add(user()));
+        assert_eq!(PASSTHROUGH_FAILURES.lock().get(&key), Some(&failed_at));
+
+        PASSTHROUGH_FAILURES
+            .lock()
+            .insert(key.clone(), Instant::now() - PASSTHROUGH_FAILURE_TTL);
+        assert!(!add(user()));
+        assert!(PASSTHROUGH_FAILURES.lock().get(&key).unwrap() > &failed_at);
+    }
+
+    #[test]
+    fn test_passthrough_failures_bounded() {
+        let mut failures = HashMap::new();
+        let storm = |i: usize| User {
+            user: format!("storm_{}", i),
+            database: "pgdog".into(),
+        };
+
+        // Wrong password storm with a different user each time.
+        for i in 0..20 {
+            record_failure(&mut failures, storm(i), 10);
+        }
+        assert_eq!(failures.len(), 10);
+
+        // Oldest ones went first.
+        assert!(!failures.contains_key(&storm(0)));
+        assert!(failures.contains_key(&storm(19)));
+
+        // Expired ones are swept.
+        for failed_at in failures.values_mut() {
+            *failed_at -= PASSTHROUGH_FAILURE_TTL;
+        }
+        record_failure(&mut failures, storm(20), 10);
+        assert_eq!(failures.len(), 1);
+
+        // Clients that sent the wrong password don't create pools until it expires.
+        passthrough_failed("passthrough_bad_password", "pgdog");
+        assert!(!add(ConfigUser::new(
+            "passthrough_bad_password",
+            "wrong",
+            "pgdog"
+        )));
     }
 }
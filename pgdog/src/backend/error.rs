@@ -36,6 +36,9 @@ pub enum Error {
     #[error("multi shard copy not connected")]
     CopyNotConnected,
 
+    #[error("COPY row is routed to shard {0}, but there are only {1} shards")]
+    CopyShard(usize, usize),
+
     #[error("{0}")]
     Pool(#[from] crate::backend::pool::Error),
 
@@ -105,8 +108,20 @@ pub enum Error {
     #[error("mirror buffer empty")]
     MirrorBufferEmpty,
 
+    #[error("rows from shard {0} are not sorted using the \"{1}\" text_merge_collation, make sure it matches the database collation")]
+    MergeCollation(usize, crate::config::TextMergeCollation),
+
+    #[error("multi-row INSERT has rows on shards {0:?}, but the transaction is already using other shards")]
+    InsertSplitShards(Vec<usize>),
+
     #[error("{0}")]
     FrontendError(Box<crate::frontend::Error>),
+
+    #[error("session setup statement \"{0}\" failed: {1}")]
+    SessionSetup(String, Box<ErrorResponse>),
+
+    #[error("sharded tables of database \"{0}\" don't match the schema, see errors above")]
+    ShardingSchema(String),
 }
 
 impl From<crate::frontend::Error> for Error {
@@ -124,7 +139,22 @@ impl Error {
             Error::Pool(PoolError::CheckoutTimeout) => true,
             Error::Pool(PoolError::AllReplicasDown) => true,
             Error::Pool(PoolError::Banned) => true,
+            Error::Pool(PoolError::LoadShed) => true,
+            Error::Pool(PoolError::StatementTimeout) => true,
             _ => false,
         }
     }
+
+    /// Client's statement_timeout expired waiting for a connection.
+    pub fn statement_timeout(&self) -> bool {
+        matches!(
+            self,
+            Error::Pool(crate::backend::pool::Error::StatementTimeout)
+        )
+    }
+
+    /// Transaction rejected by load shedding.
+    pub fn load_shed(&self) -> bool {
+        matches!(self, Error::Pool(crate::backend::pool::Error::LoadShed))
+    }
 }
@@ -8,10 +8,13 @@ pub mod protocol;
 pub mod pub_sub;
 pub mod reload_notify;
 pub mod replication;
+pub mod resharding;
 pub mod schema;
 pub mod server;
 pub mod server_options;
+pub mod server_tls;
 pub mod stats;
+pub mod users_watch;
 
 pub use error::Error;
 pub use pool::{Cluster, ClusterShardConfig, Pool, Replicas, Shard, ShardingSchema};
@@ -22,4 +25,5 @@ pub use replication::ShardedTables;
 pub use schema::Schema;
 pub use server::Server;
 pub use server_options::ServerOptions;
+pub use server_tls::ServerTls;
 pub use stats::Stats;
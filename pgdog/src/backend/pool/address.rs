@@ -1,5 +1,5 @@
 //! Server address.
-use std::net::{SocketAddr, ToSocketAddrs};
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
 
 use serde::{Deserialize, Serialize};
 use url::Url;
@@ -50,6 +50,15 @@ impl Address {
         }
     }
 
+    /// Both addresses connect to the same server as the same user.
+    /// Passwords can differ, e.g. after a password rotation.
+    pub fn same_server(&self, other: &Address) -> bool {
+        self.host == other.host
+            && self.port == other.port
+            && self.database_name == other.database_name
+            && self.user == other.user
+    }
+
     pub async fn addr(&self) -> Result<SocketAddr, Error> {
         let dns_cache_override_enabled = config().config.general.dns_ttl().is_some();
 
@@ -58,6 +67,10 @@ impl Address {
             return Ok(SocketAddr::new(ip, self.port));
         }
 
+        if let Ok(ip) = self.host.parse::<IpAddr>() {
+            return Ok(SocketAddr::new(ip, self.port));
+        }
+
         let addr_str = format!("{}:{}", self.host, self.port);
         let mut socket_addrs = addr_str.to_socket_addrs()?;
 
@@ -143,6 +156,22 @@ mod test {
         assert_eq!(address.password, "hunter3");
     }
 
+    #[test]
+    fn test_same_server() {
+        let address = Address::new_test();
+        let rotated = Address {
+            password: "rotated".into(),
+            ..Address::new_test()
+        };
+        assert!(address.same_server(&rotated));
+
+        let other = Address {
+            user: "alice".into(),
+            ..Address::new_test()
+        };
+        assert!(!address.same_server(&other));
+    }
+
     #[test]
     fn test_addr_from_url() {
         let addr =
@@ -154,4 +183,15 @@ mod test {
         assert_eq!(addr.user, "user");
         assert_eq!(addr.password, "password");
     }
+
+    #[tokio::test]
+    async fn test_addr_ipv6() {
+        let address = Address {
+            host: "::1".into(),
+            port: 6432,
+            ..Address::new_test()
+        };
+        let addr = address.addr().await.unwrap();
+        assert_eq!(addr, "[::1]:6432".parse().unwrap());
+    }
 }
@@ -9,6 +9,8 @@ static PREPARED: Lazy<Vec<Query>> = Lazy::new(|| vec![Query::new("DEALLOCATE ALL
 static DIRTY: Lazy<Vec<Query>> = Lazy::new(|| {
     vec![
         Query::new("RESET ALL"),
+        Query::new("RESET ROLE"),
+        Query::new("RESET SESSION AUTHORIZATION"),
         Query::new("SELECT pg_advisory_unlock_all()"),
     ]
 });
@@ -1,32 +1,92 @@
 //! A collection of replicas and a primary.
 
 use parking_lot::RwLock;
-use std::sync::Arc;
-use tokio::spawn;
-use tracing::{error, info};
+use std::{sync::Arc, time::Duration};
+use tokio::{spawn, time::sleep};
+use tracing::{error, info, warn};
 
 use crate::{
     backend::{
-        databases::databases,
+        databases::{self, databases},
         replication::{ReplicationConfig, ShardedColumn},
+        schema::{
+            sharding::{self, Mismatch, Validation, ValidationStatus},
+            ColumnOrder,
+        },
         Schema, ShardedTables,
     },
     config::{
-        General, MultiTenant, PoolerMode, ReadWriteSplit, ReadWriteStrategy, ShardedTable, User,
+        Database, General, MirrorStrategy, MultiTenant, PoolerMode, ReadConsistency,
+        ReadWriteSplit, ReadWriteStrategy, Role, ShardedTable, User, ValidateShardingSchema,
+    },
+    frontend::router::{
+        parser::{FunctionLists, Policy},
+        round_robin::RoundRobin,
     },
-    net::{messages::BackendKeyData, Query},
+    net::{messages::BackendKeyData, Parameter, Query},
 };
 
-use super::{Address, Config, Error, Guard, Request, Shard};
+use super::{
+    connection::multi_shard::CrossShardLimits, Address, Config, Error, Guard, Request,
+    SessionSetup, Settings, Shard, ShardSkew,
+};
 use crate::config::LoadBalancingStrategy;
 
-#[derive(Clone, Debug)]
+/// How often to retry checking sharded tables while databases are down.
+const VALIDATION_RETRY: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Debug, Default)]
 /// Database configuration.
 pub struct PoolConfig {
     /// Database address.
     pub(crate) address: Address,
     /// Pool settings.
     pub(crate) config: Config,
+    /// Healthcheck query, if not the default one.
+    pub(crate) healthcheck_query: Option<String>,
+    /// Startup parameters configured for the database.
+    pub(crate) startup_parameters: Vec<Parameter>,
+    /// User and database of the cluster this pool belongs to.
+    pub(crate) cluster: Option<databases::User>,
+}
+
+impl PoolConfig {
+    /// Create pool configuration from database/user configuration.
+    pub fn new(config: &crate::config::Config, database: &Database, user: &User) -> Self {
+        let general = &config.general;
+        Self {
+            address: Address::new(database, user),
+            config: Config {
+                healthcheck: config.healthcheck,
+                ..Config::new(general, database, user)
+            },
+            healthcheck_query: database
+                .healthcheck_query
+                .clone()
+                .or(general.healthcheck_query.clone()),
+            startup_parameters: Self::startup_parameters(database),
+            cluster: Some(databases::User {
+                user: user.name.clone(),
+                database: user.database.clone(),
+            }),
+        }
+    }
+
+    /// Startup parameters and options, skipping the ones managed by PgDog.
+    fn startup_parameters(database: &Database) -> Vec<Parameter> {
+        let mut params = database
+            .startup_parameters
+            .iter()
+            .filter(|(name, _)| !Database::managed_startup_parameter(name))
+            .map(|(name, value)| Parameter::from((name.as_str(), value.as_str())))
+            .collect::<Vec<_>>();
+
+        if let Some(ref options) = database.options {
+            params.push(Parameter::from(("options", options.as_str())));
+        }
+
+        params
+    }
 }
 
 /// A collection of sharded replicas and primaries
@@ -37,14 +97,27 @@ pub struct Cluster {
     shards: Vec<Shard>,
     user: String,
     password: String,
+    wildcard_password: Option<String>,
     pooler_mode: PoolerMode,
     sharded_tables: ShardedTables,
     replication_sharding: Option<String>,
+    replication_mode: bool,
     mirror_of: Option<String>,
+    mirror_strategy: MirrorStrategy,
     schema: Arc<RwLock<Schema>>,
+    column_order: ColumnOrder,
     multi_tenant: Option<MultiTenant>,
     rw_strategy: ReadWriteStrategy,
     rw_split: ReadWriteSplit,
+    function_lists: Arc<FunctionLists>,
+    policy: Arc<Policy>,
+    read_consistency: ReadConsistency,
+    shard_skew: Arc<ShardSkew>,
+    session_setup: Arc<SessionSetup>,
+    validate_sharding_schema: ValidateShardingSchema,
+    sharding_validation: Arc<ValidationStatus>,
+    cross_shard_limits: CrossShardLimits,
+    round_robin: RoundRobin,
 }
 
 /// Sharding configuration from the cluster.
@@ -54,6 +127,10 @@ pub struct ShardingSchema {
     pub shards: usize,
     /// Sharded tables.
     pub tables: ShardedTables,
+    /// Column order of tables, used when queries don't list columns.
+    pub columns: ColumnOrder,
+    /// Round robin counters of the cluster.
+    pub round_robin: RoundRobin,
 }
 
 impl ShardingSchema {
@@ -74,13 +151,22 @@ pub struct ClusterConfig<'a> {
     pub lb_strategy: LoadBalancingStrategy,
     pub user: &'a str,
     pub password: &'a str,
+    pub wildcard_password: Option<&'a str>,
     pub pooler_mode: PoolerMode,
     pub sharded_tables: ShardedTables,
     pub replication_sharding: Option<String>,
+    pub replication_mode: bool,
     pub mirror_of: Option<&'a str>,
+    pub mirror_strategy: MirrorStrategy,
     pub multi_tenant: &'a Option<MultiTenant>,
     pub rw_strategy: ReadWriteStrategy,
     pub rw_split: ReadWriteSplit,
+    pub function_lists: FunctionLists,
+    pub policy: Policy,
+    pub read_consistency: ReadConsistency,
+    pub session_setup: SessionSetup,
+    pub validate_sharding_schema: ValidateShardingSchema,
+    pub cross_shard_limits: CrossShardLimits,
 }
 
 impl<'a> ClusterConfig<'a> {
@@ -90,21 +176,34 @@ impl<'a> ClusterConfig<'a> {
         shards: &'a [ClusterShardConfig],
         sharded_tables: ShardedTables,
         mirror_of: Option<&'a str>,
+        mirror_strategy: MirrorStrategy,
         multi_tenant: &'a Option<MultiTenant>,
     ) -> Self {
         Self {
             name: &user.database,
             password: user.password(),
+            wildcard_password: user.wildcard_password.as_deref(),
             user: &user.name,
             replication_sharding: user.replication_sharding.clone(),
+            replication_mode: user.replication_mode.enabled(),
             pooler_mode: user.pooler_mode.unwrap_or(general.pooler_mode),
             lb_strategy: general.load_balancing_strategy,
             shards,
             sharded_tables,
             mirror_of,
+            mirror_strategy,
             multi_tenant,
             rw_strategy: general.read_write_strategy,
             rw_split: general.read_write_split,
+            function_lists: FunctionLists::new(
+                &general.function_allowlist,
+                &general.function_denylist,
+            ),
+            policy: Policy::new(user),
+            read_consistency: user.read_consistency,
+            session_setup: SessionSetup::default(),
+            validate_sharding_schema: general.validate_sharding_schema,
+            cross_shard_limits: CrossShardLimits::new(general, user),
         }
     }
 }
@@ -118,31 +217,56 @@ impl Cluster {
             lb_strategy,
             user,
             password,
+            wildcard_password,
             pooler_mode,
             sharded_tables,
             replication_sharding,
+            replication_mode,
             mirror_of,
+            mirror_strategy,
             multi_tenant,
             rw_strategy,
             rw_split,
+            function_lists,
+            policy,
+            read_consistency,
+            session_setup,
+            validate_sharding_schema,
+            cross_shard_limits,
         } = config;
 
+        let shards = shards
+            .iter()
+            .map(|config| Shard::new(&config.primary, &config.replicas, lb_strategy, rw_split))
+            .collect::<Vec<_>>();
+        let column_order = ColumnOrder::new(shards.first().cloned());
+
         Self {
-            shards: shards
-                .iter()
-                .map(|config| Shard::new(&config.primary, &config.replicas, lb_strategy, rw_split))
-                .collect(),
+            shards,
             name: name.to_owned(),
             password: password.to_owned(),
+            wildcard_password: wildcard_password.map(|p| p.to_owned()),
             user: user.to_owned(),
             pooler_mode,
             sharded_tables,
             replication_sharding,
+            replication_mode,
             mirror_of: mirror_of.map(|s| s.to_owned()),
+            mirror_strategy,
             schema: Arc::new(RwLock::new(Schema::default())),
+            column_order,
             multi_tenant: multi_tenant.clone(),
             rw_strategy,
             rw_split,
+            function_lists: Arc::new(function_lists),
+            policy: Arc::new(policy),
+            read_consistency,
+            shard_skew: Arc::new(ShardSkew::default()),
+            session_setup: Arc::new(session_setup),
+            validate_sharding_schema,
+            sharding_validation: Arc::new(ValidationStatus::default()),
+            cross_shard_limits,
+            round_robin: RoundRobin::default(),
         }
     }
 
@@ -180,19 +304,39 @@ impl Cluster {
     /// This will allocate new server connections. Use when reloading configuration
     /// and you expect to drop the current Cluster entirely.
     pub fn duplicate(&self) -> Self {
+        let shards = self
+            .shards
+            .iter()
+            .map(|s| s.duplicate())
+            .collect::<Vec<_>>();
+        let column_order = ColumnOrder::new(shards.first().cloned());
+
         Self {
-            shards: self.shards.iter().map(|s| s.duplicate()).collect(),
+            shards,
             name: self.name.clone(),
             user: self.user.clone(),
             password: self.password.clone(),
+            wildcard_password: self.wildcard_password.clone(),
             pooler_mode: self.pooler_mode,
             sharded_tables: self.sharded_tables.clone(),
             replication_sharding: self.replication_sharding.clone(),
+            replication_mode: self.replication_mode,
             mirror_of: self.mirror_of.clone(),
+            mirror_strategy: self.mirror_strategy,
             schema: self.schema.clone(),
+            column_order,
             multi_tenant: self.multi_tenant.clone(),
             rw_strategy: self.rw_strategy,
             rw_split: self.rw_split,
+            function_lists: self.function_lists.clone(),
+            policy: self.policy.clone(),
+            read_consistency: self.read_consistency,
+            shard_skew: self.shard_skew.clone(),
+            session_setup: self.session_setup.clone(),
+            validate_sharding_schema: self.validate_sharding_schema,
+            sharding_validation: Arc::new(ValidationStatus::default()),
+            cross_shard_limits: self.cross_shard_limits,
+            round_robin: self.round_robin.clone(),
         }
     }
 
@@ -215,11 +359,28 @@ impl Cluster {
         self.mirror_of.as_deref()
     }
 
+    /// How mirrored queries are routed on this cluster.
+    pub fn mirror_strategy(&self) -> MirrorStrategy {
+        self.mirror_strategy
+    }
+
     /// Get the password the user should use to connect to the database.
     pub fn password(&self) -> &str {
         &self.password
     }
 
+    /// Get the password a client should use to connect to the database.
+    ///
+    /// Users not in users.toml are mapped to the database's `default_user`
+    /// and use its `wildcard_password`. `None` if they can't connect.
+    pub fn client_password(&self, user: &str) -> Option<&str> {
+        if self.user == user {
+            Some(&self.password)
+        } else {
+            self.wildcard_password.as_deref()
+        }
+    }
+
     /// User name.
     pub fn user(&self) -> &str {
         &self.user
@@ -235,6 +396,26 @@ impl Cluster {
         self.pooler_mode
     }
 
+    /// Staleness of reads the user allows.
+    pub fn read_consistency(&self) -> ReadConsistency {
+        self.read_consistency
+    }
+
+    /// Statements run at the start of each client session.
+    pub fn session_setup(&self) -> Arc<SessionSetup> {
+        self.session_setup.clone()
+    }
+
+    /// Limits of queries sent to multiple shards.
+    pub fn cross_shard_limits(&self) -> CrossShardLimits {
+        self.cross_shard_limits
+    }
+
+    /// Skew of cross-shard queries.
+    pub fn shard_skew(&self) -> &Arc<ShardSkew> {
+        &self.shard_skew
+    }
+
     // Get sharded tables if any.
     pub fn sharded_tables(&self) -> &[ShardedTable] {
         self.sharded_tables.tables()
@@ -256,6 +437,27 @@ impl Cluster {
         true
     }
 
+    /// Server settings captured by the pools SHOW is sent to.
+    pub fn settings(&self) -> Option<Arc<Settings>> {
+        let read_only = self.read_only();
+        self.shards
+            .iter()
+            .flat_map(|shard| shard.pools_with_roles())
+            .filter(|(role, _)| read_only || *role == Role::Primary)
+            .find_map(|(_, pool)| pool.settings())
+    }
+
+    /// All databases are configured with `read_only`,
+    /// so PgDog rejects writes before sending them.
+    pub fn writes_disabled(&self) -> bool {
+        let mut pools = self
+            .shards
+            .iter()
+            .flat_map(|shard| shard.pools())
+            .peekable();
+        pools.peek().is_some() && pools.all(|pool| pool.config().read_only)
+    }
+
     /// This cluster is write_only if zero shards have a replica.
     pub fn write_only(&self) -> bool {
         for shard in &self.shards {
@@ -278,6 +480,11 @@ impl Cluster {
         &self.multi_tenant
     }
 
+    /// Server connections are opened in replication mode.
+    pub fn replication_mode(&self) -> bool {
+        self.replication_mode
+    }
+
     /// Get replication configuration for this cluster.
     pub fn replication_sharding_config(&self) -> Option<ReplicationConfig> {
         self.replication_sharding
@@ -290,6 +497,8 @@ impl Cluster {
         ShardingSchema {
             shards: self.shards.len(),
             tables: self.sharded_tables.clone(),
+            columns: self.column_order.clone(),
+            round_robin: self.round_robin.clone(),
         }
     }
 
@@ -320,6 +529,97 @@ impl Cluster {
         &self.rw_strategy
     }
 
+    /// Functions configured as read-safe or writing.
+    pub fn function_lists(&self) -> &FunctionLists {
+        &self.function_lists
+    }
+
+    /// Statements and functions blocked for the user.
+    pub fn policy(&self) -> &Policy {
+        &self.policy
+    }
+
+    /// Check sharded tables against the schema of all shards.
+    pub async fn check_sharding_schema(
+        &self,
+    ) -> Result<Vec<(usize, Mismatch)>, crate::backend::Error> {
+        let mut mismatches = vec![];
+
+        for (number, shard) in self.shards.iter().enumerate() {
+            let mut server = shard.primary_or_replica(&Request::default()).await?;
+            let shard_mismatches =
+                sharding::validate(&mut server, self.sharded_tables(), self.shards.len()).await?;
+            mismatches.extend(
+                shard_mismatches
+                    .into_iter()
+                    .map(|mismatch| (number, mismatch)),
+            );
+        }
+
+        Ok(mismatches)
+    }
+
+    /// Check sharded tables, retrying until the databases are up.
+    async fn validate_sharding_schema(&self) {
+        loop {
+            match self.check_sharding_schema().await {
+                Ok(mismatches) => {
+                    for (shard, mismatch) in &mismatches {
+                        error!(
+                            "sharded table mismatch on shard {}: {} [{}]",
+                            shard,
+                            mismatch,
+                            self.name()
+                        );
+                    }
+
+                    self.sharding_validation.set(if mismatches.is_empty() {
+                        Validation::Valid
+                    } else {
+                        Validation::Invalid
+                    });
+                    return;
+                }
+
+                // Cluster was shut down.
+                Err(crate::backend::Error::Pool(super::Error::Offline)) => return,
+
+                Err(err) => {
+                    warn!(
+                        "couldn't check sharded tables, retrying in {}s: {} [{}]",
+                        VALIDATION_RETRY.as_secs(),
+                        err,
+                        self.name()
+                    );
+                    self.sharding_validation.set(Validation::Unavailable);
+                    sleep(VALIDATION_RETRY).await;
+                }
+            }
+        }
+    }
+
+    fn validate_sharding_schema_enabled(&self) -> bool {
+        self.validate_sharding_schema.enabled()
+            && self
+                .sharded_tables()
+                .iter()
+                .any(|table| table.name.is_some())
+    }
+
+    /// How sharded tables are checked against the schema.
+    pub fn validate_sharding_schema_mode(&self) -> ValidateShardingSchema {
+        self.validate_sharding_schema
+    }
+
+    /// Wait for the first check of sharded tables against the schema.
+    pub async fn sharding_schema_validation(&self) -> Validation {
+        if !self.validate_sharding_schema_enabled() {
+            return Validation::Valid;
+        }
+
+        self.sharding_validation.attempted().await
+    }
+
     /// Launch the connection pools.
     pub(crate) fn launch(&self) {
         for shard in self.shards() {
@@ -334,6 +634,13 @@ impl Cluster {
                 }
             });
         }
+
+        if self.validate_sharding_schema_enabled() {
+            let me = self.clone();
+            spawn(async move {
+                me.validate_sharding_schema().await;
+            });
+        }
     }
 
     /// Shutdown the connection pools.
@@ -343,6 +650,12 @@ impl Cluster {
         }
     }
 
+    /// Close idle connections above the minimum pool size in every pool.
+    /// Returns how many were closed.
+    pub fn shrink(&self) -> usize {
+        self.shards().iter().map(|shard| shard.shrink()).sum()
+    }
+
     /// Execute a query on every primary in the cluster.
     pub async fn execute(
         &self,
@@ -359,6 +672,8 @@ impl Cluster {
 
 #[cfg(test)]
 mod test {
+    use std::sync::Arc;
+
     use crate::{
         backend::pool::{Address, Config, PoolConfig},
         backend::{Shard, ShardedTables},
@@ -366,6 +681,7 @@ mod test {
             DataType, Hasher, LoadBalancingStrategy, ReadWriteSplit, ReadWriteStrategy,
             ShardedTable,
         },
+        frontend::router::parser::{FunctionLists, Policy},
     };
 
     use super::Cluster;
@@ -385,6 +701,7 @@ mod test {
                         centroid_probes: 1,
                         hasher: Hasher::Postgres,
                         mapping: None,
+                        ..Default::default()
                     }],
                     vec!["sharded_omni".into()],
                 ),
@@ -393,10 +710,12 @@ mod test {
                         &Some(PoolConfig {
                             address: Address::new_test(),
                             config: Config::default(),
+                            ..Default::default()
                         }),
                         &[PoolConfig {
                             address: Address::new_test(),
                             config: Config::default(),
+                            ..Default::default()
                         }],
                         LoadBalancingStrategy::Random,
                         ReadWriteSplit::default(),
@@ -405,10 +724,12 @@ mod test {
                         &Some(PoolConfig {
                             address: Address::new_test(),
                             config: Config::default(),
+                            ..Default::default()
                         }),
                         &[PoolConfig {
                             address: Address::new_test(),
                             config: Config::default(),
+                            ..Default::default()
                         }],
                         LoadBalancingStrategy::Random,
                         ReadWriteSplit::default(),
@@ -427,8 +748,63 @@ mod test {
             cluster
         }
 
+        /// Cluster with one shard, on this database.
+        pub fn new_test_database(database_name: &str) -> Cluster {
+            let config = PoolConfig {
+                address: Address {
+                    database_name: database_name.into(),
+                    ..Address::new_test()
+                },
+                config: Config::default(),
+                ..Default::default()
+            };
+
+            Cluster {
+                shards: vec![Shard::new(
+                    &Some(config.clone()),
+                    &[config],
+                    LoadBalancingStrategy::Random,
+                    ReadWriteSplit::default(),
+                )],
+                ..Self::new_test()
+            }
+        }
+
+        pub fn new_test_read_only() -> Cluster {
+            let config = PoolConfig {
+                address: Address::new_test(),
+                config: Config {
+                    read_only: true,
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+
+            Cluster {
+                shards: vec![Shard::new(
+                    &Some(config.clone()),
+                    &[config],
+                    LoadBalancingStrategy::Random,
+                    ReadWriteSplit::default(),
+                )],
+                ..Self::new_test()
+            }
+        }
+
         pub fn set_read_write_strategy(&mut self, rw_strategy: ReadWriteStrategy) {
             self.rw_strategy = rw_strategy;
         }
+
+        pub fn set_function_lists(&mut self, function_lists: FunctionLists) {
+            self.function_lists = Arc::new(function_lists);
+        }
+
+        pub fn set_policy(&mut self, policy: Policy) {
+            self.policy = Arc::new(policy);
+        }
+
+        pub fn set_sharded_tables(&mut self, sharded_tables: ShardedTables) {
+            self.sharded_tables = sharded_tables;
+        }
     }
 }
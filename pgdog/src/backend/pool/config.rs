@@ -4,7 +4,9 @@ use std::{time::Duration, usize};
 
 use serde::{Deserialize, Serialize};
 
-use crate::config::{Database, General, PoolerMode, User};
+use crate::config::{
+    CheckoutOrder, Database, General, Healthcheck, PoolerMode, ReplicationMode, Role, User,
+};
 
 /// Pool configuration.
 #[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
@@ -15,6 +17,10 @@ pub struct Config {
     pub max: usize,
     /// How long to wait for a connection before giving up.
     pub checkout_timeout: Duration, // ms
+    /// Which waiting client is served first.
+    pub checkout_order: CheckoutOrder,
+    /// Clients waiting longer than this are served first.
+    pub checkout_starvation: Duration,
     /// Interval duration of DNS cache refresh.
     pub dns_ttl: Duration, // ms
     /// Close connections that have been idle for longer than this.
@@ -50,13 +56,25 @@ pub struct Config {
     /// Statement timeout
     pub statement_timeout: Option<Duration>,
     /// Replication mode.
-    pub replication_mode: bool,
+    pub replication_mode: ReplicationMode,
     /// Pooler mode.
     pub pooler_mode: PoolerMode,
     /// Read only mode.
     pub read_only: bool,
     /// Maximum prepared statements per connection.
     pub prepared_statements_limit: usize,
+    /// Most used prepared statements to prepare on new connections.
+    pub prepared_statements_prewarm: usize,
+    /// How long to wait for prewarming before draining the replies.
+    pub prepared_statements_prewarm_timeout: Duration,
+    /// Slow start window after the pool comes online.
+    pub slow_start: Duration,
+    /// Role the database is expected to have, checked during healthchecks.
+    pub role_check: Option<Role>,
+    /// Shed new transactions when p95 checkout wait is above this.
+    pub shed_above_wait: Option<Duration>,
+    /// Ban policies for healthcheck failures.
+    pub healthcheck: Healthcheck,
 }
 
 impl Config {
@@ -124,6 +142,11 @@ impl Config {
         self.query_timeout
     }
 
+    /// Slow start window.
+    pub fn slow_start(&self) -> Duration {
+        self.slow_start
+    }
+
     /// Default config for a primary.
     ///
     /// The ban is ignored by the shard router
@@ -147,35 +170,49 @@ impl Config {
             max: database
                 .pool_size
                 .unwrap_or(user.pool_size.unwrap_or(general.default_pool_size)),
-            healthcheck_interval: Duration::from_millis(general.healthcheck_interval),
-            idle_healthcheck_interval: Duration::from_millis(general.idle_healthcheck_interval),
-            idle_healthcheck_delay: Duration::from_millis(general.idle_healthcheck_delay),
-            healthcheck_timeout: Duration::from_millis(general.healthcheck_timeout),
-            ban_timeout: Duration::from_millis(general.ban_timeout),
-            rollback_timeout: Duration::from_millis(general.rollback_timeout),
+            healthcheck_interval: general.healthcheck_interval(),
+            idle_healthcheck_interval: general.idle_healthcheck_interval(),
+            idle_healthcheck_delay: general.idle_healthcheck_delay(),
+            healthcheck_timeout: general.healthcheck_timeout(),
+            ban_timeout: general.ban_timeout(),
+            rollback_timeout: general.rollback_timeout(),
             statement_timeout: if let Some(statement_timeout) = database.statement_timeout {
                 Some(statement_timeout)
             } else {
                 user.statement_timeout
             }
-            .map(Duration::from_millis),
+            .map(Duration::from),
             replication_mode: user.replication_mode,
             pooler_mode: database
                 .pooler_mode
                 .unwrap_or(user.pooler_mode.unwrap_or(general.pooler_mode)),
-            connect_timeout: Duration::from_millis(general.connect_timeout),
+            connect_timeout: general.connect_timeout(),
             connect_attempts: general.connect_attempts,
             connect_attempt_delay: general.connect_attempt_delay(),
-            query_timeout: Duration::from_millis(general.query_timeout),
-            checkout_timeout: Duration::from_millis(general.checkout_timeout),
-            idle_timeout: Duration::from_millis(
-                user.idle_timeout
-                    .unwrap_or(database.idle_timeout.unwrap_or(general.idle_timeout)),
-            ),
+            query_timeout: general.query_timeout(),
+            checkout_timeout: general.checkout_timeout(),
+            checkout_order: general.checkout_order,
+            checkout_starvation: general.checkout_starvation(),
+            idle_timeout: user
+                .idle_timeout
+                .unwrap_or(database.idle_timeout.unwrap_or(general.idle_timeout))
+                .into(),
             read_only: database
                 .read_only
                 .unwrap_or(user.read_only.unwrap_or_default()),
             prepared_statements_limit: general.prepared_statements_limit,
+            prepared_statements_prewarm: general.prepared_statements_prewarm,
+            prepared_statements_prewarm_timeout: general.prepared_statements_prewarm_timeout(),
+            slow_start: general.replica_slow_start(),
+            shed_above_wait: general.shed_above_wait(),
+            role_check: if database
+                .expected_role_check
+                .unwrap_or(general.expected_role_check)
+            {
+                Some(database.role)
+            } else {
+                None
+            },
             ..Default::default()
         }
     }
@@ -187,6 +224,8 @@ impl Default for Config {
             min: 1,
             max: 10,
             checkout_timeout: Duration::from_millis(5_000),
+            checkout_order: CheckoutOrder::default(),
+            checkout_starvation: Duration::from_millis(2_500),
             idle_timeout: Duration::from_millis(60_000),
             connect_timeout: Duration::from_millis(5_000),
             connect_attempts: 1,
@@ -203,11 +242,17 @@ impl Default for Config {
             ban_timeout: Duration::from_secs(300),
             rollback_timeout: Duration::from_secs(5),
             statement_timeout: None,
-            replication_mode: false,
+            replication_mode: ReplicationMode::Disabled,
             pooler_mode: PoolerMode::default(),
             read_only: false,
             prepared_statements_limit: usize::MAX,
+            prepared_statements_prewarm: 0,
+            prepared_statements_prewarm_timeout: Duration::from_millis(10),
             dns_ttl: Duration::from_millis(60_000),
+            slow_start: Duration::ZERO,
+            role_check: None,
+            shed_above_wait: None,
+            healthcheck: Healthcheck::default(),
         }
     }
 }
@@ -1,11 +1,19 @@
 //! Binding between frontend client and a connection on the backend.
 
+use std::sync::Arc;
+
 use crate::{
-    frontend::ClientRequest,
-    net::{parameter::Parameters, ProtocolMessage},
+    backend::{
+        databases::User,
+        pool::{pool_impl::parse_pg_lsn, Error as PoolError, SessionSetup},
+    },
+    frontend::{router::parser::InsertSplit, ClientRequest},
+    net::{parameter::Parameters, DataRow, Format, ProtocolMessage},
     state::State,
 };
 
+use tokio::{pin, time::sleep_until};
+
 use super::*;
 
 /// The server(s) the client is connected to.
@@ -86,19 +94,44 @@ impl Binding {
                             return Ok(message);
                         }
                         let mut read = false;
-                        for server in shards.iter_mut() {
+                        for (shard, server) in shards.iter_mut().enumerate() {
                             if !server.has_more_messages() {
                                 continue;
                             }
 
-                            let message = server.read().await?;
+                            let message = match state.deadline() {
+                                Some(deadline) => {
+                                    let reading = server.read();
+                                    pin!(reading);
+
+                                    select! {
+                                        message = &mut reading => message?,
+
+                                        // Keep reading after the cancel, so the server
+                                        // finishes the request and stays in sync.
+                                        _ = sleep_until(deadline) => {
+                                            state.timed_out();
+                                            state.cancel().await;
+                                            reading.await?
+                                        }
+                                    }
+                                }
+                                None => server.read().await?,
+                            };
                             read = true;
-                            if let Some(message) = state.forward(message)? {
+                            let forward = state.forward(shard, message)?;
+                            if state.cancel_pending() {
+                                state.cancel().await;
+                            }
+                            if let Some(message) = forward {
                                 return Ok(message);
                             }
                         }
 
                         if !read {
+                            if let Some(error) = state.flush()? {
+                                return Ok(error);
+                            }
                             break;
                         }
                     }
@@ -126,7 +159,8 @@ impl Binding {
                 }
             }
 
-            Binding::MultiShard(servers, _state) => {
+            Binding::MultiShard(servers, state) => {
+                state.start(servers);
                 for server in servers.iter_mut() {
                     server.send(client_request).await?;
                 }
@@ -136,11 +170,53 @@ impl Binding {
         }
     }
 
+    /// Send each shard its part of a multi-row INSERT split between shards.
+    pub async fn send_split(
+        &mut self,
+        split: &InsertSplit,
+        client_request: &ClientRequest,
+        cluster: &Option<Arc<User>>,
+    ) -> Result<(), Error> {
+        let shards = split.shards();
+
+        match self {
+            Binding::MultiShard(servers, state)
+                if servers.len() == shards.len()
+                    && shards
+                        .iter()
+                        .enumerate()
+                        .all(|(position, shard)| state.shard_number(position) == *shard) =>
+            {
+                state.start(servers);
+                let requests = split.requests(client_request, cluster);
+                for (server, request) in servers.iter_mut().zip(&requests.requests) {
+                    server.send(request).await?;
+                }
+
+                Ok(())
+            }
+
+            // Transaction is already using other shards.
+            _ => Err(Error::InsertSplitShards(shards)),
+        }
+    }
+
     /// Send copy messages to shards they are destined to go.
     pub async fn send_copy(&mut self, rows: Vec<CopyRow>) -> Result<(), Error> {
         match self {
             Binding::MultiShard(servers, _state) => {
                 for row in rows {
+                    // Don't drop rows meant for a shard we don't have.
+                    let shards = servers.len();
+                    let missing = match row.shard() {
+                        Shard::Direct(shard) => Some(*shard).filter(|shard| *shard >= shards),
+                        Shard::Multi(multi) => multi.iter().copied().find(|shard| *shard >= shards),
+                        Shard::All => None,
+                    };
+                    if let Some(shard) = missing {
+                        return Err(Error::CopyShard(shard, shards));
+                    }
+
                     for (shard, server) in servers.iter_mut().enumerate() {
                         match row.shard() {
                             Shard::Direct(row_shard) => {
@@ -188,7 +264,9 @@ impl Binding {
         match self {
             Binding::Admin(admin) => admin.done(),
             Binding::Server(Some(server)) => server.done(),
-            Binding::MultiShard(servers, _state) => servers.iter().all(|s| s.done()),
+            Binding::MultiShard(servers, state) => {
+                servers.iter().all(|s| s.done()) && !state.pending()
+            }
             _ => true,
         }
     }
@@ -197,7 +275,9 @@ impl Binding {
         match self {
             Binding::Admin(admin) => !admin.done(),
             Binding::Server(Some(server)) => server.has_more_messages(),
-            Binding::MultiShard(servers, _state) => servers.iter().any(|s| s.has_more_messages()),
+            Binding::MultiShard(servers, state) => {
+                servers.iter().any(|s| s.has_more_messages()) || state.pending()
+            }
             _ => false,
         }
     }
@@ -239,6 +319,52 @@ impl Binding {
         Ok(())
     }
 
+    /// `pg_current_wal_insert_lsn()` on all servers, with their shard numbers.
+    /// A single server is on the `direct` shard.
+    pub async fn wal_insert_lsn(&mut self, direct: usize) -> Result<Vec<(usize, u64)>, Error> {
+        let (servers, state) = match self {
+            Binding::Server(Some(ref mut server)) => (std::slice::from_mut(server), None),
+            Binding::MultiShard(ref mut servers, ref state) => {
+                (servers.as_mut_slice(), Some(state))
+            }
+            _ => return Ok(vec![]),
+        };
+
+        let mut lsns = vec![];
+        for (position, server) in servers.iter_mut().enumerate() {
+            let rows: Vec<DataRow> = server
+                .fetch_all("SELECT pg_current_wal_insert_lsn()")
+                .await?;
+            let lsn = rows
+                .first()
+                .and_then(|row| row.get::<String>(0, Format::Text))
+                .and_then(|lsn| parse_pg_lsn(&lsn).ok())
+                .ok_or(PoolError::PrimaryLsnQueryFailed)?;
+            let shard = state
+                .map(|state| state.shard_number(position))
+                .unwrap_or(direct);
+            lsns.push((shard, lsn));
+        }
+
+        Ok(lsns)
+    }
+
+    /// Run session setup statements on servers that haven't run them yet.
+    pub async fn setup_session(&mut self, setup: &SessionSetup) -> Result<usize, Error> {
+        match self {
+            Binding::Server(Some(ref mut server)) => server.setup_session(setup).await,
+            Binding::MultiShard(ref mut servers, _) => {
+                let mut max = 0;
+                for server in servers {
+                    max = max.max(server.setup_session(setup).await?);
+                }
+                Ok(max)
+            }
+
+            _ => Ok(0),
+        }
+    }
+
     pub async fn link_client(&mut self, params: &Parameters) -> Result<usize, Error> {
         match self {
             Binding::Server(Some(ref mut server)) => server.link_client(params).await,
@@ -271,6 +397,17 @@ impl Binding {
         }
     }
 
+    /// Lock connected servers to the client.
+    pub(super) fn lock(&mut self, lock: bool) {
+        match self {
+            Binding::Server(Some(ref mut server)) => server.lock(lock),
+            Binding::MultiShard(ref mut servers, _state) => {
+                servers.iter_mut().for_each(|s| s.lock(lock))
+            }
+            _ => (),
+        }
+    }
+
     pub(super) fn dirty(&mut self) {
         match self {
             Binding::Server(Some(ref mut server)) => server.mark_dirty(true),
@@ -299,3 +436,34 @@ impl Binding {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::backend::pool::{connection::multi_shard::CrossShardLimits, test::pool};
+
+    #[tokio::test]
+    async fn test_copy_missing_shard() {
+        let mut servers = vec![];
+        for _ in 0..2 {
+            servers.push(pool().get(&Request::default()).await.unwrap());
+        }
+        let state = MultiShard::new(
+            2,
+            &Route::write(None),
+            Arc::default(),
+            CrossShardLimits::default(),
+        );
+        let mut binding = Binding::MultiShard(servers, state);
+
+        for shard in [Shard::Direct(2), Shard::Multi(vec![0, 3])] {
+            let err = binding
+                .send_copy(vec![CopyRow::new(b"1\n", shard)])
+                .await
+                .unwrap_err();
+            assert!(matches!(err, Error::CopyShard(_, 2)), "{}", err);
+        }
+    }
+}
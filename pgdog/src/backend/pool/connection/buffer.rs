@@ -2,13 +2,16 @@
 
 use std::{
     cmp::Ordering,
-    collections::{HashSet, VecDeque},
+    collections::{HashMap, HashSet, VecDeque},
 };
 
+use feruca::Collator;
+
 use crate::{
+    config::TextMergeCollation,
     frontend::router::parser::{Aggregate, DistinctBy, DistinctColumn, OrderBy},
     net::{
-        messages::{DataRow, FromBytes, Message, Protocol, ToBytes, Vector},
+        messages::{DataRow, DataType, Datum, FromBytes, Message, Protocol, ToBytes, Vector},
         Decoder,
     },
 };
@@ -19,6 +22,8 @@ use super::Aggregates;
 #[derive(Default, Debug, Clone)]
 pub(super) struct Buffer {
     buffer: VecDeque<DataRow>,
+    /// Position of the shard each row came from, if known.
+    shards: Vec<Option<usize>>,
     full: bool,
     distinct: HashSet<DataRow>,
 }
@@ -26,9 +31,23 @@ pub(super) struct Buffer {
 impl Buffer {
     /// Add message to buffer.
     pub(super) fn add(&mut self, message: Message) -> Result<(), super::Error> {
+        self.add_row(message, None)
+    }
+
+    /// Add message received from the shard at this position.
+    pub(super) fn add_from_shard(
+        &mut self,
+        shard: usize,
+        message: Message,
+    ) -> Result<(), super::Error> {
+        self.add_row(message, Some(shard))
+    }
+
+    fn add_row(&mut self, message: Message, shard: Option<usize>) -> Result<(), super::Error> {
         let dr = DataRow::from_bytes(message.to_bytes()?)?;
 
         self.buffer.push_back(dr);
+        self.shards.push(shard);
 
         Ok(())
     }
@@ -41,84 +60,54 @@ impl Buffer {
 
     pub(super) fn reset(&mut self) {
         self.buffer.clear();
+        self.shards.clear();
         self.full = false;
     }
 
     /// Sort the buffer.
-    pub(super) fn sort(&mut self, columns: &[OrderBy], decoder: &Decoder) {
-        // Calculate column indices once, since
-        // fetching indices by name is O(number of columns).
-        let mut cols = vec![];
-        for column in columns {
-            match column {
-                OrderBy::Asc(_) => cols.push(column.clone()),
-                OrderBy::AscColumn(name) => {
-                    if let Some(index) = decoder.rd().field_index(name) {
-                        cols.push(OrderBy::Asc(index + 1));
-                    }
-                }
-                OrderBy::Desc(_) => cols.push(column.clone()),
-                OrderBy::DescColumn(name) => {
-                    if let Some(index) = decoder.rd().field_index(name) {
-                        cols.push(OrderBy::Desc(index + 1));
-                    }
-                }
-                OrderBy::AscVectorL2(_, _) => cols.push(column.clone()),
-                OrderBy::AscVectorL2Column(name, vector) => {
-                    if let Some(index) = decoder.rd().field_index(name) {
-                        cols.push(OrderBy::AscVectorL2(index + 1, vector.clone()));
-                    }
-                }
-            };
+    pub(super) fn sort(
+        &mut self,
+        columns: &[OrderBy],
+        decoder: &Decoder,
+        collation: TextMergeCollation,
+    ) {
+        let mut comparator = Comparator::new(columns, decoder, collation);
+        self.buffer
+            .make_contiguous()
+            .sort_by(|a, b| comparator.compare(a, b));
+        // Rows moved around.
+        self.shards.clear();
+    }
+
+    /// Check that rows from each shard arrived sorted, using the same
+    /// comparison as [`Buffer::sort`]. Returns the position of the first shard
+    /// that disagrees.
+    ///
+    /// Only text columns are checked, since their order depends on the collation.
+    pub(super) fn unsorted(
+        &self,
+        columns: &[OrderBy],
+        decoder: &Decoder,
+        collation: TextMergeCollation,
+    ) -> Option<usize> {
+        let mut comparator = Comparator::new(columns, decoder, collation);
+        // Aggregates replace rows, so we don't know where they came from.
+        if !comparator.text() || self.shards.len() != self.buffer.len() {
+            return None;
         }
 
-        // Sort rows.
-        let order_by = move |a: &DataRow, b: &DataRow| -> Ordering {
-            for col in cols.iter() {
-                let index = col.index();
-                let asc = col.asc();
-                let index = if let Some(index) = index {
-                    index
-                } else {
-                    continue;
-                };
-                let left = a.get_column(index, decoder);
-                let right = b.get_column(index, decoder);
-
-                let ordering = match (left, right) {
-                    (Ok(Some(left)), Ok(Some(right))) => {
-                        // Handle the special vector case.
-                        if let OrderBy::AscVectorL2(_, vector) = col {
-                            let left: Option<Vector> = left.value.try_into().ok();
-                            let right: Option<Vector> = right.value.try_into().ok();
-
-                            if let (Some(left), Some(right)) = (left, right) {
-                                let left = left.distance_l2(vector);
-                                let right = right.distance_l2(vector);
-
-                                left.partial_cmp(&right)
-                            } else {
-                                Some(Ordering::Equal)
-                            }
-                        } else if asc {
-                            left.value.partial_cmp(&right.value)
-                        } else {
-                            right.value.partial_cmp(&left.value)
-                        }
+        let mut last: HashMap<usize, &DataRow> = HashMap::new();
+        for (row, shard) in self.buffer.iter().zip(self.shards.iter()) {
+            if let Some(shard) = shard {
+                if let Some(previous) = last.insert(*shard, row) {
+                    if comparator.compare(previous, row) == Ordering::Greater {
+                        return Some(*shard);
                     }
-
-                    _ => Some(Ordering::Equal),
-                };
-
-                if ordering != Some(Ordering::Equal) {
-                    return ordering.unwrap_or(Ordering::Equal);
                 }
             }
+        }
 
-            Ordering::Equal
-        };
-
-        self.buffer.make_contiguous().sort_by(order_by);
+        None
     }
 
     /// Execute aggregate functions.
@@ -143,6 +132,7 @@ impl Buffer {
 
             if !result.is_empty() {
                 self.buffer = result;
+                self.shards.clear();
             } else {
                 self.buffer = buffer;
             }
@@ -205,6 +195,120 @@ impl Buffer {
     }
 }
 
+/// Compare rows using the ORDER BY columns.
+struct Comparator<'a> {
+    columns: Vec<OrderBy>,
+    decoder: &'a Decoder,
+    collator: Option<Collator>,
+}
+
+impl<'a> Comparator<'a> {
+    fn new(columns: &[OrderBy], decoder: &'a Decoder, collation: TextMergeCollation) -> Self {
+        // Calculate column indices once, since
+        // fetching indices by name is O(number of columns).
+        let mut cols = vec![];
+        for column in columns {
+            match column {
+                OrderBy::Asc(_) => cols.push(column.clone()),
+                OrderBy::AscColumn(name) => {
+                    if let Some(index) = decoder.rd().field_index(name) {
+                        cols.push(OrderBy::Asc(index + 1));
+                    }
+                }
+                OrderBy::Desc(_) => cols.push(column.clone()),
+                OrderBy::DescColumn(name) => {
+                    if let Some(index) = decoder.rd().field_index(name) {
+                        cols.push(OrderBy::Desc(index + 1));
+                    }
+                }
+                OrderBy::AscVectorL2(_, _) => cols.push(column.clone()),
+                OrderBy::AscVectorL2Column(name, vector) => {
+                    if let Some(index) = decoder.rd().field_index(name) {
+                        cols.push(OrderBy::AscVectorL2(index + 1, vector.clone()));
+                    }
+                }
+            };
+        }
+
+        Self {
+            columns: cols,
+            decoder,
+            collator: match collation {
+                TextMergeCollation::C => None,
+                TextMergeCollation::Unicode => Some(Collator::default()),
+            },
+        }
+    }
+
+    /// Rows are sorted by at least one text column.
+    fn text(&self) -> bool {
+        self.columns
+            .iter()
+            .filter_map(|col| col.index())
+            .any(|index| {
+                self.decoder
+                    .rd()
+                    .field(index)
+                    .map(|field| field.data_type() == DataType::Text)
+                    .unwrap_or(false)
+            })
+    }
+
+    fn compare(&mut self, a: &DataRow, b: &DataRow) -> Ordering {
+        for col in self.columns.iter() {
+            let index = col.index();
+            let asc = col.asc();
+            let index = if let Some(index) = index {
+                index
+            } else {
+                continue;
+            };
+            let left = a.get_column(index, self.decoder);
+            let right = b.get_column(index, self.decoder);
+
+            let ordering = match (left, right) {
+                (Ok(Some(left)), Ok(Some(right))) => {
+                    // Handle the special vector case.
+                    if let OrderBy::AscVectorL2(_, vector) = col {
+                        let left: Option<Vector> = left.value.try_into().ok();
+                        let right: Option<Vector> = right.value.try_into().ok();
+
+                        if let (Some(left), Some(right)) = (left, right) {
+                            let left = left.distance_l2(vector);
+                            let right = right.distance_l2(vector);
+
+                            left.partial_cmp(&right)
+                        } else {
+                            Some(Ordering::Equal)
+                        }
+                    } else {
+                        let ordering = match (&left.value, &right.value, self.collator.as_mut()) {
+                            (Datum::Text(left), Datum::Text(right), Some(collator)) => {
+                                Some(collator.collate(left.as_str(), right.as_str()))
+                            }
+                            (left, right, _) => left.partial_cmp(right),
+                        };
+
+                        if asc {
+                            ordering
+                        } else {
+                            ordering.map(Ordering::reverse)
+                        }
+                    }
+                }
+
+                _ => Some(Ordering::Equal),
+            };
+
+            if ordering != Some(Ordering::Equal) {
+                return ordering.unwrap_or(Ordering::Equal);
+            }
+        }
+
+        Ordering::Equal
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -224,7 +328,7 @@ mod test {
 
         let decoder = Decoder::from(&rd);
 
-        buf.sort(&columns, &decoder);
+        buf.sort(&columns, &decoder, TextMergeCollation::C);
         buf.full();
 
         let mut i = 1;
@@ -240,6 +344,79 @@ mod test {
         assert_eq!(i, 26);
     }
 
+    #[test]
+    fn test_sort_buffer_unicode_collation() {
+        let rd = RowDescription::new(&[Field::text("name")]);
+        let decoder = Decoder::from(&rd);
+        let columns = [OrderBy::Asc(1)];
+
+        // Each shard sorted its rows using a linguistic collation, e.g. en_US.
+        let shards = [
+            vec!["apple", "Banana", "éclair"],
+            vec!["Apple", "banana", "Eclair", "zebra"],
+        ];
+        let mut buf = Buffer::default();
+        for (shard, names) in shards.iter().enumerate() {
+            for name in names {
+                let mut dr = DataRow::new();
+                dr.add(name.to_string());
+                buf.add_from_shard(shard, dr.message().unwrap()).unwrap();
+            }
+        }
+
+        // Byte order disagrees with the shards.
+        assert_eq!(
+            buf.unsorted(&columns, &decoder, TextMergeCollation::C),
+            Some(0)
+        );
+        assert_eq!(
+            buf.unsorted(&columns, &decoder, TextMergeCollation::Unicode),
+            None
+        );
+
+        buf.sort(&columns, &decoder, TextMergeCollation::Unicode);
+        buf.full();
+
+        let mut sorted = vec![];
+        while let Some(message) = buf.take() {
+            let dr = DataRow::from_bytes(message.to_bytes().unwrap()).unwrap();
+            sorted.push(dr.get::<String>(0, Format::Text).unwrap());
+        }
+        assert_eq!(
+            sorted,
+            ["apple", "Apple", "banana", "Banana", "Eclair", "éclair", "zebra"]
+        );
+
+        // Shard sorted using byte order.
+        let mut buf = Buffer::default();
+        for (shard, name) in [(0, "apple"), (1, "Zebra"), (1, "apple"), (0, "zebra")] {
+            let mut dr = DataRow::new();
+            dr.add(name.to_string());
+            buf.add_from_shard(shard, dr.message().unwrap()).unwrap();
+        }
+        assert_eq!(
+            buf.unsorted(&columns, &decoder, TextMergeCollation::Unicode),
+            Some(1)
+        );
+        assert_eq!(
+            buf.unsorted(&columns, &decoder, TextMergeCollation::C),
+            None
+        );
+
+        // Rows without a known shard aren't checked.
+        let columns = [OrderBy::Desc(1)];
+        let mut buf = Buffer::default();
+        for name in ["a", "b"] {
+            let mut dr = DataRow::new();
+            dr.add(name.to_string());
+            buf.add(dr.message().unwrap()).unwrap();
+        }
+        assert_eq!(
+            buf.unsorted(&columns, &decoder, TextMergeCollation::C),
+            None
+        );
+    }
+
     #[test]
     fn test_aggregate_buffer() {
         let mut buf = Buffer::default();
@@ -313,7 +490,7 @@ mod test {
 
         let decoder = Decoder::from(&rd);
 
-        buf.sort(&columns, &decoder);
+        buf.sort(&columns, &decoder, TextMergeCollation::C);
         buf.full();
 
         // Verify timestamps are sorted
@@ -333,6 +510,52 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_sort_buffer_timestamptz_different_time_zones() {
+        let rd = RowDescription::new(&[Field::timestamptz("created_at")]);
+        let decoder = Decoder::from(&rd);
+        let columns = [OrderBy::Asc(1)];
+
+        // Shards have different TimeZone settings, each sorted its own rows.
+        let shards = [
+            // TimeZone = 'America/Los_Angeles'
+            vec!["2025-01-15 01:00:00-08", "2025-01-15 03:30:00-08"],
+            // TimeZone = 'Asia/Kolkata'
+            vec!["2025-01-15 14:00:00+05:30", "2025-01-15 20:00:00+05:30"],
+            // TimeZone = 'UTC'
+            vec!["2025-01-15 09:15:00+00", "2025-01-15 11:00:00.5+00"],
+        ];
+
+        let mut buf = Buffer::default();
+        for rows in &shards {
+            for row in rows {
+                let mut dr = DataRow::new();
+                dr.add(row.to_string());
+                buf.add(dr.message().unwrap()).unwrap();
+            }
+        }
+
+        buf.sort(&columns, &decoder, TextMergeCollation::C);
+        buf.full();
+
+        // Rows are passed through as the shards sent them.
+        let expected = [
+            "2025-01-15 14:00:00+05:30", // 08:30 UTC
+            "2025-01-15 01:00:00-08",    // 09:00 UTC
+            "2025-01-15 09:15:00+00",    // 09:15 UTC
+            "2025-01-15 11:00:00.5+00",  // 11:00:00.5 UTC
+            "2025-01-15 03:30:00-08",    // 11:30 UTC
+            "2025-01-15 20:00:00+05:30", // 14:30 UTC
+        ];
+
+        let mut sorted = vec![];
+        while let Some(message) = buf.take() {
+            let dr = DataRow::from_bytes(message.to_bytes().unwrap()).unwrap();
+            sorted.push(dr.get::<String>(0, Format::Text).unwrap());
+        }
+        assert_eq!(sorted, expected);
+    }
+
     #[test]
     fn test_distinct() {
         let mut buf = Buffer::default();
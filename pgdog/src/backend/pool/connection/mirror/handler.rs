@@ -103,11 +103,19 @@ impl MirrorHandler {
             debug!("mirror transaction flushed");
             self.state = MirrorHandlerState::Idle;
 
-            self.tx
-                .try_send(MirrorRequest {
-                    buffer: std::mem::take(&mut self.buffer),
-                })
-                .is_ok()
+            let request = MirrorRequest {
+                buffer: std::mem::take(&mut self.buffer),
+            };
+            // Count it before the mirror can receive it.
+            let memory = request.memory_usage();
+            QUEUED.fetch_add(memory, Ordering::Relaxed);
+
+            if self.tx.try_send(request).is_ok() {
+                true
+            } else {
+                QUEUED.fetch_sub(memory, Ordering::Relaxed);
+                false
+            }
         }
     }
 }
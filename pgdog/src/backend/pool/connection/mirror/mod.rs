@@ -1,5 +1,6 @@
 //! Client request mirroring.
 
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::time::Duration;
 
 use rand::{thread_rng, Rng};
@@ -9,13 +10,15 @@ use tokio::{spawn, sync::mpsc::*};
 use tracing::{debug, error};
 
 use crate::backend::Cluster;
-use crate::config::{config, ConfigAndUsers};
+use crate::config::{config, ConfigAndUsers, MirrorStrategy};
 use crate::frontend::client::query_engine::{QueryEngine, QueryEngineContext};
 use crate::frontend::client::timeouts::Timeouts;
-use crate::frontend::client::TransactionType;
+use crate::frontend::client::TransactionStatus;
 use crate::frontend::comms::comms;
+use crate::frontend::router::{parser::Shard, Route};
 use crate::frontend::PreparedStatements;
 use crate::net::{Parameter, Parameters, Stream};
+use crate::stats::memory::MemoryUsage;
 
 use crate::frontend::ClientRequest;
 
@@ -29,6 +32,14 @@ pub use buffer_with_delay::*;
 pub use handler::*;
 pub use request::*;
 
+/// Memory used by requests waiting in mirror queues.
+static QUEUED: AtomicUsize = AtomicUsize::new(0);
+
+/// Memory used by requests waiting in mirror queues, pooler-wide.
+pub fn queued_memory() -> usize {
+    QUEUED.load(Ordering::Relaxed)
+}
+
 /// Mirror handler. One is created for each client connected
 /// to PgDog.
 #[derive(Debug)]
@@ -43,20 +54,49 @@ pub struct Mirror {
     /// Stream that absorbs all data.
     pub stream: Stream,
     /// Transaction state.
-    pub transaction: Option<TransactionType>,
+    pub transaction: TransactionStatus,
     /// Cross-shard queries.
     pub cross_shard_disabled: bool,
+    /// How queries are routed on the mirror.
+    pub strategy: MirrorStrategy,
+    /// Number of shards in the mirror cluster.
+    pub shards: usize,
 }
 
 impl Mirror {
-    fn new(params: &Parameters, config: &ConfigAndUsers) -> Self {
+    fn new(params: &Parameters, config: &ConfigAndUsers, cluster: &Cluster) -> Self {
         Self {
-            prepared_statements: PreparedStatements::new(),
+            prepared_statements: PreparedStatements::new(Some(cluster)),
             params: params.clone(),
             timeouts: Timeouts::from_config(&config.config.general),
             stream: Stream::DevNull,
-            transaction: None,
+            transaction: TransactionStatus::Idle,
             cross_shard_disabled: config.config.general.cross_shard_disabled,
+            strategy: cluster.mirror_strategy(),
+            shards: cluster.shards().len(),
+        }
+    }
+
+    /// Shard the mirrored query should go to, given the route
+    /// it took on the source database.
+    ///
+    /// Returns `None` if the mirror should route the query itself.
+    pub fn shard(&self, route: &Route) -> Option<Shard> {
+        let shards = self.shards.max(1);
+
+        match self.strategy {
+            MirrorStrategy::Reroute => None,
+            MirrorStrategy::SingleShard => Some(Shard::Direct(0)),
+            MirrorStrategy::PreserveShard => Some(match route.shard() {
+                Shard::Direct(shard) => Shard::Direct(shard % shards),
+                Shard::Multi(multi) => {
+                    let mut multi = multi.iter().map(|shard| shard % shards).collect::<Vec<_>>();
+                    multi.sort();
+                    multi.dedup();
+                    Shard::Multi(multi)
+                }
+                Shard::All => Shard::All,
+            }),
         }
     }
 
@@ -87,7 +127,7 @@ impl Mirror {
         let mut query_engine = QueryEngine::new(&params, &comms(), false, &None)?;
 
         // Mirror traffic handler.
-        let mut mirror = Self::new(&params, &config);
+        let mut mirror = Self::new(&params, &config, cluster);
 
         // Mirror queue.
         let (tx, mut rx) = channel(config.config.general.mirror_queue);
@@ -98,6 +138,7 @@ impl Mirror {
                 select! {
                     req = rx.recv() => {
                         if let Some(mut req) = req {
+                            QUEUED.fetch_sub(req.memory_usage(), Ordering::Relaxed);
                             // TODO: timeout these.
                             if let Err(err) = mirror.handle(&mut req, &mut query_engine).await {
                                 error!("mirror error: {}", err);
@@ -127,7 +168,8 @@ impl Mirror {
                 sleep(req.delay).await;
             }
 
-            let mut context = QueryEngineContext::new_mirror(self, &mut req.buffer);
+            let shard = self.shard(&req.buffer.route);
+            let mut context = QueryEngineContext::new_mirror(self, &mut req.buffer, shard);
             query_engine.handle(&mut context).await?;
             self.transaction = context.transaction();
         }
@@ -138,7 +180,11 @@ impl Mirror {
 
 #[cfg(test)]
 mod test {
-    use crate::{backend::pool::Request, config, net::Query};
+    use crate::{
+        backend::{databases::databases, pool::Request},
+        config,
+        net::Query,
+    };
 
     use super::*;
 
@@ -228,4 +274,84 @@ mod test {
 
         cluster.shutdown();
     }
+
+    fn mirror_request(query: &str, shard: Shard) -> MirrorRequest {
+        let mut buffer = ClientRequest::from(vec![Query::new(query).into()]);
+        buffer.route = Route::write(shard);
+        MirrorRequest {
+            buffer: vec![BufferWithDelay {
+                delay: Duration::ZERO,
+                buffer,
+            }],
+        }
+    }
+
+    #[test]
+    fn test_mirror_strategy_shard() {
+        config::test::load_test_sharded();
+        let cluster = Cluster::new_test();
+        let mut mirror = Mirror::new(&Parameters::default(), &config(), &cluster);
+        assert_eq!(mirror.shards, 2);
+
+        // Source database has 4 shards.
+        let direct = Route::write(Shard::Direct(3));
+        let multi = Route::write(Shard::Multi(vec![1, 2, 3]));
+        let all = Route::write(Shard::All);
+
+        assert_eq!(mirror.strategy, MirrorStrategy::Reroute);
+        assert_eq!(mirror.shard(&direct), None);
+        assert_eq!(mirror.shard(&all), None);
+
+        mirror.strategy = MirrorStrategy::PreserveShard;
+        assert_eq!(mirror.shard(&direct), Some(Shard::Direct(1)));
+        assert_eq!(mirror.shard(&multi), Some(Shard::Multi(vec![0, 1])));
+        assert_eq!(mirror.shard(&all), Some(Shard::All));
+
+        mirror.strategy = MirrorStrategy::SingleShard;
+        assert_eq!(mirror.shard(&direct), Some(Shard::Direct(0)));
+        assert_eq!(mirror.shard(&multi), Some(Shard::Direct(0)));
+        assert_eq!(mirror.shard(&all), Some(Shard::Direct(0)));
+    }
+
+    #[tokio::test]
+    async fn test_mirror_strategy_routing() {
+        config::test::load_test_sharded();
+        let cluster = databases().cluster(("pgdog", "pgdog")).unwrap();
+        assert_eq!(cluster.shards().len(), 2);
+
+        let params = Parameters::from(vec![
+            Parameter {
+                name: "user".into(),
+                value: "pgdog".into(),
+            },
+            Parameter {
+                name: "database".into(),
+                value: "pgdog".into(),
+            },
+        ]);
+
+        for (strategy, expected) in [
+            (MirrorStrategy::Reroute, None),
+            (MirrorStrategy::PreserveShard, Some(Shard::Direct(1))),
+            (MirrorStrategy::SingleShard, Some(Shard::Direct(0))),
+        ] {
+            let mut query_engine = QueryEngine::new(&params, &comms(), false, &None).unwrap();
+            let mut mirror = Mirror::new(&params, &config(), &cluster);
+            mirror.strategy = strategy;
+
+            // Routed to shard 3 on a 4-shard source database.
+            let mut request = mirror_request("SELECT 1", Shard::Direct(3));
+            mirror
+                .handle(&mut request, &mut query_engine)
+                .await
+                .unwrap();
+
+            let shard = query_engine.router().route().shard().clone();
+            match expected {
+                Some(expected) => assert_eq!(shard, expected, "{:?}", strategy),
+                // Mirror's router picked a shard that exists in its own config.
+                None => assert_ne!(shard, Shard::Direct(3), "{:?}", strategy),
+            }
+        }
+    }
 }
@@ -1,12 +1,23 @@
 use std::ops::Deref;
 
 use super::BufferWithDelay;
+use crate::stats::memory::MemoryUsage;
 
 #[derive(Clone, Debug)]
 pub struct MirrorRequest {
     pub(super) buffer: Vec<BufferWithDelay>,
 }
 
+impl MemoryUsage for MirrorRequest {
+    #[inline]
+    fn memory_usage(&self) -> usize {
+        self.buffer
+            .iter()
+            .map(|request| request.buffer.memory_usage())
+            .sum()
+    }
+}
+
 impl Deref for MirrorRequest {
     type Target = Vec<BufferWithDelay>;
 
@@ -13,9 +13,12 @@ use crate::{
     config::{config, PoolerMode, User},
     frontend::{
         router::{parser::Shard, CopyRow, Route},
-        Router,
+        PreparedStatements, Router,
+    },
+    net::{
+        messages::parameter_status::DEFAULT_SERVER_VERSION, Bind, Message, ParameterStatus,
+        Parameters, Protocol,
     },
-    net::{Bind, Message, ParameterStatus, Protocol},
     state::State,
 };
 
@@ -39,7 +42,7 @@ pub mod multi_shard;
 use aggregate::Aggregates;
 use binding::Binding;
 use mirror::Mirror;
-use multi_shard::MultiShard;
+use multi_shard::{MultiShard, ShardRows};
 
 /// Wrapper around a server connection.
 #[derive(Default, Debug)]
@@ -177,8 +180,15 @@ impl Connection {
                 shards.push(server);
             }
             let num_shards = shards.len();
-
-            self.binding = Binding::MultiShard(shards, MultiShard::new(num_shards, route));
+            let cluster = self.cluster()?;
+            let state = MultiShard::new(
+                num_shards,
+                route,
+                cluster.shard_skew().clone(),
+                cluster.cross_shard_limits(),
+            );
+
+            self.binding = Binding::MultiShard(shards, state);
         }
 
         Ok(())
@@ -192,10 +202,28 @@ impl Connection {
         match &self.binding {
             Binding::Admin(_) => Ok(ParameterStatus::fake()),
             _ => {
-                // Try a replica. If not, try the primary.
-                if self.connect(request, &Route::read(Some(0))).await.is_err() {
-                    self.connect(request, &Route::write(Some(0))).await?;
+                // Prefer the primary, so parameters don't depend on
+                // which replica the client would've been sent to.
+                let connected = match self.connect(request, &Route::write(Some(0))).await {
+                    Ok(()) => Ok(()),
+                    Err(_) => self.connect(request, &Route::read(Some(0))).await,
                 };
+
+                match connected {
+                    Ok(()) => (),
+                    Err(err) if err.no_server() => {
+                        debug!(
+                            "pool is down, sending synthesized parameters [{}]",
+                            self.database
+                        );
+                        return Ok(ParameterStatus::synthesized(
+                            &self.user,
+                            &self.server_version(),
+                        ));
+                    }
+                    Err(err) => return Err(err),
+                }
+
                 let mut params = vec![];
                 for param in self.server()?.params().iter() {
                     if let Some(value) = param.1.as_str() {
@@ -203,11 +231,22 @@ impl Connection {
                     }
                 }
                 self.disconnect();
-                Ok(params)
+                Ok(ParameterStatus::canonical(params))
             }
         }
     }
 
+    /// Version of the database, captured when its pools last connected to it.
+    fn server_version(&self) -> String {
+        self.cluster()
+            .ok()
+            .and_then(|cluster| cluster.settings())
+            .and_then(|settings| settings.show("server_version", &Parameters::default()))
+            .map(|setting| setting.setting)
+            .or_else(|| config().config.general.server_version.clone())
+            .unwrap_or_else(|| DEFAULT_SERVER_VERSION.to_string())
+    }
+
     /// Read a message from the server connection or a pub/sub channel.
     ///
     /// Only await this future inside a `select!`. One of the conditions
@@ -288,6 +327,13 @@ impl Connection {
             } else {
                 self.send(client_request).await?;
             }
+        } else if let Some(split) = router.route().insert_split() {
+            // Each shard gets an INSERT with only its rows. Its prepared statements
+            // are scoped to the cluster, like the client's.
+            let cluster = self.cluster.as_ref().map(PreparedStatements::cluster_key);
+            self.binding
+                .send_split(split, client_request, &cluster)
+                .await?;
         } else {
             // Send query to server.
             self.send(client_request).await?;
@@ -301,6 +347,8 @@ impl Connection {
         match self.binding {
             Binding::Server(_) | Binding::MultiShard(_, _) => {
                 let user = (self.user.as_str(), self.database.as_str());
+                // Pools created from templates are closed when they're idle.
+                databases::instantiate(&self.user, &self.database);
                 // Check passthrough auth.
                 if config().config.general.passthrough_auth() && !databases().exists(user) {
                     if let Some(ref passthrough_password) = self.passthrough_password {
@@ -352,9 +400,28 @@ impl Connection {
     /// release back into the pool.
     pub(crate) fn lock(&mut self, lock: bool) {
         self.locked = lock;
-        if lock {
-            self.binding.dirty();
-        }
+        self.binding.lock(lock);
+    }
+
+    /// `pg_current_wal_insert_lsn()` of each connected server, with its shard number.
+    pub(crate) async fn wal_insert_lsn(
+        &mut self,
+        shard: &Shard,
+    ) -> Result<Vec<(usize, u64)>, Error> {
+        // Shard of a single server connection.
+        let direct = match shard {
+            Shard::Direct(shard) => *shard,
+            Shard::Multi(shards) => shards.iter().min().copied().unwrap_or_default(),
+            Shard::All => 0,
+        };
+
+        self.binding.wal_insert_lsn(direct).await
+    }
+
+    /// Server state was changed by the client and needs
+    /// to be reset before it's used by someone else.
+    pub(crate) fn mark_dirty(&mut self) {
+        self.binding.dirty();
     }
 
     /// Get connected servers addresses.
@@ -368,6 +435,15 @@ impl Connection {
         })
     }
 
+    /// Rows and bytes each shard returned for the last statement,
+    /// if the query went to multiple shards.
+    pub(crate) fn shard_rows(&self) -> Vec<ShardRows> {
+        match self.binding {
+            Binding::MultiShard(_, ref state) => state.shard_rows(),
+            _ => vec![],
+        }
+    }
+
     /// Get a connected server, if any. If multi-shard, get the first one.
     #[inline]
     fn server(&mut self) -> Result<&mut Guard, Error> {
@@ -414,3 +490,66 @@ impl DerefMut for Connection {
         &mut self.binding
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::config::test::{load_test, load_test_replicas};
+    use crate::net::BackendKeyData;
+
+    use super::*;
+
+    /// Parameters only reported by some PostgreSQL versions.
+    const VERSION_SPECIFIC: &[&str] = &["scram_iterations", "search_path"];
+
+    async fn parameters() -> Vec<ParameterStatus> {
+        let mut conn = Connection::new("pgdog", "pgdog", false, &None).unwrap();
+        conn.parameters(&Request::new(BackendKeyData::new()))
+            .await
+            .unwrap()
+    }
+
+    fn names(params: &[ParameterStatus]) -> Vec<&str> {
+        params
+            .iter()
+            .map(|param| param.name.as_str())
+            .filter(|name| !VERSION_SPECIFIC.contains(name))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_startup_parameters() {
+        for replicas in [false, true] {
+            if replicas {
+                load_test_replicas();
+            } else {
+                load_test();
+            }
+
+            let params = parameters().await;
+            assert_eq!(
+                names(&params),
+                [
+                    "application_name",
+                    "client_encoding",
+                    "DateStyle",
+                    "default_transaction_read_only",
+                    "integer_datetimes",
+                    "IntervalStyle",
+                    "is_superuser",
+                    "server_encoding",
+                    "server_version",
+                    "session_authorization",
+                    "standard_conforming_strings",
+                    "TimeZone",
+                ]
+            );
+
+            // Replicas are read-only; parameters must come from the primary.
+            let read_only = params
+                .iter()
+                .find(|param| param.name == "default_transaction_read_only")
+                .unwrap();
+            assert_eq!(read_only.value, "off");
+        }
+    }
+}
@@ -0,0 +1,64 @@
+//! Limits of queries sent to multiple shards.
+
+use std::time::Duration;
+
+use crate::{
+    config::{General, User},
+    frontend::router::parser::Shard,
+    net::messages::ErrorResponse,
+};
+
+/// Cross-shard query limits of a user.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CrossShardLimits {
+    /// Cancel queries running for longer than this.
+    pub timeout: Option<Duration>,
+    /// Cancel queries returning more rows than this.
+    pub max_rows: Option<usize>,
+    /// Limit queries sent to all shards, or to more shards than this.
+    pub min_shards: usize,
+}
+
+impl CrossShardLimits {
+    /// User's limits, or the global ones if the user doesn't override them.
+    pub fn new(general: &General, user: &User) -> Self {
+        Self {
+            timeout: user
+                .cross_shard_timeout
+                .or(general.cross_shard_timeout)
+                .map(Duration::from),
+            max_rows: user.cross_shard_max_rows.or(general.cross_shard_max_rows),
+            min_shards: general.cross_shard_limits_min_shards,
+        }
+    }
+
+    /// Limits of a query sent to these shards.
+    pub fn for_shard(&self, shard: &Shard) -> Self {
+        match shard {
+            Shard::Multi(shards) if shards.len() <= self.min_shards => Self::default(),
+            _ => *self,
+        }
+    }
+
+    /// No limits are configured.
+    pub fn is_empty(&self) -> bool {
+        self.timeout.is_none() && self.max_rows.is_none()
+    }
+}
+
+/// Limit exceeded by a cross-shard query.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CrossShardLimit {
+    Timeout(Duration),
+    MaxRows(usize),
+}
+
+impl CrossShardLimit {
+    /// Error returned to the client.
+    pub fn error(&self) -> ErrorResponse {
+        match self {
+            Self::Timeout(timeout) => ErrorResponse::cross_shard_timeout(*timeout),
+            Self::MaxRows(rows) => ErrorResponse::cross_shard_max_rows(*rows),
+        }
+    }
+}
@@ -1,13 +1,26 @@
 //! Multi-shard connection state.
 
+use std::{mem::swap, sync::Arc};
+
 use context::Context;
+use serde::Serialize;
+use tokio::time::Instant;
+use tracing::warn;
 
 use crate::{
-    frontend::{router::Route, PreparedStatements},
+    backend::{
+        pool::{Address, Guard, ShardSkew},
+        Server,
+    },
+    config::{config, TextMergeCollation},
+    frontend::{
+        router::{parser::Shard, Route},
+        PreparedStatements,
+    },
     net::{
         messages::{
-            command_complete::CommandComplete, FromBytes, Message, Protocol, RowDescription,
-            ToBytes,
+            command_complete::CommandComplete, BackendKeyData, ErrorResponse, FromBytes, Message,
+            Protocol, RowDescription, ToBytes,
         },
         Decoder,
     },
@@ -16,9 +29,12 @@ use crate::{
 use super::buffer::Buffer;
 
 mod context;
+pub mod limits;
 #[cfg(test)]
 mod test;
 
+pub use limits::{CrossShardLimit, CrossShardLimits};
+
 #[derive(Default, Debug)]
 struct Counters {
     rows: usize,
@@ -33,6 +49,56 @@ struct Counters {
     close_complete: usize,
     bind_complete: usize,
     command_complete: Option<Message>,
+    /// Errors returned by shards, combined into one.
+    error: Option<ErrorResponse>,
+    /// ReadyForQuery sent to the client after the error.
+    ready_for_query_after_error: Option<Message>,
+    /// Error was sent before ReadyForQuery, because the client sent Flush
+    /// instead of Sync. Everything is dropped until ReadyForQuery.
+    error_flushed: bool,
+}
+
+/// Rows and bytes returned by each shard for one statement,
+/// indexed by connection position.
+#[derive(Default, Debug)]
+struct ShardCounts {
+    rows: Vec<u64>,
+    bytes: Vec<u64>,
+    total: usize,
+}
+
+impl ShardCounts {
+    fn new(shards: usize) -> Self {
+        Self {
+            rows: vec![0; shards],
+            bytes: vec![0; shards],
+            total: 0,
+        }
+    }
+
+    fn add(&mut self, position: usize, bytes: usize) {
+        if let Some(rows) = self.rows.get_mut(position) {
+            *rows += 1;
+        }
+        if let Some(total) = self.bytes.get_mut(position) {
+            *total += bytes as u64;
+        }
+        self.total += 1;
+    }
+
+    fn clear(&mut self) {
+        self.rows.fill(0);
+        self.bytes.fill(0);
+        self.total = 0;
+    }
+}
+
+/// Rows and bytes one shard returned for a statement.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct ShardRows {
+    pub shard: usize,
+    pub rows: u64,
+    pub bytes: u64,
 }
 
 /// Multi-shard state.
@@ -49,22 +115,123 @@ pub struct MultiShard {
     /// Sorting/aggregate buffer.
     buffer: Buffer,
     decoder: Decoder,
+    /// Collation used to merge text columns, checked against
+    /// the order of rows from each shard.
+    collation: Option<TextMergeCollation>,
+
+    /// Rows and bytes of the statement in progress.
+    counts: ShardCounts,
+    /// Rows and bytes of the last completed statement.
+    last: ShardCounts,
+    /// Cluster's skew histogram.
+    skew: Arc<ShardSkew>,
+
+    /// User's cross-shard query limits.
+    limits: CrossShardLimits,
+    /// Request is canceled if it's still running at this time.
+    deadline: Option<Instant>,
+    /// Limit exceeded by the request.
+    exceeded: Option<CrossShardLimit>,
+    /// Servers executing the request, used to cancel it.
+    servers: Vec<(Address, BackendKeyData)>,
+    /// Request needs to be canceled.
+    cancel: bool,
 }
 
 impl MultiShard {
     /// New multi-shard state given the number of shards in the cluster.
-    pub(super) fn new(shards: usize, route: &Route) -> Self {
+    pub(super) fn new(
+        shards: usize,
+        route: &Route,
+        skew: Arc<ShardSkew>,
+        limits: CrossShardLimits,
+    ) -> Self {
         Self {
             shards,
             route: route.clone(),
             counters: Counters::default(),
+            collation: config().config.general.text_merge_collation,
+            counts: ShardCounts::new(shards),
+            last: ShardCounts::new(shards),
+            skew,
+            limits: limits.for_shard(route.shard()),
             ..Default::default()
         }
     }
 
+    /// Request was sent to the servers. Start enforcing limits,
+    /// unless the servers are still executing an earlier part of it.
+    pub(super) fn start(&mut self, servers: &[Guard]) {
+        if self.limits.is_empty() || servers.iter().any(|server| server.has_more_messages()) {
+            return;
+        }
+
+        self.deadline = self
+            .limits
+            .timeout
+            .and_then(|timeout| Instant::now().checked_add(timeout));
+        self.exceeded = None;
+        self.cancel = false;
+        self.servers = servers
+            .iter()
+            .map(|server| (server.addr().clone(), *server.id()))
+            .collect();
+    }
+
+    /// Cancel the request when this passes.
+    pub(super) fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// Request ran past the deadline.
+    pub(super) fn timed_out(&mut self) {
+        if let Some(timeout) = self.limits.timeout {
+            self.exceed(CrossShardLimit::Timeout(timeout));
+        }
+    }
+
+    /// Request exceeded a limit: drop the rest of the results,
+    /// cancel it on all shards and return the limit's error instead.
+    fn exceed(&mut self, limit: CrossShardLimit) {
+        if self.exceeded.is_none() {
+            self.exceeded = Some(limit);
+            self.deadline = None;
+            self.cancel = true;
+            self.buffer.reset();
+            self.counters.command_complete = None;
+        }
+    }
+
+    /// Request must be canceled on the shards.
+    pub(super) fn cancel_pending(&self) -> bool {
+        self.cancel
+    }
+
+    /// Send cancel requests for the request to all shards.
+    pub(super) async fn cancel(&mut self) {
+        self.cancel = false;
+        for (addr, id) in &self.servers {
+            if let Err(err) = Server::cancel(addr, id).await {
+                warn!("couldn't cancel cross-shard query: {} [{}]", err, addr);
+            }
+        }
+    }
+
+    /// Check the number of rows against `cross_shard_max_rows`.
+    fn check_max_rows(&mut self, rows: usize) -> bool {
+        match self.limits.max_rows {
+            Some(max_rows) if rows > max_rows => {
+                self.exceed(CrossShardLimit::MaxRows(max_rows));
+                true
+            }
+            _ => false,
+        }
+    }
+
     pub(super) fn reset(&mut self) {
         self.counters = Counters::default();
         self.buffer.reset();
+        self.counts.clear();
         // Don't reset:
         //  1. Route to keep routing decision
         //  2. Number of shards
@@ -73,17 +240,69 @@ impl MultiShard {
 
     /// Check if the message should be sent to the client, skipped,
     /// or modified.
-    pub(super) fn forward(&mut self, message: Message) -> Result<Option<Message>, super::Error> {
+    pub(super) fn forward(
+        &mut self,
+        shard: usize,
+        message: Message,
+    ) -> Result<Option<Message>, super::Error> {
         let mut forward = None;
 
         match message.code() {
+            // Shards that hit an error skip to the end of the request
+            // and send ReadyForQuery. Other shards keep going, so we read
+            // them to ReadyForQuery too and the connections stay in sync.
+            // Errors from all shards are sent to the client as one, right
+            // before the last ReadyForQuery.
+            // Shards return errors for the cancel requests, the client gets the limit's error.
+            'E' if self.exceeded.is_some() => (),
+
+            'E' => {
+                let error = ErrorResponse::from_bytes(message.to_bytes()?)?;
+                let shard = self.shard_number(shard);
+                self.counters.error = Some(match self.counters.error.take() {
+                    Some(first) => first.merge(error, shard),
+                    None => error.shard_context(shard),
+                });
+            }
+
             'Z' => {
                 self.counters.ready_for_query += 1;
-                forward = if self.counters.ready_for_query % self.shards == 0 {
-                    Some(message)
-                } else {
-                    None
-                };
+                if self.counters.ready_for_query % self.shards == 0 {
+                    let error = match self.exceeded.take() {
+                        Some(limit) => Some(limit.error()),
+                        None => self.counters.error.take(),
+                    };
+                    if let Some(error) = error {
+                        forward = Some(error.message()?);
+                        // Shards that failed skipped some messages, so the counters
+                        // don't line up anymore. Start over for the next Sync.
+                        self.counters = Counters {
+                            ready_for_query: self.counters.ready_for_query,
+                            ready_for_query_after_error: Some(message),
+                            ..Default::default()
+                        };
+                        self.buffer.reset();
+                        self.counts.clear();
+                    } else {
+                        if self.counters.error_flushed {
+                            self.counters = Counters {
+                                ready_for_query: self.counters.ready_for_query,
+                                ..Default::default()
+                            };
+                            self.buffer.reset();
+                            self.counts.clear();
+                        }
+                        forward = Some(message);
+                    }
+                }
+            }
+
+            // Drop everything else until all shards are done with this request.
+            _ if self.counters.error.is_some()
+                || self.counters.error_flushed
+                || self.exceeded.is_some() =>
+            {
+                ()
             }
 
             // Count CommandComplete messages.
@@ -101,13 +320,33 @@ impl MultiShard {
                 self.counters.command_complete_count += 1;
 
                 if self.counters.command_complete_count % self.shards == 0 {
+                    self.statement_done();
                     self.buffer.full();
                     self.buffer
                         .aggregate(self.route.aggregate(), &self.decoder)?;
 
-                    self.buffer.sort(self.route.order_by(), &self.decoder);
+                    if let Some(collation) = self.collation {
+                        if let Some(position) =
+                            self.buffer
+                                .unsorted(self.route.order_by(), &self.decoder, collation)
+                        {
+                            return Err(super::Error::MergeCollation(
+                                self.shard_number(position),
+                                collation,
+                            ));
+                        }
+                    }
+                    self.buffer.sort(
+                        self.route.order_by(),
+                        &self.decoder,
+                        self.collation.unwrap_or_default(),
+                    );
                     self.buffer.distinct(self.route.distinct(), &self.decoder);
 
+                    if self.check_max_rows(self.buffer.len()) {
+                        return Ok(None);
+                    }
+
                     if has_rows {
                         let rows = if self.route.should_buffer() {
                             self.buffer.len()
@@ -144,10 +383,18 @@ impl MultiShard {
             }
 
             'D' => {
+                self.counts.add(shard, message.len());
+                // Aggregates and DISTINCT merge rows, so they're checked once merged.
+                if self.route.aggregate().is_empty()
+                    && self.route.distinct().is_none()
+                    && self.check_max_rows(self.counts.total)
+                {
+                    return Ok(None);
+                }
                 if !self.route.should_buffer() && self.counters.row_description % self.shards == 0 {
                     forward = Some(message);
                 } else {
-                    self.buffer.add(message)?;
+                    self.buffer.add_from_shard(shard, message)?;
                 }
             }
 
@@ -200,15 +447,82 @@ impl MultiShard {
         Ok(forward)
     }
 
+    /// All shards finished the statement. Keep its row counts
+    /// for the debug notice and record the skew.
+    fn statement_done(&mut self) {
+        let total = self.counts.rows.iter().sum();
+        let max = self.counts.rows.iter().max().copied().unwrap_or_default();
+        self.skew.record(max, total);
+
+        swap(&mut self.last, &mut self.counts);
+        self.counts.clear();
+    }
+
+    /// Rows and bytes each shard returned for the last completed statement.
+    pub(super) fn shard_rows(&self) -> Vec<ShardRows> {
+        self.last
+            .rows
+            .iter()
+            .zip(&self.last.bytes)
+            .enumerate()
+            .map(|(position, (rows, bytes))| ShardRows {
+                shard: self.shard_number(position),
+                rows: *rows,
+                bytes: *bytes,
+            })
+            .collect()
+    }
+
+    /// Convert the position of the connection into the shard number.
+    pub(super) fn shard_number(&self, position: usize) -> usize {
+        match self.route.shard() {
+            Shard::Multi(numbers) => {
+                let mut numbers = numbers.clone();
+                numbers.sort_unstable();
+                numbers.dedup();
+                numbers.get(position).copied().unwrap_or(position)
+            }
+            _ => position,
+        }
+    }
+
     /// Multi-shard state is ready to send messages.
     pub(super) fn message(&mut self) -> Option<Message> {
         if let Some(data_row) = self.buffer.take() {
             Some(data_row)
+        } else if let Some(command_complete) = self.counters.command_complete.take() {
+            Some(command_complete)
         } else {
-            self.counters.command_complete.take()
+            self.counters.ready_for_query_after_error.take()
         }
     }
 
+    /// Shards have nothing more to send, but none of them sent ReadyForQuery,
+    /// e.g. the client sent Flush instead of Sync. The error we're holding
+    /// is sent now; the client won't send Sync until it gets it.
+    pub(super) fn flush(&mut self) -> Result<Option<Message>, super::Error> {
+        let error = match self.exceeded.take() {
+            Some(limit) => Some(limit.error()),
+            None => self.counters.error.take(),
+        };
+
+        match error {
+            Some(error) => {
+                self.counters.error_flushed = true;
+                Ok(Some(error.message()?))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// ReadyForQuery or an error hasn't been sent to the client yet,
+    /// even though all shards sent it to us.
+    pub(super) fn pending(&self) -> bool {
+        self.counters.ready_for_query_after_error.is_some()
+            || self.counters.error.is_some()
+            || self.exceeded.is_some()
+    }
+
     pub(super) fn set_context<'a>(&mut self, message: impl Into<Context<'a>>) {
         let context = message.into();
         match context {
@@ -1,25 +1,31 @@
-use crate::net::{DataRow, Field};
+use crate::frontend::router::parser::OrderBy;
+use crate::net::{BindComplete, DataRow, ErrorResponse, Field, ReadyForQuery};
 
 use super::*;
 
 #[test]
 fn test_rd_before_dr() {
-    let mut multi_shard = MultiShard::new(3, &Route::read(None));
+    let mut multi_shard = MultiShard::new(
+        3,
+        &Route::read(None),
+        Arc::default(),
+        CrossShardLimits::default(),
+    );
     let rd = RowDescription::new(&[Field::bigint("id")]);
     let mut dr = DataRow::new();
     dr.add(1i64);
     for _ in 0..2 {
         let result = multi_shard
-            .forward(rd.message().unwrap().backend())
+            .forward(0, rd.message().unwrap().backend())
             .unwrap();
         assert!(result.is_none()); // dropped
         let result = multi_shard
-            .forward(dr.message().unwrap().backend())
+            .forward(0, dr.message().unwrap().backend())
             .unwrap();
         assert!(result.is_none()); // buffered.
     }
 
-    let result = multi_shard.forward(rd.message().unwrap()).unwrap();
+    let result = multi_shard.forward(0, rd.message().unwrap()).unwrap();
     assert_eq!(result, Some(rd.message().unwrap()));
     let result = multi_shard.message();
     // Waiting for command complete
@@ -28,6 +34,7 @@ fn test_rd_before_dr() {
     for _ in 0..3 {
         let result = multi_shard
             .forward(
+                0,
                 CommandComplete::from_str("SELECT 1")
                     .message()
                     .unwrap()
@@ -59,3 +66,441 @@ fn test_rd_before_dr() {
     // Buffer is empty.
     assert!(multi_shard.message().is_none());
 }
+
+#[test]
+fn test_error_all_shards() {
+    let mut multi_shard = MultiShard::new(
+        2,
+        &Route::read(Shard::Multi(vec![3, 1])),
+        Arc::default(),
+        CrossShardLimits::default(),
+    );
+    let mut error = ErrorResponse::syntax("syntax error at or near \"SELEC\"");
+    error.position = Some("1".into());
+    error.hint = Some("check your query".into());
+
+    // Errors are held until all shards are done.
+    for shard in [1, 0] {
+        let result = multi_shard
+            .forward(shard, error.message().unwrap().backend())
+            .unwrap();
+        assert!(result.is_none());
+    }
+
+    let rfq = ReadyForQuery::idle().message().unwrap().backend();
+    assert!(multi_shard.forward(1, rfq.clone()).unwrap().is_none());
+    let result = multi_shard.forward(0, rfq.clone()).unwrap().unwrap();
+
+    let forwarded = ErrorResponse::from_bytes(result.to_bytes().unwrap()).unwrap();
+    assert_eq!(forwarded.code, "42601");
+    assert_eq!(forwarded.position.as_deref(), Some("1"));
+    assert_eq!(forwarded.hint.as_deref(), Some("check your query"));
+    assert_eq!(forwarded.context.as_deref(), Some("shard 3\nshard 1"));
+    assert!(forwarded.detail.is_none());
+
+    // ReadyForQuery follows the error.
+    assert!(multi_shard.pending());
+    assert_eq!(multi_shard.message(), Some(rfq));
+    assert!(!multi_shard.pending());
+    assert!(multi_shard.message().is_none());
+}
+
+#[test]
+fn test_error_one_shard() {
+    let mut multi_shard = MultiShard::new(
+        2,
+        &Route::read(None),
+        Arc::default(),
+        CrossShardLimits::default(),
+    );
+    let rd = RowDescription::new(&[Field::bigint("id")]);
+    let mut dr = DataRow::new();
+    dr.add(1i64);
+    let cc = CommandComplete::from_str("SELECT 1")
+        .message()
+        .unwrap()
+        .backend();
+    let rfq = ReadyForQuery::idle().message().unwrap().backend();
+    let error = ErrorResponse::syntax("division by zero");
+
+    for shard in 0..2 {
+        multi_shard
+            .forward(shard, rd.message().unwrap().backend())
+            .unwrap();
+    }
+
+    // Shard 1 fails, shard 0 keeps sending rows, which are dropped.
+    assert!(multi_shard
+        .forward(1, error.message().unwrap().backend())
+        .unwrap()
+        .is_none());
+    assert!(multi_shard.forward(1, rfq.clone()).unwrap().is_none());
+    assert!(multi_shard
+        .forward(0, dr.message().unwrap().backend())
+        .unwrap()
+        .is_none());
+    assert!(multi_shard.forward(0, cc.clone()).unwrap().is_none());
+    assert!(multi_shard.message().is_none());
+
+    let result = multi_shard.forward(0, rfq.clone()).unwrap().unwrap();
+    assert_eq!(result.code(), 'E');
+    let forwarded = ErrorResponse::from_bytes(result.to_bytes().unwrap()).unwrap();
+    assert_eq!(forwarded.context.as_deref(), Some("shard 1"));
+    assert_eq!(multi_shard.message(), Some(rfq.clone()));
+
+    // Next Sync works as usual.
+    for shard in 0..2 {
+        multi_shard
+            .forward(shard, dr.message().unwrap().backend())
+            .unwrap();
+        multi_shard.forward(shard, cc.clone()).unwrap();
+    }
+    assert_eq!(multi_shard.message().map(|m| m.code()), Some('C'));
+    assert!(multi_shard.forward(0, rfq.clone()).unwrap().is_none());
+    assert_eq!(multi_shard.forward(1, rfq.clone()).unwrap(), Some(rfq));
+}
+
+#[test]
+fn test_error_flush() {
+    let mut multi_shard = MultiShard::new(
+        2,
+        &Route::read(None),
+        Arc::default(),
+        CrossShardLimits::default(),
+    );
+    let bind_complete = BindComplete.message().unwrap().backend();
+    let rfq = ReadyForQuery::idle().message().unwrap().backend();
+    let error = ErrorResponse::syntax("division by zero");
+
+    // Client sent Bind, Execute, Flush. Shard 1 fails, shard 0 returns nothing.
+    for shard in 0..2 {
+        multi_shard.forward(shard, bind_complete.clone()).unwrap();
+    }
+    assert!(multi_shard
+        .forward(1, error.message().unwrap().backend())
+        .unwrap()
+        .is_none());
+    assert!(multi_shard.pending());
+
+    // No ReadyForQuery is coming, so the error is sent right away.
+    let result = multi_shard.flush().unwrap().unwrap();
+    let forwarded = ErrorResponse::from_bytes(result.to_bytes().unwrap()).unwrap();
+    assert_eq!(forwarded.context.as_deref(), Some("shard 1"));
+    assert!(!multi_shard.pending());
+    assert!(multi_shard.flush().unwrap().is_none());
+
+    // Client sends Sync.
+    assert!(multi_shard.forward(0, rfq.clone()).unwrap().is_none());
+    assert_eq!(
+        multi_shard.forward(1, rfq.clone()).unwrap(),
+        Some(rfq.clone())
+    );
+    assert!(multi_shard.message().is_none());
+
+    // Next request works as usual.
+    assert!(multi_shard
+        .forward(0, bind_complete.clone())
+        .unwrap()
+        .is_none());
+    assert_eq!(
+        multi_shard.forward(1, bind_complete.clone()).unwrap(),
+        Some(bind_complete)
+    );
+}
+
+#[test]
+fn test_error_merge() {
+    let error = ErrorResponse::syntax("syntax error")
+        .shard_context(0)
+        .merge(ErrorResponse::syntax("syntax error"), 1)
+        .merge(ErrorResponse::syntax("division by zero"), 2);
+    assert_eq!(error.context.as_deref(), Some("shard 0\nshard 1\nshard 2"));
+    assert_eq!(
+        error.detail.as_deref(),
+        Some("shard 2: 42601: division by zero")
+    );
+}
+
+#[tokio::test]
+async fn test_error_connections_reused() {
+    use crate::backend::databases::init;
+    use crate::backend::pool::{Connection, Request};
+    use crate::config::{set, ConfigAndUsers, Database, User};
+    use crate::frontend::ClientRequest;
+    use crate::net::Query;
+
+    let mut config = ConfigAndUsers::default();
+    config.config.databases = (0..2)
+        .map(|shard| Database {
+            name: "pgdog".into(),
+            host: "127.0.0.1".into(),
+            port: 5432,
+            database_name: Some(format!("shard_{}", shard)),
+            shard,
+            ..Default::default()
+        })
+        .collect();
+    config.users.users = vec![User {
+        name: "pgdog".into(),
+        database: "pgdog".into(),
+        password: Some("pgdog".into()),
+        ..Default::default()
+    }];
+    set(config).unwrap();
+    init();
+
+    let mut conn = Connection::new("pgdog", "pgdog", false, &None).unwrap();
+    let pools = conn
+        .cluster()
+        .unwrap()
+        .shards()
+        .iter()
+        .flat_map(|shard| shard.pools())
+        .collect::<Vec<_>>();
+
+    for _ in 0..3 {
+        conn.connect(&Request::default(), &Route::read(Shard::All))
+            .await
+            .unwrap();
+        let total = pools
+            .iter()
+            .map(|pool| pool.state().total)
+            .collect::<Vec<_>>();
+
+        // Fails on shard 1 only.
+        let request = ClientRequest::from(vec![Query::new(
+            "SELECT 1 / (current_database() = 'shard_0')::int",
+        )
+        .into()]);
+        conn.send(&request).await.unwrap();
+
+        let mut codes = vec![];
+        while conn.has_more_messages() {
+            let message = conn.read().await.unwrap();
+            codes.push(message.code());
+            if message.code() == 'E' {
+                let error = ErrorResponse::from_bytes(message.to_bytes().unwrap()).unwrap();
+                assert_eq!(error.message, "division by zero");
+                assert_eq!(error.context.as_deref(), Some("shard 1"));
+            }
+        }
+
+        assert_eq!(codes.iter().filter(|c| **c == 'E').count(), 1);
+        assert_eq!(&codes[codes.len() - 2..], &['E', 'Z']);
+        assert!(conn.done());
+        conn.disconnect();
+
+        // Connections went back to the pools instead of being closed.
+        for (pool, total) in pools.iter().zip(total) {
+            let state = pool.state();
+            assert_eq!(state.total, total);
+            assert_eq!(state.checked_out, 0);
+        }
+    }
+}
+
+#[test]
+fn test_merge_collation_mismatch() {
+    let route = Route::select(
+        Shard::Multi(vec![3, 1]),
+        vec![OrderBy::Asc(1)],
+        Default::default(),
+        Default::default(),
+        None,
+    );
+
+    // Not checked unless a collation is configured.
+    for collation in [None, Some(TextMergeCollation::C)] {
+        let mut multi_shard =
+            MultiShard::new(2, &route, Arc::default(), CrossShardLimits::default());
+        multi_shard.collation = collation;
+        let rd = RowDescription::new(&[Field::text("name")]);
+
+        for shard in 0..2 {
+            multi_shard
+                .forward(shard, rd.message().unwrap().backend())
+                .unwrap();
+        }
+
+        // Shard 3 (position 1) sorted using a linguistic collation.
+        for (shard, name) in [(0, "Apple"), (0, "banana"), (1, "apple"), (1, "Banana")] {
+            let mut dr = DataRow::new();
+            dr.add(name.to_string());
+            multi_shard
+                .forward(shard, dr.message().unwrap().backend())
+                .unwrap();
+        }
+
+        let cc = CommandComplete::from_str("SELECT 2")
+            .message()
+            .unwrap()
+            .backend();
+        multi_shard.forward(0, cc.clone()).unwrap();
+        let result = multi_shard.forward(1, cc);
+
+        if collation.is_some() {
+            assert!(matches!(
+                result.unwrap_err(),
+                super::super::Error::MergeCollation(3, TextMergeCollation::C)
+            ));
+        } else {
+            assert!(result.is_ok());
+        }
+    }
+}
+
+#[test]
+fn test_shard_rows() {
+    let skew = Arc::new(ShardSkew::default());
+    let mut multi_shard = MultiShard::new(
+        2,
+        &Route::read(Shard::Multi(vec![3, 1])),
+        skew.clone(),
+        CrossShardLimits::default(),
+    );
+    let rd = RowDescription::new(&[Field::bigint("id")]);
+    let mut dr = DataRow::new();
+    dr.add(1i64);
+    let dr = dr.message().unwrap().backend();
+
+    for shard in 0..2 {
+        multi_shard
+            .forward(shard, rd.message().unwrap().backend())
+            .unwrap();
+    }
+
+    // Shard 1 (position 0) returns 3 rows, shard 3 returns 1.
+    for shard in [0, 0, 0, 1] {
+        multi_shard.forward(shard, dr.clone()).unwrap();
+    }
+    // Statement isn't done yet.
+    assert!(multi_shard.shard_rows().iter().all(|shard| shard.rows == 0));
+
+    let cc = CommandComplete::from_str("SELECT 1")
+        .message()
+        .unwrap()
+        .backend();
+    for shard in 0..2 {
+        multi_shard.forward(shard, cc.clone()).unwrap();
+    }
+
+    assert_eq!(
+        multi_shard.shard_rows(),
+        vec![
+            ShardRows {
+                shard: 1,
+                rows: 3,
+                bytes: 3 * dr.len() as u64,
+            },
+            ShardRows {
+                shard: 3,
+                rows: 1,
+                bytes: dr.len() as u64,
+            },
+        ]
+    );
+
+    let stats = skew.stats();
+    assert_eq!(stats.count, 1);
+    assert_eq!(stats.sum, 0.75);
+    assert_eq!(stats.buckets, vec![0, 0, 0, 1, 1, 1]);
+
+    // Statements without rows don't count towards skew.
+    let cc = CommandComplete::from_str("UPDATE 0")
+        .message()
+        .unwrap()
+        .backend();
+    for shard in 0..2 {
+        multi_shard.forward(shard, cc.clone()).unwrap();
+    }
+    assert!(multi_shard.shard_rows().iter().all(|shard| shard.rows == 0));
+    assert_eq!(skew.stats().count, 1);
+}
+
+#[test]
+fn test_cross_shard_max_rows() {
+    let limits = CrossShardLimits {
+        max_rows: Some(2),
+        ..Default::default()
+    };
+    let mut multi_shard = MultiShard::new(2, &Route::read(None), Arc::default(), limits);
+    let rd = RowDescription::new(&[Field::bigint("id")]);
+    let mut dr = DataRow::new();
+    dr.add(1i64);
+    let dr = dr.message().unwrap().backend();
+
+    for shard in 0..2 {
+        multi_shard
+            .forward(shard, rd.message().unwrap().backend())
+            .unwrap();
+    }
+
+    // Rows are sent to the client until there are too many.
+    for shard in [0, 1] {
+        assert!(multi_shard.forward(shard, dr.clone()).unwrap().is_some());
+    }
+    assert!(!multi_shard.cancel_pending());
+    assert!(multi_shard.forward(0, dr.clone()).unwrap().is_none());
+    assert!(multi_shard.cancel_pending());
+
+    // Canceled shards return errors, the client gets the limit instead.
+    let canceled = ErrorResponse {
+        code: "57014".into(),
+        message: "canceling statement due to user request".into(),
+        ..Default::default()
+    };
+    let rfq = ReadyForQuery::idle().message().unwrap().backend();
+    assert!(multi_shard
+        .forward(0, canceled.message().unwrap().backend())
+        .unwrap()
+        .is_none());
+    assert!(multi_shard.forward(0, rfq.clone()).unwrap().is_none());
+    assert!(multi_shard.forward(1, dr.clone()).unwrap().is_none());
+    let cc = CommandComplete::from_str("SELECT 2")
+        .message()
+        .unwrap()
+        .backend();
+    assert!(multi_shard.forward(1, cc).unwrap().is_none());
+    let result = multi_shard.forward(1, rfq.clone()).unwrap().unwrap();
+
+    let forwarded = ErrorResponse::from_bytes(result.to_bytes().unwrap()).unwrap();
+    assert_eq!(forwarded.code, "54000");
+    assert_eq!(
+        forwarded.message,
+        "canceling cross-shard query due to cross_shard_max_rows"
+    );
+    assert_eq!(multi_shard.message(), Some(rfq));
+}
+
+#[test]
+fn test_cross_shard_limits_min_shards() {
+    let limits = CrossShardLimits {
+        max_rows: Some(2),
+        min_shards: 2,
+        ..Default::default()
+    };
+
+    // Queries sent to all shards are limited.
+    assert_eq!(limits.for_shard(&Shard::All), limits);
+
+    // Queries sent to a few shards aren't.
+    assert!(limits.for_shard(&Shard::Multi(vec![0, 1])).is_empty());
+    assert_eq!(limits.for_shard(&Shard::Multi(vec![0, 1, 2])), limits);
+
+    let route = Route::read(Shard::Multi(vec![0, 1]));
+    let mut multi_shard = MultiShard::new(3, &route, Arc::default(), limits);
+    let rd = RowDescription::new(&[Field::bigint("id")]);
+    let mut dr = DataRow::new();
+    dr.add(1i64);
+    let dr = dr.message().unwrap().backend();
+
+    for shard in 0..2 {
+        multi_shard
+            .forward(shard, rd.message().unwrap().backend())
+            .unwrap();
+    }
+    for shard in [0, 1, 0] {
+        assert!(multi_shard.forward(shard, dr.clone()).unwrap().is_some());
+    }
+    assert!(!multi_shard.cancel_pending());
+}
@@ -1,6 +1,8 @@
 //! Connection pool errors.
 use thiserror::Error;
 
+use super::healthcheck::HealthcheckFailure;
+
 #[derive(Debug, Error, PartialEq, Clone, Copy)]
 pub enum Error {
     #[error("checkout timeout")]
@@ -33,6 +35,12 @@ pub enum Error {
     #[error("healthcheck error")]
     HealthcheckError,
 
+    #[error("healthcheck failed: {0}")]
+    Healthcheck(HealthcheckFailure),
+
+    #[error("database role doesn't match configuration")]
+    UnexpectedRole,
+
     #[error("primary lsn query failed")]
     PrimaryLsnQueryFailed,
 
@@ -59,4 +67,10 @@ pub enum Error {
 
     #[error("pub/sub disabled")]
     PubSubDisabled,
+
+    #[error("checkout wait is too high, transaction rejected")]
+    LoadShed,
+
+    #[error("statement timeout expired waiting for a connection")]
+    StatementTimeout,
 }
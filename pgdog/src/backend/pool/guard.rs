@@ -10,13 +10,14 @@ use crate::backend::Server;
 use crate::state::State;
 
 use super::Error;
-use super::{cleanup::Cleanup, Pool};
+use super::{cleanup::Cleanup, Pool, Tag};
 
 /// Connection guard.
 pub struct Guard {
     server: Option<Box<Server>>,
     pub(super) pool: Pool,
     pub(super) reset: bool,
+    locked: bool,
 }
 
 impl std::fmt::Debug for Guard {
@@ -43,6 +44,26 @@ impl Guard {
             server: Some(server),
             pool,
             reset: false,
+            locked: false,
+        }
+    }
+
+    /// Lock the connection to the client until it disconnects.
+    /// Locked connections need to be reset before they can be reused.
+    pub fn lock(&mut self, lock: bool) {
+        if lock == self.locked {
+            return;
+        }
+
+        if let Some(ref mut server) = self.server {
+            if lock {
+                server.mark_dirty(true);
+            }
+            self.locked = lock;
+            self.pool.tag(
+                server.id(),
+                if lock { Tag::Locked } else { Tag::CheckedOut },
+            );
         }
     }
 
@@ -65,6 +86,7 @@ impl Guard {
             // No need to delay checkin unless we have to.
             if (rollback || reset || sync_prepared || needs_drain) && !force_close {
                 let rollback_timeout = pool.inner().config.rollback_timeout();
+                pool.tag(server.id(), Tag::Draining);
                 spawn(async move {
                     if timeout(
                         rollback_timeout,
@@ -74,6 +96,8 @@ impl Guard {
                     .is_err()
                     {
                         error!("rollback timeout [{}]", server.addr());
+                        // Don't reuse a connection that may still be in a transaction.
+                        server.stats_mut().state(State::ForceClose);
                     };
 
                     pool.checkin(server);
@@ -114,6 +138,12 @@ impl Guard {
                 server.addr(),
             );
             server.rollback().await;
+
+            // Don't reuse a connection that may still be in a transaction.
+            // Other cleanup would mark it idle again.
+            if server.error() {
+                return Ok(());
+            }
         }
 
         if cleanup.needed() {
@@ -125,7 +155,10 @@ impl Guard {
             );
             match server.execute_batch(cleanup.queries()).await {
                 Err(_) => {
+                    // Don't give the connection to another client, it may
+                    // still be running queries with another user's privileges.
                     error!("server reset error [{}]", server.addr());
+                    server.stats_mut().state(State::Error);
                 }
                 Ok(_) => {
                     server.cleaned();
@@ -190,8 +223,15 @@ impl Drop for Guard {
 
 #[cfg(test)]
 mod test {
+    use std::time::Duration;
+
+    use tokio::time::sleep;
+
     use crate::{
-        backend::pool::{test::pool, Request},
+        backend::{
+            pool::{test::pool, Request},
+            server::test::test_server,
+        },
         net::{Describe, Flush, Parse, Protocol, Query, Sync},
     };
 
@@ -254,4 +294,47 @@ mod test {
         guard.mark_dirty(true);
         drop(guard);
     }
+
+    #[tokio::test]
+    async fn test_cleanup_rollback_error() {
+        crate::logger();
+        let pool = pool();
+        let mut guard = pool.get(&Request::default()).await.unwrap();
+        let pid = guard
+            .fetch_all::<String>("SELECT pg_backend_pid()::text")
+            .await
+            .unwrap()
+            .remove(0);
+
+        guard.execute("BEGIN").await.unwrap();
+        assert!(guard.in_transaction());
+        guard.mark_dirty(true);
+
+        // ROLLBACK fails.
+        let mut other = test_server().await;
+        other
+            .execute_checked(format!("SELECT pg_terminate_backend({})", pid))
+            .await
+            .unwrap();
+        let running = format!("SELECT pid::text FROM pg_stat_activity WHERE pid = {}", pid);
+        while !other
+            .fetch_all::<String>(running.as_str())
+            .await
+            .unwrap()
+            .is_empty()
+        {
+            sleep(Duration::from_millis(10)).await;
+        }
+        drop(guard);
+
+        // Our test pool is only 1 connection, and it's a new one.
+        let mut guard = pool.get(&Request::default()).await.unwrap();
+        let new_pid = guard
+            .fetch_all::<String>("SELECT pg_backend_pid()::text")
+            .await
+            .unwrap()
+            .remove(0);
+        assert_ne!(pid, new_pid);
+        assert!(!guard.in_transaction());
+    }
 }
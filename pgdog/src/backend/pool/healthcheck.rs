@@ -8,6 +8,107 @@ use tracing::error;
 
 use super::{Error, Pool};
 use crate::backend::Server;
+use crate::config::{Healthcheck, HealthcheckPolicy, Role};
+use crate::state::State;
+
+/// Why a healthcheck failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HealthcheckFailure {
+    /// Couldn't connect to the database in time.
+    ConnectTimeout,
+    /// Healthcheck query didn't finish in time.
+    QueryTimeout,
+    /// Database returned an error with this SQLSTATE.
+    SqlError([u8; 5]),
+    /// Connection was refused or closed.
+    ConnectionReset,
+}
+
+impl HealthcheckFailure {
+    /// All failure classes, by name.
+    pub const CLASSES: [&'static str; 4] = [
+        "connect_timeout",
+        "query_timeout",
+        "sql_error",
+        "connection_reset",
+    ];
+
+    /// Classify a server error.
+    pub fn new(err: &crate::backend::Error) -> Self {
+        use crate::backend::Error;
+
+        match err {
+            Error::ExecutionError(err) | Error::ConnectionError(err) => {
+                Self::SqlError(err.code.as_bytes().try_into().unwrap_or(*b"XX000"))
+            }
+            _ => Self::ConnectionReset,
+        }
+    }
+
+    fn index(&self) -> usize {
+        match self {
+            Self::ConnectTimeout => 0,
+            Self::QueryTimeout => 1,
+            Self::SqlError(_) => 2,
+            Self::ConnectionReset => 3,
+        }
+    }
+
+    /// Failure class name.
+    pub fn class(&self) -> &'static str {
+        Self::CLASSES[self.index()]
+    }
+
+    /// SQLSTATE returned by the database, if any.
+    pub fn sqlstate(&self) -> Option<&str> {
+        match self {
+            Self::SqlError(code) => std::str::from_utf8(code).ok(),
+            _ => None,
+        }
+    }
+
+    /// Ban policy for this failure.
+    pub fn policy(&self, healthcheck: &Healthcheck) -> HealthcheckPolicy {
+        match self {
+            Self::ConnectTimeout => healthcheck.connect_timeout,
+            Self::QueryTimeout => healthcheck.query_timeout,
+            Self::SqlError(_) => healthcheck.sql_error,
+            Self::ConnectionReset => healthcheck.connection_reset,
+        }
+    }
+}
+
+impl std::fmt::Display for HealthcheckFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.sqlstate() {
+            Some(sqlstate) => write!(f, "{} ({})", self.class(), sqlstate),
+            None => write!(f, "{}", self.class()),
+        }
+    }
+}
+
+/// Healthcheck failures of each class.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct HealthcheckFailures([usize; 4]);
+
+impl HealthcheckFailures {
+    /// Count a failure, returning the new count for its class.
+    pub(super) fn add(&mut self, failure: HealthcheckFailure) -> usize {
+        let count = &mut self.0[failure.index()];
+        *count += 1;
+        *count
+    }
+
+    /// Failures of the same class as this one.
+    pub fn get(&self, failure: HealthcheckFailure) -> usize {
+        self.0[failure.index()]
+    }
+
+    /// Failures by class name.
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, usize)> {
+        HealthcheckFailure::CLASSES.into_iter().zip(self.0)
+    }
+}
 
 /// Perform a healtcheck on a connection.
 pub struct Healtcheck<'a> {
@@ -55,13 +156,58 @@ impl<'a> Healtcheck<'a> {
             return Ok(());
         }
 
-        match timeout(self.healthcheck_timeout, self.conn.healthcheck(";")).await {
-            Ok(Ok(())) => Ok(()),
-            Ok(Err(err)) => {
-                error!("server error: {} [{}]", err, self.pool.addr());
-                Err(Error::ServerError)
-            }
-            Err(_) => Err(Error::HealthcheckError),
+        match timeout(
+            self.healthcheck_timeout,
+            self.conn.healthcheck(self.pool.healthcheck_query()),
+        )
+        .await
+        {
+            Ok(Ok(())) => (),
+            Ok(Err(err)) => return Err(self.server_error(&err)),
+            Err(_) => return Err(self.failed(HealthcheckFailure::QueryTimeout)),
+        }
+
+        if let Some(role) = self.pool.config().role_check {
+            self.role_check(role).await?;
         }
+
+        self.pool.lock().healthcheck_ok();
+
+        Ok(())
+    }
+
+    fn server_error(&mut self, err: &crate::backend::Error) -> Error {
+        error!("server error: {} [{}]", err, self.pool.addr());
+        self.failed(HealthcheckFailure::new(err))
+    }
+
+    /// Close the connection without banning the pool when it's checked in,
+    /// the ban policy for the failure decides that.
+    fn failed(&mut self, failure: HealthcheckFailure) -> Error {
+        self.conn.stats_mut().state(State::ForceClose);
+        Error::Healthcheck(failure)
+    }
+
+    /// Make sure the database is still in the role we expect it to be in.
+    async fn role_check(&mut self, role: Role) -> Result<(), Error> {
+        let in_recovery = match timeout(self.healthcheck_timeout, self.conn.in_recovery()).await {
+            Ok(Ok(in_recovery)) => in_recovery,
+            Ok(Err(err)) => return Err(self.server_error(&err)),
+            Err(_) => return Err(self.failed(HealthcheckFailure::QueryTimeout)),
+        };
+
+        let expected = role == Role::Replica;
+
+        if in_recovery != expected {
+            error!(
+                "expected {} but pg_is_in_recovery() returned {} [{}]",
+                role,
+                in_recovery,
+                self.pool.addr()
+            );
+            return Err(Error::UnexpectedRole);
+        }
+
+        Ok(())
     }
 }
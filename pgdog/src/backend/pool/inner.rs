@@ -2,13 +2,22 @@
 
 use std::cmp::max;
 use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
 
 use crate::backend::{stats::Counts as BackendCounts, Server};
+use crate::config::CheckoutOrder;
 use crate::net::messages::BackendKeyData;
+use crate::stats::memory::MemoryUsage;
 
 use tokio::time::Instant;
 
-use super::{Ban, Config, Error, Mapping, Oids, Pool, Request, Stats, Taken, Waiter};
+use super::monitor::MAINTENANCE;
+use super::{
+    Ban, Config, Error, HealthcheckFailure, HealthcheckFailures, LoadShedder, Mapping, Oids, Pool,
+    Request, Settings, SlowStart, Stats, Tag, Taken, Waiter,
+};
 
 /// Pool internals protected by a mutex.
 #[derive(Default)]
@@ -24,6 +33,8 @@ pub(super) struct Inner {
     pub(super) waiting: VecDeque<Waiter>,
     /// Pool ban status.
     pub(super) ban: Option<Ban>,
+    /// Mirrors `ban`, so it can be checked without locking the pool.
+    pub(super) banned: Arc<AtomicBool>,
     /// Pool is online and available to clients.
     pub(super) online: bool,
     /// Pool is paused.
@@ -41,11 +52,27 @@ pub(super) struct Inner {
     pub(super) stats: Stats,
     /// OIDs.
     pub(super) oids: Option<Oids>,
+    /// Server settings, captured by the first connection.
+    pub(super) settings: Option<Arc<Settings>>,
     /// The pool has been changed and connections should be returned
     /// to the new pool.
     moved: Option<Pool>,
     id: u64,
     pub(super) replica_lag: ReplicaLag,
+    /// Last WAL position replayed, if this is a replica.
+    pub(super) replay_lsn: Option<u64>,
+    /// Traffic ramp after the pool comes online.
+    pub(super) slow_start: SlowStart,
+    /// Sheds transactions when checkouts are slow.
+    pub(super) load_shedder: LoadShedder,
+    /// When the healthcheck loop last ran a periodic healthcheck.
+    pub(super) last_healthcheck: Option<Instant>,
+    /// Healthcheck failures since the last successful healthcheck.
+    consecutive_failures: HealthcheckFailures,
+    /// Total healthcheck failures.
+    pub(super) healthcheck_failures: HealthcheckFailures,
+    /// Why the last healthcheck failed.
+    pub(super) last_failure_reason: Option<HealthcheckFailure>,
 }
 
 impl std::fmt::Debug for Inner {
@@ -60,6 +87,23 @@ impl std::fmt::Debug for Inner {
     }
 }
 
+impl MemoryUsage for Inner {
+    /// Idle connections and bookkeeping. Checked out connections
+    /// are counted by their clients.
+    #[inline]
+    fn memory_usage(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + self
+                .idle_connections
+                .iter()
+                .map(|server| server.memory_usage())
+                .sum::<usize>()
+            + self.taken.len()
+                * (std::mem::size_of::<BackendKeyData>() * 5 + std::mem::size_of::<Tag>())
+            + self.waiting.len() * std::mem::size_of::<Waiter>()
+    }
+}
+
 impl Inner {
     /// New inner structure.
     pub(super) fn new(config: Config, id: u64) -> Self {
@@ -69,6 +113,7 @@ impl Inner {
             config,
             waiting: VecDeque::new(),
             ban: None,
+            banned: Arc::new(AtomicBool::new(false)),
             online: false,
             paused: false,
             force_close: 0,
@@ -77,9 +122,17 @@ impl Inner {
             errors: 0,
             stats: Stats::default(),
             oids: None,
+            settings: None,
             moved: None,
             id,
             replica_lag: ReplicaLag::default(),
+            replay_lsn: None,
+            slow_start: SlowStart::default(),
+            load_shedder: LoadShedder::default(),
+            last_healthcheck: None,
+            consecutive_failures: HealthcheckFailures::default(),
+            healthcheck_failures: HealthcheckFailures::default(),
+            last_failure_reason: None,
         }
     }
     /// Total number of connections managed by the pool.
@@ -94,6 +147,21 @@ impl Inner {
         self.idle_connections.len()
     }
 
+    /// Average number of prepared statements on idle connections.
+    pub(super) fn avg_prepared_statements(&self) -> f64 {
+        if self.idle_connections.is_empty() {
+            return 0.0;
+        }
+
+        let total = self
+            .idle_connections
+            .iter()
+            .map(|conn| conn.prepared_statements().len())
+            .sum::<usize>();
+
+        total as f64 / self.idle_connections.len() as f64
+    }
+
     /// Number of connections checked out of the pool
     /// by clients.
     #[inline]
@@ -101,6 +169,28 @@ impl Inner {
         self.taken.len()
     }
 
+    /// Number of connections with this tag.
+    #[inline]
+    pub(super) fn tagged(&self, tag: Tag) -> usize {
+        match tag {
+            Tag::Idle => self.idle(),
+            tag => self.taken.tagged(tag),
+        }
+    }
+
+    /// Change the tag of a checked out connection.
+    pub(super) fn tag(&mut self, server: &BackendKeyData, tag: Tag) {
+        if let Some(ref moved) = self.moved {
+            // Checked out connections were moved too.
+            if moved.id() != self.id {
+                moved.lock().tag(server, tag);
+                return;
+            }
+        }
+
+        self.taken.tag(server, tag);
+    }
+
     /// Find the server currently linked to this client, if any.
     #[inline]
     pub(super) fn peer(&self, client_id: &BackendKeyData) -> Option<BackendKeyData> {
@@ -150,17 +240,38 @@ impl Inner {
         }
 
         let mut unbanned = false;
-        if let Some(ban) = self.ban.take() {
-            if !ban.expired(now) {
-                self.ban = Some(ban);
-            } else {
+        if let Some(ban) = self.ban {
+            if ban.expired(now) {
+                self.set_ban(None);
                 unbanned = true;
+                self.slow_start.start(now);
             }
         }
 
         unbanned
     }
 
+    /// Record checkout wait, if load shedding is enabled.
+    pub(super) fn record_wait(&mut self, wait: Duration, now: Instant) {
+        if self.config.shed_above_wait.is_some() {
+            self.load_shedder.record(wait, now);
+        }
+    }
+
+    /// Should this request be rejected to protect the pool?
+    pub(super) fn shed(&mut self, request: &Request, now: Instant) -> bool {
+        match self.config.shed_above_wait {
+            Some(target) if request.sheddable => self.load_shedder.shed(target, now),
+            _ => false,
+        }
+    }
+
+    /// Share of traffic this pool should be receiving right now.
+    #[inline]
+    pub(super) fn slow_start_weight(&self, now: Instant) -> f64 {
+        self.slow_start.weight(now, self.config.slow_start)
+    }
+
     /// Close connections that have exceeded the max age.
     #[inline]
     pub(crate) fn close_old(&mut self, now: Instant) -> usize {
@@ -181,6 +292,9 @@ impl Inner {
 
     /// Close connections that have been idle for too long
     /// without affecting the minimum pool size requirement.
+    ///
+    /// Only connections tagged idle are considered; locked, checked out
+    /// and draining connections aren't in the idle list.
     #[inline]
     pub(crate) fn close_idle(&mut self, now: Instant) -> usize {
         let (mut remove, mut removed) = (self.can_remove(), 0);
@@ -201,6 +315,62 @@ impl Inner {
         removed
     }
 
+    /// Close idle connections above the minimum pool size right away,
+    /// ignoring `idle_timeout`. Least recently used connections go first.
+    ///
+    /// If that would leave no idle connections and a healthcheck is about to run,
+    /// one is kept so the healthcheck doesn't have to open a new one.
+    pub(super) fn shrink(&mut self, now: Instant) -> usize {
+        let idle = self.idle();
+        let mut remove = self.can_remove().min(idle);
+
+        if remove == idle && remove > 0 && self.healthcheck_soon(now) {
+            remove -= 1;
+        }
+
+        // Check-in pushes to the back, so the front was idle the longest.
+        self.idle_connections.drain(..remove);
+
+        remove
+    }
+
+    /// A periodic healthcheck will run within the next maintenance interval.
+    fn healthcheck_soon(&self, now: Instant) -> bool {
+        let interval = self.config.idle_healthcheck_interval();
+
+        self.last_healthcheck
+            .map(|last| now.duration_since(last) + MAINTENANCE >= interval)
+            .unwrap_or(false)
+    }
+
+    /// Take idle connections that haven't been used or healthchecked
+    /// for at least `interval`, so they can be probed. Connections
+    /// used by clients, including locked ones, are never probed.
+    #[allow(clippy::vec_box)]
+    pub(super) fn take_stale(&mut self, now: Instant, interval: Duration) -> Vec<Box<Server>> {
+        let (stale, idle): (Vec<_>, Vec<_>) = std::mem::take(&mut self.idle_connections)
+            .into_iter()
+            .partition(|c| c.activity_age(now) >= interval);
+        self.idle_connections = idle;
+
+        for conn in &stale {
+            self.taken.take(&Mapping {
+                client: BackendKeyData::new(),
+                server: *conn.id(),
+            });
+        }
+
+        stale
+    }
+
+    /// Close a connection that failed its idle healthcheck.
+    /// Returns true if a replacement should be created.
+    pub(super) fn close_broken(&mut self, server: &Server) -> bool {
+        self.taken.check_in(server.id());
+        self.errors += 1;
+        self.should_create()
+    }
+
     /// Pool configuration options.
     #[inline]
     pub(super) fn config(&self) -> &Config {
@@ -222,26 +392,67 @@ impl Inner {
         }
     }
 
+    /// Clients in line go before this one. New clients only skip the line
+    /// in LIFO order, unless someone has been waiting for too long.
+    #[inline]
+    pub(super) fn must_wait(&self, now: Instant) -> bool {
+        !self.waiting.is_empty()
+            && (self.config.checkout_order == CheckoutOrder::Fifo || self.starving(now))
+    }
+
+    /// The client first in line has waited long enough
+    /// to be served before anyone else.
+    #[inline]
+    fn starving(&self, now: Instant) -> bool {
+        self.waiting.front().is_some_and(|waiter| {
+            now.duration_since(waiter.queued_at) >= self.config.checkout_starvation
+        })
+    }
+
+    /// Next client to get a connection.
+    #[inline]
+    fn next_waiter(&mut self, now: Instant) -> Option<Waiter> {
+        match self.config.checkout_order {
+            CheckoutOrder::Lifo if !self.starving(now) => self.waiting.pop_back(),
+            _ => self.waiting.pop_front(),
+        }
+    }
+
+    /// Give idle connections to clients in line, e.g. a connection
+    /// returned while a client was getting in line.
+    #[inline]
+    pub(super) fn serve_waiters(&mut self, now: Instant) {
+        while !self.waiting.is_empty() {
+            match self.idle_connections.pop() {
+                Some(conn) => self.put(conn, now),
+                None => break,
+            }
+        }
+    }
+
     /// Place connection back into the pool
     /// or give it to a waiting client.
     #[inline]
-    pub(super) fn put(&mut self, conn: Box<Server>, now: Instant) {
+    pub(super) fn put(&mut self, mut conn: Box<Server>, now: Instant) {
         // Try to give it to a client that's been waiting, if any.
         let id = *conn.id();
-        if let Some(waiter) = self.waiting.pop_front() {
-            if let Err(conn) = waiter.tx.send(Ok(conn)) {
-                self.idle_connections.push(conn.unwrap());
-            } else {
-                self.taken.take(&Mapping {
-                    server: id,
-                    client: waiter.request.id,
-                });
-                self.stats.counts.server_assignment_count += 1;
-                self.stats.counts.wait_time += now.duration_since(waiter.request.created_at);
+        while let Some(waiter) = self.next_waiter(now) {
+            match waiter.tx.send(Ok(conn)) {
+                // Client is gone, try the next one.
+                Err(returned) => conn = returned.unwrap(),
+                Ok(()) => {
+                    self.taken.take(&Mapping {
+                        server: id,
+                        client: waiter.request.id,
+                    });
+                    self.stats.counts.server_assignment_count += 1;
+                    self.stats.counts.wait_time += now.duration_since(waiter.request.created_at);
+                    return;
+                }
             }
-        } else {
-            self.idle_connections.push(conn);
         }
+
+        self.idle_connections.push(conn);
     }
 
     #[inline]
@@ -367,13 +578,23 @@ impl Inner {
     /// Ban the pool from serving traffic if that's allowed per configuration.
     #[inline]
     pub fn maybe_ban(&mut self, now: Instant, reason: Error) -> bool {
+        self.maybe_ban_for(now, reason, self.config.ban_timeout())
+    }
+
+    /// Ban the pool for this long, if it's bannable.
+    pub(super) fn maybe_ban_for(
+        &mut self,
+        now: Instant,
+        reason: Error,
+        ban_timeout: Duration,
+    ) -> bool {
         if self.config.bannable || reason == Error::ManualBan {
             let ban = Ban {
                 created_at: now,
                 reason,
-                ban_timeout: self.config.ban_timeout(),
+                ban_timeout,
             };
-            self.ban = Some(ban);
+            self.set_ban(Some(ban));
 
             // Tell every waiting client that this pool is busted.
             self.close_waiters(Error::Banned);
@@ -387,6 +608,30 @@ impl Inner {
         }
     }
 
+    /// Record a failed healthcheck and ban the pool if its class
+    /// failed too many times in a row.
+    pub(super) fn healthcheck_failed(&mut self, now: Instant, failure: HealthcheckFailure) -> bool {
+        self.last_failure_reason = Some(failure);
+        self.healthcheck_failures.add(failure);
+
+        let policy = failure.policy(&self.config.healthcheck);
+        if self.consecutive_failures.add(failure) < policy.failures {
+            return false;
+        }
+
+        self.consecutive_failures = HealthcheckFailures::default();
+        let ban_timeout = policy
+            .ban_timeout
+            .map(Duration::from)
+            .unwrap_or(self.config.ban_timeout());
+        self.maybe_ban_for(now, Error::Healthcheck(failure), ban_timeout)
+    }
+
+    /// Healthcheck passed.
+    pub(super) fn healthcheck_ok(&mut self) {
+        self.consecutive_failures = HealthcheckFailures::default();
+    }
+
     #[inline]
     pub(super) fn close_waiters(&mut self, err: Error) {
         for waiter in self.waiting.drain(..) {
@@ -398,11 +643,11 @@ impl Inner {
     #[inline(always)]
     pub fn maybe_unban(&mut self) -> bool {
         let mut unbanned = false;
-        if let Some(ban) = self.ban.take() {
-            if ban.reason == Error::ManualBan {
-                self.ban = Some(ban);
-            } else {
+        if let Some(ban) = self.ban {
+            if ban.reason != Error::ManualBan {
+                self.set_ban(None);
                 unbanned = true;
+                self.slow_start.start(Instant::now());
             }
         }
 
@@ -410,7 +655,12 @@ impl Inner {
     }
 
     pub fn unban(&mut self) -> bool {
-        self.ban.take().is_some()
+        let unbanned = self.ban.is_some();
+        self.set_ban(None);
+        if unbanned {
+            self.slow_start.start(Instant::now());
+        }
+        unbanned
     }
 
     #[inline(always)]
@@ -418,6 +668,12 @@ impl Inner {
         self.ban.is_some()
     }
 
+    /// Ban or unban the pool.
+    fn set_ban(&mut self, ban: Option<Ban>) {
+        self.ban = ban;
+        self.banned.store(ban.is_some(), Ordering::Relaxed);
+    }
+
     #[inline(always)]
     #[allow(dead_code)]
     pub fn manually_banned(&self) -> bool {
@@ -506,6 +762,26 @@ mod test {
 
     use super::*;
 
+    #[test]
+    fn test_shrink_keeps_one_for_healthcheck() {
+        let mut inner = Inner::default();
+        inner.config.min = 0;
+        inner.config.idle_healthcheck_interval = Duration::from_secs(30);
+
+        for _ in 0..3 {
+            inner.idle_connections.push(Box::new(Server::default()));
+        }
+
+        let now = Instant::now();
+        inner.last_healthcheck = Some(now);
+        assert_eq!(inner.shrink(now + Duration::from_secs(30)), 2);
+        assert_eq!(inner.idle(), 1);
+
+        // Healthcheck just ran, nothing to keep.
+        assert_eq!(inner.shrink(now), 1);
+        assert_eq!(inner.idle(), 0);
+    }
+
     #[test]
     fn test_invariants() {
         let mut inner = Inner::default();
@@ -590,6 +866,7 @@ mod test {
         inner.config.max = 5;
         inner.waiting.push_back(Waiter {
             request: Request::default(),
+            queued_at: Instant::now(),
             tx: channel().0,
         });
         assert_eq!(inner.config.min, 1);
@@ -653,4 +930,98 @@ mod test {
         // Not checked in because of max age.
         assert_eq!(inner.total(), 0);
     }
+
+    #[test]
+    fn test_healthcheck_ban_policy() {
+        let mut inner = Inner::default();
+        inner.config.healthcheck.query_timeout.failures = 2;
+        inner.config.healthcheck.connection_reset.ban_timeout = Some(Duration::from_secs(5).into());
+        let now = Instant::now();
+        let timeout = HealthcheckFailure::QueryTimeout;
+        let sql_error = HealthcheckFailure::SqlError(*b"57P01");
+
+        // A successful healthcheck starts the count over.
+        assert!(!inner.healthcheck_failed(now, timeout));
+        inner.healthcheck_ok();
+        assert!(!inner.healthcheck_failed(now, timeout));
+        assert!(!inner.banned());
+        assert_eq!(inner.last_failure_reason, Some(timeout));
+
+        // Classes are counted separately.
+        assert!(inner.healthcheck_failed(now, sql_error));
+        assert_eq!(inner.ban.unwrap().reason, Error::Healthcheck(sql_error));
+        assert_eq!(inner.ban.unwrap().ban_timeout, inner.config.ban_timeout);
+        inner.ban = None;
+
+        assert!(!inner.healthcheck_failed(now, timeout));
+        assert!(inner.healthcheck_failed(now, timeout));
+        inner.ban = None;
+
+        assert!(inner.healthcheck_failed(now, HealthcheckFailure::ConnectionReset));
+        assert_eq!(inner.ban.unwrap().ban_timeout, Duration::from_secs(5));
+        assert!(inner.check_ban(now + Duration::from_secs(6)));
+
+        let failures = inner.healthcheck_failures.iter().collect::<Vec<_>>();
+        assert_eq!(
+            failures,
+            vec![
+                ("connect_timeout", 0),
+                ("query_timeout", 4),
+                ("sql_error", 1),
+                ("connection_reset", 1),
+            ]
+        );
+        assert_eq!(
+            inner.last_failure_reason.unwrap().to_string(),
+            "connection_reset"
+        );
+        assert_eq!(sql_error.to_string(), "sql_error (57P01)");
+    }
+
+    #[test]
+    fn test_checkout_order() {
+        let now = Instant::now();
+        let mut inner = Inner::default();
+        inner.online = true;
+        inner.config.checkout_order = CheckoutOrder::Lifo;
+        inner.config.checkout_starvation = Duration::from_secs(1);
+        assert!(!inner.must_wait(now));
+
+        let mut clients = vec![];
+        for _ in 0..3 {
+            let (tx, rx) = channel();
+            inner.waiting.push_back(Waiter {
+                request: Request::default(),
+                queued_at: now,
+                tx,
+            });
+            clients.push(rx);
+        }
+
+        // New clients can skip the line.
+        assert!(!inner.must_wait(now));
+
+        // Most recent client first.
+        inner.put(Box::new(Server::default()), now);
+        assert!(clients[2].try_recv().is_ok());
+        assert_eq!(inner.waiting.len(), 2);
+
+        // Unless the first one in line has been waiting for too long.
+        let later = now + Duration::from_secs(1);
+        assert!(inner.must_wait(later));
+        inner.put(Box::new(Server::default()), later);
+        assert!(clients[0].try_recv().is_ok());
+        assert!(clients[1].try_recv().is_err());
+
+        inner.config.checkout_order = CheckoutOrder::Fifo;
+        assert!(inner.must_wait(now));
+
+        // Idle connection is given to the client in line.
+        inner.idle_connections.push(Box::new(Server::default()));
+        inner.serve_waiters(now);
+        assert!(clients[1].try_recv().is_ok());
+        assert!(inner.waiting.is_empty());
+        assert_eq!(inner.idle(), 0);
+        assert_eq!(inner.checked_out(), 3);
+    }
 }
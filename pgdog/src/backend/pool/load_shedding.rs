@@ -0,0 +1,219 @@
+//! Shed new transactions when checkout wait time exceeds its target.
+use std::collections::VecDeque;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Checkout waits older than this are not used to calculate p95.
+const WINDOW: Duration = Duration::from_secs(10);
+/// Maximum number of waits kept in the window.
+const MAX_SAMPLES: usize = 1_000;
+/// Don't react to a handful of slow checkouts.
+const MIN_SAMPLES: usize = 10;
+/// How often the shed percentage is adjusted.
+const INTERVAL: Duration = Duration::from_secs(1);
+/// How much the shed percentage changes every interval.
+const STEP: usize = 10;
+/// Always let some transactions through, so we can tell when the pool recovers.
+const MAX_PERCENT: usize = 90;
+
+/// Load shedding controller.
+#[derive(Debug, Clone, Default)]
+pub struct LoadShedder {
+    /// Recent checkout waits.
+    samples: VecDeque<(Instant, Duration)>,
+    /// Last calculated p95 wait.
+    p95: Duration,
+    /// Percentage of new transactions being rejected, between 0 and `MAX_PERCENT`.
+    percent: usize,
+    /// Spreads rejections evenly between requests.
+    credit: usize,
+    /// Last time the percentage was adjusted.
+    adjusted_at: Option<Instant>,
+    /// Total number of rejected transactions.
+    shed: usize,
+}
+
+/// Load shedding state, as seen by stats.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LoadShedding {
+    /// p95 checkout wait in the window.
+    pub p95_wait: Duration,
+    /// Percentage of new transactions being rejected.
+    pub percent: usize,
+    /// Total number of rejected transactions.
+    pub shed: usize,
+}
+
+impl LoadShedder {
+    /// Record how long a client waited for a connection.
+    pub(super) fn record(&mut self, wait: Duration, now: Instant) {
+        if self.samples.len() == MAX_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back((now, wait));
+    }
+
+    /// Adjust the shed percentage given the wait time target.
+    pub(super) fn adjust(&mut self, target: Duration, now: Instant) {
+        if let Some(adjusted_at) = self.adjusted_at {
+            if now.duration_since(adjusted_at) < INTERVAL {
+                return;
+            }
+        }
+        self.adjusted_at = Some(now);
+
+        while let Some((recorded_at, _)) = self.samples.front() {
+            if now.duration_since(*recorded_at) > WINDOW {
+                self.samples.pop_front();
+            } else {
+                break;
+            }
+        }
+
+        self.p95 = if self.samples.len() < MIN_SAMPLES {
+            Duration::ZERO
+        } else {
+            let mut waits = self
+                .samples
+                .iter()
+                .map(|(_, wait)| *wait)
+                .collect::<Vec<_>>();
+            waits.sort_unstable();
+            waits[(waits.len() * 95).div_ceil(100) - 1]
+        };
+
+        self.percent = if self.p95 > target {
+            (self.percent + STEP).min(MAX_PERCENT)
+        } else {
+            self.percent.saturating_sub(STEP)
+        };
+
+        if self.percent == 0 {
+            self.credit = 0;
+        }
+    }
+
+    /// Should this transaction be rejected?
+    pub(super) fn shed(&mut self, target: Duration, now: Instant) -> bool {
+        self.adjust(target, now);
+
+        self.credit += self.percent;
+        if self.credit >= 100 {
+            self.credit -= 100;
+            self.shed += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Current state.
+    pub fn state(&self) -> LoadShedding {
+        LoadShedding {
+            p95_wait: self.p95,
+            percent: self.percent,
+            shed: self.shed,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const TARGET: Duration = Duration::from_millis(200);
+
+    /// Simulate one second of traffic: record waits and
+    /// return how many of the requests were shed.
+    fn second(shedder: &mut LoadShedder, now: Instant, wait: Duration, requests: usize) -> usize {
+        let mut shed = 0;
+        for _ in 0..requests {
+            if shedder.shed(TARGET, now) {
+                shed += 1;
+            } else {
+                shedder.record(wait, now);
+            }
+        }
+        shed
+    }
+
+    #[test]
+    fn test_no_shedding_below_target() {
+        let mut shedder = LoadShedder::default();
+        let start = Instant::now();
+
+        for s in 0..30 {
+            let now = start + Duration::from_secs(s);
+            assert_eq!(second(&mut shedder, now, Duration::from_millis(50), 100), 0);
+        }
+
+        let state = shedder.state();
+        assert_eq!(state.percent, 0);
+        assert_eq!(state.shed, 0);
+        assert_eq!(state.p95_wait, Duration::from_millis(50));
+    }
+
+    #[test]
+    fn test_shedding_grows_and_recovers() {
+        let mut shedder = LoadShedder::default();
+        let start = Instant::now();
+        let slow = Duration::from_millis(500);
+        let mut shed = vec![];
+
+        // Database slows down.
+        for s in 0..12 {
+            let now = start + Duration::from_secs(s);
+            shed.push(second(&mut shedder, now, slow, 100));
+        }
+
+        // First second has no data yet, then shedding grows
+        // every second until it reaches the maximum.
+        assert_eq!(shed[0], 0);
+        assert!(shed.windows(2).all(|w| w[1] >= w[0]));
+        assert_eq!(shed[11], 90);
+        assert_eq!(shedder.state().percent, 90);
+
+        // Database recovers. Slow waits leave the window
+        // and shedding backs off.
+        let mut shed = vec![];
+        for s in 12..40 {
+            let now = start + Duration::from_secs(s);
+            shed.push(second(&mut shedder, now, Duration::from_millis(10), 100));
+        }
+
+        assert!(shed.windows(2).all(|w| w[1] <= w[0]));
+        assert_eq!(*shed.last().unwrap(), 0);
+        assert_eq!(shedder.state().percent, 0);
+        assert_eq!(shedder.state().p95_wait, Duration::from_millis(10));
+    }
+
+    #[test]
+    fn test_few_slow_checkouts_ignored() {
+        let mut shedder = LoadShedder::default();
+        let start = Instant::now();
+
+        for s in 0..5 {
+            let now = start + Duration::from_secs(s);
+            assert_eq!(second(&mut shedder, now, Duration::from_secs(5), 1), 0);
+        }
+    }
+
+    #[test]
+    fn test_p95_ignores_outliers() {
+        let mut shedder = LoadShedder::default();
+        let now = Instant::now();
+
+        for i in 0..100 {
+            let wait = if i < 4 {
+                Duration::from_secs(5)
+            } else {
+                Duration::from_millis(20)
+            };
+            shedder.record(wait, now);
+        }
+
+        assert!(!shedder.shed(TARGET, now));
+        assert_eq!(shedder.state().p95_wait, Duration::from_millis(20));
+        assert_eq!(shedder.state().percent, 0);
+    }
+}
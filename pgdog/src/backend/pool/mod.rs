@@ -12,15 +12,23 @@ pub mod error;
 pub mod guard;
 pub mod healthcheck;
 pub mod inner;
+pub mod load_shedding;
 pub mod mapping;
 pub mod monitor;
 pub mod oids;
 pub mod pool_impl;
+pub mod provenance;
+pub mod read_consistency;
 pub mod replicas;
 pub mod request;
+pub mod session_setup;
+pub mod settings;
 pub mod shard;
+pub mod shard_skew;
+pub mod slow_start;
 pub mod state;
 pub mod stats;
+pub mod tag;
 pub mod taken;
 pub mod waiting;
 
@@ -30,20 +38,29 @@ pub use config::Config;
 pub use connection::Connection;
 pub use error::Error;
 pub use guard::Guard;
-pub use healthcheck::Healtcheck;
+pub use healthcheck::{Healtcheck, HealthcheckFailure, HealthcheckFailures};
+pub use load_shedding::LoadShedding;
 use monitor::Monitor;
 pub use oids::Oids;
 pub use pool_impl::Pool;
+pub use provenance::Provenance;
+pub use read_consistency::ReadConsistencyStats;
 pub use replicas::Replicas;
 pub use request::Request;
+pub use session_setup::SessionSetup;
+pub use settings::{Setting, Settings};
 pub use shard::Shard;
+pub use shard_skew::ShardSkew;
 pub use state::State;
 pub use stats::Stats;
+pub use tag::Tag;
 
 use ban::Ban;
 use comms::Comms;
 use inner::Inner;
+use load_shedding::LoadShedder;
 use mapping::Mapping;
+use slow_start::SlowStart;
 use taken::Taken;
 use waiting::{Waiter, Waiting};
 
@@ -5,7 +5,8 @@
 //! The monitor has three (3) loops running in different Tokio tasks:
 //!
 //! * the maintenance loop which runs ~3 times per second,
-//! * the healthcheck loop which runs every `idle_healthcheck_interval`
+//! * the healthcheck loop which makes sure no idle connection goes longer
+//!   than `idle_healthcheck_interval` without a healthcheck
 //! * the new connection loop which runs every time a client asks
 //!   for a new connection to be created
 //!
@@ -32,18 +33,20 @@
 //! connections back to the idle pool in that amount of time, and new connections are no longer needed even
 //! if clients requested ones to be created ~100ms ago.
 
+use std::sync::Arc;
 use std::time::Duration;
 
-use super::{Error, Guard, Healtcheck, Oids, Pool, Request};
+use super::{Error, Guard, Healtcheck, HealthcheckFailure, Oids, Pool, Request, Settings};
 use crate::backend::Server;
+use crate::frontend::PreparedStatements;
 
 use tokio::time::{interval, sleep, timeout, Instant};
-use tokio::{select, task::spawn};
+use tokio::{pin, select, task::spawn};
 use tracing::info;
 
-use tracing::{debug, error};
+use tracing::{debug, error, warn};
 
-static MAINTENANCE: Duration = Duration::from_millis(333);
+pub(super) static MAINTENANCE: Duration = Duration::from_millis(333);
 
 /// Pool maintenance.
 ///
@@ -81,7 +84,10 @@ impl Monitor {
         let (delay, replication_mode) = {
             let lock = pool.lock();
             let config = lock.config();
-            (config.idle_healthcheck_delay(), config.replication_mode)
+            (
+                config.idle_healthcheck_delay(),
+                config.replication_mode.enabled(),
+            )
         };
 
         if !replication_mode {
@@ -140,8 +146,12 @@ impl Monitor {
     /// The healthcheck loop.
     ///
     /// Runs regularly and ensures the pool triggers healthchecks on idle connections.
+    /// Every idle connection is probed before it goes `idle_healthcheck_interval`
+    /// without being used or healthchecked.
     async fn healthchecks(pool: Pool) {
-        let mut tick = interval(pool.lock().config().idle_healthcheck_interval());
+        let interval_duration = pool.lock().config().idle_healthcheck_interval();
+        let probe_every = Self::probe_every(interval_duration);
+        let mut tick = interval(probe_every);
         let comms = pool.comms();
 
         debug!("healthchecks running [{}]", pool.addr());
@@ -165,10 +175,30 @@ impl Monitor {
 
                     }
 
-                    // If the server is okay, remove the ban if it had one.
-                    if let Ok(true) = Self::healthcheck(&pool).await {
-                        unbanned = pool.lock().maybe_unban();
+                    // Probe connections that would otherwise
+                    // go unchecked for longer than the interval.
+                    if let Err(Error::UnexpectedRole) =
+                        Self::probe_idle(&pool, interval_duration.saturating_sub(probe_every)).await
+                    {
+                        pool.ban(Error::UnexpectedRole);
                     }
+
+                    let now = Instant::now();
+                    {
+                        let mut guard = pool.lock();
+                        let due = guard
+                            .last_healthcheck
+                            .map(|last| now.duration_since(last) >= interval_duration)
+                            .unwrap_or(true);
+
+                        if !due {
+                            continue;
+                        }
+
+                        guard.last_healthcheck = Some(now);
+                    }
+
+                    unbanned = Self::periodic_healthcheck(&pool).await;
                 }
 
 
@@ -183,6 +213,65 @@ impl Monitor {
         debug!("healthchecks stopped [{}]", pool.addr());
     }
 
+    /// Healthcheck the pool and ban or unban it depending on the result.
+    /// Returns true if the pool was unbanned.
+    async fn periodic_healthcheck(pool: &Pool) -> bool {
+        match Self::healthcheck(pool).await {
+            // If the server is okay, remove the ban if it had one.
+            Ok(true) => return pool.lock().maybe_unban(),
+            // Database switched roles, stop sending it traffic.
+            Err(Error::UnexpectedRole) => pool.ban(Error::UnexpectedRole),
+            // Ban according to the failure's ban policy.
+            Err(Error::Healthcheck(failure)) => pool.healthcheck_failed(failure),
+            _ => (),
+        }
+
+        false
+    }
+
+    /// How often to look for idle connections that need a healthcheck.
+    fn probe_every(interval: Duration) -> Duration {
+        (interval / 2).clamp(Duration::from_millis(1), MAINTENANCE)
+    }
+
+    /// Healthcheck idle connections that haven't been used or healthchecked
+    /// for at least `max_age`. Broken connections are closed and replaced
+    /// up to the minimum pool size.
+    async fn probe_idle(pool: &Pool, max_age: Duration) -> Result<usize, Error> {
+        let stale = {
+            let mut guard = pool.lock();
+            if !guard.online || guard.banned() {
+                return Ok(0);
+            }
+            guard.take_stale(Instant::now(), max_age)
+        };
+
+        let healthcheck_timeout = pool.config().healthcheck_timeout;
+        let mut result = Ok(stale.len());
+
+        for mut conn in stale {
+            match Healtcheck::mandatory(&mut conn, pool, healthcheck_timeout)
+                .healthcheck()
+                .await
+            {
+                Ok(()) => pool.checkin(conn),
+                Err(err) => {
+                    error!("idle healthcheck failed: {} [{}]", err, pool.addr());
+
+                    if pool.lock().close_broken(&conn) {
+                        pool.comms().request.notify_one();
+                    }
+
+                    if err == Error::UnexpectedRole {
+                        result = Err(err);
+                    }
+                }
+            }
+        }
+
+        result
+    }
+
     /// Perform maintenance on the pool periodically.
     async fn maintenance(pool: Pool) {
         let mut tick = interval(MAINTENANCE);
@@ -231,7 +320,17 @@ impl Monitor {
 
     /// Replenish pool with one new connection.
     async fn replenish(&self) -> bool {
-        if let Ok(conn) = Self::create_connection(&self.pool).await {
+        if let Ok(mut conn) = Self::create_connection(&self.pool).await {
+            if !Self::load_settings(&self.pool, &mut conn).await
+                || !Self::prewarm(&self.pool, &mut conn).await
+            {
+                // The connection failed or is out of sync, use a cold one instead.
+                match Self::create_connection(&self.pool).await {
+                    Ok(cold) => conn = cold,
+                    Err(_) => return false,
+                }
+            }
+
             let server = Box::new(conn);
             let mut guard = self.pool.lock();
             guard.put(server, Instant::now());
@@ -241,6 +340,105 @@ impl Monitor {
         }
     }
 
+    /// Capture server settings on the pool's first connection,
+    /// so SHOW can be answered without one.
+    ///
+    /// Returns false if the server didn't reply in time and the connection can't be used.
+    async fn load_settings(pool: &Pool, conn: &mut Server) -> bool {
+        let config = pool.config();
+        if config.replication_mode.enabled() || pool.lock().settings.is_some() {
+            return true;
+        }
+
+        match timeout(config.healthcheck_timeout, Settings::load(conn)).await {
+            Ok(Ok(settings)) => {
+                debug!(
+                    "loaded {} server settings [{}]",
+                    settings.len(),
+                    pool.addr()
+                );
+                pool.lock().settings = Some(Arc::new(settings));
+                true
+            }
+
+            Ok(Err(err)) => {
+                warn!("loading server settings failed: {} [{}]", err, pool.addr());
+                !conn.error()
+            }
+
+            Err(_) => {
+                warn!("loading server settings timed out [{}]", pool.addr());
+                false
+            }
+        }
+    }
+
+    /// Prepare the most used statements on a new connection before it's used.
+    ///
+    /// Skipped if a client is waiting for a connection. If it takes longer than
+    /// `prepared_statements_prewarm_timeout`, the rest of the replies are drained,
+    /// so the connection stays in sync, for up to `healthcheck_timeout`.
+    ///
+    /// Returns false if warm-up didn't finish and the connection can't be used.
+    async fn prewarm(pool: &Pool, conn: &mut Server) -> bool {
+        let config = pool.config();
+        let limit = config
+            .prepared_statements_prewarm
+            .min(config.prepared_statements_limit);
+
+        if limit == 0 || !pool.lock().waiting.is_empty() {
+            return true;
+        }
+
+        let statements = PreparedStatements::global()
+            .lock()
+            .most_used(pool.cluster(), limit);
+
+        if statements.is_empty() {
+            return true;
+        }
+
+        let prepare = conn.prepare_many(&statements);
+        pin!(prepare);
+
+        // The future isn't dropped if it times out, so we can keep reading its replies.
+        let result = match timeout(config.prepared_statements_prewarm_timeout, &mut prepare).await {
+            Ok(result) => Ok(result),
+            Err(_) => {
+                debug!(
+                    "prewarming prepared statements is slow, draining replies [{}]",
+                    pool.addr()
+                );
+                timeout(config.healthcheck_timeout, &mut prepare).await
+            }
+        };
+
+        match result {
+            Ok(Ok(prepared)) => {
+                debug!(
+                    "prewarmed {} prepared statements [{}]",
+                    prepared,
+                    pool.addr()
+                );
+                true
+            }
+
+            Ok(Err(err)) => {
+                error!(
+                    "prewarming prepared statements failed: {} [{}]",
+                    err,
+                    pool.addr()
+                );
+                false
+            }
+
+            Err(_) => {
+                warn!("prewarming prepared statements timed out [{}]", pool.addr());
+                false
+            }
+        }
+    }
+
     #[allow(dead_code)]
     async fn fetch_oids(pool: &Pool) -> Result<(), Error> {
         if pool.lock().oids.is_none() {
@@ -282,9 +480,7 @@ impl Monitor {
             // Create a new one and close it.
             info!("creating new healthcheck connection [{}]", pool.addr());
 
-            let mut server = Self::create_connection(pool)
-                .await
-                .map_err(|_| Error::HealthcheckError)?;
+            let mut server = Self::connect(pool).await.map_err(Error::Healthcheck)?;
 
             Healtcheck::mandatory(&mut server, pool, healthcheck_timeout)
                 .healthcheck()
@@ -315,12 +511,20 @@ impl Monitor {
     }
 
     pub(super) async fn create_connection(pool: &Pool) -> Result<Server, Error> {
+        Self::connect(pool).await.map_err(|failure| match failure {
+            HealthcheckFailure::ConnectTimeout => Error::ConnectTimeout,
+            _ => Error::ServerError,
+        })
+    }
+
+    /// Create a new connection, classifying the failure if we can't.
+    async fn connect(pool: &Pool) -> Result<Server, HealthcheckFailure> {
         let connect_timeout = pool.config().connect_timeout;
         let connect_attempts = pool.config().connect_attempts;
         let connect_attempt_delay = pool.config().connect_attempt_delay;
         let options = pool.server_options();
 
-        let mut error = Error::ServerError;
+        let mut failure = HealthcheckFailure::ConnectionReset;
 
         for attempt in 0..connect_attempts {
             match timeout(
@@ -329,7 +533,11 @@ impl Monitor {
             )
             .await
             {
-                Ok(Ok(conn)) => return Ok(conn),
+                Ok(Ok(mut conn)) => {
+                    conn.prepared_statements_mut()
+                        .set_capacity(pool.config().prepared_statements_limit);
+                    return Ok(conn);
+                }
 
                 Ok(Err(err)) => {
                     error!(
@@ -342,7 +550,7 @@ impl Monitor {
                         err,
                         pool.addr(),
                     );
-                    error = Error::ServerError;
+                    failure = HealthcheckFailure::new(&err);
                 }
 
                 Err(_) => {
@@ -355,23 +563,123 @@ impl Monitor {
                         },
                         pool.addr(),
                     );
-                    error = Error::ConnectTimeout;
+                    failure = HealthcheckFailure::ConnectTimeout;
                 }
             }
 
             sleep(connect_attempt_delay).await;
         }
 
-        Err(error)
+        Err(failure)
     }
 }
 
 #[cfg(test)]
 mod test {
+    use tokio::net::TcpListener;
+
     use crate::backend::pool::test::pool;
+    use crate::backend::pool::{Address, Config, PoolConfig};
+    use crate::config::Healthcheck;
 
     use super::*;
 
+    fn unreachable_pool(port: u16, healthcheck: Healthcheck) -> Pool {
+        let pool = Pool::new(&PoolConfig {
+            address: Address {
+                host: "127.0.0.1".into(),
+                port,
+                database_name: "pgdog".into(),
+                user: "pgdog".into(),
+                password: "pgdog".into(),
+            },
+            config: Config {
+                min: 0,
+                max: 1,
+                connect_timeout: Duration::from_millis(100),
+                healthcheck,
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        // Only the healthchecks run by the test count.
+        pool.lock().last_healthcheck = Some(Instant::now());
+        pool.launch();
+        pool
+    }
+
+    #[tokio::test]
+    async fn test_healthcheck_connect_timeout() {
+        crate::logger();
+        // Accepts connections and never replies.
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        spawn(async move {
+            let mut stalled = vec![];
+            while let Ok((stream, _)) = listener.accept().await {
+                stalled.push(stream);
+            }
+        });
+
+        let mut healthcheck = Healthcheck::default();
+        healthcheck.connect_timeout.failures = 2;
+        let pool = unreachable_pool(port, healthcheck);
+
+        let failure = HealthcheckFailure::ConnectTimeout;
+        assert_eq!(
+            Monitor::healthcheck(&pool).await,
+            Err(Error::Healthcheck(failure))
+        );
+
+        assert!(!Monitor::periodic_healthcheck(&pool).await);
+        assert!(!pool.banned());
+        assert_eq!(pool.state().last_failure_reason, Some(failure));
+
+        Monitor::periodic_healthcheck(&pool).await;
+        assert!(pool.banned());
+        assert_eq!(
+            pool.lock().ban.map(|ban| ban.reason),
+            Some(Error::Healthcheck(failure))
+        );
+        // Monitor::healthcheck() doesn't count failures, only the two periodic ones do.
+        assert_eq!(pool.state().healthcheck_failures.get(failure), 2);
+
+        pool.shutdown();
+    }
+
+    #[tokio::test]
+    async fn test_healthcheck_connection_reset() {
+        crate::logger();
+        // Nothing listening, connections are reset.
+        let port = {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            listener.local_addr().unwrap().port()
+        };
+
+        let mut healthcheck = Healthcheck::default();
+        healthcheck.connection_reset.ban_timeout = Some(Duration::from_millis(50).into());
+        let pool = unreachable_pool(port, healthcheck);
+
+        assert!(!Monitor::periodic_healthcheck(&pool).await);
+        assert!(pool.banned());
+        let ban = pool.lock().ban.unwrap();
+        assert_eq!(
+            ban.reason,
+            Error::Healthcheck(HealthcheckFailure::ConnectionReset)
+        );
+        assert_eq!(ban.ban_timeout, Duration::from_millis(50));
+        assert_eq!(
+            pool.state().last_failure_reason,
+            Some(HealthcheckFailure::ConnectionReset)
+        );
+
+        // Short ban expires on its own.
+        sleep(Duration::from_millis(500)).await;
+        assert!(!pool.banned());
+
+        pool.shutdown();
+    }
+
     #[tokio::test]
     async fn test_healthcheck() {
         crate::logger();
@@ -383,4 +691,51 @@ mod test {
         let ok = Monitor::healthcheck(&pool).await.unwrap();
         assert!(!ok);
     }
+
+    #[tokio::test]
+    async fn test_idle_connections_probed() {
+        crate::logger();
+        let interval = Duration::from_millis(100);
+        let pool = Pool::new(&PoolConfig {
+            address: Address {
+                host: "127.0.0.1".into(),
+                port: 5432,
+                database_name: "pgdog".into(),
+                user: "pgdog".into(),
+                password: "pgdog".into(),
+            },
+            config: Config {
+                min: 3,
+                max: 3,
+                idle_healthcheck_interval: interval,
+                idle_healthcheck_delay: Duration::ZERO,
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        pool.launch();
+
+        while pool.lock().idle() < 3 {
+            sleep(Duration::from_millis(10)).await;
+        }
+
+        let probed = Monitor::probe_idle(&pool, Duration::ZERO).await.unwrap();
+        assert_eq!(probed, 3);
+        assert_eq!(pool.lock().idle(), 3);
+        assert_eq!(pool.lock().checked_out(), 0);
+
+        // No idle connection goes longer than the interval without a healthcheck,
+        // give or take the time it takes to run one.
+        for _ in 0..5 {
+            sleep(interval).await;
+            let mut guard = pool.lock();
+            let slack = Duration::from_millis(50);
+            assert!(guard
+                .take_stale(Instant::now(), interval + slack)
+                .is_empty());
+            assert_eq!(guard.total(), 3);
+        }
+
+        pool.shutdown();
+    }
 }
@@ -1,24 +1,25 @@
 //! Connection pool.
 
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 
 use once_cell::sync::Lazy;
 use parking_lot::{lock_api::MutexGuard, Mutex, RawMutex};
 use tokio::time::Instant;
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
-use crate::backend::{Server, ServerOptions};
+use crate::backend::{databases::User, Server, ServerOptions, ServerTls};
 use crate::config::PoolerMode;
 use crate::net::messages::{BackendKeyData, DataRow, Format};
 use crate::net::Parameter;
+use crate::stats::memory::MemoryUsage;
 
 use super::inner::CheckInResult;
 use super::inner::ReplicaLag;
 use super::{
-    Address, Comms, Config, Error, Guard, Healtcheck, Inner, Monitor, Oids, PoolConfig, Request,
-    State, Waiting,
+    Address, Comms, Config, Error, Guard, Healtcheck, HealthcheckFailure, Inner, Monitor, Oids,
+    PoolConfig, Request, State, Tag, Waiting,
 };
 
 static ID_COUNTER: Lazy<Arc<AtomicU64>> = Lazy::new(|| Arc::new(AtomicU64::new(0)));
@@ -38,6 +39,13 @@ pub(crate) struct InnerSync {
     pub(super) inner: Mutex<Inner>,
     pub(super) id: u64,
     pub(super) config: Config,
+    pub(super) healthcheck_query: Option<String>,
+    pub(super) startup_parameters: Vec<Parameter>,
+    pub(super) cluster: Option<User>,
+    /// Pool is banned, readable without the lock.
+    pub(super) banned: Arc<AtomicBool>,
+    /// Whether new connections request TLS.
+    pub(super) tls: ServerTls,
 }
 
 impl std::fmt::Debug for Pool {
@@ -52,13 +60,20 @@ impl Pool {
     /// Create new connection pool.
     pub fn new(config: &PoolConfig) -> Self {
         let id = next_pool_id();
+        let inner = Inner::new(config.config, id);
+        let banned = inner.banned.clone();
         Self {
             inner: Arc::new(InnerSync {
                 comms: Comms::new(),
                 addr: config.address.clone(),
-                inner: Mutex::new(Inner::new(config.config, id)),
+                inner: Mutex::new(inner),
                 id,
                 config: config.config,
+                healthcheck_query: config.healthcheck_query.clone(),
+                startup_parameters: config.startup_parameters.clone(),
+                cluster: config.cluster.clone(),
+                banned,
+                tls: ServerTls::default(),
             }),
         }
     }
@@ -69,6 +84,7 @@ impl Pool {
         let config = PoolConfig {
             address: Address::new_test(),
             config: Config::default(),
+            ..Default::default()
         };
 
         Self::new(&config)
@@ -83,6 +99,7 @@ impl Pool {
         let mut guard = self.lock();
         if !guard.online {
             guard.online = true;
+            guard.slow_start.start_if_new(Instant::now());
             Monitor::run(self);
         }
     }
@@ -122,7 +139,17 @@ impl Pool {
                 return Err(Error::Banned);
             }
 
-            let conn = guard.take(request);
+            // Clients already in line go first.
+            let conn = if guard.must_wait(Instant::now()) {
+                None
+            } else {
+                guard.take(request)
+            };
+
+            // Only shed requests that would have to wait for a connection.
+            if conn.is_none() && guard.shed(request, Instant::now()) {
+                return Err(Error::LoadShed);
+            }
 
             if conn.is_some() {
                 guard.stats.counts.wait_time += elapsed;
@@ -149,6 +176,11 @@ impl Pool {
             waiting.wait().await?
         };
 
+        if self.inner.config.shed_above_wait.is_some() {
+            self.lock()
+                .record_wait(granted_at.duration_since(request.created_at), granted_at);
+        }
+
         return self
             .maybe_healthcheck(
                 server,
@@ -177,7 +209,11 @@ impl Pool {
 
         if let Err(err) = healthcheck.healthcheck().await {
             drop(conn);
-            self.ban(Error::HealthcheckError);
+            match err {
+                Error::Healthcheck(failure) => self.healthcheck_failed(failure),
+                Error::UnexpectedRole => self.ban(err),
+                _ => self.ban(Error::HealthcheckError),
+            }
             return Err(err);
         }
 
@@ -189,6 +225,9 @@ impl Pool {
         Pool::new(&PoolConfig {
             address: self.addr().clone(),
             config: *self.lock().config(),
+            healthcheck_query: self.inner.healthcheck_query.clone(),
+            startup_parameters: self.inner.startup_parameters.clone(),
+            cluster: self.inner.cluster.clone(),
         })
     }
 
@@ -224,6 +263,11 @@ impl Pool {
         }
     }
 
+    /// Change the tag of a connection checked out from this pool.
+    pub(super) fn tag(&self, server: &BackendKeyData, tag: Tag) {
+        self.lock().tag(server, tag);
+    }
+
     /// Server connection used by the client.
     pub fn peer(&self, id: &BackendKeyData) -> Option<BackendKeyData> {
         self.lock().peer(id)
@@ -238,9 +282,14 @@ impl Pool {
         Ok(())
     }
 
-    /// Is this pool banned?
+    /// Is this pool banned? Doesn't lock the pool.
     pub fn banned(&self) -> bool {
-        self.lock().banned()
+        self.inner.banned.load(Ordering::Relaxed)
+    }
+
+    /// Share of traffic this pool should receive during slow start.
+    pub fn slow_start_weight(&self, now: Instant) -> f64 {
+        self.lock().slow_start_weight(now)
     }
 
     /// Pool is available to serve connections.
@@ -259,6 +308,22 @@ impl Pool {
         }
     }
 
+    /// Record a failed healthcheck, banning the pool
+    /// according to the failure's ban policy.
+    pub fn healthcheck_failed(&self, failure: HealthcheckFailure) {
+        let banned = self.lock().healthcheck_failed(Instant::now(), failure);
+
+        if banned {
+            error!(
+                "pool banned: healthcheck failed: {} [{}]",
+                failure,
+                self.addr()
+            );
+        } else {
+            warn!("healthcheck failed: {} [{}]", failure, self.addr());
+        }
+    }
+
     /// Unban this pool from serving traffic, unless manually banned.
     #[allow(dead_code)]
     pub fn maybe_unban(&self) {
@@ -293,6 +358,8 @@ impl Pool {
             let mut to_guard = destination.lock();
 
             from_guard.online = false;
+            // Keep the ramp going, same database.
+            to_guard.slow_start = from_guard.slow_start;
             let (idle, taken) = from_guard.move_conns_to(destination);
             for server in idle {
                 to_guard.put(server, now);
@@ -304,9 +371,11 @@ impl Pool {
         self.shutdown();
     }
 
-    /// The two pools refer to the same database.
+    /// The two pools refer to the same database. Connections
+    /// are kept if only the password changed.
     pub(crate) fn can_move_conns_to(&self, destination: &Pool) -> bool {
-        self.addr() == destination.addr()
+        self.addr().same_server(destination.addr())
+            && self.inner.startup_parameters == destination.inner.startup_parameters
     }
 
     /// Pause pool, closing all open connections.
@@ -317,12 +386,29 @@ impl Pool {
         guard.dump_idle();
     }
 
+    /// Close idle connections above the minimum pool size now,
+    /// without waiting for `idle_timeout`. Checked out and locked
+    /// connections are never closed. Returns how many were closed.
+    pub fn shrink(&self) -> usize {
+        let closed = self.lock().shrink(Instant::now());
+
+        if closed > 0 {
+            info!(
+                "shrink closed {} idle connections [{}]",
+                closed,
+                self.addr()
+            );
+        }
+
+        closed
+    }
+
     /// Resume the pool.
     pub fn resume(&self) {
         {
             let mut guard = self.lock();
             guard.paused = false;
-            guard.ban = None;
+            guard.unban();
         }
 
         self.comms().ready.notify_waiters();
@@ -368,6 +454,16 @@ impl Pool {
         &self.inner.config
     }
 
+    /// User and database of the cluster this pool belongs to.
+    pub fn cluster(&self) -> Option<&User> {
+        self.inner.cluster.as_ref()
+    }
+
+    /// Query used to healthcheck server connections.
+    pub fn healthcheck_query(&self) -> &str {
+        self.inner.healthcheck_query.as_deref().unwrap_or(";")
+    }
+
     /// Get startup parameters for new server connections.
     pub(super) fn server_options(&self) -> ServerOptions {
         let mut params = vec![
@@ -390,10 +486,10 @@ impl Pool {
             });
         }
 
-        if config.replication_mode {
+        if let Some(replication) = config.replication_mode.parameter() {
             params.push(Parameter {
                 name: "replication".into(),
-                value: "database".into(),
+                value: replication.into(),
             });
         }
 
@@ -404,7 +500,16 @@ impl Pool {
             });
         }
 
-        ServerOptions { params }
+        // Configured parameters override ours, e.g. application_name.
+        for param in &self.inner.startup_parameters {
+            params.retain(|p| p.name != param.name);
+            params.push(param.clone());
+        }
+
+        ServerOptions {
+            params,
+            tls: self.inner.tls.clone(),
+        }
     }
 
     /// Pool state.
@@ -412,6 +517,11 @@ impl Pool {
         State::get(self)
     }
 
+    /// Memory used by the pool's idle connections and bookkeeping.
+    pub fn memory_usage(&self) -> usize {
+        self.lock().memory_usage()
+    }
+
     /// Update pool configuration used in internals.
     #[cfg(test)]
     pub(crate) fn update_config(&self, config: Config) {
@@ -423,6 +533,11 @@ impl Pool {
         self.lock().oids
     }
 
+    /// Server settings, if the pool connected at least once.
+    pub fn settings(&self) -> Option<Arc<Settings>> {
+        self.lock().settings.clone()
+    }
+
     /// `pg_current_wal_flush_lsn()` on the primary.
     pub async fn wal_flush_lsn(&self) -> Result<u64, Error> {
         let mut guard = self.get(&Request::default()).await?;
@@ -460,20 +575,41 @@ impl Pool {
     pub fn set_replica_lag(&self, replica_lag: ReplicaLag) {
         self.lock().replica_lag = replica_lag;
     }
+
+    /// Replica is known to be lagging less than this.
+    pub fn lag_within(&self, bound: Duration) -> bool {
+        match self.lock().replica_lag {
+            ReplicaLag::Duration(lag) => lag < bound,
+            _ => false,
+        }
+    }
+
+    /// Record the last WAL position replayed by this replica.
+    pub fn set_replay_lsn(&self, lsn: u64) {
+        self.lock().replay_lsn = Some(lsn);
+    }
+
+    /// Replica replayed WAL at least up to this LSN.
+    pub fn replayed(&self, lsn: u64) -> bool {
+        self.lock()
+            .replay_lsn
+            .map(|replayed| replayed >= lsn)
+            .unwrap_or(false)
+    }
 }
 
 // -------------------------------------------------------------------------------------------------
 // ----- Utils :: Parse LSN ------------------------------------------------------------------------
 
 #[derive(Debug)]
-enum ParseLsnError {
+pub(crate) enum ParseLsnError {
     MissingSlash,
     InvalidHex,
 }
 
 /// Parse PostgreSQL LSN string to u64 bytes.
 /// See spec: https://www.postgresql.org/docs/current/datatype-pg-lsn.html
-fn parse_pg_lsn(s: &str) -> Result<u64, ParseLsnError> {
+pub(crate) fn parse_pg_lsn(s: &str) -> Result<u64, ParseLsnError> {
     let (hi_str, lo_str) = s.split_once('/').ok_or(ParseLsnError::MissingSlash)?;
 
     let hi = u32::from_str_radix(hi_str, 16).map_err(|_| ParseLsnError::InvalidHex)? as u64;
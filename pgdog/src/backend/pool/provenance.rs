@@ -0,0 +1,224 @@
+//! Where the effective pool settings came from.
+//!
+//! Mirrors the precedence in [`Config::new`]: database settings win over user settings,
+//! which win over `[general]`, except `idle_timeout`, where the user wins over the database.
+//! General settings equal to the built-in default are reported as coming from the default.
+
+use serde::Serialize;
+
+use super::Config;
+use crate::config::{Database, General, HumanDuration, Overrides, PoolerMode, User};
+
+/// Configuration layer a setting was taken from.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Layer {
+    /// Built-in default.
+    Default,
+    /// `[general]` in pgdog.toml.
+    General,
+    /// Command line argument.
+    Cli,
+    /// `[[users]]` in users.toml.
+    User,
+    /// `[[databases]]` in pgdog.toml.
+    Database,
+}
+
+impl std::fmt::Display for Layer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let layer = match self {
+            Layer::Default => "default",
+            Layer::General => "general",
+            Layer::Cli => "cli",
+            Layer::User => "user",
+            Layer::Database => "database",
+        };
+
+        write!(f, "{}", layer)
+    }
+}
+
+/// Effective value of a setting and where it came from.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize)]
+pub struct Setting<T> {
+    pub value: T,
+    pub layer: Layer,
+}
+
+impl<T> Setting<T> {
+    fn new(value: T, layer: Layer) -> Self {
+        Self { value, layer }
+    }
+}
+
+/// Effective pool settings, with their provenance.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Provenance {
+    pub pool_size: Setting<usize>,
+    pub min_pool_size: Setting<usize>,
+    pub pooler_mode: Setting<PoolerMode>,
+    pub checkout_timeout: Setting<HumanDuration>,
+    pub connect_timeout: Setting<HumanDuration>,
+    pub idle_timeout: Setting<HumanDuration>,
+    pub statement_timeout: Setting<Option<HumanDuration>>,
+}
+
+impl Provenance {
+    /// Explain the pool config for this database and user.
+    ///
+    /// `general` must already have the command line `overrides` applied,
+    /// the same way it's passed to [`Config::new`].
+    pub fn new(general: &General, database: &Database, user: &User, overrides: &Overrides) -> Self {
+        let config = Config::new(general, database, user);
+        let defaults = General::default();
+
+        let general_layer = |changed: bool, cli: bool| {
+            if cli {
+                Layer::Cli
+            } else if changed {
+                Layer::General
+            } else {
+                Layer::Default
+            }
+        };
+
+        let pool_size = if database.pool_size.is_some() {
+            Layer::Database
+        } else if user.pool_size.is_some() {
+            Layer::User
+        } else {
+            general_layer(
+                general.default_pool_size != defaults.default_pool_size,
+                overrides.default_pool_size.is_some(),
+            )
+        };
+
+        let min_pool_size = if database.min_pool_size.is_some() {
+            Layer::Database
+        } else if user.min_pool_size.is_some() {
+            Layer::User
+        } else {
+            general_layer(
+                general.min_pool_size != defaults.min_pool_size,
+                overrides.min_pool_size.is_some(),
+            )
+        };
+
+        let pooler_mode = if database.pooler_mode.is_some() {
+            Layer::Database
+        } else if user.pooler_mode.is_some() {
+            Layer::User
+        } else {
+            general_layer(
+                general.pooler_mode != defaults.pooler_mode,
+                overrides.session_mode(),
+            )
+        };
+
+        let idle_timeout = if user.idle_timeout.is_some() {
+            Layer::User
+        } else if database.idle_timeout.is_some() {
+            Layer::Database
+        } else {
+            general_layer(general.idle_timeout != defaults.idle_timeout, false)
+        };
+
+        let statement_timeout = if database.statement_timeout.is_some() {
+            Layer::Database
+        } else if user.statement_timeout.is_some() {
+            Layer::User
+        } else {
+            Layer::Default
+        };
+
+        Self {
+            pool_size: Setting::new(config.max, pool_size),
+            min_pool_size: Setting::new(config.min, min_pool_size),
+            pooler_mode: Setting::new(config.pooler_mode, pooler_mode),
+            checkout_timeout: Setting::new(
+                config.checkout_timeout.into(),
+                general_layer(general.checkout_timeout != defaults.checkout_timeout, false),
+            ),
+            connect_timeout: Setting::new(
+                config.connect_timeout.into(),
+                general_layer(general.connect_timeout != defaults.connect_timeout, false),
+            ),
+            idle_timeout: Setting::new(config.idle_timeout.into(), idle_timeout),
+            statement_timeout: Setting::new(
+                config.statement_timeout.map(HumanDuration::from),
+                statement_timeout,
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_provenance_defaults() {
+        let general = General::default();
+        let database = Database::default();
+        let user = User::default();
+
+        let provenance = Provenance::new(&general, &database, &user, &Overrides::default());
+
+        assert_eq!(provenance.pool_size.layer, Layer::Default);
+        assert_eq!(provenance.pool_size.value, general.default_pool_size);
+        assert_eq!(provenance.pooler_mode.layer, Layer::Default);
+        assert_eq!(provenance.idle_timeout.layer, Layer::Default);
+        assert_eq!(provenance.statement_timeout.value, None);
+        assert_eq!(provenance.statement_timeout.layer, Layer::Default);
+    }
+
+    #[test]
+    fn test_provenance_layers() {
+        let overrides = Overrides {
+            min_pool_size: Some(3),
+            ..Default::default()
+        };
+        let mut general = General {
+            default_pool_size: 42,
+            checkout_timeout: HumanDuration::from_secs(1),
+            ..Default::default()
+        };
+        overrides.apply(&mut general);
+
+        let database = Database {
+            pooler_mode: Some(PoolerMode::Session),
+            idle_timeout: Some(HumanDuration::from_secs(10)),
+            statement_timeout: Some(HumanDuration::from_secs(2)),
+            ..Default::default()
+        };
+        let user = User {
+            idle_timeout: Some(HumanDuration::from_secs(20)),
+            statement_timeout: Some(HumanDuration::from_secs(4)),
+            ..Default::default()
+        };
+
+        let provenance = Provenance::new(&general, &database, &user, &overrides);
+
+        assert_eq!(provenance.pool_size, Setting::new(42, Layer::General));
+        assert_eq!(provenance.min_pool_size, Setting::new(3, Layer::Cli));
+        assert_eq!(
+            provenance.pooler_mode,
+            Setting::new(PoolerMode::Session, Layer::Database)
+        );
+        assert_eq!(
+            provenance.checkout_timeout,
+            Setting::new(HumanDuration::from_secs(1), Layer::General)
+        );
+        assert_eq!(provenance.connect_timeout.layer, Layer::Default);
+        // User wins over database for idle_timeout.
+        assert_eq!(
+            provenance.idle_timeout,
+            Setting::new(HumanDuration::from_secs(20), Layer::User)
+        );
+        assert_eq!(
+            provenance.statement_timeout,
+            Setting::new(Some(HumanDuration::from_secs(2)), Layer::Database)
+        );
+    }
+}
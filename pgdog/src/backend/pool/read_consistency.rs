@@ -0,0 +1,80 @@
+//! Reads routed by each user read consistency tier.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use once_cell::sync::Lazy;
+
+use crate::config::ReadConsistency;
+
+static STATS: Lazy<ReadConsistencyStats> = Lazy::new(ReadConsistencyStats::default);
+
+/// Read counters of one tier.
+#[derive(Debug, Default)]
+struct Tier {
+    reads: AtomicU64,
+    primary_fallbacks: AtomicU64,
+}
+
+/// Snapshot of one tier's counters.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TierStats {
+    /// Reads routed with this tier.
+    pub reads: u64,
+    /// Reads sent to the primary because no replica was fresh enough.
+    pub primary_fallbacks: u64,
+}
+
+/// Read counters for all tiers.
+#[derive(Debug, Default)]
+pub struct ReadConsistencyStats {
+    any: Tier,
+    bounded: Tier,
+    strong: Tier,
+}
+
+impl ReadConsistencyStats {
+    /// Get global counters.
+    pub fn get() -> &'static ReadConsistencyStats {
+        &STATS
+    }
+
+    fn tier(&self, consistency: ReadConsistency) -> &Tier {
+        match consistency {
+            ReadConsistency::Any => &self.any,
+            ReadConsistency::Bounded(_) => &self.bounded,
+            ReadConsistency::Strong => &self.strong,
+        }
+    }
+
+    /// Count a read.
+    pub fn read(&self, consistency: ReadConsistency) {
+        self.tier(consistency).reads.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Count a read sent to the primary because of its tier.
+    pub fn primary_fallback(&self, consistency: ReadConsistency) {
+        self.tier(consistency)
+            .primary_fallbacks
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counters for each tier, by name.
+    pub fn stats(&self) -> Vec<(&'static str, TierStats)> {
+        [
+            ("any", &self.any),
+            ("bounded", &self.bounded),
+            ("strong", &self.strong),
+        ]
+        .into_iter()
+        .map(|(name, tier)| {
+            (
+                name,
+                TierStats {
+                    reads: tier.reads.load(Ordering::Relaxed),
+                    primary_fallbacks: tier.primary_fallbacks.load(Ordering::Relaxed),
+                },
+            )
+        })
+        .collect()
+    }
+}
@@ -8,11 +8,11 @@ use std::{
     time::Duration,
 };
 
-use rand::seq::SliceRandom;
-use tokio::time::timeout;
+use rand::{seq::SliceRandom, Rng};
+use tokio::time::{timeout, Instant};
 use tracing::error;
 
-use crate::config::LoadBalancingStrategy;
+use crate::config::{LoadBalancingStrategy, ReadConsistency};
 use crate::net::messages::BackendKeyData;
 
 use super::{Error, Guard, Pool, PoolConfig, Request};
@@ -47,9 +47,11 @@ impl Replicas {
 
     /// Get a live connection from the pool.
     pub async fn get(&self, request: &Request, primary: &Option<Pool>) -> Result<Guard, Error> {
-        match timeout(self.checkout_timeout, self.get_internal(request, primary)).await {
+        let (checkout_timeout, deadline) = request.checkout_timeout(self.checkout_timeout);
+        match timeout(checkout_timeout, self.get_internal(request, primary)).await {
             Ok(Ok(conn)) => Ok(conn),
             Ok(Err(err)) => Err(err),
+            Err(_) if deadline => Err(Error::StatementTimeout),
             Err(_) => Err(Error::ReplicaCheckoutTimeout),
         }
     }
@@ -102,11 +104,41 @@ impl Replicas {
         Ok(())
     }
 
+    /// At least one replica replayed WAL up to this LSN.
+    pub fn replayed(&self, lsn: u64) -> bool {
+        self.pools.iter().any(|pool| pool.replayed(lsn))
+    }
+
+    /// At least one replica is known to be lagging less than this.
+    pub fn lag_within(&self, bound: Duration) -> bool {
+        self.pools.iter().any(|pool| pool.lag_within(bound))
+    }
+
     /// Pools handle.
     pub fn pools(&self) -> &[Pool] {
         &self.pools
     }
 
+    /// Move pools that are ramping up after coming online to the back
+    /// of the list, proportionally to how far along they are in the ramp.
+    pub(super) fn slow_start(candidates: &mut Vec<&Pool>, now: Instant, rng: &mut impl Rng) {
+        let weights = candidates
+            .iter()
+            .map(|pool| pool.slow_start_weight(now))
+            .collect::<Vec<_>>();
+
+        if weights.iter().all(|weight| *weight >= 1.0) {
+            return;
+        }
+
+        let (mut selected, skipped): (Vec<_>, Vec<_>) = candidates
+            .drain(..)
+            .zip(weights)
+            .partition(|(_, weight)| rng.gen::<f64>() < *weight);
+        selected.extend(skipped);
+        candidates.extend(selected.into_iter().map(|(pool, _)| pool));
+    }
+
     async fn get_internal(
         &self,
         request: &Request,
@@ -116,6 +148,21 @@ impl Replicas {
         loop {
             let mut candidates = self.pools.iter().collect::<Vec<_>>();
 
+            // Prefer replicas that have the client's writes.
+            if let Some(lsn) = request.replay_lsn {
+                if self.replayed(lsn) {
+                    candidates.retain(|pool| pool.replayed(lsn));
+                }
+            }
+
+            // Skip replicas lagging more than the client allows.
+            if let ReadConsistency::Bounded(bound) = request.read_consistency {
+                let bound = bound.duration();
+                if candidates.iter().any(|pool| pool.lag_within(bound)) {
+                    candidates.retain(|pool| pool.lag_within(bound));
+                }
+            }
+
             if let Some(primary) = primary {
                 candidates.push(primary);
             }
@@ -136,22 +183,35 @@ impl Replicas {
                 }
             }
 
+            Self::slow_start(&mut candidates, Instant::now(), &mut rand::thread_rng());
+
             let mut banned = 0;
+            let mut shed = 0;
 
             for candidate in &candidates {
                 match candidate.get(request).await {
                     Ok(conn) => return Ok(conn),
                     Err(Error::Offline) => continue,
+                    Err(Error::LoadShed) => {
+                        shed += 1;
+                        continue;
+                    }
                     Err(Error::Banned) => {
                         banned += 1;
                         continue;
                     }
+                    // No time left to try other replicas.
+                    Err(Error::StatementTimeout) => return Err(Error::StatementTimeout),
                     Err(err) => {
                         error!("{} [{}]", err, candidate.addr());
                     }
                 }
             }
 
+            if shed == candidates.len() {
+                return Err(Error::LoadShed);
+            }
+
             // All replicas are banned, unban everyone.
             if banned == candidates.len() && !unbanned {
                 candidates
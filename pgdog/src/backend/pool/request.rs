@@ -1,5 +1,8 @@
+use std::time::Duration;
+
 use tokio::time::Instant;
 
+use crate::config::ReadConsistency;
 use crate::net::messages::BackendKeyData;
 
 /// Connection request.
@@ -7,6 +10,14 @@ use crate::net::messages::BackendKeyData;
 pub struct Request {
     pub id: BackendKeyData,
     pub created_at: Instant,
+    /// Can be rejected by load shedding.
+    pub sheddable: bool,
+    /// Client's statement_timeout expires at this time.
+    pub deadline: Option<Instant>,
+    /// Replicas must have replayed WAL up to this LSN.
+    pub replay_lsn: Option<u64>,
+    /// How stale reads from replicas can be.
+    pub read_consistency: ReadConsistency,
 }
 
 impl Request {
@@ -14,6 +25,34 @@ impl Request {
         Self {
             id,
             created_at: Instant::now(),
+            sheddable: false,
+            deadline: None,
+            replay_lsn: None,
+            read_consistency: ReadConsistency::default(),
+        }
+    }
+
+    /// Client starting a new transaction.
+    pub fn transaction(id: BackendKeyData) -> Self {
+        Self {
+            sheddable: true,
+            ..Self::new(id)
+        }
+    }
+
+    /// How long to wait for a connection, given the pool's checkout timeout.
+    /// Returns true if the client's deadline is shorter.
+    pub fn checkout_timeout(&self, checkout_timeout: Duration) -> (Duration, bool) {
+        match self.deadline {
+            Some(deadline) => {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if remaining < checkout_timeout {
+                    (remaining, true)
+                } else {
+                    (checkout_timeout, false)
+                }
+            }
+            None => (checkout_timeout, false),
         }
     }
 }
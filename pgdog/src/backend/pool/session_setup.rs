@@ -0,0 +1,128 @@
+//! Statements run at the start of every client session.
+//!
+//! Plain `SET`s become the client's initial parameters, so they're synced to
+//! server connections like parameters set by the client. Other statements are
+//! executed on each server connection before it's used by the client.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use pg_query::{protobuf::VariableSetKind, NodeEnum};
+
+use crate::frontend::router::QueryParser;
+use crate::net::Parameters;
+
+/// Session setup of a database and user.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SessionSetup {
+    /// Parameters set with `SET`.
+    params: Parameters,
+    /// Other statements, in order.
+    statements: Vec<String>,
+    /// Identifies the statements, so connections that ran them can be recognized.
+    id: u64,
+}
+
+impl SessionSetup {
+    /// Sort statements into parameters and statements executed on the server.
+    pub fn new<'a>(statements: impl IntoIterator<Item = &'a String>) -> Self {
+        let mut params = Parameters::default();
+        let mut executed = vec![];
+
+        for statement in statements {
+            let parsed = pg_query::parse(statement).ok().and_then(|ast| {
+                let [ref stmt] = ast.protobuf.stmts[..] else {
+                    return None;
+                };
+                match stmt.stmt.as_ref()?.node.as_ref()? {
+                    NodeEnum::VariableSetStmt(stmt)
+                        if stmt.kind == VariableSetKind::VarSetValue as i32 && !stmt.is_local =>
+                    {
+                        QueryParser::set_value(stmt).map(|value| (stmt.name.clone(), value))
+                    }
+                    _ => None,
+                }
+            });
+
+            match parsed {
+                Some((name, value)) => {
+                    params.insert(name, value);
+                }
+                // Statements that don't parse fail on the server, with the server's error.
+                None => executed.push(statement.clone()),
+            }
+        }
+
+        let id = if executed.is_empty() {
+            0
+        } else {
+            let mut hasher = DefaultHasher::new();
+            executed.hash(&mut hasher);
+            hasher.finish()
+        };
+
+        Self {
+            params,
+            statements: executed,
+            id,
+        }
+    }
+
+    /// Parameters set by the setup.
+    pub fn params(&self) -> &Parameters {
+        &self.params
+    }
+
+    /// Statements executed on server connections.
+    pub fn statements(&self) -> &[String] {
+        &self.statements
+    }
+
+    /// Identifier of the statements, zero if there are none.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// No setup needed.
+    pub fn is_empty(&self) -> bool {
+        self.params.is_empty() && self.statements.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::net::parameter::ParameterValue;
+
+    #[test]
+    fn test_session_setup() {
+        let statements = [
+            "SET search_path TO app, public",
+            "SET jit = off",
+            "SET LOCAL statement_timeout TO 1000",
+            "SELECT set_config('app.tenant', '1', false)",
+            "SET work_mem TO DEFAULT",
+        ]
+        .map(String::from);
+        let setup = SessionSetup::new(&statements);
+
+        assert_eq!(
+            setup.params().get("search_path"),
+            Some(&ParameterValue::Tuple(vec!["app".into(), "public".into()]))
+        );
+        assert_eq!(
+            setup.params().get("jit"),
+            Some(&ParameterValue::String("off".into()))
+        );
+        assert_eq!(setup.params().len(), 2);
+        assert_eq!(setup.statements(), &statements[2..]);
+        assert_ne!(setup.id(), 0);
+        assert!(!setup.is_empty());
+
+        let setup = SessionSetup::new(&statements[..2]);
+        assert_eq!(setup.id(), 0);
+        assert!(setup.statements().is_empty());
+
+        assert!(SessionSetup::new(&[] as &[String]).is_empty());
+    }
+}
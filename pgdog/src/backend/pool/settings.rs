@@ -0,0 +1,318 @@
+//! Server settings, captured when the pool creates its first connection.
+//!
+//! They let us answer `SHOW` without checking out a connection. Settings changed
+//! by the client are taken from its parameters, as long as we know how Postgres
+//! would display them. Otherwise, only the server can answer.
+
+use std::collections::HashMap;
+
+use crate::backend::{Error, Server};
+use crate::net::messages::DataRow;
+use crate::net::parameter::ParameterValue;
+use crate::net::Parameters;
+
+/// String settings Postgres changes before displaying them,
+/// e.g. `SET datestyle TO 'iso'` shows `ISO, MDY`.
+const CANONICALIZED: &[&str] = &[
+    "client_encoding",
+    "datestyle",
+    "default_text_search_config",
+    "log_timezone",
+    "search_path",
+    "temp_tablespaces",
+    "timezone",
+];
+
+/// Settings that show their `default_` setting outside of transactions.
+const TRANSACTION: &[&str] = &[
+    "transaction_deferrable",
+    "transaction_isolation",
+    "transaction_read_only",
+];
+
+/// Setting, as shown by `SHOW ALL`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Setting {
+    pub name: String,
+    pub setting: String,
+    pub description: String,
+}
+
+impl From<DataRow> for Setting {
+    fn from(value: DataRow) -> Self {
+        Self {
+            name: value.get_text(0).unwrap_or_default(),
+            setting: value.get_text(1).unwrap_or_default(),
+            description: value.get_text(2).unwrap_or_default(),
+        }
+    }
+}
+
+/// How values set by clients are displayed.
+#[derive(Debug, Clone, PartialEq)]
+enum Kind {
+    Bool,
+    /// Integer without a unit.
+    Integer,
+    Enum(Vec<String>),
+    String,
+    /// Only the server knows.
+    Other,
+}
+
+impl Kind {
+    /// Value as Postgres would display it, if we know how.
+    fn display(&self, value: &str) -> Option<String> {
+        match self {
+            Self::Bool => match value.trim().to_lowercase().as_str() {
+                "on" | "true" | "yes" | "t" | "y" | "1" => Some("on".into()),
+                "off" | "false" | "no" | "f" | "n" | "0" => Some("off".into()),
+                _ => None,
+            },
+            Self::Integer => value.trim().parse::<i64>().ok().map(|v| v.to_string()),
+            Self::Enum(values) => {
+                let value = value.trim().to_lowercase();
+                values.contains(&value).then_some(value)
+            }
+            Self::String => Some(value.to_string()),
+            Self::Other => None,
+        }
+    }
+}
+
+/// Row from `pg_settings`.
+struct SettingKind {
+    name: String,
+    kind: Kind,
+}
+
+impl From<DataRow> for SettingKind {
+    fn from(value: DataRow) -> Self {
+        let name = value.get_text(0).unwrap_or_default();
+        let vartype = value.get_text(1).unwrap_or_default();
+        let unit = value.get_text(2).unwrap_or_default();
+        let enumvals = value.get_text(3).unwrap_or_default();
+
+        let kind = match vartype.as_str() {
+            "bool" => Kind::Bool,
+            "integer" if unit.is_empty() => Kind::Integer,
+            "enum" => Kind::Enum(enumvals.split(',').map(|v| v.to_lowercase()).collect()),
+            "string" if !CANONICALIZED.contains(&name.to_lowercase().as_str()) => Kind::String,
+            _ => Kind::Other,
+        };
+
+        Self { name, kind }
+    }
+}
+
+/// Server settings.
+#[derive(Debug, Default)]
+pub struct Settings {
+    /// In `SHOW ALL` order.
+    settings: Vec<(Setting, Kind)>,
+    /// Position of each setting, by lowercase name.
+    index: HashMap<String, usize>,
+}
+
+impl Settings {
+    /// Fetch settings from a new server connection.
+    pub(super) async fn load(server: &mut Server) -> Result<Self, Error> {
+        let settings: Vec<Setting> = server.fetch_all("SHOW ALL").await?;
+        let kinds: Vec<SettingKind> = server
+            .fetch_all(
+                "SELECT name, vartype, coalesce(unit, ''), \
+                 coalesce(array_to_string(enumvals, ','), '') FROM pg_settings",
+            )
+            .await?;
+        let kinds = kinds
+            .into_iter()
+            .map(|kind| (kind.name, kind.kind))
+            .collect::<HashMap<_, _>>();
+
+        Ok(Self::new(
+            settings
+                .into_iter()
+                .map(|setting| {
+                    let kind = kinds.get(&setting.name).cloned().unwrap_or(Kind::Other);
+                    (setting, kind)
+                })
+                .collect(),
+        ))
+    }
+
+    fn new(settings: Vec<(Setting, Kind)>) -> Self {
+        let index = settings
+            .iter()
+            .enumerate()
+            .map(|(i, (setting, _))| (setting.name.to_lowercase(), i))
+            .collect();
+
+        Self { settings, index }
+    }
+
+    /// Number of settings.
+    pub fn len(&self) -> usize {
+        self.settings.len()
+    }
+
+    /// No settings.
+    pub fn is_empty(&self) -> bool {
+        self.settings.is_empty()
+    }
+
+    /// What `SHOW <name>` returns to a client with these parameters,
+    /// outside of a transaction. The name is spelled like Postgres does.
+    /// Returns `None` if only the server can answer.
+    pub fn show(&self, name: &str, params: &Parameters) -> Option<Setting> {
+        self.resolve(&name.to_lowercase(), &params.tracked())
+    }
+
+    /// What `SHOW ALL` returns to a client with these parameters,
+    /// outside of a transaction.
+    pub fn show_all(&self, params: &Parameters) -> Option<Vec<Setting>> {
+        let params = params.tracked();
+
+        self.settings
+            .iter()
+            .map(|(setting, _)| self.resolve(&setting.name.to_lowercase(), &params))
+            .collect()
+    }
+
+    fn get(&self, name: &str) -> Option<&(Setting, Kind)> {
+        self.index.get(name).map(|i| &self.settings[*i])
+    }
+
+    /// Resolve a setting using parameters synced to the server.
+    fn resolve(&self, name: &str, params: &Parameters) -> Option<Setting> {
+        let Some((setting, kind)) = self.get(name) else {
+            // Custom settings are shown the way they were set.
+            return match params.get(name) {
+                Some(ParameterValue::String(value)) if name.contains('.') => Some(Setting {
+                    name: name.to_string(),
+                    setting: value.clone(),
+                    description: String::new(),
+                }),
+                _ => None,
+            };
+        };
+
+        let (value, kind) = match params.get(name) {
+            None if TRANSACTION.contains(&name) => {
+                let default = format!("default_{}", name);
+                let kind = self.get(&default).map(|(_, kind)| kind).unwrap_or(kind);
+                (params.get(&default), kind)
+            }
+            value => (value, kind),
+        };
+
+        let display = match value {
+            None => setting.setting.clone(),
+            Some(ParameterValue::String(value)) => kind.display(value)?,
+            Some(ParameterValue::Tuple(_)) => return None,
+        };
+
+        Some(Setting {
+            setting: display,
+            ..setting.clone()
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn settings() -> Settings {
+        Settings::new(
+            [
+                ("DateStyle", "ISO, MDY", Kind::Other),
+                (
+                    "default_transaction_isolation",
+                    "read committed",
+                    Kind::Enum(vec![
+                        "serializable".into(),
+                        "repeatable read".into(),
+                        "read committed".into(),
+                        "read uncommitted".into(),
+                    ]),
+                ),
+                ("lock_timeout", "0", Kind::Other),
+                ("max_connections", "100", Kind::Integer),
+                ("standard_conforming_strings", "on", Kind::Bool),
+                ("transaction_isolation", "read committed", Kind::Other),
+                ("work_mem", "4MB", Kind::Other),
+            ]
+            .into_iter()
+            .map(|(name, setting, kind)| {
+                (
+                    Setting {
+                        name: name.into(),
+                        setting: setting.into(),
+                        description: format!("{} description", name),
+                    },
+                    kind,
+                )
+            })
+            .collect(),
+        )
+    }
+
+    fn show(settings: &Settings, name: &str, params: &Parameters) -> Option<(String, String)> {
+        settings
+            .show(name, params)
+            .map(|setting| (setting.name, setting.setting))
+    }
+
+    #[test]
+    fn test_show() {
+        let settings = settings();
+        let mut params = Parameters::default();
+
+        // Server defaults.
+        assert_eq!(
+            show(&settings, "datestyle", &params),
+            Some(("DateStyle".into(), "ISO, MDY".into()))
+        );
+        assert_eq!(
+            show(&settings, "transaction_isolation", &params),
+            Some(("transaction_isolation".into(), "read committed".into()))
+        );
+        assert_eq!(show(&settings, "unknown", &params), None);
+
+        // Client overrides.
+        params.insert("standard_conforming_strings", "false");
+        params.insert("max_connections", " 0100");
+        params.insert("default_transaction_isolation", "SERIALIZABLE");
+        params.insert("myapp.tenant", "1");
+        assert_eq!(
+            show(&settings, "standard_conforming_strings", &params),
+            Some(("standard_conforming_strings".into(), "off".into()))
+        );
+        assert_eq!(
+            show(&settings, "max_connections", &params),
+            Some(("max_connections".into(), "100".into()))
+        );
+        assert_eq!(
+            show(&settings, "transaction_isolation", &params),
+            Some(("transaction_isolation".into(), "serializable".into()))
+        );
+        assert_eq!(
+            show(&settings, "myapp.tenant", &params),
+            Some(("myapp.tenant".into(), "1".into()))
+        );
+
+        // Only the server knows how these are displayed.
+        params.insert("work_mem", "4096");
+        params.insert("datestyle", "iso");
+        assert_eq!(show(&settings, "work_mem", &params), None);
+        assert_eq!(show(&settings, "DateStyle", &params), None);
+        assert!(settings.show_all(&params).is_none());
+
+        params.remove("work_mem");
+        params.remove("datestyle");
+        let all = settings.show_all(&params).unwrap();
+        assert_eq!(all.len(), settings.len());
+        assert_eq!(all[3].setting, "100");
+        assert_eq!(all[3].description, "max_connections description");
+    }
+}
@@ -9,12 +9,14 @@ use tokio::{join, select, spawn, sync::Notify};
 use tracing::{debug, error};
 
 use crate::backend::PubSubListener;
-use crate::config::{config, LoadBalancingStrategy, ReadWriteSplit, Role};
+use crate::config::{
+    config, LoadBalancingStrategy, ReadConsistency, ReadWriteSplit, ReadYourWrites, Role,
+};
 use crate::net::messages::BackendKeyData;
 use crate::net::NotificationResponse;
 
 use super::inner::ReplicaLag;
-use super::{Error, Guard, Pool, PoolConfig, Replicas, Request};
+use super::{Error, Guard, Pool, PoolConfig, ReadConsistencyStats, Replicas, Request};
 
 // -------------------------------------------------------------------------------------------------
 // ----- Public Interface --------------------------------------------------------------------------
@@ -48,6 +50,9 @@ impl Shard {
     /// Get connection to one of the replica databases, using the configured
     /// load balancing algorithm.
     pub async fn replica(&self, request: &Request) -> Result<Guard, Error> {
+        let stats = ReadConsistencyStats::get();
+        stats.read(request.read_consistency);
+
         if self.replicas.is_empty() {
             self.primary
                 .as_ref()
@@ -57,6 +62,27 @@ impl Shard {
         } else {
             use ReadWriteSplit::*;
 
+            // Client's writes didn't reach any replica yet.
+            if let Some(lsn) = request.replay_lsn {
+                if self.primary.is_some() && !self.replicas.replayed(lsn) {
+                    return self.primary(request).await;
+                }
+            }
+
+            if self.primary.is_some() {
+                match request.read_consistency {
+                    ReadConsistency::Any => (),
+                    ReadConsistency::Bounded(bound) => {
+                        // No replica is known to be fresh enough.
+                        if !self.replicas.lag_within(bound.duration()) {
+                            stats.primary_fallback(request.read_consistency);
+                            return self.primary(request).await;
+                        }
+                    }
+                    ReadConsistency::Strong => return self.primary(request).await,
+                }
+            }
+
             let primary = match self.rw_split {
                 IncludePrimary => &self.primary,
                 ExcludePrimary => &None,
@@ -161,6 +187,14 @@ impl Shard {
         !self.replicas.is_empty()
     }
 
+    /// Primary is configured and banned.
+    pub fn primary_banned(&self) -> bool {
+        self.primary
+            .as_ref()
+            .map(|primary| primary.banned())
+            .unwrap_or(false)
+    }
+
     pub async fn cancel(&self, id: &BackendKeyData) -> Result<(), super::super::Error> {
         if let Some(ref primary) = self.primary {
             primary.cancel(id).await?;
@@ -193,6 +227,12 @@ impl Shard {
         pools
     }
 
+    /// Close idle connections above the minimum pool size in every pool.
+    /// Returns how many were closed.
+    pub fn shrink(&self) -> usize {
+        self.pools().iter().map(|pool| pool.shrink()).sum()
+    }
+
     /// Shutdown every pool.
     pub fn shutdown(&self) {
         self.comms.shutdown.notify_waiters();
@@ -274,10 +314,18 @@ impl Default for ShardComms {
 // -------------------------------------------------------------------------------------------------
 // ----- Monitoring --------------------------------------------------------------------------------
 
+/// How often replay LSNs are fetched for read-your-writes routing.
+const REPLAY_LSN_INTERVAL: Duration = Duration::from_millis(100);
+
 struct ShardMonitor {}
 
 impl ShardMonitor {
     pub fn run(shard: &Shard) {
+        if config().config.general.read_your_writes == ReadYourWrites::Lsn && shard.has_replicas() {
+            let shard = shard.clone();
+            spawn(async move { Self::monitor_replay_lsn(shard).await });
+        }
+
         let shard = shard.clone();
         spawn(async move { Self::monitor_replicas(shard).await });
     }
@@ -307,6 +355,29 @@ impl ShardMonitor {
         debug!("replica monitoring stopped");
     }
 
+    async fn monitor_replay_lsn(shard: Shard) {
+        let mut tick = interval(REPLAY_LSN_INTERVAL);
+        let comms = shard.comms();
+
+        loop {
+            select! {
+                _ = tick.tick() => {
+                    for replica in shard.replicas.pools() {
+                        if replica.banned() {
+                            continue;
+                        }
+
+                        match replica.wal_replay_lsn().await {
+                            Ok(lsn) => replica.set_replay_lsn(lsn),
+                            Err(e) => debug!("replica {} LSN query failed: {}", replica.id(), e),
+                        }
+                    }
+                }
+                _ = comms.shutdown.notified() => break,
+            }
+        }
+    }
+
     async fn process_replicas(shard: &Shard, max_age: Duration) {
         let Some(primary) = shard.primary.as_ref() else {
             return;
@@ -362,6 +433,7 @@ impl ShardMonitor {
             }
         };
 
+        replica.set_replay_lsn(replay_lsn);
         let bytes_behind = primary_lsn.saturating_sub(replay_lsn);
 
         let mut lag = ReplicaLag::Bytes(bytes_behind);
@@ -434,6 +506,7 @@ mod test {
     use std::collections::BTreeSet;
 
     use crate::backend::pool::{Address, Config};
+    use crate::config::HumanDuration;
 
     use super::*;
 
@@ -444,11 +517,13 @@ mod test {
         let primary = &Some(PoolConfig {
             address: Address::new_test(),
             config: Config::default(),
+            ..Default::default()
         });
 
         let replicas = &[PoolConfig {
             address: Address::new_test(),
             config: Config::default(),
+            ..Default::default()
         }];
 
         let shard = Shard::new(
@@ -476,11 +551,13 @@ mod test {
         let primary = &Some(PoolConfig {
             address: Address::new_test(),
             config: Config::default(),
+            ..Default::default()
         });
 
         let replicas = &[PoolConfig {
             address: Address::new_test(),
             config: Config::default(),
+            ..Default::default()
         }];
 
         let shard = Shard::new(
@@ -501,6 +578,137 @@ mod test {
 
         assert_eq!(ids.len(), 2);
     }
+
+    #[tokio::test]
+    async fn test_replay_lsn() {
+        crate::logger();
+
+        let primary = &Some(PoolConfig {
+            address: Address::new_test(),
+            config: Config::default(),
+            ..Default::default()
+        });
+
+        let replicas = &[PoolConfig {
+            address: Address::new_test(),
+            config: Config::default(),
+            ..Default::default()
+        }];
+
+        let shard = Shard::new(
+            primary,
+            replicas,
+            LoadBalancingStrategy::Random,
+            ReadWriteSplit::ExcludePrimary,
+        );
+        shard.launch();
+
+        let primary_id = shard.primary.as_ref().unwrap().id();
+        let replica = shard.replicas.pools[0].clone();
+        let request = Request {
+            replay_lsn: Some(200),
+            ..Default::default()
+        };
+
+        // Replica is lagging, e.g. with pg_wal_replay_pause().
+        replica.set_replay_lsn(100);
+        for _ in 0..5 {
+            let conn = shard.replica(&request).await.unwrap();
+            assert_eq!(conn.pool.id(), primary_id);
+        }
+
+        // Replay caught up.
+        replica.set_replay_lsn(300);
+        for _ in 0..5 {
+            let conn = shard.replica(&request).await.unwrap();
+            assert_eq!(conn.pool.id(), replica.id());
+        }
+
+        // Clients that didn't write can use any replica.
+        replica.set_replay_lsn(100);
+        let conn = shard.replica(&Request::default()).await.unwrap();
+        assert_eq!(conn.pool.id(), replica.id());
+
+        shard.shutdown();
+    }
+
+    #[tokio::test]
+    async fn test_read_consistency() {
+        crate::logger();
+
+        let pool_config = || PoolConfig {
+            address: Address::new_test(),
+            config: Config::default(),
+            ..Default::default()
+        };
+
+        let shard = Shard::new(
+            &Some(pool_config()),
+            &[pool_config(), pool_config()],
+            LoadBalancingStrategy::Random,
+            ReadWriteSplit::ExcludePrimary,
+        );
+        shard.launch();
+
+        let primary_id = shard.primary.as_ref().unwrap().id();
+        let fresh = shard.replicas.pools[0].clone();
+        let stale = shard.replicas.pools[1].clone();
+        fresh.set_replica_lag(ReplicaLag::Duration(Duration::from_secs(1)));
+        stale.set_replica_lag(ReplicaLag::Duration(Duration::from_secs(60)));
+
+        let request = |read_consistency| Request {
+            read_consistency,
+            ..Default::default()
+        };
+        let stats = || {
+            ReadConsistencyStats::get()
+                .stats()
+                .into_iter()
+                .find(|(tier, _)| *tier == "bounded")
+                .unwrap()
+                .1
+        };
+        let before = stats();
+
+        // Any replica.
+        let mut ids = BTreeSet::new();
+        for _ in 0..25 {
+            let conn = shard.replica(&request(ReadConsistency::Any)).await.unwrap();
+            ids.insert(conn.pool.id());
+        }
+        assert_eq!(ids, BTreeSet::from([fresh.id(), stale.id()]));
+
+        // Only replicas under the bound.
+        let bounded = request(ReadConsistency::Bounded(HumanDuration::from_secs(5)));
+        for _ in 0..10 {
+            let conn = shard.replica(&bounded).await.unwrap();
+            assert_eq!(conn.pool.id(), fresh.id());
+        }
+
+        // Every replica is too far behind, or we don't know.
+        fresh.set_replica_lag(ReplicaLag::Duration(Duration::from_secs(10)));
+        stale.set_replica_lag(ReplicaLag::Unknown);
+        for _ in 0..5 {
+            let conn = shard.replica(&bounded).await.unwrap();
+            assert_eq!(conn.pool.id(), primary_id);
+        }
+
+        let after = stats();
+        assert!(after.reads - before.reads >= 15);
+        assert!(after.primary_fallbacks - before.primary_fallbacks >= 5);
+
+        // Always the primary.
+        fresh.set_replica_lag(ReplicaLag::Duration(Duration::ZERO));
+        for _ in 0..5 {
+            let conn = shard
+                .replica(&request(ReadConsistency::Strong))
+                .await
+                .unwrap();
+            assert_eq!(conn.pool.id(), primary_id);
+        }
+
+        shard.shutdown();
+    }
 }
 
 // -------------------------------------------------------------------------------------------------
@@ -0,0 +1,86 @@
+//! Shard skew of cross-shard queries.
+//!
+//! Skew is the share of rows returned by the busiest shard: `1 / shards`
+//! means every shard returned the same number of rows, `1.0` means one shard
+//! returned all of them.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Upper bounds of the skew histogram buckets.
+pub const BUCKETS: [f64; 6] = [0.1, 0.25, 0.5, 0.75, 0.9, 1.0];
+
+/// Skew histogram of one cluster.
+#[derive(Debug, Default)]
+pub struct ShardSkew {
+    /// Statements by skew. Skew is never above 1.0, so there is no overflow bucket.
+    buckets: [AtomicU64; BUCKETS.len()],
+    /// Sum of all skews, in millionths.
+    sum: AtomicU64,
+    count: AtomicU64,
+}
+
+/// Snapshot of the skew histogram.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ShardSkewStats {
+    /// Cumulative statement count for each of [`BUCKETS`].
+    pub buckets: Vec<u64>,
+    pub sum: f64,
+    pub count: u64,
+}
+
+impl ShardSkew {
+    /// Record a statement given the rows returned by the busiest shard
+    /// and by all shards together.
+    pub fn record(&self, max: u64, total: u64) {
+        if total == 0 {
+            return;
+        }
+
+        let skew = max as f64 / total as f64;
+        let bucket = BUCKETS
+            .iter()
+            .position(|le| skew <= *le)
+            .unwrap_or(BUCKETS.len() - 1);
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.sum
+            .fetch_add((skew * 1_000_000.0) as u64, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Get the histogram.
+    pub fn stats(&self) -> ShardSkewStats {
+        let mut cumulative = 0;
+        let buckets = self
+            .buckets
+            .iter()
+            .map(|bucket| {
+                cumulative += bucket.load(Ordering::Relaxed);
+                cumulative
+            })
+            .collect();
+
+        ShardSkewStats {
+            buckets,
+            sum: self.sum.load(Ordering::Relaxed) as f64 / 1_000_000.0,
+            count: self.count.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_shard_skew() {
+        let skew = ShardSkew::default();
+        skew.record(5, 10);
+        skew.record(10, 10);
+        skew.record(0, 0); // No rows, not a skew.
+
+        let stats = skew.stats();
+        assert_eq!(stats.count, 2);
+        assert_eq!(stats.buckets, vec![0, 0, 1, 1, 1, 2]);
+        assert_eq!(stats.sum, 1.5);
+    }
+}
@@ -0,0 +1,106 @@
+//! Slow start for pools that just came online.
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Smallest share of traffic a pool receives at the start of the window.
+const MIN_WEIGHT: f64 = 0.1;
+
+/// Slow start ramp.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct SlowStart {
+    /// When the pool became healthy.
+    started_at: Option<Instant>,
+}
+
+impl SlowStart {
+    /// Restart the ramp, e.g. after the pool was unbanned.
+    pub(super) fn start(&mut self, now: Instant) {
+        self.started_at = Some(now);
+    }
+
+    /// Start the ramp only if it hasn't been started before.
+    pub(super) fn start_if_new(&mut self, now: Instant) {
+        if self.started_at.is_none() {
+            self.start(now);
+        }
+    }
+
+    /// Time left in the slow start window.
+    pub(super) fn remaining(&self, now: Instant, window: Duration) -> Duration {
+        match self.started_at {
+            Some(started_at) => window.saturating_sub(now.duration_since(started_at)),
+            None => Duration::ZERO,
+        }
+    }
+
+    /// Share of traffic the pool should be receiving, between `MIN_WEIGHT` and 1.0.
+    pub(super) fn weight(&self, now: Instant, window: Duration) -> f64 {
+        let remaining = self.remaining(now, window);
+        if remaining.is_zero() {
+            return 1.0;
+        }
+
+        let elapsed = 1.0 - remaining.as_secs_f64() / window.as_secs_f64();
+        MIN_WEIGHT + (1.0 - MIN_WEIGHT) * elapsed
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_ramp() {
+        let window = Duration::from_secs(60);
+        let now = Instant::now();
+        let mut slow_start = SlowStart::default();
+
+        // Not started, full weight.
+        assert_eq!(slow_start.weight(now, window), 1.0);
+        assert_eq!(slow_start.remaining(now, window), Duration::ZERO);
+
+        slow_start.start(now);
+        assert_eq!(slow_start.weight(now, window), MIN_WEIGHT);
+        assert_eq!(slow_start.remaining(now, window), window);
+
+        let halfway = now + Duration::from_secs(30);
+        let weight = slow_start.weight(halfway, window);
+        assert!((weight - 0.55).abs() < 1e-9);
+        assert_eq!(
+            slow_start.remaining(halfway, window),
+            Duration::from_secs(30)
+        );
+
+        let done = now + window;
+        assert_eq!(slow_start.weight(done, window), 1.0);
+        assert_eq!(
+            slow_start.weight(done + Duration::from_secs(1), window),
+            1.0
+        );
+    }
+
+    #[test]
+    fn test_disabled() {
+        let now = Instant::now();
+        let mut slow_start = SlowStart::default();
+        slow_start.start(now);
+        assert_eq!(slow_start.weight(now, Duration::ZERO), 1.0);
+    }
+
+    #[test]
+    fn test_restart() {
+        let window = Duration::from_secs(60);
+        let now = Instant::now();
+        let mut slow_start = SlowStart::default();
+        slow_start.start(now);
+
+        let later = now + Duration::from_secs(45);
+        slow_start.start_if_new(later);
+        assert_eq!(slow_start.remaining(later, window), Duration::from_secs(15));
+
+        // Banned and unbanned again.
+        slow_start.start(later);
+        assert_eq!(slow_start.remaining(later, window), window);
+        assert_eq!(slow_start.weight(later, window), MIN_WEIGHT);
+    }
+}
@@ -3,13 +3,20 @@ use std::time::Duration;
 use crate::config::PoolerMode;
 use tokio::time::Instant;
 
-use super::{inner::ReplicaLag, Ban, Config, Pool, Stats};
+use super::{
+    inner::ReplicaLag, Ban, Config, HealthcheckFailure, HealthcheckFailures, LoadShedding, Pool,
+    Stats, Tag,
+};
 
 /// Pool state.
 #[derive(Debug)]
 pub struct State {
-    /// Number of connections checked out.
+    /// Number of connections checked out, including locked and draining ones.
     pub checked_out: usize,
+    /// Number of checked out connections locked to their clients.
+    pub locked: usize,
+    /// Number of checked out connections being cleaned up before check-in.
+    pub draining: usize,
     /// Number of idle connections.
     pub idle: usize,
     /// Total number of connections managed by the pool.
@@ -38,10 +45,22 @@ pub struct State {
     pub stats: Stats,
     /// Max wait.
     pub maxwait: Duration,
+    /// Mean wait of clients in line.
+    pub avgwait: Duration,
     /// Pool mode
     pub pooler_mode: PoolerMode,
     /// Lag
     pub replica_lag: ReplicaLag,
+    /// Time left until the pool receives its full share of traffic.
+    pub slow_start_remaining: Duration,
+    /// Load shedding.
+    pub load_shedding: LoadShedding,
+    /// Average number of prepared statements on idle connections.
+    pub avg_prepared_statements: f64,
+    /// Why the last healthcheck failed.
+    pub last_failure_reason: Option<HealthcheckFailure>,
+    /// Healthcheck failures of each class.
+    pub healthcheck_failures: HealthcheckFailures,
 }
 
 impl State {
@@ -51,6 +70,8 @@ impl State {
 
         State {
             checked_out: guard.checked_out(),
+            locked: guard.tagged(Tag::Locked),
+            draining: guard.tagged(Tag::Draining),
             idle: guard.idle(),
             total: guard.total(),
             online: guard.online,
@@ -70,8 +91,20 @@ impl State {
                 .next()
                 .map(|req| now.duration_since(req.request.created_at))
                 .unwrap_or(Duration::ZERO),
+            avgwait: guard
+                .waiting
+                .iter()
+                .map(|req| now.duration_since(req.request.created_at))
+                .sum::<Duration>()
+                .checked_div(guard.waiting.len() as u32)
+                .unwrap_or(Duration::ZERO),
             pooler_mode: guard.config().pooler_mode,
             replica_lag: guard.replica_lag,
+            slow_start_remaining: guard.slow_start.remaining(now, guard.config.slow_start),
+            load_shedding: guard.load_shedder.state(),
+            avg_prepared_statements: guard.avg_prepared_statements(),
+            last_failure_reason: guard.last_failure_reason,
+            healthcheck_failures: guard.healthcheck_failures,
         }
     }
 }
@@ -2,13 +2,15 @@
 
 use crate::backend::stats::Counts as BackendCounts;
 
+use serde::Serialize;
+
 use std::{
     iter::Sum,
     ops::{Add, Div, Sub},
     time::Duration,
 };
 
-#[derive(Debug, Clone, Default, Copy)]
+#[derive(Debug, Clone, Default, Copy, Serialize)]
 pub struct Counts {
     pub xact_count: usize,
     pub query_count: usize,
@@ -23,6 +25,7 @@ pub struct Counts {
     pub rollbacks: usize,
     pub healthchecks: usize,
     pub close: usize,
+    pub prewarm_count: usize,
 }
 
 impl Sub for Counts {
@@ -45,6 +48,7 @@ impl Sub for Counts {
             rollbacks: self.rollbacks.saturating_sub(rhs.rollbacks),
             healthchecks: self.healthchecks.saturating_add(rhs.healthchecks),
             close: self.close.saturating_add(rhs.close),
+            prewarm_count: self.prewarm_count.saturating_sub(rhs.prewarm_count),
         }
     }
 }
@@ -67,6 +71,7 @@ impl Div<usize> for Counts {
             rollbacks: self.rollbacks.saturating_div(rhs),
             healthchecks: self.healthchecks.saturating_div(rhs),
             close: self.close.saturating_div(rhs),
+            prewarm_count: self.prewarm_count.saturating_div(rhs),
         }
     }
 }
@@ -89,6 +94,7 @@ impl Add<BackendCounts> for Counts {
             rollbacks: self.rollbacks + rhs.rollbacks,
             healthchecks: self.healthchecks + rhs.healthchecks,
             close: self.close + rhs.close,
+            prewarm_count: self.prewarm_count + rhs.prewarm,
         }
     }
 }
@@ -124,14 +130,16 @@ impl Add for Counts {
             rollbacks: self.rollbacks.saturating_add(rhs.rollbacks),
             healthchecks: self.healthchecks.saturating_add(rhs.healthchecks),
             close: self.close.saturating_add(rhs.close),
+            prewarm_count: self.prewarm_count.saturating_add(rhs.prewarm_count),
         }
     }
 }
 
-#[derive(Debug, Clone, Default, Copy)]
+#[derive(Debug, Clone, Default, Copy, Serialize)]
 pub struct Stats {
     // Total counts.
     pub counts: Counts,
+    #[serde(skip)]
     last_counts: Counts,
     // Average counts.
     pub averages: Counts,
@@ -0,0 +1,30 @@
+//! What a pooled connection is doing right now.
+
+/// State of a connection managed by the pool.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Tag {
+    /// In the idle list, can be given to a client,
+    /// healthchecked or closed by maintenance.
+    #[default]
+    Idle,
+    /// Checked out by a client (or the pool itself, for a healthcheck).
+    CheckedOut,
+    /// Locked to a client until it disconnects, e.g. it's holding
+    /// an advisory lock or the session was changed.
+    Locked,
+    /// Returned by a client and being cleaned up before check-in.
+    Draining,
+}
+
+impl std::fmt::Display for Tag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let tag = match self {
+            Tag::Idle => "idle",
+            Tag::CheckedOut => "checked_out",
+            Tag::Locked => "locked",
+            Tag::Draining => "draining",
+        };
+
+        write!(f, "{}", tag)
+    }
+}
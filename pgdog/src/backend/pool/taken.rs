@@ -2,12 +2,13 @@ use fnv::FnvHashMap as HashMap;
 
 use crate::net::BackendKeyData;
 
-use super::Mapping;
+use super::{Mapping, Tag};
 
 #[derive(Default, Clone, Debug)]
 pub(super) struct Taken {
     client_server: HashMap<BackendKeyData, BackendKeyData>,
     server_client: HashMap<BackendKeyData, BackendKeyData>,
+    tags: HashMap<BackendKeyData, Tag>,
 }
 
 impl Taken {
@@ -15,6 +16,7 @@ impl Taken {
     pub(super) fn take(&mut self, mapping: &Mapping) {
         self.client_server.insert(mapping.client, mapping.server);
         self.server_client.insert(mapping.server, mapping.client);
+        self.tags.insert(mapping.server, Tag::CheckedOut);
     }
 
     #[inline]
@@ -23,6 +25,22 @@ impl Taken {
         if let Some(client) = client {
             self.client_server.remove(&client);
         }
+        self.tags.remove(server);
+    }
+
+    /// Change the tag of a checked out connection.
+    /// Connections that aren't checked out are ignored.
+    #[inline]
+    pub(super) fn tag(&mut self, server: &BackendKeyData, tag: Tag) {
+        if let Some(existing) = self.tags.get_mut(server) {
+            *existing = tag;
+        }
+    }
+
+    /// Number of checked out connections with this tag.
+    #[inline]
+    pub(super) fn tagged(&self, tag: Tag) -> usize {
+        self.tags.values().filter(|t| **t == tag).count()
     }
 
     #[inline]
@@ -49,5 +67,6 @@ impl Taken {
     pub(super) fn clear(&mut self) {
         self.client_server.clear();
         self.server_client.clear();
+        self.tags.clear();
     }
 }
@@ -10,13 +10,15 @@ use tokio::task::yield_now;
 use tokio::time::{sleep, timeout};
 use tokio_util::task::TaskTracker;
 
+use crate::config::Role;
 use crate::net::ProtocolMessage;
-use crate::net::{Parse, Protocol, Query, Sync};
+use crate::net::{Parameter, Parameters, Parse, Protocol, Query, Sync};
 use crate::state::State;
 
 use super::*;
 
 mod replica;
+mod replication;
 
 pub fn pool() -> Pool {
     let config = Config {
@@ -34,6 +36,7 @@ pub fn pool() -> Pool {
             password: "pgdog".into(),
         },
         config,
+        ..Default::default()
     });
     pool.launch();
     pool
@@ -56,6 +59,7 @@ pub fn pool_with_prepared_capacity(capacity: usize) -> Pool {
             password: "pgdog".into(),
         },
         config,
+        ..Default::default()
     });
     pool.launch();
     pool
@@ -159,6 +163,131 @@ async fn test_bans() {
     assert!(conn.is_err());
 }
 
+#[tokio::test]
+async fn test_checkout_statement_timeout() {
+    let pool = pool();
+    pool.update_config(Config {
+        checkout_timeout: Duration::from_millis(5_000),
+        max: 1,
+        ..Default::default()
+    });
+
+    let _hold = pool.get(&Request::default()).await.unwrap();
+
+    let mut request = Request::default();
+    request.deadline = Some(request.created_at + Duration::from_millis(100));
+
+    let start = Instant::now();
+    let err = pool.get(&request).await.expect_err("statement timeout");
+    assert_eq!(err, Error::StatementTimeout);
+    assert!(start.elapsed() < Duration::from_millis(1_000));
+
+    // Client's deadline doesn't ban the pool.
+    assert!(!pool.banned());
+    assert_eq!(pool.lock().waiting.len(), 0);
+}
+
+#[tokio::test]
+async fn test_cancel_waiting() {
+    let pool = pool();
+    pool.update_config(Config {
+        checkout_timeout: Duration::from_millis(5_000),
+        max: 1,
+        ..Default::default()
+    });
+
+    let hold = pool.get(&Request::default()).await.unwrap();
+
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let mut clients = vec![];
+    for i in 0..5 {
+        let waiting = pool.clone();
+        let tx = tx.clone();
+        clients.push(spawn(async move {
+            let conn = waiting.get(&Request::default()).await.unwrap();
+            tx.send(i).unwrap();
+            drop(conn);
+        }));
+
+        // Keep them in line in order.
+        while pool.lock().waiting.len() <= i {
+            yield_now().await;
+        }
+    }
+
+    // Clients in the middle disconnect.
+    for i in [1, 3] {
+        clients[i].abort();
+        assert!((&mut clients[i]).await.unwrap_err().is_cancelled());
+    }
+    assert_eq!(pool.lock().waiting.len(), 3);
+    assert_eq!(pool.state().waiting, 3);
+
+    let start = Instant::now();
+    drop(hold);
+
+    let mut served = vec![];
+    for _ in 0..3 {
+        served.push(
+            timeout(Duration::from_secs(1), rx.recv())
+                .await
+                .unwrap()
+                .unwrap(),
+        );
+    }
+    assert_eq!(served, vec![0, 2, 4]);
+    assert!(start.elapsed() < Duration::from_secs(1));
+    assert_eq!(pool.lock().waiting.len(), 0);
+}
+
+#[tokio::test]
+async fn test_checkout_fifo() {
+    let pool = pool();
+    pool.update_config(Config {
+        checkout_timeout: Duration::from_secs(10),
+        max: 2,
+        min: 2,
+        ..Default::default()
+    });
+
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let tracker = TaskTracker::new();
+    for i in 0..50 {
+        let pool = pool.clone();
+        let tx = tx.clone();
+        tracker.spawn(async move {
+            let _conn = pool.get(&Request::default()).await.unwrap();
+            tx.send(i).unwrap();
+            // Mix of short and long transactions.
+            let duration = if i % 5 == 0 { 10 } else { 1 };
+            sleep(Duration::from_millis(duration)).await;
+        });
+        sleep(Duration::from_millis(1)).await;
+    }
+    drop(tx);
+
+    tracker.close();
+    tracker.wait().await;
+
+    let mut served = vec![];
+    while let Some(i) = rx.recv().await {
+        served.push(i);
+    }
+    assert_eq!(served.len(), 50);
+
+    // Clients are served in the order they arrived, give or take
+    // the two that got a connection at the same time.
+    for (position, client) in served.into_iter().enumerate() {
+        assert!(
+            position.abs_diff(client) <= 2,
+            "client {} served at position {}",
+            client,
+            position
+        );
+    }
+    assert_eq!(pool.lock().waiting.len(), 0);
+}
+
 #[tokio::test]
 async fn test_offline() {
     let pool = pool();
@@ -437,3 +566,431 @@ async fn test_prepared_statements_limit() {
     assert_eq!(guard.prepared_statements_mut().len(), 100);
     assert_eq!(guard.stats().total.prepared_statements, 100); // stats are accurate.
 }
+
+fn healthcheck_pool(healthcheck_query: Option<String>, role_check: Option<Role>) -> Pool {
+    let pool = Pool::new(&PoolConfig {
+        address: Address {
+            host: "127.0.0.1".into(),
+            port: 5432,
+            database_name: "pgdog".into(),
+            user: "pgdog".into(),
+            password: "pgdog".into(),
+        },
+        config: Config {
+            max: 1,
+            min: 1,
+            healthcheck_interval: Duration::ZERO,
+            checkout_timeout: Duration::from_millis(1000),
+            role_check,
+            ..Default::default()
+        },
+        healthcheck_query,
+        ..Default::default()
+    });
+    pool.launch();
+    pool
+}
+
+#[tokio::test]
+async fn test_healthcheck_query() {
+    assert_eq!(pool().healthcheck_query(), ";");
+
+    let pool = healthcheck_pool(Some("SELECT 1".into()), None);
+    assert_eq!(pool.healthcheck_query(), "SELECT 1");
+    let conn = pool.get(&Request::default()).await.unwrap();
+    assert_eq!(conn.stats().total.healthchecks, 1);
+    drop(conn);
+
+    let pool = healthcheck_pool(Some("SELECT 1/0".into()), None);
+    let err = pool.get(&Request::default()).await;
+    let failure = HealthcheckFailure::SqlError(*b"22012");
+    assert_eq!(err.err(), Some(Error::Healthcheck(failure)));
+    assert!(pool.banned());
+    assert_eq!(pool.state().last_failure_reason, Some(failure));
+    assert_eq!(
+        pool.lock().ban.map(|ban| ban.reason),
+        Some(Error::Healthcheck(failure))
+    );
+}
+
+#[tokio::test]
+async fn test_healthcheck_query_timeout() {
+    let pool = Pool::new(&PoolConfig {
+        address: Address::new_test(),
+        config: Config {
+            max: 1,
+            min: 1,
+            healthcheck_interval: Duration::ZERO,
+            healthcheck_timeout: Duration::from_millis(100),
+            healthcheck: crate::config::Healthcheck {
+                query_timeout: crate::config::HealthcheckPolicy {
+                    failures: 2,
+                    ban_timeout: None,
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        healthcheck_query: Some("SELECT pg_sleep(1)".into()),
+        ..Default::default()
+    });
+    pool.launch();
+
+    let failure = HealthcheckFailure::QueryTimeout;
+
+    // First timeout is forgiven.
+    let err = pool.get(&Request::default()).await;
+    assert_eq!(err.err(), Some(Error::Healthcheck(failure)));
+    assert!(!pool.banned());
+    assert_eq!(pool.state().last_failure_reason, Some(failure));
+
+    let err = pool.get(&Request::default()).await;
+    assert_eq!(err.err(), Some(Error::Healthcheck(failure)));
+    assert!(pool.banned());
+    assert_eq!(pool.state().healthcheck_failures.get(failure), 2);
+}
+
+#[tokio::test]
+async fn test_startup_parameters() {
+    let pool = Pool::new(&PoolConfig {
+        address: Address::new_test(),
+        config: Config {
+            max: 1,
+            min: 1,
+            ..Default::default()
+        },
+        startup_parameters: vec![
+            Parameter::from(("application_name", "test_startup_parameters")),
+            Parameter::from(("options", "-c work_mem=12MB")),
+        ],
+        ..Default::default()
+    });
+    pool.launch();
+
+    let mut conn = pool.get(&Request::default()).await.unwrap();
+    let settings = conn
+        .fetch_all::<String>(
+            "SELECT current_setting('application_name') || ',' || current_setting('work_mem')",
+        )
+        .await
+        .unwrap();
+    assert_eq!(settings[0], "test_startup_parameters,12MB");
+
+    // Client asking for the same settings doesn't change anything.
+    let mut params = Parameters::default();
+    params.insert("application_name", "test_startup_parameters");
+    params.insert("work_mem", "12MB");
+    assert_eq!(conn.link_client(&params).await.unwrap(), 0);
+
+    params.insert("work_mem", "16MB");
+    assert_eq!(conn.link_client(&params).await.unwrap(), 1);
+    let work_mem = conn.fetch_all::<String>("SHOW work_mem").await.unwrap();
+    assert_eq!(work_mem[0], "16MB");
+}
+
+#[tokio::test]
+async fn test_healthcheck_role_check() {
+    // Test database is a primary.
+    let pool = healthcheck_pool(None, Some(Role::Primary));
+    let mut conn = pool.get(&Request::default()).await.unwrap();
+    assert!(!conn.in_recovery().await.unwrap());
+    drop(conn);
+    assert!(!pool.banned());
+
+    let pool = healthcheck_pool(None, Some(Role::Replica));
+    let err = pool.get(&Request::default()).await;
+    assert_eq!(err.err(), Some(Error::UnexpectedRole));
+    assert!(pool.banned());
+    assert_eq!(
+        pool.lock().ban.map(|ban| ban.reason),
+        Some(Error::UnexpectedRole)
+    );
+}
+
+#[tokio::test]
+async fn test_locked_not_healthchecked_or_reaped() {
+    crate::logger();
+
+    let pool = Pool::new(&PoolConfig {
+        address: Address::new_test(),
+        config: Config {
+            max: 1,
+            min: 0,
+            idle_timeout: Duration::from_millis(10),
+            idle_healthcheck_interval: Duration::from_millis(10),
+            idle_healthcheck_delay: Duration::from_millis(0),
+            ..Default::default()
+        },
+        ..Default::default()
+    });
+    pool.launch();
+
+    let mut conn = pool.get(&Request::default()).await.unwrap();
+    conn.execute("SELECT pg_advisory_lock(1181)").await.unwrap();
+    conn.lock(true);
+    assert!(conn.dirty());
+
+    let state = pool.state();
+    assert_eq!(state.locked, 1);
+    assert_eq!(state.checked_out, 1);
+    assert_eq!(state.idle, 0);
+    assert_eq!(state.total, 1);
+
+    let healthchecks = conn.stats().total.healthchecks;
+
+    // A few healthcheck intervals and idle timeouts go by.
+    sleep(Duration::from_millis(250)).await;
+
+    assert_eq!(conn.stats().total.healthchecks, healthchecks);
+    let state = pool.state();
+    assert_eq!(state.locked, 1);
+    assert_eq!(state.total, 1);
+
+    conn.lock(false);
+    let state = pool.state();
+    assert_eq!(state.locked, 0);
+    assert_eq!(state.checked_out, 1);
+
+    // Dirty connection is cleaned up before it's checked in.
+    drop(conn);
+    let state = pool.state();
+    assert_eq!(state.draining, 1);
+    assert_eq!(state.checked_out, 1);
+
+    sleep(Duration::from_millis(100)).await;
+    let state = pool.state();
+    assert_eq!(state.draining, 0);
+    assert_eq!(state.checked_out, 0);
+}
+
+#[tokio::test]
+async fn test_prepared_statements_prewarm() {
+    crate::logger();
+
+    let cluster = crate::backend::databases::User {
+        user: "pgdog".into(),
+        database: "prewarm".into(),
+    };
+
+    // Statements clients of this cluster prepared already.
+    let global = crate::frontend::PreparedStatements::global();
+    let names = ["SELECT $1::bigint", "SELECT $1::text"]
+        .into_iter()
+        .map(|query| {
+            global
+                .lock()
+                .insert(
+                    &Parse::named("test", query),
+                    &Some(Arc::new(cluster.clone())),
+                )
+                .1
+        })
+        .collect::<Vec<_>>();
+
+    // Replies that take longer than the timeout are drained,
+    // so the connection is still prewarmed.
+    for timeout in [Duration::from_secs(1), Duration::ZERO] {
+        let pool = Pool::new(&PoolConfig {
+            address: Address::new_test(),
+            config: Config {
+                max: 2,
+                min: 2,
+                prepared_statements_prewarm: 10,
+                prepared_statements_prewarm_timeout: timeout,
+                ..Default::default()
+            },
+            cluster: Some(cluster.clone()),
+            ..Default::default()
+        });
+        pool.launch();
+
+        while pool.lock().idle() < 2 {
+            sleep(Duration::from_millis(10)).await;
+        }
+
+        let first = pool.get(&Request::default()).await.unwrap();
+        let mut second = pool.get(&Request::default()).await.unwrap();
+
+        // Prepared before the connection was used by anyone.
+        assert_eq!(second.stats().total.prewarm, 2);
+        for name in &names {
+            assert!(second.prepared_statements_mut().contains(name));
+        }
+
+        let prepared = second
+            .fetch_all::<String>("SELECT name FROM pg_prepared_statements")
+            .await
+            .unwrap();
+        for name in &names {
+            assert!(prepared.contains(name));
+        }
+
+        assert_eq!(first.stats().total.prewarm, 2);
+    }
+}
+
+#[tokio::test]
+async fn test_shrink() {
+    crate::logger();
+
+    let pool = Pool::new(&PoolConfig {
+        address: Address::new_test(),
+        config: Config {
+            max: 5,
+            min: 1,
+            idle_timeout: Duration::from_secs(3600),
+            ..Default::default()
+        },
+        ..Default::default()
+    });
+    pool.launch();
+
+    // Grow the pool under load.
+    let mut conns = vec![];
+    for _ in 0..5 {
+        conns.push(pool.get(&Request::default()).await.unwrap());
+    }
+    let held = conns.pop().unwrap();
+    drop(conns);
+
+    while pool.lock().idle() < 4 {
+        sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(pool.lock().total(), 5);
+
+    // The checked out connection counts towards the minimum.
+    assert_eq!(pool.shrink(), 4);
+    let state = pool.state();
+    assert_eq!(state.idle, 0);
+    assert_eq!(state.checked_out, 1);
+
+    drop(held);
+    while pool.lock().idle() < 1 {
+        sleep(Duration::from_millis(10)).await;
+    }
+
+    assert_eq!(pool.shrink(), 0);
+    let guard = pool.lock();
+    assert_eq!(guard.idle(), guard.min());
+}
+
+#[tokio::test]
+async fn test_rotate_password() {
+    crate::logger();
+
+    let admin = pool();
+    let mut admin = admin.get(&Request::default()).await.unwrap();
+    admin
+        .execute("DROP ROLE IF EXISTS pgdog_rotate")
+        .await
+        .unwrap();
+    admin
+        .execute("CREATE ROLE pgdog_rotate LOGIN PASSWORD 'before'")
+        .await
+        .unwrap();
+
+    let rotate = |password: &str| {
+        let pool = Pool::new(&PoolConfig {
+            address: Address {
+                user: "pgdog_rotate".into(),
+                password: password.into(),
+                ..Address::new_test()
+            },
+            config: Config {
+                max: 2,
+                min: 0,
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        pool.launch();
+        pool
+    };
+
+    let old = rotate("before");
+    let mut before = old.get(&Request::default()).await.unwrap();
+
+    admin
+        .execute("ALTER ROLE pgdog_rotate PASSWORD 'after'")
+        .await
+        .unwrap();
+
+    let new = rotate("after");
+    assert!(old.can_move_conns_to(&new));
+    old.move_conns_to(&new);
+
+    // Established connection stays alive.
+    before.execute("SELECT 1").await.unwrap();
+
+    // New connections use the new password.
+    let mut after = new.get(&Request::default()).await.unwrap();
+    after.execute("SELECT 1").await.unwrap();
+    assert_eq!(new.state().checked_out, 2);
+
+    // Connection we had is returned to the new pool.
+    drop(before);
+    while new.lock().idle() < 1 {
+        sleep(Duration::from_millis(10)).await;
+    }
+}
+
+#[tokio::test]
+async fn test_shed_only_without_idle() {
+    crate::logger();
+
+    let pool = Pool::new(&PoolConfig {
+        address: Address::new_test(),
+        config: Config {
+            max: 1,
+            min: 1,
+            shed_above_wait: Some(Duration::from_millis(1)),
+            ..Default::default()
+        },
+        ..Default::default()
+    });
+    pool.launch();
+
+    // Checkouts were slow for a while, so most transactions are shed.
+    let start = tokio::time::Instant::now();
+    {
+        let mut guard = pool.lock();
+        for s in 0..10 {
+            let now = start + Duration::from_secs(s);
+            for _ in 0..20 {
+                guard.record_wait(Duration::from_secs(1), now);
+            }
+            guard.shed(
+                &Request::transaction(crate::net::BackendKeyData::new()),
+                now,
+            );
+        }
+    }
+
+    // Idle connection is available, so nothing is shed.
+    for _ in 0..10 {
+        let mut conn = pool
+            .get(&Request::transaction(crate::net::BackendKeyData::new()))
+            .await
+            .unwrap();
+        conn.execute("SELECT 1").await.unwrap();
+    }
+
+    // No idle connections left, new transactions are shed.
+    let _conn = pool
+        .get(&Request::transaction(crate::net::BackendKeyData::new()))
+        .await
+        .unwrap();
+    let mut shed = 0;
+    for _ in 0..10 {
+        if let Err(Error::LoadShed) = pool
+            .get(&Request {
+                deadline: Some(tokio::time::Instant::now() + Duration::from_millis(10)),
+                ..Request::transaction(crate::net::BackendKeyData::new())
+            })
+            .await
+        {
+            shed += 1;
+        }
+    }
+    assert!(shed >= 8);
+}
@@ -18,6 +18,7 @@ fn replicas() -> Replicas {
             checkout_timeout: Duration::from_millis(1000),
             ..Default::default()
         },
+        ..Default::default()
     };
     let mut two = one.clone();
     two.address.host = "localhost".into();
@@ -62,3 +63,41 @@ async fn test_replicas() {
     replicas.get(&Request::default(), &None).await.unwrap();
     assert!(replicas.pools.iter().all(|pool| !pool.banned()));
 }
+
+#[tokio::test]
+async fn test_slow_start_ordering() {
+    use rand::{rngs::StdRng, SeedableRng};
+    use tokio::time::Instant;
+
+    let replicas = replicas();
+    let window = Duration::from_secs(60);
+    let now = Instant::now();
+
+    // First replica just came back from a ban.
+    replicas.pools[0].lock().config.slow_start = window;
+    replicas.pools[0].lock().slow_start.start(now);
+
+    let mut rng = StdRng::seed_from_u64(1234);
+    let mut first = 0;
+    for _ in 0..1000 {
+        let mut candidates = replicas.pools.iter().collect::<Vec<_>>();
+        Replicas::slow_start(&mut candidates, now, &mut rng);
+        assert_eq!(candidates.len(), 2);
+        if candidates[0].addr() == replicas.pools[0].addr() {
+            first += 1;
+        }
+    }
+
+    // Roughly 10% of traffic at the start of the window.
+    assert!(first > 50 && first < 150, "{}", first);
+
+    // Window is over, ordering is left alone.
+    let later = now + window;
+    let mut candidates = replicas.pools.iter().collect::<Vec<_>>();
+    Replicas::slow_start(&mut candidates, later, &mut rng);
+    assert_eq!(candidates[0].addr(), replicas.pools[0].addr());
+    assert_eq!(
+        replicas.pools[0].lock().slow_start.remaining(later, window),
+        Duration::ZERO
+    );
+}
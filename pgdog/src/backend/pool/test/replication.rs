@@ -0,0 +1,88 @@
+use bytes::{BufMut, BytesMut};
+
+use crate::backend::prepared_statements::HandleResult;
+use crate::backend::PreparedStatements;
+use crate::config::ReplicationMode;
+use crate::net::{CopyData, CopyDone, Message, ProtocolMessage, Query};
+
+use super::*;
+
+fn message(code: char) -> Message {
+    let mut payload = BytesMut::new();
+    payload.put_u8(code as u8);
+    payload.put_i32(4);
+    Message::new(payload.freeze())
+}
+
+fn replication(mode: ReplicationMode) -> Pool {
+    Pool::new(&PoolConfig {
+        address: Address {
+            host: "127.0.0.1".into(),
+            port: 5432,
+            database_name: "pgdog".into(),
+            user: "pgdog".into(),
+            password: "pgdog".into(),
+        },
+        config: Config {
+            replication_mode: mode,
+            ..Default::default()
+        },
+        ..Default::default()
+    })
+}
+
+#[test]
+fn test_replication_startup_parameter() {
+    for (mode, expected) in [
+        (ReplicationMode::Disabled, None),
+        (ReplicationMode::Logical, Some("database")),
+        (ReplicationMode::Physical, Some("true")),
+    ] {
+        let options = replication(mode).server_options();
+        let replication = options
+            .params
+            .iter()
+            .find(|p| p.name == "replication")
+            .map(|p| p.value.as_str());
+        assert_eq!(replication, expected);
+        assert_eq!(options.replication_mode(), mode.enabled());
+    }
+}
+
+#[test]
+fn test_physical_replication_passthrough() {
+    let mut prepared = PreparedStatements::new();
+
+    let start: ProtocolMessage =
+        Query::new("START_REPLICATION SLOT standby PHYSICAL 0/3000000").into();
+    assert!(matches!(
+        prepared.handle(&start).unwrap(),
+        HandleResult::Forward
+    ));
+
+    // CopyBothResponse, followed by WAL and keepalives.
+    for code in ['W', 'd', 'd', 'd'] {
+        assert!(prepared.forward(&message(code)).unwrap());
+        assert!(!prepared.done());
+    }
+
+    // Standby status update.
+    let update: ProtocolMessage = CopyData::new(b"r").into();
+    assert!(matches!(
+        prepared.handle(&update).unwrap(),
+        HandleResult::Forward
+    ));
+    assert!(prepared.forward(&message('d')).unwrap());
+
+    // Client ends the stream, server confirms.
+    assert!(matches!(
+        prepared.handle(&CopyDone.into()).unwrap(),
+        HandleResult::Forward
+    ));
+    for code in ['c', 'C'] {
+        assert!(prepared.forward(&message(code)).unwrap());
+        assert!(!prepared.done());
+    }
+    assert!(prepared.forward(&message('Z')).unwrap());
+    assert!(prepared.done());
+}
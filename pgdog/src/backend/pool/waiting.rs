@@ -6,10 +6,15 @@ use tokio::{
     time::{timeout, Instant},
 };
 
+/// Client waiting in line for a connection.
+///
+/// Dropping it before it's served removes the client
+/// from the queue, e.g. when the client disconnects.
 pub(super) struct Waiting {
     pool: Pool,
     rx: Receiver<Result<Box<Server>, Error>>,
     request: Request,
+    done: bool,
 }
 
 impl Waiting {
@@ -22,18 +27,33 @@ impl Waiting {
             if !guard.online {
                 return Err(Error::Offline);
             }
-            guard.waiting.push_back(Waiter { request, tx })
+            let now = Instant::now();
+            guard.waiting.push_back(Waiter {
+                request,
+                queued_at: now,
+                tx,
+            });
+            // A connection could've been returned before we got in line.
+            guard.serve_waiters(now);
         }
 
         // Tell maintenance we are in line waiting for a connection.
         pool.comms().request.notify_one();
 
-        Ok(Self { pool, rx, request })
+        Ok(Self {
+            pool,
+            rx,
+            request,
+            done: false,
+        })
     }
 
-    pub(super) async fn wait(self) -> Result<(Guard, Instant), Error> {
-        let checkout_timeout = self.pool.inner().config.checkout_timeout;
-        let server = timeout(checkout_timeout, self.rx).await;
+    pub(super) async fn wait(mut self) -> Result<(Guard, Instant), Error> {
+        let (checkout_timeout, deadline) = self
+            .request
+            .checkout_timeout(self.pool.inner().config.checkout_timeout);
+        let server = timeout(checkout_timeout, &mut self.rx).await;
+        self.done = true;
 
         let now = Instant::now();
         match server {
@@ -42,11 +62,18 @@ impl Waiting {
                 Ok((Guard::new(self.pool.clone(), server, now), now))
             }
 
+            // Client's own deadline, the pool isn't necessarily unhealthy.
+            Err(_err) if deadline => {
+                self.pool.lock().remove_waiter(&self.request.id);
+                Err(Error::StatementTimeout)
+            }
+
             Err(_err) => {
                 let mut guard = self.pool.lock();
                 if !guard.banned() {
                     guard.maybe_ban(now, Error::CheckoutTimeout);
                 }
+                guard.record_wait(checkout_timeout, now);
                 guard.remove_waiter(&self.request.id);
                 Err(Error::CheckoutTimeout)
             }
@@ -59,8 +86,26 @@ impl Waiting {
     }
 }
 
+impl Drop for Waiting {
+    fn drop(&mut self) {
+        if self.done {
+            return;
+        }
+
+        // Cancelled, get out of line.
+        self.pool.lock().remove_waiter(&self.request.id);
+
+        // We could have been served just before leaving the queue.
+        if let Ok(Ok(server)) = self.rx.try_recv() {
+            drop(Guard::new(self.pool.clone(), server, Instant::now()));
+        }
+    }
+}
+
 #[derive(Debug)]
 pub(super) struct Waiter {
     pub(super) request: Request,
+    /// When the client got in line.
+    pub(super) queued_at: Instant,
     pub(super) tx: Sender<Result<Box<Server>, Error>>,
 }
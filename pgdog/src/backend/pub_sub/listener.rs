@@ -3,7 +3,7 @@
 //! Handles notifications from Postgres and sends them out
 //! to a broadcast channel.
 //!
-use std::{collections::HashMap, sync::Arc, time::Duration};
+use std::{collections::HashMap, sync::Arc};
 
 use once_cell::sync::Lazy;
 use parking_lot::Mutex;
@@ -97,7 +97,7 @@ impl PubSubListener {
                             error!("pub/sub error: {} [{}]", err, pool.addr());
                             // Don't reconnect for another connect attempt delay
                             // to avoid connection storms during incidents.
-                            sleep(Duration::from_millis(config().config.general.connect_attempt_delay)).await;
+                            sleep(config().config.general.connect_attempt_delay()).await;
                         }
                     }
                 }
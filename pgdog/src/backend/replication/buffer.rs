@@ -78,39 +78,19 @@ impl Buffer {
                         self.oid = Some(relation.oid);
                     }
                     XLogPayload::Update(update) => {
-                        let (table, columns) = self.sharding_key(update.oid)?;
-                        let column = self
-                            .replication_config
-                            .sharded_column(table, &columns)
-                            .and_then(|column| update.column(column.position))
-                            .and_then(|column| column.as_str());
-                        if let Some(column) = column {
-                            let shard =
-                                shard_str(column, &self.sharding_schema, &vec![], CENTROID_PROBES);
-                            if self.shard == shard {
-                                self.message = Some(xlog_data);
-                                return self.flush();
-                            }
-                        } else {
+                        let shard = self.row_shard(update.oid, |position| {
+                            update.column(position).and_then(|column| column.as_str())
+                        })?;
+                        if shard.is_none_or(|shard| shard == self.shard) {
                             self.message = Some(xlog_data);
                             return self.flush();
                         }
                     }
                     XLogPayload::Insert(insert) => {
-                        let (table, columns) = self.sharding_key(insert.oid)?;
-                        let column = self
-                            .replication_config
-                            .sharded_column(table, &columns)
-                            .and_then(|column| insert.column(column.position))
-                            .and_then(|column| column.as_str());
-                        if let Some(column) = column {
-                            let shard =
-                                shard_str(column, &self.sharding_schema, &vec![], CENTROID_PROBES);
-                            if self.shard == shard {
-                                self.message = Some(xlog_data);
-                                return self.flush();
-                            }
-                        } else {
+                        let shard = self.row_shard(insert.oid, |position| {
+                            insert.column(position).and_then(|column| column.as_str())
+                        })?;
+                        if shard.is_none_or(|shard| shard == self.shard) {
                             self.message = Some(xlog_data);
                             return self.flush();
                         }
@@ -163,6 +143,34 @@ impl Buffer {
         Ok(())
     }
 
+    /// Shard of an inserted or updated row. `None` if the table
+    /// isn't sharded or the row doesn't have the whole sharding key.
+    fn row_shard<'a>(
+        &self,
+        oid: i32,
+        column: impl Fn(usize) -> Option<&'a str>,
+    ) -> Result<Option<Shard>, Error> {
+        let (table, columns) = self.sharding_key(oid)?;
+        let Some(sharded) = self.replication_config.sharded_column(table, &columns) else {
+            return Ok(None);
+        };
+
+        let Some(values) = sharded
+            .positions
+            .iter()
+            .map(|position| column(*position))
+            .collect::<Option<Vec<_>>>()
+        else {
+            return Ok(None);
+        };
+
+        Ok(Some(if sharded.composite() {
+            sharded.composite_shard(&values, self.sharding_schema.shards)
+        } else {
+            shard_str(values[0], &self.sharding_schema, &vec![], CENTROID_PROBES)
+        }))
+    }
+
     fn sharding_key(&self, oid: i32) -> Result<(&str, Vec<&str>), Error> {
         let relation = self.relations.get(&oid).ok_or(Error::NoRelationMessage)?;
         let columns = relation.columns();
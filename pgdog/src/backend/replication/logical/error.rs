@@ -65,6 +65,9 @@ pub enum Error {
 
     #[error("no replicas available for table sync")]
     NoReplicas,
+
+    #[error("replication slot \"{0}\" is holding {1} bytes of WAL, stopped replication")]
+    SlotLagExceeded(String, u64),
 }
 
 impl From<ErrorResponse> for Error {
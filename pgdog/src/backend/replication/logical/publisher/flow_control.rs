@@ -0,0 +1,266 @@
+//! Flow control for replication streams.
+//!
+//! Changes read from the replication slot are queued for the [`StreamSubscriber`]
+//! to apply to the destination. If the destination falls behind, e.g. while it's
+//! building indexes, reading from the slot is paused once `max_apply_backlog` bytes
+//! are queued, and resumed when the queue is down to half of that.
+//!
+//! The source keeps the WAL for the slot in the meantime, so the slot lag is checked
+//! regularly. We warn when it's over `slot_lag_warning` and stop replicating when it's
+//! over `slot_lag_abort`. The slot isn't dropped, so replication can resume from the last
+//! position confirmed by the destination.
+//!
+//! [`StreamSubscriber`]: crate::backend::replication::logical::StreamSubscriber
+
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::Notify;
+use tokio::time::sleep;
+use tracing::{error, info, warn};
+
+use super::super::Error;
+use super::progress::Progress;
+use crate::backend::{pool::Request, Cluster};
+use crate::config::Replication;
+
+/// How often the replication slot lag is checked.
+const SLOT_LAG_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Flow control settings.
+#[derive(Debug, Clone, Copy)]
+pub struct FlowControl {
+    /// Stop reading from the slot when this many bytes are waiting to be applied.
+    pub max_apply_backlog: usize,
+    /// Warn when the source holds this many bytes of WAL for the slot.
+    pub slot_lag_warning: u64,
+    /// Stop replicating when the source holds this many bytes of WAL for the slot.
+    pub slot_lag_abort: Option<u64>,
+}
+
+impl From<&Replication> for FlowControl {
+    fn from(config: &Replication) -> Self {
+        Self {
+            max_apply_backlog: config.max_apply_backlog,
+            slot_lag_warning: config.slot_lag_warning,
+            slot_lag_abort: config.slot_lag_abort,
+        }
+    }
+}
+
+impl FlowControl {
+    /// Check the slot lag until the stream stops.
+    pub async fn monitor(
+        &self,
+        cluster: &Cluster,
+        shard: usize,
+        slot: &str,
+        progress: &Progress,
+        backlog: &Backlog,
+    ) {
+        let mut warned = false;
+
+        loop {
+            sleep(SLOT_LAG_INTERVAL).await;
+
+            let lag = match slot_lag(cluster, shard, slot).await {
+                Ok(Some(lag)) => lag,
+                Ok(None) => continue,
+                Err(err) => {
+                    warn!(
+                        "couldn't check lag of replication slot \"{}\": {} [shard {}]",
+                        slot, err, shard
+                    );
+                    continue;
+                }
+            };
+            progress.set_slot_lag(lag);
+
+            if lag > self.slot_lag_warning {
+                if !warned {
+                    warn!(
+                        "source is holding {:.3} MB of WAL for replication slot \"{}\" [shard {}]",
+                        lag as f64 / 1024.0 / 1024.0,
+                        slot,
+                        shard
+                    );
+                }
+                warned = true;
+            } else if warned {
+                info!(
+                    "replication slot \"{}\" lag is down to {:.3} MB [shard {}]",
+                    slot,
+                    lag as f64 / 1024.0 / 1024.0,
+                    shard
+                );
+                warned = false;
+            }
+
+            if let Some(abort) = self.slot_lag_abort {
+                if lag > abort && !backlog.stopped() {
+                    error!(
+                        "replication slot \"{}\" lag is over {} bytes, stopping replication [shard {}]",
+                        slot, abort, shard
+                    );
+                    backlog.stop();
+                }
+            }
+        }
+    }
+}
+
+/// Bytes of WAL the source is holding for the slot.
+async fn slot_lag(cluster: &Cluster, shard: usize, slot: &str) -> Result<Option<u64>, Error> {
+    let mut primary = cluster.shards()[shard].primary(&Request::default()).await?;
+
+    let lag = primary
+        .fetch_all::<i64>(format!(
+            "SELECT COALESCE(pg_wal_lsn_diff(pg_current_wal_lsn(), restart_lsn), 0)::bigint FROM pg_replication_slots WHERE slot_name = '{}'",
+            slot
+        ))
+        .await?
+        .pop();
+
+    Ok(lag.map(|lag| lag.max(0) as u64))
+}
+
+#[derive(Debug)]
+struct Inner {
+    bytes: AtomicUsize,
+    max: usize,
+    paused: AtomicBool,
+    stopped: AtomicBool,
+    resumed: Notify,
+}
+
+/// Changes read from the slot that weren't applied to the destination yet.
+///
+/// Clones share the same backlog.
+#[derive(Debug, Clone)]
+pub struct Backlog {
+    inner: Arc<Inner>,
+}
+
+impl Backlog {
+    /// Pause reading when more than `max` bytes are waiting to be applied.
+    pub fn new(max: usize) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                bytes: AtomicUsize::new(0),
+                max,
+                paused: AtomicBool::new(false),
+                stopped: AtomicBool::new(false),
+                resumed: Notify::new(),
+            }),
+        }
+    }
+
+    /// Data read from the slot. Returns true if reading
+    /// should pause until the destination catches up.
+    pub fn received(&self, bytes: usize) -> bool {
+        let backlog = self.inner.bytes.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        if backlog > self.inner.max {
+            self.inner.paused.store(true, Ordering::Relaxed);
+        }
+
+        self.paused()
+    }
+
+    /// Data applied to the destination.
+    pub fn applied(&self, bytes: usize) {
+        let backlog = self.inner.bytes.fetch_sub(bytes, Ordering::Relaxed) - bytes;
+        if backlog <= self.inner.max / 2 && self.inner.paused.swap(false, Ordering::Relaxed) {
+            self.inner.resumed.notify_one();
+        }
+    }
+
+    /// Wait until the destination catches up, or reading is stopped.
+    pub async fn resumed(&self) {
+        while self.paused() && !self.stopped() {
+            self.inner.resumed.notified().await;
+        }
+    }
+
+    /// Reading from the slot is paused.
+    pub fn paused(&self) -> bool {
+        self.inner.paused.load(Ordering::Relaxed)
+    }
+
+    /// Bytes waiting to be applied.
+    pub fn bytes(&self) -> usize {
+        self.inner.bytes.load(Ordering::Relaxed)
+    }
+
+    /// Stop reading from the slot for good. Changes already
+    /// read are still applied.
+    pub fn stop(&self) {
+        self.inner.stopped.store(true, Ordering::Relaxed);
+        self.inner.resumed.notify_one();
+    }
+
+    /// Reading from the slot was stopped.
+    pub fn stopped(&self) -> bool {
+        self.inner.stopped.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tokio::{spawn, sync::mpsc::unbounded_channel};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_pause_resume() {
+        let backlog = Backlog::new(100);
+        let (tx, mut rx) = unbounded_channel::<usize>();
+
+        // Destination that takes a while to apply each change.
+        let destination = {
+            let backlog = backlog.clone();
+            spawn(async move {
+                while let Some(bytes) = rx.recv().await {
+                    sleep(Duration::from_millis(5)).await;
+                    backlog.applied(bytes);
+                }
+            })
+        };
+
+        let mut pauses = 0;
+        for _ in 0..100 {
+            let paused = backlog.received(10);
+            tx.send(10).unwrap();
+            assert!(backlog.bytes() <= 110);
+
+            if paused {
+                pauses += 1;
+                backlog.resumed().await;
+                assert!(!backlog.paused());
+                assert!(backlog.bytes() <= 50);
+            }
+        }
+        assert!(pauses >= 10);
+
+        drop(tx);
+        destination.await.unwrap();
+        assert_eq!(backlog.bytes(), 0);
+        assert!(!backlog.paused());
+    }
+
+    #[tokio::test]
+    async fn test_stop() {
+        let backlog = Backlog::new(10);
+        assert!(backlog.received(20));
+        assert!(!backlog.stopped());
+
+        backlog.stop();
+        assert!(backlog.stopped());
+        // Still paused, but the reader doesn't wait anymore.
+        assert!(backlog.paused());
+        backlog.resumed().await;
+
+        backlog.applied(20);
+        assert_eq!(backlog.bytes(), 0);
+    }
+}
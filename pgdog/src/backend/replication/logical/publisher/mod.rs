@@ -1,12 +1,14 @@
 pub mod slot;
 pub use slot::*;
 pub mod copy;
+pub mod flow_control;
 pub mod parallel_sync;
 pub mod progress;
 pub mod publisher_impl;
 pub mod queries;
 pub mod table;
 pub use copy::*;
+pub use flow_control::{Backlog, FlowControl};
 pub use parallel_sync::ParallelSyncManager;
 pub use queries::*;
 pub use table::*;
@@ -1,7 +1,10 @@
-use std::sync::atomic::{AtomicI64, AtomicUsize, Ordering};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
 use tokio::sync::Notify;
 use tokio::time::sleep;
 use tokio::{select, spawn};
@@ -9,11 +12,18 @@ use tracing::info;
 
 use crate::backend::replication::publisher::{Lsn, PublicationTable};
 
+/// Replication streams running in this process, by slot name.
+static SLOTS: Lazy<Mutex<HashMap<String, Arc<Inner>>>> = Lazy::new(Mutex::default);
+
 #[derive(Debug)]
 struct Inner {
     table: Option<PublicationTable>,
     bytes_sharded: AtomicUsize,
     lsn: AtomicI64,
+    /// Bytes of WAL the source is holding for the replication slot.
+    slot_lag: AtomicU64,
+    /// Reading from the replication slot is paused.
+    paused: AtomicBool,
     done: Notify,
 }
 
@@ -47,6 +57,8 @@ impl Progress {
         let inner = Arc::new(Inner {
             bytes_sharded: AtomicUsize::new(0),
             lsn: AtomicI64::new(0),
+            slot_lag: AtomicU64::new(0),
+            paused: AtomicBool::new(false),
             done: Notify::new(),
             table: table.cloned(),
         });
@@ -71,13 +83,26 @@ impl Progress {
                             ProgressKind::Replication => "replicated",
                         };
 
+                        let mut state = String::new();
+                        let slot_lag = notify.slot_lag.load(Ordering::Relaxed);
+                        if slot_lag > 0 {
+                            state.push_str(&format!(
+                                ", slot lag {:.3} MB",
+                                slot_lag as f64 / 1024.0 / 1024.0
+                            ));
+                        }
+                        if notify.paused.load(Ordering::Relaxed) {
+                            state.push_str(", paused");
+                        }
+
                         info!(
-                            "{} {:.3} MB{} position {} [{:.3} MB/sec]",
+                            "{} {:.3} MB{} position {} [{:.3} MB/sec{}]",
                             name,
                             written as f64 / 1024.0 / 1024.0,
                             table,
                             Lsn::from_i64(lsn),
-                            (written - prev) as f64 / 5.0 / 1024.0 / 1024.0
+                            (written - prev) as f64 / 5.0 / 1024.0 / 1024.0,
+                            state,
                         );
 
                         prev = written;
@@ -100,9 +125,108 @@ impl Progress {
         self.inner.lsn.store(lsn, Ordering::Relaxed);
     }
 
+    /// Last position reported with [`Progress::update`].
+    pub fn lsn(&self) -> i64 {
+        self.inner.lsn.load(Ordering::Relaxed)
+    }
+
+    /// Bytes of WAL the source is holding for the replication slot,
+    /// as of the last check.
+    pub fn slot_lag(&self) -> u64 {
+        self.inner.slot_lag.load(Ordering::Relaxed)
+    }
+
+    pub(super) fn set_slot_lag(&self, lag: u64) {
+        self.inner.slot_lag.store(lag, Ordering::Relaxed);
+    }
+
+    /// Reading from the replication slot is paused
+    /// until the destination catches up.
+    pub fn paused(&self) -> bool {
+        self.inner.paused.load(Ordering::Relaxed)
+    }
+
+    pub(super) fn set_paused(&self, paused: bool) {
+        self.inner.paused.store(paused, Ordering::Relaxed);
+    }
+
     pub fn done(&self) {
         self.inner.done.notify_one();
     }
+
+    /// Report the slot in [`slots`] until the returned guard is dropped.
+    pub(super) fn slot_metrics(&self, slot: &str) -> SlotMetrics {
+        SLOTS.lock().insert(slot.to_owned(), self.inner.clone());
+        SlotMetrics {
+            slot: slot.to_owned(),
+        }
+    }
+}
+
+/// Removes the slot from [`slots`] when the stream stops.
+#[derive(Debug)]
+pub(super) struct SlotMetrics {
+    slot: String,
+}
+
+impl Drop for SlotMetrics {
+    fn drop(&mut self) {
+        SLOTS.lock().remove(&self.slot);
+    }
+}
+
+/// Replication slot streaming in this process.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SlotStats {
+    pub slot: String,
+    /// Bytes of WAL the source is holding for the slot, as of the last check.
+    pub lag: u64,
+    /// Reading from the slot is paused until the destination catches up.
+    pub paused: bool,
+}
+
+/// Replication slots streaming in this process.
+pub fn slots() -> Vec<SlotStats> {
+    SLOTS
+        .lock()
+        .iter()
+        .map(|(slot, inner)| SlotStats {
+            slot: slot.clone(),
+            lag: inner.slot_lag.load(Ordering::Relaxed),
+            paused: inner.paused.load(Ordering::Relaxed),
+        })
+        .collect()
+}
+
+/// Progress of replication streams, by shard.
+#[derive(Debug, Clone, Default)]
+pub struct StreamsProgress {
+    streams: Arc<Mutex<HashMap<usize, Progress>>>,
+}
+
+impl StreamsProgress {
+    pub(super) fn insert(&self, shard: usize, progress: &Progress) {
+        self.streams.lock().insert(shard, progress.clone());
+    }
+
+    /// Position of each shard's stream. Shards that didn't start
+    /// streaming yet are missing.
+    pub fn lsns(&self) -> HashMap<usize, i64> {
+        self.streams
+            .lock()
+            .iter()
+            .map(|(shard, progress)| (*shard, progress.lsn()))
+            .collect()
+    }
+
+    /// Replication slot lag of each shard, in bytes.
+    pub fn slot_lags(&self) -> HashMap<usize, u64> {
+        self.streams
+            .lock()
+            .iter()
+            .map(|(shard, progress)| (*shard, progress.slot_lag()))
+            .collect()
+    }
 }
 
 impl Drop for Progress {
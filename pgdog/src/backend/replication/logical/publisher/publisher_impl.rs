@@ -1,21 +1,38 @@
 use std::collections::HashMap;
 use std::time::Duration;
 
-use tokio::{select, spawn};
-use tracing::{debug, error, info};
+use tokio::sync::{
+    mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+    watch,
+};
+use tokio::time::sleep;
+use tokio::{select, spawn, try_join};
+use tracing::{debug, error, info, warn};
 
 use super::super::{publisher::Table, Error};
-use super::ReplicationSlot;
+use super::{Backlog, FlowControl, ReplicationSlot};
 
 use crate::backend::replication::logical::subscriber::stream::StreamSubscriber;
-use crate::backend::replication::publisher::progress::Progress;
+use crate::backend::replication::publisher::progress::{Progress, StreamsProgress};
 use crate::backend::replication::publisher::Lsn;
 use crate::backend::replication::{
     logical::publisher::ReplicationData, publisher::ParallelSyncManager,
 };
 use crate::backend::{pool::Request, Cluster};
-use crate::config::Role;
-use crate::net::replication::ReplicationMeta;
+use crate::config::{config, Role};
+use crate::net::replication::{ReplicationMeta, StatusUpdate};
+use crate::net::CopyData;
+
+/// How often we confirm our position to the source while reading from the slot is paused.
+const PAUSED_STATUS_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Why we stopped reading from the replication slot.
+enum Stopped {
+    /// Source ended the stream.
+    Drained,
+    /// Source is holding too much WAL for the slot.
+    Aborted,
+}
 
 #[derive(Debug)]
 pub struct Publisher {
@@ -27,6 +44,10 @@ pub struct Publisher {
     tables: HashMap<usize, Vec<Table>>,
     /// Replication slots.
     slots: HashMap<usize, ReplicationSlot>,
+    /// Name of the replication slots, if they are reused across restarts.
+    slot_name: Option<String>,
+    /// Progress of the replication streams.
+    streams: StreamsProgress,
 }
 
 impl Publisher {
@@ -36,9 +57,40 @@ impl Publisher {
             publication: publication.to_string(),
             tables: HashMap::new(),
             slots: HashMap::new(),
+            slot_name: None,
+            streams: StreamsProgress::default(),
         }
     }
 
+    /// Name the replication slots, instead of using random names. Slots that
+    /// already exist are used as-is, resuming replication where it stopped.
+    ///
+    /// Each shard gets its own slot, suffixed with the shard number.
+    pub fn with_slot_name(mut self, name: &str) -> Self {
+        self.slot_name = Some(name.to_string());
+        self
+    }
+
+    /// Name of the replication slot for this shard.
+    pub fn shard_slot_name(name: &str, shard: usize) -> String {
+        format!("{}_{}", name, shard)
+    }
+
+    /// Tables in the publication, by shard.
+    pub fn tables(&self) -> &HashMap<usize, Vec<Table>> {
+        &self.tables
+    }
+
+    /// Tables in the publication, by shard.
+    pub fn tables_mut(&mut self) -> &mut HashMap<usize, Vec<Table>> {
+        &mut self.tables
+    }
+
+    /// Progress of the replication streams, updated while [`Publisher::replicate`] is running.
+    pub fn streams(&self) -> StreamsProgress {
+        self.streams.clone()
+    }
+
     /// Synchronize tables for all shards.
     pub async fn sync_tables(&mut self) -> Result<(), Error> {
         for (number, shard) in self.cluster.shards().iter().enumerate() {
@@ -64,7 +116,12 @@ impl Publisher {
             let addr = shard.primary(&Request::default()).await?.addr().clone();
 
             let mut slot = ReplicationSlot::replication(&self.publication, &addr);
-            slot.create_slot().await?;
+            if let Some(ref name) = self.slot_name {
+                slot = slot.with_name(&Self::shard_slot_name(name, number));
+                slot.create_or_resume().await?;
+            } else {
+                slot.create_slot().await?;
+            }
 
             self.slots.insert(number, slot);
         }
@@ -76,9 +133,14 @@ impl Publisher {
     ///
     /// This uses a dedicated replication slot which will survive crashes and reboots.
     /// N.B.: The slot needs to be manually dropped!
+    ///
+    /// If the destination falls behind, reading from the slot pauses until it catches up,
+    /// see [`FlowControl`]. If the source is holding more WAL than `slot_lag_abort`,
+    /// replication stops, keeping the slot to resume from later.
     pub async fn replicate(&mut self, dest: &Cluster) -> Result<(), Error> {
         // Replicate shards in parallel.
         let mut streams = vec![];
+        let flow = FlowControl::from(&config().config.replication);
 
         // Synchronize tables from publication.
         if self.tables.is_empty() {
@@ -107,46 +169,22 @@ impl Publisher {
                 .ok_or(Error::NoReplicationSlot(number))?;
             stream.set_current_lsn(slot.lsn().lsn);
 
+            let progress = Progress::new_stream();
+            progress.update(0, slot.lsn().lsn);
+            self.streams.insert(number, &progress);
+
+            let backlog = Backlog::new(flow.max_apply_backlog);
+            let slot_name = slot.name().to_owned();
+            let source = self.cluster.clone();
+
             // Replicate in parallel.
             let handle = spawn(async move {
-                slot.start_replication().await?;
-                let progress = Progress::new_stream();
-
-                loop {
-                    select! {
-                        replication_data = slot.replicate(Duration::MAX) => {
-                            match replication_data {
-                                Ok(Some(ReplicationData::CopyData(data))) => {
-                                    let lsn = if let Some(ReplicationMeta::KeepAlive(ka)) = data.replication_meta() {
-                                        // If the LSN hasn't moved, we reached the end of the stream.
-                                        // If Postgres is getting requesting reply, provide our LSN now.
-                                        if !stream.set_current_lsn(ka.wal_end) || ka.reply() {
-                                            slot.status_update(stream.status_update()).await?;
-                                        }
-                                        debug!("origin at lsn {} [{}]", Lsn::from_i64(ka.wal_end), slot.server()?.addr());
-                                        ka.wal_end
-                                    } else {
-                                        if let Some(status_update) = stream.handle(data).await? {
-                                            slot.status_update(status_update).await?;
-                                        }
-                                        stream.lsn()
-                                    };
-                                    progress.update(stream.bytes_sharded(), lsn);
-                                }
-                                Ok(Some(ReplicationData::CopyDone)) => (),
-                                Ok(None) => {
-                                    slot.drop_slot().await?;
-                                    break;
-                                }
-                                Err(err) => {
-                                    return Err(err);
-                                }
-                            }
-                        }
+                select! {
+                    result = Self::stream(&mut slot, stream, &progress, &backlog) => result,
+                    () = flow.monitor(&source, number, &slot_name, &progress, &backlog) => {
+                        unreachable!("slot lag is checked until the stream stops")
                     }
                 }
-
-                Ok::<(), Error>(())
             });
 
             streams.push(handle);
@@ -162,11 +200,164 @@ impl Publisher {
         Ok(())
     }
 
+    /// Stream changes from the slot to the destination.
+    ///
+    /// Changes are read and applied concurrently, so reading can
+    /// pause while the destination catches up.
+    async fn stream(
+        slot: &mut ReplicationSlot,
+        stream: StreamSubscriber,
+        progress: &Progress,
+        backlog: &Backlog,
+    ) -> Result<(), Error> {
+        slot.start_replication().await?;
+        let _metrics = progress.slot_metrics(slot.name());
+
+        let (queue, changes) = unbounded_channel();
+        let (flushed, mut confirmed) = watch::channel(stream.lsn());
+
+        let (stopped, ()) = try_join!(
+            Self::read(slot, queue, &mut confirmed, progress, backlog),
+            Self::apply(stream, changes, flushed, progress, backlog),
+        )?;
+
+        match stopped {
+            Stopped::Drained => slot.drop_slot().await,
+            Stopped::Aborted => {
+                // Everything we read is applied now.
+                Self::confirm(slot, &mut confirmed).await?;
+                Err(Error::SlotLagExceeded(
+                    slot.name().to_owned(),
+                    progress.slot_lag(),
+                ))
+            }
+        }
+    }
+
+    /// Read changes from the slot and queue them to be applied,
+    /// pausing while the destination is behind.
+    async fn read(
+        slot: &mut ReplicationSlot,
+        queue: UnboundedSender<CopyData>,
+        confirmed: &mut watch::Receiver<i64>,
+        progress: &Progress,
+        backlog: &Backlog,
+    ) -> Result<Stopped, Error> {
+        loop {
+            if backlog.stopped() {
+                return Ok(Stopped::Aborted);
+            }
+
+            let data = match slot.replicate(Duration::MAX).await? {
+                Some(ReplicationData::CopyData(data)) => data,
+                Some(ReplicationData::CopyDone) => continue,
+                None => return Ok(Stopped::Drained),
+            };
+
+            // If Postgres is requesting a reply, provide our LSN now.
+            let reply = if let Some(ReplicationMeta::KeepAlive(ka)) = data.replication_meta() {
+                debug!(
+                    "origin at lsn {} [{}]",
+                    Lsn::from_i64(ka.wal_end),
+                    slot.server()?.addr()
+                );
+                ka.reply()
+            } else {
+                false
+            };
+
+            let paused = backlog.received(data.len());
+            // Only fails if applying changes failed, which stops the stream.
+            let _ = queue.send(data);
+
+            if reply || confirmed.has_changed().unwrap_or(false) {
+                Self::confirm(slot, confirmed).await?;
+            }
+
+            if paused {
+                let addr = slot.server()?.addr().clone();
+                warn!(
+                    "destination is {} bytes behind, pausing replication [{}]",
+                    backlog.bytes(),
+                    addr
+                );
+                progress.set_paused(true);
+
+                while backlog.paused() && !backlog.stopped() {
+                    select! {
+                        _ = backlog.resumed() => (),
+                        // Let the source know we're still here.
+                        _ = sleep(PAUSED_STATUS_INTERVAL) => Self::confirm(slot, confirmed).await?,
+                    }
+                }
+
+                progress.set_paused(false);
+                if !backlog.stopped() {
+                    info!("destination caught up, resuming replication [{}]", addr);
+                }
+            }
+        }
+    }
+
+    /// Apply queued changes to the destination.
+    async fn apply(
+        mut stream: StreamSubscriber,
+        mut changes: UnboundedReceiver<CopyData>,
+        flushed: watch::Sender<i64>,
+        progress: &Progress,
+        backlog: &Backlog,
+    ) -> Result<(), Error> {
+        while let Some(data) = changes.recv().await {
+            let len = data.len();
+
+            let lsn = if let Some(ReplicationMeta::KeepAlive(ka)) = data.replication_meta() {
+                // If the LSN hasn't moved, we reached the end of the stream.
+                if !stream.set_current_lsn(ka.wal_end) || ka.reply() {
+                    flushed.send_replace(stream.lsn());
+                }
+                ka.wal_end
+            } else if let Some(status_update) = stream.handle(data).await? {
+                // Report the position once the transaction is committed.
+                flushed.send_replace(status_update.last_flushed);
+                status_update.last_flushed
+            } else {
+                progress.lsn()
+            };
+
+            progress.update(stream.bytes_sharded(), lsn);
+            backlog.applied(len);
+        }
+
+        Ok(())
+    }
+
+    /// Confirm the position flushed to the destination.
+    async fn confirm(
+        slot: &mut ReplicationSlot,
+        confirmed: &mut watch::Receiver<i64>,
+    ) -> Result<(), Error> {
+        let lsn = *confirmed.borrow_and_update();
+        slot.status_update(StatusUpdate::new(Lsn::from_i64(lsn)))
+            .await
+    }
+
     /// Sync data from all tables in a publication from one shard to N shards,
     /// re-sharding the cluster in the process.
+    pub async fn data_sync(&mut self, dest: &Cluster) -> Result<(), Error> {
+        self.copy_data(dest).await?;
+
+        // Replicate changes.
+        self.replicate(dest).await?;
+
+        Ok(())
+    }
+
+    /// Copy data from all tables in a publication, without replicating changes.
+    /// Replication slots are created before the copy, so [`Publisher::replicate`]
+    /// can pick up changes made while it was running.
     ///
     /// TODO: Parallelize shard syncs.
-    pub async fn data_sync(&mut self, dest: &Cluster) -> Result<(), Error> {
+    pub async fn copy_data(&mut self, dest: &Cluster) -> Result<(), Error> {
         // Create replication slots.
         self.create_slots().await?;
 
@@ -199,9 +390,128 @@ impl Publisher {
             self.tables.insert(number, tables);
         }
 
-        // Replicate changes.
-        self.replicate(dest).await?;
-
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::time::Instant;
+
+    use super::super::progress::slots;
+    use super::*;
+    use crate::backend::server::test::test_server;
+    use crate::backend::{Server, ServerOptions};
+
+    /// Wait for the condition, for up to 10 seconds.
+    async fn wait_for(what: &str, mut condition: impl FnMut() -> bool) {
+        let started = Instant::now();
+        while !condition() {
+            assert!(
+                started.elapsed() < Duration::from_secs(10),
+                "timed out waiting for {}",
+                what
+            );
+            sleep(Duration::from_millis(50)).await;
+        }
+    }
+
+    async fn count(server: &mut Server) -> i64 {
+        server
+            .fetch_all::<i64>("SELECT COUNT(*)::bigint FROM flow_control_test")
+            .await
+            .unwrap()
+            .pop()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_stream_pauses_for_slow_destination() {
+        crate::logger();
+
+        let mut source = test_server().await;
+        let destination = Cluster::new_test_database("shard_0");
+        destination.launch();
+        let mut dest = destination.primary(0, &Request::default()).await.unwrap();
+
+        for query in [
+            "DROP PUBLICATION IF EXISTS flow_control_test",
+            "DROP TABLE IF EXISTS flow_control_test",
+            "CREATE TABLE flow_control_test (id BIGSERIAL PRIMARY KEY, value TEXT)",
+            "CREATE PUBLICATION flow_control_test FOR TABLE flow_control_test",
+        ] {
+            source.execute_checked(query).await.unwrap();
+        }
+        for query in [
+            "DROP TABLE IF EXISTS flow_control_test",
+            "CREATE TABLE flow_control_test (id BIGINT PRIMARY KEY, value TEXT)",
+        ] {
+            dest.execute_checked(query).await.unwrap();
+        }
+
+        let tables = Table::load("flow_control_test", &mut source).await.unwrap();
+        let addr = source.addr().clone();
+        let name = "flow_control_test_slot";
+        let mut slot = ReplicationSlot::replication("flow_control_test", &addr).with_name(name);
+        slot.create_or_resume().await.unwrap();
+
+        let mut stream = StreamSubscriber::new(&destination, &tables);
+        stream.set_current_lsn(slot.lsn().lsn);
+
+        // Destination that can't apply anything until the lock is released.
+        let mut lock = Server::connect(dest.addr(), ServerOptions::default())
+            .await
+            .unwrap();
+        lock.execute_checked("BEGIN").await.unwrap();
+        lock.execute_checked("LOCK TABLE flow_control_test IN ACCESS EXCLUSIVE MODE")
+            .await
+            .unwrap();
+
+        let progress = Progress::new_stream();
+        let backlog = Backlog::new(16 * 1024);
+        let handle = {
+            let (progress, backlog) = (progress.clone(), backlog.clone());
+            spawn(async move { Publisher::stream(&mut slot, stream, &progress, &backlog).await })
+        };
+
+        source
+            .execute_checked(
+                "INSERT INTO flow_control_test (value) SELECT repeat('x', 1024) FROM generate_series(1, 100)",
+            )
+            .await
+            .unwrap();
+
+        // Reading stops once the backlog is full.
+        wait_for("replication to pause", || progress.paused()).await;
+        assert!(backlog.bytes() > 8 * 1024);
+        assert!(slots().iter().any(|slot| slot.slot == name && slot.paused));
+
+        // And resumes once the destination catches up.
+        lock.execute_checked("ROLLBACK").await.unwrap();
+        wait_for("replication to resume", || !progress.paused()).await;
+
+        let mut rows = 0;
+        for _ in 0..100 {
+            rows = count(&mut dest).await;
+            if rows == 100 {
+                break;
+            }
+            sleep(Duration::from_millis(100)).await;
+        }
+        assert_eq!(rows, 100);
+        assert!(!backlog.paused());
+
+        handle.abort();
+        let _ = handle.await;
+        assert!(slots().iter().all(|slot| slot.slot != name));
+
+        let mut slot = ReplicationSlot::replication("flow_control_test", &addr).with_name(name);
+        slot.connect().await.unwrap();
+        slot.drop_slot().await.unwrap();
+        source
+            .execute_checked("DROP PUBLICATION flow_control_test")
+            .await
+            .unwrap();
+        destination.shutdown();
+    }
+}
@@ -122,6 +122,18 @@ impl ReplicationSlot {
         }
     }
 
+    /// Use this name instead of a random one, so the slot
+    /// can be found again after a restart.
+    pub fn with_name(mut self, name: &str) -> Self {
+        self.name = name.to_string();
+        self
+    }
+
+    /// Slot name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
     /// Connect to database using replication mode.
     pub async fn connect(&mut self) -> Result<(), Error> {
         self.server = Some(Server::connect(&self.address, ServerOptions::new_replication()).await?);
@@ -178,6 +190,39 @@ impl ReplicationSlot {
         Ok(lsn)
     }
 
+    /// Use the slot if it already exists, resuming from the last position
+    /// confirmed by the subscriber. Create it otherwise.
+    pub async fn create_or_resume(&mut self) -> Result<Lsn, Error> {
+        if self.server.is_none() {
+            self.connect().await?;
+        }
+
+        let existing = self
+            .server()?
+            .fetch_all::<DataRow>(&format!(
+                "SELECT confirmed_flush_lsn::text FROM pg_replication_slots WHERE slot_name = '{}'",
+                self.name
+            ))
+            .await?
+            .pop();
+
+        let Some(existing) = existing else {
+            return self.create_slot().await;
+        };
+
+        let lsn = existing
+            .get::<String>(0, Format::Text)
+            .ok_or(Error::MissingData)?;
+        self.lsn = Lsn::from_str(&lsn)?;
+
+        debug!(
+            "replication slot \"{}\" at lsn {} resumed [{}]",
+            self.name, self.lsn, self.address,
+        );
+
+        Ok(self.lsn)
+    }
+
     /// Drop the slot.
     pub async fn drop_slot(&mut self) -> Result<(), Error> {
         let drop_slot = self.drop_slot_query(true);
@@ -1,6 +1,10 @@
 //! Tables sharded in the database.
 use crate::{
     config::{DataType, ShardedTable},
+    frontend::router::{
+        parser::Shard,
+        sharding::{ContextBuilder, Value},
+    },
     net::messages::Vector,
 };
 use std::{collections::HashSet, sync::Arc};
@@ -55,7 +59,7 @@ impl ShardedTables {
             .find(|t| t.name.as_deref() == Some(name))
     }
 
-    /// Find out which column (if any) is sharded in the given table.
+    /// Find out which columns (if any) are the sharding key of the given table.
     pub fn sharded_column(&self, table: &str, columns: &[&str]) -> Option<ShardedColumn> {
         let with_names = self
             .tables()
@@ -64,28 +68,16 @@ impl ShardedTables {
             .collect::<Vec<_>>();
         let without_names = self.tables().iter().filter(|t| t.name.is_none());
 
-        let get_column = |sharded_table: &ShardedTable, columns: &[&str]| {
-            columns
-                .iter()
-                .position(|c| *c == sharded_table.column)
-                .map(|position| ShardedColumn {
-                    data_type: sharded_table.data_type,
-                    position,
-                    centroids: sharded_table.centroids.clone(),
-                    centroid_probes: sharded_table.centroid_probes,
-                })
-        };
-
         for sharded_table in with_names {
             if Some(table) == sharded_table.name.as_deref() {
-                if let Some(column) = get_column(sharded_table, columns) {
+                if let Some(column) = ShardedColumn::from_sharded_table(sharded_table, columns) {
                     return Some(column);
                 }
             }
         }
 
         for sharded_table in without_names {
-            if let Some(column) = get_column(sharded_table, columns) {
+            if let Some(column) = ShardedColumn::from_sharded_table(sharded_table, columns) {
                 return Some(column);
             }
         }
@@ -100,18 +92,86 @@ pub struct ShardedColumn {
     pub position: usize,
     pub centroids: Vec<Vector>,
     pub centroid_probes: usize,
+    /// Position of each sharding key column, in key order.
+    pub positions: Vec<usize>,
+    /// Table, if its sharding key has more than one column.
+    composite: Option<ShardedTable>,
 }
 
 impl ShardedColumn {
+    /// All sharding key columns must be present.
     pub fn from_sharded_table(table: &ShardedTable, columns: &[&str]) -> Option<Self> {
-        columns
+        let positions = table
+            .key_columns()
+            .into_iter()
+            .map(|key| columns.iter().position(|c| *c == key))
+            .collect::<Option<Vec<_>>>()?;
+
+        Some(ShardedColumn {
+            data_type: table.data_type,
+            position: positions[0],
+            centroids: table.centroids.clone(),
+            centroid_probes: table.centroid_probes,
+            positions,
+            composite: table.composite().then(|| table.clone()),
+        })
+    }
+
+    /// Sharding key has more than one column.
+    pub fn composite(&self) -> bool {
+        self.composite.is_some()
+    }
+
+    /// Shard for a composite sharding key, given the text
+    /// value of each key column, in key order.
+    pub fn composite_shard(&self, values: &[&str], shards: usize) -> Shard {
+        let Some(ref table) = self.composite else {
+            return Shard::All;
+        };
+
+        let values = values
             .iter()
-            .position(|c| *c == table.column.as_str())
-            .map(|index| ShardedColumn {
-                data_type: table.data_type,
-                position: index,
-                centroids: table.centroids.clone(),
-                centroid_probes: table.centroid_probes,
-            })
+            .enumerate()
+            .map(|(i, value)| Value::new(*value, table.data_type_at(i)))
+            .collect();
+
+        ContextBuilder::new(table)
+            .values(values)
+            .shards(shards)
+            .build()
+            .and_then(|context| context.apply())
+            .unwrap_or(Shard::All)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_composite_sharded_column() {
+        let tables = ShardedTables::from(
+            &[ShardedTable {
+                name: Some("accounts".into()),
+                columns: vec!["tenant_id".into(), "region".into()],
+                data_types: vec![DataType::Bigint, DataType::Varchar],
+                ..Default::default()
+            }][..],
+        );
+
+        // Whole key is needed.
+        assert!(tables
+            .sharded_column("accounts", &["id", "tenant_id"])
+            .is_none());
+
+        let column = tables
+            .sharded_column("accounts", &["id", "region", "tenant_id"])
+            .unwrap();
+        assert!(column.composite());
+        assert_eq!(column.positions, vec![2, 1]);
+
+        let shard = column.composite_shard(&["1", "us-east-1"], 4);
+        assert!(matches!(shard, Shard::Direct(_)));
+        assert_eq!(shard, column.composite_shard(&["1", "us-east-1"], 4));
     }
 }
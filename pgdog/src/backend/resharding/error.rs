@@ -0,0 +1,35 @@
+use std::path::PathBuf;
+
+use thiserror::Error;
+
+use super::Phase;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("{0}")]
+    Backend(#[from] crate::backend::Error),
+
+    #[error("{0}")]
+    Pool(#[from] crate::backend::pool::Error),
+
+    #[error("{0}")]
+    SchemaSync(#[from] crate::backend::schema::sync::Error),
+
+    #[error("{0}")]
+    Replication(#[from] crate::backend::replication::logical::Error),
+
+    #[error("state file \"{0}\": {1}")]
+    State(PathBuf, std::io::Error),
+
+    #[error("state file \"{0}\": {1}")]
+    StateFormat(PathBuf, serde_json::Error),
+
+    #[error("state file \"{0}\" is for a different resharding: {1}")]
+    StateMismatch(PathBuf, String),
+
+    #[error("can't cut over in phase \"{0}\"")]
+    NotReady(Phase),
+
+    #[error("replication stopped: {0}")]
+    ReplicationStopped(String),
+}
@@ -0,0 +1,573 @@
+//! Resharding: moving a database to a new cluster with a different number of shards.
+//!
+//! Phases run in order, see [`Phase`]. After the data sync, changes are streamed
+//! to the destination until the cutover. The state is saved after each phase, so
+//! a stopped resharding resumes from the phase it was in:
+//!
+//! * schema sync phases are re-run, skipping objects created by the previous attempt,
+//! * data sync truncates the destination tables and copies them again,
+//! * replication resumes from the last position confirmed by the destination.
+//!
+//! The cutover pauses the source database in PgDog, waits for the destination to
+//! replicate all changes and prints the configuration that serves the destination
+//! in its place. If the cutover fails, the source is resumed.
+
+pub mod error;
+pub mod state;
+
+use std::collections::{BTreeSet, HashMap};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+use tokio::spawn;
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, Instant};
+use tracing::{error, info, warn};
+
+pub use error::Error;
+pub use state::{Phase, State, TableLsn};
+
+use super::pool::{Address, Request};
+use super::replication::logical::publisher::progress::StreamsProgress;
+use super::replication::logical::publisher::{Lsn, ReplicationSlot};
+use super::replication::logical::Publisher;
+use super::schema::sync::pg_dump::{PgDump, SyncState};
+use super::{Cluster, Server, ServerOptions};
+use crate::config::Role;
+
+/// How often replication lag is checked.
+const LAG_CHECK_INTERVAL: Duration = Duration::from_millis(250);
+/// How often replication lag is logged.
+const LAG_REPORT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Resharding options.
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub publication: String,
+    /// Where the state is saved between phases.
+    pub state_path: PathBuf,
+    /// Replication lag, in bytes, low enough to cut over.
+    pub max_lag: u64,
+    /// Admin database of the PgDog serving the source, paused during the cutover.
+    /// If not set, writes to the source need to be stopped some other way.
+    pub pause: Option<Address>,
+}
+
+/// Source database paused in PgDog. If the cutover fails or is cancelled,
+/// the database is resumed, so clients don't wait forever.
+struct Pause {
+    server: Option<Server>,
+    database: String,
+}
+
+impl Pause {
+    async fn new(admin: &Address, database: &str) -> Result<Self, Error> {
+        let mut server = Server::connect(admin, ServerOptions::default()).await?;
+        server
+            .execute_checked(format!("PAUSE {}", database))
+            .await?;
+
+        Ok(Self {
+            server: Some(server),
+            database: database.to_owned(),
+        })
+    }
+
+    /// Leave the database paused.
+    fn keep(mut self) {
+        self.server.take();
+    }
+
+    /// Resume the database.
+    async fn resume(mut self) {
+        if let Some(server) = self.server.take() {
+            resume(server, &self.database).await;
+        }
+    }
+}
+
+impl Drop for Pause {
+    fn drop(&mut self) {
+        if let Some(server) = self.server.take() {
+            let database = self.database.clone();
+            spawn(async move { resume(server, &database).await });
+        }
+    }
+}
+
+async fn resume(mut server: Server, database: &str) {
+    info!("resuming \"{}\" [{}]", database, server.addr());
+    if let Err(err) = server.execute_checked(format!("RESUME {}", database)).await {
+        error!(
+            "failed to resume \"{}\": {} [{}]",
+            database,
+            err,
+            server.addr()
+        );
+    }
+}
+
+/// Changes streaming to the destination.
+#[derive(Debug)]
+struct Streaming {
+    handle: JoinHandle<Result<(), crate::backend::replication::logical::Error>>,
+    progress: StreamsProgress,
+}
+
+/// Resharding from source to destination cluster.
+#[derive(Debug)]
+pub struct Resharding {
+    source: Cluster,
+    destination: Cluster,
+    options: Options,
+    state: State,
+    /// Phase we resumed in, if it was started before.
+    resumed: Option<Phase>,
+    /// Publisher used for the data sync, until replication starts.
+    publisher: Option<Publisher>,
+    streaming: Option<Streaming>,
+}
+
+impl Resharding {
+    /// Start a new resharding or resume the one saved in the state file.
+    pub fn new(source: &Cluster, destination: &Cluster, options: Options) -> Result<Self, Error> {
+        let state = State::new(source.name(), destination.name(), &options.publication);
+
+        let (state, resumed) = match State::load(&options.state_path)? {
+            Some(saved) => {
+                saved.check(&state, &options.state_path)?;
+                info!(
+                    "resuming resharding of \"{}\" into \"{}\" in phase \"{}\"",
+                    saved.from, saved.to, saved.phase
+                );
+                let phase = saved.phase;
+                (saved, Some(phase))
+            }
+            None => {
+                state.save(&options.state_path)?;
+                (state, None)
+            }
+        };
+
+        Ok(Self {
+            source: source.clone(),
+            destination: destination.clone(),
+            options,
+            state,
+            resumed,
+            publisher: None,
+            streaming: None,
+        })
+    }
+
+    /// Current phase.
+    pub fn phase(&self) -> Phase {
+        self.state.phase
+    }
+
+    /// Resharding state.
+    pub fn state(&self) -> &State {
+        &self.state
+    }
+
+    /// Run all phases until the cutover, which has to be confirmed
+    /// by the operator. Replication keeps running in the background.
+    pub async fn prepare(&mut self) -> Result<(), Error> {
+        loop {
+            let phase = self.state.phase;
+
+            if phase >= Phase::Replication && phase < Phase::Done {
+                self.start_replication().await?;
+            }
+
+            match phase {
+                Phase::SchemaPreData => self.schema_sync(SyncState::PreData).await?,
+                Phase::DataSync => self.data_sync().await?,
+                Phase::Replication => (),
+                Phase::SchemaPostData => self.schema_sync(SyncState::PostData).await?,
+                Phase::CatchUp => self.catch_up().await?,
+                Phase::Cutover | Phase::Done => return Ok(()),
+            }
+
+            self.next_phase()?;
+        }
+    }
+
+    /// Pause writes to the source, wait for the destination to replicate
+    /// all changes and stop replication. Returns the configuration changes
+    /// that route traffic to the destination.
+    pub async fn cutover(&mut self) -> Result<String, Error> {
+        if self.state.phase != Phase::Cutover {
+            return Err(Error::NotReady(self.state.phase));
+        }
+
+        self.start_replication().await?;
+
+        let pause = if let Some(ref admin) = self.options.pause {
+            info!("pausing \"{}\" [{}]", self.source.name(), admin);
+            Some(Pause::new(admin, self.source.name()).await?)
+        } else {
+            warn!(
+                "not pausing \"{}\", make sure nothing is writing to it",
+                self.source.name()
+            );
+            None
+        };
+
+        match self.finish_cutover().await {
+            Ok(()) => {
+                // Clients wait until the configuration is reloaded.
+                if let Some(pause) = pause {
+                    pause.keep();
+                }
+                Ok(self.config_changes())
+            }
+
+            Err(err) => {
+                if let Some(pause) = pause {
+                    pause.resume().await;
+                }
+                Err(err)
+            }
+        }
+    }
+
+    /// Cutover steps that run while the source is paused.
+    async fn finish_cutover(&mut self) -> Result<(), Error> {
+        self.drain().await?;
+        // Sequences are set to the source values once no more rows are written to it.
+        self.schema_sync(SyncState::Cutover).await?;
+        self.stop_replication().await?;
+
+        self.next_phase()
+    }
+
+    /// Move to the next phase and save it.
+    fn next_phase(&mut self) -> Result<(), Error> {
+        info!("resharding phase \"{}\" complete", self.state.phase);
+        self.state.phase = self.state.phase.next();
+        self.state.save(&self.options.state_path)?;
+
+        Ok(())
+    }
+
+    /// Phase was started by a previous run.
+    fn resumed(&self) -> bool {
+        self.resumed == Some(self.state.phase)
+    }
+
+    async fn schema_sync(&mut self, state: SyncState) -> Result<(), Error> {
+        // Objects created by the previous attempt already exist.
+        let ignore_errors = self.resumed();
+        if ignore_errors {
+            warn!(
+                "phase \"{}\" was interrupted, ignoring schema errors",
+                self.state.phase
+            );
+        }
+
+        let dump = PgDump::new(&self.source, &self.options.publication);
+
+        for output in dump.dump().await? {
+            output
+                .restore(&self.destination, ignore_errors, state)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn data_sync(&mut self) -> Result<(), Error> {
+        let mut publisher = self.publisher();
+        publisher.sync_tables().await?;
+
+        // Remove rows copied by the previous attempt.
+        let tables = publisher
+            .tables()
+            .values()
+            .flatten()
+            .map(|table| format!("\"{}\".\"{}\"", table.table.schema, table.table.name))
+            .collect::<BTreeSet<_>>();
+
+        if self.resumed() && !tables.is_empty() {
+            let truncate = format!(
+                "TRUNCATE {}",
+                tables.into_iter().collect::<Vec<_>>().join(", ")
+            );
+
+            for shard in self.destination.shards() {
+                let mut primary = shard.primary(&Request::default()).await?;
+                info!("{} [{}]", truncate, primary.addr());
+                primary.execute_checked(truncate.as_str()).await?;
+            }
+        }
+
+        publisher.copy_data(&self.destination).await?;
+
+        self.state.tables = publisher
+            .tables()
+            .iter()
+            .flat_map(|(shard, tables)| {
+                tables.iter().map(|table| TableLsn {
+                    shard: *shard,
+                    schema: table.table.schema.clone(),
+                    name: table.table.name.clone(),
+                    lsn: table.lsn.to_string(),
+                })
+            })
+            .collect();
+        self.publisher = Some(publisher);
+
+        Ok(())
+    }
+
+    /// Start streaming changes, unless already running.
+    async fn start_replication(&mut self) -> Result<(), Error> {
+        if self.streaming.is_some() {
+            return Ok(());
+        }
+
+        let mut publisher = match self.publisher.take() {
+            Some(publisher) => publisher,
+            None => {
+                let mut publisher = self.publisher();
+                publisher.sync_tables().await?;
+                self.restore_table_lsns(&mut publisher)?;
+                publisher
+            }
+        };
+
+        let progress = publisher.streams();
+        let destination = self.destination.clone();
+        let handle = spawn(async move { publisher.replicate(&destination).await });
+
+        self.streaming = Some(Streaming { handle, progress });
+
+        Ok(())
+    }
+
+    /// Use table positions saved after the data sync.
+    fn restore_table_lsns(&self, publisher: &mut Publisher) -> Result<(), Error> {
+        for (shard, tables) in publisher.tables_mut() {
+            for table in tables {
+                let saved = self.state.tables.iter().find(|saved| {
+                    saved.shard == *shard
+                        && saved.schema == table.table.schema
+                        && saved.name == table.table.name
+                });
+
+                match saved {
+                    Some(saved) => {
+                        table.lsn = Lsn::from_str(&saved.lsn)
+                            .map_err(crate::backend::replication::logical::Error::from)?
+                    }
+                    None => warn!(
+                        "table \"{}\".\"{}\" wasn't copied, replicating all its changes",
+                        table.table.schema, table.table.name
+                    ),
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Wait for replication lag to get below the threshold.
+    async fn catch_up(&mut self) -> Result<(), Error> {
+        let mut reported = Instant::now();
+
+        loop {
+            let lag = self.lag().await?;
+            let max = lag.iter().copied().max().unwrap_or_default();
+
+            if max <= self.options.max_lag {
+                info!(
+                    "replication lag is {} bytes, below {} bytes, ready for cutover",
+                    max, self.options.max_lag
+                );
+                return Ok(());
+            }
+
+            if reported.elapsed() >= LAG_REPORT_INTERVAL {
+                let slot_lags = self
+                    .streaming
+                    .as_ref()
+                    .map(|streaming| streaming.progress.slot_lags())
+                    .unwrap_or_default();
+                for (shard, lag) in lag.iter().enumerate() {
+                    info!(
+                        "replication lag is {} bytes, source is holding {} bytes of WAL [shard {}]",
+                        lag,
+                        slot_lags.get(&shard).copied().unwrap_or_default(),
+                        shard
+                    );
+                }
+                reported = Instant::now();
+            }
+
+            sleep(LAG_CHECK_INTERVAL).await;
+        }
+    }
+
+    /// Replication lag of each source shard, in bytes.
+    async fn lag(&mut self) -> Result<Vec<u64>, Error> {
+        let replicated = self.replicated().await?;
+        let current = self.current_lsns().await?;
+
+        Ok(current
+            .into_iter()
+            .enumerate()
+            .map(|(shard, lsn)| {
+                let replicated = replicated.get(&shard).copied().unwrap_or_default();
+                lsn.saturating_sub(replicated).max(0) as u64
+            })
+            .collect())
+    }
+
+    /// Wait for the destination to replicate everything written to the source so far.
+    async fn drain(&mut self) -> Result<(), Error> {
+        let targets = self.current_lsns().await?;
+        let mut reported = Instant::now();
+
+        info!("waiting for replication to drain");
+
+        loop {
+            let replicated = self.replicated().await?;
+            let behind = targets
+                .iter()
+                .enumerate()
+                .filter(|(shard, target)| {
+                    replicated.get(shard).copied().unwrap_or_default() < **target
+                })
+                .collect::<Vec<_>>();
+
+            if behind.is_empty() {
+                info!("replication drained");
+                return Ok(());
+            }
+
+            if reported.elapsed() >= LAG_REPORT_INTERVAL {
+                for (shard, target) in behind {
+                    info!(
+                        "replicating up to {} [shard {}]",
+                        Lsn::from_i64(*target),
+                        shard
+                    );
+                }
+                reported = Instant::now();
+            }
+
+            sleep(LAG_CHECK_INTERVAL).await;
+        }
+    }
+
+    /// Stream positions, checking replication is still running.
+    async fn replicated(&mut self) -> Result<HashMap<usize, i64>, Error> {
+        let Some(ref streaming) = self.streaming else {
+            return Err(Error::ReplicationStopped("not started".into()));
+        };
+
+        if streaming.handle.is_finished() {
+            let streaming = self.streaming.take().unwrap();
+            let reason = match streaming.handle.await {
+                Ok(Err(err)) => err.to_string(),
+                Err(err) => err.to_string(),
+                Ok(Ok(())) => "stream ended".into(),
+            };
+            return Err(Error::ReplicationStopped(reason));
+        }
+
+        Ok(streaming.progress.lsns())
+    }
+
+    /// Current WAL position of each source shard.
+    async fn current_lsns(&self) -> Result<Vec<i64>, Error> {
+        let mut lsns = vec![];
+
+        for shard in self.source.shards() {
+            let mut primary = shard.primary(&Request::default()).await?;
+            let lsn = primary
+                .fetch_all::<String>("SELECT pg_current_wal_lsn()::text")
+                .await?
+                .pop()
+                .ok_or(crate::backend::replication::logical::Error::MissingData)?;
+            let lsn =
+                Lsn::from_str(&lsn).map_err(crate::backend::replication::logical::Error::from)?;
+            lsns.push(lsn.lsn);
+        }
+
+        Ok(lsns)
+    }
+
+    /// Stop streaming and drop the replication slots.
+    async fn stop_replication(&mut self) -> Result<(), Error> {
+        if let Some(streaming) = self.streaming.take() {
+            streaming.handle.abort();
+            let _ = streaming.handle.await;
+        }
+
+        for (number, shard) in self.source.shards().iter().enumerate() {
+            let addr = shard.primary(&Request::default()).await?.addr().clone();
+            let name = Publisher::shard_slot_name(&self.state.slot_name, number);
+            let mut slot =
+                ReplicationSlot::replication(&self.options.publication, &addr).with_name(&name);
+            slot.connect().await?;
+            // Waits for the aborted stream to release it.
+            slot.drop_slot().await?;
+        }
+
+        Ok(())
+    }
+
+    fn publisher(&self) -> Publisher {
+        Publisher::new(&self.source, &self.options.publication)
+            .with_slot_name(&self.state.slot_name)
+    }
+
+    /// Configuration serving the destination under the source's name.
+    fn config_changes(&self) -> String {
+        let mut config = format!(
+            "# Replace the \"{}\" databases in pgdog.toml with:\n",
+            self.source.name()
+        );
+
+        for (number, shard) in self.destination.shards().iter().enumerate() {
+            for (role, pool) in shard.pools_with_roles() {
+                let addr = pool.addr();
+                config.push_str(&format!(
+                    "\n[[databases]]\nname = \"{}\"\nhost = \"{}\"\nport = {}\ndatabase_name = \"{}\"\nshard = {}\n",
+                    self.source.name(),
+                    addr.host,
+                    addr.port,
+                    addr.database_name,
+                    number,
+                ));
+                if role == Role::Replica {
+                    config.push_str("role = \"replica\"\n");
+                }
+            }
+        }
+
+        config.push_str(&format!(
+            "\n# Move sharded tables and users of \"{}\" to \"{}\", run RELOAD and RESUME {} in the admin database.\n",
+            self.destination.name(),
+            self.source.name(),
+            self.source.name(),
+        ));
+
+        config
+    }
+}
+
+impl Drop for Resharding {
+    fn drop(&mut self) {
+        // Release the replication slots, so the next run can use them.
+        if let Some(ref streaming) = self.streaming {
+            streaming.handle.abort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test;
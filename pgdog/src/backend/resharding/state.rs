@@ -0,0 +1,182 @@
+//! Resharding state, saved between phases so an interrupted
+//! resharding can be resumed.
+
+use std::fmt::Display;
+use std::fs::{rename, write};
+use std::io::ErrorKind;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use super::Error;
+use crate::util::random_string;
+
+/// Resharding phase. Phases run in this order.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    /// Create tables in the destination.
+    #[default]
+    SchemaPreData,
+    /// Copy table data.
+    DataSync,
+    /// Start streaming changes made since the copy.
+    Replication,
+    /// Create indexes and constraints in the destination.
+    SchemaPostData,
+    /// Wait for replication lag to get below the threshold.
+    CatchUp,
+    /// Waiting for the operator to confirm the cutover.
+    Cutover,
+    /// Destination is ready to serve traffic.
+    Done,
+}
+
+impl Phase {
+    /// Phase after this one.
+    pub fn next(&self) -> Self {
+        match self {
+            Self::SchemaPreData => Self::DataSync,
+            Self::DataSync => Self::Replication,
+            Self::Replication => Self::SchemaPostData,
+            Self::SchemaPostData => Self::CatchUp,
+            Self::CatchUp => Self::Cutover,
+            Self::Cutover | Self::Done => Self::Done,
+        }
+    }
+}
+
+impl Display for Phase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::SchemaPreData => "schema sync (pre-data)",
+            Self::DataSync => "data sync",
+            Self::Replication => "replication",
+            Self::SchemaPostData => "schema sync (post-data)",
+            Self::CatchUp => "catch up",
+            Self::Cutover => "cutover",
+            Self::Done => "done",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Position of a table's copy in the WAL. Changes before it
+/// are already in the copied data.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TableLsn {
+    pub shard: usize,
+    pub schema: String,
+    pub name: String,
+    pub lsn: String,
+}
+
+/// Resharding state.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct State {
+    /// Source database.
+    pub from: String,
+    /// Destination database.
+    pub to: String,
+    pub publication: String,
+    /// Phase that is running, or will run next.
+    pub phase: Phase,
+    /// Prefix of the replication slot names, one slot per source shard.
+    pub slot_name: String,
+    /// Table positions, known once the data sync is done.
+    pub tables: Vec<TableLsn>,
+}
+
+impl State {
+    /// New resharding.
+    pub fn new(from: &str, to: &str, publication: &str) -> Self {
+        Self {
+            from: from.to_string(),
+            to: to.to_string(),
+            publication: publication.to_string(),
+            phase: Phase::default(),
+            slot_name: format!("__pgdog_repl_{}", random_string(19).to_lowercase()),
+            tables: vec![],
+        }
+    }
+
+    /// Load state saved by a previous run, if any.
+    pub fn load(path: &Path) -> Result<Option<Self>, Error> {
+        match std::fs::read_to_string(path) {
+            Ok(state) => serde_json::from_str(&state)
+                .map(Some)
+                .map_err(|err| Error::StateFormat(path.to_owned(), err)),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(Error::State(path.to_owned(), err)),
+        }
+    }
+
+    /// Save state. The file is replaced in one step,
+    /// so it's never partially written.
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let state = serde_json::to_string_pretty(self)
+            .map_err(|err| Error::StateFormat(path.to_owned(), err))?;
+        let tmp = path.with_extension("tmp");
+
+        write(&tmp, state)
+            .and_then(|_| rename(&tmp, path))
+            .map_err(|err| Error::State(path.to_owned(), err))
+    }
+
+    /// Check the state belongs to this resharding.
+    pub fn check(&self, other: &Self, path: &Path) -> Result<(), Error> {
+        for (name, ours, theirs) in [
+            ("source", &self.from, &other.from),
+            ("destination", &self.to, &other.to),
+            ("publication", &self.publication, &other.publication),
+        ] {
+            if ours != theirs {
+                return Err(Error::StateMismatch(
+                    path.to_owned(),
+                    format!("{} is \"{}\", not \"{}\"", name, ours, theirs),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_state() {
+        let path = std::env::temp_dir().join(format!(
+            "pgdog_resharding_state_{}.json",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+
+        assert!(State::load(&path).unwrap().is_none());
+
+        let mut state = State::new("prod", "prod_sharded", "all_tables");
+        state.phase = Phase::DataSync.next();
+        state.tables.push(TableLsn {
+            shard: 0,
+            schema: "public".into(),
+            name: "users".into(),
+            lsn: "0/16B3748".into(),
+        });
+        state.save(&path).unwrap();
+
+        let loaded = State::load(&path).unwrap().unwrap();
+        assert_eq!(loaded, state);
+        assert_eq!(loaded.phase, Phase::Replication);
+        loaded.check(&state, &path).unwrap();
+
+        let other = State::new("prod", "prod_sharded", "other_tables");
+        assert!(matches!(
+            state.check(&other, &path),
+            Err(Error::StateMismatch(_, _))
+        ));
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
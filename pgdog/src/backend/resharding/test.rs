@@ -0,0 +1,275 @@
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+use crate::backend::databases::{databases, init};
+use crate::backend::server::test::test_server;
+use crate::config::{set, ConfigAndUsers, Database, User};
+use crate::net::{
+    Authentication, BackendKeyData, CommandComplete, FromBytes, Query, ReadyForQuery, Stream,
+    ToBytes,
+};
+
+use super::*;
+
+fn load_config() {
+    let mut config = ConfigAndUsers::default();
+    config.config.databases = [
+        ("resharding_source", "pgdog"),
+        ("resharding_dest", "shard_0"),
+    ]
+    .into_iter()
+    .map(|(name, database_name)| Database {
+        name: name.into(),
+        host: "127.0.0.1".into(),
+        port: 5432,
+        database_name: Some(database_name.into()),
+        ..Default::default()
+    })
+    .collect();
+    config.users.users = ["resharding_source", "resharding_dest"]
+        .into_iter()
+        .map(|database| User {
+            name: "pgdog".into(),
+            database: database.into(),
+            password: Some("pgdog".into()),
+            ..Default::default()
+        })
+        .collect();
+
+    set(config).unwrap();
+    init();
+}
+
+async fn destination() -> Server {
+    let addr = Address {
+        database_name: "shard_0".into(),
+        ..Address::new_test()
+    };
+    Server::connect(&addr, ServerOptions::default())
+        .await
+        .unwrap()
+}
+
+async fn count(server: &mut Server) -> i64 {
+    server
+        .fetch_all::<i64>("SELECT COUNT(*)::bigint FROM resharding_test")
+        .await
+        .unwrap()
+        .pop()
+        .unwrap()
+}
+
+/// Wait for the destination to have this many rows.
+async fn wait_for_rows(server: &mut Server, rows: i64) {
+    for _ in 0..100 {
+        if count(server).await == rows {
+            return;
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+
+    panic!("destination doesn't have {} rows", rows);
+}
+
+/// Wait for the previous run to release the replication slot.
+async fn wait_for_slot(server: &mut Server, slot: &str) {
+    for _ in 0..100 {
+        let active = server
+            .fetch_all::<String>(format!(
+                "SELECT active::text FROM pg_replication_slots WHERE slot_name = '{}'",
+                slot
+            ))
+            .await
+            .unwrap();
+        if active == ["false"] {
+            return;
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+
+    panic!("slot \"{}\" is still in use", slot);
+}
+
+/// PgDog admin database that records the commands it receives.
+async fn mock_admin(commands: Arc<Mutex<Vec<String>>>) -> Address {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            let commands = commands.clone();
+
+            spawn(async move {
+                let mut len = stream.read_i32().await.unwrap();
+                // SSLRequest
+                if stream.read_i32().await.unwrap() == 80877103 {
+                    stream.write_u8(b'N').await.unwrap();
+                    len = stream.read_i32().await.unwrap();
+                    stream.read_i32().await.unwrap();
+                }
+                let mut startup = vec![0; len as usize - 8];
+                stream.read_exact(&mut startup).await.unwrap();
+
+                let mut stream = Stream::plain(stream);
+                stream.send(&Authentication::Ok).await.unwrap();
+                stream.send(&BackendKeyData::new()).await.unwrap();
+                stream.send_flush(&ReadyForQuery::idle()).await.unwrap();
+
+                while let Ok(message) = stream.read().await {
+                    if message.code() != 'Q' {
+                        break;
+                    }
+                    let query = Query::from_bytes(message.to_bytes().unwrap()).unwrap();
+                    let command = query.query().split_whitespace().next().unwrap().to_owned();
+                    commands.lock().push(query.query().to_owned());
+
+                    stream.send(&CommandComplete::new(command)).await.unwrap();
+                    stream.send_flush(&ReadyForQuery::idle()).await.unwrap();
+                }
+            });
+        }
+    });
+
+    Address {
+        port,
+        ..Address::new_test()
+    }
+}
+
+#[tokio::test]
+async fn test_pause_resumed_unless_kept() {
+    let commands = Arc::new(Mutex::new(vec![]));
+    let admin = mock_admin(commands.clone()).await;
+
+    // Failed cutover.
+    let pause = Pause::new(&admin, "source").await.unwrap();
+    pause.resume().await;
+    assert_eq!(*commands.lock(), ["PAUSE source", "RESUME source"]);
+
+    // Cancelled cutover.
+    commands.lock().clear();
+    let pause = Pause::new(&admin, "source").await.unwrap();
+    drop(pause);
+    for _ in 0..100 {
+        if commands.lock().len() == 2 {
+            break;
+        }
+        sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(*commands.lock(), ["PAUSE source", "RESUME source"]);
+
+    // Successful cutover.
+    commands.lock().clear();
+    let pause = Pause::new(&admin, "source").await.unwrap();
+    pause.keep();
+    sleep(Duration::from_millis(100)).await;
+    assert_eq!(*commands.lock(), ["PAUSE source"]);
+}
+
+#[tokio::test]
+async fn test_resharding() {
+    let mut source = test_server().await;
+    let mut dest = destination().await;
+
+    for query in [
+        "DROP PUBLICATION IF EXISTS resharding_test",
+        "DROP TABLE IF EXISTS resharding_test",
+        "CREATE TABLE resharding_test (id BIGSERIAL PRIMARY KEY, value TEXT)",
+        "CREATE INDEX resharding_test_value_idx ON resharding_test (value)",
+        "INSERT INTO resharding_test (value) SELECT 'value_' || i FROM generate_series(1, 100) i",
+        "CREATE PUBLICATION resharding_test FOR TABLE resharding_test",
+    ] {
+        source.execute_checked(query).await.unwrap();
+    }
+    dest.execute_checked("DROP TABLE IF EXISTS resharding_test")
+        .await
+        .unwrap();
+
+    load_config();
+    let from = databases().cluster(("pgdog", "resharding_source")).unwrap();
+    let to = databases().cluster(("pgdog", "resharding_dest")).unwrap();
+
+    let state_path =
+        std::env::temp_dir().join(format!("pgdog_resharding_{}.json", std::process::id()));
+    let _ = std::fs::remove_file(&state_path);
+    let options = Options {
+        publication: "resharding_test".into(),
+        state_path: state_path.clone(),
+        max_lag: 16 * 1024 * 1024,
+        pause: None,
+    };
+
+    let mut resharding = Resharding::new(&from, &to, options.clone()).unwrap();
+    assert!(matches!(
+        resharding.cutover().await,
+        Err(Error::NotReady(Phase::SchemaPreData))
+    ));
+
+    resharding.prepare().await.unwrap();
+    assert_eq!(resharding.phase(), Phase::Cutover);
+    assert_eq!(
+        State::load(&state_path).unwrap().unwrap().phase,
+        Phase::Cutover
+    );
+    assert_eq!(count(&mut dest).await, 100);
+
+    // Changes are streamed.
+    source
+        .execute_checked("INSERT INTO resharding_test (value) VALUES ('streamed')")
+        .await
+        .unwrap();
+    wait_for_rows(&mut dest, 101).await;
+
+    let slot = Publisher::shard_slot_name(&resharding.state().slot_name, 0);
+    drop(resharding);
+    wait_for_slot(&mut source, &slot).await;
+
+    // Changes made while stopped are replicated once resumed.
+    source
+        .execute_checked("INSERT INTO resharding_test (value) VALUES ('resumed')")
+        .await
+        .unwrap();
+
+    let mut resharding = Resharding::new(&from, &to, options).unwrap();
+    assert_eq!(resharding.phase(), Phase::Cutover);
+    resharding.prepare().await.unwrap();
+
+    let config = resharding.cutover().await.unwrap();
+    assert_eq!(resharding.phase(), Phase::Done);
+    assert!(config.contains("name = \"resharding_source\""));
+    assert!(config.contains("database_name = \"shard_0\""));
+    assert_eq!(count(&mut dest).await, 102);
+
+    // Post-data schema and sequences are in place.
+    let indexes = dest
+        .fetch_all::<String>(
+            "SELECT indexname::text FROM pg_indexes WHERE indexname = 'resharding_test_value_idx'",
+        )
+        .await
+        .unwrap();
+    assert_eq!(indexes.len(), 1);
+    let next = dest
+        .fetch_all::<i64>("SELECT nextval('resharding_test_id_seq')")
+        .await
+        .unwrap();
+    assert_eq!(next, [103]);
+
+    // Slots are dropped.
+    let slots = source
+        .fetch_all::<String>(format!(
+            "SELECT slot_name::text FROM pg_replication_slots WHERE slot_name = '{}'",
+            slot
+        ))
+        .await
+        .unwrap();
+    assert!(slots.is_empty());
+
+    source
+        .execute_checked("DROP PUBLICATION resharding_test")
+        .await
+        .unwrap();
+    let _ = std::fs::remove_file(&state_path);
+}
@@ -0,0 +1,268 @@
+//! Column order cache.
+//!
+//! Used to find the sharding key in statements without a column list,
+//! e.g. `INSERT INTO sharded VALUES (...)` or `COPY sharded FROM STDIN`.
+
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use parking_lot::Mutex;
+use tokio::runtime::Handle;
+use tracing::{debug, warn};
+
+use super::Error;
+use crate::{
+    backend::{
+        pool::{Request, Shard},
+        Server,
+    },
+    net::messages::DataRow,
+};
+
+static COLUMN_ORDER: &str = include_str!("column_order.sql");
+
+/// How long loaded columns are used before reloading them.
+const TTL: Duration = Duration::from_secs(300);
+/// Minimum time between warnings about failed loads.
+const WARNING_INTERVAL: Duration = Duration::from_secs(60);
+
+type Tables = HashMap<(String, String), Arc<Vec<String>>>;
+
+struct ColumnRow {
+    schema: String,
+    table: String,
+    column: String,
+}
+
+impl From<DataRow> for ColumnRow {
+    fn from(value: DataRow) -> Self {
+        Self {
+            schema: value.get_text(0).unwrap_or_default(),
+            table: value.get_text(1).unwrap_or_default(),
+            column: value.get_text(2).unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    tables: Arc<Tables>,
+    loaded_at: Option<Instant>,
+    loading: bool,
+    generation: usize,
+    last_warning: Option<Instant>,
+}
+
+/// Column order of all tables, loaded on first use.
+#[derive(Debug, Clone, Default)]
+pub struct ColumnOrder {
+    inner: Arc<Mutex<Inner>>,
+    shard: Option<Shard>,
+}
+
+impl ColumnOrder {
+    /// Create cache loading columns from the given shard.
+    pub fn new(shard: Option<Shard>) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Inner::default())),
+            shard,
+        }
+    }
+
+    /// Get columns of a table, in the order they are defined.
+    ///
+    /// Loads columns in the background if they are missing or expired,
+    /// returning `None` until they are available.
+    pub fn columns(&self, schema: Option<&str>, table: &str) -> Option<Arc<Vec<String>>> {
+        let tables = {
+            let mut guard = self.inner.lock();
+            let fresh = guard
+                .loaded_at
+                .map(|loaded_at| loaded_at.elapsed() < TTL)
+                .unwrap_or(false);
+
+            if !fresh && !guard.loading && self.refresh(guard.generation) {
+                guard.loading = true;
+            }
+
+            guard.tables.clone()
+        };
+
+        Self::lookup(&tables, schema, table)
+    }
+
+    /// Drop loaded columns, e.g. after DDL.
+    pub fn invalidate(&self) {
+        let mut guard = self.inner.lock();
+        guard.tables = Arc::new(Tables::default());
+        guard.loaded_at = None;
+        guard.loading = false;
+        guard.generation += 1;
+    }
+
+    /// Load column order for all tables.
+    pub async fn load(
+        server: &mut Server,
+    ) -> Result<HashMap<(String, String), Vec<String>>, Error> {
+        let mut tables = HashMap::new();
+        let rows: Vec<ColumnRow> = server.fetch_all(COLUMN_ORDER).await?;
+
+        for row in rows {
+            tables
+                .entry((row.schema, row.table))
+                .or_insert_with(Vec::new)
+                .push(row.column);
+        }
+
+        Ok(tables)
+    }
+
+    /// Set columns directly.
+    pub fn set(&self, tables: HashMap<(String, String), Vec<String>>) {
+        let generation = self.inner.lock().generation;
+        self.loaded(generation, tables);
+    }
+
+    fn lookup(tables: &Tables, schema: Option<&str>, table: &str) -> Option<Arc<Vec<String>>> {
+        if let Some(columns) =
+            tables.get(&(schema.unwrap_or("public").to_owned(), table.to_owned()))
+        {
+            return Some(columns.clone());
+        }
+
+        if schema.is_some() {
+            return None;
+        }
+
+        // Not in public, use it only if the name is unambiguous.
+        let mut matches = tables.iter().filter(|((_, name), _)| name == table);
+        match (matches.next(), matches.next()) {
+            (Some((_, columns)), None) => Some(columns.clone()),
+            _ => None,
+        }
+    }
+
+    fn refresh(&self, generation: usize) -> bool {
+        let (Some(shard), Ok(handle)) = (self.shard.clone(), Handle::try_current()) else {
+            return false;
+        };
+
+        let me = self.clone();
+        handle.spawn(async move {
+            let result = match shard.primary_or_replica(&Request::default()).await {
+                Ok(mut server) => Self::load(&mut server).await,
+                Err(err) => Err(err.into()),
+            };
+
+            match result {
+                Ok(tables) => me.loaded(generation, tables),
+                Err(err) => me.failed(generation, &err),
+            }
+        });
+
+        true
+    }
+
+    fn loaded(&self, generation: usize, tables: HashMap<(String, String), Vec<String>>) {
+        let mut guard = self.inner.lock();
+
+        // Invalidated while loading.
+        if guard.generation != generation {
+            return;
+        }
+
+        debug!("loaded column order for {} tables", tables.len());
+
+        guard.tables = Arc::new(
+            tables
+                .into_iter()
+                .map(|(table, columns)| (table, Arc::new(columns)))
+                .collect(),
+        );
+        guard.loaded_at = Some(Instant::now());
+        guard.loading = false;
+    }
+
+    fn failed(&self, generation: usize, err: &Error) {
+        let mut guard = self.inner.lock();
+
+        if guard.generation == generation {
+            guard.loading = false;
+        }
+
+        let warn_now = guard
+            .last_warning
+            .map(|last_warning| last_warning.elapsed() >= WARNING_INTERVAL)
+            .unwrap_or(true);
+
+        if warn_now {
+            guard.last_warning = Some(Instant::now());
+            warn!("failed to load column order: {}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::backend::pool::test::pool;
+
+    use super::*;
+
+    fn tables() -> HashMap<(String, String), Vec<String>> {
+        HashMap::from([
+            (
+                ("public".into(), "sharded".into()),
+                vec!["value".into(), "id".into()],
+            ),
+            (
+                ("tenant".into(), "users".into()),
+                vec!["customer_id".into(), "email".into()],
+            ),
+        ])
+    }
+
+    #[test]
+    fn test_column_order() {
+        let cache = ColumnOrder::default();
+        assert!(cache.columns(None, "sharded").is_none());
+
+        cache.set(tables());
+        assert_eq!(
+            cache.columns(None, "sharded").unwrap().as_slice(),
+            ["value", "id"]
+        );
+        assert_eq!(
+            cache.columns(None, "users").unwrap().as_slice(),
+            ["customer_id", "email"]
+        );
+        assert!(cache.columns(Some("public"), "users").is_none());
+
+        cache.invalidate();
+        assert!(cache.columns(None, "sharded").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_load_column_order() {
+        let pool = pool();
+        let mut conn = pool.get(&Request::default()).await.unwrap();
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS test_column_order (value TEXT, id BIGINT, created_at TIMESTAMPTZ)",
+        )
+        .await
+        .unwrap();
+        conn.execute("ALTER TABLE test_column_order DROP COLUMN IF EXISTS created_at")
+            .await
+            .unwrap();
+
+        let tables = ColumnOrder::load(&mut conn).await.unwrap();
+        assert_eq!(
+            tables
+                .get(&("public".into(), "test_column_order".into()))
+                .unwrap(),
+            &["value", "id"]
+        );
+    }
+}
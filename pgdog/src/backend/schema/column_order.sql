@@ -0,0 +1,17 @@
+SELECT
+    n.nspname::text,
+    c.relname::text,
+    a.attname::text
+FROM
+    pg_attribute a
+    INNER JOIN pg_class c ON c.oid = a.attrelid
+    INNER JOIN pg_namespace n ON n.oid = c.relnamespace
+WHERE
+    c.relkind IN ('r', 'p')
+    AND a.attnum > 0
+    AND NOT a.attisdropped
+    AND n.nspname NOT IN ('pg_catalog', 'information_schema')
+ORDER BY
+    n.nspname,
+    c.relname,
+    a.attnum;
@@ -1,12 +1,15 @@
 //! Schema operations.
+pub mod column_order;
 pub mod columns;
 pub mod relation;
+pub mod sharding;
 pub mod sync;
 
 use std::sync::Arc;
 use std::{collections::HashMap, ops::Deref};
 use tracing::debug;
 
+pub use column_order::ColumnOrder;
 pub use relation::Relation;
 
 use super::{pool::Request, Cluster, Error, Server};
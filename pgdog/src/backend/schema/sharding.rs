@@ -0,0 +1,305 @@
+//! Check sharded tables against the database schema.
+//!
+//! A `data_type` that doesn't match the column type hashes
+//! sharding keys differently than the data was sharded with,
+//! so queries go to the wrong shards without any errors.
+
+use std::collections::HashMap;
+
+use thiserror::Error;
+use tokio::sync::watch;
+
+use super::Error;
+use crate::{
+    backend::Server,
+    config::{DataType, ShardedTable},
+    net::messages::DataRow,
+};
+
+static SHARDING: &str = include_str!("sharding.sql");
+
+/// Sharded table configuration that doesn't match the database schema.
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum Mismatch {
+    #[error("table \"{0}\" has no sharding column \"{1}\"")]
+    MissingColumn(String, String),
+
+    #[error("sharding column \"{1}\" of table \"{0}\" is {2}, but its data_type is \"{3}\"")]
+    DataType(String, String, String, DataType),
+
+    #[error("table \"{0}\" is hash partitioned with modulus {1}, but there are {2} shards")]
+    PartitionModulus(String, usize, usize),
+}
+
+/// Result of checking sharded tables against the schema.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Validation {
+    /// Not checked yet.
+    Pending,
+    /// Databases couldn't be reached, checking again later.
+    Unavailable,
+    /// Sharded tables match the schema.
+    Valid,
+    /// Some sharded tables don't match the schema.
+    Invalid,
+}
+
+/// Latest validation of a cluster's sharded tables.
+#[derive(Debug)]
+pub struct ValidationStatus {
+    tx: watch::Sender<Validation>,
+}
+
+impl Default for ValidationStatus {
+    fn default() -> Self {
+        Self {
+            tx: watch::Sender::new(Validation::Pending),
+        }
+    }
+}
+
+impl ValidationStatus {
+    /// Record validation result.
+    pub fn set(&self, validation: Validation) {
+        self.tx.send_replace(validation);
+    }
+
+    /// Wait for the first validation attempt to finish.
+    pub async fn attempted(&self) -> Validation {
+        let mut rx = self.tx.subscribe();
+        rx.wait_for(|validation| *validation != Validation::Pending)
+            .await
+            .map(|validation| *validation)
+            .unwrap_or(Validation::Pending)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct ColumnRow {
+    schema: String,
+    table: String,
+    column: String,
+    data_type: String,
+    partition_strategy: String,
+    partition_key: bool,
+    modulus: usize,
+}
+
+impl From<DataRow> for ColumnRow {
+    fn from(value: DataRow) -> Self {
+        Self {
+            schema: value.get_text(0).unwrap_or_default(),
+            table: value.get_text(1).unwrap_or_default(),
+            column: value.get_text(2).unwrap_or_default(),
+            data_type: value.get_text(3).unwrap_or_default(),
+            partition_strategy: value.get_text(4).unwrap_or_default(),
+            partition_key: value.get_text(5).unwrap_or_default() == "true",
+            modulus: value
+                .get_text(6)
+                .and_then(|modulus| modulus.parse().ok())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// Check sharded tables against the schema of one shard.
+pub async fn validate(
+    server: &mut Server,
+    tables: &[ShardedTable],
+    shards: usize,
+) -> Result<Vec<Mismatch>, Error> {
+    let rows: Vec<ColumnRow> = server.fetch_all(SHARDING).await?;
+    Ok(mismatches(&rows, tables, shards))
+}
+
+/// Column types hashed the same way as the data type.
+fn column_types(data_type: DataType) -> &'static [&'static str] {
+    match data_type {
+        // Postgres hashes integers of all sizes the same way.
+        DataType::Bigint => &["bigint", "integer", "smallint"],
+        DataType::Uuid => &["uuid"],
+        DataType::Varchar => &["character varying", "text"],
+        DataType::Vector => &["vector"],
+    }
+}
+
+fn mismatches(rows: &[ColumnRow], tables: &[ShardedTable], shards: usize) -> Vec<Mismatch> {
+    let mut relations: HashMap<(&str, &str), HashMap<&str, &ColumnRow>> = HashMap::new();
+    for row in rows {
+        relations
+            .entry((row.schema.as_str(), row.table.as_str()))
+            .or_default()
+            .insert(row.column.as_str(), row);
+    }
+
+    let mut mismatches = vec![];
+
+    for table in tables {
+        let Some(ref name) = table.name else {
+            continue;
+        };
+
+        // Tables that don't exist yet are checked once they are created.
+        for ((schema, _), columns) in relations
+            .iter()
+            .filter(|((_, relation), _)| *relation == name.as_str())
+        {
+            let qualified = format!("{}.{}", schema, name);
+
+            for (position, column) in table.key_columns().into_iter().enumerate() {
+                let Some(row) = columns.get(column) else {
+                    mismatches.push(Mismatch::MissingColumn(
+                        qualified.clone(),
+                        column.to_owned(),
+                    ));
+                    continue;
+                };
+
+                let data_type = table.data_type_at(position);
+                if !column_types(data_type).contains(&row.data_type.as_str()) {
+                    mismatches.push(Mismatch::DataType(
+                        qualified.clone(),
+                        column.to_owned(),
+                        row.data_type.clone(),
+                        data_type,
+                    ));
+                }
+            }
+
+            // Hash partitions on the sharding key split data like shards do.
+            let key = table.key_columns();
+            let partitioned = key.iter().all(|column| {
+                columns
+                    .get(column)
+                    .map(|row| row.partition_strategy == "h" && row.partition_key)
+                    .unwrap_or(false)
+            });
+            let modulus = columns.values().next().map(|row| row.modulus).unwrap_or(0);
+
+            if partitioned && shards > 1 && modulus > 0 && modulus != shards {
+                mismatches.push(Mismatch::PartitionModulus(qualified, modulus, shards));
+            }
+        }
+    }
+
+    mismatches
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::backend::pool::test::pool;
+    use crate::backend::pool::Request;
+
+    fn table(name: &str, column: &str, data_type: DataType) -> ShardedTable {
+        ShardedTable {
+            database: "pgdog".into(),
+            name: Some(name.into()),
+            column: column.into(),
+            data_type,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_validate_sharding_schema() {
+        let pool = pool();
+        let mut conn = pool.get(&Request::default()).await.unwrap();
+
+        // Deliberately wrong configuration for the test schema.
+        let tables = vec![
+            table("sharded", "id", DataType::Uuid),
+            table("sharded", "value", DataType::Varchar),
+            table("sharded_uuid", "id_uuid", DataType::Bigint),
+            table("sharded_uuid", "id", DataType::Uuid),
+            table("sharded_missing", "id", DataType::Bigint),
+            ShardedTable {
+                name: None,
+                ..table("", "id", DataType::Uuid)
+            },
+        ];
+
+        let mismatches = validate(&mut conn, &tables, 2).await.unwrap();
+        assert_eq!(
+            mismatches,
+            vec![
+                Mismatch::DataType(
+                    "public.sharded".into(),
+                    "id".into(),
+                    "bigint".into(),
+                    DataType::Uuid
+                ),
+                Mismatch::DataType(
+                    "public.sharded_uuid".into(),
+                    "id_uuid".into(),
+                    "uuid".into(),
+                    DataType::Bigint
+                ),
+                Mismatch::MissingColumn("public.sharded_uuid".into(), "id".into()),
+            ]
+        );
+
+        let tables = vec![table("sharded", "id", DataType::Bigint)];
+        assert!(validate(&mut conn, &tables, 2).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_validate_partition_modulus() {
+        let pool = pool();
+        let mut conn = pool.get(&Request::default()).await.unwrap();
+
+        conn.execute(
+            "CREATE TEMP TABLE test_partition_modulus (id BIGINT, tenant_id BIGINT) PARTITION BY HASH (id)",
+        )
+        .await
+        .unwrap();
+        for remainder in 0..3 {
+            conn.execute(format!(
+                "CREATE TEMP TABLE test_partition_modulus_{0} PARTITION OF test_partition_modulus FOR VALUES WITH (MODULUS 3, REMAINDER {0})",
+                remainder
+            ))
+            .await
+            .unwrap();
+        }
+        let schema = conn
+            .fetch_all::<String>("SELECT pg_my_temp_schema()::regnamespace::text")
+            .await
+            .unwrap()
+            .pop()
+            .unwrap();
+
+        let tables = vec![
+            table("test_partition_modulus", "id", DataType::Bigint),
+            // Partitioned by another column.
+            table("test_partition_modulus", "tenant_id", DataType::Bigint),
+        ];
+
+        assert_eq!(
+            validate(&mut conn, &tables, 2).await.unwrap(),
+            vec![Mismatch::PartitionModulus(
+                format!("{}.test_partition_modulus", schema),
+                3,
+                2
+            )]
+        );
+        assert!(validate(&mut conn, &tables, 3).await.unwrap().is_empty());
+
+        conn.execute("DROP TABLE test_partition_modulus")
+            .await
+            .unwrap();
+    }
+
+    #[test]
+    fn test_mismatch_message() {
+        let mismatch = Mismatch::DataType(
+            "public.sharded".into(),
+            "id".into(),
+            "uuid".into(),
+            DataType::Bigint,
+        );
+        assert_eq!(
+            mismatch.to_string(),
+            r#"sharding column "id" of table "public.sharded" is uuid, but its data_type is "bigint""#
+        );
+    }
+}
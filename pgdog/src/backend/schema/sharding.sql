@@ -0,0 +1,27 @@
+SELECT
+    n.nspname::text,
+    c.relname::text,
+    a.attname::text,
+    format_type(a.atttypid, NULL)::text,
+    COALESCE(p.partstrat::text, ''),
+    COALESCE(a.attnum = ANY(p.partattrs::int2[]), false)::text,
+    COALESCE((
+        SELECT
+            max(substring(pg_get_expr(part.relpartbound, part.oid) FROM 'modulus (\d+)')::int)
+        FROM
+            pg_inherits i
+            INNER JOIN pg_class part ON part.oid = i.inhrelid
+        WHERE
+            i.inhparent = c.oid
+    ), 0)::text
+FROM
+    pg_attribute a
+    INNER JOIN pg_class c ON c.oid = a.attrelid
+    INNER JOIN pg_namespace n ON n.oid = c.relnamespace
+    LEFT JOIN pg_partitioned_table p ON p.partrelid = c.oid
+WHERE
+    c.relkind IN ('r', 'p')
+    AND NOT c.relispartition
+    AND a.attnum > 0
+    AND NOT a.attisdropped
+    AND n.nspname NOT IN ('pg_catalog', 'information_schema', 'pgdog');
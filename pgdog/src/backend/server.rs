@@ -12,8 +12,9 @@ use tokio::{
 use tracing::{debug, error, info, trace, warn};
 
 use super::{
-    pool::Address, prepared_statements::HandleResult, Error, PreparedStatements, ServerOptions,
-    Stats,
+    pool::{Address, SessionSetup},
+    prepared_statements::HandleResult,
+    Error, PreparedStatements, ServerOptions, ServerTls, Stats,
 };
 use crate::{
     auth::{md5, scram::Client},
@@ -23,16 +24,16 @@ use crate::{
             hello::SslReply, Authentication, BackendKeyData, ErrorResponse, FromBytes, Message,
             ParameterStatus, Password, Protocol, Query, ReadyForQuery, Startup, Terminate, ToBytes,
         },
-        Close, Parameter, ProtocolMessage, Sync,
+        Close, Parameter, Parse, ProtocolMessage, Sync,
     },
     stats::memory::MemoryUsage,
 };
 use crate::{
     config::{config, PoolerMode, TlsVerifyMode},
     net::{
-        messages::{DataRow, NoticeResponse},
+        messages::{DataRow, Format, NoticeResponse},
         parameter::Parameters,
-        tls::connector_with_verify_mode,
+        tls::{connector, connector_with_verify_mode},
         CommandComplete, Stream,
     },
 };
@@ -59,6 +60,8 @@ pub struct Server {
     replication_mode: bool,
     pooler_mode: PoolerMode,
     stream_buffer: BytesMut,
+    /// Session setup statements that ran on this connection.
+    session_setup: u64,
 }
 
 impl MemoryUsage for Server {
@@ -73,23 +76,63 @@ impl MemoryUsage for Server {
             + 7 * std::mem::size_of::<bool>()
             + std::mem::size_of::<PoolerMode>()
             + self.stream_buffer.capacity()
+            + self
+                .stream
+                .as_ref()
+                .map(|stream| stream.memory_usage())
+                .unwrap_or(0)
+            + std::mem::size_of::<u64>()
     }
 }
 
 impl Server {
     /// Create new PostgreSQL server connection.
     pub async fn connect(addr: &Address, options: ServerOptions) -> Result<Self, Error> {
+        let tls_mode = config().config.general.tls_verify;
+        Self::connect_tls(addr, options, tls_mode).await
+    }
+
+    /// Create new server connection, requesting TLS if the pool prefers it.
+    ///
+    /// If the server rejects the connection because of its encryption, e.g. it
+    /// started requiring TLS, we retry once the other way and remember what worked.
+    pub(crate) async fn connect_tls(
+        addr: &Address,
+        options: ServerOptions,
+        tls_mode: TlsVerifyMode,
+    ) -> Result<Self, Error> {
+        let tls = options.tls.request(tls_mode);
+
+        match Self::connect_once(addr, options.clone(), tls_mode, tls).await {
+            Err(err) if ServerTls::retry(tls_mode, tls, &err) => {
+                warn!(
+                    "{}, retrying {} TLS [{}]",
+                    err,
+                    if tls { "without" } else { "with" },
+                    addr
+                );
+                let server = Self::connect_once(addr, options.clone(), tls_mode, !tls).await?;
+                options.tls.set(!tls);
+                Ok(server)
+            }
+
+            result => result,
+        }
+    }
+
+    async fn connect_once(
+        addr: &Address,
+        options: ServerOptions,
+        tls_mode: TlsVerifyMode,
+        tls: bool,
+    ) -> Result<Self, Error> {
         debug!("=> {}", addr);
         let stream = TcpStream::connect(addr.addr().await?).await?;
         tweak(&stream)?;
 
         let mut stream = Stream::plain(stream);
 
-        let cfg = config();
-        let tls_mode = cfg.config.general.tls_verify;
-
-        // Only attempt TLS if not in Disabled mode
-        if tls_mode != TlsVerifyMode::Disabled {
+        if tls {
             debug!(
                 "requesting TLS connection with verify mode: {:?} [{}]",
                 tls_mode, addr,
@@ -106,10 +149,12 @@ impl Server {
             if ssl == SslReply::Yes {
                 debug!("server supports TLS, initiating TLS handshake [{}]", addr);
 
-                let connector = connector_with_verify_mode(
-                    tls_mode,
-                    cfg.config.general.tls_server_ca_certificate.as_ref(),
-                )?;
+                // Server started requiring TLS, but we don't verify certificates.
+                let connector = if tls_mode == TlsVerifyMode::Disabled {
+                    connector_with_verify_mode(TlsVerifyMode::Prefer, None)?
+                } else {
+                    connector()?
+                };
                 let plain = stream.take()?;
 
                 let server_name = ServerName::try_from(addr.host.clone())?;
@@ -138,12 +183,10 @@ impl Server {
                     "server does not support TLS, continuing without encryption [{}]",
                     addr
                 );
+                options.tls.reset();
             }
         } else {
-            debug!(
-                "TLS verification mode is None, skipping TLS entirely [{}]",
-                addr
-            );
+            debug!("not requesting TLS [{}]", addr);
         }
 
         stream
@@ -256,6 +299,7 @@ impl Server {
             re_synced: false,
             pooler_mode: PoolerMode::Transaction,
             stream_buffer: BytesMut::with_capacity(1024),
+            session_setup: 0,
         };
 
         server.stats.memory_used(server.memory_usage()); // Stream capacity.
@@ -346,7 +390,7 @@ impl Server {
                 .await
             {
                 Ok(message) => {
-                    let message = message.stream(self.streaming).backend();
+                    let message = message.backend();
                     match self.prepared_statements.forward(&message) {
                         Ok(forward) => {
                             if forward {
@@ -391,7 +435,11 @@ impl Server {
                         self.in_transaction = true;
                         self.stats.state(State::IdleInTransaction);
                     }
-                    'E' => self.stats.transaction_error(now),
+                    'E' => {
+                        // Aborted transaction still needs a ROLLBACK.
+                        self.in_transaction = true;
+                        self.stats.transaction_error(now);
+                    }
                     status => {
                         self.stats.state(State::Error);
                         return Err(Error::UnexpectedTransactionStatus(status));
@@ -418,6 +466,7 @@ impl Server {
                 match cmd.command() {
                     "PREPARE" | "DEALLOCATE" => self.sync_prepared = true,
                     "RESET" => self.client_params.clear(), // Someone reset params, we're gonna need to re-sync.
+                    "DISCARD ALL" => self.session_setup = 0,
                     _ => (),
                 }
             }
@@ -427,7 +476,8 @@ impl Server {
             _ => (),
         }
 
-        Ok(message)
+        // CopyBothResponse starts the stream and ReadyForQuery ends it.
+        Ok(message.stream(self.streaming))
     }
 
     /// Synchronize parameters between client and server.
@@ -447,8 +497,8 @@ impl Server {
         // Compare client and server params.
         if !params.identical(&self.client_params) {
             let tracked = params.tracked();
-            let mut queries = self.client_params.reset_queries();
-            queries.extend(tracked.set_queries());
+            // Only change what's different, e.g. application_name.
+            let queries = tracked.sync_queries(&self.current_params(&tracked));
             if !queries.is_empty() {
                 debug!("syncing {} params", queries.len());
                 self.execute_batch(&queries).await?;
@@ -460,6 +510,48 @@ impl Server {
         }
     }
 
+    /// Run the session setup statements, unless they already ran on this connection.
+    pub async fn setup_session(&mut self, setup: &SessionSetup) -> Result<usize, Error> {
+        if setup.id() == 0 || self.session_setup == setup.id() {
+            return Ok(0);
+        }
+
+        for statement in setup.statements() {
+            match self.execute_checked(statement.as_str()).await {
+                Ok(_) => (),
+                Err(Error::ExecutionError(err)) => {
+                    return Err(Error::SessionSetup(statement.clone(), err))
+                }
+                Err(err) => return Err(err),
+            }
+        }
+
+        self.session_setup = setup.id();
+        Ok(setup.statements().len())
+    }
+
+    /// Values of the tracked parameters on the server. Unless changed by a client,
+    /// they are what the connection started with: sent in the startup message
+    /// or reported by the server, so we don't set them again.
+    fn current_params(&self, tracked: &Parameters) -> Parameters {
+        let mut baseline = Parameters::default();
+        for setting in self.startup_options.settings() {
+            baseline.insert(setting.name, setting.value);
+        }
+        for (name, value) in self.params.iter() {
+            baseline.insert(name, value.clone());
+        }
+
+        let mut current = self.client_params.clone();
+        for (name, value) in baseline.iter() {
+            if tracked.contains_key(name) && !current.contains_key(name) {
+                current.insert(name, value.clone());
+            }
+        }
+
+        current
+    }
+
     pub fn changed_params(&self) -> &Parameters {
         &self.changed_params
     }
@@ -633,10 +725,21 @@ impl Server {
         Ok(())
     }
 
+    /// Check if the server is a replica in recovery.
+    pub async fn in_recovery(&mut self) -> Result<bool, Error> {
+        let rows = self
+            .fetch_all::<DataRow>("SELECT pg_is_in_recovery()")
+            .await?;
+        rows.first()
+            .and_then(|row| row.get::<bool>(0, Format::Text))
+            .ok_or(Error::DecoderRowError)
+    }
+
     /// Attempt to rollback the transaction on this server, if any has been started.
     pub async fn rollback(&mut self) {
         if self.in_transaction() {
-            if let Err(_err) = self.execute("ROLLBACK").await {
+            if let Err(err) = self.execute_checked("ROLLBACK").await {
+                error!("rollback error: {} [{}]", err, self.addr());
                 self.stats.state(State::Error);
             }
             self.stats.rollback();
@@ -744,6 +847,60 @@ impl Server {
         Ok(())
     }
 
+    /// Prepare statements in one batch, e.g. before the connection is first used.
+    /// Statements after one that fails to prepare are skipped by the server.
+    /// Returns how many statements were prepared.
+    pub async fn prepare_many(&mut self, parses: &[Parse]) -> Result<usize, Error> {
+        let mut pending = vec![];
+        for parse in parses {
+            if !self.prepared_statements.contains(parse.name()) {
+                pending.push(parse);
+            }
+        }
+
+        if pending.is_empty() {
+            return Ok(0);
+        }
+
+        let mut buf = vec![];
+        for parse in &pending {
+            buf.push(parse.message()?);
+        }
+
+        buf.push(Sync.message()?);
+
+        debug!("preparing {} statements [{}]", pending.len(), self.addr());
+
+        self.stream().send_many(&buf).await?;
+
+        let mut prepared = 0;
+        loop {
+            let response = self.stream().read().await?;
+            match response.code() {
+                '1' => {
+                    let parse = pending.get(prepared).ok_or(Error::ProtocolOutOfSync)?;
+                    self.prepared_statements.prepared(parse.name());
+                    prepared += 1;
+                }
+                'E' => {
+                    let error = ErrorResponse::from_bytes(response.to_bytes()?)?;
+                    warn!(
+                        "failed to prepare statement: {} [{}]",
+                        error.message,
+                        self.addr()
+                    );
+                }
+                'N' => (),
+                'Z' => break,
+                c => return Err(Error::UnexpectedMessage(c)),
+            }
+        }
+
+        self.stats.prewarm(prepared, self.prepared_statements.len());
+
+        Ok(prepared)
+    }
+
     pub async fn reconnect(&self) -> Result<Server, Error> {
         Self::connect(&self.addr, self.startup_options.clone()).await
     }
@@ -758,6 +915,7 @@ impl Server {
     #[inline]
     pub fn reset_params(&mut self) {
         self.client_params.clear();
+        self.session_setup = 0;
     }
 
     #[inline]
@@ -798,12 +956,26 @@ impl Server {
         }
     }
 
+    /// How long since the connection was last used or healthchecked.
+    #[inline]
+    pub fn activity_age(&self, instant: Instant) -> Duration {
+        self.idle_for(instant).min(self.healthcheck_age(instant))
+    }
+
     /// Get server address.
     #[inline]
     pub fn addr(&self) -> &Address {
         &self.addr
     }
 
+    /// Connection is encrypted.
+    pub fn tls(&self) -> bool {
+        self.stream
+            .as_ref()
+            .map(|stream| stream.is_tls())
+            .unwrap_or(false)
+    }
+
     #[inline]
     fn stream(&mut self) -> &mut Stream {
         self.stream.as_mut().unwrap()
@@ -922,6 +1094,7 @@ pub mod test {
                 replication_mode: false,
                 pooler_mode: PoolerMode::Transaction,
                 stream_buffer: BytesMut::with_capacity(1024),
+                session_setup: 0,
             }
         }
     }
@@ -1065,7 +1238,7 @@ pub mod test {
         for i in 0..25 {
             let name = format!("test_prepared_{}", i);
             let parse = Parse::named(&name, format!("SELECT $1, 'test_{}'", name));
-            let (new, new_name) = PreparedStatements::global().lock().insert(&parse);
+            let (new, new_name) = PreparedStatements::global().lock().insert(&parse, &None);
             let name = new_name;
             let parse = parse.rename(&name);
             assert!(new);
@@ -1146,7 +1319,7 @@ pub mod test {
         use crate::net::bind::Parameter;
         let global = PreparedStatements::global();
         let parse = Parse::named("random_name", "SELECT $1");
-        let (new, name) = global.lock().insert(&parse);
+        let (new, name) = global.lock().insert(&parse, &None);
         assert!(new);
         let parse = parse.rename(&name);
         assert_eq!(parse.name(), "__pgdog_1");
@@ -1181,6 +1354,57 @@ pub mod test {
         }
     }
 
+    #[tokio::test]
+    async fn test_evicted_prepared_statement() {
+        use crate::net::bind::Parameter;
+
+        let global = PreparedStatements::global();
+        let names = [
+            "SELECT $1::bigint AS evicted_a",
+            "SELECT $1::bigint AS evicted_b",
+        ]
+        .into_iter()
+        .map(|query| global.lock().insert(&Parse::named("test", query), &None).1)
+        .collect::<Vec<_>>();
+
+        let mut server = test_server().await;
+        server.prepared_statements_mut().set_capacity(1);
+
+        let execute = |name: &str| -> ClientRequest {
+            vec![
+                ProtocolMessage::from(Bind::new_params(
+                    name,
+                    &[Parameter {
+                        len: 1,
+                        data: "1".as_bytes().to_vec(),
+                    }],
+                )),
+                Execute::new().into(),
+                Sync {}.into(),
+            ]
+            .into()
+        };
+
+        for name in names.iter().chain(names.first()) {
+            server.send(&execute(name)).await.unwrap();
+            for c in ['2', 'D', 'C', 'Z'] {
+                let msg = server.read().await.unwrap();
+                assert_eq!(msg.code(), c);
+            }
+            assert!(server.done());
+
+            // Least recently used statement is closed
+            // when the connection is over capacity.
+            let close = server.ensure_prepared_capacity();
+            assert!(close.len() <= 1);
+            server.close_many(&close).await.unwrap();
+            assert_eq!(server.prepared_statements().len(), 1);
+            assert!(server.prepared_statements_mut().contains(name));
+        }
+
+        assert_eq!(server.stats().total.close, 2);
+    }
+
     #[tokio::test]
     async fn test_bad_parse() {
         let mut server = test_server().await;
@@ -1540,7 +1764,7 @@ pub mod test {
     async fn test_manual_prepared() {
         let mut server = test_server().await;
 
-        let mut prep = PreparedStatements::new();
+        let mut prep = PreparedStatements::new(None);
         let parse = prep.insert_anyway(Parse::named("test", "SELECT 1::bigint"));
         assert_eq!(parse.name(), "__pgdog_1");
 
@@ -1632,6 +1856,32 @@ pub mod test {
         assert_eq!(changed, 0);
     }
 
+    #[tokio::test]
+    async fn test_application_name_pg_stat_activity() {
+        let mut server = test_server().await;
+        let query = "SELECT application_name FROM pg_stat_activity WHERE pid = pg_backend_pid()";
+        let default_name = server.fetch_all::<String>(query).await.unwrap();
+
+        let mut params = Parameters::default();
+        params.insert("application_name", "test_pg_stat_activity");
+        params.insert("TimeZone", "UTC");
+        assert_eq!(server.link_client(&params).await.unwrap(), 2);
+
+        let app_name = server.fetch_all::<String>(query).await.unwrap();
+        assert_eq!(app_name[0], "test_pg_stat_activity");
+
+        // Next client uses a different name, only that is changed.
+        params.insert("application_name", "test_pg_stat_activity_2");
+        assert_eq!(server.link_client(&params).await.unwrap(), 1);
+        let app_name = server.fetch_all::<String>(query).await.unwrap();
+        assert_eq!(app_name[0], "test_pg_stat_activity_2");
+
+        // Client without a name gets the connection default back.
+        assert_eq!(server.link_client(&Parameters::default()).await.unwrap(), 2);
+        let app_name = server.fetch_all::<String>(query).await.unwrap();
+        assert_eq!(app_name, default_name);
+    }
+
     #[tokio::test]
     async fn test_rollback() {
         let mut server = test_server().await;
@@ -1726,7 +1976,7 @@ pub mod test {
             params.insert("application_name", value);
 
             let changed = server.link_client(&params).await?;
-            assert_eq!(changed, 2); // RESET, SET.
+            assert_eq!(changed, 1); // SET.
 
             let changed = server.link_client(&params).await?;
             assert_eq!(changed, 0);
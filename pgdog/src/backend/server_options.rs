@@ -1,15 +1,18 @@
+use super::ServerTls;
 use crate::net::Parameter;
 
 #[derive(Debug, Clone, Default)]
 pub struct ServerOptions {
     pub params: Vec<Parameter>,
+    /// TLS preference of the pool creating the connection.
+    pub tls: ServerTls,
 }
 
 impl ServerOptions {
     pub fn replication_mode(&self) -> bool {
         self.params
             .iter()
-            .any(|p| p.name == "replication" && p.value == "database")
+            .any(|p| p.name == "replication" && matches!(p.value.as_str(), "database" | "true"))
     }
 
     pub fn new_replication() -> Self {
@@ -18,6 +21,75 @@ impl ServerOptions {
                 name: "replication".into(),
                 value: "database".into(),
             }],
+            ..Default::default()
         }
     }
+
+    /// Settings the server connection starts with, including
+    /// the ones passed in `options`, e.g. `-c search_path=app`.
+    pub fn settings(&self) -> Vec<Parameter> {
+        let mut settings = vec![];
+
+        for param in &self.params {
+            if param.name == "options" {
+                settings.extend(Self::parse_options(&param.value));
+            } else {
+                settings.push(param.clone());
+            }
+        }
+
+        settings
+    }
+
+    /// Parse `-c name=value` and `--name=value` settings from `options`.
+    fn parse_options(options: &str) -> Vec<Parameter> {
+        let mut settings = vec![];
+        let mut args = options.split_whitespace();
+
+        while let Some(arg) = args.next() {
+            let setting = if arg == "-c" {
+                args.next()
+            } else {
+                arg.strip_prefix("-c").or_else(|| arg.strip_prefix("--"))
+            };
+
+            if let Some((name, value)) = setting.and_then(|setting| setting.split_once('=')) {
+                settings.push(Parameter {
+                    name: name.replace('-', "_"),
+                    value: value.to_string(),
+                });
+            }
+        }
+
+        settings
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_options_settings() {
+        let options = ServerOptions {
+            params: vec![
+                Parameter::from(("application_name", "PgDog")),
+                Parameter::from((
+                    "options",
+                    "-c search_path=app --statement-timeout=5s -cwork_mem=12MB",
+                )),
+            ],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            options.settings(),
+            vec![
+                Parameter::from(("application_name", "PgDog")),
+                Parameter::from(("search_path", "app")),
+                Parameter::from(("statement_timeout", "5s")),
+                Parameter::from(("work_mem", "12MB")),
+            ]
+        );
+    }
 }
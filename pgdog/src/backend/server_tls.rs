@@ -0,0 +1,277 @@
+//! TLS preference for server connections.
+//!
+//! Databases can start or stop requiring TLS while PgDog is running, e.g. after
+//! turning on `ssl` and adding `hostssl` rules. Each pool remembers what the
+//! server accepted last, so new connections don't keep failing until a restart.
+
+use std::sync::{
+    atomic::{AtomicU8, Ordering},
+    Arc,
+};
+
+use super::Error;
+use crate::config::TlsVerifyMode;
+
+const CONFIGURED: u8 = 0;
+const TLS: u8 = 1;
+const PLAIN: u8 = 2;
+
+/// Whether new server connections request TLS.
+///
+/// Clones share the same preference.
+#[derive(Debug, Clone, Default)]
+pub struct ServerTls {
+    preference: Arc<AtomicU8>,
+}
+
+impl ServerTls {
+    /// Request TLS for the next connection.
+    pub fn request(&self, mode: TlsVerifyMode) -> bool {
+        match mode {
+            TlsVerifyMode::VerifyCa | TlsVerifyMode::VerifyFull => true,
+            TlsVerifyMode::Prefer | TlsVerifyMode::Disabled => {
+                match self.preference.load(Ordering::Relaxed) {
+                    TLS => true,
+                    PLAIN => false,
+                    _ => mode == TlsVerifyMode::Prefer,
+                }
+            }
+        }
+    }
+
+    /// Server only accepts connections with TLS, or only without it.
+    pub fn set(&self, tls: bool) {
+        self.preference
+            .store(if tls { TLS } else { PLAIN }, Ordering::Relaxed);
+    }
+
+    /// Server doesn't support TLS, go back to what's configured.
+    pub fn reset(&self) {
+        self.preference.store(CONFIGURED, Ordering::Relaxed);
+    }
+
+    /// Server rejected the connection because of its encryption, and it
+    /// should be retried the other way, if `tls_verify` allows it.
+    ///
+    /// Postgres reports this as, for example:
+    ///
+    /// ```text
+    /// FATAL: no pg_hba.conf entry for host "10.0.0.1", user "pgdog", database "pgdog", no encryption
+    /// ```
+    pub fn retry(mode: TlsVerifyMode, tls: bool, err: &Error) -> bool {
+        let Error::ConnectionError(err) = err else {
+            return false;
+        };
+
+        if err.code != "28000" || !err.message.starts_with("no pg_hba.conf entry") {
+            return false;
+        }
+
+        if tls {
+            let required = matches!(mode, TlsVerifyMode::VerifyCa | TlsVerifyMode::VerifyFull);
+            // "SSL on" before Postgres 14.
+            !required
+                && (err.message.ends_with("SSL encryption") || err.message.ends_with("SSL on"))
+        } else {
+            err.message.ends_with("no encryption") || err.message.ends_with("SSL off")
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{path::PathBuf, time::Duration};
+
+    use bytes::{BufMut, BytesMut};
+    use tokio::{
+        io::{copy_bidirectional, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+        spawn,
+    };
+
+    use super::*;
+    use crate::backend::{
+        pool::{Address, Config, PoolConfig, Request},
+        Pool, Server, ServerOptions,
+    };
+    use crate::net::{messages::ErrorResponse, tls::load_acceptor, ToBytes};
+
+    /// How the mock server is configured.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    enum Hba {
+        /// `ssl = off`.
+        Plain,
+        /// `ssl = on` and `hostssl` rules only.
+        Tls,
+        /// `ssl = on` and `hostnossl` rules only.
+        NoTls,
+    }
+
+    /// Answers SSLRequest and checks encryption like Postgres,
+    /// then passes the connection to the test database.
+    async fn mock(hba: Arc<parking_lot::Mutex<Hba>>) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let acceptor = load_acceptor(
+            &PathBuf::from("tests/tls/cert.pem"),
+            &PathBuf::from("tests/tls/key.pem"),
+        )
+        .unwrap()
+        .unwrap();
+
+        spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let hba = *hba.lock();
+                let acceptor = acceptor.clone();
+
+                spawn(async move {
+                    let len = stream.read_i32().await.unwrap();
+                    let code = stream.read_i32().await.unwrap();
+
+                    // SSLRequest
+                    if code == 80877103 {
+                        if hba == Hba::Plain {
+                            stream.write_u8(b'N').await.unwrap();
+                            proxy(stream, BytesMut::new()).await;
+                        } else {
+                            stream.write_u8(b'S').await.unwrap();
+                            let stream = acceptor.accept(stream).await.unwrap();
+                            if hba == Hba::NoTls {
+                                reject(stream, None, "SSL encryption").await;
+                            } else {
+                                proxy(stream, BytesMut::new()).await;
+                            }
+                        }
+                    } else if hba == Hba::Tls {
+                        reject(stream, Some(len as usize - 8), "no encryption").await;
+                    } else {
+                        let mut startup = BytesMut::new();
+                        startup.put_i32(len);
+                        startup.put_i32(code);
+                        proxy(stream, startup).await;
+                    }
+                });
+            }
+        });
+
+        port
+    }
+
+    /// Read the rest of the startup message and return the pg_hba.conf error.
+    async fn reject(
+        mut stream: impl AsyncRead + AsyncWrite + Unpin,
+        remaining: Option<usize>,
+        encryption: &str,
+    ) {
+        let remaining = match remaining {
+            Some(remaining) => remaining,
+            None => stream.read_i32().await.unwrap() as usize - 4,
+        };
+        let mut startup = vec![0; remaining];
+        stream.read_exact(&mut startup).await.unwrap();
+
+        let mut error = ErrorResponse::auth("pgdog", "pgdog");
+        error.message = format!(
+            "no pg_hba.conf entry for host \"127.0.0.1\", user \"pgdog\", database \"pgdog\", {}",
+            encryption
+        );
+        stream.write_all(&error.to_bytes().unwrap()).await.unwrap();
+        stream.flush().await.unwrap();
+    }
+
+    /// Pass the connection to the test database.
+    async fn proxy(mut stream: impl AsyncRead + AsyncWrite + Unpin, startup: BytesMut) {
+        let mut server = TcpStream::connect("127.0.0.1:5432").await.unwrap();
+        server.write_all(&startup).await.unwrap();
+        let _ = copy_bidirectional(&mut stream, &mut server).await;
+    }
+
+    fn address(port: u16) -> Address {
+        Address {
+            host: "127.0.0.1".into(),
+            port,
+            database_name: "pgdog".into(),
+            user: "pgdog".into(),
+            password: "pgdog".into(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_server_tls_disabled() {
+        crate::logger();
+        let hba = Arc::new(parking_lot::Mutex::new(Hba::Plain));
+        let addr = address(mock(hba.clone()).await);
+        let options = ServerOptions::default();
+        let mode = TlsVerifyMode::Disabled;
+
+        let server = Server::connect_tls(&addr, options.clone(), mode)
+            .await
+            .unwrap();
+        assert!(!server.tls());
+
+        // Server started requiring TLS.
+        *hba.lock() = Hba::Tls;
+        for _ in 0..2 {
+            let server = Server::connect_tls(&addr, options.clone(), mode)
+                .await
+                .unwrap();
+            assert!(server.tls());
+            assert!(options.tls.request(mode));
+        }
+
+        // Server turned TLS off.
+        *hba.lock() = Hba::Plain;
+        let server = Server::connect_tls(&addr, options.clone(), mode)
+            .await
+            .unwrap();
+        assert!(!server.tls());
+        assert!(!options.tls.request(mode));
+    }
+
+    #[tokio::test]
+    async fn test_server_tls_required() {
+        crate::logger();
+        let hba = Arc::new(parking_lot::Mutex::new(Hba::NoTls));
+        let addr = address(mock(hba.clone()).await);
+        let options = ServerOptions::default();
+
+        // Verification is required, so we don't fall back to plain text.
+        let err = Server::connect_tls(&addr, options.clone(), TlsVerifyMode::VerifyFull)
+            .await
+            .unwrap_err();
+        assert!(ServerTls::retry(TlsVerifyMode::Prefer, true, &err));
+        assert!(!ServerTls::retry(TlsVerifyMode::VerifyFull, true, &err));
+        assert!(!ServerTls::retry(TlsVerifyMode::Prefer, false, &err));
+        assert!(options.tls.request(TlsVerifyMode::Prefer));
+    }
+
+    #[tokio::test]
+    async fn test_pool_recovers() {
+        crate::logger();
+        let hba = Arc::new(parking_lot::Mutex::new(Hba::Plain));
+        let pool = Pool::new(&PoolConfig {
+            address: address(mock(hba.clone()).await),
+            config: Config {
+                min: 0,
+                max: 5,
+                connect_attempts: 1,
+                connect_timeout: Duration::from_secs(5),
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        pool.launch();
+
+        // Hold on to connections so every checkout creates a new one.
+        let mut guards = vec![];
+        for hba_conf in [Hba::Plain, Hba::NoTls, Hba::Tls, Hba::Plain, Hba::Tls] {
+            *hba.lock() = hba_conf;
+            let mut guard = pool.get(&Request::default()).await.unwrap();
+            guard.execute("SELECT 1").await.unwrap();
+            assert_eq!(guard.tls(), hba_conf == Hba::Tls, "{:?}", hba_conf);
+            guards.push(guard);
+        }
+
+        pool.shutdown();
+    }
+}
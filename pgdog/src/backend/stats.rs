@@ -63,6 +63,7 @@ pub struct Counts {
     pub bind: usize,
     pub healthchecks: usize,
     pub close: usize,
+    pub prewarm: usize,
     pub memory_used: usize,
 }
 
@@ -86,6 +87,7 @@ impl Add for Counts {
             bind: self.bind.saturating_add(rhs.bind),
             healthchecks: self.healthchecks.saturating_add(rhs.healthchecks),
             close: self.close.saturating_add(rhs.close),
+            prewarm: self.prewarm.saturating_add(rhs.prewarm),
             memory_used: self.memory_used, // It's a gauge.
         }
     }
@@ -180,6 +182,14 @@ impl Stats {
         self.update();
     }
 
+    /// Statements prepared before the connection was first used.
+    pub fn prewarm(&mut self, prepared: usize, size: usize) {
+        self.total.prepared_statements = size;
+        self.total.prewarm += prepared;
+        self.last_checkout.prewarm += prepared;
+        self.update();
+    }
+
     pub fn copy_mode(&mut self) {
         self.state(State::CopyMode);
     }
@@ -0,0 +1,133 @@
+//! Reload users.toml when it changes.
+//!
+//! The file is checked every `users_watch_interval`. It's reloaded once it stopped
+//! changing for one interval, so we don't read it while it's being written.
+//! Pools keep their server connections; only new connections use the new passwords.
+
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use tokio::fs::metadata;
+use tokio::time::sleep;
+use tracing::{error, info};
+
+use super::databases::reload;
+use crate::config::config;
+
+/// What we know about the file without reading it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Version {
+    modified: Option<SystemTime>,
+    len: u64,
+}
+
+impl Version {
+    async fn get(path: &Path) -> Option<Self> {
+        let metadata = metadata(path).await.ok()?;
+        Some(Self {
+            modified: metadata.modified().ok(),
+            len: metadata.len(),
+        })
+    }
+}
+
+/// Changes to users.toml.
+#[derive(Debug)]
+struct Watch {
+    /// Version we loaded.
+    loaded: Option<Version>,
+    /// Changed version, waiting for it to settle.
+    pending: Option<Version>,
+}
+
+impl Watch {
+    fn new(loaded: Option<Version>) -> Self {
+        Self {
+            loaded,
+            pending: None,
+        }
+    }
+
+    /// Check the latest version. Returns true if it should be loaded.
+    fn changed(&mut self, version: Option<Version>) -> bool {
+        // File is being replaced, or was removed. Keep the users we have.
+        let Some(version) = version else {
+            return false;
+        };
+
+        if self.loaded == Some(version) {
+            self.pending = None;
+            false
+        } else if self.pending == Some(version) {
+            self.loaded = Some(version);
+            self.pending = None;
+            true
+        } else {
+            self.pending = Some(version);
+            false
+        }
+    }
+}
+
+/// Start watching users.toml, if enabled.
+pub fn start() {
+    let config = config();
+    let Some(interval) = config.config.general.users_watch_interval() else {
+        return;
+    };
+
+    if config.config.general.users_command.is_some() {
+        info!("users are loaded with users_command, not watching users.toml");
+        return;
+    }
+
+    tokio::spawn(watch(interval));
+}
+
+async fn watch(interval: Duration) {
+    let path = config().users_path.clone();
+    let mut watch = Watch::new(Version::get(&path).await);
+
+    info!("watching \"{}\" for changes", path.display());
+
+    loop {
+        sleep(interval).await;
+
+        if watch.changed(Version::get(&path).await) {
+            info!("\"{}\" changed, reloading", path.display());
+            // Old users stay until the file is fixed.
+            if let Err(err) = reload() {
+                error!("users reload error: {}", err);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_watch() {
+        let version = |len| {
+            Some(Version {
+                modified: Some(SystemTime::UNIX_EPOCH),
+                len,
+            })
+        };
+        let mut watch = Watch::new(version(1));
+
+        assert!(!watch.changed(version(1)));
+        // Still being written.
+        assert!(!watch.changed(version(2)));
+        assert!(!watch.changed(version(3)));
+        // Settled.
+        assert!(watch.changed(version(3)));
+        assert!(!watch.changed(version(3)));
+
+        // Removed files are ignored.
+        assert!(!watch.changed(None));
+        assert!(!watch.changed(None));
+        assert!(!watch.changed(version(3)));
+    }
+}
@@ -0,0 +1,341 @@
+//! In-process benchmark.
+//!
+//! Drives synthetic clients through the real frontend code path,
+//! skipping the startup handshake and authentication, and measures
+//! throughput, latency and allocations.
+
+pub mod alloc;
+
+use std::fmt::Display;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use bytes::{BufMut, BytesMut};
+use thiserror::Error;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, BufStream};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::spawn;
+use tracing::info;
+
+use crate::backend::databases::databases;
+use crate::frontend::Client;
+use crate::net::{Bind, Execute, Parameters, Parse, Query, Stream, Sync, Terminate, ToBytes};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("{0}")]
+    Net(#[from] crate::net::Error),
+
+    #[error("{0}")]
+    Backend(#[from] crate::backend::Error),
+
+    #[error("{0}")]
+    Join(#[from] tokio::task::JoinError),
+}
+
+/// Benchmark settings.
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// Number of concurrent clients.
+    pub clients: usize,
+    /// How long to run for.
+    pub duration: Duration,
+    /// User name.
+    pub user: String,
+    /// Database name.
+    pub database: String,
+    /// Queries executed in turn by each client.
+    pub queries: Vec<String>,
+    /// Percentage of queries sent using the extended protocol.
+    pub extended: usize,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            clients: 10,
+            duration: Duration::from_secs(10),
+            user: "pgdog".into(),
+            database: "pgdog".into(),
+            queries: vec!["SELECT 1".into()],
+            extended: 50,
+        }
+    }
+}
+
+/// Benchmark results.
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    /// Number of clients.
+    pub clients: usize,
+    /// Time spent running queries.
+    pub elapsed: Duration,
+    /// Queries that completed successfully.
+    pub queries: usize,
+    /// Queries that returned an error.
+    pub errors: usize,
+    /// Latency of each query, in microseconds, sorted.
+    latencies: Vec<u64>,
+    /// Allocations made during the benchmark, if counted.
+    pub allocations: Option<usize>,
+}
+
+impl Report {
+    /// Queries per second.
+    pub fn throughput(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs > 0.0 {
+            (self.queries + self.errors) as f64 / secs
+        } else {
+            0.0
+        }
+    }
+
+    /// Latency percentile, e.g. 0.99 for p99.
+    pub fn percentile(&self, percentile: f64) -> Duration {
+        if self.latencies.is_empty() {
+            return Duration::ZERO;
+        }
+        let index = ((self.latencies.len() as f64 * percentile).ceil() as usize)
+            .clamp(1, self.latencies.len())
+            - 1;
+        Duration::from_micros(self.latencies[index])
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+
+        writeln!(f, "clients:     {}", self.clients)?;
+        writeln!(f, "duration:    {:.2}s", self.elapsed.as_secs_f64())?;
+        writeln!(
+            f,
+            "queries:     {} ({:.1}/s)",
+            self.queries,
+            self.throughput()
+        )?;
+        writeln!(f, "errors:      {}", self.errors)?;
+        writeln!(f, "latency p50: {:.3}ms", ms(self.percentile(0.5)))?;
+        writeln!(f, "latency p95: {:.3}ms", ms(self.percentile(0.95)))?;
+        writeln!(f, "latency p99: {:.3}ms", ms(self.percentile(0.99)))?;
+        writeln!(f, "latency max: {:.3}ms", ms(self.percentile(1.0)))?;
+        match self.allocations {
+            Some(allocations) => {
+                let total = (self.queries + self.errors).max(1);
+                write!(
+                    f,
+                    "allocations: {} ({:.1}/query)",
+                    allocations,
+                    allocations as f64 / total as f64
+                )
+            }
+            None => write!(f, "allocations: n/a (build with --features bench)"),
+        }
+    }
+}
+
+/// Results from one client.
+#[derive(Debug, Default)]
+struct ClientReport {
+    queries: usize,
+    errors: usize,
+    latencies: Vec<u64>,
+}
+
+/// Run the benchmark.
+pub async fn run(options: &Options) -> Result<Report, Error> {
+    // Fail early if the database isn't configured.
+    databases().cluster((options.user.as_str(), options.database.as_str()))?;
+
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+
+    let mut conns = vec![];
+    for _ in 0..options.clients {
+        conns.push(connect(&listener, addr, options).await?);
+    }
+
+    info!(
+        "running benchmark with {} clients for {:.2}s",
+        options.clients,
+        options.duration.as_secs_f64()
+    );
+
+    let allocations = alloc::allocations();
+    let start = Instant::now();
+    let deadline = start + options.duration;
+
+    let mut handles = vec![];
+    for (id, conn) in conns.into_iter().enumerate() {
+        let options = options.clone();
+        handles.push(spawn(
+            async move { drive(id, conn, &options, deadline).await },
+        ));
+    }
+
+    let mut report = Report {
+        clients: options.clients,
+        ..Default::default()
+    };
+
+    for handle in handles {
+        let client = handle.await??;
+        report.queries += client.queries;
+        report.errors += client.errors;
+        report.latencies.extend(client.latencies);
+    }
+
+    report.elapsed = start.elapsed();
+    report.allocations = alloc::allocations()
+        .zip(allocations)
+        .map(|(end, start)| end.saturating_sub(start));
+    report.latencies.sort_unstable();
+
+    Ok(report)
+}
+
+/// Connect a client to the frontend, skipping the startup handshake.
+async fn connect(
+    listener: &TcpListener,
+    addr: SocketAddr,
+    options: &Options,
+) -> Result<BufStream<TcpStream>, Error> {
+    let (conn, accepted) = tokio::join!(TcpStream::connect(addr), listener.accept());
+    let conn = conn?;
+    let (mut stream, mut peer) = accepted?;
+
+    // The client isn't authenticated, so only serve our own connection
+    // and drop anything else that found the port.
+    let local = conn.local_addr()?;
+    while peer != local {
+        (stream, peer) = listener.accept().await?;
+    }
+
+    let mut params = Parameters::default();
+    params.insert("user", options.user.as_str());
+    params.insert("database", options.database.as_str());
+    params.insert("application_name", "pgdog_bench");
+
+    let client = Client::new_local(Stream::plain(stream), peer, params);
+    spawn(client.serve_local());
+
+    Ok(BufStream::new(conn))
+}
+
+/// Execute queries until the deadline.
+async fn drive(
+    id: usize,
+    mut conn: BufStream<TcpStream>,
+    options: &Options,
+    deadline: Instant,
+) -> Result<ClientReport, Error> {
+    let mut report = ClientReport::default();
+    let mut prepared = vec![false; options.queries.len()];
+    let mut request = BytesMut::new();
+    // Spread extended protocol queries evenly.
+    let mut credit = 0;
+    let mut n = id;
+
+    while Instant::now() < deadline {
+        let index = n % options.queries.len();
+        let query = &options.queries[index];
+        n += 1;
+
+        credit += options.extended;
+        let extended = credit >= 100;
+        if extended {
+            credit -= 100;
+        }
+
+        request.clear();
+        if extended {
+            let name = format!("__pgdog_bench_{}", index);
+            if !prepared[index] {
+                request.put(Parse::named(&name, query).to_bytes()?);
+                prepared[index] = true;
+            }
+            request.put(Bind::new_statement(&name).to_bytes()?);
+            request.put(Execute::new().to_bytes()?);
+            request.put(Sync::new().to_bytes()?);
+        } else {
+            request.put(Query::new(query).to_bytes()?);
+        }
+
+        let start = Instant::now();
+        conn.write_all(&request).await?;
+        conn.flush().await?;
+        let ok = read_until_ready(&mut conn).await?;
+        let latency = start.elapsed();
+
+        if ok {
+            report.queries += 1;
+        } else {
+            report.errors += 1;
+        }
+        report.latencies.push(latency.as_micros() as u64);
+    }
+
+    conn.write_all(&Terminate.to_bytes()?).await?;
+    conn.flush().await?;
+
+    Ok(report)
+}
+
+/// Read messages until ReadyForQuery. Returns false if the server
+/// sent an error.
+async fn read_until_ready(conn: &mut BufStream<TcpStream>) -> Result<bool, Error> {
+    let mut ok = true;
+    let mut buf = vec![];
+
+    loop {
+        let code = conn.read_u8().await? as char;
+        let len = conn.read_i32().await? as usize;
+        buf.resize(len.saturating_sub(4), 0);
+        conn.read_exact(&mut buf).await?;
+
+        match code {
+            'E' => ok = false,
+            'Z' => return Ok(ok),
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::config::test::load_test;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_bench() {
+        load_test();
+
+        let options = Options {
+            clients: 2,
+            duration: Duration::from_millis(500),
+            queries: vec!["SELECT 1".into(), "SELECT 2".into()],
+            ..Default::default()
+        };
+
+        let report = run(&options).await.unwrap();
+        assert!(report.queries > 0);
+        assert_eq!(report.errors, 0);
+        assert_eq!(report.latencies.len(), report.queries);
+        assert!(report.percentile(0.5) <= report.percentile(0.99));
+
+        let options = Options {
+            clients: 1,
+            duration: Duration::from_millis(100),
+            queries: vec!["SELECT * FROM table_does_not_exist".into()],
+            ..Default::default()
+        };
+        let report = run(&options).await.unwrap();
+        assert_eq!(report.queries, 0);
+        assert!(report.errors > 0);
+    }
+}
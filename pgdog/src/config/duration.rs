@@ -0,0 +1,226 @@
+//! Durations in the configuration files.
+
+use std::fmt::Display;
+use std::str::FromStr;
+use std::time::Duration;
+
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use super::Error;
+
+/// Duration set in the configuration.
+///
+/// Integers are milliseconds, for backward compatibility. Strings
+/// need a unit: `ms`, `s`, `m` (or `min`), `h` or `d`, e.g. `"250ms"` or `"5s"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct HumanDuration(Duration);
+
+impl HumanDuration {
+    /// Effectively no timeout.
+    pub const MAX: Self = Self(Duration::MAX);
+
+    pub const fn from_millis(millis: u64) -> Self {
+        Self(Duration::from_millis(millis))
+    }
+
+    pub const fn from_secs(secs: u64) -> Self {
+        Self(Duration::from_secs(secs))
+    }
+
+    /// Get the duration.
+    pub const fn duration(&self) -> Duration {
+        self.0
+    }
+
+    /// Make sure the setting called `name` is at least `min`.
+    pub fn at_least(self, name: &str, min: HumanDuration) -> Result<Self, Error> {
+        if self < min {
+            Err(Error::DurationTooShort(name.to_owned(), self, min))
+        } else {
+            Ok(self)
+        }
+    }
+
+    /// Milliseconds, saturating at `u64::MAX`.
+    pub fn as_millis(&self) -> u64 {
+        u64::try_from(self.0.as_millis()).unwrap_or(u64::MAX)
+    }
+}
+
+impl From<HumanDuration> for Duration {
+    fn from(value: HumanDuration) -> Self {
+        value.0
+    }
+}
+
+impl From<Duration> for HumanDuration {
+    fn from(value: Duration) -> Self {
+        Self(value)
+    }
+}
+
+impl From<u64> for HumanDuration {
+    fn from(millis: u64) -> Self {
+        Self::from_millis(millis)
+    }
+}
+
+impl FromStr for HumanDuration {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let value = s.trim();
+        let split = value
+            .find(|c: char| !c.is_ascii_digit() && c != '_')
+            .unwrap_or(value.len());
+        let (amount, unit) = value.split_at(split);
+        let amount = amount
+            .replace('_', "")
+            .parse::<u64>()
+            .map_err(|_| Error::InvalidDuration(s.to_owned()))?;
+
+        let multiplier = match unit.trim() {
+            "" | "ms" => 1,
+            "s" => 1_000,
+            "m" | "min" => 60 * 1_000,
+            "h" => 60 * 60 * 1_000,
+            "d" => 24 * 60 * 60 * 1_000,
+            _ => return Err(Error::InvalidDuration(s.to_owned())),
+        };
+
+        amount
+            .checked_mul(multiplier)
+            .map(Self::from_millis)
+            .ok_or(Error::InvalidDuration(s.to_owned()))
+    }
+}
+
+impl Display for HumanDuration {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let millis = self.as_millis();
+        if millis == u64::MAX {
+            return write!(f, "unlimited");
+        }
+
+        for (unit, size) in [
+            ("d", 24 * 60 * 60 * 1_000),
+            ("h", 60 * 60 * 1_000),
+            ("m", 60 * 1_000),
+            ("s", 1_000),
+        ] {
+            if millis >= size && millis % size == 0 {
+                return write!(f, "{}{}", millis / size, unit);
+            }
+        }
+
+        write!(f, "{}ms", millis)
+    }
+}
+
+impl Serialize for HumanDuration {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(self.as_millis())
+    }
+}
+
+struct HumanDurationVisitor;
+
+impl Visitor<'_> for HumanDurationVisitor {
+    type Value = HumanDuration;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter
+            .write_str("milliseconds or a duration with a unit, e.g. \"250ms\", \"5s\", \"1m\"")
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<Self::Value, E> {
+        Ok(HumanDuration::from_millis(value))
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<Self::Value, E> {
+        u64::try_from(value)
+            .map(HumanDuration::from_millis)
+            .map_err(|_| E::invalid_value(de::Unexpected::Signed(value), &self))
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+        value
+            .parse()
+            .map_err(|_| E::invalid_value(de::Unexpected::Str(value), &self))
+    }
+}
+
+impl<'de> Deserialize<'de> for HumanDuration {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(HumanDurationVisitor)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Deserialize, Serialize)]
+    struct Settings {
+        timeout: HumanDuration,
+    }
+
+    fn parse(toml: &str) -> Result<Duration, toml::de::Error> {
+        toml::from_str::<Settings>(toml).map(|settings| settings.timeout.duration())
+    }
+
+    #[test]
+    fn test_integers_are_millis() {
+        assert_eq!(parse("timeout = 5000").unwrap(), Duration::from_secs(5));
+        assert_eq!(parse("timeout = 5_000").unwrap(), Duration::from_secs(5));
+        assert_eq!(parse("timeout = 0").unwrap(), Duration::ZERO);
+        assert!(parse("timeout = -1").is_err());
+    }
+
+    #[test]
+    fn test_strings_with_units() {
+        for (value, expected) in [
+            (r#""250ms""#, Duration::from_millis(250)),
+            (r#""5s""#, Duration::from_secs(5)),
+            (r#""1m""#, Duration::from_secs(60)),
+            (r#""2min""#, Duration::from_secs(120)),
+            (r#""1h""#, Duration::from_secs(3600)),
+            (r#""1d""#, Duration::from_secs(86400)),
+            (r#""10 s""#, Duration::from_secs(10)),
+            (r#""1500""#, Duration::from_millis(1500)),
+        ] {
+            assert_eq!(
+                parse(&format!("timeout = {}", value)).unwrap(),
+                expected,
+                "{}",
+                value
+            );
+        }
+
+        for value in [r#""5x""#, r#""s""#, r#""""#, r#""-5s""#, r#""1.5s""#, "1.5"] {
+            assert!(parse(&format!("timeout = {}", value)).is_err(), "{}", value);
+        }
+    }
+
+    #[test]
+    fn test_serialize_round_trip() {
+        let settings = Settings {
+            timeout: HumanDuration::from_secs(5),
+        };
+        let toml = toml::to_string(&settings).unwrap();
+        assert_eq!(toml.trim(), "timeout = 5000");
+        assert_eq!(parse(&toml).unwrap(), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(HumanDuration::from_millis(250).to_string(), "250ms");
+        assert_eq!(HumanDuration::from_millis(1500).to_string(), "1500ms");
+        assert_eq!(HumanDuration::from_secs(5).to_string(), "5s");
+        assert_eq!(HumanDuration::from_secs(120).to_string(), "2m");
+        assert_eq!(HumanDuration::from_secs(3600).to_string(), "1h");
+        assert_eq!(HumanDuration::from_millis(0).to_string(), "0ms");
+        assert_eq!(HumanDuration::MAX.to_string(), "unlimited");
+    }
+}
@@ -2,6 +2,8 @@
 
 use thiserror::Error;
 
+use super::HumanDuration;
+
 /// Configuration error.
 #[derive(Debug, Error)]
 pub enum Error {
@@ -11,6 +13,9 @@ pub enum Error {
     #[error("{0}")]
     Deser(#[from] toml::de::Error),
 
+    #[error("{0}")]
+    Ser(#[from] toml::ser::Error),
+
     #[error("{0}, line {1}")]
     MissingField(String, usize),
 
@@ -20,8 +25,66 @@ pub enum Error {
     #[error("{0}")]
     Json(#[from] serde_json::Error),
 
+    #[error("unsupported database URL scheme \"{0}\", expected \"postgres\"")]
+    UrlScheme(String),
+
+    #[error("unknown database URL parameter \"{0}\"")]
+    UrlParameter(String),
+
+    #[error("invalid value \"{1}\" for database URL parameter \"{0}\"")]
+    UrlParameterValue(String, String),
+
+    #[error("database URL host \"{0}\" is a unix socket, only TCP connections are supported")]
+    UrlUnixSocket(String),
+
+    #[error("user \"{0}\" has different passwords for database \"{1}\"")]
+    UrlConflictingPassword(String, String),
+
+    #[error("database \"{0}\" has more than one primary for shard {1}")]
+    DuplicatePrimary(String, usize),
+
+    #[error("database \"{0}\" is missing shard {1}")]
+    MissingShard(String, usize),
+
+    #[error(
+        "invalid duration \"{0}\", expected milliseconds or a unit, e.g. \"250ms\", \"5s\", \"1m\""
+    )]
+    InvalidDuration(String),
+
+    #[error("\"{0}\" is {1}, it must be at least {2}")]
+    DurationTooShort(String, HumanDuration, HumanDuration),
+
+    #[error("database \"{0}\" requires TLS, but tls_certificate and tls_private_key aren't set")]
+    TlsNotConfigured(String),
+
+    #[error("user \"{0}\" of database \"{1}\" requires TLS, but tls_certificate and tls_private_key aren't set")]
+    UserTlsNotConfigured(String, String),
+
+    #[error("manual query \"{0}\" routes to shard {1}, but there are only {2} shards")]
+    ManualQueryShard(String, usize, usize),
+
+    #[error("manual query \"{0}\" can't set both shard and shards")]
+    ManualQueryShardAndShards(String),
+
+    #[error("users_command \"{0}\" failed: {1}")]
+    UsersCommand(String, String),
+
     #[error("incomplete startup")]
     IncompleteStartup,
+
+    #[error("database \"{0}\" can't set startup parameter \"{1}\", it's managed by PgDog")]
+    ManagedStartupParameter(String, String),
+
+    #[error(
+        "sharded table \"{0}\" in database \"{1}\" has {2} data_types for {3} sharding key columns"
+    )]
+    ShardedTableDataTypes(String, String, usize, usize),
+
+    #[error("sharded table \"{0}\" in database \"{1}\" sends NULL keys to shard {2}, but there are only {3} shards")]
+    NullShard(String, String, usize, usize),
+
+    #[error("{0} has an invalid session_setup statement \"{1}\": {2}")]
+    SessionSetup(String, String, String),
 }
 
 impl Error {
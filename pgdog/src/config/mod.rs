@@ -1,17 +1,20 @@
 //! Configuration.
 
 pub mod convert;
+pub mod duration;
 pub mod error;
 pub mod overrides;
 pub mod url;
 
+pub use duration::HumanDuration;
 use error::Error;
 pub use overrides::Overrides;
 use parking_lot::Mutex;
 
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
 use std::fs::read_to_string;
 use std::net::Ipv4Addr;
+use std::process::Stdio;
 use std::sync::Arc;
 use std::time::Duration;
 use std::usize;
@@ -19,10 +22,11 @@ use std::{collections::HashMap, path::PathBuf};
 
 use crate::frontend::router::sharding::Mapping;
 use crate::net::messages::Vector;
-use crate::util::{human_duration_optional, random_string};
+use crate::util::{human_duration_optional, random_string, wildcard};
 use arc_swap::ArcSwap;
 use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
 use tracing::info;
 use tracing::warn;
 
@@ -45,6 +49,7 @@ pub fn load(config: &PathBuf, users: &PathBuf) -> Result<ConfigAndUsers, Error>
 pub fn set(mut config: ConfigAndUsers) -> Result<ConfigAndUsers, Error> {
     config.config.check();
     for table in config.config.sharded_tables.iter_mut() {
+        table.normalize_columns();
         table.load_centroids()?;
     }
     CONFIG.store(Arc::new(config.clone()));
@@ -52,10 +57,13 @@ pub fn set(mut config: ConfigAndUsers) -> Result<ConfigAndUsers, Error> {
 }
 
 /// Load configuration from a list of database URLs.
-pub fn from_urls(urls: &[String]) -> Result<ConfigAndUsers, Error> {
+///
+/// Databases and users from the URLs replace the ones loaded
+/// from the configuration files; all other settings are kept.
+pub fn from_urls(urls: &[String], name: Option<&str>) -> Result<ConfigAndUsers, Error> {
     let _lock = LOCK.lock();
     let config = (*config()).clone();
-    let config = config.databases_from_urls(urls)?;
+    let config = config.databases_from_urls(urls, name)?;
     CONFIG.store(Arc::new(config.clone()));
     Ok(config)
 }
@@ -63,23 +71,7 @@ pub fn from_urls(urls: &[String]) -> Result<ConfigAndUsers, Error> {
 /// Override some settings.
 pub fn overrides(overrides: Overrides) {
     let mut config = (*config()).clone();
-    let Overrides {
-        default_pool_size,
-        min_pool_size,
-        session_mode,
-    } = overrides;
-
-    if let Some(default_pool_size) = default_pool_size {
-        config.config.general.default_pool_size = default_pool_size;
-    }
-
-    if let Some(min_pool_size) = min_pool_size {
-        config.config.general.min_pool_size = min_pool_size;
-    }
-
-    if let Some(true) = session_mode {
-        config.config.general.pooler_mode = PoolerMode::Session;
-    }
+    overrides.apply(&mut config.config.general);
 
     CONFIG.store(Arc::new(config));
 }
@@ -95,12 +87,30 @@ pub struct ConfigAndUsers {
     pub config_path: PathBuf,
     /// Path to users.toml.
     pub users_path: PathBuf,
+    /// SHA-1 of pgdog.toml and users.toml, in hex.
+    pub digest: String,
+}
+
+/// Settings for pools created on demand from templates.
+#[derive(Debug, Clone)]
+pub struct Template {
+    /// Database entry the database was created from, if it matched its `name_pattern`.
+    pub database: Option<String>,
+    /// `name_pattern` of the users.toml entry the user matched, if any.
+    pub user_pattern: Option<String>,
+    /// User entry, renamed to the client's user and database.
+    pub user: Option<User>,
+    /// Config with database entries for the requested database.
+    pub config: Config,
 }
 
 impl ConfigAndUsers {
     /// Load configuration from disk or use defaults.
     pub fn load(config_path: &PathBuf, users_path: &PathBuf) -> Result<Self, Error> {
-        let config: Config = if let Ok(config) = read_to_string(config_path) {
+        let mut digest = Sha1::new();
+
+        let mut config: Config = if let Ok(config) = read_to_string(config_path) {
+            digest.update(&config);
             let config = match toml::from_str(&config) {
                 Ok(config) => config,
                 Err(err) => return Err(Error::config(&config, err)),
@@ -115,6 +125,11 @@ impl ConfigAndUsers {
             Config::default()
         };
 
+        config.check_tls()?;
+        config.check_manual_queries()?;
+        config.check_sharded_tables()?;
+        config.load_sharded_mappings()?;
+
         if config.admin.random() {
             #[cfg(debug_assertions)]
             info!("[debug only] admin password: {}", config.admin.password);
@@ -126,9 +141,15 @@ impl ConfigAndUsers {
             info!("multi-tenant protection enabled");
         }
 
-        let users: Users = if let Ok(users) = read_to_string(users_path) {
-            let mut users: Users = toml::from_str(&users)?;
-            users.check(&config);
+        let users: Users = if let Some(command) = &config.general.users_command {
+            let users = Users::from_command(command)?;
+            digest.update(&users);
+            let users = Users::parse(&users, &config)?;
+            info!("loaded users from \"{}\"", command);
+            users
+        } else if let Ok(users) = read_to_string(users_path) {
+            digest.update(&users);
+            let users = Users::parse(&users, &config)?;
             info!("loaded \"{}\"", users_path.display());
             users
         } else {
@@ -144,6 +165,70 @@ impl ConfigAndUsers {
             users,
             config_path: config_path.to_owned(),
             users_path: users_path.to_owned(),
+            digest: digest
+                .finalize()
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect(),
+        })
+    }
+
+    /// Clients of this user and database must use TLS.
+    pub fn require_tls(&self, user: &str, database: &str) -> bool {
+        let database = self.config.database_template(database).unwrap_or(database);
+        self.config
+            .databases
+            .iter()
+            .any(|d| d.name == database && d.require_tls)
+            || self
+                .users
+                .users
+                .iter()
+                .any(|u| u.matches(user) && u.database == database && u.require_tls)
+    }
+
+    /// Settings for pools created on demand for the user and database, because the
+    /// database matches a template's `name_pattern`, or the user matches one in users.toml.
+    pub fn template(&self, user: &str, database: &str) -> Option<Template> {
+        let template = self.config.database_template(database);
+        let name = template.unwrap_or(database);
+        if template.is_none()
+            && !self
+                .config
+                .databases
+                .iter()
+                .any(|d| d.name == database && d.name_pattern.is_none())
+        {
+            return None;
+        }
+
+        let mut users = self.users.users.iter().filter(|u| u.database == name);
+        let exact = users
+            .clone()
+            .find(|u| u.name_pattern.is_none() && u.name == user);
+        let pattern = users.find(|u| u.name_pattern.is_some() && u.matches(user));
+
+        // Pools for users in users.toml are created at startup.
+        if template.is_none() && (exact.is_some() || pattern.is_none()) {
+            return None;
+        }
+
+        Some(Template {
+            database: template.map(String::from),
+            user_pattern: match exact {
+                Some(_) => None,
+                None => pattern.and_then(|u| u.name_pattern.clone()),
+            },
+            user: exact.or(pattern).map(|u| User {
+                name: user.to_owned(),
+                database: database.to_owned(),
+                name_pattern: None,
+                ..u.clone()
+            }),
+            config: match template {
+                Some(template) => self.config.instantiate(template, database),
+                None => self.config.clone(),
+            },
         })
     }
 
@@ -219,6 +304,14 @@ pub struct Config {
     /// Replication config.
     #[serde(default)]
     pub replication: Replication,
+
+    /// Trace export.
+    #[serde(default)]
+    pub telemetry: Telemetry,
+
+    /// Healthcheck ban policies.
+    #[serde(default)]
+    pub healthcheck: Healthcheck,
 }
 
 impl Config {
@@ -240,6 +333,80 @@ impl Config {
         databases
     }
 
+    /// Database entries with a `name_pattern` are templates, not databases clients can connect to.
+    pub fn is_template(&self, name: &str) -> bool {
+        self.databases
+            .iter()
+            .any(|d| d.name == name && d.name_pattern.is_some())
+    }
+
+    /// Template for a database that isn't configured, i.e., the name of
+    /// the first database entry with a `name_pattern` matching it.
+    pub fn database_template(&self, database: &str) -> Option<&str> {
+        if self.databases.iter().any(|d| d.name == database) {
+            return None;
+        }
+
+        self.databases
+            .iter()
+            .find(|d| {
+                d.name_pattern
+                    .as_ref()
+                    .is_some_and(|pattern| wildcard(pattern, database))
+            })
+            .map(|d| d.name.as_str())
+    }
+
+    /// Copy of the config with the template's database entries and sharded tables
+    /// added for the database, connecting to the database with the same name.
+    pub fn instantiate(&self, template: &str, database: &str) -> Config {
+        let mut config = self.clone();
+
+        for entry in self.databases.iter().filter(|d| d.name == template) {
+            config.databases.push(Database {
+                name: database.to_owned(),
+                name_pattern: None,
+                database_name: Some(database.to_owned()),
+                ..entry.clone()
+            });
+        }
+
+        for table in self
+            .sharded_tables
+            .iter()
+            .filter(|t| t.database == template)
+        {
+            config.sharded_tables.push(ShardedTable {
+                database: database.to_owned(),
+                ..table.clone()
+            });
+        }
+
+        for tables in self
+            .omnisharded_tables
+            .iter()
+            .filter(|t| t.database == template)
+        {
+            config.omnisharded_tables.push(OmnishardedTables {
+                database: database.to_owned(),
+                ..tables.clone()
+            });
+        }
+
+        for mapping in self
+            .sharded_mappings
+            .iter()
+            .filter(|m| m.database == template)
+        {
+            config.sharded_mappings.push(ShardedMapping {
+                database: database.to_owned(),
+                ..mapping.clone()
+            });
+        }
+
+        config
+    }
+
     /// Organize sharded tables by database name.
     pub fn sharded_tables(&self) -> HashMap<String, Vec<ShardedTable>> {
         let mut tables = HashMap::new();
@@ -280,6 +447,33 @@ impl Config {
         queries
     }
 
+    /// Replace configured list mappings with the ones saved in `sharded_mappings_path`.
+    pub fn load_sharded_mappings(&mut self) -> Result<(), Error> {
+        let Some(ref path) = self.general.sharded_mappings_path else {
+            return Ok(());
+        };
+
+        // Nothing was saved yet.
+        let Ok(saved) = read_to_string(path) else {
+            return Ok(());
+        };
+
+        let saved: ShardedMappings = toml::from_str(&saved)?;
+        let keys = saved
+            .sharded_mappings
+            .iter()
+            .map(|mapping| mapping.key())
+            .collect::<HashSet<_>>();
+
+        self.sharded_mappings.retain(|mapping| {
+            mapping.kind != ShardedMappingKind::List || !keys.contains(&mapping.key())
+        });
+        self.sharded_mappings.extend(saved.sharded_mappings);
+        info!("loaded \"{}\"", path.display());
+
+        Ok(())
+    }
+
     /// Sharded mappings.
     pub fn sharded_mappings(
         &self,
@@ -288,13 +482,7 @@ impl Config {
 
         for mapping in &self.sharded_mappings {
             let mapping = mapping.clone();
-            let entry = mappings
-                .entry((
-                    mapping.database.clone(),
-                    mapping.column.clone(),
-                    mapping.table.clone(),
-                ))
-                .or_insert_with(Vec::new);
+            let entry = mappings.entry(mapping.key()).or_insert_with(Vec::new);
             entry.push(mapping);
         }
 
@@ -303,7 +491,7 @@ impl Config {
 
     pub fn check(&self) {
         // Check databases.
-        let mut duplicate_primaries = HashSet::new();
+        let mut duplicates = HashSet::new();
         for database in self.databases.clone() {
             let id = (
                 database.name.clone(),
@@ -311,14 +499,159 @@ impl Config {
                 database.shard,
                 database.port,
             );
-            let new = duplicate_primaries.insert(id);
-            if !new {
+            let new = duplicates.insert(id);
+            // Duplicate primaries are reported by check_topology().
+            if !new && database.role != Role::Primary {
                 warn!(
                     "database \"{}\" (shard={}) has a duplicate {}",
                     database.name, database.shard, database.role,
                 );
             }
+
+            if let Err(err) = database.check_startup_parameters() {
+                warn!("{}, it will be ignored", err);
+            }
+
+            if let Err(err) = database.check_session_setup() {
+                warn!("{}", err);
+            }
+
+            if self
+                .databases
+                .iter()
+                .any(|d| d.name == database.name && d.name_pattern != database.name_pattern)
+            {
+                warn!(
+                    "database \"{}\" has different \"name_pattern\" settings, using the first one",
+                    database.name
+                );
+            }
+        }
+
+        if let Err(err) = self.check_topology() {
+            warn!("{}", err);
+        }
+
+        // Configs written before these checks existed keep loading.
+        if let Err(err) = self.check_timeouts() {
+            warn!("{}", err);
+        }
+    }
+
+    /// Check that each database has at most one primary per shard
+    /// and that its shards are numbered without gaps, starting at 0.
+    pub fn check_topology(&self) -> Result<(), Error> {
+        let mut shards: BTreeMap<&str, BTreeMap<usize, usize>> = BTreeMap::new();
+        for database in &self.databases {
+            let primaries = shards
+                .entry(database.name.as_str())
+                .or_default()
+                .entry(database.shard)
+                .or_default();
+            if database.role == Role::Primary {
+                *primaries += 1;
+                if *primaries > 1 {
+                    return Err(Error::DuplicatePrimary(
+                        database.name.clone(),
+                        database.shard,
+                    ));
+                }
+            }
+        }
+
+        for (name, shards) in shards {
+            for (expected, shard) in shards.keys().enumerate() {
+                if *shard != expected {
+                    return Err(Error::MissingShard(name.to_owned(), expected));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Check that timeouts are within their allowed range.
+    ///
+    /// `statement_timeout` isn't checked: like in Postgres, 0 disables it.
+    pub fn check_timeouts(&self) -> Result<(), Error> {
+        self.general.check_timeouts()
+    }
+
+    /// Databases requiring TLS need TLS to be configured.
+    pub fn check_tls(&self) -> Result<(), Error> {
+        if self.general.tls().is_none() {
+            if let Some(database) = self.databases.iter().find(|d| d.require_tls) {
+                return Err(Error::TlsNotConfigured(database.name.clone()));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Manual queries must route to shards that exist.
+    pub fn check_manual_queries(&self) -> Result<(), Error> {
+        if self.databases.is_empty() {
+            return Ok(());
+        }
+
+        let shards = self
+            .databases
+            .iter()
+            .map(|database| database.shard + 1)
+            .max()
+            .unwrap_or_default();
+
+        for query in &self.manual_queries {
+            if query.shard.is_some() && query.shards.is_some() {
+                return Err(Error::ManualQueryShardAndShards(query.fingerprint.clone()));
+            }
+
+            if let Some(shard) = query.shard_numbers().into_iter().find(|s| *s >= shards) {
+                return Err(Error::ManualQueryShard(
+                    query.fingerprint.clone(),
+                    shard,
+                    shards,
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Check that sharding keys are configured consistently.
+    pub fn check_sharded_tables(&self) -> Result<(), Error> {
+        for table in &self.sharded_tables {
+            let columns = table.key_columns().len();
+            if !table.data_types.is_empty() && table.data_types.len() != columns {
+                return Err(Error::ShardedTableDataTypes(
+                    table.name.clone().unwrap_or_else(|| table.column.clone()),
+                    table.database.clone(),
+                    table.data_types.len(),
+                    columns,
+                ));
+            }
+
+            if let NullShard::Shard(shard) = table.null_shard {
+                let shards = self
+                    .databases
+                    .iter()
+                    .filter(|database| database.name == table.database)
+                    .map(|database| database.shard + 1)
+                    .max()
+                    .unwrap_or_default();
+
+                if shard >= shards {
+                    return Err(Error::NullShard(
+                        table.name.clone().unwrap_or_else(|| table.column.clone()),
+                        table.database.clone(),
+                        shard,
+                        shards,
+                    ));
+                }
+            }
         }
+
+        Ok(())
     }
 
     /// Multi-tenanncy is enabled.
@@ -339,6 +672,12 @@ pub struct General {
     /// Spawn this many Tokio threads.
     #[serde(default = "General::workers")]
     pub workers: usize,
+    /// Maximum number of client connections waiting to be accepted.
+    #[serde(default = "General::listen_backlog")]
+    pub listen_backlog: u32,
+    /// Accept client connections on this many sockets, using `SO_REUSEPORT`.
+    #[serde(default = "General::accept_workers")]
+    pub accept_workers: usize,
     /// Default pool size, e.g. 10.
     #[serde(default = "General::default_pool_size")]
     pub default_pool_size: usize,
@@ -349,23 +688,23 @@ pub struct General {
     #[serde(default)]
     pub pooler_mode: PoolerMode,
     /// How often to check a connection.
-    #[serde(default = "General::healthcheck_interval")]
-    pub healthcheck_interval: u64,
+    #[serde(default = "General::default_healthcheck_interval")]
+    pub healthcheck_interval: HumanDuration,
     /// How often to issue a healthcheck via an idle connection.
-    #[serde(default = "General::idle_healthcheck_interval")]
-    pub idle_healthcheck_interval: u64,
+    #[serde(default = "General::default_idle_healthcheck_interval")]
+    pub idle_healthcheck_interval: HumanDuration,
     /// Delay idle healthchecks by this time at startup.
-    #[serde(default = "General::idle_healthcheck_delay")]
-    pub idle_healthcheck_delay: u64,
+    #[serde(default = "General::default_idle_healthcheck_delay")]
+    pub idle_healthcheck_delay: HumanDuration,
     /// Healthcheck timeout.
-    #[serde(default = "General::healthcheck_timeout")]
-    pub healthcheck_timeout: u64,
+    #[serde(default = "General::default_healthcheck_timeout")]
+    pub healthcheck_timeout: HumanDuration,
     /// Maximum duration of a ban.
-    #[serde(default = "General::ban_timeout")]
-    pub ban_timeout: u64,
+    #[serde(default = "General::default_ban_timeout")]
+    pub ban_timeout: HumanDuration,
     /// Rollback timeout.
-    #[serde(default = "General::rollback_timeout")]
-    pub rollback_timeout: u64,
+    #[serde(default = "General::default_rollback_timeout")]
+    pub rollback_timeout: HumanDuration,
     /// Load balancing strategy.
     #[serde(default = "General::load_balancing_strategy")]
     pub load_balancing_strategy: LoadBalancingStrategy,
@@ -375,6 +714,30 @@ pub struct General {
     /// Read write split.
     #[serde(default)]
     pub read_write_split: ReadWriteSplit,
+    /// Send reads to servers that have the client's previous writes.
+    #[serde(default)]
+    pub read_your_writes: ReadYourWrites,
+    /// How long reads go to the primary after a write, with `read_your_writes = "sticky"`,
+    /// or cross-shard reads with `read_your_writes = "lsn"`.
+    #[serde(default = "General::default_read_your_writes_window")]
+    pub read_your_writes_window: HumanDuration,
+    /// Send reads to replicas while the primary is banned, even if the
+    /// conservative read/write strategy would send them to the primary.
+    #[serde(default)]
+    pub degraded_reads: bool,
+    /// Functions that don't send SELECTs to the primary, e.g. `st_*`.
+    #[serde(default)]
+    pub function_allowlist: Vec<String>,
+    /// Functions that always send SELECTs to the primary.
+    #[serde(default)]
+    pub function_denylist: Vec<String>,
+    /// Collation used to merge text columns sorted on multiple shards.
+    /// Rows aren't checked against it unless it's set.
+    #[serde(default)]
+    pub text_merge_collation: Option<TextMergeCollation>,
+    /// Check that sharded tables match the database schema after connecting.
+    #[serde(default)]
+    pub validate_sharding_schema: ValidateShardingSchema,
     /// TLS certificate.
     pub tls_certificate: Option<PathBuf>,
     /// TLS private key.
@@ -386,54 +749,105 @@ pub struct General {
     pub tls_server_ca_certificate: Option<PathBuf>,
     /// Shutdown timeout.
     #[serde(default = "General::default_shutdown_timeout")]
-    pub shutdown_timeout: u64,
+    pub shutdown_timeout: HumanDuration,
     /// Broadcast IP.
     pub broadcast_address: Option<Ipv4Addr>,
     /// Broadcast port.
     #[serde(default = "General::broadcast_port")]
     pub broadcast_port: u16,
+    /// Name of this PgDog deployment, shared with peers found by service discovery.
+    #[serde(default)]
+    pub cluster_name: Option<String>,
     /// Load queries to file (warning: slow, don't use in production).
     #[serde(default)]
     pub query_log: Option<PathBuf>,
+    /// Number of routing decisions remembered for each client.
+    #[serde(default)]
+    pub routing_history: usize,
+    /// Hide sharding key values in the routing history and query log.
+    #[serde(default = "General::redact_sharding_keys")]
+    pub redact_sharding_keys: bool,
+    /// Append authentication audit events to this file as JSON lines.
+    /// If not set, they are logged with the `auth_audit` target.
+    #[serde(default)]
+    pub auth_audit_log: Option<PathBuf>,
     /// Enable OpenMetrics server on this port.
     pub openmetrics_port: Option<u16>,
     /// OpenMetrics prefix.
     pub openmetrics_namespace: Option<String>,
+    /// Serve the admin HTTP API on this port, in addition to the OpenMetrics port.
+    pub admin_http_port: Option<u16>,
+    /// Bearer token required by the admin HTTP API. The API is disabled if not set.
+    pub admin_http_token: Option<String>,
     /// Prepared statatements support.
     #[serde(default)]
     prepared_statements: PreparedStatements,
     /// Limit on the number of prepared statements in the server cache.
     #[serde(default = "General::prepared_statements_limit")]
     pub prepared_statements_limit: usize,
+    /// Prepare this many of the most used statements on new server connections.
+    #[serde(default)]
+    pub prepared_statements_prewarm: usize,
+    /// How long preparing statements on a new connection can take before
+    /// we stop waiting for it. The server's replies are still read.
+    #[serde(default = "General::default_prepared_statements_prewarm_timeout")]
+    pub prepared_statements_prewarm_timeout: HumanDuration,
     #[serde(default = "General::query_cache_limit")]
     pub query_cache_limit: usize,
+    /// Remove statements from the query cache if unused for this long.
+    #[serde(default)]
+    pub query_cache_max_age: Option<HumanDuration>,
     /// Automatically add connection pools for user/database pairs we don't have.
     #[serde(default)]
     pub passthrough_auth: PassthoughAuth,
     /// Server connect timeout.
     #[serde(default = "General::default_connect_timeout")]
-    pub connect_timeout: u64,
+    pub connect_timeout: HumanDuration,
     /// Attempt connections multiple times on bad networks.
     #[serde(default = "General::connect_attempts")]
     pub connect_attempts: u64,
     /// How long to wait between connection attempts.
     #[serde(default = "General::default_connect_attempt_delay")]
-    pub connect_attempt_delay: u64,
+    pub connect_attempt_delay: HumanDuration,
     /// How long to wait for a query to return the result before aborting. Dangerous: don't use unless your network is bad.
     #[serde(default = "General::default_query_timeout")]
-    pub query_timeout: u64,
+    pub query_timeout: HumanDuration,
     /// Checkout timeout.
-    #[serde(default = "General::checkout_timeout")]
-    pub checkout_timeout: u64,
+    #[serde(default = "General::default_checkout_timeout")]
+    pub checkout_timeout: HumanDuration,
     /// Dry run for sharding. Parse the query, route to shard 0.
     #[serde(default)]
     pub dry_run: bool,
+    /// What to do with joins of sharded tables that aren't on their sharding keys.
+    #[serde(default)]
+    pub cross_shard_join: CrossShardJoin,
+    /// Don't parse queries longer than this many bytes, route them using `oversized_query` instead.
+    #[serde(default)]
+    pub max_query_length_for_parsing: Option<usize>,
+    /// How to route queries too long to parse.
+    #[serde(default)]
+    pub oversized_query: OversizedQuery,
+    /// File where list mappings changed with `ADD MAPPING` and `DROP MAPPING` are saved.
+    /// They replace the list mappings configured for the same columns.
+    pub sharded_mappings_path: Option<PathBuf>,
+    /// Reject SET ROLE and SET SESSION AUTHORIZATION in transaction mode.
+    #[serde(default)]
+    pub reject_set_role: bool,
     /// Idle timeout.
-    #[serde(default = "General::idle_timeout")]
-    pub idle_timeout: u64,
+    #[serde(default = "General::default_idle_timeout")]
+    pub idle_timeout: HumanDuration,
     /// Client idle timeout.
     #[serde(default = "General::default_client_idle_timeout")]
-    pub client_idle_timeout: u64,
+    pub client_idle_timeout: HumanDuration,
+    /// Disconnect streaming replication clients that haven't sent anything for this long, in ms.
+    #[serde(default = "General::default_replication_client_timeout")]
+    pub replication_client_timeout: HumanDuration,
+    /// Maximum number of databases and users created from templates, i.e., entries with a `name_pattern`.
+    #[serde(default = "General::default_template_limit")]
+    pub template_limit: usize,
+    /// Close pools created from templates once no clients used them for this long, in ms.
+    #[serde(default = "General::default_template_idle_timeout")]
+    pub template_idle_timeout: HumanDuration,
     /// Mirror queue size.
     #[serde(default = "General::mirror_queue")]
     pub mirror_queue: usize,
@@ -445,12 +859,84 @@ pub struct General {
     /// Disable cross-shard queries.
     #[serde(default)]
     pub cross_shard_disabled: bool,
+    /// Cancel queries sent to multiple shards that run for longer than this.
+    #[serde(default)]
+    pub cross_shard_timeout: Option<HumanDuration>,
+    /// Cancel queries sent to multiple shards that return more rows than this.
+    #[serde(default)]
+    pub cross_shard_max_rows: Option<usize>,
+    /// Cross-shard limits apply to queries sent to all shards, or to more shards than this.
+    #[serde(default)]
+    pub cross_shard_limits_min_shards: usize,
     /// How often to refresh DNS entries, in ms.
     #[serde(default)]
-    pub dns_ttl: Option<u64>,
+    pub dns_ttl: Option<HumanDuration>,
     /// LISTEN/NOTIFY channel size.
     #[serde(default)]
     pub pub_sub_channel_size: usize,
+    /// Gradually ramp up traffic to replicas that just came online, in ms.
+    #[serde(default)]
+    pub replica_slow_start: HumanDuration,
+    /// Stop reading from the server once this many bytes are waiting to be sent to the client.
+    #[serde(default = "General::client_write_buffer")]
+    pub client_write_buffer: usize,
+    /// Flush responses to the client once this many bytes are buffered,
+    /// instead of waiting for the end of the response.
+    #[serde(default = "General::flush_threshold")]
+    pub flush_threshold: usize,
+    /// Query used to healthcheck server connections.
+    #[serde(default)]
+    pub healthcheck_query: Option<String>,
+    /// `server_version` sent to clients while the database is down,
+    /// if PgDog didn't connect to it yet.
+    #[serde(default)]
+    pub server_version: Option<String>,
+    /// Check that primaries and replicas haven't switched roles during healthchecks.
+    #[serde(default)]
+    pub expected_role_check: bool,
+    /// Run single-statement transactions without sending BEGIN and COMMIT to the server.
+    #[serde(default)]
+    pub elide_single_statement_transactions: bool,
+    /// Reject new transactions when p95 checkout wait exceeds this, in ms.
+    #[serde(default)]
+    pub shed_above_wait_ms: Option<HumanDuration>,
+    /// Don't wait for a connection longer than the client's statement_timeout.
+    #[serde(default = "General::checkout_statement_timeout")]
+    pub checkout_statement_timeout: bool,
+    /// Which client waiting for a connection gets served first.
+    #[serde(default)]
+    pub checkout_order: CheckoutOrder,
+    /// Clients waiting for longer than this percentage of `checkout_timeout`
+    /// are served before anyone else.
+    #[serde(default = "General::checkout_starvation_threshold")]
+    pub checkout_starvation_threshold: u64,
+    /// Send session mode clients reconnecting with the same `pgdog.session_tag`
+    /// to the shard they used before, if they disconnected less than this long ago.
+    #[serde(default)]
+    pub session_pin_ttl: Option<HumanDuration>,
+    /// Soft time budget for each plugin routing call, in ms.
+    #[serde(default)]
+    pub plugin_timeout_ms: Option<HumanDuration>,
+    /// What to do with plugins that keep going over `plugin_timeout_ms`.
+    #[serde(default)]
+    pub plugin_slow_action: PluginSlowAction,
+    /// Run this command and read users from its output instead of users.toml.
+    #[serde(default)]
+    pub users_command: Option<String>,
+    /// Check users.toml for changes this often and reload it.
+    #[serde(default)]
+    pub users_watch_interval: Option<HumanDuration>,
+}
+
+/// What to do with a plugin that's repeatedly too slow.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PluginSlowAction {
+    /// Log a warning and keep calling it.
+    #[default]
+    Warn,
+    /// Stop calling it until PgDog is restarted.
+    Disable,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
@@ -518,18 +1004,27 @@ impl Default for General {
             host: Self::host(),
             port: Self::port(),
             workers: Self::workers(),
+            listen_backlog: Self::listen_backlog(),
+            accept_workers: Self::accept_workers(),
             default_pool_size: Self::default_pool_size(),
             min_pool_size: Self::min_pool_size(),
             pooler_mode: PoolerMode::default(),
-            healthcheck_interval: Self::healthcheck_interval(),
-            idle_healthcheck_interval: Self::idle_healthcheck_interval(),
-            idle_healthcheck_delay: Self::idle_healthcheck_delay(),
-            healthcheck_timeout: Self::healthcheck_timeout(),
-            ban_timeout: Self::ban_timeout(),
-            rollback_timeout: Self::rollback_timeout(),
+            healthcheck_interval: Self::default_healthcheck_interval(),
+            idle_healthcheck_interval: Self::default_idle_healthcheck_interval(),
+            idle_healthcheck_delay: Self::default_idle_healthcheck_delay(),
+            healthcheck_timeout: Self::default_healthcheck_timeout(),
+            ban_timeout: Self::default_ban_timeout(),
+            rollback_timeout: Self::default_rollback_timeout(),
             load_balancing_strategy: Self::load_balancing_strategy(),
             read_write_strategy: ReadWriteStrategy::default(),
             read_write_split: ReadWriteSplit::default(),
+            read_your_writes: ReadYourWrites::default(),
+            read_your_writes_window: Self::default_read_your_writes_window(),
+            degraded_reads: bool::default(),
+            function_allowlist: vec![],
+            function_denylist: vec![],
+            text_merge_collation: None,
+            validate_sharding_schema: ValidateShardingSchema::default(),
             tls_certificate: None,
             tls_private_key: None,
             tls_verify: Self::default_tls_verify(),
@@ -537,27 +1032,64 @@ impl Default for General {
             shutdown_timeout: Self::default_shutdown_timeout(),
             broadcast_address: None,
             broadcast_port: Self::broadcast_port(),
+            cluster_name: None,
             query_log: None,
+            routing_history: 0,
+            redact_sharding_keys: Self::redact_sharding_keys(),
+            auth_audit_log: None,
             openmetrics_port: None,
             openmetrics_namespace: None,
+            admin_http_port: None,
+            admin_http_token: None,
             prepared_statements: PreparedStatements::default(),
             prepared_statements_limit: Self::prepared_statements_limit(),
+            prepared_statements_prewarm: 0,
+            prepared_statements_prewarm_timeout: Self::default_prepared_statements_prewarm_timeout(
+            ),
             query_cache_limit: Self::query_cache_limit(),
+            query_cache_max_age: None,
             passthrough_auth: PassthoughAuth::default(),
             connect_timeout: Self::default_connect_timeout(),
             connect_attempt_delay: Self::default_connect_attempt_delay(),
             connect_attempts: Self::connect_attempts(),
             query_timeout: Self::default_query_timeout(),
-            checkout_timeout: Self::checkout_timeout(),
+            checkout_timeout: Self::default_checkout_timeout(),
             dry_run: bool::default(),
-            idle_timeout: Self::idle_timeout(),
+            cross_shard_join: CrossShardJoin::default(),
+            max_query_length_for_parsing: None,
+            oversized_query: OversizedQuery::default(),
+            sharded_mappings_path: None,
+            reject_set_role: bool::default(),
+            idle_timeout: Self::default_idle_timeout(),
             client_idle_timeout: Self::default_client_idle_timeout(),
+            replication_client_timeout: Self::default_replication_client_timeout(),
+            template_limit: Self::default_template_limit(),
+            template_idle_timeout: Self::default_template_idle_timeout(),
             mirror_queue: Self::mirror_queue(),
             mirror_exposure: Self::mirror_exposure(),
             auth_type: AuthType::default(),
             cross_shard_disabled: bool::default(),
+            cross_shard_timeout: None,
+            cross_shard_max_rows: None,
+            cross_shard_limits_min_shards: 0,
             dns_ttl: None,
             pub_sub_channel_size: 0,
+            replica_slow_start: HumanDuration::default(),
+            client_write_buffer: Self::client_write_buffer(),
+            flush_threshold: Self::flush_threshold(),
+            healthcheck_query: None,
+            server_version: None,
+            expected_role_check: bool::default(),
+            elide_single_statement_transactions: bool::default(),
+            shed_above_wait_ms: None,
+            checkout_statement_timeout: Self::checkout_statement_timeout(),
+            checkout_order: CheckoutOrder::default(),
+            checkout_starvation_threshold: Self::checkout_starvation_threshold(),
+            session_pin_ttl: None,
+            plugin_timeout_ms: None,
+            plugin_slow_action: PluginSlowAction::default(),
+            users_command: None,
+            users_watch_interval: None,
         }
     }
 }
@@ -575,6 +1107,14 @@ impl General {
         2
     }
 
+    fn listen_backlog() -> u32 {
+        1024
+    }
+
+    fn accept_workers() -> usize {
+        1
+    }
+
     fn default_pool_size() -> usize {
         10
     }
@@ -583,52 +1123,149 @@ impl General {
         1
     }
 
-    fn healthcheck_interval() -> u64 {
-        30_000
+    fn default_healthcheck_interval() -> HumanDuration {
+        HumanDuration::from_secs(30)
+    }
+
+    fn default_idle_healthcheck_interval() -> HumanDuration {
+        HumanDuration::from_secs(30)
+    }
+
+    fn default_idle_healthcheck_delay() -> HumanDuration {
+        HumanDuration::from_secs(5)
+    }
+
+    fn default_ban_timeout() -> HumanDuration {
+        HumanDuration::from_secs(300)
+    }
+
+    fn default_prepared_statements_prewarm_timeout() -> HumanDuration {
+        HumanDuration::from_millis(10)
+    }
+
+    fn default_rollback_timeout() -> HumanDuration {
+        HumanDuration::from_secs(5)
+    }
+
+    fn default_read_your_writes_window() -> HumanDuration {
+        HumanDuration::from_secs(1)
+    }
+
+    fn default_idle_timeout() -> HumanDuration {
+        HumanDuration::from_secs(60)
+    }
+
+    fn default_client_idle_timeout() -> HumanDuration {
+        HumanDuration::MAX
+    }
+
+    fn default_replication_client_timeout() -> HumanDuration {
+        HumanDuration::from_secs(60)
+    }
+
+    fn default_template_limit() -> usize {
+        1000
+    }
+
+    fn default_template_idle_timeout() -> HumanDuration {
+        HumanDuration::from_secs(300)
+    }
+
+    fn default_query_timeout() -> HumanDuration {
+        HumanDuration::MAX
+    }
+
+    pub fn healthcheck_interval(&self) -> Duration {
+        self.healthcheck_interval.into()
+    }
+
+    pub fn idle_healthcheck_interval(&self) -> Duration {
+        self.idle_healthcheck_interval.into()
+    }
+
+    pub fn idle_healthcheck_delay(&self) -> Duration {
+        self.idle_healthcheck_delay.into()
     }
 
-    fn idle_healthcheck_interval() -> u64 {
-        30_000
+    pub fn healthcheck_timeout(&self) -> Duration {
+        self.healthcheck_timeout.into()
     }
 
-    fn idle_healthcheck_delay() -> u64 {
-        5_000
+    pub fn ban_timeout(&self) -> Duration {
+        self.ban_timeout.into()
     }
 
-    fn ban_timeout() -> u64 {
-        Duration::from_secs(300).as_millis() as u64
+    pub fn rollback_timeout(&self) -> Duration {
+        self.rollback_timeout.into()
     }
 
-    fn rollback_timeout() -> u64 {
-        5_000
+    pub fn prepared_statements_prewarm_timeout(&self) -> Duration {
+        self.prepared_statements_prewarm_timeout.into()
     }
 
-    fn idle_timeout() -> u64 {
-        Duration::from_secs(60).as_millis() as u64
+    pub fn connect_timeout(&self) -> Duration {
+        self.connect_timeout.into()
     }
 
-    fn default_client_idle_timeout() -> u64 {
-        Duration::MAX.as_millis() as u64
+    pub fn checkout_timeout(&self) -> Duration {
+        self.checkout_timeout.into()
     }
 
-    fn default_query_timeout() -> u64 {
-        Duration::MAX.as_millis() as u64
+    pub fn idle_timeout(&self) -> Duration {
+        self.idle_timeout.into()
     }
 
     pub(crate) fn query_timeout(&self) -> Duration {
-        Duration::from_millis(self.query_timeout)
+        self.query_timeout.into()
     }
 
     pub fn dns_ttl(&self) -> Option<Duration> {
-        self.dns_ttl.map(Duration::from_millis)
+        self.dns_ttl.map(Duration::from)
     }
 
     pub(crate) fn client_idle_timeout(&self) -> Duration {
-        Duration::from_millis(self.client_idle_timeout)
+        self.client_idle_timeout.into()
+    }
+
+    pub(crate) fn replication_client_timeout(&self) -> Duration {
+        self.replication_client_timeout.into()
+    }
+
+    pub(crate) fn template_idle_timeout(&self) -> Duration {
+        self.template_idle_timeout.into()
     }
 
     pub(crate) fn connect_attempt_delay(&self) -> Duration {
-        Duration::from_millis(self.connect_attempt_delay)
+        self.connect_attempt_delay.into()
+    }
+
+    pub(crate) fn replica_slow_start(&self) -> Duration {
+        self.replica_slow_start.into()
+    }
+
+    pub(crate) fn shed_above_wait(&self) -> Option<Duration> {
+        self.shed_above_wait_ms.map(Duration::from)
+    }
+
+    /// How long a client can wait before it's served ahead of everyone else.
+    pub(crate) fn checkout_starvation(&self) -> Duration {
+        self.checkout_timeout() * self.checkout_starvation_threshold.min(100) as u32 / 100
+    }
+
+    pub(crate) fn plugin_timeout(&self) -> Option<Duration> {
+        self.plugin_timeout_ms.map(Duration::from)
+    }
+
+    pub(crate) fn session_pin_ttl(&self) -> Option<Duration> {
+        self.session_pin_ttl.map(Duration::from)
+    }
+
+    pub(crate) fn query_cache_max_age(&self) -> Option<Duration> {
+        self.query_cache_max_age.map(Duration::from)
+    }
+
+    pub fn users_watch_interval(&self) -> Option<Duration> {
+        self.users_watch_interval.map(Duration::from)
     }
 
     fn load_balancing_strategy() -> LoadBalancingStrategy {
@@ -639,16 +1276,16 @@ impl General {
         TlsVerifyMode::Prefer
     }
 
-    fn default_shutdown_timeout() -> u64 {
-        60_000
+    fn default_shutdown_timeout() -> HumanDuration {
+        HumanDuration::from_secs(60)
     }
 
-    fn default_connect_timeout() -> u64 {
-        5_000
+    fn default_connect_timeout() -> HumanDuration {
+        HumanDuration::from_secs(5)
     }
 
-    fn default_connect_attempt_delay() -> u64 {
-        0
+    fn default_connect_attempt_delay() -> HumanDuration {
+        HumanDuration::default()
     }
 
     fn connect_attempts() -> u64 {
@@ -659,12 +1296,24 @@ impl General {
         Self::port() + 1
     }
 
-    fn healthcheck_timeout() -> u64 {
-        Duration::from_secs(5).as_millis() as u64
+    fn default_healthcheck_timeout() -> HumanDuration {
+        HumanDuration::from_secs(5)
+    }
+
+    fn default_checkout_timeout() -> HumanDuration {
+        HumanDuration::from_secs(5)
+    }
+
+    fn checkout_statement_timeout() -> bool {
+        true
+    }
+
+    fn redact_sharding_keys() -> bool {
+        true
     }
 
-    fn checkout_timeout() -> u64 {
-        Duration::from_secs(5).as_millis() as u64
+    fn checkout_starvation_threshold() -> u64 {
+        50
     }
 
     fn mirror_queue() -> usize {
@@ -683,9 +1332,59 @@ impl General {
         usize::MAX
     }
 
+    fn client_write_buffer() -> usize {
+        1024 * 1024
+    }
+
+    fn flush_threshold() -> usize {
+        16 * 1024
+    }
+
     /// Get shutdown timeout as a duration.
     pub fn shutdown_timeout(&self) -> Duration {
-        Duration::from_millis(self.shutdown_timeout)
+        self.shutdown_timeout.into()
+    }
+
+    /// Check that timeouts are within their allowed range.
+    pub fn check_timeouts(&self) -> Result<(), Error> {
+        let one = HumanDuration::from_millis(1);
+        for (name, value, min) in [
+            ("healthcheck_interval", self.healthcheck_interval, one),
+            (
+                "idle_healthcheck_interval",
+                self.idle_healthcheck_interval,
+                one,
+            ),
+            ("healthcheck_timeout", self.healthcheck_timeout, one),
+            ("rollback_timeout", self.rollback_timeout, one),
+            (
+                "prepared_statements_prewarm_timeout",
+                self.prepared_statements_prewarm_timeout,
+                one,
+            ),
+            ("connect_timeout", self.connect_timeout, one),
+            ("query_timeout", self.query_timeout, one),
+            ("client_idle_timeout", self.client_idle_timeout, one),
+            (
+                "replication_client_timeout",
+                self.replication_client_timeout,
+                one,
+            ),
+            ("template_idle_timeout", self.template_idle_timeout, one),
+            (
+                "checkout_timeout",
+                self.checkout_timeout,
+                HumanDuration::from_millis(10),
+            ),
+        ] {
+            value.at_least(name, min)?;
+        }
+
+        if let Some(cross_shard_timeout) = self.cross_shard_timeout {
+            cross_shard_timeout.at_least("cross_shard_timeout", one)?;
+        }
+
+        Ok(())
     }
 
     /// Get TLS config, if any.
@@ -739,6 +1438,17 @@ pub enum LoadBalancingStrategy {
     LeastActiveConnections,
 }
 
+/// Order in which clients waiting for a connection are served.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckoutOrder {
+    /// Longest waiting client first.
+    #[default]
+    Fifo,
+    /// Most recent client first.
+    Lifo,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Copy)]
 #[serde(rename_all = "snake_case")]
 pub enum TlsVerifyMode {
@@ -757,49 +1467,254 @@ pub enum ReadWriteSplit {
     ExcludePrimary,
 }
 
-/// Database server proxied by pgDog.
-#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Ord, PartialOrd, Eq)]
-#[serde(deny_unknown_fields)]
-pub struct Database {
-    /// Database name visible to the clients.
-    pub name: String,
-    /// Database role, e.g. primary.
-    #[serde(default)]
-    pub role: Role,
-    /// Database host or IP address, e.g. 127.0.0.1.
-    pub host: String,
-    /// Database port, e.g. 5432.
-    #[serde(default = "Database::port")]
-    pub port: u16,
-    /// Shard.
-    #[serde(default)]
-    pub shard: usize,
-    /// PostgreSQL database name, e.g. "postgres".
-    pub database_name: Option<String>,
-    /// Use this user to connect to the database, overriding the userlist.
-    pub user: Option<String>,
-    /// Use this password to login, overriding the userlist.
-    pub password: Option<String>,
-    // Maximum number of connections to this database from this pooler.
-    // #[serde(default = "Database::max_connections")]
-    // pub max_connections: usize,
-    /// Pool size for this database pools, overriding `default_pool_size`.
-    pub pool_size: Option<usize>,
-    /// Minimum pool size for this database pools, overriding `min_pool_size`.
-    pub min_pool_size: Option<usize>,
-    /// Pooler mode.
-    pub pooler_mode: Option<PoolerMode>,
-    /// Statement timeout.
-    pub statement_timeout: Option<u64>,
-    /// Idle timeout.
-    pub idle_timeout: Option<u64>,
-    /// Mirror of another database.
-    pub mirror_of: Option<String>,
-    /// Read-only mode.
-    pub read_only: Option<bool>,
-}
+/// Consistency of reads that follow writes from the same client.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum ReadYourWrites {
+    /// Reads can go to any replica.
+    #[default]
+    Off,
+    /// Reads go to the primary for `read_your_writes_window` after a write.
+    Sticky,
+    /// Reads go to replicas that replayed the client's last write, or the primary.
+    Lsn,
+}
+
+/// How stale reads sent to replicas are allowed to be.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum ReadConsistency {
+    /// Reads can go to any replica.
+    #[default]
+    Any,
+    /// Reads go to replicas lagging less than this, or the primary.
+    Bounded(HumanDuration),
+    /// Reads always go to the primary.
+    Strong,
+}
+
+impl std::fmt::Display for ReadConsistency {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Any => write!(f, "any"),
+            Self::Bounded(_) => write!(f, "bounded"),
+            Self::Strong => write!(f, "strong"),
+        }
+    }
+}
+
+/// Collation used to compare text columns when merging
+/// rows sorted by multiple shards.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum TextMergeCollation {
+    /// Byte order, like the "C" collation.
+    #[default]
+    C,
+    /// Unicode Collation Algorithm, close to ICU and en_US collations.
+    Unicode,
+}
+
+impl std::fmt::Display for TextMergeCollation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::C => write!(f, "c"),
+            Self::Unicode => write!(f, "unicode"),
+        }
+    }
+}
+
+/// Checking sharded tables against the database schema,
+/// configured as `true`, `false` or `"strict"`.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Default, Copy)]
+#[serde(
+    try_from = "ValidateShardingSchemaValue",
+    into = "ValidateShardingSchemaValue"
+)]
+pub enum ValidateShardingSchema {
+    /// Don't check.
+    #[default]
+    Disabled,
+    /// Log mismatches as errors.
+    Enabled,
+    /// Log mismatches and refuse to start.
+    Strict,
+}
+
+impl ValidateShardingSchema {
+    /// Sharded tables are checked.
+    pub fn enabled(&self) -> bool {
+        *self != Self::Disabled
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum ValidateShardingSchemaValue {
+    Enabled(bool),
+    Mode(String),
+}
+
+impl TryFrom<ValidateShardingSchemaValue> for ValidateShardingSchema {
+    type Error = String;
+
+    fn try_from(value: ValidateShardingSchemaValue) -> Result<Self, Self::Error> {
+        match value {
+            ValidateShardingSchemaValue::Enabled(true) => Ok(Self::Enabled),
+            ValidateShardingSchemaValue::Enabled(false) => Ok(Self::Disabled),
+            ValidateShardingSchemaValue::Mode(mode) => match mode.as_str() {
+                "strict" => Ok(Self::Strict),
+                _ => Err(format!(
+                    r#"invalid validate_sharding_schema "{}", expected true, false or "strict""#,
+                    mode
+                )),
+            },
+        }
+    }
+}
+
+impl From<ValidateShardingSchema> for ValidateShardingSchemaValue {
+    fn from(value: ValidateShardingSchema) -> Self {
+        match value {
+            ValidateShardingSchema::Disabled => Self::Enabled(false),
+            ValidateShardingSchema::Enabled => Self::Enabled(true),
+            ValidateShardingSchema::Strict => Self::Mode("strict".into()),
+        }
+    }
+}
+
+/// Handling of joins between sharded tables that can return incomplete results.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum CrossShardJoin {
+    /// Return an error to the client.
+    #[default]
+    Error,
+    /// Log a warning and run the query on all shards.
+    Warn,
+}
+
+/// Routing of queries longer than `max_query_length_for_parsing`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum OversizedQuery {
+    /// Send the query to the primary, on all shards unless the query
+    /// has a shard comment. Rejected if cross-shard queries are disabled.
+    #[default]
+    Primary,
+    /// Return an error to the client.
+    Error,
+}
+
+/// How mirrored queries are routed on the mirror.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Ord, PartialOrd, Eq, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum MirrorStrategy {
+    /// Route queries using the mirror's sharding configuration.
+    #[default]
+    Reroute,
+    /// Use the original shard number, modulo the mirror's shard count.
+    PreserveShard,
+    /// Send everything to shard 0.
+    SingleShard,
+}
+
+/// Database server proxied by pgDog.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Ord, PartialOrd, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct Database {
+    /// Database name visible to the clients.
+    pub name: String,
+    /// Use this entry as a template for databases matching the pattern, e.g. `customer_*`.
+    /// Pools are created when clients connect, to the database with the requested name.
+    pub name_pattern: Option<String>,
+    /// Database role, e.g. primary.
+    #[serde(default)]
+    pub role: Role,
+    /// Database host or IP address, e.g. 127.0.0.1.
+    pub host: String,
+    /// Database port, e.g. 5432.
+    #[serde(default = "Database::port")]
+    pub port: u16,
+    /// Shard.
+    #[serde(default)]
+    pub shard: usize,
+    /// PostgreSQL database name, e.g. "postgres".
+    pub database_name: Option<String>,
+    /// Use this user to connect to the database, overriding the userlist.
+    pub user: Option<String>,
+    /// Use this password to login, overriding the userlist.
+    pub password: Option<String>,
+    // Maximum number of connections to this database from this pooler.
+    // #[serde(default = "Database::max_connections")]
+    // pub max_connections: usize,
+    /// Pool size for this database pools, overriding `default_pool_size`.
+    pub pool_size: Option<usize>,
+    /// Minimum pool size for this database pools, overriding `min_pool_size`.
+    pub min_pool_size: Option<usize>,
+    /// Pooler mode.
+    pub pooler_mode: Option<PoolerMode>,
+    /// Statement timeout. 0 disables it, like in Postgres.
+    pub statement_timeout: Option<HumanDuration>,
+    /// Idle timeout.
+    pub idle_timeout: Option<HumanDuration>,
+    /// Mirror of another database.
+    pub mirror_of: Option<String>,
+    /// How mirrored queries are routed.
+    #[serde(default)]
+    pub mirror_strategy: MirrorStrategy,
+    /// Read-only mode.
+    pub read_only: Option<bool>,
+    /// Healthcheck query, overriding `healthcheck_query`.
+    pub healthcheck_query: Option<String>,
+    /// Check the database role during healthchecks, overriding `expected_role_check`.
+    pub expected_role_check: Option<bool>,
+    /// Connect users missing from users.toml using this user's pool.
+    pub default_user: Option<String>,
+    /// Parameters sent in the startup message of server connections, e.g. `pgaudit.log`.
+    #[serde(default)]
+    pub startup_parameters: BTreeMap<String, String>,
+    /// Command-line options sent to the server on connection, e.g. `-c search_path=app`.
+    pub options: Option<String>,
+    /// Reject clients that don't use TLS.
+    #[serde(default)]
+    pub require_tls: bool,
+    /// Statements run at the start of each client session, e.g. `SET jit TO off`.
+    #[serde(default)]
+    pub session_setup: Vec<String>,
+    /// Functions that don't send SELECTs to the primary, overriding `function_allowlist`.
+    pub function_allowlist: Option<Vec<String>>,
+    /// Functions that always send SELECTs to the primary, overriding `function_denylist`.
+    pub function_denylist: Option<Vec<String>>,
+}
 
 impl Database {
+    /// Startup parameter is set by PgDog and can't be configured.
+    pub fn managed_startup_parameter(name: &str) -> bool {
+        ["user", "database", "replication", "client_encoding"]
+            .iter()
+            .any(|managed| managed.eq_ignore_ascii_case(name))
+    }
+
+    /// Check that startup parameters don't override the ones managed by PgDog.
+    pub fn check_startup_parameters(&self) -> Result<(), Error> {
+        for name in self.startup_parameters.keys() {
+            if Self::managed_startup_parameter(name) {
+                return Err(Error::ManagedStartupParameter(
+                    self.name.clone(),
+                    name.clone(),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Check that session setup statements are valid SQL.
+    pub fn check_session_setup(&self) -> Result<(), Error> {
+        check_session_setup(&format!("database \"{}\"", self.name), &self.session_setup)
+    }
+
     #[allow(dead_code)]
     fn max_connections() -> usize {
         usize::MAX
@@ -834,6 +1749,16 @@ impl std::fmt::Display for Role {
 pub struct Plugin {
     /// Plugin name.
     pub name: String,
+    /// Plugin configuration, passed to the plugin as JSON.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub config: Option<toml::Value>,
+}
+
+impl Plugin {
+    /// Plugin configuration as JSON, `null` if not set.
+    pub fn config_json(&self) -> String {
+        serde_json::to_string(&self.config).unwrap_or_else(|_| "null".into())
+    }
 }
 
 /// Users and passwords.
@@ -846,6 +1771,37 @@ pub struct Users {
 }
 
 impl Users {
+    /// Parse and check users TOML.
+    pub fn parse(source: &str, config: &Config) -> Result<Self, Error> {
+        // Errors don't quote the source, it has passwords.
+        let mut users: Users = toml::from_str(source).map_err(|err| Error::config(source, err))?;
+        users.check_timeouts()?;
+        users.check_tls(config)?;
+        users.check(config);
+        Ok(users)
+    }
+
+    /// Get users TOML from the output of a shell command.
+    pub fn from_command(command: &str) -> Result<String, Error> {
+        let output = std::process::Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .stdin(Stdio::null())
+            .stderr(Stdio::inherit())
+            .output()
+            .map_err(|err| Error::UsersCommand(command.into(), err.to_string()))?;
+
+        if !output.status.success() {
+            return Err(Error::UsersCommand(
+                command.into(),
+                output.status.to_string(),
+            ));
+        }
+
+        String::from_utf8(output.stdout)
+            .map_err(|_| Error::UsersCommand(command.into(), "output isn't UTF-8".into()))
+    }
+
     /// Organize users by database name.
     pub fn users(&self) -> HashMap<String, Vec<User>> {
         let mut users = HashMap::new();
@@ -858,8 +1814,40 @@ impl Users {
         users
     }
 
+    /// Users requiring TLS need TLS to be configured.
+    pub fn check_tls(&self, config: &Config) -> Result<(), Error> {
+        if config.general.tls().is_none() {
+            if let Some(user) = self.users.iter().find(|u| u.require_tls) {
+                return Err(Error::UserTlsNotConfigured(
+                    user.name.clone(),
+                    user.database.clone(),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Check that timeouts are within their allowed range.
+    ///
+    /// `statement_timeout` isn't checked: like in Postgres, 0 disables it.
+    pub fn check_timeouts(&self) -> Result<(), Error> {
+        for user in &self.users {
+            if let Some(cross_shard_timeout) = user.cross_shard_timeout {
+                cross_shard_timeout
+                    .at_least("cross_shard_timeout", HumanDuration::from_millis(1))?;
+            }
+        }
+
+        Ok(())
+    }
+
     pub fn check(&mut self, config: &Config) {
         for user in &mut self.users {
+            if let Err(err) = user.check_session_setup() {
+                warn!("{}", err);
+            }
+
             if user.password().is_empty() {
                 if !config.general.passthrough_auth() {
                     warn!(
@@ -877,6 +1865,31 @@ impl Users {
                 }
             }
         }
+
+        let mut checked = HashSet::new();
+        for database in &config.databases {
+            if let Some(ref default_user) = database.default_user {
+                if !checked.insert(&database.name) {
+                    continue;
+                }
+                let user = self
+                    .users
+                    .iter()
+                    .find(|u| &u.name == default_user && u.database == database.name);
+                match user {
+                    None => warn!(
+                        "default user \"{}\" for database \"{}\" is not in users.toml",
+                        default_user, database.name
+                    ),
+                    Some(user) if user.wildcard_password.is_none() => warn!(
+                        "default user \"{}\" for database \"{}\" doesn't have a wildcard_password, \
+                        so users missing from users.toml can't connect",
+                        default_user, database.name
+                    ),
+                    _ => (),
+                }
+            }
+        }
     }
 }
 
@@ -886,6 +1899,9 @@ impl Users {
 pub struct User {
     /// User name.
     pub name: String,
+    /// Use this entry for users matching the pattern, e.g. `app_*`.
+    /// Pools are created when clients connect, as the requested user.
+    pub name_pattern: Option<String>,
     /// Database name, from pgdog.toml.
     pub database: String,
     /// User's password.
@@ -900,17 +1916,38 @@ pub struct User {
     pub server_user: Option<String>,
     /// Server password.
     pub server_password: Option<String>,
-    /// Statement timeout.
-    pub statement_timeout: Option<u64>,
+    /// Statement timeout. 0 disables it, like in Postgres.
+    pub statement_timeout: Option<HumanDuration>,
     /// Relication mode.
     #[serde(default)]
-    pub replication_mode: bool,
+    pub replication_mode: ReplicationMode,
     /// Sharding into this database.
     pub replication_sharding: Option<String>,
     /// Idle timeout.
-    pub idle_timeout: Option<u64>,
+    pub idle_timeout: Option<HumanDuration>,
     /// Read-only mode.
     pub read_only: Option<bool>,
+    /// Password for clients connecting as this database's `default_user`.
+    pub wildcard_password: Option<String>,
+    /// Reject clients that don't use TLS.
+    #[serde(default)]
+    pub require_tls: bool,
+    /// Staleness of reads sent to replicas.
+    #[serde(default)]
+    pub read_consistency: ReadConsistency,
+    /// Statements run at the start of each client session, after the database's.
+    #[serde(default)]
+    pub session_setup: Vec<String>,
+    /// Cross-shard query timeout, overriding `cross_shard_timeout`.
+    pub cross_shard_timeout: Option<HumanDuration>,
+    /// Cross-shard query row limit, overriding `cross_shard_max_rows`.
+    pub cross_shard_max_rows: Option<usize>,
+    /// Statements this user isn't allowed to run.
+    #[serde(default)]
+    pub blocked_statements: Vec<BlockedStatement>,
+    /// Functions this user isn't allowed to call, e.g. `pg_sleep`.
+    #[serde(default)]
+    pub blocked_functions: Vec<String>,
 }
 
 impl User {
@@ -921,6 +1958,143 @@ impl User {
             ""
         }
     }
+
+    /// This entry is for the user, by name or `name_pattern`.
+    pub fn matches(&self, user: &str) -> bool {
+        match self.name_pattern {
+            Some(ref pattern) => wildcard(pattern, user),
+            None => self.name == user,
+        }
+    }
+
+    /// Check that session setup statements are valid SQL.
+    pub fn check_session_setup(&self) -> Result<(), Error> {
+        check_session_setup(
+            &format!("user \"{}\" of database \"{}\"", self.name, self.database),
+            &self.session_setup,
+        )
+    }
+}
+
+/// Parse session setup statements, so typos are found before clients connect.
+fn check_session_setup(owner: &str, statements: &[String]) -> Result<(), Error> {
+    for statement in statements {
+        if let Err(err) = pg_query::parse(statement) {
+            return Err(Error::SessionSetup(
+                owner.to_owned(),
+                statement.clone(),
+                err.to_string(),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Kind of statement blocked by a user's `blocked_statements`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Ord, PartialOrd)]
+#[serde(rename_all = "snake_case")]
+pub enum BlockedStatement {
+    /// `COPY`, to or from the server.
+    Copy,
+    /// `CREATE`, `ALTER`, `DROP`, `GRANT`, etc.
+    Ddl,
+    Truncate,
+    DeleteWithoutWhere,
+    UpdateWithoutWhere,
+}
+
+impl std::fmt::Display for BlockedStatement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Copy => write!(f, "copy"),
+            Self::Ddl => write!(f, "ddl"),
+            Self::Truncate => write!(f, "truncate"),
+            Self::DeleteWithoutWhere => write!(f, "delete_without_where"),
+            Self::UpdateWithoutWhere => write!(f, "update_without_where"),
+        }
+    }
+}
+
+/// OpenTelemetry trace export. Spans are only exported
+/// if PgDog was built with the `telemetry` feature.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Telemetry {
+    /// Export spans.
+    #[serde(default)]
+    pub enabled: bool,
+    /// OTLP collector gRPC endpoint.
+    #[serde(default = "Telemetry::endpoint")]
+    pub endpoint: String,
+    /// Service name attached to spans.
+    #[serde(default = "Telemetry::service_name")]
+    pub service_name: String,
+}
+
+impl Default for Telemetry {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            endpoint: Self::endpoint(),
+            service_name: Self::service_name(),
+        }
+    }
+}
+
+impl Telemetry {
+    fn endpoint() -> String {
+        "http://127.0.0.1:4317".into()
+    }
+
+    fn service_name() -> String {
+        "pgdog".into()
+    }
+}
+
+/// How healthcheck failures of each class ban the database.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Healthcheck {
+    /// Couldn't connect to the database in time.
+    #[serde(default)]
+    pub connect_timeout: HealthcheckPolicy,
+    /// Healthcheck query didn't finish in time.
+    #[serde(default)]
+    pub query_timeout: HealthcheckPolicy,
+    /// Database returned an error.
+    #[serde(default)]
+    pub sql_error: HealthcheckPolicy,
+    /// Connection was refused or closed.
+    #[serde(default)]
+    pub connection_reset: HealthcheckPolicy,
+}
+
+/// Ban policy for one class of healthcheck failures.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct HealthcheckPolicy {
+    /// Consecutive failures before the database is banned.
+    #[serde(default = "HealthcheckPolicy::failures")]
+    pub failures: usize,
+    /// How long the ban lasts, `ban_timeout` if not set.
+    #[serde(default)]
+    pub ban_timeout: Option<HumanDuration>,
+}
+
+impl Default for HealthcheckPolicy {
+    fn default() -> Self {
+        Self {
+            failures: Self::failures(),
+            ban_timeout: None,
+        }
+    }
+}
+
+impl HealthcheckPolicy {
+    fn failures() -> usize {
+        1
+    }
 }
 
 /// Admin database settings.
@@ -985,6 +2159,9 @@ pub struct ShardedTable {
     /// Table sharded on this column.
     #[serde(default)]
     pub column: String,
+    /// Table sharded on these columns together, e.g. `["tenant_id", "region"]`.
+    #[serde(default)]
+    pub columns: Vec<String>,
     /// This table is the primary sharding anchor (e.g. "users").
     #[serde(default)]
     pub primary: bool,
@@ -996,18 +2173,70 @@ pub struct ShardedTable {
     /// Data type of the column.
     #[serde(default)]
     pub data_type: DataType,
+    /// Data type of each column in `columns`. Defaults to `data_type`.
+    #[serde(default)]
+    pub data_types: Vec<DataType>,
     /// How many centroids to probe.
     #[serde(default)]
     pub centroid_probes: usize,
     /// Hasher function.
     #[serde(default)]
     pub hasher: Hasher,
+    /// Where rows with a NULL sharding key go.
+    #[serde(default)]
+    pub null_shard: NullShard,
+    /// Routing of `SELECT ... FOR UPDATE` without a sharding key.
+    #[serde(default)]
+    pub queue_pattern_routing: QueuePatternRouting,
     /// Explicit routing rules.
     #[serde(skip, default)]
     pub mapping: Option<Mapping>,
 }
 
 impl ShardedTable {
+    /// Columns making up the sharding key, in order.
+    pub fn key_columns(&self) -> Vec<&str> {
+        if self.columns.is_empty() {
+            vec![self.column.as_str()]
+        } else {
+            self.columns.iter().map(|column| column.as_str()).collect()
+        }
+    }
+
+    /// Rows with equal keys are placed on the same shard in both tables.
+    pub fn same_sharding(&self, other: &ShardedTable) -> bool {
+        let columns = self.key_columns().len();
+        columns == other.key_columns().len()
+            && (0..columns).all(|i| self.data_type_at(i) == other.data_type_at(i))
+            && self.hasher == other.hasher
+            && self.centroids == other.centroids
+            && self.mapping == other.mapping
+            && self.null_shard == other.null_shard
+    }
+
+    /// Sharding key has more than one column.
+    pub fn composite(&self) -> bool {
+        self.columns.len() > 1
+    }
+
+    /// Data type of the sharding key column at the given position.
+    pub fn data_type_at(&self, position: usize) -> DataType {
+        self.data_types
+            .get(position)
+            .copied()
+            .unwrap_or(self.data_type)
+    }
+
+    /// Treat a single-column `columns` like `column`.
+    pub fn normalize_columns(&mut self) {
+        if self.columns.len() == 1 {
+            self.column = self.columns.remove(0);
+            if !self.data_types.is_empty() {
+                self.data_type = self.data_types.remove(0);
+            }
+        }
+    }
+
     /// Load centroids from file, if provided.
     ///
     /// Centroids can be very large vectors (1000+ columns).
@@ -1045,6 +2274,134 @@ pub enum Hasher {
     Sha1,
 }
 
+/// Where rows with a NULL sharding key go,
+/// configured as a shard number, `"error"` or `"round_robin"`.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Default, Copy)]
+#[serde(try_from = "NullShardValue", into = "NullShardValue")]
+pub enum NullShard {
+    /// Reject the row.
+    #[default]
+    Error,
+    /// Spread rows evenly between all shards.
+    RoundRobin,
+    /// Send rows to this shard.
+    Shard(usize),
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum NullShardValue {
+    Shard(usize),
+    Policy(String),
+}
+
+impl TryFrom<NullShardValue> for NullShard {
+    type Error = String;
+
+    fn try_from(value: NullShardValue) -> Result<Self, Self::Error> {
+        match value {
+            NullShardValue::Shard(shard) => Ok(Self::Shard(shard)),
+            NullShardValue::Policy(policy) => match policy.as_str() {
+                "error" => Ok(Self::Error),
+                "round_robin" => Ok(Self::RoundRobin),
+                _ => Err(format!(
+                    r#"invalid null_shard "{}", expected a shard number, "error" or "round_robin""#,
+                    policy
+                )),
+            },
+        }
+    }
+}
+
+impl From<NullShard> for NullShardValue {
+    fn from(value: NullShard) -> Self {
+        match value {
+            NullShard::Error => Self::Policy("error".into()),
+            NullShard::RoundRobin => Self::Policy("round_robin".into()),
+            NullShard::Shard(shard) => Self::Shard(shard),
+        }
+    }
+}
+
+/// Replication connections opened for a user,
+/// configured as `true`, `false` or `"physical"`.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Default, Copy)]
+#[serde(try_from = "ReplicationModeValue", into = "ReplicationModeValue")]
+pub enum ReplicationMode {
+    /// Regular connections.
+    #[default]
+    Disabled,
+    /// `replication=database`, for logical replication.
+    Logical,
+    /// `replication=true`, for physical replication, e.g. `pg_basebackup`.
+    Physical,
+}
+
+impl ReplicationMode {
+    /// Server connections are opened in replication mode.
+    pub fn enabled(&self) -> bool {
+        *self != Self::Disabled
+    }
+
+    /// Value of the `replication` startup parameter.
+    pub fn parameter(&self) -> Option<&'static str> {
+        match self {
+            Self::Disabled => None,
+            Self::Logical => Some("database"),
+            Self::Physical => Some("true"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum ReplicationModeValue {
+    Enabled(bool),
+    Mode(String),
+}
+
+impl TryFrom<ReplicationModeValue> for ReplicationMode {
+    type Error = String;
+
+    fn try_from(value: ReplicationModeValue) -> Result<Self, Self::Error> {
+        match value {
+            ReplicationModeValue::Enabled(false) => Ok(Self::Disabled),
+            ReplicationModeValue::Enabled(true) => Ok(Self::Logical),
+            ReplicationModeValue::Mode(mode) => match mode.as_str() {
+                "logical" => Ok(Self::Logical),
+                "physical" => Ok(Self::Physical),
+                _ => Err(format!(
+                    r#"invalid replication_mode "{}", expected true, false, "logical" or "physical""#,
+                    mode
+                )),
+            },
+        }
+    }
+}
+
+impl From<ReplicationMode> for ReplicationModeValue {
+    fn from(value: ReplicationMode) -> Self {
+        match value {
+            ReplicationMode::Disabled => Self::Enabled(false),
+            ReplicationMode::Logical => Self::Enabled(true),
+            ReplicationMode::Physical => Self::Mode("physical".into()),
+        }
+    }
+}
+
+/// Routing of locking `SELECT`s without a sharding key, e.g. job queue workers
+/// polling with `SELECT ... FOR UPDATE SKIP LOCKED`.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Default, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum QueuePatternRouting {
+    /// Send the query to all shards.
+    #[default]
+    Disabled,
+    /// Send the query to one shard, picked from the client's identity.
+    /// Each client always uses the same shard.
+    WorkerSticky,
+}
+
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default, Copy)]
 #[serde(rename_all = "snake_case")]
 pub enum DataType {
@@ -1055,6 +2412,17 @@ pub enum DataType {
     Varchar,
 }
 
+impl std::fmt::Display for DataType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Bigint => write!(f, "bigint"),
+            Self::Uuid => write!(f, "uuid"),
+            Self::Vector => write!(f, "vector"),
+            Self::Varchar => write!(f, "varchar"),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
 #[serde(rename_all = "snake_case", deny_unknown_fields)]
 pub struct ShardedMapping {
@@ -1069,6 +2437,24 @@ pub struct ShardedMapping {
     pub shard: usize,
 }
 
+impl ShardedMapping {
+    /// Database, column and table this mapping applies to.
+    pub fn key(&self) -> (String, String, Option<String>) {
+        (
+            self.database.clone(),
+            self.column.clone(),
+            self.table.clone(),
+        )
+    }
+}
+
+/// List mappings saved in `sharded_mappings_path`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ShardedMappings {
+    #[serde(default)]
+    pub sharded_mappings: Vec<ShardedMapping>,
+}
+
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
 #[serde(rename_all = "snake_case", deny_unknown_fields)]
 pub enum ShardedMappingKind {
@@ -1112,8 +2498,39 @@ pub struct OmnishardedTables {
 
 /// Queries with manual routing rules.
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[serde(deny_unknown_fields)]
 pub struct ManualQuery {
     pub fingerprint: String,
+    /// Send the query to this shard.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shard: Option<usize>,
+    /// Send the query to these shards.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shards: Option<Vec<usize>>,
+    /// Send the query to replicas or the primary.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub role: Option<ManualQueryRole>,
+}
+
+impl ManualQuery {
+    /// Shard numbers the query is sent to, if set.
+    pub fn shard_numbers(&self) -> Vec<usize> {
+        self.shard
+            .iter()
+            .chain(self.shards.iter().flatten())
+            .copied()
+            .collect()
+    }
+}
+
+/// Where manual queries are sent within a shard.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum ManualQueryRole {
+    /// Replicas.
+    Read,
+    /// Primary.
+    Write,
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
@@ -1303,18 +2720,39 @@ pub struct Replication {
     /// Path to the pg_dump executable.
     #[serde(default = "Replication::pg_dump_path")]
     pub pg_dump_path: PathBuf,
+    /// Pause reading from the replication slot when this many bytes
+    /// are waiting to be applied to the destination.
+    #[serde(default = "Replication::max_apply_backlog")]
+    pub max_apply_backlog: usize,
+    /// Warn when the source is holding this many bytes of WAL for the replication slot.
+    #[serde(default = "Replication::slot_lag_warning")]
+    pub slot_lag_warning: u64,
+    /// Stop replication when the source is holding this many bytes of WAL for the replication slot.
+    #[serde(default)]
+    pub slot_lag_abort: Option<u64>,
 }
 
 impl Replication {
     fn pg_dump_path() -> PathBuf {
         PathBuf::from("pg_dump")
     }
+
+    fn max_apply_backlog() -> usize {
+        64 * 1024 * 1024
+    }
+
+    fn slot_lag_warning() -> u64 {
+        1024 * 1024 * 1024
+    }
 }
 
 impl Default for Replication {
     fn default() -> Self {
         Self {
             pg_dump_path: Self::pg_dump_path(),
+            max_apply_backlog: Self::max_apply_backlog(),
+            slot_lag_warning: Self::slot_lag_warning(),
+            slot_lag_abort: None,
         }
     }
 }
@@ -1344,6 +2782,29 @@ pub mod test {
         init();
     }
 
+    pub fn load_test_sharded() {
+        let mut config = ConfigAndUsers::default();
+        config.config.databases = (0..2)
+            .map(|shard| Database {
+                name: "pgdog".into(),
+                host: "127.0.0.1".into(),
+                port: 5432,
+                database_name: Some("pgdog".into()),
+                shard,
+                ..Default::default()
+            })
+            .collect();
+        config.users.users = vec![User {
+            name: "pgdog".into(),
+            database: "pgdog".into(),
+            password: Some("pgdog".into()),
+            ..Default::default()
+        }];
+
+        set(config).unwrap();
+        init();
+    }
+
     pub fn load_test_replicas() {
         let mut config = ConfigAndUsers::default();
         config.config.databases = vec![
@@ -1401,6 +2862,10 @@ retries = 5
 [[plugins]]
 name = "pgdog_routing"
 
+[[plugins]]
+name = "pgdog_tenants"
+config = { endpoint = "https://tenants.example.com", refresh_interval = 60 }
+
 [multi_tenant]
 column = "tenant_id"
 "#;
@@ -1408,6 +2873,11 @@ column = "tenant_id"
         let config: Config = toml::from_str(source).unwrap();
         assert_eq!(config.databases[0].name, "production");
         assert_eq!(config.plugins[0].name, "pgdog_routing");
+        assert_eq!(config.plugins[0].config_json(), "null");
+        assert_eq!(
+            config.plugins[1].config_json(),
+            r#"{"endpoint":"https://tenants.example.com","refresh_interval":60}"#
+        );
         assert!(config.tcp.keepalive());
         assert_eq!(config.tcp.interval().unwrap(), Duration::from_millis(5000));
         assert_eq!(
@@ -1419,6 +2889,40 @@ column = "tenant_id"
         assert_eq!(config.multi_tenant.unwrap().column, "tenant_id");
     }
 
+    #[test]
+    fn test_startup_parameters() {
+        let source = r#"
+[[databases]]
+name = "production"
+host = "127.0.0.1"
+options = "-c search_path=app"
+
+[databases.startup_parameters]
+"pgaudit.log" = "all"
+"#;
+
+        let config: Config = toml::from_str(source).unwrap();
+        let database = &config.databases[0];
+        assert_eq!(database.options.as_deref(), Some("-c search_path=app"));
+        assert_eq!(
+            database
+                .startup_parameters
+                .get("pgaudit.log")
+                .map(|v| v.as_str()),
+            Some("all")
+        );
+        assert!(database.check_startup_parameters().is_ok());
+
+        let mut database = database.clone();
+        database
+            .startup_parameters
+            .insert("Replication".into(), "database".into());
+        assert!(matches!(
+            database.check_startup_parameters(),
+            Err(Error::ManagedStartupParameter(_, name)) if name == "Replication"
+        ));
+    }
+
     #[test]
     fn test_prepared_statements_disabled_in_session_mode() {
         let mut config = ConfigAndUsers::default();
@@ -1452,6 +2956,547 @@ column = "tenant_id"
         config.config.general.prepared_statements = PreparedStatements::Disabled;
         assert!(!config.prepared_statements(), "Prepared statements should remain disabled when explicitly set to Disabled in transaction mode");
     }
+
+    #[test]
+    fn test_check_topology() {
+        let database = |shard: usize, role: Role| Database {
+            name: "pgdog".into(),
+            host: "127.0.0.1".into(),
+            shard,
+            role,
+            ..Default::default()
+        };
+
+        let mut config = Config {
+            databases: vec![
+                database(0, Role::Primary),
+                database(0, Role::Replica),
+                database(0, Role::Replica),
+                database(1, Role::Primary),
+            ],
+            ..Default::default()
+        };
+        assert!(config.check_topology().is_ok());
+
+        config.databases.push(database(1, Role::Primary));
+        assert!(matches!(
+            config.check_topology(),
+            Err(Error::DuplicatePrimary(name, 1)) if name == "pgdog"
+        ));
+
+        config.databases = vec![database(0, Role::Primary), database(2, Role::Primary)];
+        assert!(matches!(
+            config.check_topology(),
+            Err(Error::MissingShard(name, 1)) if name == "pgdog"
+        ));
+
+        config.databases = vec![database(1, Role::Replica)];
+        assert!(matches!(
+            config.check_topology(),
+            Err(Error::MissingShard(_, 0))
+        ));
+    }
+
+    #[test]
+    fn test_human_durations() {
+        let config: Config = toml::from_str(
+            r#"
+[general]
+checkout_timeout = 2500
+connect_timeout = "10s"
+idle_timeout = "2m"
+dns_ttl = "250ms"
+
+[[databases]]
+name = "pgdog"
+host = "127.0.0.1"
+statement_timeout = "30s"
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.general.checkout_timeout(),
+            Duration::from_millis(2500)
+        );
+        assert_eq!(config.general.connect_timeout(), Duration::from_secs(10));
+        assert_eq!(config.general.idle_timeout(), Duration::from_secs(120));
+        assert_eq!(config.general.dns_ttl(), Some(Duration::from_millis(250)));
+        assert_eq!(config.general.healthcheck_timeout(), Duration::from_secs(5));
+        assert_eq!(
+            config.databases[0].statement_timeout,
+            Some(HumanDuration::from_secs(30))
+        );
+        assert!(config.check_timeouts().is_ok());
+
+        let err = toml::from_str::<Config>("[general]\nconnect_timeout = \"5 parsecs\"");
+        assert!(err.is_err());
+    }
+
+    #[test]
+    fn test_check_timeouts() {
+        let mut config = Config::default();
+        assert!(config.check_timeouts().is_ok());
+
+        config.general.checkout_timeout = HumanDuration::from_millis(5);
+        assert!(matches!(
+            config.check_timeouts(),
+            Err(Error::DurationTooShort(name, _, _)) if name == "checkout_timeout"
+        ));
+
+        config.general.checkout_timeout = HumanDuration::from_secs(1);
+        config.general.healthcheck_timeout = HumanDuration::from_millis(0);
+        assert!(matches!(
+            config.check_timeouts(),
+            Err(Error::DurationTooShort(name, _, _)) if name == "healthcheck_timeout"
+        ));
+
+        // 0 disables statement_timeout.
+        config.general.healthcheck_timeout = HumanDuration::from_secs(5);
+        config.databases = vec![Database {
+            statement_timeout: Some(HumanDuration::from_millis(0)),
+            ..Default::default()
+        }];
+        assert!(config.check_timeouts().is_ok());
+
+        // Configs that used to load still do, the check only warns.
+        let source = r#"
+[general]
+checkout_timeout = 5
+healthcheck_timeout = 0
+
+[[databases]]
+name = "pgdog"
+host = "127.0.0.1"
+statement_timeout = 0
+"#;
+        let mut config: Config = toml::from_str(source).unwrap();
+        assert!(config.check_timeouts().is_err());
+        config.check();
+
+        let users: Users = Users::parse(
+            r#"
+[[users]]
+name = "pgdog"
+database = "pgdog"
+statement_timeout = 0
+"#,
+            &config,
+        )
+        .unwrap();
+        assert_eq!(
+            users.users[0].statement_timeout,
+            Some(HumanDuration::from_millis(0))
+        );
+
+        // A cross-shard query can't finish in no time.
+        let users = Users::parse(
+            r#"
+[[users]]
+name = "pgdog"
+database = "pgdog"
+cross_shard_timeout = 0
+"#,
+            &config,
+        );
+        assert!(matches!(
+            users,
+            Err(Error::DurationTooShort(name, _, _)) if name == "cross_shard_timeout"
+        ));
+    }
+
+    #[test]
+    fn test_require_tls() {
+        let source = r#"
+[general]
+tls_certificate = "cert.pem"
+tls_private_key = "key.pem"
+
+[[databases]]
+name = "scratch"
+host = "127.0.0.1"
+
+[[databases]]
+name = "pii"
+host = "127.0.0.1"
+require_tls = true
+"#;
+        let mut config: Config = toml::from_str(source).unwrap();
+        assert!(config.check_tls().is_ok());
+
+        let users = Users {
+            users: vec![User {
+                name: "alice".into(),
+                database: "scratch".into(),
+                require_tls: true,
+                ..Default::default()
+            }],
+        };
+        assert!(users.check_tls(&config).is_ok());
+
+        let config_and_users = ConfigAndUsers {
+            config: config.clone(),
+            users: users.clone(),
+            ..Default::default()
+        };
+        assert!(config_and_users.require_tls("bob", "pii"));
+        assert!(config_and_users.require_tls("alice", "scratch"));
+        assert!(!config_and_users.require_tls("bob", "scratch"));
+
+        config.general.tls_certificate = None;
+        assert!(matches!(
+            config.check_tls(),
+            Err(Error::TlsNotConfigured(name)) if name == "pii"
+        ));
+        assert!(matches!(
+            users.check_tls(&config),
+            Err(Error::UserTlsNotConfigured(user, database)) if user == "alice" && database == "scratch"
+        ));
+    }
+
+    #[test]
+    fn test_manual_queries() {
+        let source = r#"
+[[databases]]
+name = "pgdog"
+host = "127.0.0.1"
+
+[[databases]]
+name = "pgdog"
+host = "127.0.0.1"
+shard = 1
+
+[[databases]]
+name = "pgdog"
+host = "127.0.0.1"
+shard = 2
+
+[[manual_queries]]
+fingerprint = "e78fe2c08de5f079"
+
+[[manual_queries]]
+fingerprint = "43258d068030bb3e"
+shard = 1
+role = "write"
+
+[[manual_queries]]
+fingerprint = "08aab2cee482a97d"
+shards = [0, 2]
+role = "read"
+"#;
+        let mut config: Config = toml::from_str(source).unwrap();
+        assert!(config.check_manual_queries().is_ok());
+        assert_eq!(config.manual_queries[1].shard_numbers(), vec![1]);
+        assert_eq!(config.manual_queries[2].shard_numbers(), vec![0, 2]);
+        assert_eq!(config.manual_queries[2].role, Some(ManualQueryRole::Read));
+
+        config.manual_queries[2].shards = Some(vec![0, 3]);
+        assert!(matches!(
+            config.check_manual_queries(),
+            Err(Error::ManualQueryShard(fingerprint, 3, 3)) if fingerprint == "08aab2cee482a97d"
+        ));
+
+        config.manual_queries[2].shards = None;
+        config.manual_queries[1].shards = Some(vec![0]);
+        assert!(matches!(
+            config.check_manual_queries(),
+            Err(Error::ManualQueryShardAndShards(fingerprint)) if fingerprint == "43258d068030bb3e"
+        ));
+    }
+
+    #[test]
+    fn test_check_sharded_tables() {
+        let source = r#"
+[[sharded_tables]]
+database = "pgdog"
+name = "accounts"
+columns = ["tenant_id", "region"]
+data_types = ["bigint", "varchar"]
+"#;
+        let mut config: Config = toml::from_str(source).unwrap();
+        assert!(config.check_sharded_tables().is_ok());
+
+        config.sharded_tables[0].data_types.pop();
+        assert!(matches!(
+            config.check_sharded_tables(),
+            Err(Error::ShardedTableDataTypes(table, database, 1, 2)) if table == "accounts" && database == "pgdog"
+        ));
+
+        config.sharded_tables[0].data_types.clear();
+        assert!(config.check_sharded_tables().is_ok());
+
+        let source = r#"
+[[databases]]
+name = "pgdog"
+host = "127.0.0.1"
+shard = 0
+
+[[databases]]
+name = "pgdog"
+host = "127.0.0.1"
+shard = 1
+
+[[sharded_tables]]
+database = "pgdog"
+name = "users"
+column = "id"
+null_shard = 1
+"#;
+        let mut config: Config = toml::from_str(source).unwrap();
+        assert!(config.check_sharded_tables().is_ok());
+
+        config.sharded_tables[0].null_shard = NullShard::Shard(2);
+        assert!(matches!(
+            config.check_sharded_tables(),
+            Err(Error::NullShard(table, _, 2, 2)) if table == "users"
+        ));
+    }
+
+    #[test]
+    fn test_read_consistency() {
+        let source = r#"
+[[users]]
+name = "analytics"
+database = "pgdog"
+
+[[users]]
+name = "dashboard"
+database = "pgdog"
+read_consistency = { bounded = "5s" }
+
+[[users]]
+name = "checkout"
+database = "pgdog"
+read_consistency = "strong"
+"#;
+        let users: Users = toml::from_str(source).unwrap();
+        let tiers = users
+            .users
+            .iter()
+            .map(|user| user.read_consistency)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tiers,
+            vec![
+                ReadConsistency::Any,
+                ReadConsistency::Bounded(HumanDuration::from_secs(5)),
+                ReadConsistency::Strong,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_session_setup() {
+        let source = r#"
+[[users]]
+name = "app"
+database = "pgdog"
+session_setup = ["SET search_path TO app, public", "SET jit TO off"]
+
+[[users]]
+name = "tenant"
+database = "pgdog"
+session_setup = ["SELEKT set_tenant()"]
+"#;
+        let users: Users = toml::from_str(source).unwrap();
+        assert_eq!(users.users[0].session_setup.len(), 2);
+        assert!(users.users[0].check_session_setup().is_ok());
+        assert!(matches!(
+            users.users[1].check_session_setup(),
+            Err(Error::SessionSetup(_, statement, _)) if statement == "SELEKT set_tenant()"
+        ));
+        assert!(Database::default().check_session_setup().is_ok());
+    }
+
+    #[test]
+    fn test_validate_sharding_schema() {
+        for (value, expected) in [
+            ("true", ValidateShardingSchema::Enabled),
+            ("false", ValidateShardingSchema::Disabled),
+            (r#""strict""#, ValidateShardingSchema::Strict),
+        ] {
+            let source = format!("[general]\nvalidate_sharding_schema = {}", value);
+            let config: Config = toml::from_str(&source).unwrap();
+            assert_eq!(config.general.validate_sharding_schema, expected);
+        }
+
+        let config: Config = toml::from_str("").unwrap();
+        assert!(!config.general.validate_sharding_schema.enabled());
+        assert!(
+            toml::from_str::<Config>("[general]\nvalidate_sharding_schema = \"loose\"").is_err()
+        );
+    }
+
+    #[test]
+    fn test_healthcheck_policies() {
+        let source = r#"
+[healthcheck.query_timeout]
+failures = 2
+
+[healthcheck.connection_reset]
+ban_timeout = "5s"
+"#;
+        let config: Config = toml::from_str(source).unwrap();
+        let healthcheck = config.healthcheck;
+        assert_eq!(healthcheck.query_timeout.failures, 2);
+        assert_eq!(healthcheck.query_timeout.ban_timeout, None);
+        assert_eq!(healthcheck.connection_reset.failures, 1);
+        assert_eq!(
+            healthcheck.connection_reset.ban_timeout,
+            Some(HumanDuration::from_secs(5))
+        );
+        assert_eq!(healthcheck.sql_error, HealthcheckPolicy::default());
+        assert_eq!(healthcheck.connect_timeout, HealthcheckPolicy::default());
+    }
+
+    #[test]
+    fn test_replication_mode() {
+        let source = r#"
+[[users]]
+name = "pgdog"
+database = "pgdog"
+
+[[users]]
+name = "logical"
+database = "pgdog"
+replication_mode = true
+
+[[users]]
+name = "physical"
+database = "pgdog"
+replication_mode = "physical"
+"#;
+        let users: Users = toml::from_str(source).unwrap();
+        let modes = users
+            .users
+            .iter()
+            .map(|user| user.replication_mode)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            modes,
+            vec![
+                ReplicationMode::Disabled,
+                ReplicationMode::Logical,
+                ReplicationMode::Physical
+            ]
+        );
+
+        let invalid = r#"
+[[users]]
+name = "pgdog"
+database = "pgdog"
+replication_mode = "standby"
+"#;
+        assert!(toml::from_str::<Users>(invalid).is_err());
+    }
+
+    #[test]
+    fn test_template() {
+        let source = r#"
+[[databases]]
+name = "pgdog"
+host = "127.0.0.1"
+
+[[databases]]
+name = "customers"
+name_pattern = "customer_*"
+host = "127.0.0.1"
+pool_size = 5
+require_tls = true
+
+[[sharded_tables]]
+database = "customers"
+column = "tenant_id"
+"#;
+        let users = r#"
+[[users]]
+name = "pgdog"
+database = "customers"
+password = "pgdog"
+
+[[users]]
+name = "apps"
+name_pattern = "app_*"
+database = "customers"
+password = "apps"
+
+[[users]]
+name = "pgdog"
+database = "pgdog"
+password = "pgdog"
+
+[[users]]
+name = "analysts"
+name_pattern = "analyst_*"
+database = "pgdog"
+password = "analysts"
+"#;
+        let config_and_users = ConfigAndUsers {
+            config: toml::from_str(source).unwrap(),
+            users: toml::from_str(users).unwrap(),
+            ..Default::default()
+        };
+
+        let template = config_and_users.template("pgdog", "customer_1").unwrap();
+        assert_eq!(template.database.as_deref(), Some("customers"));
+        assert_eq!(template.user_pattern, None);
+        let user = template.user.unwrap();
+        assert_eq!(
+            (user.name.as_str(), user.database.as_str()),
+            ("pgdog", "customer_1")
+        );
+        assert_eq!(user.password(), "pgdog");
+
+        // Same settings, connecting to the requested database.
+        let databases = template.config.databases();
+        let database = &databases["customer_1"][0][0];
+        assert_eq!(database.database_name.as_deref(), Some("customer_1"));
+        assert_eq!(database.pool_size, Some(5));
+        assert_eq!(database.name_pattern, None);
+        assert_eq!(template.config.sharded_tables()["customer_1"].len(), 1);
+
+        let template = config_and_users.template("app_1", "customer_2").unwrap();
+        assert_eq!(template.user_pattern.as_deref(), Some("app_*"));
+        assert_eq!(template.user.unwrap().name, "app_1");
+
+        // Passthrough auth can add users without an entry.
+        let template = config_and_users.template("bob", "customer_3").unwrap();
+        assert!(template.user.is_none());
+
+        // Users matching a pattern, for a database in pgdog.toml.
+        let template = config_and_users.template("analyst_1", "pgdog").unwrap();
+        assert_eq!(template.database, None);
+        assert_eq!(template.user_pattern.as_deref(), Some("analyst_*"));
+        assert!(config_and_users.template("pgdog", "pgdog").is_none());
+        assert!(config_and_users.template("bob", "pgdog").is_none());
+
+        // Templates aren't databases.
+        assert!(config_and_users.template("pgdog", "customers").is_none());
+        assert!(config_and_users.template("pgdog", "other").is_none());
+        assert!(config_and_users.config.is_template("customers"));
+
+        assert!(config_and_users.require_tls("app_1", "customer_1"));
+        assert!(!config_and_users.require_tls("analyst_1", "pgdog"));
+    }
+
+    #[test]
+    fn test_users_command() {
+        let users = Users::from_command(
+            r#"printf '[[users]]\nname = "pgdog"\ndatabase = "pgdog"\nserver_password = "rotated"\n'"#,
+        )
+        .unwrap();
+        let users = Users::parse(&users, &Config::default()).unwrap();
+        assert_eq!(users.users[0].server_password, Some("rotated".into()));
+
+        let err = Users::from_command("exit 3").unwrap_err();
+        assert!(matches!(err, Error::UsersCommand(_, _)));
+
+        // Parse errors don't include the passwords.
+        let err = Users::parse("[[users]]\npassword = \"hunter2\"\n", &Config::default())
+            .unwrap_err()
+            .to_string();
+        assert!(!err.contains("hunter2"), "{}", err);
+    }
 }
 
 //--------------------------------------------------------------------------------------------------
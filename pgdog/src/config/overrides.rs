@@ -1,6 +1,30 @@
+use super::{General, PoolerMode};
+
 #[derive(Debug, Clone, Default)]
 pub struct Overrides {
     pub default_pool_size: Option<usize>,
     pub min_pool_size: Option<usize>,
     pub session_mode: Option<bool>,
 }
+
+impl Overrides {
+    /// Apply command line overrides to general settings.
+    pub fn apply(&self, general: &mut General) {
+        if let Some(default_pool_size) = self.default_pool_size {
+            general.default_pool_size = default_pool_size;
+        }
+
+        if let Some(min_pool_size) = self.min_pool_size {
+            general.min_pool_size = min_pool_size;
+        }
+
+        if self.session_mode() {
+            general.pooler_mode = PoolerMode::Session;
+        }
+    }
+
+    /// Session mode was requested on the command line.
+    pub fn session_mode(&self) -> bool {
+        self.session_mode == Some(true)
+    }
+}
@@ -0,0 +1,171 @@
+//! Accepting client connections.
+//!
+//! Accept errors never stop the listener. Errors caused by a client going away
+//! before we accepted it are skipped. Anything else, e.g. running out of file
+//! descriptors (`EMFILE`/`ENFILE`), pauses accepting for [`ACCEPT_BACKOFF`] so we don't
+//! spin while the system recovers. Warnings are logged at most once per [`WARN_INTERVAL`].
+
+use std::io::{Error, ErrorKind};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use tokio::time::Instant;
+use tracing::warn;
+
+/// How long to stop accepting connections after an error.
+pub const ACCEPT_BACKOFF: Duration = Duration::from_millis(50);
+/// Log accept errors at most this often.
+pub const WARN_INTERVAL: Duration = Duration::from_secs(5);
+
+static STATS: Lazy<AcceptStats> = Lazy::new(AcceptStats::default);
+
+/// Accepted connections per second, measured over the last interval.
+#[derive(Debug, Default)]
+struct Rate {
+    since: Option<Instant>,
+    accepted: u64,
+    per_sec: f64,
+}
+
+/// Listener counters.
+#[derive(Debug, Default)]
+pub struct AcceptStats {
+    accepted: AtomicU64,
+    errors: AtomicU64,
+    /// Errors since the last warning.
+    unlogged: AtomicU64,
+    last_warning: Mutex<Option<Instant>>,
+    rate: Mutex<Rate>,
+}
+
+/// Snapshot of listener counters.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Accepts {
+    pub accepted: u64,
+    pub errors: u64,
+    pub accepted_per_sec: f64,
+}
+
+impl AcceptStats {
+    /// Get global counters.
+    pub fn get() -> &'static AcceptStats {
+        &STATS
+    }
+
+    /// Count an accepted connection.
+    pub fn accepted(&self) {
+        self.accepted.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Count an accept error. Returns how long to wait before accepting again, if at all.
+    pub fn error(&self, err: &Error, now: Instant) -> Option<Duration> {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+        let unlogged = self.unlogged.fetch_add(1, Ordering::Relaxed) + 1;
+
+        let backoff = Self::backoff(err);
+
+        let log = {
+            let mut last_warning = self.last_warning.lock();
+            match *last_warning {
+                Some(last) if now.duration_since(last) < WARN_INTERVAL => false,
+                _ => {
+                    *last_warning = Some(now);
+                    true
+                }
+            }
+        };
+
+        if log {
+            self.unlogged.fetch_sub(unlogged, Ordering::Relaxed);
+            warn!(
+                "error accepting client connection: {} ({} since the last warning){}",
+                err,
+                unlogged,
+                backoff
+                    .map(|backoff| format!(", pausing for {}ms", backoff.as_millis()))
+                    .unwrap_or_default(),
+            );
+        }
+
+        backoff
+    }
+
+    /// How long to pause accepting after this error. Errors caused by
+    /// the client going away don't affect the listener.
+    fn backoff(err: &Error) -> Option<Duration> {
+        match err.kind() {
+            ErrorKind::ConnectionAborted
+            | ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionRefused
+            | ErrorKind::Interrupted => None,
+            _ => Some(ACCEPT_BACKOFF),
+        }
+    }
+
+    /// Update the accept rate. Called periodically by the listener.
+    pub fn calc_rate(&self, now: Instant) {
+        let accepted = self.accepted.load(Ordering::Relaxed);
+        let mut rate = self.rate.lock();
+
+        if let Some(since) = rate.since {
+            let elapsed = now.duration_since(since).as_secs_f64();
+            if elapsed > 0.0 {
+                rate.per_sec = accepted.saturating_sub(rate.accepted) as f64 / elapsed;
+            }
+        }
+
+        rate.since = Some(now);
+        rate.accepted = accepted;
+    }
+
+    /// Get counters.
+    pub fn stats(&self) -> Accepts {
+        Accepts {
+            accepted: self.accepted.load(Ordering::Relaxed),
+            errors: self.errors.load(Ordering::Relaxed),
+            accepted_per_sec: self.rate.lock().per_sec,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_accept_errors() {
+        let stats = AcceptStats::default();
+        let now = Instant::now();
+
+        let emfile = Error::from_raw_os_error(libc::EMFILE);
+        assert_eq!(stats.error(&emfile, now), Some(ACCEPT_BACKOFF));
+        let enfile = Error::from_raw_os_error(libc::ENFILE);
+        assert_eq!(stats.error(&enfile, now), Some(ACCEPT_BACKOFF));
+        let aborted = Error::from(ErrorKind::ConnectionAborted);
+        assert_eq!(stats.error(&aborted, now), None);
+        assert_eq!(stats.stats().errors, 3);
+
+        // Only the first error in the window was logged.
+        assert_eq!(stats.unlogged.load(Ordering::Relaxed), 2);
+        stats.error(&emfile, now + WARN_INTERVAL);
+        assert_eq!(stats.unlogged.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn test_accept_rate() {
+        let stats = AcceptStats::default();
+        let now = Instant::now();
+
+        stats.calc_rate(now);
+        for _ in 0..30 {
+            stats.accepted();
+        }
+        stats.calc_rate(now + Duration::from_secs(2));
+
+        let accepts = stats.stats();
+        assert_eq!(accepts.accepted, 30);
+        assert_eq!(accepts.accepted_per_sec, 15.0);
+    }
+}
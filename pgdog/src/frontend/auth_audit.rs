@@ -0,0 +1,344 @@
+//! Authentication audit log.
+//!
+//! Every connection attempt is recorded with who connected, from where, how they
+//! authenticated and how it went. Events are JSON lines appended to `auth_audit_log`,
+//! or logged with the `auth_audit` target if it's not set.
+//!
+//! Failures with the same reason from the same address are written once per
+//! [`SUPPRESS_WINDOW`], so an attack doesn't flood the log. The next event written
+//! for that address and reason says how many were skipped.
+//!
+//! Passwords and SCRAM messages are never recorded.
+
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use chrono::{SecondsFormat, Utc};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::Serialize;
+use tokio::{fs::OpenOptions, io::AsyncWriteExt};
+use tracing::{error, info, warn};
+
+use crate::backend::databases::databases;
+use crate::config::{config, AuthType};
+
+/// Window for suppressing repeated failures from the same address.
+pub const SUPPRESS_WINDOW: Duration = Duration::from_secs(60);
+/// Maximum number of addresses we track failures for.
+pub const MAX_SOURCES: usize = 10_000;
+
+static AUDIT: Lazy<AuthAudit> = Lazy::new(AuthAudit::default);
+
+/// How the client proved who they are.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthMethod {
+    Scram,
+    Md5,
+    Trust,
+}
+
+impl AuthMethod {
+    /// Method used for the auth type, mirroring the handshake in [`super::Client`].
+    pub fn new(auth_type: &AuthType, tls: bool) -> Self {
+        match (auth_type, tls) {
+            // SCRAM falls back to MD5 over TLS.
+            (AuthType::Scram, true) | (AuthType::Md5, _) => Self::Md5,
+            (AuthType::Scram, false) => Self::Scram,
+            (AuthType::Trust, _) => Self::Trust,
+        }
+    }
+}
+
+/// Connection attempt result.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthOutcome {
+    Success,
+    Failure,
+}
+
+/// Why the connection attempt failed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthFailure {
+    /// Client didn't use TLS, but the database requires it.
+    TlsRequired,
+    /// No such database.
+    UnknownDatabase,
+    /// Database exists, but the user isn't allowed to connect to it.
+    UnknownUser,
+    /// Password, MD5 hash or SCRAM proof didn't match.
+    BadPassword,
+    /// PgDog is shutting down.
+    ShuttingDown,
+    /// Couldn't get a connection to the database.
+    PoolDown,
+}
+
+impl AuthFailure {
+    /// Client asked for a user/database pair we don't have.
+    pub fn unknown(database: &str) -> Self {
+        let exists = databases()
+            .all()
+            .keys()
+            .any(|user| user.database == database);
+
+        if exists {
+            Self::UnknownUser
+        } else {
+            Self::UnknownDatabase
+        }
+    }
+}
+
+/// Audit log event.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AuthEvent {
+    /// When it happened, in UTC.
+    pub timestamp: String,
+    /// Client address.
+    pub addr: SocketAddr,
+    pub user: String,
+    pub database: String,
+    pub method: AuthMethod,
+    pub tls: bool,
+    pub outcome: AuthOutcome,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<AuthFailure>,
+    /// Same failures from this address not written since the last one.
+    pub suppressed: u64,
+}
+
+/// Connection attempt being audited.
+#[derive(Debug, Clone)]
+pub struct AuthAttempt {
+    addr: SocketAddr,
+    user: String,
+    database: String,
+    method: AuthMethod,
+    tls: bool,
+}
+
+impl AuthAttempt {
+    /// Start auditing a connection attempt.
+    pub fn new(
+        addr: SocketAddr,
+        user: &str,
+        database: &str,
+        method: AuthMethod,
+        tls: bool,
+    ) -> Self {
+        Self {
+            addr,
+            user: user.to_owned(),
+            database: database.to_owned(),
+            method,
+            tls,
+        }
+    }
+
+    /// Client connected.
+    pub async fn success(&self) {
+        AUDIT.record(self.event(None)).await;
+    }
+
+    /// Client was refused.
+    pub async fn failure(&self, reason: AuthFailure) {
+        AUDIT.record(self.event(Some(reason))).await;
+    }
+
+    fn event(&self, reason: Option<AuthFailure>) -> AuthEvent {
+        AuthEvent {
+            timestamp: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            addr: self.addr,
+            user: self.user.clone(),
+            database: self.database.clone(),
+            method: self.method,
+            tls: self.tls,
+            outcome: if reason.is_some() {
+                AuthOutcome::Failure
+            } else {
+                AuthOutcome::Success
+            },
+            reason,
+            suppressed: 0,
+        }
+    }
+}
+
+/// Failures from one address.
+#[derive(Debug)]
+struct Source {
+    /// Last time we wrote a failure.
+    written: Instant,
+    /// Failures skipped since then.
+    suppressed: u64,
+}
+
+/// Authentication audit log.
+#[derive(Debug, Default)]
+pub struct AuthAudit {
+    sources: Mutex<HashMap<(IpAddr, AuthFailure), Source>>,
+    suppressed: AtomicU64,
+}
+
+impl AuthAudit {
+    /// Get the audit log.
+    pub fn get() -> &'static AuthAudit {
+        &AUDIT
+    }
+
+    /// Total number of events that weren't written.
+    pub fn suppressed(&self) -> u64 {
+        self.suppressed.load(Ordering::Relaxed)
+    }
+
+    /// Write the event, unless it's a repeated failure.
+    pub async fn record(&self, mut event: AuthEvent) {
+        if !self.admit(&mut event, Instant::now()) {
+            return;
+        }
+
+        let line = match serde_json::to_string(&event) {
+            Ok(line) => line,
+            Err(err) => {
+                error!("couldn't serialize auth audit event: {}", err);
+                return;
+            }
+        };
+
+        if let Some(path) = &config().config.general.auth_audit_log {
+            if let Err(err) = Self::append(path, &line).await {
+                error!(
+                    "couldn't write auth audit log to \"{}\": {}",
+                    path.display(),
+                    err
+                );
+            }
+        } else if event.outcome == AuthOutcome::Success {
+            info!(target: "auth_audit", "{}", line);
+        } else {
+            warn!(target: "auth_audit", "{}", line);
+        }
+    }
+
+    /// Check if the event should be written. Adds the number of
+    /// skipped failures to the event if it's written.
+    fn admit(&self, event: &mut AuthEvent, now: Instant) -> bool {
+        let Some(reason) = event.reason else {
+            return true;
+        };
+
+        let mut sources = self.sources.lock();
+        let key = (event.addr.ip(), reason);
+
+        if let Some(source) = sources.get_mut(&key) {
+            if now.duration_since(source.written) < SUPPRESS_WINDOW {
+                source.suppressed += 1;
+                self.suppressed.fetch_add(1, Ordering::Relaxed);
+                return false;
+            }
+
+            event.suppressed = source.suppressed;
+            source.written = now;
+            source.suppressed = 0;
+            return true;
+        }
+
+        if sources.len() >= MAX_SOURCES {
+            sources.retain(|_, source| now.duration_since(source.written) < SUPPRESS_WINDOW);
+            // Too many addresses failing at once, start over.
+            if sources.len() >= MAX_SOURCES {
+                sources.clear();
+            }
+        }
+
+        sources.insert(
+            key,
+            Source {
+                written: now,
+                suppressed: 0,
+            },
+        );
+
+        true
+    }
+
+    async fn append(path: &Path, line: &str) -> std::io::Result<()> {
+        let mut file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(path)
+            .await?;
+        file.write_all(format!("{}\n", line).as_bytes()).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn event(addr: &str, reason: Option<AuthFailure>) -> AuthEvent {
+        let attempt = AuthAttempt::new(
+            addr.parse().unwrap(),
+            "pgdog",
+            "pgdog",
+            AuthMethod::Scram,
+            false,
+        );
+        attempt.event(reason)
+    }
+
+    #[test]
+    fn test_suppress_repeated_failures() {
+        let audit = AuthAudit::default();
+        let now = Instant::now();
+        let failure = Some(AuthFailure::BadPassword);
+
+        assert!(audit.admit(&mut event("127.0.0.1:1234", failure), now));
+
+        // Same address, any port.
+        for port in 0..3 {
+            let addr = format!("127.0.0.1:{}", 2000 + port);
+            assert!(!audit.admit(&mut event(&addr, failure), now));
+        }
+        assert_eq!(audit.suppressed(), 3);
+
+        // Other reasons, addresses and successes are written.
+        assert!(audit.admit(
+            &mut event("127.0.0.1:1234", Some(AuthFailure::UnknownUser)),
+            now
+        ));
+        assert!(audit.admit(&mut event("127.0.0.2:1234", failure), now));
+        assert!(audit.admit(&mut event("127.0.0.1:1234", None), now));
+
+        // Next window reports what was skipped.
+        let mut next = event("127.0.0.1:1234", failure);
+        assert!(audit.admit(&mut next, now + SUPPRESS_WINDOW));
+        assert_eq!(next.suppressed, 3);
+        let mut next = event("127.0.0.1:1234", failure);
+        assert!(audit.admit(&mut next, now + SUPPRESS_WINDOW * 2));
+        assert_eq!(next.suppressed, 0);
+    }
+
+    #[test]
+    fn test_event_json() {
+        let json =
+            serde_json::to_value(event("10.0.0.1:5432", Some(AuthFailure::TlsRequired))).unwrap();
+        assert_eq!(json["addr"], "10.0.0.1:5432");
+        assert_eq!(json["method"], "scram");
+        assert_eq!(json["outcome"], "failure");
+        assert_eq!(json["reason"], "tls_required");
+        assert_eq!(json["tls"], false);
+        assert!(json["timestamp"].as_str().unwrap().ends_with('Z'));
+
+        let json = serde_json::to_value(event("10.0.0.1:5432", None)).unwrap();
+        assert_eq!(json["outcome"], "success");
+        assert!(json.get("reason").is_none());
+    }
+}
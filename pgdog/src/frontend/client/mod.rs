@@ -1,7 +1,7 @@
 //! Frontend client.
 
 use std::net::SocketAddr;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use bytes::BytesMut;
 use timeouts::Timeouts;
@@ -9,14 +9,17 @@ use tokio::time::timeout;
 use tokio::{select, spawn};
 use tracing::{debug, enabled, error, info, trace, Level as LogLevel};
 
-use super::{ClientRequest, Comms, Error, PreparedStatements};
+use super::{ClientRequest, Comms, ConnectedClient, Error, PreparedStatements};
 use crate::auth::{md5, scram::Server};
 use crate::backend::{
     databases,
-    pool::{Connection, Request},
+    pool::{Cluster, Connection, Request},
 };
-use crate::config::{self, AuthType};
+use crate::config::{self, AuthType, ReadYourWrites};
+use crate::frontend::auth_audit::{AuthAttempt, AuthFailure, AuthMethod};
 use crate::frontend::client::query_engine::{QueryEngine, QueryEngineContext};
+use crate::frontend::router::parser::Shard;
+use crate::frontend::session_pins::{SessionKey, SessionPins};
 use crate::net::messages::{
     Authentication, BackendKeyData, ErrorResponse, FromBytes, Message, Password, Protocol,
     ReadyForQuery, ToBytes,
@@ -43,12 +46,21 @@ pub struct Client {
     streaming: bool,
     shutdown: bool,
     prepared_statements: PreparedStatements,
-    transaction: Option<TransactionType>,
+    transaction: TransactionStatus,
     timeouts: Timeouts,
     client_request: ClientRequest,
     stream_buffer: BytesMut,
     cross_shard_disabled: bool,
+    client_write_buffer: usize,
+    flush_threshold: usize,
+    elide_transactions: bool,
+    read_your_writes: ReadYourWrites,
+    read_your_writes_window: Duration,
     passthrough_password: Option<String>,
+    /// Last time the client sent us a message.
+    last_message: Instant,
+    /// Shard used by this session, in session mode.
+    session_shard: Option<usize>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -57,20 +69,97 @@ pub enum TransactionType {
     ReadWrite,
 }
 
+/// Client transaction status, as reported in ReadyForQuery.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TransactionStatus {
+    /// Not in a transaction ('I').
+    #[default]
+    Idle,
+    /// In a transaction ('T').
+    InTransaction(TransactionType),
+    /// In a failed transaction ('E'). Only ROLLBACK or COMMIT end it,
+    /// everything else is refused by the server.
+    Failed(TransactionType),
+}
+
+impl TransactionStatus {
+    /// Status reported by the server in ReadyForQuery.
+    pub fn from_status(status: char, transaction_type: Option<TransactionType>) -> Self {
+        // The server doesn't say if the transaction is read-only.
+        let transaction_type = transaction_type.unwrap_or(TransactionType::ReadWrite);
+
+        match status {
+            'T' => Self::InTransaction(transaction_type),
+            'E' => Self::Failed(transaction_type),
+            _ => Self::Idle,
+        }
+    }
+
+    /// Transaction type, if in a transaction.
+    pub fn transaction_type(&self) -> Option<TransactionType> {
+        match self {
+            Self::Idle => None,
+            Self::InTransaction(transaction_type) | Self::Failed(transaction_type) => {
+                Some(*transaction_type)
+            }
+        }
+    }
+
+    /// In a transaction, failed or not.
+    pub fn in_transaction(&self) -> bool {
+        !matches!(self, Self::Idle)
+    }
+
+    /// In a failed transaction.
+    pub fn failed(&self) -> bool {
+        matches!(self, Self::Failed(_))
+    }
+
+    /// The transaction got an error, if we're in one.
+    pub fn fail(self) -> Self {
+        match self {
+            Self::InTransaction(transaction_type) => Self::Failed(transaction_type),
+            status => status,
+        }
+    }
+
+    /// ReadyForQuery reporting this status.
+    pub fn ready_for_query(&self) -> ReadyForQuery {
+        match self {
+            Self::Idle => ReadyForQuery::idle(),
+            Self::InTransaction(_) => ReadyForQuery::in_transaction(true),
+            Self::Failed(_) => ReadyForQuery::error(),
+        }
+    }
+}
+
+impl From<Option<TransactionType>> for TransactionStatus {
+    fn from(transaction_type: Option<TransactionType>) -> Self {
+        match transaction_type {
+            Some(transaction_type) => Self::InTransaction(transaction_type),
+            None => Self::Idle,
+        }
+    }
+}
+
 impl MemoryUsage for Client {
+    /// Includes the client's entry in [`Comms`], which keeps a copy of its
+    /// connection parameters. Statements in the global prepared statements cache,
+    /// routing history and server connections are counted elsewhere.
     #[inline]
     fn memory_usage(&self) -> usize {
-        std::mem::size_of::<SocketAddr>()
-            + std::mem::size_of::<Stream>()
-            + std::mem::size_of::<BackendKeyData>()
-            + self.connect_params.memory_usage()
-            + self.params.memory_usage()
-            + std::mem::size_of::<Comms>()
-            + std::mem::size_of::<bool>() * 5
-            + self.prepared_statements.memory_used()
-            + std::mem::size_of::<Timeouts>()
-            + self.stream_buffer.memory_usage()
-            + self.client_request.memory_usage()
+        std::mem::size_of::<Self>()
+            + self.stream.heap_usage()
+            + self.connect_params.heap_usage()
+            + self.params.heap_usage()
+            + std::mem::size_of::<ConnectedClient>()
+            + self.connect_params.heap_usage()
+            + self
+                .prepared_statements
+                .memory_used()
+                .saturating_sub(std::mem::size_of::<PreparedStatements>())
+            + self.stream_buffer.heap_usage()
+            + self.client_request.heap_usage()
             + self
                 .passthrough_password
                 .as_ref()
@@ -96,39 +185,75 @@ impl Client {
         let auth_type = &config.config.general.auth_type;
 
         let id = BackendKeyData::new();
+        let audit = AuthAttempt::new(
+            addr,
+            user,
+            database,
+            AuthMethod::new(auth_type, stream.is_tls()),
+            stream.is_tls(),
+        );
+
+        // Check before asking for a password.
+        if !admin && !stream.is_tls() && config.require_tls(user, database) {
+            audit.failure(AuthFailure::TlsRequired).await;
+            stream
+                .fatal(ErrorResponse::tls_required(user, database))
+                .await?;
+            return Ok(());
+        }
+
+        // Databases and users matching a template. The client authenticates with
+        // the password from the template's users.toml entry before pools are created.
+        let template_password = if admin {
+            None
+        } else {
+            databases::template_password(user, database)
+        };
+        let auth_type = &config.config.general.auth_type;
+        if let Some(ref password) = template_password {
+            if !Self::authenticate(&mut stream, user, password, auth_type).await? {
+                audit.failure(AuthFailure::BadPassword).await;
+                stream.fatal(ErrorResponse::auth(user, database)).await?;
+                return Ok(());
+            }
+            databases::instantiate(user, database);
+        }
 
-        // Auto database.
+        // Auto database. Clients that authenticated with a template's password
+        // already use pools created for them.
         let exists = databases::databases().exists((user, database));
-        let passthrough_password = if config.config.general.passthrough_auth() && !admin {
-            let password = if auth_type.trust() {
-                // Use empty password.
-                // TODO: Postgres must be using "trust" auth
-                // or some other kind of authentication that doesn't require a password.
-                Password::new_password("")
-            } else {
-                // Get the password.
-                stream
-                    .send_flush(&Authentication::ClearTextPassword)
-                    .await?;
-                let password = stream.read().await?;
-                Password::from_bytes(password.to_bytes()?)?
-            };
+        let passthrough_password =
+            if config.config.general.passthrough_auth() && !admin && template_password.is_none() {
+                let password = if auth_type.trust() {
+                    // Use empty password.
+                    // TODO: Postgres must be using "trust" auth
+                    // or some other kind of authentication that doesn't require a password.
+                    Password::new_password("")
+                } else {
+                    // Get the password.
+                    stream
+                        .send_flush(&Authentication::ClearTextPassword)
+                        .await?;
+                    let password = stream.read().await?;
+                    Password::from_bytes(password.to_bytes()?)?
+                };
 
-            if !exists {
-                let user = config::User::from_params(&params, &password).ok();
-                if let Some(user) = user {
-                    databases::add(user);
+                if !exists {
+                    let user = config::User::from_params(&params, &password).ok();
+                    if let Some(user) = user {
+                        databases::add(user);
+                    }
                 }
-            }
-            password.password().map(|p| p.to_owned())
-        } else {
-            None
-        };
+                password.password().map(|p| p.to_owned())
+            } else {
+                None
+            };
 
         // Get server parameters and send them to the client.
         let mut conn = match Connection::new(user, database, admin, &passthrough_password) {
             Ok(conn) => conn,
             Err(_) => {
+                audit.failure(AuthFailure::unknown(database)).await;
                 stream.fatal(ErrorResponse::auth(user, database)).await?;
                 return Ok(());
             }
@@ -137,37 +262,26 @@ impl Client {
         let password = if admin {
             admin_password
         } else {
-            conn.cluster()?.password()
-        };
-
-        let auth_type = &config.config.general.auth_type;
-        let auth_ok = match (auth_type, stream.is_tls()) {
-            // TODO: SCRAM doesn't work with TLS currently because of
-            // lack of support for channel binding in our scram library.
-            // Defaulting to MD5.
-            (AuthType::Scram, true) | (AuthType::Md5, _) => {
-                let md5 = md5::Client::new(user, password);
-                stream.send_flush(&md5.challenge()).await?;
-                let password = Password::from_bytes(stream.read().await?.to_bytes()?)?;
-                if let Password::PasswordMessage { response } = password {
-                    md5.check(&response)
-                } else {
-                    false
+            // Users missing from users.toml use the default user's wildcard password.
+            match conn.cluster()?.client_password(user) {
+                Some(password) => password,
+                None => {
+                    audit.failure(AuthFailure::UnknownUser).await;
+                    stream.fatal(ErrorResponse::auth(user, database)).await?;
+                    return Ok(());
                 }
             }
-
-            (AuthType::Scram, false) => {
-                stream.send_flush(&Authentication::scram()).await?;
-
-                let scram = Server::new(password);
-                let res = scram.handle(&mut stream).await;
-                matches!(res, Ok(true))
-            }
-
-            (AuthType::Trust, _) => true,
         };
 
+        let auth_ok = template_password.is_some()
+            || Self::authenticate(&mut stream, user, password, auth_type).await?;
+
         if !auth_ok {
+            // Don't let clients retrying with the wrong password create or replace pools.
+            if passthrough_password.is_some() {
+                databases::passthrough_failed(user, database);
+            }
+            audit.failure(AuthFailure::BadPassword).await;
             stream.fatal(ErrorResponse::auth(user, database)).await?;
             return Ok(());
         } else {
@@ -176,6 +290,7 @@ impl Client {
 
         // Check if the pooler is shutting down.
         if comms.offline() && !admin {
+            audit.failure(AuthFailure::ShuttingDown).await;
             stream.fatal(ErrorResponse::shutting_down()).await?;
             return Ok(());
         }
@@ -185,6 +300,7 @@ impl Client {
             Err(err) => {
                 if err.no_server() {
                     error!("connection pool is down");
+                    audit.failure(AuthFailure::PoolDown).await;
                     stream.fatal(ErrorResponse::connection()).await?;
                     return Ok(());
                 } else {
@@ -193,6 +309,13 @@ impl Client {
             }
         };
 
+        audit.success().await;
+
+        let mut session_params = params.clone();
+        if let Ok(cluster) = conn.cluster() {
+            Self::session_setup(&mut session_params, cluster);
+        }
+
         for param in server_params {
             stream.send(&param).await?;
         }
@@ -210,16 +333,23 @@ impl Client {
             comms,
             admin,
             streaming: false,
-            params: params.clone(),
+            params: session_params,
             connect_params: params,
-            prepared_statements: PreparedStatements::new(),
-            transaction: None,
+            prepared_statements: PreparedStatements::new(conn.cluster().ok()),
+            transaction: TransactionStatus::Idle,
             timeouts: Timeouts::from_config(&config.config.general),
             client_request: ClientRequest::new(),
             stream_buffer: BytesMut::new(),
             shutdown: false,
             cross_shard_disabled: false,
+            client_write_buffer: config.config.general.client_write_buffer,
+            flush_threshold: config.config.general.flush_threshold,
+            elide_transactions: config.config.general.elide_single_statement_transactions,
+            read_your_writes: config.config.general.read_your_writes,
+            read_your_writes_window: config.config.general.read_your_writes_window.into(),
             passthrough_password,
+            last_message: Instant::now(),
+            session_shard: None,
         };
 
         drop(conn);
@@ -236,34 +366,106 @@ impl Client {
         Ok(())
     }
 
+    /// Ask the client for its password and check it.
+    async fn authenticate(
+        stream: &mut Stream,
+        user: &str,
+        password: &str,
+        auth_type: &AuthType,
+    ) -> Result<bool, Error> {
+        Ok(match (auth_type, stream.is_tls()) {
+            // TODO: SCRAM doesn't work with TLS currently because of
+            // lack of support for channel binding in our scram library.
+            // Defaulting to MD5.
+            (AuthType::Scram, true) | (AuthType::Md5, _) => {
+                let md5 = md5::Client::new(user, password);
+                stream.send_flush(&md5.challenge()).await?;
+                let password = Password::from_bytes(stream.read().await?.to_bytes()?)?;
+                if let Password::PasswordMessage { response } = password {
+                    md5.check(&response)
+                } else {
+                    false
+                }
+            }
+
+            (AuthType::Scram, false) => {
+                stream.send_flush(&Authentication::scram()).await?;
+
+                let scram = Server::new(password);
+                let res = scram.handle(stream).await;
+                matches!(res, Ok(true))
+            }
+
+            (AuthType::Trust, _) => true,
+        })
+    }
+
     #[cfg(test)]
     pub fn new_test(stream: Stream, addr: SocketAddr) -> Self {
-        use crate::{config::config, frontend::comms::comms};
-
         let mut connect_params = Parameters::default();
         connect_params.insert("user", "pgdog");
         connect_params.insert("database", "pgdog");
 
+        Self::new_local(stream, addr, connect_params)
+    }
+
+    /// Create a client that skips the startup handshake and authentication.
+    ///
+    /// The client is trusted to be `user`, so this must never be reachable from
+    /// a network listener. Only tests and `pgdog bench`, over its own loopback
+    /// connections, use it.
+    pub(crate) fn new_local(stream: Stream, addr: SocketAddr, connect_params: Parameters) -> Self {
+        use crate::{config::config, frontend::comms::comms};
+
+        let mut params = connect_params.clone();
+        let user = connect_params.get_default("user", "postgres");
+        let database = connect_params.get_default("database", user);
+        if let Ok(cluster) = databases::databases().cluster((user, database)) {
+            Self::session_setup(&mut params, &cluster);
+        }
+
         Self {
             stream,
             addr,
             id: BackendKeyData::new(),
             comms: comms(),
             streaming: false,
-            prepared_statements: PreparedStatements::new(),
-            connect_params: connect_params.clone(),
-            params: connect_params,
+            prepared_statements: PreparedStatements::new(None),
+            connect_params,
+            params,
             admin: false,
-            transaction: None,
+            transaction: TransactionStatus::Idle,
             timeouts: Timeouts::from_config(&config().config.general),
             client_request: ClientRequest::new(),
             stream_buffer: BytesMut::new(),
             shutdown: false,
             cross_shard_disabled: false,
+            client_write_buffer: config().config.general.client_write_buffer,
+            flush_threshold: config().config.general.flush_threshold,
+            elide_transactions: config().config.general.elide_single_statement_transactions,
+            read_your_writes: config().config.general.read_your_writes,
+            read_your_writes_window: config().config.general.read_your_writes_window.into(),
             passthrough_password: None,
+            last_message: Instant::now(),
+            session_shard: None,
         }
     }
 
+    /// Parameters set by the session setup, as if the client set them after connecting.
+    /// Other setup statements run when the client is paired with a server.
+    fn session_setup(params: &mut Parameters, cluster: &Cluster) {
+        for (name, value) in cluster.session_setup().params().iter() {
+            params.insert(name, value.clone());
+        }
+    }
+
+    /// Serve a client created with [`Client::new_local`] until it disconnects.
+    pub(crate) async fn serve_local(mut self) {
+        self.comms
+            .connect(&self.id, self.addr, &self.connect_params);
+        self.spawn_internal().await;
+    }
+
     /// Get client's identifier.
     pub fn id(&self) -> BackendKeyData {
         self.id
@@ -271,14 +473,24 @@ impl Client {
 
     /// Run the client and log disconnect.
     async fn spawn_internal(&mut self) {
-        match self.run().await {
-            Ok(_) => info!("client disconnected [{}]", self.addr),
+        let result = self.run().await;
+        let totals = self
+            .comms
+            .client_stats()
+            .map(|stats| stats.totals)
+            .unwrap_or_default();
+
+        match result {
+            Ok(_) => info!("client disconnected [{}] [{}]", self.addr, totals),
             Err(err) => {
                 let _ = self
                     .stream
                     .error(ErrorResponse::from_err(&err), false)
                     .await;
-                error!("client disconnected with error [{}]: {}", self.addr, err)
+                error!(
+                    "client disconnected with error [{}] [{}]: {}",
+                    self.addr, totals, err
+                )
             }
         }
     }
@@ -287,6 +499,7 @@ impl Client {
     async fn run(&mut self) -> Result<(), Error> {
         let shutdown = self.comms.shutting_down();
         let mut offline;
+        let mut replication_timeout = false;
         let mut query_engine = QueryEngine::from_client(self)?;
 
         loop {
@@ -302,12 +515,23 @@ impl Client {
                     if query_engine.done() {
                         continue; // Wake up task.
                     }
+
+                    // Replication streams don't end on their own.
+                    query_engine.stop_streaming().await?;
                 }
 
                 // Async messages.
                 message = query_engine.read_backend() => {
                     let message = message?;
-                    self.server_message(&mut query_engine, message).await?;
+                    // Don't wait forever on a replication client that stopped reading.
+                    let deadline = self.replication_deadline();
+                    match timeout(deadline, self.server_message(&mut query_engine, message)).await {
+                        Ok(result) => result?,
+                        Err(_) => {
+                            replication_timeout = true;
+                            break;
+                        }
+                    }
                 }
 
                 buffer = self.buffer(client_state) => {
@@ -318,6 +542,10 @@ impl Client {
 
                     match event {
                         BufferEvent::DisconnectAbrupt => break,
+                        BufferEvent::ReplicationTimeout => {
+                            replication_timeout = true;
+                            break;
+                        }
                         BufferEvent::DisconnectGraceful => {
                             let done = query_engine.done();
 
@@ -332,6 +560,32 @@ impl Client {
             }
         }
 
+        query_engine.publish_stats();
+
+        // Client is gone, but the server is still streaming.
+        if query_engine.streaming() {
+            let wait = config::config().config.general.rollback_timeout();
+            query_engine.end_streaming(wait).await?;
+        }
+
+        if replication_timeout {
+            let replication_client_timeout = self.timeouts.replication_client_timeout;
+            error!(
+                "replication client sent nothing for {}ms, disconnecting [{}]",
+                replication_client_timeout.as_millis(),
+                self.addr
+            );
+            // The client is probably gone, don't wait on it for long.
+            let _ = timeout(
+                replication_client_timeout,
+                self.stream.fatal(ErrorResponse::replication_client_timeout(
+                    replication_client_timeout,
+                )),
+            )
+            .await;
+            return Ok(());
+        }
+
         if offline && !self.shutdown {
             self.stream
                 .send_flush(&ErrorResponse::shutting_down())
@@ -341,6 +595,43 @@ impl Client {
         Ok(())
     }
 
+    /// Remember the shard this session used, so the client
+    /// can get back to it after reconnecting.
+    fn remember_session(&self) {
+        let Some(ttl) = config::config().config.general.session_pin_ttl() else {
+            return;
+        };
+
+        if let (Some(shard), Some(key)) =
+            (self.session_shard, SessionKey::from_params(&self.params))
+        {
+            debug!(
+                "remembering shard {} for session \"{}\" [{}]",
+                shard, key.tag, self.addr
+            );
+            SessionPins::global().remember(key, shard, ttl);
+        }
+    }
+
+    /// Shard this session was pinned to before the client reconnected.
+    fn session_pin(&self) -> Option<Shard> {
+        config::config().config.general.session_pin_ttl()?;
+        let key = SessionKey::from_params(&self.params)?;
+        SessionPins::global().get(&key).map(Shard::Direct)
+    }
+
+    /// How long a streaming replication client can stay silent before
+    /// it's disconnected.
+    fn replication_deadline(&self) -> Duration {
+        if self.streaming {
+            self.timeouts
+                .replication_client_timeout
+                .saturating_sub(self.last_message.elapsed())
+        } else {
+            Duration::MAX
+        }
+    }
+
     async fn server_message(
         &mut self,
         query_engine: &mut QueryEngine,
@@ -349,15 +640,22 @@ impl Client {
         let mut context = QueryEngineContext::new(self);
         query_engine.server_message(&mut context, message).await?;
         self.transaction = context.transaction();
+        // Forward CopyData from replication clients, e.g. standby status updates,
+        // without buffering.
+        self.streaming = query_engine.streaming();
 
         Ok(())
     }
 
     /// Handle client messages.
     async fn client_messages(&mut self, query_engine: &mut QueryEngine) -> Result<(), Error> {
+        let session_pin = self.session_pin();
         let mut context = QueryEngineContext::new(self);
-        query_engine.handle(&mut context).await?;
+        context.shard_override = session_pin;
+        let result = query_engine.handle(&mut context).await;
         self.transaction = context.transaction();
+        self.session_shard = query_engine.session_shard();
+        result?;
         return Ok(());
     }
 
@@ -378,14 +676,23 @@ impl Client {
         self.prepared_statements.capacity = config.config.general.prepared_statements_limit;
         self.timeouts = Timeouts::from_config(&config.config.general);
         self.cross_shard_disabled = config.config.general.cross_shard_disabled;
+        self.client_write_buffer = config.config.general.client_write_buffer;
+        self.flush_threshold = config.config.general.flush_threshold;
+        self.elide_transactions = config.config.general.elide_single_statement_transactions;
+        self.read_your_writes = config.config.general.read_your_writes;
+        self.read_your_writes_window = config.config.general.read_your_writes_window.into();
 
         while !self.client_request.full() {
-            let idle_timeout = self
-                .timeouts
-                .client_idle_timeout(&state, &self.client_request);
+            let idle_timeout = if self.streaming {
+                self.replication_deadline()
+            } else {
+                self.timeouts
+                    .client_idle_timeout(&state, &self.client_request)
+            };
 
             let message =
                 match timeout(idle_timeout, self.stream.read_buf(&mut self.stream_buffer)).await {
+                    Err(_) if self.streaming => return Ok(BufferEvent::ReplicationTimeout),
                     Err(_) => {
                         self.stream
                             .fatal(ErrorResponse::client_idle_timeout(idle_timeout))
@@ -397,8 +704,9 @@ impl Client {
                     Ok(Err(_)) => return Ok(BufferEvent::DisconnectAbrupt),
                 };
 
+            self.last_message = Instant::now();
             if timer.is_none() {
-                timer = Some(Instant::now());
+                timer = Some(self.last_message);
             }
 
             // Terminate (B & F).
@@ -443,6 +751,7 @@ impl Drop for Client {
     fn drop(&mut self) {
         self.comms.disconnect();
         self.prepared_statements.close_all();
+        self.remember_session();
     }
 }
 
@@ -453,5 +762,7 @@ pub mod test;
 enum BufferEvent {
     DisconnectGraceful,
     DisconnectAbrupt,
+    /// Replication client didn't send anything for `replication_client_timeout`.
+    ReplicationTimeout,
     HaveRequest,
 }
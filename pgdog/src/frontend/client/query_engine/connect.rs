@@ -1,4 +1,6 @@
-use tokio::time::timeout;
+use std::io::ErrorKind;
+
+use tokio::{select, time::timeout};
 
 use super::*;
 
@@ -17,14 +19,45 @@ impl QueryEngine {
             return Ok(true);
         }
 
-        let request = Request::new(self.client_id);
+        let mut request = Request::transaction(self.client_id);
+        request.deadline = context
+            .timeouts
+            .checkout_deadline(context.params, request.created_at);
+        if let Ok(cluster) = self.backend.cluster() {
+            request.read_consistency = cluster.read_consistency();
+        }
+        let route = &self.read_your_writes_route(context, route, &mut request);
 
         self.stats.waiting(request.created_at);
         self.comms.stats(self.stats);
+        self.spans.checkout();
+
+        // Stop waiting for a connection if the client disconnects.
+        let result = select! {
+            result = self.backend.connect(&request, &route) => result,
+            _ = context.stream.closed() => {
+                return Err(crate::net::Error::Io(ErrorKind::UnexpectedEof.into()).into());
+            }
+        };
+
+        self.stats.checked_out();
 
-        let connected = match self.backend.connect(&request, &route).await {
+        let connected = match result {
             Ok(_) => {
                 self.stats.connected();
+                self.writes_connected(route);
+                if self.backend.session_mode() {
+                    self.session_shard = match route.shard() {
+                        Shard::Direct(shard) => Some(*shard),
+                        _ => None,
+                    };
+                }
+                self.spans.checked_out(
+                    route,
+                    &mut self.backend,
+                    self.stats.current.last_wait_time,
+                    None,
+                );
                 self.stats.locked(route.lock_session());
                 // This connection will be locked to this client
                 // until they disconnect.
@@ -41,11 +74,17 @@ impl QueryEngine {
                             .collect::<Vec<_>>()
                             .join(","),
                         route,
-                        self.stats.wait_time.as_secs_f64() * 1000.0
+                        self.stats.current.last_wait_time.as_secs_f64() * 1000.0
                     );
                 }
 
-                let query_timeout = context.timeouts.query_timeout(&self.stats.state);
+                let query_timeout = context.timeouts.query_timeout(&self.stats.current.state);
+                // Servers new to this client run the session setup first.
+                // A failure disconnects the client.
+                if let Ok(cluster) = self.backend.cluster() {
+                    let setup = cluster.session_setup();
+                    timeout(query_timeout, self.backend.setup_session(&setup)).await??;
+                }
                 // We may need to sync params with the server and that reads from the socket.
                 timeout(query_timeout, self.backend.link_client(&context.params)).await??;
 
@@ -53,17 +92,30 @@ impl QueryEngine {
             }
 
             Err(err) => {
-                self.stats.error();
+                self.stats.idle(context.in_transaction());
+                self.spans.checked_out(
+                    route,
+                    &mut self.backend,
+                    self.stats.current.last_wait_time,
+                    Some(&err),
+                );
 
                 if err.no_server() {
-                    error!("{} [{:?}]", err, context.stream.peer_addr());
-                    let bytes_sent = context
-                        .stream
-                        .error(ErrorResponse::from_err(&err), context.in_transaction())
-                        .await?;
+                    let error = if err.load_shed() {
+                        debug!("{} [{:?}]", err, context.stream.peer_addr());
+                        ErrorResponse::load_shed()
+                    } else if err.statement_timeout() {
+                        debug!("{} [{:?}]", err, context.stream.peer_addr());
+                        ErrorResponse::statement_timeout()
+                    } else {
+                        error!("{} [{:?}]", err, context.stream.peer_addr());
+                        ErrorResponse::from_err(&err)
+                    };
+                    let bytes_sent = context.error(error).await?;
                     self.stats.sent(bytes_sent);
                     self.backend.disconnect();
                     self.router.reset();
+                    self.spans.transaction_done();
                 } else {
                     return Err(err.into());
                 }
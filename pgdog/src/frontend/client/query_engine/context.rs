@@ -1,10 +1,14 @@
+use std::time::Duration;
+
 use crate::{
     backend::pool::connection::mirror::Mirror,
+    config::ReadYourWrites,
     frontend::{
-        client::{timeouts::Timeouts, TransactionType},
+        client::{timeouts::Timeouts, TransactionStatus, TransactionType},
+        router::parser::Shard,
         Client, ClientRequest, PreparedStatements,
     },
-    net::{Parameters, Stream},
+    net::{ErrorResponse, Parameters, ReadyForQuery, Stream},
     stats::memory::MemoryUsage,
 };
 
@@ -18,14 +22,31 @@ pub struct QueryEngineContext<'a> {
     pub(super) client_request: &'a mut ClientRequest,
     /// Client's socket to send responses to.
     pub(super) stream: &'a mut Stream,
-    /// Client in transaction?
-    pub(super) transaction: Option<TransactionType>,
+    /// Client transaction status.
+    pub(super) transaction: TransactionStatus,
     /// Timeouts
     pub(super) timeouts: Timeouts,
     /// Cross shard  queries are disabled.
     pub(super) cross_shard_disabled: bool,
     /// Client memory usage.
     pub(super) memory_usage: usize,
+    /// Flush to the client once this many bytes are buffered.
+    pub(super) client_write_buffer: usize,
+    /// Flush to the client once this many bytes are buffered, to batch small writes.
+    pub(super) flush_threshold: usize,
+    /// Send the query to this shard instead of the one picked by the router.
+    pub(super) shard_override: Option<Shard>,
+    /// Skip BEGIN/COMMIT for transactions with a single read.
+    pub(super) elide_transactions: bool,
+    /// Send reads to servers that have the client's writes.
+    pub(super) read_your_writes: ReadYourWrites,
+    /// Reads stick to the primary for this long after a write.
+    pub(super) read_your_writes_window: Duration,
+    /// We returned an error for an extended protocol request that didn't end
+    /// with Sync, so the rest of the batch has to be skipped until Sync.
+    pub(super) batch_rejected: bool,
+    /// Errors we returned to the client.
+    pub(super) errors: usize,
 }
 
 impl<'a> QueryEngineContext<'a> {
@@ -41,11 +62,23 @@ impl<'a> QueryEngineContext<'a> {
             timeouts: client.timeouts,
             cross_shard_disabled: client.cross_shard_disabled,
             memory_usage,
+            client_write_buffer: client.client_write_buffer,
+            flush_threshold: client.flush_threshold,
+            shard_override: None,
+            elide_transactions: client.elide_transactions,
+            read_your_writes: client.read_your_writes,
+            read_your_writes_window: client.read_your_writes_window,
+            batch_rejected: false,
+            errors: 0,
         }
     }
 
     /// Create context from mirror.
-    pub fn new_mirror(mirror: &'a mut Mirror, buffer: &'a mut ClientRequest) -> Self {
+    pub fn new_mirror(
+        mirror: &'a mut Mirror,
+        buffer: &'a mut ClientRequest,
+        shard_override: Option<Shard>,
+    ) -> Self {
         Self {
             prepared_statements: &mut mirror.prepared_statements,
             params: &mut mirror.params,
@@ -55,14 +88,56 @@ impl<'a> QueryEngineContext<'a> {
             timeouts: mirror.timeouts,
             cross_shard_disabled: mirror.cross_shard_disabled,
             memory_usage: 0,
+            // Mirror stream discards everything.
+            client_write_buffer: usize::MAX,
+            flush_threshold: usize::MAX,
+            shard_override,
+            elide_transactions: false,
+            read_your_writes: ReadYourWrites::Off,
+            read_your_writes_window: Duration::ZERO,
+            batch_rejected: false,
+            errors: 0,
         }
     }
 
-    pub fn transaction(&self) -> Option<TransactionType> {
+    pub fn transaction(&self) -> TransactionStatus {
         self.transaction
     }
 
+    pub fn transaction_type(&self) -> Option<TransactionType> {
+        self.transaction.transaction_type()
+    }
+
     pub fn in_transaction(&self) -> bool {
-        self.transaction.is_some()
+        self.transaction.in_transaction()
+    }
+
+    /// ReadyForQuery with the client's transaction status.
+    pub fn ready_for_query(&self) -> ReadyForQuery {
+        self.transaction.ready_for_query()
+    }
+
+    /// Send an error to the client. It fails the transaction, if there is one.
+    ///
+    /// Like Postgres, ReadyForQuery only follows if the request ends with Sync
+    /// or is a simple query. Extended protocol requests ending with Flush
+    /// get the error only, and the rest of the batch is skipped until Sync.
+    pub async fn error(&mut self, error: ErrorResponse) -> Result<usize, crate::net::Error> {
+        self.transaction = self.transaction.fail();
+        self.errors += 1;
+
+        let flush = self
+            .client_request
+            .messages
+            .last()
+            .map(|message| message.code() == 'H')
+            .unwrap_or(false);
+
+        if flush {
+            self.batch_rejected = true;
+            self.stream.send_flush(&error).await
+        } else {
+            self.stream.error(error, self.in_transaction()).await
+        }
     }
 }
@@ -1,4 +1,4 @@
-use crate::net::{CommandComplete, Protocol, ReadyForQuery};
+use crate::net::{CommandComplete, Protocol};
 
 use super::*;
 
@@ -11,7 +11,7 @@ impl QueryEngine {
             .stream
             .send_many(&[
                 CommandComplete::from_str("DEALLOCATE").message()?,
-                ReadyForQuery::in_transaction(context.in_transaction()).message()?,
+                context.ready_for_query().message()?,
             ])
             .await?;
 
@@ -0,0 +1,161 @@
+//! Routing metadata sent to the client after each statement,
+//! enabled with `SET pgdog.debug TO on`.
+
+use std::time::{Duration, Instant};
+
+use crate::backend::pool::connection::multi_shard::ShardRows;
+use crate::net::{
+    BindComplete, CommandComplete, NoData, NoticeResponse, ParameterDescription, ParseComplete,
+    Protocol, ProtocolMessage,
+};
+
+use super::*;
+
+/// Notices start with this, so they are easy to spot and filter.
+pub const DEBUG_PREFIX: &str = "pgdog debug:";
+
+/// Debug mode state.
+#[derive(Debug, Clone)]
+pub struct QueryDebug {
+    /// Client asked for debug notices.
+    enabled: bool,
+    /// Time spent waiting for a server connection.
+    checkout: Duration,
+    /// Statement started executing.
+    started: Instant,
+    /// Server is in COPY or replication mode.
+    copy: bool,
+}
+
+impl Default for QueryDebug {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            checkout: Duration::ZERO,
+            started: Instant::now(),
+            copy: false,
+        }
+    }
+}
+
+impl QueryDebug {
+    /// Debug mode is on.
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Request is about to be sent to the server.
+    pub(super) fn sent(&mut self, checkout: Duration) {
+        self.checkout = checkout;
+        self.started = Instant::now();
+    }
+}
+
+impl QueryEngine {
+    /// Turn debug mode on or off. Not sent to the server.
+    pub(super) async fn set_debug(
+        &mut self,
+        context: &mut QueryEngineContext<'_>,
+        enabled: bool,
+    ) -> Result<(), Error> {
+        self.debug.enabled = enabled;
+
+        let mut messages = vec![];
+
+        // Answer each message like the server would for a SET.
+        for message in context.client_request.messages.iter() {
+            match message {
+                ProtocolMessage::Query(_) => {
+                    messages.push(CommandComplete::from_str("SET").message()?);
+                    messages.push(context.ready_for_query().message()?);
+                }
+                ProtocolMessage::Parse(_) => messages.push(ParseComplete.message()?),
+                ProtocolMessage::Bind(_) => messages.push(BindComplete.message()?),
+                ProtocolMessage::Describe(describe) => {
+                    if describe.is_statement() {
+                        messages.push(ParameterDescription::empty().message()?);
+                    }
+                    messages.push(NoData.message()?);
+                }
+                ProtocolMessage::Execute(_) => {
+                    messages.push(CommandComplete::from_str("SET").message()?)
+                }
+                ProtocolMessage::Sync(_) => messages.push(context.ready_for_query().message()?),
+                _ => (),
+            }
+        }
+
+        let bytes_sent = context.stream.send_many(&messages).await?;
+
+        self.stats.sent(bytes_sent);
+
+        Ok(())
+    }
+
+    /// Build the debug notice sent to the client after a CommandComplete.
+    pub(super) fn debug_notice(&mut self, code: char) -> Option<NoticeResponse> {
+        match code {
+            // CopyInResponse, CopyOutResponse, CopyBothResponse
+            'G' | 'H' | 'W' => self.debug.copy = true,
+            'Z' => self.debug.copy = false,
+            _ => (),
+        }
+
+        if code != 'C' || !self.debug.enabled || self.debug.copy || self.streaming {
+            return None;
+        }
+
+        let cluster = self.backend.cluster().ok()?;
+        if cluster.replication_mode() {
+            return None;
+        }
+
+        let servers = self
+            .backend
+            .addr()
+            .ok()?
+            .into_iter()
+            .map(|addr| format!("{}:{}", addr.host, addr.port))
+            .collect::<Vec<_>>()
+            .join(",");
+
+        let ast_cache = match self.router.cache_hit() {
+            Some(true) => "hit",
+            Some(false) => "miss",
+            None => "none",
+        };
+
+        let execution = self.debug.started.elapsed();
+        let mut message = format!(
+            "{} {}, server={}, checkout={:.3}ms, execution={:.3}ms, ast_cache={}",
+            DEBUG_PREFIX,
+            self.router.route(),
+            servers,
+            self.debug.checkout.as_secs_f64() * 1000.0,
+            execution.as_secs_f64() * 1000.0,
+            ast_cache,
+        );
+
+        let shard_rows = self.backend.shard_rows();
+        if !shard_rows.is_empty() {
+            let join = |value: fn(&ShardRows) -> u64| {
+                shard_rows
+                    .iter()
+                    .map(|shard| format!("{}:{}", shard.shard, value(shard)))
+                    .collect::<Vec<_>>()
+                    .join(",")
+            };
+            message.push_str(&format!(
+                ", shard_rows={}, shard_bytes={}",
+                join(|shard| shard.rows),
+                join(|shard| shard.bytes),
+            ));
+        }
+
+        // Next statement in the same request starts now.
+        self.debug.started = Instant::now();
+        self.debug.checkout = Duration::ZERO;
+
+        Some(NoticeResponse::from(ErrorResponse::debug(&message)))
+    }
+}
@@ -0,0 +1,247 @@
+//! Single-statement transaction elision.
+//!
+//! When enabled, the statement right after `BEGIN` is sent to the server
+//! without `BEGIN`. If `COMMIT` comes next, the transaction never existed
+//! on the server and we reply to the client ourselves. Anything else
+//! starts the transaction on the server for real, with the `BEGIN` we held back.
+//!
+//! Since the elided statement already ran on its own when the client sends
+//! something else, only reads that don't lock anything are elided this way.
+//! When the whole transaction is sent in one query, e.g. `BEGIN; UPDATE ...; COMMIT`,
+//! we know nothing else follows, so any statement that isn't DDL and doesn't lock
+//! the session runs without `BEGIN` and `COMMIT`.
+//!
+//! Isolation level and `DEFERRABLE` change how the statement runs,
+//! so those transactions always reach the server.
+//!
+//! Once a transaction is started on the server, it keeps its connection until
+//! it ends, even if the client is idle in transaction. Detaching read-only
+//! transactions with `pg_export_snapshot()` and resuming them elsewhere with
+//! `SET TRANSACTION SNAPSHOT` doesn't work: an exported snapshot can only be
+//! imported while the transaction that exported it is still open, so the
+//! exporting connection would stay pinned anyway.
+
+use tracing::debug;
+
+use crate::{
+    frontend::router::parser::TransactionOptions,
+    net::{CommandComplete, Message, Protocol, ProtocolMessage, ReadyForQuery},
+};
+
+use super::*;
+
+/// Transaction the client started but the server hasn't seen.
+#[derive(Debug, Clone)]
+pub struct ElidedTransaction {
+    /// BEGIN we didn't send, or `None` if the client sent COMMIT
+    /// in the same query.
+    begin: Option<TransactionOptions>,
+    /// The statement returned an error.
+    failed: bool,
+}
+
+impl ElidedTransaction {
+    /// The statement returned an error, so the transaction is aborted.
+    pub fn failed(&self) -> bool {
+        self.failed
+    }
+}
+
+/// Statement is a plain `BEGIN`, without options.
+fn plain_begin(statement: &str) -> bool {
+    let words = words(statement);
+    matches!(
+        words.as_slice(),
+        ["begin"] | ["begin", "transaction" | "work"] | ["start", "transaction"]
+    )
+}
+
+/// Statement is `COMMIT` or `END`.
+fn commit(statement: &str) -> bool {
+    let words = words(statement);
+    matches!(
+        words.as_slice(),
+        ["commit" | "end"] | ["commit" | "end", "transaction" | "work"]
+    )
+}
+
+fn words(statement: &str) -> Vec<String> {
+    statement
+        .trim()
+        .trim_end_matches(';')
+        .split_whitespace()
+        .map(|word| word.to_lowercase())
+        .collect()
+}
+
+impl QueryEngine {
+    /// Check if the client sent a whole transaction running one statement, e.g.
+    /// `BEGIN; SELECT 1; COMMIT`. If so, the request is replaced with the statement,
+    /// so it's routed on its own, and the original query is returned.
+    pub(super) fn elide_candidate(
+        &self,
+        context: &mut QueryEngineContext<'_>,
+    ) -> Result<Option<String>, Error> {
+        if !context.elide_transactions
+            || context.in_transaction()
+            || self.begin_stmt.is_some()
+            || self.backend.connected()
+        {
+            return Ok(None);
+        }
+
+        let query = match context.client_request.messages.as_slice() {
+            [ProtocolMessage::Query(query)] => query.query().to_string(),
+            _ => return Ok(None),
+        };
+
+        let statements = match pg_query::split_with_scanner(&query) {
+            Ok(statements) => statements,
+            Err(_) => return Ok(None),
+        };
+
+        match statements.as_slice() {
+            [begin, statement, end] if plain_begin(begin) && commit(end) => {
+                let statement = statement.trim().to_string();
+                context.client_request.rewrite(&statement)?;
+                Ok(Some(query))
+            }
+
+            _ => Ok(None),
+        }
+    }
+
+    /// Check if the statement sent with BEGIN and COMMIT in one query can run
+    /// on its own. It can't be DDL or lock the session.
+    pub(super) fn can_elide_query(&self, route: &Route) -> bool {
+        matches!(self.router.command(), Command::Query(_))
+            && self.router.ddl_statement() == Some(false)
+            && !route.lock_session()
+    }
+
+    /// Check if the statement after BEGIN can run without it. Another statement
+    /// could follow, so it has to read data without locking it, be the only
+    /// statement in the request, and the transaction can't change isolation level.
+    pub(super) fn can_elide(
+        &self,
+        context: &QueryEngineContext<'_>,
+        route: &Route,
+        begin: &TransactionOptions,
+    ) -> bool {
+        context.elide_transactions
+            && self.can_elide_query(route)
+            && self.router.read_statement()
+            && begin.isolation_level.is_none()
+            && begin.deferrable.is_none()
+            && context.client_request.single_statement()
+    }
+
+    /// Execute the statement without sending BEGIN and COMMIT. The client
+    /// gets our reply to BEGIN first.
+    pub(super) async fn elide_query(
+        &mut self,
+        context: &mut QueryEngineContext<'_>,
+    ) -> Result<(), Error> {
+        debug!("eliding BEGIN and COMMIT");
+
+        let bytes_sent = context
+            .stream
+            .send(&CommandComplete::new_begin().message()?.backend())
+            .await?;
+        self.stats.sent(bytes_sent);
+        self.elided = Some(ElidedTransaction {
+            begin: None,
+            failed: false,
+        });
+
+        Ok(())
+    }
+
+    /// Execute the statement without sending BEGIN first.
+    pub(super) fn elide(&mut self, begin: TransactionOptions) {
+        debug!("eliding BEGIN");
+        self.elided = Some(ElidedTransaction {
+            begin: Some(begin),
+            failed: false,
+        });
+    }
+
+    /// The client sent another statement, so start the transaction on the server.
+    /// Returns false if the elided statement failed and the client got an error instead.
+    pub(super) async fn resume_transaction(
+        &mut self,
+        context: &mut QueryEngineContext<'_>,
+    ) -> Result<bool, Error> {
+        // Parse, Describe, etc. don't need a transaction.
+        if !context.client_request.executable() {
+            return Ok(true);
+        }
+
+        match self.elided.take() {
+            Some(elided) if elided.failed => {
+                let bytes_sent = context
+                    .error(ErrorResponse::in_failed_transaction())
+                    .await?;
+                self.stats.sent(bytes_sent);
+                self.elided = Some(elided);
+                Ok(false)
+            }
+
+            Some(ElidedTransaction {
+                begin: Some(begin), ..
+            }) => {
+                debug!("resuming elided transaction");
+                self.begin_stmt = Some(begin);
+                Ok(true)
+            }
+
+            // The client sent COMMIT with the statement.
+            elided => {
+                self.elided = elided;
+                Ok(true)
+            }
+        }
+    }
+
+    /// Server responses to the elided statement. The server isn't in a transaction,
+    /// but the client thinks it is until COMMIT.
+    pub(super) async fn elided_message(
+        &mut self,
+        context: &mut QueryEngineContext<'_>,
+        code: char,
+        message: Message,
+    ) -> Result<Message, Error> {
+        let elided = match self.elided.as_mut() {
+            Some(elided) => elided,
+            None => return Ok(message),
+        };
+
+        match code {
+            'E' => {
+                elided.failed = true;
+                Ok(message)
+            }
+
+            // COMMIT isn't executed after an error, so the client
+            // stays in the failed transaction until it ends it.
+            'Z' if elided.failed => Ok(ReadyForQuery::error().message()?.backend()),
+
+            // The client hasn't sent COMMIT yet.
+            'Z' if elided.begin.is_some() => {
+                Ok(ReadyForQuery::in_transaction(true).message()?.backend())
+            }
+
+            'Z' => {
+                let bytes_sent = context
+                    .stream
+                    .send(&CommandComplete::new_commit().message()?.backend())
+                    .await?;
+                self.stats.sent(bytes_sent);
+                self.elided = None;
+                Ok(message)
+            }
+
+            _ => Ok(message),
+        }
+    }
+}
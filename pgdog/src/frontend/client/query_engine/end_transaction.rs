@@ -1,4 +1,7 @@
-use crate::net::{CommandComplete, NoticeResponse, Protocol, ReadyForQuery};
+use crate::{
+    frontend::client::TransactionStatus,
+    net::{CommandComplete, NoticeResponse, Protocol, ReadyForQuery},
+};
 
 use super::*;
 
@@ -8,7 +11,8 @@ impl QueryEngine {
         context: &mut QueryEngineContext<'_>,
         rollback: bool,
     ) -> Result<(), Error> {
-        let cmd = if rollback {
+        // COMMIT of a failed transaction rolls it back.
+        let cmd = if rollback || context.transaction().failed() {
             CommandComplete::new_rollback()
         } else {
             CommandComplete::new_commit()
@@ -20,10 +24,13 @@ impl QueryEngine {
         };
         messages.push(cmd.message()?.backend());
         messages.push(ReadyForQuery::idle().message()?);
+        context.transaction = TransactionStatus::Idle;
 
         let bytes_sent = context.stream.send_many(&messages).await?;
         self.stats.sent(bytes_sent);
         self.begin_stmt = None;
+        self.elided = None;
+        self.spans.transaction_done();
 
         debug!("transaction ended");
         Ok(())
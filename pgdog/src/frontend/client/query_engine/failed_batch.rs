@@ -0,0 +1,56 @@
+//! Extended protocol batches that failed before Sync.
+//!
+//! After an error, Postgres discards every message until Sync. If we kept
+//! forwarding them, we'd expect replies the server will never send and
+//! attribute the next ReadyForQuery to the wrong request. So we drop them too.
+//!
+//! Requests rejected by PgDog, e.g. with `cross_shard_disabled`, are handled the
+//! same way, so the client sees the same replies as if the server rejected them.
+
+use tracing::debug;
+
+use super::*;
+
+impl QueryEngine {
+    /// Track errors and ends of batches sent by the server.
+    pub(super) fn track_batch(&mut self, code: char) {
+        match code {
+            // ErrorResponse (B)
+            'E' => self.batch_failed = true,
+            // ReadyForQuery (B)
+            'Z' => self.batch_failed = false,
+            _ => (),
+        }
+    }
+
+    /// Drop client messages until Sync after an error, returned by the server or by us.
+    /// The Sync is handled like any other and gets ReadyForQuery.
+    /// Returns true if nothing is left to handle.
+    pub(super) fn skip_until_sync(&mut self, context: &mut QueryEngineContext<'_>) -> bool {
+        if !self.batch_failed {
+            return false;
+        }
+
+        let messages = &mut context.client_request.messages;
+        match messages.iter().position(|message| message.code() == 'S') {
+            Some(sync) => {
+                if sync > 0 {
+                    debug!("skipping {} messages until sync", sync);
+                    messages.drain(..sync);
+                }
+                // Without a server, we answer the Sync, so the batch ends here.
+                // Otherwise, it ends with the server's ReadyForQuery.
+                if !self.backend.connected() {
+                    self.batch_failed = false;
+                }
+                false
+            }
+
+            None => {
+                debug!("skipping {} messages until sync", messages.len());
+                messages.clear();
+                true
+            }
+        }
+    }
+}
@@ -0,0 +1,51 @@
+//! Commands in a failed transaction.
+//!
+//! Postgres refuses everything but ROLLBACK and COMMIT until a failed transaction
+//! ends. Commands we normally answer ourselves, like SET before the transaction
+//! reached the server or DEALLOCATE, get the same error instead of a success
+//! the server would never have returned.
+
+use tracing::debug;
+
+use super::*;
+
+impl QueryEngine {
+    /// Refuse a command we'd answer ourselves if the transaction failed.
+    /// Returns true if the client got an error.
+    pub(super) async fn refuse_in_failed_transaction(
+        &mut self,
+        context: &mut QueryEngineContext<'_>,
+    ) -> Result<bool, Error> {
+        if !context.transaction().failed() {
+            return Ok(false);
+        }
+
+        let local = match self.router.command() {
+            Command::Shards(_)
+            | Command::Show { .. }
+            | Command::StartTransaction { .. }
+            | Command::Deallocate
+            | Command::SetDebug(_)
+            | Command::Listen { .. }
+            | Command::Notify { .. }
+            | Command::Unlisten(_) => true,
+            Command::CommitTransaction | Command::RollbackTransaction => false,
+            // Sent to the server if it has the transaction. If it doesn't,
+            // e.g. the transaction failed before reaching it, it would succeed.
+            _ => !self.backend.connected(),
+        };
+
+        if !local {
+            return Ok(false);
+        }
+
+        debug!("refusing command in failed transaction");
+
+        let bytes_sent = context
+            .error(ErrorResponse::in_failed_transaction())
+            .await?;
+        self.stats.sent(bytes_sent);
+
+        Ok(true)
+    }
+}
@@ -1,6 +1,6 @@
 use tokio::io::AsyncWriteExt;
 
-use crate::net::{Close, CloseComplete, FromBytes, Protocol, ReadyForQuery, ToBytes};
+use crate::net::{Close, CloseComplete, FromBytes, Protocol, ToBytes};
 
 use super::*;
 
@@ -41,10 +41,7 @@ impl QueryEngine {
                 }
                 'S' => {
                     if only_close || only_sync && !self.backend.connected() {
-                        bytes_sent += context
-                            .stream
-                            .send(&ReadyForQuery::in_transaction(context.in_transaction()))
-                            .await?;
+                        bytes_sent += context.stream.send(&context.ready_for_query()).await?;
                     }
                 }
                 c => {
@@ -1,24 +1,38 @@
 use crate::{
     backend::pool::{Connection, Request},
     frontend::{
-        router::{parser::Shard, Route},
+        router::{
+            parser::{Shard, TransactionOptions},
+            ErrorClass, Route, RouterStats, RoutingDecision, RoutingHistory,
+        },
+        session_pins::SESSION_TAG,
         BufferedQuery, Client, Command, Comms, Error, Router, RouterContext, Stats,
     },
-    net::{BackendKeyData, ErrorResponse, Message, Parameters},
+    net::{parameter::ROLE_PARAMS, BackendKeyData, CopyDone, ErrorResponse, Message, Parameters},
     state::State,
+    telemetry::Spans,
 };
 
-use tracing::debug;
+use std::time::Duration;
+
+use tokio::time::timeout;
+use tracing::{debug, warn};
 
 pub mod connect;
 pub mod context;
 pub mod deallocate;
+pub mod debug;
+pub mod elide_transaction;
 pub mod end_transaction;
+pub mod failed_batch;
+pub mod failed_transaction;
 pub mod incomplete_requests;
 pub mod pub_sub;
 pub mod query;
+pub mod read_your_writes;
 pub mod route_query;
 pub mod set;
+pub mod show;
 pub mod show_shards;
 pub mod start_transaction;
 pub mod unknown_command;
@@ -27,10 +41,13 @@ pub mod unknown_command;
 mod testing;
 
 pub use context::QueryEngineContext;
+pub use debug::QueryDebug;
+pub use elide_transaction::ElidedTransaction;
+pub use read_your_writes::Writes;
 
 #[derive(Default, Debug)]
 pub struct QueryEngine {
-    begin_stmt: Option<BufferedQuery>,
+    begin_stmt: Option<TransactionOptions>,
     router: Router,
     comms: Comms,
     stats: Stats,
@@ -38,6 +55,24 @@ pub struct QueryEngine {
     streaming: bool,
     client_id: BackendKeyData,
     test_mode: bool,
+    /// Bytes sent to the client since the last flush.
+    unflushed: usize,
+    /// Routing metadata notices.
+    debug: QueryDebug,
+    /// Transaction started by the client but not on the server.
+    elided: Option<ElidedTransaction>,
+    /// Trace spans.
+    spans: Spans,
+    /// Shard used by this session, in session mode.
+    session_shard: Option<usize>,
+    /// Client's writes, for read-your-writes routing.
+    writes: Writes,
+    /// Server or we returned an error before Sync, so messages are ignored until Sync.
+    batch_failed: bool,
+    /// Client's last routing decisions.
+    routing_history: RoutingHistory,
+    /// Query and its routing decision, written to the query log once it finishes.
+    query_log: Option<(String, RoutingDecision)>,
 }
 
 impl<'a> QueryEngine {
@@ -57,6 +92,8 @@ impl<'a> QueryEngine {
             backend,
             client_id: comms.client_id(),
             comms: comms.clone(),
+            // Clients that aren't connected through the listener keep their own.
+            routing_history: comms.routing_history().unwrap_or_else(RoutingHistory::new),
             #[cfg(test)]
             test_mode: true,
             #[cfg(not(test))]
@@ -79,27 +116,116 @@ impl<'a> QueryEngine {
         Ok(self.backend.read().await?)
     }
 
+    /// Bytes waiting to be flushed to the client.
+    pub fn unflushed(&self) -> usize {
+        self.unflushed
+    }
+
     /// Query engine finished executing.
     pub fn done(&self) -> bool {
-        !self.backend.connected() && self.begin_stmt.is_none()
+        !self.backend.connected() && self.begin_stmt.is_none() && self.elided.is_none()
+    }
+
+    /// Make stats visible to admin commands, e.g. before the client disconnects.
+    pub fn publish_stats(&self) {
+        self.comms.stats(self.stats);
     }
 
     /// Current state.
     pub fn client_state(&self) -> State {
-        self.stats.state
+        self.stats.current.state
+    }
+
+    /// Shard this client used in session mode, if it used only one.
+    pub fn session_shard(&self) -> Option<usize> {
+        self.session_shard
+    }
+
+    /// Server is streaming replication data.
+    pub fn streaming(&self) -> bool {
+        self.streaming
+    }
+
+    /// Ask the server to end the replication stream. It will reply
+    /// with CopyDone, CommandComplete and ReadyForQuery, like it does
+    /// when the client ends the stream.
+    pub async fn stop_streaming(&mut self) -> Result<(), Error> {
+        if self.streaming {
+            debug!("stopping replication stream");
+            self.backend.send(&vec![CopyDone.into()].into()).await?;
+        }
+
+        Ok(())
+    }
+
+    /// End the replication stream and wait for the server to finish it,
+    /// so the walsender exits cleanly, then release the connection.
+    /// If the server doesn't finish in time, the connection is closed instead.
+    pub async fn end_streaming(&mut self, wait: Duration) -> Result<(), Error> {
+        if !self.streaming {
+            return Ok(());
+        }
+
+        self.stop_streaming().await?;
+        self.streaming = false;
+
+        let finished = timeout(wait, async {
+            loop {
+                // CopyDone (B), CommandComplete (B), ReadyForQuery (B)
+                if self.backend.read().await?.code() == 'Z' {
+                    return Ok::<(), Error>(());
+                }
+            }
+        })
+        .await;
+
+        match finished {
+            Ok(Ok(())) => self.backend.disconnect(),
+            _ => {
+                warn!("replication stream didn't end in time, closing server connection");
+                self.backend.force_close();
+            }
+        }
+
+        Ok(())
     }
 
     /// Handle client request.
     pub async fn handle(&mut self, context: &mut QueryEngineContext<'_>) -> Result<(), Error> {
+        let result = self.handle_request(context).await;
+
+        if context.batch_rejected {
+            self.batch_failed = true;
+        }
+
+        result
+    }
+
+    async fn handle_request(&mut self, context: &mut QueryEngineContext<'_>) -> Result<(), Error> {
         self.stats
             .received(context.client_request.total_message_len());
 
+        if self.streaming {
+            if let Some(status_update) = context.client_request.status_update() {
+                self.stats.status_update(&status_update);
+            }
+        }
+
+        // An earlier part of the batch failed, so we skip the rest like Postgres would.
+        if self.skip_until_sync(context) {
+            self.update_stats(context);
+            return Ok(());
+        }
+
         // Intercept commands we don't have to forward to a server.
         if self.intercept_incomplete(context).await? {
             self.update_stats(context);
             return Ok(());
         }
 
+        // BEGIN; <statement>; COMMIT is routed using the statement.
+        let elide = self.elide_candidate(context)?;
+
         // Route transaction to the right servers.
         if !self.route_transaction(context).await? {
             self.update_stats(context);
@@ -107,36 +233,65 @@ impl<'a> QueryEngine {
             return Ok(());
         }
 
-        // Queue up request to mirrors, if any.
-        // Do this before sending query to actual server
-        // to have accurate timings between queries.
-        self.backend.mirror(&context.client_request);
+        // Don't answer for the server if its transaction failed.
+        if self.refuse_in_failed_transaction(context).await? {
+            self.update_stats(context);
+            return Ok(());
+        }
 
         let command = self.router.command();
         let route = command.route().clone();
 
+        if let Some(query) = elide {
+            if self.can_elide_query(&route) {
+                self.elide_query(context).await?;
+            } else {
+                // The server runs the whole transaction.
+                context.client_request.rewrite(&query)?;
+            }
+        }
+
         // FIXME, we should not to copy route twice.
         context.client_request.route = route.clone();
 
+        // Queue up request to mirrors, if any.
+        // Do this before sending query to actual server
+        // to have accurate timings between queries.
+        self.backend.mirror(&context.client_request);
+
         match command {
             Command::Shards(shards) => self.show_shards(context, *shards).await?,
-            Command::StartTransaction(begin) => {
-                self.start_transaction(context, begin.clone()).await?
+            Command::Show { settings, all } => self.show(context, &settings.clone(), *all).await?,
+            Command::StartTransaction { query, options } => {
+                self.start_transaction(context, query.clone(), options.clone())
+                    .await?
             }
             Command::CommitTransaction => {
-                if self.backend.connected() {
+                if let Some(elided) = self.elided.take() {
+                    self.end_transaction(context, elided.failed()).await?
+                } else if self.backend.connected() {
+                    self.writes_commit(context)?;
                     self.execute(context, &route).await?
                 } else {
                     self.end_transaction(context, false).await?
                 }
             }
             Command::RollbackTransaction => {
-                if self.backend.connected() {
+                if self.elided.take().is_some() {
+                    self.end_transaction(context, true).await?
+                } else if self.backend.connected() {
                     self.execute(context, &route).await?
                 } else {
                     self.end_transaction(context, true).await?
                 }
             }
+            Command::SetTransaction(options) => {
+                if self.backend.connected() || self.begin_stmt.is_none() {
+                    self.execute(context, &route).await?
+                } else {
+                    self.set_transaction(context, options.clone()).await?
+                }
+            }
             Command::Query(_) => self.execute(context, &route).await?,
             Command::Listen { channel, shard } => {
                 self.listen(context, &channel.clone(), shard.clone())
@@ -153,7 +308,14 @@ impl<'a> QueryEngine {
             Command::Unlisten(channel) => self.unlisten(context, &channel.clone()).await?,
             Command::Set { name, value } => {
                 if self.backend.connected() {
-                    self.execute(context, &route).await?
+                    // Keep the tag in case the session is pinned when the client leaves.
+                    if name == SESSION_TAG {
+                        context.params.insert(name.clone(), value.clone());
+                    }
+                    self.execute(context, &route).await?;
+                    if ROLE_PARAMS.contains(&name.as_str()) {
+                        self.backend.mark_dirty();
+                    }
                 } else {
                     self.set(context, name.clone(), value.clone()).await?
                 }
@@ -164,6 +326,7 @@ impl<'a> QueryEngine {
                 self.execute(context, &route).await?;
             }
             Command::Deallocate => self.deallocate(context).await?,
+            Command::SetDebug(enabled) => self.set_debug(context, *enabled).await?,
             command => self.unknown_command(context, command.clone()).await?,
         }
 
@@ -186,12 +349,13 @@ impl<'a> QueryEngine {
             }
         };
 
-        self.stats.state = state;
+        self.stats.current.state = state;
+        self.stats.errors(std::mem::take(&mut context.errors));
 
         self.stats
             .prepared_statements(context.prepared_statements.len_local());
         self.stats.memory_used(context.memory_usage);
 
-        self.comms.stats(self.stats);
+        self.publish_stats();
     }
 }
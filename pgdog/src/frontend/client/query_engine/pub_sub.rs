@@ -1,4 +1,4 @@
-use crate::net::{CommandComplete, Protocol, ReadyForQuery};
+use crate::net::{CommandComplete, Protocol};
 
 use super::*;
 
@@ -46,7 +46,7 @@ impl QueryEngine {
             .stream
             .send_many(&[
                 CommandComplete::new(command).message()?,
-                ReadyForQuery::in_transaction(context.in_transaction()).message()?,
+                context.ready_for_query().message()?,
             ])
             .await?;
 
@@ -1,8 +1,10 @@
+use std::time::Duration;
+
 use tokio::time::timeout;
 
 use crate::{
-    frontend::client::TransactionType,
-    net::{Message, Protocol, ProtocolMessage},
+    frontend::client::TransactionStatus,
+    net::{FromBytes, Message, Protocol, ProtocolMessage, ReadyForQuery},
     state::State,
 };
 
@@ -19,25 +21,43 @@ impl QueryEngine {
     ) -> Result<(), Error> {
         // Check for cross-shard quries.
         if context.cross_shard_disabled && route.is_cross_shard() {
-            let bytes_sent = context
-                .stream
-                .error(
-                    ErrorResponse::cross_shard_disabled(),
-                    context.in_transaction(),
-                )
-                .await?;
+            if let Ok(cluster) = self.backend.cluster() {
+                RouterStats::get().error(cluster.user(), cluster.name(), ErrorClass::CrossShard);
+            }
+            let error = self.routing_context(ErrorResponse::cross_shard_disabled());
+            let bytes_sent = context.error(error).await?;
             self.stats.sent(bytes_sent);
             return Ok(());
         }
 
+        self.spans.statement(context.client_request);
+
+        // Statement after an elided one, start the transaction on the server.
+        let resumed = self.elided.is_some();
+        if !self.resume_transaction(context).await? {
+            return Ok(());
+        }
+
+        let connected = self.backend.connected();
         if !self.connect(context, &route).await? {
             return Ok(());
         }
 
+        // Role changes must not leak to the next client.
+        if route.changes_role() {
+            self.backend.mark_dirty();
+        }
+
+        self.writes_execute(context, route);
+
         // We need to run a query now.
         if context.client_request.executable() {
             if let Some(begin_stmt) = self.begin_stmt.take() {
-                self.backend.execute(begin_stmt.query()).await?;
+                if !resumed && self.can_elide(context, route, &begin_stmt) {
+                    self.elide(begin_stmt);
+                } else {
+                    self.backend.execute(&begin_stmt.begin()).await?;
+                }
             }
         }
 
@@ -48,6 +68,13 @@ impl QueryEngine {
             }
         }
 
+        // Only report checkout time for new connections.
+        self.debug.sent(if connected {
+            Duration::ZERO
+        } else {
+            self.stats.current.last_wait_time
+        });
+
         self.backend
             .handle_client_request(context.client_request, &mut self.router, self.streaming)
             .await?;
@@ -76,33 +103,61 @@ impl QueryEngine {
         self.streaming = message.streaming();
 
         let code = message.code();
-        let message = message.backend();
+        self.track_batch(code);
+        let mut message = self
+            .elided_message(context, code, message.backend())
+            .await?;
+
+        // Server doesn't know the client's names for prepared statements.
+        if matches!(code, 'E' | 'N') {
+            message = context.prepared_statements.restore_names(message)?;
+        }
+
+        // Result of the LSN query we added to COMMIT.
+        if self.writes_capture(code, &message)? {
+            return Ok(());
+        }
+        self.spans.server_message(&message);
         let has_more_messages = self.backend.has_more_messages();
 
         // Messages that we need to send to the client immediately.
-        // ReadyForQuery (B) | CopyInResponse (B) | ErrorResponse(B) | NoticeResponse(B) | NotificationResponse (B)
-        let flush = matches!(code, 'Z' | 'G' | 'E' | 'N' | 'A')
+        // ReadyForQuery (B) | PortalSuspended (B) | CopyInResponse (B) | ErrorResponse(B) | NoticeResponse(B) | NotificationResponse (B)
+        //
+        // Everything else is batched into as few writes as possible, until the response
+        // is done or `flush_threshold` bytes are buffered, e.g. for large results and COPY.
+        //
+        // Flushing also applies backpressure: we won't read more from the server
+        // until the client drained its socket. This keeps memory bounded for slow clients.
+        let flush = matches!(code, 'Z' | 's' | 'G' | 'E' | 'N' | 'A')
             || !has_more_messages
-            || message.streaming();
+            || message.streaming()
+            || self.unflushed + message.len()
+                >= context.flush_threshold.min(context.client_write_buffer);
 
         // Server finished executing a query.
         // ReadyForQuery (B)
+        if code == 'E' {
+            self.stats.errors(1);
+        }
+
         if code == 'Z' {
             self.stats.query();
+            self.log_query().await?;
             // TODO: This is messed up.
             //
             // 1. We're ignoring server-set transaction state. Client gets a ReadyForQuery with transaction state set to Idle even
             // if they sent a BEGIN statement to us already.
             // 2. We're sending non-data fetching statements to the server without starting a transacation, e.g. Parse, Describe, Sync.
             // 3. We're confusing the hell out of pretty much anyone reading this. I wrote the damn thing and I'm still confused.
-            let in_transaction = message.in_transaction() || self.begin_stmt.is_some();
-            if !in_transaction {
-                context.transaction = None;
-            } else if context.transaction.is_none() {
-                // Query parser is disabled, so the server is responsible for telling us
-                // we started a transaction.
-                context.transaction = Some(TransactionType::ReadWrite);
-            }
+            let status = match ReadyForQuery::from_bytes(message.payload())?.status {
+                // Client sent BEGIN, but we haven't sent it to the server yet.
+                'I' if self.begin_stmt.is_some() => 'T',
+                status => status,
+            };
+            // If the query parser is disabled, the server is responsible for telling us
+            // we started a transaction.
+            context.transaction =
+                TransactionStatus::from_status(status, context.transaction_type());
 
             self.stats.idle(context.in_transaction());
 
@@ -114,6 +169,7 @@ impl QueryEngine {
         self.stats.sent(message.len());
 
         if self.backend.done() {
+            self.writes_done(context).await?;
             let changed_params = self.backend.changed_params();
 
             // Release the connection back into the pool before flushing data to client.
@@ -123,10 +179,11 @@ impl QueryEngine {
             }
 
             self.router.reset();
+            self.spans.transaction_done();
 
             debug!(
                 "transaction finished [{:.3}ms]",
-                self.stats.last_transaction_time.as_secs_f64() * 1000.0
+                self.stats.current.last_transaction_time.as_secs_f64() * 1000.0
             );
 
             // Update client params with values
@@ -140,10 +197,18 @@ impl QueryEngine {
             }
         }
 
+        // Routing metadata for clients in debug mode.
+        if let Some(notice) = self.debug_notice(code) {
+            self.unflushed += context.stream.send(&message).await?;
+            message = notice.message()?;
+            self.stats.sent(message.len());
+        }
+
         if flush {
             context.stream.send_flush(&message).await?;
+            self.unflushed = 0;
         } else {
-            context.stream.send(&message).await?;
+            self.unflushed += context.stream.send(&message).await?;
         }
 
         Ok(())
@@ -0,0 +1,216 @@
+//! Read-your-writes consistency.
+//!
+//! With `read_your_writes = "sticky"`, reads go to the primary for a while after
+//! the client writes something. With `read_your_writes = "lsn"`, we record the
+//! primary's WAL position after each write and send reads only to replicas that
+//! replayed it, or to the primary if none have.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use tracing::{debug, warn};
+
+use crate::{
+    backend::pool::{pool_impl::parse_pg_lsn, Request},
+    config::ReadYourWrites,
+    net::{DataRow, Format, FromBytes, ToBytes},
+};
+
+use super::*;
+
+/// Appended to simple protocol COMMITs to fetch the LSN in the same round trip.
+const WAL_INSERT_LSN: &str = "SELECT pg_current_wal_insert_lsn()";
+
+/// Client's writes.
+#[derive(Debug, Default)]
+pub struct Writes {
+    /// Shards of the primaries we're connected to.
+    primary: Option<Shard>,
+    /// Transaction wrote to the primary.
+    pending: bool,
+    /// Last write finished at.
+    last_write: Option<Instant>,
+    /// WAL position after the last write, by shard.
+    lsns: HashMap<usize, u64>,
+    /// Reading the LSN sent with COMMIT.
+    capture: Capture,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+enum Capture {
+    #[default]
+    Off,
+    /// Waiting for COMMIT to complete.
+    Commit,
+    /// Reading the LSN that follows it.
+    Lsn,
+}
+
+impl Writes {
+    /// WAL position after the client's last write on this shard.
+    pub fn lsn(&self, shard: usize) -> Option<u64> {
+        self.lsns.get(&shard).copied()
+    }
+
+    /// Client wrote something less than `window` ago.
+    fn recent(&self, window: Duration) -> bool {
+        self.last_write
+            .map(|last_write| last_write.elapsed() < window)
+            .unwrap_or(false)
+    }
+}
+
+impl QueryEngine {
+    /// Send reads to the primary if replicas may not have the client's writes yet.
+    pub(super) fn read_your_writes_route(
+        &self,
+        context: &QueryEngineContext<'_>,
+        route: &Route,
+        request: &mut Request,
+    ) -> Route {
+        if route.is_write() {
+            return route.clone();
+        }
+
+        let primary = match context.read_your_writes {
+            ReadYourWrites::Off => false,
+            ReadYourWrites::Sticky => self.writes.recent(context.read_your_writes_window),
+            ReadYourWrites::Lsn => match route.shard() {
+                Shard::Direct(shard) => {
+                    request.replay_lsn = self.writes.lsn(*shard);
+                    false
+                }
+                // LSNs of different primaries can't be compared,
+                // so these reads stick to the primaries instead.
+                _ => self.writes.recent(context.read_your_writes_window),
+            },
+        };
+
+        if primary {
+            debug!("reading from primary after write");
+            route.clone().set_read(false)
+        } else {
+            route.clone()
+        }
+    }
+
+    /// Connected to servers for this route.
+    pub(super) fn writes_connected(&mut self, route: &Route) {
+        self.writes.primary = route.is_write().then(|| route.shard().clone());
+    }
+
+    /// Statement is about to run on the connected servers.
+    pub(super) fn writes_execute(&mut self, context: &QueryEngineContext<'_>, route: &Route) {
+        if context.read_your_writes != ReadYourWrites::Off
+            && route.is_write()
+            && self.writes.primary.is_some()
+        {
+            self.writes.pending = true;
+        }
+    }
+
+    /// Fetch the LSN with COMMIT, saving a round trip later.
+    pub(super) fn writes_commit(
+        &mut self,
+        context: &mut QueryEngineContext<'_>,
+    ) -> Result<(), Error> {
+        if context.read_your_writes != ReadYourWrites::Lsn
+            || !self.writes.pending
+            || !matches!(self.writes.primary, Some(Shard::Direct(_)))
+            || !context.client_request.single_statement()
+        {
+            return Ok(());
+        }
+
+        if let Some(BufferedQuery::Query(query)) = context.client_request.query()? {
+            let query = format!(
+                "{}; {}",
+                query.query().trim_end().trim_end_matches(';'),
+                WAL_INSERT_LSN
+            );
+            context.client_request.rewrite(&query)?;
+            self.writes.capture = Capture::Commit;
+        }
+
+        Ok(())
+    }
+
+    /// Read the LSN sent after COMMIT. Returns true if the message
+    /// is part of its result and shouldn't be sent to the client.
+    pub(super) fn writes_capture(&mut self, code: char, message: &Message) -> Result<bool, Error> {
+        match (self.writes.capture, code) {
+            (Capture::Off, _) => Ok(false),
+
+            (_, 'Z') => {
+                self.writes.capture = Capture::Off;
+                Ok(false)
+            }
+
+            (Capture::Commit, 'C') => {
+                self.writes.capture = Capture::Lsn;
+                Ok(false)
+            }
+
+            // COMMIT failed and the server skipped the rest.
+            (Capture::Commit, 'E') => {
+                self.writes.capture = Capture::Off;
+                Ok(false)
+            }
+
+            (Capture::Lsn, 'D') => {
+                let row = DataRow::from_bytes(message.to_bytes()?)?;
+                let lsn = row
+                    .get::<String>(0, Format::Text)
+                    .and_then(|lsn| parse_pg_lsn(&lsn).ok());
+
+                if let (Some(lsn), Some(Shard::Direct(shard))) = (lsn, &self.writes.primary) {
+                    debug!("write finished at LSN {} on shard {}", lsn, shard);
+                    self.writes.lsns.insert(*shard, lsn);
+                    self.writes.last_write = Some(Instant::now());
+                    self.writes.pending = false;
+                }
+
+                Ok(true)
+            }
+
+            (Capture::Lsn, 'T' | 'C' | 'E') => Ok(true),
+
+            _ => Ok(false),
+        }
+    }
+
+    /// Transaction finished, remember what the client wrote.
+    pub(super) async fn writes_done(
+        &mut self,
+        context: &QueryEngineContext<'_>,
+    ) -> Result<(), Error> {
+        if !self.writes.pending {
+            return Ok(());
+        }
+
+        self.writes.pending = false;
+        self.writes.last_write = Some(Instant::now());
+
+        match context.read_your_writes {
+            ReadYourWrites::Off | ReadYourWrites::Sticky => (),
+            ReadYourWrites::Lsn => {
+                let Some(shard) = self.writes.primary.clone() else {
+                    return Ok(());
+                };
+
+                match self.backend.wal_insert_lsn(&shard).await {
+                    Ok(lsns) => {
+                        for (shard, lsn) in lsns {
+                            debug!("write finished at LSN {} on shard {}", lsn, shard);
+                            self.writes.lsns.insert(shard, lsn);
+                        }
+                    }
+
+                    Err(err) => warn!("failed to fetch WAL LSN after write: {}", err),
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -1,4 +1,12 @@
-use crate::net::{EmptyQueryResponse, ReadyForQuery};
+use crate::{
+    config::config,
+    frontend::{
+        router::{Error as RouterError, RoutingDecision},
+        QueryLogger,
+    },
+    net::{EmptyQueryResponse, NoticeResponse},
+};
+use pgdog_plugin::pg_query::fingerprint;
 use tracing::{error, trace};
 
 use super::*;
@@ -20,35 +28,71 @@ impl QueryEngine {
             return Ok(true);
         };
 
-        let router_context = RouterContext::new(
+        // Replication stream, e.g. standby status updates.
+        // Keep sending them to the server that's streaming.
+        if self.streaming {
+            return Ok(true);
+        }
+
+        let mut router_context = RouterContext::new(
             context.client_request,
             cluster,
             context.prepared_statements,
             context.params,
-            context.transaction,
+            context.transaction_type(),
         )?;
+        router_context.client_id = Some(self.client_id);
         match self.router.query(router_context) {
             Ok(cmd) => {
                 trace!("routing {:#?} to {:#?}", context.client_request, cmd);
+                let degraded = cmd.route().degraded();
+
+                if let Some(ref shard) = context.shard_override {
+                    self.router.override_shard(shard);
+                }
+
+                self.record_decision(context, None).await?;
+
+                if degraded {
+                    let notice = NoticeResponse::from(ErrorResponse::degraded_read());
+                    let bytes_sent = context.stream.send(&notice).await?;
+                    self.stats.sent(bytes_sent);
+                }
             }
             Err(err) => {
+                RouterStats::get().error(cluster.user(), cluster.name(), ErrorClass::new(&err));
+                self.record_decision(context, Some(&err)).await?;
+
                 if err.empty_query() {
                     let mut bytes_sent = context.stream.send(&EmptyQueryResponse).await?;
                     bytes_sent += context
                         .stream
-                        .send_flush(&ReadyForQuery::in_transaction(context.in_transaction()))
+                        .send_flush(&context.ready_for_query())
                         .await?;
                     self.stats.sent(bytes_sent);
-                } else {
-                    error!("{:?} [{:?}]", err, context.stream.peer_addr());
+                } else if err.read_only() {
                     let bytes_sent = context
-                        .stream
-                        .error(
-                            ErrorResponse::syntax(err.to_string().as_str()),
-                            context.in_transaction(),
-                        )
+                        .error(ErrorResponse::read_only(err.to_string().as_str()))
+                        .await?;
+                    self.stats.sent(bytes_sent);
+                } else if err.in_transaction() {
+                    let bytes_sent = context
+                        .error(ErrorResponse::active_transaction(err.to_string().as_str()))
                         .await?;
                     self.stats.sent(bytes_sent);
+                } else if err.blocked() {
+                    let bytes_sent = context
+                        .error(ErrorResponse::blocked(err.to_string().as_str()))
+                        .await?;
+                    self.stats.sent(bytes_sent);
+                } else {
+                    error!("{:?} [{:?}]", err, context.stream.peer_addr());
+                    let mut error = ErrorResponse::syntax(err.to_string().as_str());
+                    if err.routing() {
+                        error = self.routing_context(error);
+                    }
+                    let bytes_sent = context.error(error).await?;
+                    self.stats.sent(bytes_sent);
                 }
                 return Ok(false);
             }
@@ -56,4 +100,79 @@ impl QueryEngine {
 
         Ok(true)
     }
+
+    /// Record the routing decision in the client's routing history
+    /// and the query log, if they are enabled.
+    async fn record_decision(
+        &mut self,
+        context: &QueryEngineContext<'_>,
+        error: Option<&RouterError>,
+    ) -> Result<(), Error> {
+        let query_log = config().config.general.query_log.is_some();
+        if !self.routing_history.enabled() && !query_log {
+            return Ok(());
+        }
+
+        let Some(query) = context.client_request.query()? else {
+            return Ok(());
+        };
+        // The router already fingerprinted the query in dry run mode or to find a manual query.
+        let fingerprint = match self.router.fingerprint() {
+            Some(fingerprint) => fingerprint.to_owned(),
+            None => fingerprint(query.query())
+                .map(|fingerprint| fingerprint.hex)
+                .unwrap_or_default(),
+        };
+
+        let mut inputs = self.router.inputs().clone();
+        inputs.session_shard = context.shard_override.clone();
+
+        let decision = match error {
+            Some(error) => RoutingDecision::failed(&fingerprint, &inputs, error),
+            None => RoutingDecision::new(&fingerprint, self.router.command(), &inputs),
+        };
+
+        if query_log {
+            // Query answered without a server, e.g. SET.
+            self.log_query().await?;
+
+            match error {
+                // Query won't run.
+                Some(_) => {
+                    QueryLogger::new(query.query())
+                        .decision(&decision)
+                        .log()
+                        .await?
+                }
+                // Logged once it finishes, with the rows each shard returned.
+                None => self.query_log = Some((query.query().to_string(), decision.clone())),
+            }
+        }
+
+        self.routing_history.record(decision);
+
+        Ok(())
+    }
+
+    /// Write the query that just finished to the query log.
+    pub(super) async fn log_query(&mut self) -> Result<(), Error> {
+        if let Some((query, decision)) = self.query_log.take() {
+            let shard_rows = self.backend.shard_rows();
+            QueryLogger::new(&query)
+                .decision(&decision)
+                .shard_rows(&shard_rows)
+                .log()
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Add the last routing decision to an error, if the routing history is enabled.
+    pub(super) fn routing_context(&self, mut error: ErrorResponse) -> ErrorResponse {
+        if let Some(decision) = self.routing_history.last() {
+            error.context = Some(format!("routing decision: {}", decision.json()));
+        }
+        error
+    }
 }
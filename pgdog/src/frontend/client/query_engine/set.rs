@@ -1,4 +1,4 @@
-use crate::net::{parameter::ParameterValue, CommandComplete, Protocol, ReadyForQuery};
+use crate::net::{parameter::ParameterValue, CommandComplete, Protocol};
 
 use super::*;
 
@@ -16,7 +16,7 @@ impl QueryEngine {
             .stream
             .send_many(&vec![
                 CommandComplete::from_str("SET").message()?,
-                ReadyForQuery::in_transaction(context.in_transaction()).message()?,
+                context.ready_for_query().message()?,
             ])
             .await?;
 
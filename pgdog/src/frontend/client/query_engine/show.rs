@@ -0,0 +1,50 @@
+use crate::backend::pool::Setting;
+use crate::net::{CommandComplete, DataRow, Field, Protocol, RowDescription};
+
+use super::*;
+
+impl QueryEngine {
+    /// SHOW answered without a server, like Postgres would.
+    pub(super) async fn show(
+        &mut self,
+        context: &mut QueryEngineContext<'_>,
+        settings: &[Setting],
+        all: bool,
+    ) -> Result<(), Error> {
+        let mut messages = vec![];
+
+        if all {
+            messages.push(
+                RowDescription::new(&[
+                    Field::text("name"),
+                    Field::text("setting"),
+                    Field::text("description"),
+                ])
+                .message()?,
+            );
+            for setting in settings {
+                messages.push(
+                    DataRow::from_columns(vec![
+                        setting.name.as_str(),
+                        setting.setting.as_str(),
+                        setting.description.as_str(),
+                    ])
+                    .message()?,
+                );
+            }
+        } else {
+            for setting in settings {
+                messages.push(RowDescription::new(&[Field::text(&setting.name)]).message()?);
+                messages.push(DataRow::from_columns(vec![setting.setting.as_str()]).message()?);
+            }
+        }
+
+        messages.push(CommandComplete::from_str("SHOW").message()?);
+        messages.push(context.ready_for_query().message()?);
+
+        let bytes_sent = context.stream.send_many(&messages).await?;
+        self.stats.sent(bytes_sent);
+
+        Ok(())
+    }
+}
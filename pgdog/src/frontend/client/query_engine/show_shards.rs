@@ -1,4 +1,4 @@
-use crate::net::{CommandComplete, DataRow, Field, Protocol, ReadyForQuery, RowDescription};
+use crate::net::{CommandComplete, DataRow, Field, Protocol, RowDescription};
 
 use super::*;
 
@@ -15,7 +15,7 @@ impl QueryEngine {
                 RowDescription::new(&[Field::bigint("shards")]).message()?,
                 DataRow::from_columns(vec![shards]).message()?,
                 CommandComplete::from_str("SHOW").message()?,
-                ReadyForQuery::in_transaction(context.in_transaction()).message()?,
+                context.ready_for_query().message()?,
             ])
             .await?;
 
@@ -1,8 +1,11 @@
 use pg_query::protobuf::{a_const, node, Node, TransactionStmtKind};
 
 use crate::{
-    frontend::client::TransactionType,
-    net::{CommandComplete, Protocol, ReadyForQuery},
+    frontend::{
+        client::{TransactionStatus, TransactionType},
+        router::parser::TransactionOptions,
+    },
+    net::{CommandComplete, Protocol},
 };
 
 use super::*;
@@ -13,19 +16,51 @@ impl QueryEngine {
         &mut self,
         context: &mut QueryEngineContext<'_>,
         begin: BufferedQuery,
+        options: TransactionOptions,
     ) -> Result<(), Error> {
-        context.transaction = detect_transaction_type(&begin);
+        context.transaction = detect_transaction_type(&begin).into();
 
         let bytes_sent = context
             .stream
             .send_many(&[
                 CommandComplete::new_begin().message()?.backend(),
-                ReadyForQuery::in_transaction(context.in_transaction()).message()?,
+                context.ready_for_query().message()?,
+            ])
+            .await?;
+
+        self.stats.sent(bytes_sent);
+        self.begin_stmt = Some(options);
+
+        Ok(())
+    }
+
+    /// SET TRANSACTION issued before the transaction started on the server.
+    pub(super) async fn set_transaction(
+        &mut self,
+        context: &mut QueryEngineContext<'_>,
+        options: TransactionOptions,
+    ) -> Result<(), Error> {
+        if let Some(ref mut begin) = self.begin_stmt {
+            begin.merge(&options);
+
+            if let Some(read_only) = begin.read_only {
+                context.transaction = TransactionStatus::InTransaction(if read_only {
+                    TransactionType::ReadOnly
+                } else {
+                    TransactionType::ReadWrite
+                });
+            }
+        }
+
+        let bytes_sent = context
+            .stream
+            .send_many(&[
+                CommandComplete::from_str("SET").message()?,
+                context.ready_for_query().message()?,
             ])
             .await?;
 
         self.stats.sent(bytes_sent);
-        self.begin_stmt = Some(begin);
 
         Ok(())
     }
@@ -12,4 +12,8 @@ impl QueryEngine {
     pub fn stats(&mut self) -> &mut Stats {
         &mut self.stats
     }
+
+    pub fn writes(&self) -> &Writes {
+        &self.writes
+    }
 }
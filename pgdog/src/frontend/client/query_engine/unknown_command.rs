@@ -7,11 +7,10 @@ impl QueryEngine {
         command: Command,
     ) -> Result<(), Error> {
         let bytes_sent = context
-            .stream
-            .error(
-                ErrorResponse::syntax(&format!("unknown command: {:?}", command)),
-                context.in_transaction(),
-            )
+            .error(ErrorResponse::syntax(&format!(
+                "unknown command: {:?}",
+                command
+            )))
             .await?;
 
         self.stats.sent(bytes_sent);
@@ -1,30 +1,38 @@
 use std::time::{Duration, Instant};
 
 use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt, BufStream},
+    io::{AsyncReadExt, AsyncWriteExt},
     net::{TcpListener, TcpStream},
-    time::timeout,
+    time::{sleep, timeout},
 };
 
 use bytes::{Buf, BufMut, BytesMut};
 
 use crate::{
-    backend::databases::databases,
+    backend::{
+        databases::{databases, init},
+        pool::Request,
+        server::test::test_server,
+    },
     config::{
         config, set,
-        test::{load_test, load_test_replicas},
-        Role,
+        test::{load_test, load_test_replicas, load_test_sharded},
+        AuthType, HumanDuration, PoolerMode, ReadYourWrites, Role, ShardedTable,
     },
     frontend::{
         client::{BufferEvent, QueryEngine},
-        Client,
+        router::{parser::Shard, sharding::bigint},
+        session_pins::{SessionKey, SESSION_TAG},
+        Client, SessionPins,
     },
     net::{
-        bind::Parameter, Bind, Close, CommandComplete, DataRow, Describe, ErrorResponse, Execute,
-        Field, Flush, Format, FromBytes, Parse, Protocol, Query, ReadyForQuery, RowDescription,
-        Sync, Terminate, ToBytes,
+        bind::Parameter, replication::StatusUpdate, Bind, Close, CommandComplete, DataRow,
+        Describe, ErrorResponse, Execute, Field, Flush, Format, FromBytes, NoticeResponse, Parse,
+        Password, Protocol, ProtocolMessage, Query, ReadyForQuery, RowDescription, Sync, Terminate,
+        ToBytes,
     },
     state::State,
+    stats::memory::MemoryUsage,
 };
 
 use super::Stream;
@@ -52,8 +60,7 @@ pub async fn parallel_test_client() -> (TcpStream, Client) {
     let connect_handle = tokio::spawn(async move {
         let (stream, addr) = stream.accept().await.unwrap();
 
-        let stream = BufStream::new(stream);
-        let stream = Stream::Plain(stream);
+        let stream = Stream::plain(stream);
 
         Client::new_test(stream, addr)
     });
@@ -127,8 +134,8 @@ async fn test_test_client() {
     assert_eq!(client.client_request.total_message_len(), query.len());
 
     client.client_messages(&mut engine).await.unwrap();
-    assert!(client.transaction.is_none());
-    assert_eq!(engine.stats().state, State::Active);
+    assert!(!client.transaction.in_transaction());
+    assert_eq!(engine.stats().current.state, State::Active);
     // Buffer not cleared yet.
     assert_eq!(client.client_request.total_message_len(), query.len());
 
@@ -273,6 +280,48 @@ async fn test_client_extended() {
     handle.await.unwrap();
 }
 
+#[tokio::test]
+async fn test_anonymous_statement_reuse() {
+    let (mut conn, mut client, _) = new_client!(false);
+
+    let handle = tokio::spawn(async move {
+        client.run().await.unwrap();
+    });
+
+    for i in 0..100 {
+        let value = i.to_string();
+        conn.write_all(&buffer!(
+            { Parse::new_anonymous("SELECT $1::bigint") },
+            {
+                Bind::new_params(
+                    "",
+                    &[Parameter {
+                        len: value.len() as i32,
+                        data: value.as_bytes().to_vec(),
+                    }],
+                )
+            },
+            { Execute::new() },
+            { Sync }
+        ))
+        .await
+        .unwrap();
+
+        let messages = read!(conn, ['1', '2', 'D', 'C', 'Z']);
+        let row = DataRow::from_bytes(messages[2].clone().freeze()).unwrap();
+        assert_eq!(row.get::<i64>(0, Format::Text), Some(i));
+    }
+
+    conn.write_all(&buffer!({ Terminate })).await.unwrap();
+    handle.await.unwrap();
+
+    // Statement was prepared once per server connection, not once per execution.
+    let cluster = databases().cluster(("pgdog", "pgdog")).unwrap();
+    let state = cluster.shards()[0].pools()[0].state();
+    assert!(state.stats.counts.parse_count <= state.total);
+    assert!(state.stats.counts.bind_count >= 100);
+}
+
 #[tokio::test]
 async fn test_client_with_replicas() {
     crate::logger();
@@ -407,6 +456,64 @@ async fn test_abrupt_disconnect() {
     client.run().await.unwrap();
 }
 
+#[tokio::test]
+async fn test_abrupt_disconnect_session_rollback() {
+    crate::logger();
+    load_test();
+    let mut config = (*config()).clone();
+    config.config.general.pooler_mode = PoolerMode::Session;
+    set(config).unwrap();
+    init();
+
+    let (mut conn, mut client) = parallel_test_client().await;
+    let mut engine = QueryEngine::from_client(&client).unwrap();
+
+    conn.write_all(&buffer!({ Query::new("BEGIN") }, {
+        Query::new("SELECT 1/0")
+    }))
+    .await
+    .unwrap();
+
+    for _ in 0..2 {
+        client.buffer(State::Idle).await.unwrap();
+        client.client_messages(&mut engine).await.unwrap();
+    }
+
+    // Wait for the server to report the aborted transaction.
+    loop {
+        let msg = engine.backend().read().await.unwrap();
+        let aborted = msg.code() == 'Z' && msg.to_bytes().unwrap().last() == Some(&b'E');
+        client.server_message(&mut engine, msg).await.unwrap();
+
+        if aborted {
+            break;
+        }
+    }
+
+    // Client goes away in the middle of the transaction.
+    drop(conn);
+    let event = client.buffer(State::Idle).await.unwrap();
+    assert_eq!(event, BufferEvent::DisconnectAbrupt);
+    drop(engine);
+    drop(client);
+
+    sleep(Duration::from_millis(100)).await;
+
+    let cluster = databases().cluster(("pgdog", "pgdog")).unwrap();
+    let pool = cluster.shards()[0].pools()[0].clone();
+    let state = pool.state();
+    assert_eq!(state.stats.counts.rollbacks, 1);
+    assert_eq!(state.checked_out, 0);
+    assert_eq!(state.out_of_sync, 0);
+    assert_eq!(state.errors, 0);
+    assert!(state.idle > 0);
+
+    // Connection was rolled back and returned to the pool.
+    let server = pool.get(&Request::default()).await.unwrap();
+    assert!(!server.in_transaction());
+    assert!(server.can_check_in());
+}
+
 #[tokio::test]
 async fn test_lock_session() {
     let (mut conn, mut client, mut engine) = new_client!(true);
@@ -434,6 +541,18 @@ async fn test_lock_session() {
     assert!(!engine.backend().done());
     assert!(client.params.contains_key("application_name"));
 
+    let locked = || {
+        let cluster = databases().cluster(("pgdog", "pgdog")).unwrap();
+        cluster.shards()[0]
+            .pools()
+            .iter()
+            .map(|pool| pool.state().locked)
+            .sum::<usize>()
+    };
+    // Locked connections aren't idle, so they won't be
+    // healthchecked or closed by pool maintenance.
+    assert_eq!(locked(), 1);
+
     engine.backend().disconnect();
 }
 
@@ -449,7 +568,7 @@ async fn test_transaction_state() {
     client.client_messages(&mut engine).await.unwrap();
     read!(conn, ['C', 'Z']);
 
-    assert!(client.transaction.is_some());
+    assert!(client.transaction.in_transaction());
     assert!(engine.router().route().is_write());
     assert!(engine.router().in_transaction());
 
@@ -465,7 +584,7 @@ async fn test_transaction_state() {
     client.client_messages(&mut engine).await.unwrap();
 
     assert!(engine.router().routed());
-    assert!(client.transaction.is_some());
+    assert!(client.transaction.in_transaction());
     assert!(engine.router().route().is_write());
     assert!(engine.router().in_transaction());
 
@@ -509,7 +628,7 @@ async fn test_transaction_state() {
     read!(conn, ['2', 'D', 'C', 'Z']);
 
     assert!(engine.router().routed());
-    assert!(client.transaction.is_some());
+    assert!(client.transaction.in_transaction());
     assert!(engine.router().route().is_write());
     assert!(engine.router().in_transaction());
 
@@ -529,10 +648,87 @@ async fn test_transaction_state() {
 
     read!(conn, ['C', 'Z']);
 
-    assert!(client.transaction.is_none());
+    assert!(!client.transaction.in_transaction());
     assert!(!engine.router().routed());
 }
 
+#[tokio::test]
+async fn test_begin_options() {
+    let (mut conn, mut client, mut engine) = new_client!(true);
+
+    macro_rules! show {
+        ($name:expr) => {{
+            conn.write_all(&buffer!({ Query::new(format!("SHOW {}", $name)) }))
+                .await
+                .unwrap();
+            client.buffer(State::Idle).await.unwrap();
+            client.client_messages(&mut engine).await.unwrap();
+
+            for c in ['T', 'D', 'C', 'Z'] {
+                let msg = engine.backend().read().await.unwrap();
+                assert_eq!(msg.code(), c);
+                client.server_message(&mut engine, msg).await.unwrap();
+            }
+
+            let messages = read!(conn, ['T', 'D', 'C', 'Z']);
+            DataRow::from_bytes(messages[1].clone().freeze())
+                .unwrap()
+                .get::<String>(0, Format::Text)
+                .unwrap()
+        }};
+    }
+
+    macro_rules! intercepted {
+        ($query:expr) => {{
+            conn.write_all(&buffer!({ Query::new($query) }))
+                .await
+                .unwrap();
+            client.buffer(State::Idle).await.unwrap();
+            client.client_messages(&mut engine).await.unwrap();
+            read!(conn, ['C', 'Z']);
+            assert!(!engine.backend().connected());
+        }};
+    }
+
+    macro_rules! commit {
+        () => {{
+            conn.write_all(&buffer!({ Query::new("COMMIT") }))
+                .await
+                .unwrap();
+            client.buffer(State::Idle).await.unwrap();
+            client.client_messages(&mut engine).await.unwrap();
+
+            for c in ['C', 'Z'] {
+                let msg = engine.backend().read().await.unwrap();
+                assert_eq!(msg.code(), c);
+                client.server_message(&mut engine, msg).await.unwrap();
+            }
+
+            read!(conn, ['C', 'Z']);
+            assert!(!client.transaction.in_transaction());
+        }};
+    }
+
+    intercepted!("BEGIN ISOLATION LEVEL SERIALIZABLE");
+    assert_eq!(show!("transaction_isolation"), "serializable");
+    commit!();
+
+    // SET TRANSACTION before the first statement is merged into BEGIN.
+    intercepted!("BEGIN READ ONLY");
+    intercepted!("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ");
+    intercepted!("SET TRANSACTION DEFERRABLE");
+    assert_eq!(show!("transaction_isolation"), "repeatable read");
+    assert_eq!(show!("transaction_read_only"), "on");
+    assert_eq!(show!("transaction_deferrable"), "on");
+    commit!();
+
+    // Default options are not carried over to the next transaction.
+    intercepted!("BEGIN");
+    assert_eq!(show!("transaction_isolation"), "read committed");
+    assert_eq!(show!("transaction_read_only"), "off");
+    commit!();
+}
+
 #[tokio::test]
 async fn test_close_parse() {
     let (mut conn, mut client, mut engine) = new_client!(true);
@@ -582,7 +778,7 @@ async fn test_client_idle_timeout() {
     let (mut conn, mut client, _inner) = new_client!(false);
 
     let mut config = (*config()).clone();
-    config.config.general.client_idle_timeout = 25;
+    config.config.general.client_idle_timeout = HumanDuration::from_millis(25);
     set(config).unwrap();
 
     let start = Instant::now();
@@ -602,6 +798,229 @@ async fn test_client_idle_timeout() {
     .is_err());
 }
 
+#[tokio::test]
+async fn test_session_pin() {
+    crate::logger();
+    load_test_sharded();
+    let mut config = (*config()).clone();
+    config.config.general.pooler_mode = PoolerMode::Session;
+    config.config.general.session_pin_ttl = Some(HumanDuration::from_secs(60));
+    set(config).unwrap();
+    init();
+
+    // Session builds its work on shard 1.
+    let (mut conn, mut client) = parallel_test_client().await;
+    let mut engine = QueryEngine::from_client(&client).unwrap();
+
+    conn.write_all(&buffer!(
+        { Query::new("SET pgdog.session_tag TO 'test_session_pin'") },
+        { Query::new("/* pgdog_shard: 1 */ SELECT 1") }
+    ))
+    .await
+    .unwrap();
+
+    for _ in 0..2 {
+        client.buffer(State::Idle).await.unwrap();
+        client.client_messages(&mut engine).await.unwrap();
+    }
+    assert_eq!(client.client_request.route.shard(), &Shard::Direct(1));
+    read!(conn, ['C', 'Z', 'T', 'D', 'C', 'Z']);
+
+    let key = SessionKey::from_params(&client.params).unwrap();
+    assert_eq!(SessionPins::global().get(&key), None);
+
+    // Connection drops.
+    drop(conn);
+    drop(engine);
+    drop(client);
+    assert_eq!(SessionPins::global().get(&key), Some(1));
+
+    // Client reconnects with the same tag and goes back to shard 1.
+    let (mut conn, mut client) = parallel_test_client().await;
+    let mut engine = QueryEngine::from_client(&client).unwrap();
+
+    conn.write_all(&buffer!(
+        { Query::new("SET pgdog.session_tag TO 'test_session_pin'") },
+        { Query::new("SELECT 1") }
+    ))
+    .await
+    .unwrap();
+
+    for _ in 0..2 {
+        client.buffer(State::Idle).await.unwrap();
+        client.client_messages(&mut engine).await.unwrap();
+    }
+    assert_eq!(client.client_request.route.shard(), &Shard::Direct(1));
+    assert_eq!(engine.session_shard(), Some(1));
+    read!(conn, ['C', 'Z', 'T', 'D', 'C', 'Z']);
+
+    // Other sessions aren't pinned.
+    let (_conn, mut client) = parallel_test_client().await;
+    client.params.insert(SESSION_TAG, "test_session_pin_other");
+    assert!(client.session_pin().is_none());
+}
+
+#[tokio::test]
+async fn test_set_role_reset() {
+    crate::logger();
+    load_test();
+    let mut config = (*config()).clone();
+    config.users.users[0].pool_size = Some(1);
+    set(config).unwrap();
+    init();
+
+    macro_rules! send {
+        ($conn:expr, $client:expr, $engine:expr, $query:expr, $codes:expr) => {{
+            $conn
+                .write_all(&buffer!({ Query::new($query) }))
+                .await
+                .unwrap();
+            $client.buffer(State::Idle).await.unwrap();
+            $client.client_messages(&mut $engine).await.unwrap();
+
+            for c in $codes {
+                let msg = $engine.backend().read().await.unwrap();
+                assert_eq!(msg.code(), c);
+                $client.server_message(&mut $engine, msg).await.unwrap();
+            }
+
+            read!($conn, $codes)
+        }};
+    }
+
+    // Client changes the role inside a transaction, bypassing parameter tracking.
+    let (mut conn, mut client) = parallel_test_client().await;
+    let mut engine = QueryEngine::from_client(&client).unwrap();
+
+    conn.write_all(&buffer!({ Query::new("BEGIN") }))
+        .await
+        .unwrap();
+    client.buffer(State::Idle).await.unwrap();
+    client.client_messages(&mut engine).await.unwrap();
+    read!(conn, ['C', 'Z']);
+
+    send!(conn, client, engine, "SET ROLE pgdog1", ['C', 'Z']);
+    let messages = send!(
+        conn,
+        client,
+        engine,
+        "SELECT current_user",
+        ['T', 'D', 'C', 'Z']
+    );
+    let user = DataRow::from_bytes(messages[1].clone().freeze())
+        .unwrap()
+        .get::<String>(0, Format::Text)
+        .unwrap();
+    assert_eq!(user, "pgdog1");
+    send!(conn, client, engine, "COMMIT", ['C', 'Z']);
+    assert!(!engine.backend().connected());
+
+    // Next client gets the same server connection, with the role reset.
+    let (mut conn, mut client) = parallel_test_client().await;
+    let mut engine = QueryEngine::from_client(&client).unwrap();
+
+    let messages = send!(
+        conn,
+        client,
+        engine,
+        "SELECT current_user",
+        ['T', 'D', 'C', 'Z']
+    );
+    let user = DataRow::from_bytes(messages[1].clone().freeze())
+        .unwrap()
+        .get::<String>(0, Format::Text)
+        .unwrap();
+    assert_eq!(user, "pgdog");
+}
+
+#[tokio::test]
+async fn test_read_your_writes_lsn() {
+    crate::logger();
+    load_test();
+    let mut config = (*config()).clone();
+    config.config.general.read_your_writes = ReadYourWrites::Lsn;
+    set(config).unwrap();
+    init();
+
+    let (mut conn, mut client) = parallel_test_client().await;
+    let mut engine = QueryEngine::from_client(&client).unwrap();
+
+    conn.write_all(&buffer!({ Query::new("BEGIN") }))
+        .await
+        .unwrap();
+    client.buffer(State::Idle).await.unwrap();
+    client.client_messages(&mut engine).await.unwrap();
+    read!(conn, ['C', 'Z']);
+
+    for query in ["SELECT 1", "COMMIT"] {
+        conn.write_all(&buffer!({ Query::new(query) }))
+            .await
+            .unwrap();
+        client.buffer(State::Idle).await.unwrap();
+        client.client_messages(&mut engine).await.unwrap();
+
+        loop {
+            let msg = engine.backend().read().await.unwrap();
+            let code = msg.code();
+            client.server_message(&mut engine, msg).await.unwrap();
+            if code == 'Z' {
+                break;
+            }
+        }
+    }
+
+    // LSN was fetched with COMMIT and the client didn't see it.
+    read!(conn, ['T', 'D', 'C', 'Z', 'C', 'Z']);
+    assert!(engine.writes().lsn(0).unwrap() > 0);
+    assert!(!engine.backend().connected());
+}
+
+#[tokio::test]
+async fn test_replication_client_timeout() {
+    let (mut conn, mut client, _inner) = new_client!(false);
+
+    let mut config = (*config()).clone();
+    config.config.general.replication_client_timeout = HumanDuration::from_millis(50);
+    set(config).unwrap();
+
+    // Server started streaming replication data to the client.
+    client.streaming = true;
+    client.last_message = Instant::now();
+
+    let status_update = StatusUpdate {
+        last_written: 3,
+        last_flushed: 2,
+        last_applied: 1,
+        system_clock: 0,
+        reply: 0,
+    };
+    conn.write_all(&status_update.wrapped().unwrap().to_bytes().unwrap())
+        .await
+        .unwrap();
+
+    let res = client.buffer(State::Active).await.unwrap();
+    assert_eq!(res, BufferEvent::HaveRequest);
+    assert_eq!(
+        client.client_request.status_update().unwrap().last_flushed,
+        2
+    );
+
+    // Subscriber went silent.
+    let start = Instant::now();
+    let res = client.buffer(State::Active).await.unwrap();
+    assert_eq!(res, BufferEvent::ReplicationTimeout);
+    assert!(client.last_message.elapsed() >= Duration::from_millis(50));
+    assert!(start.elapsed() < Duration::from_secs(1));
+
+    // Regular clients aren't affected.
+    client.streaming = false;
+    assert!(
+        timeout(Duration::from_millis(100), client.buffer(State::Active))
+            .await
+            .is_err()
+    );
+}
+
 #[tokio::test]
 async fn test_prepared_syntax_error() {
     let (mut conn, mut client, mut engine) = new_client!(false);
@@ -631,3 +1050,1505 @@ async fn test_prepared_syntax_error() {
 
     assert_eq!(stmts.lock().statements().iter().next().unwrap().1.used, 0);
 }
+
+#[tokio::test]
+async fn test_require_tls() {
+    crate::logger();
+    load_test();
+    let mut config = (*config()).clone();
+    for database in config.config.databases.iter_mut() {
+        database.require_tls = true;
+    }
+    set(config).unwrap();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let handle = tokio::spawn(async move {
+        let (stream, addr) = listener.accept().await.unwrap();
+        let stream = Stream::plain(stream);
+        let mut params = crate::net::Parameters::default();
+        params.insert("user", "pgdog");
+        params.insert("database", "pgdog");
+
+        Client::spawn(stream, params, addr, crate::frontend::comms::comms()).await
+    });
+
+    let mut conn = TcpStream::connect(&format!("127.0.0.1:{}", port))
+        .await
+        .unwrap();
+
+    let error = read_one!(conn);
+    assert_eq!(error[0] as char, 'E');
+    let error = ErrorResponse::from_bytes(error.freeze()).unwrap();
+    assert_eq!(error.code, "28000");
+    assert_eq!(error.severity, "FATAL");
+    assert!(error.hint.unwrap().contains("sslmode=require"));
+
+    let terminate = read_one!(conn);
+    assert_eq!(terminate[0] as char, 'X');
+
+    handle.await.unwrap().unwrap();
+}
+
+/// Connect with [`Client::spawn`] pretending to be `addr`.
+async fn spawn_audited(
+    user: &'static str,
+    database: &'static str,
+    addr: &'static str,
+) -> (
+    TcpStream,
+    tokio::task::JoinHandle<Result<(), crate::frontend::Error>>,
+) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let handle = tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let stream = Stream::plain(stream);
+        let mut params = crate::net::Parameters::default();
+        params.insert("user", user);
+        params.insert("database", database);
+
+        Client::spawn(
+            stream,
+            params,
+            addr.parse().unwrap(),
+            crate::frontend::comms::comms(),
+        )
+        .await
+    });
+
+    let conn = TcpStream::connect(&format!("127.0.0.1:{}", port))
+        .await
+        .unwrap();
+
+    (conn, handle)
+}
+
+#[tokio::test]
+async fn test_auth_audit_log() {
+    crate::logger();
+    load_test();
+    let path = std::env::temp_dir().join(format!("pgdog_auth_audit_{}.log", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let mut config = (*config()).clone();
+    config.config.general.auth_audit_log = Some(path.clone());
+    config.config.general.auth_type = AuthType::Md5;
+    set(config.clone()).unwrap();
+
+    let last_event = || {
+        let log = std::fs::read_to_string(&path).unwrap();
+        assert!(!log.contains("pgdog_bad_password"));
+        serde_json::from_str::<serde_json::Value>(log.lines().last().unwrap()).unwrap()
+    };
+
+    let refused = |error: BytesMut| {
+        assert_eq!(error[0] as char, 'E');
+        let error = ErrorResponse::from_bytes(error.freeze()).unwrap();
+        assert_eq!(error.severity, "FATAL");
+    };
+
+    // Unknown database.
+    let (mut conn, handle) = spawn_audited("pgdog", "pgdog_missing", "10.1.0.1:1234").await;
+    refused(read_one!(conn));
+    handle.await.unwrap().unwrap();
+    let event = last_event();
+    assert_eq!(event["outcome"], "failure");
+    assert_eq!(event["reason"], "unknown_database");
+    assert_eq!(event["addr"], "10.1.0.1:1234");
+    assert_eq!(event["database"], "pgdog_missing");
+
+    // Unknown user.
+    let (mut conn, handle) = spawn_audited("pgdog_missing", "pgdog", "10.1.0.2:1234").await;
+    refused(read_one!(conn));
+    handle.await.unwrap().unwrap();
+    let event = last_event();
+    assert_eq!(event["reason"], "unknown_user");
+    assert_eq!(event["user"], "pgdog_missing");
+
+    // Bad password.
+    let (mut conn, handle) = spawn_audited("pgdog", "pgdog", "10.1.0.3:1234").await;
+    let challenge = read_one!(conn);
+    assert_eq!(challenge[0] as char, 'R');
+    conn.write_all(
+        &Password::new_password("pgdog_bad_password")
+            .to_bytes()
+            .unwrap(),
+    )
+    .await
+    .unwrap();
+    refused(read_one!(conn));
+    handle.await.unwrap().unwrap();
+    let event = last_event();
+    assert_eq!(event["reason"], "bad_password");
+    assert_eq!(event["method"], "md5");
+    assert_eq!(event["tls"], false);
+
+    // Repeated failures are suppressed and counted.
+    for _ in 0..2 {
+        let (mut conn, handle) = spawn_audited("pgdog", "pgdog_missing", "10.1.0.1:4321").await;
+        refused(read_one!(conn));
+        handle.await.unwrap().unwrap();
+    }
+    assert_eq!(last_event()["reason"], "bad_password");
+
+    // TLS required.
+    let mut tls = config.clone();
+    for database in tls.config.databases.iter_mut() {
+        database.require_tls = true;
+    }
+    set(tls).unwrap();
+    let (mut conn, handle) = spawn_audited("pgdog", "pgdog", "10.1.0.4:1234").await;
+    refused(read_one!(conn));
+    handle.await.unwrap().unwrap();
+    assert_eq!(last_event()["reason"], "tls_required");
+
+    // Success.
+    config.config.general.auth_type = AuthType::Trust;
+    set(config).unwrap();
+    let (mut conn, handle) = spawn_audited("pgdog", "pgdog", "10.1.0.5:1234").await;
+    loop {
+        let message = read_one!(conn);
+        assert_ne!(message[0] as char, 'E');
+        if message[0] as char == 'Z' {
+            break;
+        }
+    }
+    let event = last_event();
+    assert_eq!(event["outcome"], "success");
+    assert_eq!(event["method"], "trust");
+    assert!(event.get("reason").is_none());
+    conn.write_all(&Terminate.to_bytes().unwrap())
+        .await
+        .unwrap();
+    handle.await.unwrap().unwrap();
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn test_template_bad_password() {
+    crate::logger();
+    load_test();
+
+    let mut config = (*config()).clone();
+    config.config.general.auth_type = AuthType::Md5;
+    config.config.databases = vec![crate::config::Database {
+        name: "shards".into(),
+        name_pattern: Some("shard_*".into()),
+        host: "127.0.0.1".into(),
+        ..Default::default()
+    }];
+    config.users.users = vec![crate::config::User {
+        name: "pgdog".into(),
+        database: "shards".into(),
+        password: Some("pgdog".into()),
+        ..Default::default()
+    }];
+    set(config).unwrap();
+    init();
+
+    let (mut conn, handle) = spawn_audited("pgdog", "shard_0", "10.1.0.6:1234").await;
+    let challenge = read_one!(conn);
+    assert_eq!(challenge[0] as char, 'R');
+    conn.write_all(&Password::new_password("wrong").to_bytes().unwrap())
+        .await
+        .unwrap();
+    let error = read_one!(conn);
+    assert_eq!(error[0] as char, 'E');
+    handle.await.unwrap().unwrap();
+
+    // No pools for clients that didn't authenticate.
+    assert!(databases().instances().is_empty());
+    assert!(databases().cluster(("pgdog", "shard_0")).is_err());
+}
+
+#[tokio::test]
+async fn test_template_passthrough_auth() {
+    crate::logger();
+    load_test();
+
+    let mut config = (*config()).clone();
+    config.config.general.auth_type = AuthType::Md5;
+    config.config.general.passthrough_auth = crate::config::PassthoughAuth::EnabledPlain;
+    config.config.databases = vec![crate::config::Database {
+        name: "shards".into(),
+        name_pattern: Some("shard_*".into()),
+        host: "127.0.0.1".into(),
+        ..Default::default()
+    }];
+    config.users.users = vec![crate::config::User {
+        name: "pgdog".into(),
+        database: "shards".into(),
+        password: Some("pgdog".into()),
+        ..Default::default()
+    }];
+    set(config).unwrap();
+    init();
+
+    let (mut conn, handle) = spawn_audited("pgdog", "shard_0", "10.1.0.7:1234").await;
+    let challenge = read_one!(conn);
+    assert_eq!(challenge[0] as char, 'R');
+    let md5 = crate::auth::md5::Client::new_salt("pgdog", "pgdog", &challenge[9..13]).unwrap();
+    conn.write_all(&md5.response().to_bytes().unwrap())
+        .await
+        .unwrap();
+
+    // The template's password was checked, so passthrough doesn't ask for it again.
+    let ok = read_one!(conn);
+    assert_eq!(ok[0] as char, 'R');
+    assert_eq!(i32::from_be_bytes(ok[5..9].try_into().unwrap()), 0);
+
+    while read_one!(conn)[0] as char != 'Z' {}
+    conn.write_all(&Terminate.to_bytes().unwrap())
+        .await
+        .unwrap();
+    handle.await.unwrap().unwrap();
+
+    assert!(databases().cluster(("pgdog", "shard_0")).is_ok());
+}
+
+/// Run a query and count how many times the response was flushed to the client.
+async fn count_flushes(
+    conn: &mut TcpStream,
+    client: &mut Client,
+    engine: &mut QueryEngine,
+    query: &str,
+) -> usize {
+    conn.write_all(&buffer!({ Query::new(query) }))
+        .await
+        .unwrap();
+    client.buffer(State::Idle).await.unwrap();
+    client.client_messages(engine).await.unwrap();
+
+    let mut flushes = 0;
+    loop {
+        let message = engine.backend().read().await.unwrap();
+        let code = message.code();
+        client.server_message(engine, message).await.unwrap();
+
+        // Nothing is left unflushed only right after a flush.
+        if engine.unflushed() == 0 {
+            flushes += 1;
+        }
+
+        if code == 'Z' {
+            break;
+        }
+    }
+
+    // Everything reached the client.
+    loop {
+        let message = read_one!(conn);
+        if message[0] as char == 'Z' {
+            break;
+        }
+    }
+
+    flushes
+}
+
+#[tokio::test]
+async fn test_flush_threshold() {
+    let (mut conn, mut client, mut engine) = new_client!(false);
+    let rows = "SELECT id, 'row' AS name FROM generate_series(1, 100) id";
+
+    // Whole result in one write.
+    let flushes = count_flushes(&mut conn, &mut client, &mut engine, rows).await;
+    assert_eq!(flushes, 1);
+
+    // Single row responses are sent right away.
+    let flushes = count_flushes(&mut conn, &mut client, &mut engine, "SELECT 1").await;
+    assert_eq!(flushes, 1);
+
+    // Large results are flushed in batches.
+    let mut config = (*config()).clone();
+    config.config.general.flush_threshold = 256;
+    set(config).unwrap();
+
+    let flushes = count_flushes(&mut conn, &mut client, &mut engine, rows).await;
+    assert!(flushes > 5, "{}", flushes);
+    assert!(flushes < 100, "{}", flushes);
+}
+
+#[tokio::test]
+async fn test_slow_client_backpressure() {
+    let (mut conn, mut client, mut engine) = new_client!(false);
+
+    let write_buffer = 64 * 1024;
+    let mut config = (*config()).clone();
+    config.config.general.client_write_buffer = write_buffer;
+    set(config).unwrap();
+
+    conn.write_all(&buffer!({
+        Query::new("SELECT repeat('a', 100) FROM generate_series(1, 100000)")
+    }))
+    .await
+    .unwrap();
+
+    client.buffer(State::Idle).await.unwrap();
+    let baseline = client.memory_usage();
+    client.client_messages(&mut engine).await.unwrap();
+
+    // Slow reader, pausing between reads.
+    let reader = tokio::spawn(async move {
+        let mut rows = 0;
+        loop {
+            let msg = read_one!(conn);
+            match msg[0] as char {
+                'D' => {
+                    rows += 1;
+                    if rows % 10_000 == 0 {
+                        tokio::time::sleep(Duration::from_millis(10)).await;
+                    }
+                }
+                'Z' => break,
+                _ => (),
+            }
+        }
+        rows
+    });
+
+    loop {
+        let msg = engine.backend().read().await.unwrap();
+        let code = msg.code();
+        client.server_message(&mut engine, msg).await.unwrap();
+
+        // Never holding more than the configured buffer for the client.
+        assert!(engine.unflushed() < write_buffer);
+        assert!(client.memory_usage() <= baseline + write_buffer);
+
+        if code == 'Z' {
+            break;
+        }
+    }
+
+    assert_eq!(reader.await.unwrap(), 100_000);
+}
+
+#[tokio::test]
+async fn test_debug_notice() {
+    let (mut conn, mut client, _) = new_client!(true);
+
+    let handle = tokio::spawn(async move {
+        client.run().await.unwrap();
+    });
+
+    macro_rules! notice {
+        ($messages:expr) => {{
+            let notice = NoticeResponse::from_bytes($messages.clone().freeze()).unwrap();
+            assert_eq!(notice.message.severity(), "NOTICE");
+            notice.message.message
+        }};
+    }
+
+    // Off by default.
+    conn.write_all(&buffer!({ Query::new("SELECT 1") }))
+        .await
+        .unwrap();
+    read!(conn, ['T', 'D', 'C', 'Z']);
+
+    conn.write_all(&buffer!({ Query::new("SET pgdog.debug TO on") }))
+        .await
+        .unwrap();
+    read!(conn, ['C', 'Z']);
+
+    conn.write_all(&buffer!({ Query::new("SELECT 1") }))
+        .await
+        .unwrap();
+    let messages = read!(conn, ['T', 'D', 'C', 'N', 'Z']);
+    let notice = notice!(messages[3]);
+    assert!(notice.starts_with("pgdog debug: shard=0, role=replica, server=127.0.0.1:5432"));
+    assert!(notice.contains("checkout="));
+    assert!(notice.contains("execution="));
+    assert!(notice.ends_with("ast_cache=none"));
+
+    // Each statement gets its own notice.
+    conn.write_all(&buffer!({ Query::new("SELECT 1; SELECT 2") }))
+        .await
+        .unwrap();
+    let messages = read!(conn, ['T', 'D', 'C', 'N', 'T', 'D', 'C', 'N', 'Z']);
+    assert!(notice!(messages[3]).starts_with("pgdog debug:"));
+    assert!(notice!(messages[7]).starts_with("pgdog debug:"));
+
+    for ast_cache in ["miss", "hit"] {
+        conn.write_all(&buffer!(
+            { Parse::new_anonymous("SELECT $1::bigint AS test_debug_notice") },
+            {
+                Bind::new_params(
+                    "",
+                    &[Parameter {
+                        len: 1,
+                        data: "1".as_bytes().to_vec(),
+                    }],
+                )
+            },
+            { Execute::new() },
+            { Sync }
+        ))
+        .await
+        .unwrap();
+        let messages = read!(conn, ['1', '2', 'D', 'C', 'N', 'Z']);
+        assert!(notice!(messages[4]).ends_with(&format!("ast_cache={}", ast_cache)));
+    }
+
+    // No notices inside COPY.
+    conn.write_all(&buffer!({ Query::new("COPY (SELECT 1) TO STDOUT") }))
+        .await
+        .unwrap();
+    read!(conn, ['H', 'd', 'c', 'C', 'Z']);
+
+    // Extended protocol gets a full reply.
+    conn.write_all(&buffer!(
+        { Parse::new_anonymous("SET pgdog.debug TO off") },
+        { Bind::new_statement("") },
+        { Describe::new_portal("") },
+        { Execute::new() },
+        { Sync }
+    ))
+    .await
+    .unwrap();
+    read!(conn, ['1', '2', 'n', 'C', 'Z']);
+
+    conn.write_all(&buffer!({ Query::new("SELECT 1") }))
+        .await
+        .unwrap();
+    read!(conn, ['T', 'D', 'C', 'Z']);
+
+    conn.write_all(&buffer!({ Terminate })).await.unwrap();
+    handle.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_elide_single_statement_transactions() {
+    let (mut conn, mut client, _) = new_client!(false);
+
+    let mut config = (*config()).clone();
+    config.config.general.elide_single_statement_transactions = true;
+    set(config).unwrap();
+
+    let handle = tokio::spawn(async move {
+        client.run().await.unwrap();
+    });
+
+    // Transactions and queries the server ran.
+    let counts = || {
+        databases().cluster(("pgdog", "pgdog")).unwrap().shards()[0]
+            .pools()
+            .iter()
+            .map(|pool| pool.state().stats.counts)
+            .fold((0, 0), |(xact, query), counts| {
+                (xact + counts.xact_count, query + counts.query_count)
+            })
+    };
+
+    macro_rules! query {
+        ($query:expr, $codes:expr) => {{
+            conn.write_all(&buffer!({ Query::new($query) }))
+                .await
+                .unwrap();
+            let messages = read!(conn, $codes);
+            let rfq = ReadyForQuery::from_bytes(messages.last().unwrap().clone().freeze()).unwrap();
+            (messages, rfq.status)
+        }};
+    }
+
+    // Connection is healthchecked on first use.
+    query!("SELECT 1", ['T', 'D', 'C', 'Z']);
+
+    macro_rules! tag {
+        ($message:expr) => {
+            CommandComplete::from_bytes($message.clone().freeze())
+                .unwrap()
+                .command()
+                .to_string()
+        };
+    }
+
+    // Single read sent with BEGIN and COMMIT, which aren't sent to the server.
+    let (xact, queries) = counts();
+    let (messages, status) = query!("BEGIN; SELECT 1; COMMIT", ['C', 'T', 'D', 'C', 'C', 'Z']);
+    assert_eq!(status, 'I');
+    assert_eq!(tag!(messages[0]), "BEGIN");
+    assert_eq!(tag!(messages[4]), "COMMIT");
+    assert_eq!(counts(), (xact + 1, queries + 1));
+
+    // Any statement that isn't DDL, since nothing else follows.
+    let (messages, status) = query!("BEGIN; DO $$ BEGIN END $$; COMMIT", ['C', 'C', 'C', 'Z']);
+    assert_eq!(status, 'I');
+    assert_eq!(tag!(messages[0]), "BEGIN");
+    assert_eq!(tag!(messages[1]), "DO");
+    assert_eq!(tag!(messages[2]), "COMMIT");
+
+    // Single read sent in separate queries, BEGIN and COMMIT aren't sent to the server.
+    let (xact, queries) = counts();
+    assert_eq!(query!("BEGIN", ['C', 'Z']).1, 'T');
+    assert_eq!(query!("SELECT 1", ['T', 'D', 'C', 'Z']).1, 'T');
+    let (messages, status) = query!("COMMIT", ['C', 'Z']);
+    assert_eq!(status, 'I');
+    assert_eq!(tag!(messages[0]), "COMMIT");
+    assert_eq!(counts(), (xact + 1, queries + 1));
+
+    // Second statement starts the transaction for real.
+    let (xact, queries) = counts();
+    query!("BEGIN", ['C', 'Z']);
+    assert_eq!(query!("SELECT 1", ['T', 'D', 'C', 'Z']).1, 'T');
+    assert_eq!(query!("SELECT 2", ['T', 'D', 'C', 'Z']).1, 'T');
+    assert_eq!(query!("COMMIT", ['C', 'Z']).1, 'I');
+    // SELECT 1 ran on its own, SELECT 2 in BEGIN/COMMIT.
+    assert_eq!(counts(), (xact + 2, queries + 4));
+
+    // Writes could be followed by ROLLBACK, so they run in the transaction.
+    let (xact, queries) = counts();
+    query!("BEGIN", ['C', 'Z']);
+    assert_eq!(query!("DO $$ BEGIN END $$", ['C', 'Z']).1, 'T');
+    assert_eq!(query!("COMMIT", ['C', 'Z']).1, 'I');
+    assert_eq!(counts(), (xact + 1, queries + 3));
+
+    // Explicit locks aren't elided.
+    let (xact, queries) = counts();
+    query!("BEGIN", ['C', 'Z']);
+    query!("SELECT pg_advisory_xact_lock(1)", ['T', 'D', 'C', 'Z']);
+    assert_eq!(query!("COMMIT", ['C', 'Z']).1, 'I');
+    assert_eq!(counts(), (xact + 1, queries + 3));
+
+    // Errors in a statement sent alone abort the transaction too.
+    query!("BEGIN", ['C', 'Z']);
+    assert_eq!(query!("SELECT 1/0", ['E', 'Z']).1, 'E');
+    let (messages, status) = query!("SELECT 1", ['E', 'Z']);
+    assert_eq!(status, 'E');
+    let error = ErrorResponse::from_bytes(messages[0].clone().freeze()).unwrap();
+    assert_eq!(error.code, "25P02");
+    let (messages, status) = query!("ROLLBACK", ['C', 'Z']);
+    assert_eq!(status, 'I');
+    assert_eq!(tag!(messages[0]), "ROLLBACK");
+
+    // Transaction options and locks are kept, so the server runs the whole query.
+    for query in [
+        "BEGIN READ ONLY; SELECT 1; COMMIT",
+        "BEGIN ISOLATION LEVEL REPEATABLE READ; SELECT 1; COMMIT",
+        "BEGIN; SELECT pg_advisory_xact_lock(1); COMMIT",
+    ] {
+        let (messages, status) = query!(query, ['C', 'T', 'D', 'C', 'C', 'Z']);
+        assert_eq!(status, 'I');
+        assert_eq!(tag!(messages[0]), "BEGIN");
+        assert_eq!(tag!(messages[4]), "COMMIT");
+    }
+
+    // DDL runs in the transaction.
+    let (messages, status) = query!(
+        "BEGIN; CREATE TEMP TABLE elided (id BIGINT) ON COMMIT DROP; COMMIT",
+        ['C', 'C', 'C', 'Z']
+    );
+    assert_eq!(status, 'I');
+    assert_eq!(tag!(messages[1]), "CREATE TABLE");
+
+    // Errors abort the transaction.
+    let (xact, queries) = counts();
+    let (messages, status) = query!("BEGIN; SELECT 1/0; COMMIT", ['C', 'E', 'Z']);
+    assert_eq!(status, 'E');
+    assert_eq!(tag!(messages[0]), "BEGIN");
+    let (messages, status) = query!("SELECT 1", ['E', 'Z']);
+    assert_eq!(status, 'E');
+    let error = ErrorResponse::from_bytes(messages[0].clone().freeze()).unwrap();
+    assert_eq!(error.code, "25P02");
+    let (messages, status) = query!("COMMIT", ['C', 'Z']);
+    assert_eq!(status, 'I');
+    assert_eq!(tag!(messages[0]), "ROLLBACK");
+    assert_eq!(counts(), (xact + 1, queries + 1));
+
+    conn.write_all(&buffer!({ Terminate })).await.unwrap();
+    handle.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_skip_until_sync_after_error() {
+    let (mut conn, mut client, _) = new_client!(false);
+
+    let handle = tokio::spawn(async move {
+        client.run().await.unwrap();
+    });
+
+    // Whole batch at once: the server skips Bind and Execute itself.
+    conn.write_all(&buffer!(
+        { Parse::named("test", "SELECT sdfsf") },
+        { Bind::new_statement("test") },
+        { Execute::new() },
+        { Sync }
+    ))
+    .await
+    .unwrap();
+    let messages = read!(conn, ['E', 'Z']);
+    let rfq = ReadyForQuery::from_bytes(messages[1].clone().freeze()).unwrap();
+    assert_eq!(rfq.status, 'I');
+
+    // Same batch, flushed after each message. Postgres replies with
+    // ErrorResponse to Parse and nothing else until Sync.
+    conn.write_all(&buffer!({ Parse::named("test", "SELECT sdfsf") }, {
+        Flush
+    }))
+    .await
+    .unwrap();
+    read!(conn, ['E']);
+
+    conn.write_all(&buffer!({ Bind::new_statement("test") }, { Flush }))
+        .await
+        .unwrap();
+    conn.write_all(&buffer!({ Execute::new() }, { Flush }))
+        .await
+        .unwrap();
+    conn.write_all(&buffer!({ Sync })).await.unwrap();
+
+    // Only ReadyForQuery for the Sync.
+    let messages = read!(conn, ['Z']);
+    let rfq = ReadyForQuery::from_bytes(messages[0].clone().freeze()).unwrap();
+    assert_eq!(rfq.status, 'I');
+
+    // Next batch gets its own replies.
+    conn.write_all(&buffer!(
+        { Parse::new_anonymous("SELECT 1") },
+        { Bind::new_statement("") },
+        { Execute::new() },
+        { Sync }
+    ))
+    .await
+    .unwrap();
+    let messages = read!(conn, ['1', '2', 'D', 'C', 'Z']);
+    let rfq = ReadyForQuery::from_bytes(messages[4].clone().freeze()).unwrap();
+    assert_eq!(rfq.status, 'I');
+
+    conn.write_all(&buffer!({ Terminate })).await.unwrap();
+    handle.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_insert_split_between_shards() {
+    crate::logger();
+    load_test_sharded();
+    let mut config = (*config()).clone();
+    let mut shard = config.config.databases[0].clone();
+    shard.shard = 2;
+    config.config.databases.push(shard);
+    config.config.sharded_tables = vec![ShardedTable {
+        database: "pgdog".into(),
+        name: Some("test_insert_split".into()),
+        column: "id".into(),
+        ..Default::default()
+    }];
+    set(config).unwrap();
+    init();
+
+    let (mut conn, mut client) = parallel_test_client().await;
+    let handle = tokio::spawn(async move {
+        client.run().await.unwrap();
+    });
+
+    // Read until ReadyForQuery, returning the messages.
+    macro_rules! read_until_ready {
+        () => {{
+            let mut messages = vec![];
+            loop {
+                let message = read_one!(conn);
+                let code = message[0] as char;
+                messages.push(message);
+                if code == 'Z' {
+                    break messages;
+                }
+            }
+        }};
+    }
+
+    // All shards are the same database in tests.
+    conn.write_all(&buffer!(
+        { Query::new("/* pgdog_shard: 0 */ DROP TABLE IF EXISTS test_insert_split") },
+        {
+            Query::new(
+                "/* pgdog_shard: 0 */ CREATE TABLE test_insert_split (id BIGINT PRIMARY KEY, value TEXT)",
+            )
+        }
+    ))
+    .await
+    .unwrap();
+    read_until_ready!();
+    read_until_ready!();
+
+    let mut ids =
+        [0, 1, 2, 0].map(|shard| (1..).find(|id| bigint(*id) as usize % 3 == shard).unwrap());
+    // Two different rows on shard 0.
+    ids[3] = (ids[0] + 1..)
+        .find(|id| bigint(*id) as usize % 3 == 0)
+        .unwrap();
+
+    let returned = |messages: &[BytesMut]| {
+        let mut ids = messages
+            .iter()
+            .filter(|message| message[0] == b'D')
+            .map(|message| {
+                DataRow::from_bytes(message.clone().freeze())
+                    .unwrap()
+                    .get::<i64>(0, Format::Text)
+                    .unwrap()
+            })
+            .collect::<Vec<_>>();
+        ids.sort();
+        ids
+    };
+    let command = |messages: &[BytesMut]| {
+        let message = messages.iter().find(|message| message[0] == b'C').unwrap();
+        CommandComplete::from_bytes(message.clone().freeze())
+            .unwrap()
+            .command()
+            .to_owned()
+    };
+    let mut expected = ids.to_vec();
+    expected.sort();
+
+    // Simple protocol.
+    conn.write_all(&buffer!({
+        Query::new(format!(
+            "INSERT INTO test_insert_split (id, value) VALUES ({}, 'a'), ({}, 'b'), ({}, 'c'), ({}, 'd') RETURNING id",
+            ids[0], ids[1], ids[2], ids[3]
+        ))
+    }))
+    .await
+    .unwrap();
+    let messages = read!(conn, ['T', 'D', 'D', 'D', 'D', 'C', 'Z']);
+    assert_eq!(command(&messages), "INSERT 0 4");
+    assert_eq!(returned(&messages), expected);
+
+    let params = |offset: i64| {
+        ids.iter()
+            .flat_map(|id| [(id + offset).to_string(), "value".to_string()])
+            .map(|param| Parameter {
+                len: param.len() as i32,
+                data: param.into_bytes(),
+            })
+            .collect::<Vec<_>>()
+    };
+    let insert =
+        "INSERT INTO test_insert_split (id, value) VALUES ($1, $2), ($3, $4), ($5, $6), ($7, $8) \
+        ON CONFLICT (id) DO UPDATE SET value = excluded.value RETURNING id";
+
+    // Extended protocol, anonymous statement.
+    conn.write_all(&buffer!(
+        { Parse::new_anonymous(insert) },
+        { Bind::new_params("", &params(0)) },
+        { Describe::new_portal("") },
+        { Execute::new() },
+        { Sync }
+    ))
+    .await
+    .unwrap();
+    let messages = read!(conn, ['1', '2', 'T', 'D', 'D', 'D', 'D', 'C', 'Z']);
+    assert_eq!(command(&messages), "INSERT 0 4");
+    assert_eq!(returned(&messages), expected);
+
+    // Named statement, executed again without Parse.
+    conn.write_all(&buffer!(
+        { Parse::named("test_insert_split", insert) },
+        { Bind::new_params("test_insert_split", &params(0)) },
+        { Execute::new() },
+        { Sync }
+    ))
+    .await
+    .unwrap();
+    let messages = read!(conn, ['1', '2', 'D', 'D', 'D', 'D', 'C', 'Z']);
+    assert_eq!(command(&messages), "INSERT 0 4");
+
+    conn.write_all(&buffer!(
+        { Bind::new_params("test_insert_split", &params(0)) },
+        { Execute::new() },
+        { Sync }
+    ))
+    .await
+    .unwrap();
+    let messages = read!(conn, ['2', 'D', 'D', 'D', 'D', 'C', 'Z']);
+    assert_eq!(command(&messages), "INSERT 0 4");
+    assert_eq!(returned(&messages), expected);
+
+    // Parameters outside of the rows can't be split.
+    conn.write_all(&buffer!(
+        {
+            Parse::new_anonymous(
+                "WITH v AS (SELECT $5::text AS value) \
+                INSERT INTO test_insert_split (id, value) VALUES ($1, $2), ($3, $4)",
+            )
+        },
+        {
+            Bind::new_params(
+                "",
+                &params(0).into_iter().skip(2).take(5).collect::<Vec<_>>(),
+            )
+        },
+        { Execute::new() },
+        { Sync }
+    ))
+    .await
+    .unwrap();
+    let messages = read!(conn, ['E', 'Z']);
+    let error = ErrorResponse::from_bytes(messages[0].clone().freeze()).unwrap();
+    assert!(error.message.contains("$5"), "{}", error.message);
+
+    conn.write_all(&buffer!({
+        Query::new("/* pgdog_shard: 0 */ DROP TABLE test_insert_split")
+    }))
+    .await
+    .unwrap();
+    read_until_ready!();
+
+    conn.write_all(&buffer!({ Terminate })).await.unwrap();
+    handle.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_failed_transaction() {
+    let (mut conn, mut client, _) = new_client!(true);
+
+    let handle = tokio::spawn(async move {
+        client.run().await.unwrap();
+    });
+
+    let mut server = test_server().await;
+
+    // Same statements through PgDog and directly to Postgres.
+    let queries = [
+        ("BEGIN", ['C', 'Z']),
+        ("SELECT 1/0", ['E', 'Z']),
+        ("SET statement_timeout TO 1000", ['E', 'Z']),
+        ("SELECT 1", ['E', 'Z']),
+        ("DEALLOCATE ALL", ['E', 'Z']),
+        ("COMMIT", ['C', 'Z']),
+        ("SET statement_timeout TO 1000", ['C', 'Z']),
+    ];
+
+    for (query, codes) in queries {
+        conn.write_all(&buffer!({ Query::new(query) }))
+            .await
+            .unwrap();
+        let pgdog = read!(conn, codes);
+
+        server
+            .send(&vec![ProtocolMessage::from(Query::new(query))].into())
+            .await
+            .unwrap();
+        let mut postgres = vec![];
+        loop {
+            let message = server.read().await.unwrap();
+            let code = message.code();
+            postgres.push(message.to_bytes().unwrap());
+            if code == 'Z' {
+                break;
+            }
+        }
+
+        assert_eq!(pgdog.len(), postgres.len(), "{}", query);
+        for (pgdog, postgres) in pgdog.into_iter().zip(postgres) {
+            let pgdog = pgdog.freeze();
+            if pgdog[0] == b'E' {
+                // Source file and line are different.
+                let pgdog = ErrorResponse::from_bytes(pgdog).unwrap();
+                let postgres = ErrorResponse::from_bytes(postgres).unwrap();
+                assert_eq!(pgdog.code, postgres.code, "{}", query);
+                assert_eq!(pgdog.message, postgres.message, "{}", query);
+            } else {
+                assert_eq!(pgdog, postgres, "{}", query);
+            }
+        }
+    }
+
+    conn.write_all(&buffer!({ Terminate })).await.unwrap();
+    handle.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_read_only_user() {
+    crate::logger();
+    load_test_replicas();
+    let mut config = (*config()).clone();
+    config.users.users[0].read_only = Some(true);
+    set(config).unwrap();
+    init();
+
+    let (mut conn, mut client) = parallel_test_client().await;
+    let handle = tokio::spawn(async move {
+        client.run().await.unwrap();
+    });
+
+    let cluster = databases().cluster(("pgdog", "pgdog")).unwrap();
+    assert!(cluster.writes_disabled());
+
+    // Rejected before a connection is checked out.
+    conn.write_all(&buffer!({
+        Query::new("CREATE TABLE test_read_only_user (id BIGINT)")
+    }))
+    .await
+    .unwrap();
+    let messages = read!(conn, ['E', 'Z']);
+    let error = ErrorResponse::from_bytes(messages[0].clone().freeze()).unwrap();
+    assert_eq!(error.code, "25006");
+    let ready = ReadyForQuery::from_bytes(messages[1].clone().freeze()).unwrap();
+    assert_eq!(ready.status, 'I');
+
+    conn.write_all(&buffer!({ Query::new("SELECT 1") }))
+        .await
+        .unwrap();
+    read!(conn, ['T', 'D', 'C', 'Z']);
+
+    // Transactions can start, but the first write fails them.
+    for (query, codes, status) in [
+        ("BEGIN", vec!['C', 'Z'], 'T'),
+        (
+            "INSERT INTO test_read_only_user VALUES (1)",
+            vec!['E', 'Z'],
+            'E',
+        ),
+        ("SELECT 1", vec!['E', 'Z'], 'E'),
+        ("ROLLBACK", vec!['C', 'Z'], 'I'),
+    ] {
+        conn.write_all(&buffer!({ Query::new(query) }))
+            .await
+            .unwrap();
+        let messages = read!(conn, codes);
+        let ready = ReadyForQuery::from_bytes(messages.last().unwrap().clone().freeze()).unwrap();
+        assert_eq!(ready.status, status, "{}", query);
+    }
+
+    conn.write_all(&buffer!({ Terminate })).await.unwrap();
+    handle.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_show_local() {
+    let (mut conn, mut client, _) = new_client!(false);
+
+    let handle = tokio::spawn(async move {
+        client.run().await.unwrap();
+    });
+
+    let mut server = test_server().await;
+
+    macro_rules! pgdog {
+        ($query:expr) => {{
+            conn.write_all(&buffer!({ Query::new($query) }))
+                .await
+                .unwrap();
+            let mut messages = vec![];
+            loop {
+                let message = read_one!(conn).freeze();
+                let code = message[0] as char;
+                messages.push(message);
+                if code == 'Z' {
+                    break;
+                }
+            }
+            messages
+        }};
+    }
+
+    macro_rules! postgres {
+        ($query:expr) => {{
+            server
+                .execute($query)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|message| message.to_bytes().unwrap())
+                .collect::<Vec<_>>()
+        }};
+    }
+
+    // Pool captures server settings on its first connection.
+    pgdog!("SELECT 1");
+    let cluster = databases().cluster(("pgdog", "pgdog")).unwrap();
+    assert!(cluster.settings().is_some());
+    let pool = cluster.shards()[0].pools()[0].clone();
+    let assignments = pool.state().stats.counts.server_assignment_count;
+
+    let queries = [
+        "SHOW transaction_isolation",
+        "SHOW TRANSACTION ISOLATION LEVEL",
+        "SHOW standard_conforming_strings",
+        "SHOW TimeZone",
+        "SHOW DateStyle",
+        "SHOW IntervalStyle",
+        "SHOW server_version",
+        "SHOW server_encoding",
+        "SHOW client_encoding",
+        "SHOW integer_datetimes",
+        "SHOW is_superuser",
+        "SHOW max_connections",
+        "SHOW work_mem",
+        "SHOW statement_timeout",
+        "SHOW search_path",
+        "SHOW default_transaction_read_only",
+        "SHOW transaction_read_only",
+        // Changed by the client.
+        "SET default_transaction_isolation TO 'REPEATABLE READ'",
+        "SHOW transaction_isolation",
+        "SHOW default_transaction_isolation",
+        "SET standard_conforming_strings TO false",
+        "SHOW standard_conforming_strings",
+        "SET extra_float_digits TO 2",
+        "SHOW extra_float_digits",
+    ];
+
+    for query in queries {
+        let pgdog = pgdog!(query);
+        let postgres = postgres!(query);
+
+        // Postgres reports some settings with ParameterStatus.
+        if !query.starts_with("SET") {
+            assert_eq!(pgdog, postgres, "{}", query);
+        }
+    }
+
+    // SHOW ALL has the same settings, in the same order.
+    let pgdog = pgdog!("SHOW ALL");
+    let postgres = postgres!("SHOW ALL");
+    assert_eq!(pgdog.len(), postgres.len());
+    for (pgdog, postgres) in pgdog.into_iter().zip(postgres) {
+        if pgdog[0] == b'D' {
+            let pgdog = DataRow::from_bytes(pgdog).unwrap();
+            // Connections were opened by different programs.
+            if pgdog.get_text(0).as_deref() == Some("application_name") {
+                continue;
+            }
+            assert_eq!(pgdog, DataRow::from_bytes(postgres).unwrap());
+        } else {
+            assert_eq!(pgdog, postgres);
+        }
+    }
+
+    // Custom settings are shown the way the client set them.
+    pgdog!("SET pgdog_test.tenant TO 'one'");
+    let messages = pgdog!("SHOW pgdog_test.tenant");
+    let row = DataRow::from_bytes(messages[1].clone()).unwrap();
+    assert_eq!(row.get_text(0).as_deref(), Some("one"));
+
+    // Answered without a server.
+    assert_eq!(
+        pool.state().stats.counts.server_assignment_count,
+        assignments
+    );
+
+    // Settings can change inside transactions.
+    pgdog!("BEGIN");
+    pgdog!("SET LOCAL statement_timeout TO 1000");
+    let messages = pgdog!("SHOW statement_timeout");
+    let row = DataRow::from_bytes(messages[1].clone()).unwrap();
+    assert_eq!(row.get_text(0).as_deref(), Some("1s"));
+    pgdog!("COMMIT");
+
+    conn.write_all(&buffer!({ Terminate })).await.unwrap();
+    handle.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_show_session_mode() {
+    crate::logger();
+    load_test();
+    let mut config = (*config()).clone();
+    config.config.general.pooler_mode = PoolerMode::Session;
+    set(config).unwrap();
+    init();
+
+    let (mut conn, mut client) = parallel_test_client().await;
+    let handle = tokio::spawn(async move {
+        client.run().await.unwrap();
+    });
+
+    // Changed without SET, so only the server knows.
+    conn.write_all(&buffer!({
+        Query::new("SELECT set_config('statement_timeout', '1234', false)")
+    }))
+    .await
+    .unwrap();
+    read!(conn, ['T', 'D', 'C', 'Z']);
+
+    conn.write_all(&buffer!({ Query::new("SHOW statement_timeout") }))
+        .await
+        .unwrap();
+    let messages = read!(conn, ['T', 'D', 'C', 'Z']);
+    let row = DataRow::from_bytes(messages[1].clone().freeze()).unwrap();
+    assert_eq!(row.get_text(0).as_deref(), Some("1234ms"));
+
+    conn.write_all(&buffer!({ Terminate })).await.unwrap();
+    handle.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_session_setup() {
+    crate::logger();
+    load_test();
+    let mut config = (*config()).clone();
+    config.users.users[0].session_setup = vec![
+        "SET statement_timeout TO 1234".into(),
+        "SELECT set_config('pgdog_test.setup', 'on', false)".into(),
+    ];
+    set(config.clone()).unwrap();
+    init();
+
+    // SETs are the client's initial parameters.
+    let (mut conn, mut client) = parallel_test_client().await;
+    assert_eq!(
+        client
+            .params
+            .get("statement_timeout")
+            .and_then(|value| value.as_str()),
+        Some("1234")
+    );
+
+    let handle = tokio::spawn(async move {
+        client.run().await.unwrap();
+    });
+
+    // Other statements run on the server before the client's queries.
+    for _ in 0..2 {
+        conn.write_all(&buffer!({
+            Query::new(
+                "SELECT current_setting('statement_timeout'), current_setting('pgdog_test.setup')",
+            )
+        }))
+        .await
+        .unwrap();
+        let messages = read!(conn, ['T', 'D', 'C', 'Z']);
+        let row = DataRow::from_bytes(messages[1].clone().freeze()).unwrap();
+        assert_eq!(row.get_text(0).as_deref(), Some("1234ms"));
+        assert_eq!(row.get_text(1).as_deref(), Some("on"));
+    }
+
+    conn.write_all(&buffer!({ Terminate })).await.unwrap();
+    handle.await.unwrap();
+
+    // Failed setup disconnects the client.
+    config.users.users[0].session_setup = vec!["SELECT 1/0".into()];
+    set(config).unwrap();
+    init();
+
+    let (mut conn, mut client) = parallel_test_client().await;
+    let handle = tokio::spawn(async move { client.run().await });
+
+    conn.write_all(&buffer!({ Query::new("SELECT 1") }))
+        .await
+        .unwrap();
+    let err = handle.await.unwrap().unwrap_err();
+    assert!(
+        err.to_string()
+            .contains(r#"session setup statement "SELECT 1/0" failed"#),
+        "{}",
+        err
+    );
+}
+
+#[tokio::test]
+async fn test_degraded_reads() {
+    crate::logger();
+    load_test_replicas();
+    let mut config = (*config()).clone();
+    config.config.general.degraded_reads = true;
+    set(config).unwrap();
+    init();
+
+    let (mut conn, mut client) = parallel_test_client().await;
+    let handle = tokio::spawn(async move {
+        client.run().await.unwrap();
+    });
+
+    let cluster = databases().cluster(("pgdog", "pgdog")).unwrap();
+    let (_, primary) = cluster.shards()[0]
+        .pools_with_roles()
+        .into_iter()
+        .find(|(role, _)| *role == Role::Primary)
+        .unwrap();
+    primary.ban(crate::backend::pool::Error::ManualBan);
+    assert!(cluster.shards()[0].primary_banned());
+
+    // Conservative strategy sends reads in transactions to the primary.
+    conn.write_all(&buffer!({ Query::new("BEGIN") }))
+        .await
+        .unwrap();
+    read!(conn, ['C', 'Z']);
+
+    conn.write_all(&buffer!({ Query::new("SELECT 1") }))
+        .await
+        .unwrap();
+    let messages = read!(conn, ['N', 'T', 'D', 'C', 'Z']);
+    let notice = NoticeResponse::from_bytes(messages[0].clone().freeze()).unwrap();
+    assert_eq!(notice.message.severity(), "WARNING");
+    assert_eq!(notice.message.code, "01000");
+
+    conn.write_all(&buffer!({ Query::new("COMMIT") }))
+        .await
+        .unwrap();
+    read!(conn, ['C', 'Z']);
+
+    // Reads outside transactions go to replicas anyway.
+    conn.write_all(&buffer!({ Query::new("SELECT 1") }))
+        .await
+        .unwrap();
+    read!(conn, ['T', 'D', 'C', 'Z']);
+
+    conn.write_all(&buffer!({ Terminate })).await.unwrap();
+    handle.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_routing_history() {
+    crate::logger();
+    load_test_sharded();
+    let mut config = (*config()).clone();
+    config.config.general.routing_history = 2;
+    config.config.general.redact_sharding_keys = false;
+    config.config.general.cross_shard_disabled = true;
+    config.config.sharded_tables = vec![ShardedTable {
+        database: "pgdog".into(),
+        name: Some("sharded".into()),
+        column: "id".into(),
+        ..Default::default()
+    }];
+    set(config).unwrap();
+    init();
+
+    let (mut conn, mut client) = parallel_test_client().await;
+    let handle = tokio::spawn(async move {
+        client.run().await.unwrap();
+    });
+
+    let ids = [0, 1].map(|shard| (1..).find(|id| bigint(*id) as usize % 2 == shard).unwrap());
+
+    // Single shard, allowed.
+    conn.write_all(&buffer!({
+        Query::new(format!("SELECT * FROM sharded WHERE id = {}", ids[0]))
+    }))
+    .await
+    .unwrap();
+    loop {
+        let message = read_one!(conn);
+        assert_ne!(message[0] as char, 'E');
+        if message[0] as char == 'Z' {
+            break;
+        }
+    }
+
+    // Both shards, blocked by cross_shard_disabled.
+    conn.write_all(&buffer!({
+        Query::new(format!(
+            "SELECT * FROM sharded WHERE id = {} OR id = {}",
+            ids[0], ids[1]
+        ))
+    }))
+    .await
+    .unwrap();
+    let messages = read!(conn, ['E', 'Z']);
+    let error = ErrorResponse::from_bytes(messages[0].clone().freeze()).unwrap();
+    assert_eq!(error.code, "58000");
+
+    let context = error.context.unwrap();
+    let decision: serde_json::Value =
+        serde_json::from_str(context.strip_prefix("routing decision: ").unwrap()).unwrap();
+    assert_eq!(decision["command"]["command"], "query");
+    let mut shards = decision["route"]["shard"]["multi"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|shard| shard.as_u64().unwrap())
+        .collect::<Vec<_>>();
+    shards.sort();
+    assert_eq!(shards, vec![0, 1]);
+    assert_eq!(
+        decision["inputs"]["sharding_values"],
+        serde_json::json!([ids[0].to_string(), ids[1].to_string()])
+    );
+    assert!(!decision["fingerprint"].as_str().unwrap().is_empty());
+
+    conn.write_all(&buffer!({ Terminate })).await.unwrap();
+    handle.await.unwrap();
+}
+
+/// Read messages until ReadyForQuery, or just one message if not `sync`.
+/// Returns message codes, with the transaction status after ReadyForQuery.
+async fn read_codes(conn: &mut TcpStream, sync: bool) -> String {
+    let mut codes = String::new();
+    loop {
+        let message = read_one!(conn);
+        let code = message[0] as char;
+        codes.push(code);
+        if code == 'Z' {
+            codes.push(message[5] as char);
+        }
+        if !sync || code == 'Z' {
+            break codes;
+        }
+    }
+}
+
+/// Send a failing query using the simple and extended protocols,
+/// in and out of a transaction, and collect the replies.
+async fn rejection_replies(conn: &mut TcpStream, query: &str) -> Vec<String> {
+    let mut replies = vec![];
+
+    conn.write_all(&buffer!({ Query::new(query) }))
+        .await
+        .unwrap();
+    replies.push(read_codes(conn, true).await);
+
+    conn.write_all(&buffer!(
+        { Parse::new_anonymous(query) },
+        { Bind::new_statement("") },
+        { Execute::new() },
+        { Sync }
+    ))
+    .await
+    .unwrap();
+    replies.push(read_codes(conn, true).await);
+
+    // Flush returns the error, Sync ends the batch.
+    conn.write_all(&buffer!(
+        { Parse::new_anonymous(query) },
+        { Bind::new_statement("") },
+        { Describe::new_portal("") },
+        { Execute::new() },
+        { Flush }
+    ))
+    .await
+    .unwrap();
+    replies.push(read_codes(conn, false).await);
+    conn.write_all(&buffer!({ Sync })).await.unwrap();
+    replies.push(read_codes(conn, true).await);
+
+    // Error fails the transaction.
+    conn.write_all(&buffer!({ Query::new("BEGIN") }))
+        .await
+        .unwrap();
+    replies.push(read_codes(conn, true).await);
+    conn.write_all(&buffer!(
+        { Parse::new_anonymous(query) },
+        { Bind::new_statement("") },
+        { Execute::new() },
+        { Flush }
+    ))
+    .await
+    .unwrap();
+    replies.push(read_codes(conn, false).await);
+    conn.write_all(&buffer!({ Sync })).await.unwrap();
+    replies.push(read_codes(conn, true).await);
+    conn.write_all(&buffer!({ Query::new("ROLLBACK") }))
+        .await
+        .unwrap();
+    replies.push(read_codes(conn, true).await);
+
+    replies
+}
+
+#[tokio::test]
+async fn test_rejected_batch_replies() {
+    crate::logger();
+    load_test_sharded();
+    let mut config = (*config()).clone();
+    config.config.general.cross_shard_disabled = true;
+    config.config.sharded_tables = vec![ShardedTable {
+        database: "pgdog".into(),
+        name: Some("sharded".into()),
+        column: "id".into(),
+        ..Default::default()
+    }];
+    set(config).unwrap();
+    init();
+
+    let (mut conn, mut client) = parallel_test_client().await;
+    let handle = tokio::spawn(async move {
+        client.run().await.unwrap();
+    });
+
+    // Rejected by Postgres.
+    let expected = rejection_replies(&mut conn, "SELECT sdfsf").await;
+    assert_eq!(
+        expected,
+        vec!["EZI", "EZI", "E", "ZI", "CZT", "E", "ZE", "CZI"]
+    );
+
+    let ids = [0, 1].map(|shard| (1..).find(|id| bigint(*id) as usize % 2 == shard).unwrap());
+
+    // Rejected by PgDog: cross-shard query and cross-shard join.
+    for query in [
+        format!(
+            "SELECT * FROM sharded WHERE id = {} OR id = {}",
+            ids[0], ids[1]
+        ),
+        "SELECT * FROM sharded a JOIN sharded b ON a.email = b.email".to_string(),
+    ] {
+        assert_eq!(
+            rejection_replies(&mut conn, &query).await,
+            expected,
+            "{}",
+            query
+        );
+    }
+
+    conn.write_all(&buffer!({ Terminate })).await.unwrap();
+    handle.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_client_stats_totals() {
+    crate::logger();
+    load_test();
+
+    let (mut conn, mut client) = parallel_test_client().await;
+    client
+        .comms
+        .connect(&client.id, client.addr, &client.connect_params);
+    let comms = client.comms.clone();
+    let handle = tokio::spawn(async move {
+        client.run().await.unwrap();
+    });
+
+    let mut requests = vec![];
+    for _ in 0..5 {
+        requests.push(buffer!({ Query::new("SELECT 1") }));
+    }
+    requests.push(buffer!({ Query::new("SELECT sdfsf") }));
+    requests.push(buffer!(
+        { Parse::new_anonymous("SELECT $1::bigint") },
+        { Bind::new_params("", &[Parameter::new(b"1")]) },
+        { Execute::new() },
+        { Sync }
+    ));
+
+    let mut received = 0;
+    let mut sent = 0;
+    for request in &requests {
+        conn.write_all(request).await.unwrap();
+        received += request.len();
+        loop {
+            let message = read_one!(conn);
+            sent += message.len();
+            if message[0] as char == 'Z' {
+                break;
+            }
+        }
+    }
+
+    // Stats are published after the response is sent.
+    let stats = timeout(Duration::from_secs(5), async {
+        loop {
+            let stats = comms.client_stats().unwrap();
+            if stats.totals.queries == requests.len() {
+                break stats;
+            }
+            sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap();
+
+    assert_eq!(stats.totals.transactions, 7);
+    assert_eq!(stats.totals.errors, 1);
+    assert_eq!(stats.totals.bytes_received, received);
+    assert_eq!(stats.totals.bytes_sent, sent);
+    assert!(stats.totals.wait_time >= stats.current.last_wait_time);
+    assert_eq!(stats.current.state, State::Idle);
+
+    conn.write_all(&buffer!({ Terminate })).await.unwrap();
+    handle.await.unwrap();
+}
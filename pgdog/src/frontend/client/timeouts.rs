@@ -1,11 +1,15 @@
 use std::time::Duration;
 
-use crate::{config::General, frontend::ClientRequest, state::State};
+use tokio::time::Instant;
+
+use crate::{config::General, frontend::ClientRequest, net::Parameters, state::State};
 
 #[derive(Debug, Clone, Copy)]
 pub struct Timeouts {
     pub(super) query_timeout: Duration,
     pub(super) client_idle_timeout: Duration,
+    pub(super) replication_client_timeout: Duration,
+    pub(super) checkout_statement_timeout: bool,
 }
 
 impl Default for Timeouts {
@@ -13,6 +17,8 @@ impl Default for Timeouts {
         Self {
             query_timeout: Duration::MAX,
             client_idle_timeout: Duration::MAX,
+            replication_client_timeout: Duration::MAX,
+            checkout_statement_timeout: true,
         }
     }
 }
@@ -22,6 +28,18 @@ impl Timeouts {
         Self {
             query_timeout: general.query_timeout(),
             client_idle_timeout: general.client_idle_timeout(),
+            replication_client_timeout: general.replication_client_timeout(),
+            checkout_statement_timeout: general.checkout_statement_timeout,
+        }
+    }
+
+    /// Checkout must finish before the client's statement_timeout expires.
+    #[inline]
+    pub(crate) fn checkout_deadline(&self, params: &Parameters, start: Instant) -> Option<Instant> {
+        if self.checkout_statement_timeout {
+            params.statement_timeout().map(|timeout| start + timeout)
+        } else {
+            None
         }
     }
 
@@ -1,7 +1,10 @@
 //! ClientRequest (messages buffer).
 use crate::{
     net::{
-        messages::{Bind, CopyData, Protocol, Query},
+        messages::{
+            replication::{ReplicationMeta, StatusUpdate},
+            Bind, CopyData, Protocol, Query,
+        },
         Error, ProtocolMessage,
     },
     stats::memory::MemoryUsage,
@@ -22,10 +25,10 @@ pub struct ClientRequest {
 }
 
 impl MemoryUsage for ClientRequest {
+    /// Heap memory owned by the route isn't counted.
     #[inline]
     fn memory_usage(&self) -> usize {
-        // ProtocolMessage uses memory allocated by BytesMut (mostly).
-        self.messages.capacity() * std::mem::size_of::<ProtocolMessage>()
+        std::mem::size_of::<Route>() + self.messages.memory_usage()
     }
 }
 
@@ -129,6 +132,20 @@ impl ClientRequest {
         Ok(rows)
     }
 
+    /// Last standby status update sent by a replication client, if any.
+    pub fn status_update(&self) -> Option<StatusUpdate> {
+        self.messages
+            .iter()
+            .rev()
+            .find_map(|message| match message {
+                ProtocolMessage::CopyData(copy_data) => match copy_data.replication_meta() {
+                    Some(ReplicationMeta::StatusUpdate(status_update)) => Some(status_update),
+                    _ => None,
+                },
+                _ => None,
+            })
+    }
+
     /// Remove all CopyData messages and return the rest.
     pub fn without_copy_data(&self) -> Self {
         let mut messages = self.messages.clone();
@@ -156,6 +173,28 @@ impl ClientRequest {
             .any(|m| ['E', 'Q', 'B'].contains(&m.code()))
     }
 
+    /// The buffer executes exactly one statement and waits for its result.
+    pub fn single_statement(&self) -> bool {
+        let executes = self
+            .messages
+            .iter()
+            .filter(|m| matches!(m.code(), 'E' | 'Q'))
+            .count();
+        let syncs = self.messages.iter().filter(|m| m.code() == 'S').count();
+        let last = self.messages.last().map(|m| m.code());
+
+        match last {
+            Some('Q') if executes == 1 => match self.messages.last() {
+                Some(ProtocolMessage::Query(query)) => pg_query::split_with_scanner(query.query())
+                    .map(|statements| statements.len() == 1)
+                    .unwrap_or(false),
+                _ => false,
+            },
+            Some('S') => executes == 1 && syncs == 1,
+            _ => false,
+        }
+    }
+
     /// Rewrite query in buffer.
     pub fn rewrite(&mut self, query: &str) -> Result<(), Error> {
         if self.messages.iter().any(|c| c.code() != 'Q') {
@@ -181,3 +220,31 @@ impl From<Vec<ProtocolMessage>> for ClientRequest {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::net::messages::{Parameter, Sync};
+
+    #[test]
+    fn test_memory_usage() {
+        let mut request = ClientRequest::new();
+        let empty = request.memory_usage();
+
+        request.messages.push(Query::new("a".repeat(4096)).into());
+        let params = vec![Parameter::new(&[1; 4096]); 10];
+        request
+            .messages
+            .push(Bind::new_params("__pgdog_1", &params).into());
+        request.messages.push(Sync.into());
+        let full = request.memory_usage();
+        assert!(full >= empty + 4096 * 11);
+
+        request.messages.clear();
+        let cleared = request.memory_usage();
+        assert_eq!(cleared, empty);
+
+        request.messages.shrink_to_fit();
+        assert!(request.memory_usage() < cleared);
+    }
+}
@@ -1,5 +1,6 @@
 //! Communication to/from connected clients.
 
+use std::collections::HashSet;
 use std::net::SocketAddr;
 use std::sync::{
     atomic::{AtomicBool, Ordering},
@@ -12,10 +13,11 @@ use parking_lot::Mutex;
 use tokio::sync::Notify;
 use tokio_util::task::TaskTracker;
 
+use crate::backend::databases::{ToUser, User};
 use crate::net::messages::BackendKeyData;
 use crate::net::Parameters;
 
-use super::{ConnectedClient, Stats};
+use super::{router::RoutingHistory, ConnectedClient, Stats};
 
 static COMMS: Lazy<Comms> = Lazy::new(Comms::new);
 
@@ -68,6 +70,63 @@ impl Comms {
         self.global.clients.lock().clone()
     }
 
+    /// Users and databases of connected clients.
+    pub fn users(&self) -> HashSet<User> {
+        self.global
+            .clients
+            .lock()
+            .values()
+            .map(|client| {
+                let user = client.paramters.get_default("user", "postgres");
+                let database = client.paramters.get_default("database", user);
+                (user, database).to_user()
+            })
+            .collect()
+    }
+
+    /// IDs of all connected clients. Cheap to copy while holding the lock,
+    /// unlike the clients themselves.
+    pub fn client_ids(&self) -> Vec<BackendKeyData> {
+        self.global.clients.lock().keys().copied().collect()
+    }
+
+    /// Get up to `limit` clients, and the number of all connected clients.
+    pub fn clients_limit(&self, limit: usize) -> (usize, Vec<(BackendKeyData, ConnectedClient)>) {
+        let guard = self.global.clients.lock();
+        let clients = guard
+            .iter()
+            .take(limit)
+            .map(|(id, client)| (*id, client.clone()))
+            .collect();
+        (guard.len(), clients)
+    }
+
+    /// Get clients by ID. Clients that disconnected are skipped.
+    pub fn clients_by_id(&self, ids: &[BackendKeyData]) -> Vec<ConnectedClient> {
+        let guard = self.global.clients.lock();
+        ids.iter().filter_map(|id| guard.get(id).cloned()).collect()
+    }
+
+    /// Get a client by its process ID.
+    pub fn client_by_pid(&self, pid: i32) -> Option<ConnectedClient> {
+        self.global
+            .clients
+            .lock()
+            .iter()
+            .find(|(id, _)| id.pid == pid)
+            .map(|(_, client)| client.clone())
+    }
+
+    /// Routing history of this client.
+    pub fn routing_history(&self) -> Option<RoutingHistory> {
+        let id = self.id?;
+        self.global
+            .clients
+            .lock()
+            .get(&id)
+            .map(|client| client.routing_history.clone())
+    }
+
     /// Number of connected clients.
     pub fn clients_len(&self) -> usize {
         self.global.clients.lock().len()
@@ -78,10 +137,21 @@ impl Comms {
             .clients
             .lock()
             .values()
-            .map(|v| v.stats.memory_used)
+            .map(|v| v.stats.current.memory_used)
             .sum::<usize>()
     }
 
+    /// Memory used by each client. Only holds the lock
+    /// long enough to copy the numbers.
+    pub fn clients_memory_usage(&self) -> Vec<(BackendKeyData, SocketAddr, usize)> {
+        self.global
+            .clients
+            .lock()
+            .iter()
+            .map(|(id, client)| (*id, client.addr, client.stats.current.memory_used))
+            .collect()
+    }
+
     pub fn tracker(&self) -> &TaskTracker {
         &self.global.tracker
     }
@@ -133,6 +203,16 @@ impl Comms {
         }
     }
 
+    /// Stats this client published last.
+    pub fn client_stats(&self) -> Option<Stats> {
+        let id = self.id?;
+        self.global
+            .clients
+            .lock()
+            .get(&id)
+            .map(|client| client.stats)
+    }
+
     /// Notify clients pgDog is shutting down.
     pub fn shutdown(&self) {
         self.global.offline.store(true, Ordering::Relaxed);
@@ -3,6 +3,8 @@ use std::net::SocketAddr;
 
 use crate::net::Parameters;
 
+use super::router::RoutingHistory;
+
 use super::Stats;
 
 /// Connected client.
@@ -16,6 +18,8 @@ pub struct ConnectedClient {
     pub connected_at: DateTime<Local>,
     /// Client connection parameters.
     pub paramters: Parameters,
+    /// Last routing decisions.
+    pub routing_history: RoutingHistory,
 }
 
 impl ConnectedClient {
@@ -26,6 +30,7 @@ impl ConnectedClient {
             addr,
             connected_at: Local::now(),
             paramters: params.clone(),
+            routing_history: RoutingHistory::new(),
         }
     }
 }
@@ -1,28 +1,36 @@
 //! Connection listener. Handles all client connections.
 
+use std::io;
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
 
 use crate::backend::databases::{databases, reload, shutdown};
 use crate::config::config;
 use crate::net::messages::BackendKeyData;
 use crate::net::messages::{hello::SslReply, Startup};
 use crate::net::tls::acceptor;
-use crate::net::{tweak, Stream};
+use crate::net::{stream::BUFFER_SIZE, tweak, Stream};
 use crate::sighup::Sighup;
-use tokio::net::{TcpListener, TcpStream};
+use tokio::net::{lookup_host, TcpListener, TcpSocket, TcpStream};
 use tokio::signal::ctrl_c;
 use tokio::sync::Notify;
-use tokio::time::timeout;
+use tokio::time::{interval, sleep, timeout, Instant};
 use tokio::{select, spawn};
 
 use tracing::{error, info, warn};
 
 use super::{
+    accept::AcceptStats,
     comms::{comms, Comms},
     Client, Error,
 };
 
+/// Largest write buffer allocated for each client, regardless of `flush_threshold`.
+const MAX_WRITE_BUFFER: usize = 64 * 1024;
+/// How often to update the accept rate.
+const RATE_INTERVAL: Duration = Duration::from_secs(1);
+
 /// Client connections listener and handler.
 #[derive(Debug, Clone)]
 pub struct Listener {
@@ -41,35 +49,29 @@ impl Listener {
 
     /// Listen for client connections and handle them.
     pub async fn listen(&mut self) -> Result<(), Error> {
+        let (backlog, workers) = {
+            let config = config();
+            (
+                config.config.general.listen_backlog,
+                config.config.general.accept_workers,
+            )
+        };
+        let listeners = Self::bind(&self.addr, backlog, workers).await?;
         info!("🐕 PgDog listening on {}", self.addr);
-        let listener = TcpListener::bind(&self.addr).await?;
         let comms = comms();
         let shutdown_signal = comms.shutting_down();
         let mut sighup = Sighup::new()?;
+        let mut rate = interval(RATE_INTERVAL);
 
-        loop {
-            let comms = comms.clone();
+        let workers = listeners
+            .into_iter()
+            .map(|listener| spawn(Self::accept(listener)))
+            .collect::<Vec<_>>();
 
+        loop {
             select! {
-                connection = listener.accept() => {
-                   let (stream, addr) = connection?;
-                   let offline = comms.offline();
-
-                   let client_comms = comms.clone();
-                   let future = async move {
-                       match Self::handle_client(stream, addr, client_comms).await {
-                           Ok(_) => (),
-                           Err(err) => if !err.disconnect() {
-                               error!("client crashed: {:?}", err);
-                           }
-                       };
-                   };
-
-                   if offline {
-                       spawn(future);
-                   } else {
-                       comms.tracker().spawn(future);
-                   }
+                _ = rate.tick() => {
+                    AcceptStats::get().calc_rate(Instant::now());
                 }
 
                 _ = shutdown_signal.notified() => {
@@ -92,9 +94,107 @@ impl Listener {
             }
         }
 
+        for worker in workers {
+            worker.abort();
+        }
+
         Ok(())
     }
 
+    /// Bind `workers` sockets to the address. Sockets share the address
+    /// using `SO_REUSEPORT`, so only Unix gets more than one.
+    pub async fn bind(addr: &str, backlog: u32, workers: usize) -> Result<Vec<TcpListener>, Error> {
+        let mut error = io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("could not resolve to any address: {}", addr),
+        );
+
+        for addr in lookup_host(addr).await? {
+            match Self::bind_addr(addr, backlog, workers) {
+                Ok(listeners) => return Ok(listeners),
+                Err(err) => error = err,
+            }
+        }
+
+        Err(error.into())
+    }
+
+    fn bind_addr(
+        mut addr: SocketAddr,
+        backlog: u32,
+        workers: usize,
+    ) -> io::Result<Vec<TcpListener>> {
+        let workers = if cfg!(unix) { workers.max(1) } else { 1 };
+        let mut listeners = Vec::with_capacity(workers);
+
+        for _ in 0..workers {
+            let socket = if addr.is_ipv4() {
+                TcpSocket::new_v4()?
+            } else {
+                TcpSocket::new_v6()?
+            };
+
+            // Same as TcpListener::bind.
+            #[cfg(unix)]
+            socket.set_reuseaddr(true)?;
+            #[cfg(unix)]
+            if workers > 1 {
+                socket.set_reuseport(true)?;
+            }
+
+            socket.bind(addr)?;
+            let listener = socket.listen(backlog)?;
+            // Bind the other sockets to the port picked for the first one.
+            addr = listener.local_addr()?;
+            listeners.push(listener);
+        }
+
+        Ok(listeners)
+    }
+
+    /// Accept client connections until the task is aborted.
+    async fn accept(listener: TcpListener) {
+        let stats = AcceptStats::get();
+
+        loop {
+            match listener.accept().await {
+                Ok((stream, addr)) => {
+                    stats.accepted();
+                    Self::spawn_client(stream, addr);
+                }
+
+                Err(err) => {
+                    if let Some(backoff) = stats.error(&err, Instant::now()) {
+                        sleep(backoff).await;
+                    }
+                }
+            }
+        }
+    }
+
+    fn spawn_client(stream: TcpStream, addr: SocketAddr) {
+        let comms = comms();
+        let offline = comms.offline();
+
+        let client_comms = comms.clone();
+        let future = async move {
+            match Self::handle_client(stream, addr, client_comms).await {
+                Ok(_) => (),
+                Err(err) => {
+                    if !err.disconnect() {
+                        error!("client crashed: {:?}", err);
+                    }
+                }
+            };
+        };
+
+        if offline {
+            spawn(future);
+        } else {
+            comms.tracker().spawn(future);
+        }
+    }
+
     /// Shutdown this listener.
     pub fn shutdown(&self) {
         self.shutdown.notify_one();
@@ -136,7 +236,13 @@ impl Listener {
     async fn handle_client(stream: TcpStream, addr: SocketAddr, comms: Comms) -> Result<(), Error> {
         tweak(&stream)?;
 
-        let mut stream = Stream::plain(stream);
+        // Coalesce responses into as few writes as we flush.
+        let write_buffer = config()
+            .config
+            .general
+            .flush_threshold
+            .clamp(BUFFER_SIZE, MAX_WRITE_BUFFER);
+        let mut stream = Stream::plain_with_write_buffer(stream, write_buffer);
         let tls = acceptor();
 
         loop {
@@ -144,11 +250,14 @@ impl Listener {
 
             match startup {
                 Startup::Ssl => {
-                    if let Some(tls) = tls {
+                    if let Some(ref tls) = tls {
                         stream.send_flush(&SslReply::Yes).await?;
                         let plain = stream.take()?;
                         let cipher = tls.accept(plain).await?;
-                        stream = Stream::tls(tokio_rustls::TlsStream::Server(cipher));
+                        stream = Stream::tls_with_write_buffer(
+                            tokio_rustls::TlsStream::Server(cipher),
+                            write_buffer,
+                        );
                     } else {
                         stream.send_flush(&SslReply::No).await?;
                     }
@@ -170,3 +279,112 @@ impl Listener {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+
+    /// Ask for TLS, PgDog answers with one byte.
+    async fn ssl_request(addr: SocketAddr) -> u8 {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(&[0, 0, 0, 8, 0x04, 0xd2, 0x16, 0x2f])
+            .await
+            .unwrap();
+        stream.read_u8().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_accept_workers() {
+        crate::logger();
+        let listeners = Listener::bind("127.0.0.1:0", 4096, 4).await.unwrap();
+        let addr = listeners[0].local_addr().unwrap();
+
+        if cfg!(unix) {
+            assert_eq!(listeners.len(), 4);
+            assert!(listeners
+                .iter()
+                .all(|listener| listener.local_addr().unwrap() == addr));
+        }
+
+        for listener in listeners {
+            spawn(Listener::accept(listener));
+        }
+
+        let accepted = AcceptStats::get().stats().accepted;
+
+        // Connection storm.
+        for _ in 0..10 {
+            let replies = futures::future::join_all((0..200).map(|_| ssl_request(addr))).await;
+            assert!(replies.into_iter().all(|reply| reply == b'N'));
+        }
+
+        assert!(AcceptStats::get().stats().accepted >= accepted + 2000);
+    }
+
+    // Lowers RLIMIT_NOFILE for the whole process, which breaks tests running
+    // alongside it. Run it on its own:
+    // cargo test test_accept_survives_emfile -- --ignored
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    #[ignore]
+    async fn test_accept_survives_emfile() {
+        crate::logger();
+
+        fn set_nofile(limit: libc::rlimit) {
+            assert_eq!(unsafe { libc::setrlimit(libc::RLIMIT_NOFILE, &limit) }, 0);
+        }
+
+        let listener = Listener::bind("127.0.0.1:0", 1024, 1)
+            .await
+            .unwrap()
+            .pop()
+            .unwrap();
+        let addr = listener.local_addr().unwrap();
+        spawn(Listener::accept(listener));
+
+        let stats = AcceptStats::get();
+        let errors = stats.stats().errors;
+
+        let mut original = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        assert_eq!(
+            unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut original) },
+            0
+        );
+
+        // Leave room for a few connections only.
+        let open = std::fs::read_dir("/proc/self/fd").unwrap().count() as libc::rlim_t;
+        set_nofile(libc::rlimit {
+            rlim_cur: open + 16,
+            rlim_max: original.rlim_max,
+        });
+
+        // The kernel queues connections we can't accept until
+        // we run out of file descriptors.
+        let mut clients = vec![];
+        for _ in 0..1000 {
+            if stats.stats().errors > errors {
+                break;
+            }
+            match TcpStream::connect(addr).await {
+                Ok(stream) => clients.push(stream),
+                Err(_) => sleep(Duration::from_millis(10)).await,
+            }
+        }
+        assert!(stats.stats().errors > errors);
+
+        drop(clients);
+        set_nofile(original);
+
+        // Listener is still accepting.
+        let reply = timeout(Duration::from_secs(5), ssl_request(addr))
+            .await
+            .unwrap();
+        assert_eq!(reply, b'N');
+    }
+}
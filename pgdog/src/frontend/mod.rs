@@ -1,5 +1,7 @@
 //! pgDog frontend manages connections to clients.
 
+pub mod accept;
+pub mod auth_audit;
 pub mod buffered_query;
 pub mod client;
 pub mod client_request;
@@ -13,6 +15,7 @@ pub mod prepared_statements;
 #[cfg(debug_assertions)]
 pub mod query_logger;
 pub mod router;
+pub mod session_pins;
 pub mod stats;
 
 pub use buffered_query::BufferedQuery;
@@ -26,4 +29,5 @@ pub use prepared_statements::{PreparedStatements, Rewrite};
 pub use query_logger::QueryLogger;
 pub use router::{Command, Router};
 pub use router::{RouterContext, SearchPath};
+pub use session_pins::SessionPins;
 pub use stats::Stats;
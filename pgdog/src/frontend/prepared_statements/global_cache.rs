@@ -1,10 +1,11 @@
 use bytes::Bytes;
 
 use crate::{
+    backend::databases::User,
     net::messages::{Parse, RowDescription},
     stats::memory::MemoryUsage,
 };
-use std::{collections::hash_map::HashMap, str::from_utf8};
+use std::{collections::hash_map::HashMap, str::from_utf8, sync::Arc};
 
 // Format the globally unique prepared statement
 // name based on the counter.
@@ -17,6 +18,7 @@ pub struct Statement {
     parse: Parse,
     row_description: Option<RowDescription>,
     version: usize,
+    cluster: Option<Arc<User>>,
 }
 
 impl MemoryUsage for Statement {
@@ -36,11 +38,17 @@ impl Statement {
         self.parse.query()
     }
 
+    /// User/database pair of the cluster this statement was prepared for.
+    pub fn cluster(&self) -> Option<&User> {
+        self.cluster.as_deref()
+    }
+
     fn cache_key(&self) -> CacheKey {
         CacheKey {
             query: self.parse.query_ref(),
             data_types: self.parse.data_types_ref(),
             version: self.version,
+            cluster: self.cluster.clone(),
         }
     }
 }
@@ -52,18 +60,24 @@ impl Statement {
 /// with different data types, we can't re-use it and
 /// need to plan a new one.
 ///
+/// The same query sent to different databases can mean different things,
+/// so statements are only shared between clients of the same cluster.
+///
 #[derive(Debug, Clone, PartialEq, Hash, Eq)]
 pub struct CacheKey {
     pub query: Bytes,
     pub data_types: Bytes,
     pub version: usize,
+    pub cluster: Option<Arc<User>>,
 }
 
 impl MemoryUsage for CacheKey {
     #[inline]
     fn memory_usage(&self) -> usize {
-        // Bytes refer to memory allocated by someone else.
-        std::mem::size_of::<Bytes>() * 2 + self.version.memory_usage()
+        // Bytes and the cluster refer to memory allocated by someone else.
+        std::mem::size_of::<Bytes>() * 2
+            + self.version.memory_usage()
+            + std::mem::size_of::<Option<Arc<User>>>()
     }
 }
 
@@ -128,11 +142,12 @@ impl GlobalCache {
     ///
     /// If the statement exists, no entry is created
     /// and the global name is returned instead.
-    pub fn insert(&mut self, parse: &Parse) -> (bool, String) {
+    pub fn insert(&mut self, parse: &Parse, cluster: &Option<Arc<User>>) -> (bool, String) {
         let parse_key = CacheKey {
             query: parse.query_ref(),
             data_types: parse.data_types_ref(),
             version: 0,
+            cluster: cluster.clone(),
         };
 
         if let Some(entry) = self.statements.get_mut(&parse_key) {
@@ -147,6 +162,7 @@ impl GlobalCache {
                 query: parse.query_ref(),
                 data_types: parse.data_types_ref(),
                 version: 0,
+                cluster: cluster.clone(),
             };
 
             self.statements.insert(
@@ -163,6 +179,7 @@ impl GlobalCache {
                     parse,
                     row_description: None,
                     version: 0,
+                    cluster: cluster.clone(),
                 },
             );
 
@@ -172,7 +189,7 @@ impl GlobalCache {
 
     /// Insert a prepared statement into the global cache ignoring
     /// duplicate check.
-    pub fn insert_anyway(&mut self, parse: &Parse) -> String {
+    pub fn insert_anyway(&mut self, parse: &Parse, cluster: &Option<Arc<User>>) -> String {
         self.counter += 1;
         self.versions += 1;
 
@@ -183,6 +200,7 @@ impl GlobalCache {
             query: parse.query_ref(),
             data_types: parse.data_types_ref(),
             version: self.versions,
+            cluster: cluster.clone(),
         };
 
         self.statements.insert(
@@ -199,6 +217,7 @@ impl GlobalCache {
                 parse,
                 row_description: None,
                 version: self.versions,
+                cluster: cluster.clone(),
             },
         );
 
@@ -308,6 +327,31 @@ impl GlobalCache {
         }
     }
 
+    /// Parse messages of the statements used by the most clients of a cluster,
+    /// most used first.
+    pub fn most_used(&self, cluster: Option<&User>, limit: usize) -> Vec<Parse> {
+        let mut statements = self
+            .names
+            .values()
+            .filter(|stmt| stmt.cluster() == cluster)
+            .filter_map(|stmt| {
+                self.statements
+                    .get(&stmt.cache_key())
+                    .filter(|cached| cached.used > 0)
+                    .map(|cached| (cached, &stmt.parse))
+            })
+            .collect::<Vec<_>>();
+
+        // Older statements first on ties, so the order is stable.
+        statements.sort_by(|(a, _), (b, _)| b.used.cmp(&a.used).then(a.counter.cmp(&b.counter)));
+
+        statements
+            .into_iter()
+            .take(limit)
+            .map(|(_, parse)| parse.clone())
+            .collect()
+    }
+
     /// Get all prepared statements by name.
     pub fn names(&self) -> &HashMap<String, Statement> {
         &self.names
@@ -316,6 +360,27 @@ impl GlobalCache {
     pub fn statements(&self) -> &HashMap<CacheKey, CachedStmt> {
         &self.statements
     }
+
+    /// Number of statements and memory they use, by cluster.
+    /// Statements not prepared for a cluster are under `None`.
+    pub fn usage(&self) -> HashMap<Option<&User>, ClusterUsage> {
+        let mut usage: HashMap<Option<&User>, ClusterUsage> = HashMap::new();
+        for (name, stmt) in &self.names {
+            let entry = usage.entry(stmt.cluster()).or_default();
+            entry.statements += 1;
+            entry.memory_used += name.memory_usage() + stmt.memory_usage();
+        }
+        usage
+    }
+}
+
+/// Prepared statements of one cluster.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ClusterUsage {
+    /// Number of statements.
+    pub statements: usize,
+    /// Memory used by the statements, approx.
+    pub memory_used: usize,
 }
 
 #[cfg(test)]
@@ -326,12 +391,12 @@ mod test {
     fn test_prep_stmt_cache_close() {
         let mut cache = GlobalCache::default();
         let parse = Parse::named("test", "SELECT $1");
-        let (new, name) = cache.insert(&parse);
+        let (new, name) = cache.insert(&parse, &None);
         assert!(new);
         assert_eq!(name, "__pgdog_1");
 
         for _ in 0..25 {
-            let (new, name) = cache.insert(&parse);
+            let (new, name) = cache.insert(&parse, &None);
             assert!(!new);
             assert_eq!(name, "__pgdog_1");
         }
@@ -351,7 +416,7 @@ mod test {
         assert!(cache.statements.is_empty());
         assert!(cache.names.is_empty());
 
-        let name = cache.insert_anyway(&parse);
+        let name = cache.insert_anyway(&parse, &None);
         cache.close(&name, 0);
 
         assert!(cache.names.is_empty());
@@ -365,7 +430,7 @@ mod test {
 
         for stmt in 0..25 {
             let parse = Parse::named("__sqlx_1", format!("SELECT {}", stmt));
-            let (new, name) = cache.insert(&parse);
+            let (new, name) = cache.insert(&parse, &None);
             assert!(new);
             names.push(name);
         }
@@ -383,4 +448,80 @@ mod test {
         assert_eq!(cache.close_unused(19), 0);
         assert_eq!(cache.len(), 20);
     }
+
+    #[test]
+    fn test_cluster_scope() {
+        let mut cache = GlobalCache::default();
+        let parse = Parse::named("test", "SELECT * FROM users WHERE id = $1");
+        let pgdog = Some(Arc::new(User {
+            user: "pgdog".into(),
+            database: "pgdog".into(),
+        }));
+        let other = Some(Arc::new(User {
+            user: "pgdog".into(),
+            database: "other".into(),
+        }));
+
+        let (new, pgdog_name) = cache.insert(&parse, &pgdog);
+        assert!(new);
+        let (new, other_name) = cache.insert(&parse, &other);
+        assert!(new);
+        assert_ne!(pgdog_name, other_name);
+
+        // Same cluster shares the statement.
+        let (new, name) = cache.insert(&parse, &pgdog);
+        assert!(!new);
+        assert_eq!(name, pgdog_name);
+
+        let usage = cache.usage();
+        assert_eq!(usage.len(), 2);
+        let pgdog_usage = usage[&pgdog.as_deref()];
+        assert_eq!(pgdog_usage.statements, 1);
+        assert!(pgdog_usage.memory_used > 0);
+        assert_eq!(usage[&other.as_deref()].statements, 1);
+
+        cache.close(&pgdog_name, 0);
+        cache.close(&pgdog_name, 0);
+        let usage = cache.usage();
+        assert!(!usage.contains_key(&pgdog.as_deref()));
+        assert_eq!(cache.query(&other_name), Some(parse.query()));
+    }
+
+    #[test]
+    fn test_most_used() {
+        let mut cache = GlobalCache::default();
+        let pgdog = Some(Arc::new(User {
+            user: "pgdog".into(),
+            database: "pgdog".into(),
+        }));
+        let other = Some(Arc::new(User {
+            user: "pgdog".into(),
+            database: "other".into(),
+        }));
+
+        for (query, clients) in [("SELECT 1", 1), ("SELECT 2", 3), ("SELECT 3", 2)] {
+            for _ in 0..clients {
+                cache.insert(&Parse::named("test", query), &pgdog);
+            }
+        }
+        for _ in 0..10 {
+            cache.insert(&Parse::named("test", "SELECT 4"), &other);
+        }
+
+        let queries = |limit| {
+            cache
+                .most_used(pgdog.as_deref(), limit)
+                .iter()
+                .map(|parse| parse.query().to_owned())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(queries(2), vec!["SELECT 2", "SELECT 3"]);
+        assert_eq!(queries(10), vec!["SELECT 2", "SELECT 3", "SELECT 1"]);
+        assert!(cache.most_used(None, 10).is_empty());
+
+        // Statements are renamed to their global names.
+        let parse = &cache.most_used(other.as_deref(), 1)[0];
+        assert_eq!(parse.name(), "__pgdog_4");
+    }
 }
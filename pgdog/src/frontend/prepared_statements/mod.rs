@@ -6,17 +6,20 @@ use once_cell::sync::Lazy;
 use parking_lot::Mutex;
 
 use crate::{
-    net::{Parse, ProtocolMessage},
+    backend::{databases::User, Cluster},
+    net::{Message, Parse, ProtocolMessage},
     stats::memory::MemoryUsage,
 };
 
 pub mod error;
 pub mod global_cache;
+pub mod reverse;
 pub mod rewrite;
 
 pub use error::Error;
-pub use global_cache::GlobalCache;
+pub use global_cache::{ClusterUsage, GlobalCache};
 
+pub use reverse::Reverse;
 pub use rewrite::Rewrite;
 
 static CACHE: Lazy<PreparedStatements> = Lazy::new(PreparedStatements::default);
@@ -24,6 +27,8 @@ static CACHE: Lazy<PreparedStatements> = Lazy::new(PreparedStatements::default);
 #[derive(Clone, Debug)]
 pub struct PreparedStatements {
     pub(super) global: Arc<Mutex<GlobalCache>>,
+    /// Cluster the statements are prepared for.
+    pub(super) cluster: Option<Arc<User>>,
     pub(super) local: HashMap<String, String>,
     pub(super) enabled: bool,
     pub(super) capacity: usize,
@@ -37,6 +42,7 @@ impl MemoryUsage for PreparedStatements {
             + self.enabled.memory_usage()
             + self.capacity.memory_usage()
             + std::mem::size_of::<Arc<Mutex<GlobalCache>>>()
+            + std::mem::size_of::<Option<Arc<User>>>()
     }
 }
 
@@ -44,6 +50,7 @@ impl Default for PreparedStatements {
     fn default() -> Self {
         Self {
             global: Arc::new(Mutex::new(GlobalCache::default())),
+            cluster: None,
             local: HashMap::default(),
             enabled: true,
             capacity: usize::MAX,
@@ -53,14 +60,25 @@ impl Default for PreparedStatements {
 }
 
 impl PreparedStatements {
-    /// New shared prepared statements cache.
-    pub fn new() -> Self {
-        CACHE.clone()
+    /// New shared prepared statements cache for clients of the cluster.
+    /// Statements are only shared with clients of the same cluster.
+    pub fn new(cluster: Option<&Cluster>) -> Self {
+        let mut statements = CACHE.clone();
+        statements.cluster = cluster.map(Self::cluster_key);
+        statements
+    }
+
+    /// Key of the cluster's statements in the global cache.
+    pub fn cluster_key(cluster: &Cluster) -> Arc<User> {
+        Arc::new(User {
+            user: cluster.user().to_owned(),
+            database: cluster.name().to_owned(),
+        })
     }
 
     /// Get global cache.
     pub fn global() -> Arc<Mutex<GlobalCache>> {
-        Self::new().global.clone()
+        CACHE.global.clone()
     }
 
     /// Maybe rewrite message.
@@ -70,18 +88,29 @@ impl PreparedStatements {
         Ok(message)
     }
 
+    /// Replace global statement names in server errors and notices
+    /// with the names this client used.
+    pub fn restore_names(&self, message: Message) -> Result<Message, Error> {
+        if self.local.is_empty() {
+            return Ok(message);
+        }
+
+        Reverse::new(self).message(message)
+    }
+
     /// Register prepared statement with the global cache.
     pub fn insert(&mut self, parse: Parse) -> Parse {
-        let (_new, name) = { self.global.lock().insert(&parse) };
+        let (_new, name) = { self.global.lock().insert(&parse, &self.cluster) };
         let existed = self.local.insert(parse.name().to_owned(), name.clone());
         self.memory_used = self.memory_usage();
 
-        // Client prepared it again because it got an error the first time.
-        // We can check if this is a new statement first, but this is an error
-        // condition which happens very infrequently, so we optimize for the happy path.
-        if existed.is_some() {
+        // Client prepared it again because it got an error the first time,
+        // or replaced the anonymous statement with another one.
+        // We can check if this is a new statement first, but this happens
+        // infrequently for named statements, so we optimize for the happy path.
+        if let Some(existed) = existed {
             {
-                self.global.lock().decrement(&name);
+                self.global.lock().decrement(&existed);
             }
         }
 
@@ -90,7 +119,7 @@ impl PreparedStatements {
 
     /// Insert statement into the cache bypassing duplicate checks.
     pub fn insert_anyway(&mut self, parse: Parse) -> Parse {
-        let name = self.global.lock().insert_anyway(&parse);
+        let name = self.global.lock().insert_anyway(&parse, &self.cluster);
         self.local.insert(parse.name().to_owned(), name.clone());
         self.memory_used = self.memory_usage();
         parse.rename_fast(&name)
@@ -228,4 +257,29 @@ mod test {
             0
         );
     }
+
+    #[test]
+    fn test_scoped_by_cluster() {
+        let mut pgdog = PreparedStatements::default();
+        pgdog.cluster = Some(Arc::new(User {
+            user: "pgdog".into(),
+            database: "pgdog".into(),
+        }));
+        let mut other = pgdog.clone();
+        other.cluster = Some(Arc::new(User {
+            user: "pgdog".into(),
+            database: "other".into(),
+        }));
+        let mut same = pgdog.clone();
+        same.local.clear();
+
+        let parse = || Parse::named("__sqlx_1", "SELECT * FROM users");
+        let pgdog_name = pgdog.insert(parse()).name().to_owned();
+        let other_name = other.insert(parse()).name().to_owned();
+        let same_name = same.insert(parse()).name().to_owned();
+
+        assert_ne!(pgdog_name, other_name);
+        assert_eq!(pgdog_name, same_name);
+        assert_eq!(pgdog.global.lock().len(), 2);
+    }
 }
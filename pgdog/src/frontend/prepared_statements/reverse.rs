@@ -0,0 +1,236 @@
+//! Map global prepared statement names back to the client's names.
+//!
+//! Prepared statements are renamed to `__pgdog_N` before they reach the server,
+//! so errors and notices mentioning them would otherwise leak the global names
+//! to the client. Rows returned by queries against `pg_prepared_statements` are
+//! not rewritten and will still show the global names.
+use std::collections::HashMap;
+
+use crate::net::{ErrorResponse, FromBytes, Message, NoticeResponse, Protocol, ToBytes};
+
+use super::{Error, PreparedStatements};
+
+/// Prefix of global prepared statement names.
+const PREFIX: &str = "__pgdog_";
+
+/// Reverse lookup from global to client statement names.
+#[derive(Debug)]
+pub struct Reverse<'a> {
+    names: HashMap<&'a str, &'a str>,
+}
+
+impl<'a> Reverse<'a> {
+    /// Build reverse lookup from the client's local cache.
+    pub fn new(statements: &'a PreparedStatements) -> Self {
+        let mut names: HashMap<&str, &str> = HashMap::new();
+
+        for (client, global) in statements.local.iter() {
+            // The unnamed statement has no name we can give back.
+            if client.is_empty() {
+                continue;
+            }
+
+            // Identical statements share a global name; pick one consistently.
+            names
+                .entry(global.as_str())
+                .and_modify(|existing| {
+                    if client.as_str() < *existing {
+                        *existing = client.as_str();
+                    }
+                })
+                .or_insert(client.as_str());
+        }
+
+        Self { names }
+    }
+
+    /// Rewrite global names in an ErrorResponse (B) or NoticeResponse (B).
+    /// Other messages are returned unchanged.
+    pub fn message(&self, message: Message) -> Result<Message, Error> {
+        let code = message.code();
+        if !matches!(code, 'E' | 'N') || self.names.is_empty() {
+            return Ok(message);
+        }
+
+        let mut error = ErrorResponse::from_bytes(message.to_bytes()?)?;
+        if !self.error(&mut error) {
+            return Ok(message);
+        }
+
+        let message = if code == 'E' {
+            error.message()?
+        } else {
+            NoticeResponse::from(error).message()?
+        };
+
+        Ok(message.backend())
+    }
+
+    /// Rewrite text fields of an error. Returns true if anything changed.
+    fn error(&self, error: &mut ErrorResponse) -> bool {
+        let mut changed = false;
+
+        if let Some(message) = self.text(&error.message) {
+            error.message = message;
+            changed = true;
+        }
+
+        for field in [
+            &mut error.detail,
+            &mut error.hint,
+            &mut error.context,
+            &mut error.internal_query,
+        ] {
+            if let Some(text) = field.as_ref().and_then(|text| self.text(text)) {
+                *field = Some(text);
+                changed = true;
+            }
+        }
+
+        changed
+    }
+
+    /// Replace every global name owned by this client in text.
+    /// Returns None if nothing was replaced.
+    pub fn text(&self, text: &str) -> Option<String> {
+        if !text.contains(PREFIX) {
+            return None;
+        }
+
+        let mut result = String::with_capacity(text.len());
+        let mut replaced = false;
+        let mut rest = text;
+
+        while let Some(start) = rest.find(PREFIX) {
+            let digits = rest[start + PREFIX.len()..]
+                .bytes()
+                .take_while(|b| b.is_ascii_digit())
+                .count();
+            let end = start + PREFIX.len() + digits;
+
+            // Names must be whole identifiers, e.g. not "my__pgdog_1" or "__pgdog_1a".
+            let standalone = !rest[..start].chars().next_back().is_some_and(is_identifier)
+                && !rest[end..].chars().next().is_some_and(is_identifier);
+
+            let client = if digits > 0 && standalone {
+                self.names.get(&rest[start..end])
+            } else {
+                None
+            };
+
+            result.push_str(&rest[..start]);
+            match client {
+                Some(client) => {
+                    result.push_str(client);
+                    replaced = true;
+                }
+                None => result.push_str(&rest[start..end]),
+            }
+            rest = &rest[end..];
+        }
+
+        result.push_str(rest);
+
+        if replaced {
+            Some(result)
+        } else {
+            None
+        }
+    }
+}
+
+fn is_identifier(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '$'
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn statements(names: &[(&str, &str)]) -> PreparedStatements {
+        let mut statements = PreparedStatements::default();
+        for (client, global) in names {
+            statements
+                .local
+                .insert(client.to_string(), global.to_string());
+        }
+        statements
+    }
+
+    #[test]
+    fn test_reverse_text() {
+        let statements = statements(&[("my_stmt", "__pgdog_3"), ("other", "__pgdog_4")]);
+        let reverse = Reverse::new(&statements);
+
+        assert_eq!(
+            reverse
+                .text("prepared statement \"__pgdog_3\" does not exist")
+                .unwrap(),
+            "prepared statement \"my_stmt\" does not exist"
+        );
+        assert_eq!(
+            reverse
+                .text("__pgdog_3 and __pgdog_4, again __pgdog_3")
+                .unwrap(),
+            "my_stmt and other, again my_stmt"
+        );
+        assert!(reverse.text("no statements here").is_none());
+        // Not ours.
+        assert!(reverse.text("prepared statement \"__pgdog_5\"").is_none());
+    }
+
+    #[test]
+    fn test_reverse_substrings() {
+        let statements = statements(&[("one", "__pgdog_1"), ("twelve", "__pgdog_12")]);
+        let reverse = Reverse::new(&statements);
+
+        assert_eq!(
+            reverse.text("__pgdog_12, __pgdog_1, __pgdog_123").unwrap(),
+            "twelve, one, __pgdog_123"
+        );
+        assert!(reverse.text("x__pgdog_1 __pgdog_1x __pgdog_").is_none());
+    }
+
+    #[test]
+    fn test_reverse_skips_unnamed() {
+        let statements = statements(&[("", "__pgdog_1")]);
+        let reverse = Reverse::new(&statements);
+
+        assert!(reverse.text("prepared statement \"__pgdog_1\"").is_none());
+    }
+
+    #[test]
+    fn test_reverse_error_and_notice() {
+        let statements = statements(&[("my_stmt", "__pgdog_7")]);
+        let reverse = Reverse::new(&statements);
+
+        let error = ErrorResponse {
+            code: "26000".into(),
+            message: "prepared statement \"__pgdog_7\" does not exist".into(),
+            hint: Some("DEALLOCATE __pgdog_7".into()),
+            ..Default::default()
+        };
+
+        let message = reverse.message(error.message().unwrap()).unwrap();
+        assert_eq!(message.code(), 'E');
+        let error = ErrorResponse::from_bytes(message.to_bytes().unwrap()).unwrap();
+        assert_eq!(
+            error.message,
+            "prepared statement \"my_stmt\" does not exist"
+        );
+        assert_eq!(error.hint.as_deref(), Some("DEALLOCATE my_stmt"));
+        assert_eq!(error.code, "26000");
+
+        let notice = NoticeResponse::from(ErrorResponse {
+            message: "prepared statement \"__pgdog_7\" replaced".into(),
+            ..Default::default()
+        });
+        let message = reverse.message(notice.message().unwrap()).unwrap();
+        assert_eq!(message.code(), 'N');
+        let notice = NoticeResponse::from_bytes(message.to_bytes().unwrap()).unwrap();
+        assert_eq!(
+            notice.message.message,
+            "prepared statement \"my_stmt\" replaced"
+        );
+    }
+}
@@ -116,4 +116,78 @@ mod test {
         assert_eq!(statements.len_local(), 1);
         assert_eq!(statements.global.lock().len(), 1);
     }
+
+    #[test]
+    fn test_rewrite_anonymous_reuse() {
+        use bytes::{BufMut, BytesMut};
+
+        // Anonymous Parse declaring parameter data types.
+        fn parse_with_types(query: &str, oids: &[i32]) -> Parse {
+            let mut payload = BytesMut::new();
+            payload.put_u8(b'P');
+            payload.put_i32(0);
+            payload.put_u8(0);
+            payload.put(query.as_bytes());
+            payload.put_u8(0);
+            payload.put_i16(oids.len() as i16);
+            for oid in oids {
+                payload.put_i32(*oid);
+            }
+            let len = (payload.len() - 1) as i32;
+            payload[1..5].copy_from_slice(&len.to_be_bytes());
+            Parse::from_bytes(payload.freeze()).unwrap()
+        }
+
+        let mut statements = PreparedStatements::default();
+        let mut rewrite = Rewrite::new(&mut statements);
+
+        let mut names = vec![];
+        for _ in 0..10 {
+            let parse = Parse::from_bytes(
+                rewrite
+                    .rewrite(Parse::new_anonymous("SELECT $1").into())
+                    .unwrap()
+                    .to_bytes()
+                    .unwrap(),
+            )
+            .unwrap();
+            names.push(parse.name().to_owned());
+        }
+
+        // Same statement is reused every time.
+        names.dedup();
+        assert_eq!(names.len(), 1);
+
+        // Declared data types don't match, can't reuse it.
+        let parse = Parse::from_bytes(
+            rewrite
+                .rewrite(parse_with_types("SELECT $1", &[20]).into())
+                .unwrap()
+                .to_bytes()
+                .unwrap(),
+        )
+        .unwrap();
+        assert_ne!(parse.name(), names[0]);
+
+        // Bind is sent to the statement prepared last.
+        let bind = Bind::from_bytes(
+            rewrite
+                .rewrite(Bind::new_statement("").into())
+                .unwrap()
+                .to_bytes()
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(bind.statement(), parse.name());
+
+        assert_eq!(statements.len_local(), 1);
+        let global = statements.global.lock();
+        assert_eq!(global.len(), 2);
+
+        // Only the last anonymous statement is in use.
+        for stmt in global.statements().values() {
+            let used = if stmt.name() == parse.name() { 1 } else { 0 };
+            assert_eq!(stmt.used, used, "{}", stmt.name());
+        }
+    }
 }
@@ -2,21 +2,40 @@
 //!
 //! DO NOT USE IN PRODUCTION. This is very slow.
 //!
+use serde_json::json;
 use tokio::{fs::OpenOptions, io::AsyncWriteExt};
 
-use crate::config::config;
+use crate::{backend::pool::connection::multi_shard::ShardRows, config::config};
 
-use super::{ClientRequest, Error};
+use super::{router::RoutingDecision, Error};
 
 /// Log queries.
 pub struct QueryLogger<'a> {
-    buffer: &'a ClientRequest,
+    query: &'a str,
+    decision: Option<&'a RoutingDecision>,
+    shard_rows: &'a [ShardRows],
 }
 
 impl<'a> QueryLogger<'a> {
     /// Create new query logger.
-    pub fn new(buffer: &'a ClientRequest) -> Self {
-        Self { buffer }
+    pub fn new(query: &'a str) -> Self {
+        Self {
+            query,
+            decision: None,
+            shard_rows: &[],
+        }
+    }
+
+    /// Log the routing decision with the query, as a JSON line.
+    pub fn decision(mut self, decision: &'a RoutingDecision) -> Self {
+        self.decision = Some(decision);
+        self
+    }
+
+    /// Rows and bytes each shard returned, if the query went to multiple shards.
+    pub fn shard_rows(mut self, shard_rows: &'a [ShardRows]) -> Self {
+        self.shard_rows = shard_rows;
+        self
     }
 
     /// Log queries
@@ -24,15 +43,23 @@ impl<'a> QueryLogger<'a> {
         let path = &config().config.general.query_log;
 
         if let Some(path) = path {
-            if let Some(query) = self.buffer.query()? {
-                let mut file = OpenOptions::new()
-                    .append(true)
-                    .create(true)
-                    .open(path)
-                    .await?;
-                let line = format!("{}\n", query.trim());
-                file.write_all(line.as_bytes()).await?;
-            }
+            let mut file = OpenOptions::new()
+                .append(true)
+                .create(true)
+                .open(path)
+                .await?;
+            let line = match self.decision {
+                Some(decision) => format!(
+                    "{}\n",
+                    json!({
+                        "query": self.query.trim(),
+                        "routing": decision,
+                        "shard_rows": self.shard_rows,
+                    })
+                ),
+                None => format!("{}\n", self.query.trim()),
+            };
+            file.write_all(line.as_bytes()).await?;
         }
 
         Ok(())
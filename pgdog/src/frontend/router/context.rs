@@ -2,7 +2,7 @@ use super::Error;
 use crate::{
     backend::Cluster,
     frontend::{client::TransactionType, BufferedQuery, ClientRequest, PreparedStatements},
-    net::{Bind, Parameters},
+    net::{BackendKeyData, Bind, Parameters},
 };
 
 #[derive(Debug)]
@@ -23,6 +23,8 @@ pub struct RouterContext<'a> {
     pub copy_mode: bool,
     /// Do we have an executable buffer?
     pub executable: bool,
+    /// Client sending the query.
+    pub client_id: Option<BackendKeyData>,
 }
 
 impl<'a> RouterContext<'a> {
@@ -46,6 +48,7 @@ impl<'a> RouterContext<'a> {
             transaction,
             copy_mode,
             executable: buffer.executable(),
+            client_id: None,
         })
     }
 
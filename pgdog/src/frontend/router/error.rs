@@ -1,5 +1,7 @@
 use thiserror::Error;
 
+use super::ErrorClass;
+
 #[derive(Debug, Error)]
 pub enum Error {
     #[error("routing plugin missing")]
@@ -31,4 +33,66 @@ impl Error {
     pub fn empty_query(&self) -> bool {
         matches!(self, Self::Parser(super::parser::Error::EmptyQuery))
     }
+
+    /// Write rejected by a read-only database.
+    pub fn read_only(&self) -> bool {
+        matches!(self, Self::Parser(super::parser::Error::ReadOnly(..)))
+    }
+
+    /// Statement can't run inside a transaction.
+    pub fn in_transaction(&self) -> bool {
+        matches!(
+            self,
+            Self::Parser(super::parser::Error::VacuumInTransaction)
+        )
+    }
+
+    /// Query blocked by a plugin or a user's policy.
+    pub fn blocked(&self) -> bool {
+        matches!(
+            self,
+            Self::Parser(
+                super::parser::Error::BlockedByPlugin(_)
+                    | super::parser::Error::BlockedByPluginMessage(..)
+                    | super::parser::Error::BlockedStatement(..)
+                    | super::parser::Error::BlockedFunction(..)
+            )
+        )
+    }
+
+    /// Query was rejected because of the shards it would go to,
+    /// e.g. a cross-shard join or a sharding key that can't be routed.
+    pub fn routing(&self) -> bool {
+        ErrorClass::new(self) == ErrorClass::CrossShard
+            || matches!(
+                self,
+                Self::Parser(
+                    super::parser::Error::Sharder(_) | super::parser::Error::NullShardingKey { .. }
+                )
+            )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::net::ErrorResponse;
+
+    #[test]
+    fn test_blocked_by_plugin_message() {
+        let err = Error::Parser(super::super::parser::Error::BlockedByPluginMessage(
+            "tenants".into(),
+            "tenant_id required".into(),
+        ));
+        assert!(err.blocked());
+
+        // What the client receives.
+        let error = ErrorResponse::blocked(err.to_string().as_str());
+        assert_eq!(error.code, "42501");
+        assert_eq!(error.severity, "ERROR");
+        assert_eq!(
+            error.message,
+            "query is blocked by plugin \"tenants\": tenant_id required"
+        );
+    }
 }
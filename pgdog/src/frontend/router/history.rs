@@ -0,0 +1,262 @@
+//! Routing decisions made for each client, for debugging.
+//!
+//! Every client keeps its last `routing_history` decisions in a fixed-size
+//! ring buffer, shown by `SHOW ROUTING HISTORY <client_pid>`. Sharding key
+//! values are redacted unless `redact_sharding_keys` is disabled.
+
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::sync::Arc;
+
+use chrono::{SecondsFormat, Utc};
+use parking_lot::Mutex;
+use serde::Serialize;
+
+use super::parser::{CommandSummary, Shard};
+use super::sharding::{Data, Value};
+use super::{Command, Route};
+use crate::config::config;
+
+/// Maximum number of sharding key values kept for one decision.
+pub const MAX_SHARDING_VALUES: usize = 8;
+/// Sharding key values longer than this are truncated.
+pub const MAX_SHARDING_VALUE_LEN: usize = 64;
+/// Shown instead of redacted sharding key values.
+pub const REDACTED: &str = "<redacted>";
+
+/// Inputs to the router that decided the route.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct RoutingInputs {
+    /// Sharding key values extracted from the query, in the order they were found.
+    pub sharding_values: Vec<String>,
+    /// Shard set by a `pgdog_shard` or `pgdog_sharding_key` comment.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment_shard: Option<Shard>,
+    /// Shard the session is pinned to.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session_shard: Option<Shard>,
+    /// A plugin overrode the route.
+    pub plugin_override: bool,
+    /// Route came from a manual query.
+    pub manual_query: bool,
+}
+
+impl RoutingInputs {
+    /// Record a sharding key value.
+    pub fn sharding_value(&mut self, value: &Value) {
+        if self.sharding_values.len() >= MAX_SHARDING_VALUES {
+            return;
+        }
+
+        let mut text = match value.data() {
+            Data::Text(text) => text.to_string(),
+            Data::Integer(integer) => integer.to_string(),
+            Data::Binary(binary) => binary.iter().fold(String::from("\\x"), |mut text, byte| {
+                let _ = write!(text, "{:02x}", byte);
+                text
+            }),
+            Data::Null => "NULL".into(),
+        };
+
+        if text.len() > MAX_SHARDING_VALUE_LEN {
+            let mut end = MAX_SHARDING_VALUE_LEN;
+            while !text.is_char_boundary(end) {
+                end -= 1;
+            }
+            text.truncate(end);
+        }
+
+        self.sharding_values.push(text);
+    }
+
+    /// Replace sharding key values with a placeholder.
+    pub fn redact(&mut self) {
+        for value in &mut self.sharding_values {
+            *value = REDACTED.into();
+        }
+    }
+}
+
+/// Route chosen for a query and why.
+#[derive(Debug, Clone, Serialize)]
+pub struct RoutingDecision {
+    /// When the query was routed, in UTC.
+    pub timestamp: String,
+    /// Query fingerprint.
+    pub fingerprint: String,
+    pub command: CommandSummary,
+    /// Route taken by queries. Not set if routing failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub route: Option<Route>,
+    pub inputs: RoutingInputs,
+    /// Routing error, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl RoutingDecision {
+    /// Query routed successfully.
+    pub fn new(fingerprint: &str, command: &Command, inputs: &RoutingInputs) -> Self {
+        let mut inputs = inputs.clone();
+        if config().config.general.redact_sharding_keys {
+            inputs.redact();
+        }
+
+        Self {
+            timestamp: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            fingerprint: fingerprint.to_owned(),
+            command: CommandSummary::from(command),
+            route: match command {
+                Command::Query(route) => Some(route.clone()),
+                _ => None,
+            },
+            inputs,
+            error: None,
+        }
+    }
+
+    /// Router failed to route the query.
+    pub fn failed(fingerprint: &str, inputs: &RoutingInputs, error: &impl ToString) -> Self {
+        let mut decision = Self::new(fingerprint, &Command::default(), inputs);
+        decision.route = None;
+        decision.error = Some(error.to_string());
+        decision
+    }
+
+    /// Serialize the decision as JSON.
+    pub fn json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
+
+/// Last routing decisions made for a client.
+///
+/// Holds at most `capacity` decisions. Clones share the same buffer.
+#[derive(Debug, Clone, Default)]
+pub struct RoutingHistory {
+    decisions: Arc<Mutex<VecDeque<RoutingDecision>>>,
+    capacity: usize,
+}
+
+impl RoutingHistory {
+    /// Create history of the configured size.
+    pub fn new() -> Self {
+        Self::with_capacity(config().config.general.routing_history)
+    }
+
+    /// Create history holding up to `capacity` decisions.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            decisions: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+            capacity,
+        }
+    }
+
+    /// History is recorded.
+    pub fn enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    /// Record a decision, forgetting the oldest one if full.
+    pub fn record(&self, decision: RoutingDecision) {
+        if !self.enabled() {
+            return;
+        }
+
+        let mut decisions = self.decisions.lock();
+        if decisions.len() >= self.capacity {
+            decisions.pop_front();
+        }
+        decisions.push_back(decision);
+    }
+
+    /// Most recent decision.
+    pub fn last(&self) -> Option<RoutingDecision> {
+        self.decisions.lock().back().cloned()
+    }
+
+    /// All decisions, oldest first.
+    pub fn decisions(&self) -> Vec<RoutingDecision> {
+        self.decisions.lock().iter().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::DataType;
+
+    fn decision(fingerprint: &str) -> RoutingDecision {
+        RoutingDecision::new(
+            fingerprint,
+            &Command::Query(Route::read(Shard::Direct(1))),
+            &RoutingInputs::default(),
+        )
+    }
+
+    #[test]
+    fn test_routing_history_ring() {
+        let history = RoutingHistory::with_capacity(2);
+        for fingerprint in ["a", "b", "c"] {
+            history.record(decision(fingerprint));
+        }
+
+        let fingerprints = history
+            .decisions()
+            .into_iter()
+            .map(|decision| decision.fingerprint)
+            .collect::<Vec<_>>();
+        assert_eq!(fingerprints, vec!["b", "c"]);
+        assert_eq!(history.last().unwrap().fingerprint, "c");
+
+        let disabled = RoutingHistory::with_capacity(0);
+        disabled.record(decision("a"));
+        assert!(disabled.last().is_none());
+    }
+
+    #[test]
+    fn test_sharding_values() {
+        let mut inputs = RoutingInputs::default();
+        inputs.sharding_value(&Value::new("1234", DataType::Bigint));
+        inputs.sharding_value(&Value::new(&[1u8, 255][..], DataType::Bigint));
+        inputs.sharding_value(&Value::new("é".repeat(40).as_str(), DataType::Varchar));
+        for _ in 0..MAX_SHARDING_VALUES {
+            inputs.sharding_value(&Value::new(5i64, DataType::Bigint));
+        }
+
+        assert_eq!(inputs.sharding_values.len(), MAX_SHARDING_VALUES);
+        assert_eq!(inputs.sharding_values[0], "1234");
+        assert_eq!(inputs.sharding_values[1], "\\x01ff");
+        assert_eq!(inputs.sharding_values[2], "é".repeat(32));
+
+        inputs.redact();
+        assert!(inputs.sharding_values.iter().all(|value| value == REDACTED));
+    }
+
+    #[test]
+    fn test_decision_json() {
+        let mut inputs = RoutingInputs::default();
+        inputs.sharding_value(&Value::new("1234", DataType::Bigint));
+        inputs.comment_shard = Some(Shard::Direct(1));
+
+        let decision = RoutingDecision::new(
+            "abc",
+            &Command::Query(Route::read(Shard::Direct(1))),
+            &inputs,
+        );
+        let json: serde_json::Value = serde_json::from_str(&decision.json()).unwrap();
+
+        assert_eq!(json["fingerprint"], "abc");
+        assert_eq!(json["command"]["command"], "query");
+        assert_eq!(json["route"]["shard"]["direct"], 1);
+        assert_eq!(json["route"]["role"], "replica");
+        assert_eq!(json["inputs"]["sharding_values"][0], REDACTED);
+        assert_eq!(json["inputs"]["comment_shard"]["direct"], 1);
+        assert!(json.get("error").is_none());
+
+        let failed = RoutingDecision::failed("abc", &inputs, &"cross-shard join");
+        let json: serde_json::Value = serde_json::from_str(&failed.json()).unwrap();
+        assert!(json.get("route").is_none());
+        assert_eq!(json["error"], "cross-shard join");
+    }
+}
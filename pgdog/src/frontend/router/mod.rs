@@ -3,13 +3,16 @@
 pub mod context;
 pub mod copy;
 pub mod error;
+pub mod history;
 pub mod parser;
 pub mod round_robin;
 pub mod search_path;
 pub mod sharding;
+pub mod stats;
 
 pub use copy::CopyRow;
 pub use error::Error;
+pub use history::{RoutingDecision, RoutingHistory, RoutingInputs};
 use lazy_static::lazy_static;
 use parser::Shard;
 pub use parser::{Command, QueryParser, Route};
@@ -18,6 +21,7 @@ use super::ClientRequest;
 pub use context::RouterContext;
 pub use search_path::SearchPath;
 pub use sharding::{Lists, Ranges};
+pub use stats::{ErrorClass, RouterStats};
 
 /// Query router.
 #[derive(Debug)]
@@ -56,11 +60,18 @@ impl Router {
         }
 
         let command = self.query_parser.parse(context)?;
-        self.routed = !matches!(command, Command::StartTransaction(_));
+        self.routed = !matches!(command, Command::StartTransaction { .. });
         self.latest_command = command;
         Ok(&self.latest_command)
     }
 
+    /// Send the current query to a different shard.
+    pub fn override_shard(&mut self, shard: &Shard) {
+        if let Command::Query(ref mut route) = self.latest_command {
+            route.set_shard_raw_mut(shard);
+        }
+    }
+
     /// Parse CopyData messages and shard them.
     pub fn copy_data(&mut self, buffer: &ClientRequest) -> Result<Vec<CopyRow>, Error> {
         match self.latest_command {
@@ -102,6 +113,31 @@ impl Router {
         self.query_parser.in_transaction()
     }
 
+    /// Was the last statement found in the AST cache?
+    pub fn cache_hit(&self) -> Option<bool> {
+        self.query_parser.cache_hit()
+    }
+
+    /// Does the last statement only read data without locking it?
+    pub fn read_statement(&self) -> bool {
+        self.query_parser.read_statement()
+    }
+
+    /// Is the last statement DDL? `None` if it wasn't parsed.
+    pub fn ddl_statement(&self) -> Option<bool> {
+        self.query_parser.ddl_statement()
+    }
+
+    /// Fingerprint of the last statement, if routing needed it.
+    pub fn fingerprint(&self) -> Option<&str> {
+        self.query_parser.fingerprint()
+    }
+
+    /// Inputs that decided the route of the last statement.
+    pub fn inputs(&self) -> &RoutingInputs {
+        self.query_parser.inputs()
+    }
+
     /// Get last commmand computed by the query parser.
     pub fn command(&self) -> &Command {
         &self.latest_command
@@ -1,11 +1,20 @@
 //! AST cache.
 //!
 //! Shared between all clients and databases.
+//!
+//! The cache is bounded by `query_cache_limit`, evicting the least recently
+//! used statement on insert. Statements unused for longer than
+//! `query_cache_max_age` are removed by sweeps that run on inserts.
+//!
+//! Statements with syntax errors are remembered for a short time, so clients
+//! retrying them in a loop don't run the parser every time.
 
 use lru::LruCache;
 use once_cell::sync::Lazy;
 use pg_query::*;
 use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use std::time::{Duration, Instant};
 
 use parking_lot::Mutex;
 use std::sync::Arc;
@@ -15,6 +24,12 @@ use super::Route;
 
 static CACHE: Lazy<Cache> = Lazy::new(Cache::new);
 
+/// Number of statements with syntax errors remembered.
+const SYNTAX_ERRORS_LIMIT: usize = 1_000;
+
+/// How long syntax errors are remembered.
+const SYNTAX_ERRORS_MAX_AGE: Duration = Duration::from_secs(10);
+
 /// AST cache statistics.
 #[derive(Default, Debug, Copy, Clone)]
 pub struct Stats {
@@ -26,6 +41,20 @@ pub struct Stats {
     pub direct: usize,
     /// Multi-shard queries.
     pub multi: usize,
+    /// Statements evicted from the cache.
+    pub evictions: usize,
+    /// Age-based sweeps.
+    pub sweeps: usize,
+    /// How long the last sweep took.
+    pub last_sweep_duration: Duration,
+    /// Queries not parsed because they were too long.
+    pub skipped: usize,
+    /// Queries the parser failed on.
+    pub failed: usize,
+    /// Queries with syntax errors.
+    pub syntax_errors: usize,
+    /// Syntax errors returned without running the parser.
+    pub syntax_error_hits: usize,
 }
 
 /// Abstract syntax tree (query) cache entry,
@@ -39,6 +68,10 @@ pub struct CachedAst {
     pub stats: Arc<Mutex<Stats>>,
     /// Was this entry cached?
     pub cached: bool,
+    /// Was this entry found in the cache?
+    pub hit: bool,
+    /// When this entry was last used.
+    pub last_used: Instant,
 }
 
 impl CachedAst {
@@ -46,6 +79,8 @@ impl CachedAst {
     fn new(ast: ParseResult) -> Self {
         Self {
             cached: true,
+            hit: false,
+            last_used: Instant::now(),
             ast: Arc::new(ast),
             stats: Arc::new(Mutex::new(Stats {
                 hits: 1,
@@ -79,6 +114,77 @@ struct Inner {
     queries: LruCache<String, CachedAst>,
     /// Cache global stats.
     stats: Stats,
+    /// Remove statements unused for this long.
+    max_age: Option<Duration>,
+    /// When the cache was last swept.
+    last_sweep: Instant,
+    /// Statements with syntax errors: the error and when it was found.
+    syntax_errors: LruCache<String, (String, Instant)>,
+}
+
+impl Inner {
+    /// Add a statement, evicting the least recently used one
+    /// if the cache is full.
+    fn insert(&mut self, query: String, entry: CachedAst) {
+        if let Some((evicted, _)) = self.queries.push(query.clone(), entry) {
+            if evicted != query {
+                self.stats.evictions += 1;
+            }
+        }
+    }
+
+    /// Is it time to sweep the cache?
+    fn sweep_due(&self, now: Instant) -> bool {
+        self.max_age
+            .map(|max_age| now.duration_since(self.last_sweep) >= max_age / 2)
+            .unwrap_or(false)
+    }
+
+    /// Syntax error found in this statement recently.
+    fn syntax_error(&mut self, query: &str, now: Instant) -> Option<Error> {
+        let (error, found) = self.syntax_errors.get(query)?.clone();
+
+        if now.saturating_duration_since(found) >= SYNTAX_ERRORS_MAX_AGE {
+            self.syntax_errors.pop(query);
+            return None;
+        }
+
+        self.stats.syntax_error_hits += 1;
+        Some(Error::Parse(error))
+    }
+
+    /// Remove statements unused for longer than `max_age`.
+    ///
+    /// Statements still referenced by clients are kept. Prepared statements
+    /// aren't checked, so the sweep doesn't need their global lock; they're parsed
+    /// again the next time they run.
+    fn sweep(&mut self, now: Instant) -> usize {
+        let Some(max_age) = self.max_age else {
+            return 0;
+        };
+        let started = Instant::now();
+
+        let expired = self
+            .queries
+            .iter()
+            .filter(|(query, entry)| {
+                now.saturating_duration_since(entry.last_used) >= max_age
+                    && Arc::strong_count(&entry.ast) == 1
+            })
+            .map(|(query, _)| query.clone())
+            .collect::<Vec<_>>();
+
+        for query in &expired {
+            self.queries.pop(query);
+        }
+
+        self.stats.evictions += expired.len();
+        self.stats.sweeps += 1;
+        self.stats.last_sweep_duration = started.elapsed();
+        self.last_sweep = now;
+
+        expired.len()
+    }
 }
 
 /// AST cache.
@@ -94,6 +200,9 @@ impl Cache {
             inner: Arc::new(Mutex::new(Inner {
                 queries: LruCache::unbounded(),
                 stats: Stats::default(),
+                max_age: None,
+                last_sweep: Instant::now(),
+                syntax_errors: LruCache::new(NonZeroUsize::new(SYNTAX_ERRORS_LIMIT).unwrap()),
             })),
         }
     }
@@ -113,6 +222,29 @@ impl Cache {
         debug!("ast cache size set to {}", capacity);
     }
 
+    /// Remove statements unused for longer than `max_age`.
+    /// `None` disables age-based eviction.
+    pub fn set_max_age(max_age: Option<Duration>) {
+        CACHE.inner.lock().max_age = max_age;
+    }
+
+    /// Sweep the cache if it's time, removing statements
+    /// unused for longer than the configured max age.
+    fn maybe_sweep(&self) {
+        let now = Instant::now();
+        let removed = {
+            let mut guard = self.inner.lock();
+            if !guard.sweep_due(now) {
+                return;
+            }
+            guard.sweep(now)
+        };
+
+        if removed > 0 {
+            debug!("ast cache sweep removed {} statements", removed);
+        }
+    }
+
     /// Parse a statement by either getting it from cache
     /// or using pg_query parser.
     ///
@@ -124,7 +256,10 @@ impl Cache {
             let mut guard = self.inner.lock();
             let ast = guard.queries.get_mut(query).map(|entry| {
                 entry.stats.lock().hits += 1; // No contention on this.
-                entry.clone()
+                entry.last_used = Instant::now();
+                let mut entry = entry.clone();
+                entry.hit = true;
+                entry
             });
             if let Some(ast) = ast {
                 guard.stats.hits += 1;
@@ -133,38 +268,77 @@ impl Cache {
         }
 
         // Parse query without holding lock.
-        let entry = CachedAst::new(parse(query)?);
+        let entry = CachedAst::new(self.parse_checked(query)?);
+
+        {
+            let mut guard = self.inner.lock();
+            guard.insert(query.to_owned(), entry.clone());
+            guard.stats.misses += 1;
+        }
 
-        let mut guard = self.inner.lock();
-        guard.queries.put(query.to_owned(), entry.clone());
-        guard.stats.misses += 1;
+        self.maybe_sweep();
 
         Ok(entry)
     }
 
     /// Parse a statement but do not store it in the cache.
     pub fn parse_uncached(&self, query: &str) -> Result<CachedAst> {
-        let mut entry = CachedAst::new(parse(query)?);
+        let mut entry = CachedAst::new(self.parse_checked(query)?);
         entry.cached = false;
         Ok(entry)
     }
 
-    /// Record a query sent over the simple protocol, while removing parameters.
-    pub fn record_normalized(&self, query: &str, route: &Route) -> Result<()> {
-        let normalized = pg_query::normalize(query)?;
+    /// Run the parser, unless the statement had a syntax error recently.
+    ///
+    /// Only syntax errors are remembered. They depend on the text alone,
+    /// so the statement can't succeed when it's retried.
+    fn parse_checked(&self, query: &str) -> Result<ParseResult> {
+        if let Some(error) = self.inner.lock().syntax_error(query, Instant::now()) {
+            return Err(error);
+        }
 
-        let mut guard = self.inner.lock();
+        parse(query).inspect_err(|err| {
+            if let Error::Parse(error) = err {
+                let mut guard = self.inner.lock();
+                guard
+                    .syntax_errors
+                    .put(query.to_owned(), (error.clone(), Instant::now()));
+                guard.stats.syntax_errors += 1;
+            }
+        })
+    }
 
-        if let Some(entry) = guard.queries.get(&normalized) {
-            entry.update_stats(route);
-            guard.stats.hits += 1;
-        } else {
-            let entry = CachedAst::new(parse(&normalized)?);
+    /// Count a query that wasn't parsed because it's too long.
+    pub fn skipped(&self) {
+        self.inner.lock().stats.skipped += 1;
+    }
+
+    /// Count a query the parser failed on.
+    pub fn failed(&self) {
+        self.inner.lock().stats.failed += 1;
+    }
+
+    /// Record a query sent over the simple protocol, with parameters
+    /// already removed by [`pg_query::normalize`].
+    pub fn record_normalized(&self, normalized: &str, route: &Route) -> Result<()> {
+        {
+            let mut guard = self.inner.lock();
+
+            if let Some(entry) = guard.queries.get_mut(normalized) {
+                entry.update_stats(route);
+                entry.last_used = Instant::now();
+                guard.stats.hits += 1;
+                return Ok(());
+            }
+
+            let entry = CachedAst::new(parse(normalized)?);
             entry.update_stats(route);
-            guard.queries.put(normalized, entry);
+            guard.insert(normalized.to_owned(), entry);
             guard.stats.misses += 1;
         }
 
+        self.maybe_sweep();
+
         Ok(())
     }
 
@@ -207,14 +381,33 @@ impl Cache {
             .collect()
     }
 
+    /// Approximate memory used by the cache: query text and
+    /// cache entries. The size of the parse trees isn't known.
+    pub fn memory_usage() -> usize {
+        let entry = std::mem::size_of::<CachedAst>()
+            + std::mem::size_of::<ParseResult>()
+            + std::mem::size_of::<Stats>();
+
+        Self::get()
+            .inner
+            .lock()
+            .queries
+            .iter()
+            .map(|(query, _)| query.capacity() + entry)
+            .sum()
+    }
+
     /// Reset cache, removing all statements
     /// and setting stats to 0.
     pub fn reset() {
         let cache = Self::get();
         let mut guard = cache.inner.lock();
         guard.queries.clear();
+        guard.syntax_errors.clear();
         guard.stats.hits = 0;
         guard.stats.misses = 0;
+        guard.stats.evictions = 0;
+        guard.stats.sweeps = 0;
     }
 }
 
@@ -306,6 +499,78 @@ mod test {
         assert!(faster > 10.0);
     }
 
+    #[test]
+    fn test_lru_eviction() {
+        let cache = Cache::new();
+        cache.inner.lock().queries.resize(2.try_into().unwrap());
+
+        for q in 0..3 {
+            cache.parse(&format!("SELECT {}", q)).unwrap();
+        }
+        // Parsing a cached query doesn't evict anything.
+        assert!(cache.parse("SELECT 2").unwrap().hit);
+
+        let guard = cache.inner.lock();
+        assert_eq!(guard.queries.len(), 2);
+        assert_eq!(guard.stats.evictions, 1);
+        assert!(!guard.queries.contains("SELECT 0"));
+    }
+
+    #[test]
+    fn test_sweep() {
+        let cache = Cache::new();
+        cache.inner.lock().max_age = Some(Duration::from_millis(50));
+
+        cache.parse("SELECT 'unused'").unwrap();
+        let in_use = cache.parse("SELECT 'in use'").unwrap();
+
+        std::thread::sleep(Duration::from_millis(60));
+
+        let removed = cache.inner.lock().sweep(Instant::now());
+        assert_eq!(removed, 1);
+
+        let guard = cache.inner.lock();
+        assert!(!guard.queries.contains("SELECT 'unused'"));
+        assert!(guard.queries.contains("SELECT 'in use'"));
+        assert_eq!(guard.stats.evictions, 1);
+        assert_eq!(guard.stats.sweeps, 1);
+        drop(guard);
+        drop(in_use);
+
+        // Sweeps run on inserts.
+        std::thread::sleep(Duration::from_millis(60));
+        cache.parse("SELECT 'new'").unwrap();
+        let guard = cache.inner.lock();
+        assert!(!guard.queries.contains("SELECT 'in use'"));
+        assert!(guard.queries.contains("SELECT 'new'"));
+        assert_eq!(guard.stats.sweeps, 2);
+    }
+
+    #[test]
+    fn test_syntax_errors() {
+        let cache = Cache::new();
+        let query = "SELEKT * FROM users";
+
+        for _ in 0..5 {
+            assert!(matches!(cache.parse(query), Err(Error::Parse(_))));
+            assert!(matches!(cache.parse_uncached(query), Err(Error::Parse(_))));
+        }
+
+        let stats = cache.inner.lock().stats;
+        assert_eq!(stats.syntax_errors, 1);
+        assert_eq!(stats.syntax_error_hits, 9);
+        assert_eq!(stats.misses, 0);
+
+        // Syntax errors are forgotten after a while.
+        let later = Instant::now() + SYNTAX_ERRORS_MAX_AGE;
+        assert!(cache.inner.lock().syntax_error(query, later).is_none());
+        assert!(cache.parse(query).is_err());
+        assert_eq!(cache.inner.lock().stats.syntax_errors, 2);
+
+        // Valid statements aren't affected.
+        assert!(cache.parse("SELECT * FROM users").is_ok());
+    }
+
     #[test]
     fn test_normalize() {
         let q = "SELECT * FROM users WHERE id = 1";
@@ -1,14 +1,19 @@
 use super::*;
-use crate::{frontend::BufferedQuery, net::parameter::ParameterValue};
+use crate::{backend::pool::Setting, frontend::BufferedQuery, net::parameter::ParameterValue};
 use lazy_static::lazy_static;
+use serde::Serialize;
 
 #[derive(Debug, Clone)]
 pub enum Command {
     Query(Route),
     Copy(Box<CopyParser>),
-    StartTransaction(BufferedQuery),
+    StartTransaction {
+        query: BufferedQuery,
+        options: TransactionOptions,
+    },
     CommitTransaction,
     RollbackTransaction,
+    SetTransaction(TransactionOptions),
     ReplicationMeta,
     Set {
         name: String,
@@ -17,7 +22,13 @@ pub enum Command {
     PreparedStatement(Prepare),
     Rewrite(String),
     Shards(usize),
+    /// SHOW answered from server settings captured by the pool.
+    Show {
+        settings: Vec<Setting>,
+        all: bool,
+    },
     Deallocate,
+    SetDebug(bool),
     Listen {
         channel: String,
         shard: Shard,
@@ -43,6 +54,57 @@ impl Command {
     }
 }
 
+/// Kind of command, without its contents, e.g. for logging.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case", tag = "command")]
+pub enum CommandSummary {
+    Query,
+    Copy,
+    StartTransaction,
+    CommitTransaction,
+    RollbackTransaction,
+    SetTransaction,
+    ReplicationMeta,
+    Set { name: String },
+    PreparedStatement,
+    Rewrite,
+    Shards,
+    Show,
+    Deallocate,
+    SetDebug,
+    Listen { shard: Shard },
+    Notify { shard: Shard },
+    Unlisten,
+}
+
+impl From<&Command> for CommandSummary {
+    fn from(command: &Command) -> Self {
+        match command {
+            Command::Query(_) => Self::Query,
+            Command::Copy(_) => Self::Copy,
+            Command::StartTransaction { .. } => Self::StartTransaction,
+            Command::CommitTransaction => Self::CommitTransaction,
+            Command::RollbackTransaction => Self::RollbackTransaction,
+            Command::SetTransaction(_) => Self::SetTransaction,
+            Command::ReplicationMeta => Self::ReplicationMeta,
+            Command::Set { name, .. } => Self::Set { name: name.clone() },
+            Command::PreparedStatement(_) => Self::PreparedStatement,
+            Command::Rewrite(_) => Self::Rewrite,
+            Command::Shards(_) => Self::Shards,
+            Command::Show { .. } => Self::Show,
+            Command::Deallocate => Self::Deallocate,
+            Command::SetDebug(_) => Self::SetDebug,
+            Command::Listen { shard, .. } => Self::Listen {
+                shard: shard.clone(),
+            },
+            Command::Notify { shard, .. } => Self::Notify {
+                shard: shard.clone(),
+            },
+            Command::Unlisten(_) => Self::Unlisten,
+        }
+    }
+}
+
 impl Default for Command {
     fn default() -> Self {
         Command::Query(Route::write(Shard::All))
@@ -11,6 +11,8 @@ use super::Error;
 static SHARD: Lazy<Regex> = Lazy::new(|| Regex::new(r#"pgdog_shard: *([0-9]+)"#).unwrap());
 static SHARDING_KEY: Lazy<Regex> =
     Lazy::new(|| Regex::new(r#"pgdog_sharding_key: *([0-9a-zA-Z]+)"#).unwrap());
+static SQLCOMMENTER: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"([^\s=,/*]+)='((?:[^'\\]|\\.)*)'"#).unwrap());
 
 /// Extract shard number from a comment.
 ///
@@ -48,3 +50,82 @@ pub fn shard(query: &str, schema: &ShardingSchema) -> Result<Shard, Error> {
 
     Ok(Shard::All)
 }
+
+/// Extract key/value tags from a sqlcommenter comment,
+/// e.g. `/*traceparent='00-...-01',route='%2Fusers'*/`.
+///
+/// See <https://google.github.io/sqlcommenter/spec/>.
+pub fn tags(query: &str) -> Vec<(String, String)> {
+    let tokens = match scan(query) {
+        Ok(tokens) => tokens,
+        Err(_) => return vec![],
+    };
+
+    for token in tokens.tokens.iter() {
+        if token.token == Token::CComment as i32 {
+            let comment = &query[token.start as usize..token.end as usize];
+            let tags = SQLCOMMENTER
+                .captures_iter(comment)
+                .filter_map(|cap| {
+                    let key = unescape(cap.get(1)?.as_str());
+                    let value = unescape(&cap.get(2)?.as_str().replace(r"\'", "'"));
+                    Some((key, value))
+                })
+                .collect::<Vec<_>>();
+
+            if !tags.is_empty() {
+                return tags;
+            }
+        }
+    }
+
+    vec![]
+}
+
+/// Decode URL-encoded characters, e.g. `%2F`.
+fn unescape(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok();
+            if let Some(byte) = hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_sqlcommenter_tags() {
+        let query = r"SELECT * FROM users /*action='index',controller='users',traceparent='00-5bd66ef5095369c7b0d1f8f4bd33716a-c532cb4098ac3dd2-01',route='%2Fusers%2F%3Aid',name='O\'Brien'*/";
+        assert_eq!(
+            tags(query),
+            vec![
+                ("action".into(), "index".into()),
+                ("controller".into(), "users".into()),
+                (
+                    "traceparent".into(),
+                    "00-5bd66ef5095369c7b0d1f8f4bd33716a-c532cb4098ac3dd2-01".into()
+                ),
+                ("route".into(), "/users/:id".into()),
+                ("name".into(), "O'Brien".into()),
+            ]
+        );
+
+        assert!(tags("SELECT 1 /* pgdog_shard: 1 */").is_empty());
+        assert!(tags("SELECT 'a=''b''' -- c='d'").is_empty());
+    }
+}
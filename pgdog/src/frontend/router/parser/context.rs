@@ -1,18 +1,25 @@
 //! Shortcut the parser given the cluster config.
 
-use std::os::raw::c_void;
+use std::{os::raw::c_void, slice::from_raw_parts, str::from_utf8};
 
+use pg_query::NodeEnum;
 use pgdog_plugin::pg_query::protobuf::ParseResult;
-use pgdog_plugin::{PdParameters, PdRouterContext, PdStatement};
+use pgdog_plugin::{
+    DataType as PdDataType, PdParameters, PdRouterContext, PdSessionParameters, PdStatement, PdStr,
+};
 
+use crate::frontend::router::sharding::{ContextBuilder, Data};
 use crate::net::Bind;
 use crate::{
     backend::ShardingSchema,
-    config::{config, MultiTenant, ReadWriteStrategy},
+    config::{
+        config, CrossShardJoin, DataType, MultiTenant, OversizedQuery, PoolerMode,
+        ReadWriteStrategy, ShardedTable,
+    },
     frontend::{BufferedQuery, PreparedStatements, RouterContext},
 };
 
-use super::Error;
+use super::{Error, FunctionLists, Policy, Shard};
 
 /// Query parser context.
 ///
@@ -24,6 +31,8 @@ pub struct QueryParserContext<'a> {
     pub(super) read_only: bool,
     /// Cluster has no replicas, only a primary.
     pub(super) write_only: bool,
+    /// Databases are configured `read_only`, writes are rejected.
+    pub(super) writes_disabled: bool,
     /// Number of shards in the cluster.
     pub(super) shards: usize,
     /// Which tables are sharded and using which columns.
@@ -32,6 +41,10 @@ pub struct QueryParserContext<'a> {
     pub(super) router_context: RouterContext<'a>,
     /// How aggressively we want to send reads to replicas.
     pub(super) rw_strategy: &'a ReadWriteStrategy,
+    /// Functions configured as read-safe or writing.
+    pub(super) function_lists: &'a FunctionLists,
+    /// Statements and functions blocked for the user.
+    pub(super) policy: &'a Policy,
     /// Are we re-writing prepared statements sent over the simple protocol?
     pub(super) full_prepared_statements: bool,
     /// Do we need the router at all? Shortcut to bypass this for unsharded
@@ -43,6 +56,20 @@ pub struct QueryParserContext<'a> {
     pub(super) multi_tenant: &'a Option<MultiTenant>,
     /// Dry run enabled?
     pub(super) dry_run: bool,
+    /// Server connections are in replication mode.
+    pub(super) replication_mode: bool,
+    /// What to do with joins that aren't shard-safe.
+    pub(super) cross_shard_join: CrossShardJoin,
+    /// Reject `SET ROLE` and `SET SESSION AUTHORIZATION`.
+    pub(super) reject_set_role: bool,
+    /// Don't parse queries longer than this.
+    pub(super) max_query_length_for_parsing: Option<usize>,
+    /// How to route queries that are too long to parse.
+    pub(super) oversized_query: OversizedQuery,
+    /// Send reads to replicas while the primary is banned.
+    pub(super) degraded_reads: bool,
+    /// Record the inputs that decided the route.
+    pub(super) record_inputs: bool,
 }
 
 impl<'a> QueryParserContext<'a> {
@@ -52,21 +79,33 @@ impl<'a> QueryParserContext<'a> {
         Self {
             read_only: router_context.cluster.read_only(),
             write_only: router_context.cluster.write_only(),
+            writes_disabled: router_context.cluster.writes_disabled(),
             shards: router_context.cluster.shards().len(),
             sharding_schema: router_context.cluster.sharding_schema(),
             rw_strategy: router_context.cluster.read_write_strategy(),
+            function_lists: router_context.cluster.function_lists(),
+            policy: router_context.cluster.policy(),
             full_prepared_statements: config.prepared_statements_full(),
             router_needed: router_context.cluster.router_needed(),
             pub_sub_enabled: config.config.general.pub_sub_enabled(),
             multi_tenant: router_context.cluster.multi_tenant(),
             dry_run: config.config.general.dry_run,
+            replication_mode: router_context.cluster.replication_mode(),
+            cross_shard_join: config.config.general.cross_shard_join,
+            reject_set_role: config.config.general.reject_set_role
+                && router_context.cluster.pooler_mode() == PoolerMode::Transaction,
+            max_query_length_for_parsing: config.config.general.max_query_length_for_parsing,
+            oversized_query: config.config.general.oversized_query,
+            degraded_reads: config.config.general.degraded_reads,
+            record_inputs: config.config.general.routing_history > 0
+                || config.config.general.query_log.is_some(),
             router_context,
         }
     }
 
     /// Write override enabled?
     pub(super) fn write_override(&self) -> bool {
-        self.router_context.in_transaction() && self.rw_conservative()
+        self.router_context.in_transaction() && self.rw_conservative() && !self.writes_disabled
     }
 
     /// Are we using the conservative read/write separation strategy?
@@ -83,6 +122,71 @@ impl<'a> QueryParserContext<'a> {
             || self.pub_sub_enabled
             || self.multi_tenant().is_some()
             || self.dry_run
+            || self.writes_disabled
+            || !self.policy.is_empty()
+    }
+
+    /// Query is `SET pgdog.debug` or `RESET pgdog.debug`, which are handled
+    /// by the parser even if it's otherwise disabled.
+    pub(super) fn set_debug(&self) -> bool {
+        let query = match self.router_context.query {
+            Some(ref query) => query.query(),
+            None => return false,
+        };
+
+        let set = query
+            .split_whitespace()
+            .next()
+            .map(|keyword| {
+                keyword.eq_ignore_ascii_case("set") || keyword.eq_ignore_ascii_case("reset")
+            })
+            .unwrap_or(false);
+
+        // Only SETs are parsed here, so the shortcut stays cheap for everything else.
+        set && pg_query::parse(query)
+            .map(|ast| {
+                ast.protobuf.stmts.iter().any(|stmt| {
+                    matches!(
+                        stmt.stmt.as_ref().and_then(|stmt| stmt.node.as_ref()),
+                        Some(NodeEnum::VariableSetStmt(stmt)) if stmt.name == "pgdog.debug"
+                    )
+                })
+            })
+            .unwrap_or(false)
+    }
+
+    /// Query is `SHOW`, `SET` or `RESET`, which are handled by the parser even if it's
+    /// otherwise disabled. SHOW can be answered from client params, so SETs must be tracked.
+    pub(super) fn show_or_set(&self) -> bool {
+        if let Some(BufferedQuery::Query(ref query)) = self.router_context.query {
+            query
+                .query()
+                .split_whitespace()
+                .next()
+                .map(|keyword| {
+                    ["show", "set", "reset"]
+                        .iter()
+                        .any(|command| keyword.eq_ignore_ascii_case(command))
+                })
+                .unwrap_or(false)
+        } else {
+            false
+        }
+    }
+
+    /// Query is `SET ROLE`, `SET SESSION AUTHORIZATION` or their `RESET`, which
+    /// are handled by the parser even if it's otherwise disabled.
+    pub(super) fn set_role(&self) -> bool {
+        self.router_context
+            .query
+            .as_ref()
+            .map(|query| {
+                let query = query.query().to_lowercase();
+                let mut words = query.split_whitespace();
+                matches!(words.next(), Some("set" | "reset"))
+                    && (query.contains("role") || query.contains("authorization"))
+            })
+            .unwrap_or(false)
     }
 
     /// Get the query we're parsing, if any.
@@ -130,6 +234,139 @@ impl<'a> QueryParserContext<'a> {
             query: unsafe { PdStatement::from_proto(ast) },
             write_override: 0, // This is set inside `QueryParser::plugins`.
             params,
+            // SAFETY: Same as above, the sharding schema outlives plugin execution.
+            sharding_schema: &self.sharding_schema as *const ShardingSchema as *const c_void,
+            shard_for_value: Some(shard_for_value),
+            // This is set inside `QueryParser::plugins`.
+            session_params: PdSessionParameters::default(),
         }
     }
 }
+
+/// Sharding function called by plugins via [`pgdog_plugin::Context::shard_for_value`].
+///
+/// Returns the shard number or `-1` if the value can't be mapped to one shard.
+///
+/// # Safety
+///
+/// `sharding_schema` must point to a valid [`ShardingSchema`]
+/// and `value` must be valid for the duration of the call.
+pub(crate) unsafe extern "C" fn shard_for_value(
+    sharding_schema: *const c_void,
+    value: PdStr,
+    format: u8,
+    data_type: u8,
+) -> i64 {
+    if sharding_schema.is_null() || value.data.is_null() {
+        return -1;
+    }
+
+    let schema = unsafe { &*(sharding_schema as *const ShardingSchema) };
+    if schema.shards == 0 {
+        return -1;
+    }
+
+    let data_type = match PdDataType::try_from(data_type) {
+        Ok(PdDataType::Bigint) => DataType::Bigint,
+        Ok(PdDataType::Uuid) => DataType::Uuid,
+        Ok(PdDataType::Varchar) => DataType::Varchar,
+        Ok(PdDataType::Vector) => DataType::Vector,
+        Err(_) => return -1,
+    };
+
+    let bytes = unsafe { from_raw_parts(value.data as *const u8, value.len) };
+    let data = match format {
+        0 => match from_utf8(bytes) {
+            Ok(text) => Data::Text(text),
+            Err(_) => return -1,
+        },
+        1 => Data::Binary(bytes),
+        _ => return -1,
+    };
+
+    // Use the hasher and mappings of a sharded table with the same data type.
+    let default = ShardedTable {
+        data_type,
+        ..Default::default()
+    };
+    let table = schema
+        .tables()
+        .tables()
+        .iter()
+        .find(|table| table.data_type == data_type && !table.composite())
+        .unwrap_or(&default);
+
+    let shard = ContextBuilder::new(table)
+        .data(data)
+        .shards(schema.shards)
+        .round_robin(&schema.round_robin)
+        .build()
+        .and_then(|context| context.apply());
+
+    match shard {
+        Ok(Shard::Direct(shard)) => shard as i64,
+        _ => -1,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pgdog_plugin::{Context, ParameterValue};
+
+    use super::*;
+    use crate::backend::ShardedTables;
+    use crate::frontend::router::sharding::bigint;
+
+    #[test]
+    fn test_plugin_shard_for_value() {
+        let schema = ShardingSchema {
+            shards: 3,
+            tables: ShardedTables::new(
+                vec![ShardedTable {
+                    data_type: DataType::Bigint,
+                    column: "id".into(),
+                    ..Default::default()
+                }],
+                vec![],
+            ),
+            ..Default::default()
+        };
+
+        let proto = pgdog_plugin::pg_query::parse("SELECT 1").unwrap().protobuf;
+        let context: Context = PdRouterContext {
+            shards: 3,
+            has_replicas: 0,
+            has_primary: 1,
+            in_transaction: 0,
+            write_override: 0,
+            query: unsafe { PdStatement::from_proto(&proto) },
+            params: PdParameters::default(),
+            sharding_schema: &schema as *const ShardingSchema as *const c_void,
+            shard_for_value: Some(shard_for_value),
+            session_params: PdSessionParameters::default(),
+        }
+        .into();
+
+        for id in 0..25_i64 {
+            let expected = bigint(id) as usize % 3;
+            let text = id.to_string();
+            assert_eq!(
+                context.shard_for_value(ParameterValue::Text(&text), PdDataType::Bigint),
+                Some(expected)
+            );
+            let binary = id.to_be_bytes();
+            assert_eq!(
+                context.shard_for_value(ParameterValue::Binary(&binary), PdDataType::Bigint),
+                Some(expected)
+            );
+        }
+
+        assert_eq!(
+            context.shard_for_value(ParameterValue::Text("not a number"), PdDataType::Bigint),
+            None
+        );
+        assert!(context
+            .shard_for_value(ParameterValue::Text("test"), PdDataType::Varchar)
+            .is_some());
+    }
+}
@@ -7,7 +7,7 @@ use crate::{
     config::ShardedTable,
     frontend::router::{
         parser::Shard,
-        sharding::{ContextBuilder, Tables},
+        sharding::{self, Context, ContextBuilder, Tables, Value},
         CopyRow,
     },
     net::messages::{CopyData, ToBytes},
@@ -15,6 +15,9 @@ use crate::{
 
 use super::{binary::Data, BinaryStream, Column, CsvStream, Error, Table};
 
+/// Maximum length of row text included in errors.
+const MAX_ROW_TEXT: usize = 64;
+
 /// Copy information parsed from a COPY statement.
 #[derive(Debug, Clone)]
 pub struct CopyInfo {
@@ -68,8 +71,10 @@ pub struct CopyParser {
     sharding_schema: ShardingSchema,
     /// This COPY is dealing with a sharded table.
     sharded_table: Option<ShardedTable>,
-    /// The sharding column is in this position in each row.
-    sharded_column: usize,
+    /// The sharding key columns are in these positions in each row.
+    sharded_columns: Vec<usize>,
+    /// Number of rows parsed so far.
+    rows: usize,
 }
 
 impl Default for CopyParser {
@@ -82,7 +87,8 @@ impl Default for CopyParser {
             stream: CopyStream::Text(Box::new(CsvStream::new(',', false, CopyFormat::Csv, "\\N"))),
             sharding_schema: ShardingSchema::default(),
             sharded_table: None,
-            sharded_column: 0,
+            sharded_columns: vec![],
+            rows: 0,
         }
     }
 }
@@ -96,7 +102,7 @@ impl CopyParser {
         };
 
         let mut format = CopyFormat::Text;
-        let mut null_string = "\\N".to_owned();
+        let mut null_string = None;
 
         if let Some(ref rel) = stmt.relation {
             let mut columns = vec![];
@@ -111,7 +117,7 @@ impl CopyParser {
 
             if let Some(key) = Tables::new(&cluster.sharding_schema()).key(table, &columns) {
                 parser.sharded_table = Some(key.table.clone());
-                parser.sharded_column = key.position;
+                parser.sharded_columns = key.positions;
             }
 
             parser.columns = columns.len();
@@ -155,7 +161,7 @@ impl CopyParser {
                         "null" => {
                             if let Some(ref arg) = elem.arg {
                                 if let Some(NodeEnum::String(ref string)) = arg.node {
-                                    null_string = string.sval.clone();
+                                    null_string = Some(string.sval.clone());
                                 }
                             }
                         }
@@ -169,6 +175,14 @@ impl CopyParser {
         parser.stream = if format == CopyFormat::Binary {
             CopyStream::Binary(BinaryStream::default())
         } else {
+            // NULL is an unquoted empty string in CSV and \N in text format.
+            let null_string = null_string.unwrap_or_else(|| {
+                if format == CopyFormat::Csv {
+                    String::new()
+                } else {
+                    "\\N".to_owned()
+                }
+            });
             CopyStream::Text(Box::new(CsvStream::new(
                 parser.delimiter(),
                 parser.headers,
@@ -210,18 +224,26 @@ impl CopyParser {
                     for record in stream.records() {
                         // Totally broken.
                         let record = record?;
+                        self.rows += 1;
 
                         let shard = if let Some(table) = &self.sharded_table {
-                            let key = record
-                                .get(self.sharded_column)
-                                .ok_or(Error::NoShardingColumn)?;
+                            let mut values = vec![];
+                            for (i, position) in self.sharded_columns.iter().enumerate() {
+                                let key = record.get(*position).ok_or(Error::NoShardingColumn)?;
+                                values.push(if record.is_null(*position) {
+                                    Value::null(table.data_type_at(i))
+                                } else {
+                                    Value::new(key, table.data_type_at(i))
+                                });
+                            }
 
                             let ctx = ContextBuilder::new(table)
-                                .data(key)
+                                .values(values)
                                 .shards(self.sharding_schema.shards)
+                                .round_robin(&self.sharding_schema.round_robin)
                                 .build()?;
 
-                            ctx.apply()?
+                            Self::apply(ctx, self.rows, || record.to_string())?
                         } else {
                             Shard::All
                         };
@@ -244,20 +266,26 @@ impl CopyParser {
                             rows.push(CopyRow::new(&terminator, Shard::All));
                             break;
                         }
+                        self.rows += 1;
                         let shard = if let Some(table) = &self.sharded_table {
-                            let key = tuple
-                                .get(self.sharded_column)
-                                .ok_or(Error::NoShardingColumn)?;
-                            if let Data::Column(key) = key {
-                                let ctx = ContextBuilder::new(table)
-                                    .data(&key[..])
-                                    .shards(self.sharding_schema.shards)
-                                    .build()?;
-
-                                ctx.apply()?
-                            } else {
-                                Shard::All
+                            let mut values = vec![];
+                            for (i, position) in self.sharded_columns.iter().enumerate() {
+                                let key = tuple.get(*position).ok_or(Error::NoShardingColumn)?;
+                                values.push(match key {
+                                    Data::Column(key) => {
+                                        Value::new(&key[..], table.data_type_at(i))
+                                    }
+                                    Data::Null => Value::null(table.data_type_at(i)),
+                                });
                             }
+
+                            let ctx = ContextBuilder::new(table)
+                                .values(values)
+                                .shards(self.sharding_schema.shards)
+                                .round_robin(&self.sharding_schema.round_robin)
+                                .build()?;
+
+                            Self::apply(ctx, self.rows, || format!("{:?}", tuple))?
                         } else {
                             Shard::All
                         };
@@ -270,12 +298,30 @@ impl CopyParser {
 
         Ok(rows)
     }
+
+    /// Get the shard for a row, reporting which row has a NULL sharding key.
+    fn apply(ctx: Context<'_>, row: usize, text: impl Fn() -> String) -> Result<Shard, Error> {
+        match ctx.apply() {
+            Err(sharding::Error::NullShardingKey) => {
+                let text = text();
+                let text = text.trim_end();
+                let text = match text.char_indices().nth(MAX_ROW_TEXT) {
+                    Some((end, _)) => format!("{}...", &text[..end]),
+                    None => text.to_owned(),
+                };
+                Err(Error::NullShardingKey { row, text })
+            }
+            result => Ok(result?),
+        }
+    }
 }
 
 #[cfg(test)]
 mod test {
     use pg_query::parse;
 
+    use crate::config::NullShard;
+
     use super::*;
 
     #[test]
@@ -429,4 +475,96 @@ mod test {
         assert_eq!(sharded[1].message().data().len(), 2 + 4 + 8 + 4 + 3);
         assert_eq!(sharded[2].message().data(), (-1_i16).to_be_bytes());
     }
+
+    #[test]
+    fn test_copy_composite_key() {
+        use crate::config::DataType;
+        use crate::frontend::router::sharding::{bigint, combine, varchar};
+
+        let mut copy = CopyParser {
+            sharding_schema: ShardingSchema {
+                shards: 4,
+                ..Default::default()
+            },
+            sharded_table: Some(ShardedTable {
+                name: Some("events".into()),
+                columns: vec!["tenant_id".into(), "region".into()],
+                data_types: vec![DataType::Bigint, DataType::Varchar],
+                ..Default::default()
+            }),
+            sharded_columns: vec![2, 0],
+            ..Default::default()
+        };
+
+        let rows = copy.shard(&[CopyData::new(b"us,data,5\n")]).unwrap();
+        assert_eq!(
+            rows[0].shard(),
+            &Shard::Direct(combine(bigint(5), varchar(b"us")) as usize % 4)
+        );
+    }
+
+    fn null_copy(format: CopyFormat, null_shard: NullShard) -> CopyParser {
+        let (delimiter, null_string) = match format {
+            CopyFormat::Csv => (',', ""),
+            _ => ('\t', "\\N"),
+        };
+
+        CopyParser {
+            delimiter: Some(delimiter),
+            stream: CopyStream::Text(Box::new(CsvStream::new(
+                delimiter,
+                false,
+                format,
+                null_string,
+            ))),
+            sharding_schema: ShardingSchema {
+                shards: 2,
+                ..Default::default()
+            },
+            sharded_table: Some(ShardedTable {
+                name: Some("sharded".into()),
+                column: "id".into(),
+                null_shard,
+                ..Default::default()
+            }),
+            sharded_columns: vec![0],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_copy_null_key() {
+        let mut copy = null_copy(CopyFormat::Text, NullShard::Shard(1));
+        let rows = copy.shard(&[CopyData::new(b"\\N\tnull\n")]).unwrap();
+        assert_eq!(rows[0].shard(), &Shard::Direct(1));
+
+        let mut copy = null_copy(CopyFormat::Text, NullShard::Error);
+        let err = copy
+            .shard(&[CopyData::new(b"1\tone\n\\N\tnull\n")])
+            .unwrap_err();
+        match err {
+            Error::NullShardingKey { row, text } => {
+                assert_eq!(row, 2);
+                assert_eq!(text, "\\N\tnull");
+            }
+            err => panic!("unexpected error: {:?}", err),
+        }
+    }
+
+    #[test]
+    fn test_copy_null_key_csv() {
+        let mut copy = null_copy(CopyFormat::Csv, NullShard::RoundRobin);
+        let rows = copy.shard(&[CopyData::new(b",a\n,b\n")]).unwrap();
+        let shards = rows
+            .iter()
+            .map(|row| row.shard().clone())
+            .collect::<Vec<_>>();
+        assert!(shards.iter().all(|shard| matches!(shard, Shard::Direct(_))));
+
+        // Quoted empty string is not NULL.
+        let mut copy = null_copy(CopyFormat::Csv, NullShard::Error);
+        let rows = copy.shard(&[CopyData::new(b"\"\",b\n")]).unwrap();
+        assert!(matches!(rows[0].shard(), Shard::Direct(_)));
+        assert_eq!(rows[0].message().data(), b"\"\",\"b\"\n");
+    }
 }
@@ -106,12 +106,21 @@ impl CsvStream {
                 }
 
                 ReadRecordResult::Record => {
+                    let quoted = if self.format == CopyFormat::Csv {
+                        record::quoted_fields(
+                            &self.buffer[self.read..self.read + read],
+                            self.delimiter as u8,
+                        )
+                    } else {
+                        vec![]
+                    };
                     let record = Record::new(
                         &self.record[..written],
                         &self.ends[..ends],
                         self.delimiter,
                         self.format,
                         &self.null_string,
+                        quoted,
                     );
                     self.read += read;
                     self.record.fill(0u8);
@@ -215,4 +224,24 @@ mod test {
         let output = record.to_string();
         assert_eq!(output, "\"four\",\\N,\\N\n");
     }
+
+    #[test]
+    fn test_csv_quoted_null() {
+        let csv = "a,,\"\",\"x,\"\"y\"\"\",\nb,c,d,e,\"\"\n";
+        let mut reader = CsvStream::new(',', false, CopyFormat::Csv, "");
+        reader.write(csv.as_bytes());
+
+        let record = reader.record().unwrap().unwrap();
+        assert_eq!(record.get(1), Some(""));
+        assert_eq!(record.get(2), Some(""));
+        assert_eq!(record.get(3), Some("x,\"y\""));
+        assert!(!record.is_null(0));
+        assert!(record.is_null(1));
+        assert!(!record.is_null(2));
+        assert!(!record.is_null(3));
+        assert!(record.is_null(4));
+
+        let record = reader.record().unwrap().unwrap();
+        assert!(!record.is_null(4));
+    }
 }
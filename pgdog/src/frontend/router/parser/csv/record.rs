@@ -14,6 +14,8 @@ pub struct Record {
     pub format: CopyFormat,
     /// Null string.
     pub null_string: String,
+    /// Fields that were quoted in the CSV input.
+    pub quoted: Vec<bool>,
 }
 
 impl std::fmt::Debug for Record {
@@ -24,6 +26,7 @@ impl std::fmt::Debug for Record {
             .field("delimiter", &self.delimiter)
             .field("format", &self.format)
             .field("null_string", &self.null_string)
+            .field("quoted", &self.quoted)
             .finish()
     }
 }
@@ -37,10 +40,10 @@ impl std::fmt::Display for Record {
                 .map(|field| match self.format {
                     CopyFormat::Csv => {
                         let text = self.get(field).unwrap();
-                        if text == self.null_string {
+                        if self.is_null(field) {
                             text.to_owned()
                         } else {
-                            format!("\"{}\"", self.get(field).unwrap())
+                            format!("\"{}\"", text)
                         }
                     }
                     _ => self.get(field).unwrap().to_string(),
@@ -58,6 +61,7 @@ impl Record {
         delimiter: char,
         format: CopyFormat,
         null_string: &str,
+        quoted: Vec<bool>,
     ) -> Self {
         let mut last = 0;
         let mut fields = vec![];
@@ -71,6 +75,7 @@ impl Record {
             delimiter,
             format,
             null_string: null_string.to_owned(),
+            quoted,
         }
     }
 
@@ -90,4 +95,46 @@ impl Record {
             .cloned()
             .and_then(|range| from_utf8(&self.data[range]).ok())
     }
+
+    /// Field is NULL. In CSV, a quoted null string is a regular value,
+    /// e.g. `""` is an empty string while an empty unquoted field is NULL.
+    pub fn is_null(&self, index: usize) -> bool {
+        self.get(index) == Some(self.null_string.as_str())
+            && !self.quoted.get(index).copied().unwrap_or(false)
+    }
+}
+
+/// Find which fields of a raw CSV record are quoted.
+pub(super) fn quoted_fields(raw: &[u8], delimiter: u8) -> Vec<bool> {
+    let mut quoted = vec![];
+    let mut field_start = true;
+    let mut in_quotes = false;
+
+    for &byte in raw {
+        if field_start {
+            field_start = false;
+            quoted.push(byte == b'"');
+            if byte == b'"' {
+                in_quotes = true;
+                continue;
+            }
+        }
+
+        if byte == b'"' {
+            // Closing quote, or escaped quote inside a quoted field.
+            in_quotes = !in_quotes;
+        } else if !in_quotes {
+            if byte == delimiter {
+                field_start = true;
+            } else if byte == b'\n' || byte == b'\r' {
+                break;
+            }
+        }
+    }
+
+    if field_start {
+        quoted.push(false);
+    }
+
+    quoted
 }
@@ -0,0 +1,167 @@
+//! Dry run routing decisions.
+//!
+//! Records what the query router would have done for each query,
+//! while everything is actually sent to shard 0.
+
+use std::collections::HashMap;
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use tracing::info;
+
+use super::Shard;
+
+static DRY_RUN: Lazy<Mutex<DryRun>> = Lazy::new(|| Mutex::new(DryRun::default()));
+
+/// Maximum number of cross-shard fingerprints kept.
+const TOP_K: usize = 100;
+
+/// Cross-shard query seen in dry run mode.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CrossShard {
+    /// Query fingerprint.
+    pub fingerprint: String,
+    /// Last query seen with this fingerprint, normalized.
+    pub query: String,
+    /// Computed route.
+    pub shard: Shard,
+    /// Number of times this query was seen. Can overcount
+    /// for fingerprints that replaced an evicted one.
+    pub count: usize,
+}
+
+/// Dry run summary.
+#[derive(Debug, Clone, Default)]
+pub struct DryRun {
+    /// Queries by computed shard.
+    shards: HashMap<String, usize>,
+    /// Most frequent cross-shard queries.
+    cross_shard: HashMap<String, CrossShard>,
+}
+
+impl DryRun {
+    /// Record the computed route for a query.
+    pub fn record(&mut self, fingerprint: &str, query: &str, shard: &Shard) {
+        *self.shards.entry(shard.to_string()).or_default() += 1;
+
+        if !matches!(shard, Shard::Direct(_)) {
+            self.record_cross_shard(fingerprint, query, shard);
+        }
+    }
+
+    /// Keep the top K cross-shard fingerprints. When full, the least
+    /// frequent fingerprint is replaced and the new one inherits its count.
+    fn record_cross_shard(&mut self, fingerprint: &str, query: &str, shard: &Shard) {
+        if let Some(entry) = self.cross_shard.get_mut(fingerprint) {
+            entry.count += 1;
+            entry.query = query.to_owned();
+            entry.shard = shard.clone();
+            return;
+        }
+
+        let mut count = 1;
+        if self.cross_shard.len() >= TOP_K {
+            let evict = self
+                .cross_shard
+                .values()
+                .min_by_key(|entry| entry.count)
+                .map(|entry| (entry.fingerprint.clone(), entry.count));
+            if let Some((evict, evicted_count)) = evict {
+                self.cross_shard.remove(&evict);
+                count += evicted_count;
+            }
+        }
+
+        self.cross_shard.insert(
+            fingerprint.to_owned(),
+            CrossShard {
+                fingerprint: fingerprint.to_owned(),
+                query: query.to_owned(),
+                shard: shard.clone(),
+                count,
+            },
+        );
+    }
+
+    /// Query counts by computed shard, sorted by shard.
+    pub fn shards(&self) -> Vec<(String, usize)> {
+        let mut shards = self
+            .shards
+            .iter()
+            .map(|(shard, count)| (shard.clone(), *count))
+            .collect::<Vec<_>>();
+        shards.sort();
+        shards
+    }
+
+    /// Cross-shard queries, most frequent first.
+    pub fn cross_shard(&self) -> Vec<CrossShard> {
+        let mut queries = self.cross_shard.values().cloned().collect::<Vec<_>>();
+        queries.sort_by(|a, b| {
+            b.count
+                .cmp(&a.count)
+                .then_with(|| a.fingerprint.cmp(&b.fingerprint))
+        });
+        queries
+    }
+}
+
+/// Log and record the route computed in dry run mode. The query is normalized,
+/// so parameter values don't end up in the logs.
+pub fn record(fingerprint: &str, query: &str, computed: &Shard, actual: &Shard) {
+    info!(
+        r#"[dry run] fingerprint="{}" computed="{}" actual="{}" query="{}""#,
+        fingerprint, computed, actual, query
+    );
+
+    DRY_RUN.lock().record(fingerprint, query, computed);
+}
+
+/// Get a copy of the dry run summary.
+pub fn summary() -> DryRun {
+    DRY_RUN.lock().clone()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_dry_run_summary() {
+        let mut dry_run = DryRun::default();
+
+        dry_run.record("a", "SELECT 1", &Shard::Direct(1));
+        dry_run.record("b", "SELECT 2", &Shard::All);
+        dry_run.record("b", "SELECT 2", &Shard::All);
+        dry_run.record("c", "SELECT 3", &Shard::Multi(vec![0, 1]));
+
+        assert_eq!(
+            dry_run.shards(),
+            vec![("1".into(), 1), ("[0, 1]".into(), 1), ("all".into(), 2)]
+        );
+
+        let cross_shard = dry_run.cross_shard();
+        assert_eq!(cross_shard.len(), 2);
+        assert_eq!(cross_shard[0].fingerprint, "b");
+        assert_eq!(cross_shard[0].count, 2);
+        assert_eq!(cross_shard[1].fingerprint, "c");
+    }
+
+    #[test]
+    fn test_dry_run_top_k() {
+        let mut dry_run = DryRun::default();
+
+        for _ in 0..10 {
+            dry_run.record("frequent", "SELECT 1", &Shard::All);
+        }
+
+        for i in 0..TOP_K * 2 {
+            dry_run.record(&i.to_string(), "SELECT 2", &Shard::All);
+        }
+
+        let cross_shard = dry_run.cross_shard();
+        assert_eq!(cross_shard.len(), TOP_K);
+        assert_eq!(cross_shard[0].fingerprint, "frequent");
+        assert_eq!(cross_shard[0].count, 10);
+    }
+}
@@ -2,6 +2,7 @@
 
 use thiserror::Error;
 
+use crate::config::BlockedStatement;
 use crate::frontend::router::sharding;
 
 #[derive(Debug, Error)]
@@ -9,6 +10,12 @@ pub enum Error {
     #[error("{0}")]
     PgQuery(pg_query::Error),
 
+    #[error("query is {0} bytes long, which is over max_query_length_for_parsing ({1})")]
+    QueryTooLong(usize, usize),
+
+    #[error("query parser failed: {0}")]
+    ParserPanic(String),
+
     #[error("only CSV is supported for sharded copy")]
     OnlyCsv,
 
@@ -45,6 +52,12 @@ pub enum Error {
     #[error("set shard syntax error")]
     SetShard,
 
+    #[error("SET ROLE and SET SESSION AUTHORIZATION aren't allowed in transaction mode")]
+    SetRole,
+
+    #[error("pgdog.debug must be on or off")]
+    SetDebug,
+
     #[error("no multi tenant id")]
     MultiTenantId,
 
@@ -59,4 +72,42 @@ pub enum Error {
 
     #[error("query is blocked by plugin \"{0}\"")]
     BlockedByPlugin(String),
+
+    #[error("query is blocked by plugin \"{0}\": {1}")]
+    BlockedByPluginMessage(String, String),
+
+    #[error(
+        "query is blocked by policy \"blocked_statements\": {0} isn't allowed for user \"{1}\""
+    )]
+    BlockedStatement(BlockedStatement, String),
+
+    #[error("query is blocked by policy \"blocked_functions\": function \"{0}\" matches \"{1}\", which isn't allowed for user \"{2}\"")]
+    BlockedFunction(String, String, String),
+
+    #[error("COPY with a query must target a single shard")]
+    CrossShardCopy,
+
+    #[error("join between \"{0}\" and \"{1}\" isn't on their sharding keys and would return incomplete results, join on the sharding key or filter both tables to the same shard")]
+    CrossShardJoin(String, String),
+
+    #[error("INSERT ... SELECT must read from a single shard, re-sharding data with INSERT ... SELECT isn't supported")]
+    CrossShardInsertSelect,
+
+    #[error("INSERT ... SELECT into \"{0}\" must copy its sharding key from the sharding key of the source table, re-sharding data with INSERT ... SELECT isn't supported")]
+    InsertSelectShardingKey(String),
+
+    #[error("multi-row INSERT with rows on different shards can only use parameters in VALUES, ON CONFLICT and RETURNING, ${0} is used elsewhere")]
+    InsertSplitParameter(usize),
+
+    #[error("multi-row INSERT with rows on different shards can only use parameters as values, in type casts, function calls and operators")]
+    InsertSplitExpression,
+
+    #[error("cannot execute {0}, database \"{1}\" is read-only")]
+    ReadOnly(&'static str, String),
+
+    #[error("VACUUM cannot run inside a transaction block")]
+    VacuumInTransaction,
+
+    #[error("sharding key is null in row {row}: {text}")]
+    NullShardingKey { row: usize, text: String },
 }
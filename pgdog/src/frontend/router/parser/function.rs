@@ -1,8 +1,10 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use once_cell::sync::Lazy;
 use pg_query::{protobuf, Node, NodeEnum};
 
+use crate::util::wildcard;
+
 static WRITE_ONLY: Lazy<HashMap<&'static str, LockingBehavior>> = Lazy::new(|| {
     HashMap::from([
         ("pg_advisory_lock", LockingBehavior::Lock),
@@ -19,6 +21,31 @@ static WRITE_ONLY: Lazy<HashMap<&'static str, LockingBehavior>> = Lazy::new(|| {
     ])
 });
 
+/// Large object functions. Large objects live on one shard
+/// and their descriptors are only valid inside the transaction that opened them.
+static LARGE_OBJECT: Lazy<HashSet<&'static str>> = Lazy::new(|| {
+    HashSet::from([
+        "lo_creat",
+        "lo_create",
+        "lo_import",
+        "lo_export",
+        "lo_open",
+        "lo_close",
+        "loread",
+        "lowrite",
+        "lo_lseek",
+        "lo_lseek64",
+        "lo_tell",
+        "lo_tell64",
+        "lo_truncate",
+        "lo_truncate64",
+        "lo_unlink",
+        "lo_get",
+        "lo_put",
+        "lo_from_bytea",
+    ])
+});
+
 #[derive(Debug, Copy, Clone, PartialEq, Default)]
 pub enum LockingBehavior {
     Lock,
@@ -31,6 +58,7 @@ pub enum LockingBehavior {
 pub struct FunctionBehavior {
     pub writes: bool,
     pub locking_behavior: LockingBehavior,
+    pub large_object: bool,
 }
 
 impl FunctionBehavior {
@@ -42,8 +70,76 @@ impl FunctionBehavior {
     }
 }
 
+/// Function name pattern, e.g. `st_*` or `public.crypt`.
+///
+/// Patterns without a schema match functions in any schema.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct FunctionPattern {
+    schema: Option<String>,
+    name: String,
+}
+
+impl FunctionPattern {
+    pub(super) fn new(pattern: &str) -> Self {
+        let pattern = pattern.to_lowercase();
+        match pattern.rsplit_once('.') {
+            Some((schema, name)) => Self {
+                schema: Some(schema.to_owned()),
+                name: name.to_owned(),
+            },
+            None => Self {
+                schema: None,
+                name: pattern,
+            },
+        }
+    }
+
+    pub(super) fn matches(&self, function: &Function) -> bool {
+        let schema = match (&self.schema, function.schema) {
+            (None, _) => true,
+            (Some(pattern), Some(schema)) => wildcard(pattern, &schema.to_lowercase()),
+            (Some(_), None) => false,
+        };
+
+        schema && wildcard(&self.name, &function.name.to_lowercase())
+    }
+}
+
+/// Functions configured as read-safe or as writing, with
+/// `function_allowlist` and `function_denylist`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FunctionLists {
+    allow: Vec<FunctionPattern>,
+    deny: Vec<FunctionPattern>,
+}
+
+impl FunctionLists {
+    pub fn new(allow: &[String], deny: &[String]) -> Self {
+        Self {
+            allow: allow
+                .iter()
+                .map(|pattern| FunctionPattern::new(pattern))
+                .collect(),
+            deny: deny
+                .iter()
+                .map(|pattern| FunctionPattern::new(pattern))
+                .collect(),
+        }
+    }
+
+    fn allowed(&self, function: &Function) -> bool {
+        self.allow.iter().any(|pattern| pattern.matches(function))
+    }
+
+    fn denied(&self, function: &Function) -> bool {
+        self.deny.iter().any(|pattern| pattern.matches(function))
+    }
+}
+
 pub struct Function<'a> {
     pub name: &'a str,
+    /// Schema, if the function name is schema-qualified.
+    pub schema: Option<&'a str>,
 }
 
 impl<'a> Function<'a> {
@@ -51,6 +147,7 @@ impl<'a> Function<'a> {
         match node {
             Some(NodeEnum::String(protobuf::String { sval })) => Ok(Self {
                 name: sval.as_str(),
+                schema: None,
             }),
 
             _ => Err(()),
@@ -63,11 +160,35 @@ impl<'a> Function<'a> {
             FunctionBehavior {
                 writes: true,
                 locking_behavior: *locks,
+                ..Default::default()
+            }
+        } else if LARGE_OBJECT.contains(&self.name) {
+            FunctionBehavior {
+                writes: true,
+                locking_behavior: LockingBehavior::Lock,
+                large_object: true,
             }
         } else {
             FunctionBehavior::default()
         }
     }
+
+    /// What the function does, taking configured function lists into account.
+    ///
+    /// Functions on the denylist always write. Functions on the allowlist
+    /// are read-safe, unless they're on the denylist too.
+    pub fn behavior_with(&self, lists: &FunctionLists) -> FunctionBehavior {
+        if lists.denied(self) {
+            FunctionBehavior {
+                writes: true,
+                ..self.behavior()
+            }
+        } else if lists.allowed(self) {
+            FunctionBehavior::default()
+        } else {
+            self.behavior()
+        }
+    }
 }
 
 impl<'a> TryFrom<&'a Node> for Function<'a> {
@@ -75,8 +196,14 @@ impl<'a> TryFrom<&'a Node> for Function<'a> {
     fn try_from(value: &'a Node) -> Result<Self, Self::Error> {
         match &value.node {
             Some(NodeEnum::FuncCall(func)) => {
-                if let Some(node) = func.funcname.last() {
-                    return Self::from_string(&node.node);
+                let mut names = func.funcname.iter().rev();
+                if let Some(node) = names.next() {
+                    let mut function = Self::from_string(&node.node)?;
+                    function.schema = names
+                        .next()
+                        .and_then(|node| Self::from_string(&node.node).ok())
+                        .map(|schema| schema.name);
+                    return Ok(function);
                 }
             }
 
@@ -122,4 +249,76 @@ mod test {
             _ => panic!("not a select"),
         }
     }
+
+    #[test]
+    fn test_large_object_function() {
+        for query in [
+            "SELECT lo_creat(-1)",
+            "SELECT lo_import('/tmp/file')",
+            "SELECT loread(0, 1024)",
+            "SELECT lo_unlink(1234)::int",
+        ] {
+            let ast = parse(query).unwrap();
+            let root = ast.protobuf.stmts.first().unwrap().stmt.as_ref().unwrap();
+
+            match root.node.as_ref() {
+                Some(NodeEnum::SelectStmt(stmt)) => {
+                    let func = Function::try_from(stmt.target_list.first().unwrap()).unwrap();
+                    let behavior = func.behavior();
+                    assert!(behavior.writes, "{}", query);
+                    assert!(behavior.large_object, "{}", query);
+                    assert_eq!(behavior.locking_behavior, LockingBehavior::Lock);
+                }
+
+                _ => panic!("not a select"),
+            }
+        }
+    }
+
+    fn select_function(query: &str, lists: &FunctionLists) -> FunctionBehavior {
+        let ast = parse(query).unwrap();
+        let root = ast.protobuf.stmts.first().unwrap().stmt.as_ref().unwrap();
+
+        match root.node.as_ref() {
+            Some(NodeEnum::SelectStmt(stmt)) => {
+                Function::try_from(stmt.target_list.first().unwrap())
+                    .unwrap()
+                    .behavior_with(lists)
+            }
+            _ => panic!("not a select"),
+        }
+    }
+
+    #[test]
+    fn test_function_lists() {
+        let lists = FunctionLists::new(
+            &[
+                "st_*".into(),
+                "extensions.gen_random_*".into(),
+                "nextval".into(),
+            ],
+            &["refresh_*".into(), "public.st_transform".into()],
+        );
+
+        for (query, writes) in [
+            ("SELECT st_distance(a, b)", false),
+            ("SELECT public.st_distance(a, b)", false),
+            ("SELECT ST_Area(a)", false),
+            ("SELECT extensions.gen_random_uuid()", false),
+            ("SELECT gen_random_uuid()", false),
+            ("SELECT nextval('seq')", false),
+            ("SELECT refresh_cache()", true),
+            ("SELECT app.refresh_cache()::int", true),
+            ("SELECT public.st_transform(a, 4326)", true),
+            ("SELECT st_transform(a, 4326)", false),
+            ("SELECT pg_advisory_lock(1)", true),
+        ] {
+            assert_eq!(select_function(query, &lists).writes, writes, "{}", query);
+        }
+
+        // Without lists, nothing changes.
+        let lists = FunctionLists::default();
+        assert!(!select_function("SELECT refresh_cache()", &lists).writes);
+        assert!(select_function("SELECT nextval('seq')", &lists).writes);
+    }
 }
@@ -4,7 +4,8 @@ use pg_query::{protobuf::*, NodeEnum};
 use crate::{
     backend::ShardingSchema,
     frontend::router::{
-        round_robin,
+        history::RoutingInputs,
+        round_robin::Purpose,
         sharding::{ContextBuilder, Tables, Value as ShardingValue},
     },
     net::Bind,
@@ -40,6 +41,121 @@ impl<'a> Insert<'a> {
         self.stmt.relation.as_ref().map(Table::from)
     }
 
+    /// Get the `SELECT` providing the rows, if it reads from tables,
+    /// e.g. `INSERT INTO t SELECT * FROM s`.
+    pub fn select(&self) -> Option<&'a SelectStmt> {
+        match self
+            .stmt
+            .select_stmt
+            .as_deref()
+            .and_then(|node| node.node.as_ref())
+        {
+            Some(NodeEnum::SelectStmt(stmt)) if !stmt.from_clause.is_empty() => Some(stmt),
+            _ => None,
+        }
+    }
+
+    /// Check that `INSERT ... SELECT` copies the sharding key of the destination
+    /// table from the sharding key of a source table sharded the same way,
+    /// so rows stay on the shard they were read from.
+    ///
+    /// Returns `None` if the destination table isn't sharded.
+    pub fn same_sharding_key(&'a self, schema: &'a ShardingSchema) -> Option<bool> {
+        let select = self.select()?;
+        let table = self.table()?;
+        let tables = Tables::new(schema);
+        let columns = self.columns();
+
+        let Some(key) = tables.key(table, &columns) else {
+            return tables.sharded(table).map(|_| false);
+        };
+
+        let sources = Self::sources(&select.from_clause);
+        let mut source = None;
+        let mut source_columns = vec![];
+
+        for position in &key.positions {
+            let column = select
+                .target_list
+                .get(*position)
+                .and_then(|target| match &target.node {
+                    Some(NodeEnum::ResTarget(target)) => target.val.as_deref(),
+                    _ => None,
+                })
+                .and_then(|value| Column::try_from(&value.node).ok());
+            let Some(column) = column else {
+                return Some(false);
+            };
+
+            let table = match column.table {
+                Some(reference) => sources
+                    .iter()
+                    .find(|(name, _)| *name == reference)
+                    .map(|(_, table)| *table),
+                None if sources.len() == 1 => sources.first().map(|(_, table)| *table),
+                None => None,
+            };
+
+            // All key columns must come from the same table.
+            match (table, source) {
+                (Some(table), None) => source = Some(table),
+                (Some(table), Some(source)) if table == source => (),
+                _ => return Some(false),
+            }
+
+            source_columns.push(Column {
+                name: column.name,
+                ..Default::default()
+            });
+        }
+
+        let same = source
+            .and_then(|source| tables.key(source, &source_columns))
+            .map(|source_key| {
+                source_key
+                    .positions
+                    .iter()
+                    .copied()
+                    .eq(0..source_columns.len())
+                    && source_key.table.same_sharding(key.table)
+            })
+            .unwrap_or(false);
+
+        Some(same)
+    }
+
+    /// Tables in the `FROM` clause, by the name or alias used to reference them.
+    fn sources(from_clause: &'a [Node]) -> Vec<(&'a str, Table<'a>)> {
+        let mut sources = vec![];
+
+        for node in from_clause {
+            match &node.node {
+                Some(NodeEnum::RangeVar(range_var)) => {
+                    let reference = range_var
+                        .alias
+                        .as_ref()
+                        .map(|alias| alias.aliasname.as_str())
+                        .unwrap_or(range_var.relname.as_str());
+                    let table = Table {
+                        name: range_var.relname.as_str(),
+                        schema: Some(range_var.schemaname.as_str()).filter(|s| !s.is_empty()),
+                    };
+                    sources.push((reference, table));
+                }
+
+                Some(NodeEnum::JoinExpr(join)) => {
+                    for arg in [&join.larg, &join.rarg].into_iter().flatten() {
+                        sources.extend(Self::sources(std::slice::from_ref(arg.as_ref())));
+                    }
+                }
+
+                _ => (),
+            }
+        }
+
+        sources
+    }
+
     /// Get rows from the statement.
     pub fn tuples(&'a self) -> Vec<Tuple<'a>> {
         if let Some(select) = &self.stmt.select_stmt {
@@ -61,6 +177,17 @@ impl<'a> Insert<'a> {
         &'a self,
         schema: &'a ShardingSchema,
         bind: Option<&Bind>,
+    ) -> Result<Shard, Error> {
+        self.shard_with_inputs(schema, bind, None)
+    }
+
+    /// Get the sharding key for the statement,
+    /// recording the sharding key values in `inputs`.
+    pub fn shard_with_inputs(
+        &'a self,
+        schema: &'a ShardingSchema,
+        bind: Option<&Bind>,
+        mut inputs: Option<&mut RoutingInputs>,
     ) -> Result<Shard, Error> {
         let tables = Tables::new(schema);
         let columns = self.columns();
@@ -70,64 +197,157 @@ impl<'a> Insert<'a> {
         let key = table.and_then(|table| tables.key(table, &columns));
 
         if let Some(key) = key {
+            // Rows can go to different shards, see [`super::InsertSplit`].
+            if self.tuples().len() > 1 {
+                return Ok(match self.row_shards(schema, bind)? {
+                    Some(shards) if shards.iter().all(|shard| *shard == shards[0]) => {
+                        Shard::Direct(shards[0])
+                    }
+                    _ => Shard::All,
+                });
+            }
+
             if let Some(bind) = bind {
-                if let Ok(Some(param)) = bind.parameter(key.position) {
+                let mut params = vec![];
+                for position in &key.positions {
                     // Arrays not supported as sharding keys at the moment.
-                    let value = ShardingValue::from_param(&param, key.table.data_type)?;
-                    let ctx = ContextBuilder::new(key.table)
-                        .value(value)
-                        .shards(schema.shards)
-                        .build()?;
-                    return Ok(ctx.apply()?);
+                    let Ok(Some(param)) = bind.parameter(*position) else {
+                        return Ok(Shard::All);
+                    };
+                    params.push(param);
                 }
+                let values = params
+                    .iter()
+                    .enumerate()
+                    .map(|(i, param)| ShardingValue::from_param(param, key.table.data_type_at(i)))
+                    .collect::<Result<Vec<_>, _>>()?;
+                if let Some(ref mut inputs) = inputs {
+                    values.iter().for_each(|value| inputs.sharding_value(value));
+                }
+                let ctx = ContextBuilder::new(key.table)
+                    .values(values)
+                    .shards(schema.shards)
+                    .round_robin(&schema.round_robin)
+                    .build()?;
+                return Ok(ctx.apply()?);
             } else {
                 let tuples = self.tuples();
-
-                // TODO: support rewriting INSERTs to run against multiple shards.
                 if tuples.len() != 1 {
                     return Ok(Shard::All);
                 }
 
-                if let Some(value) = tuples.first().and_then(|tuple| tuple.get(key.position)) {
-                    match value {
-                        Value::Integer(int) => {
-                            let ctx = ContextBuilder::new(key.table)
-                                .data(*int)
-                                .shards(schema.shards)
-                                .build()?;
-                            return Ok(ctx.apply()?);
+                let mut values = vec![];
+                for (i, position) in key.positions.iter().enumerate() {
+                    let data_type = key.table.data_type_at(i);
+                    match tuples.first().and_then(|tuple| tuple.get(*position)) {
+                        Some(Value::Integer(int)) => {
+                            values.push(ShardingValue::new(*int, data_type))
                         }
-
-                        Value::String(str) => {
-                            let ctx = ContextBuilder::new(key.table)
-                                .data(*str)
-                                .shards(schema.shards)
-                                .build()?;
-                            return Ok(ctx.apply()?);
+                        Some(Value::String(str)) => {
+                            values.push(ShardingValue::new(*str, data_type))
                         }
-
-                        _ => (),
+                        _ => return Ok(Shard::All),
                     }
                 }
+                if let Some(ref mut inputs) = inputs {
+                    values.iter().for_each(|value| inputs.sharding_value(value));
+                }
+                let ctx = ContextBuilder::new(key.table)
+                    .values(values)
+                    .shards(schema.shards)
+                    .round_robin(&schema.round_robin)
+                    .build()?;
+                return Ok(ctx.apply()?);
             }
         } else if let Some(table) = table {
             // If this table is sharded, but the sharding key isn't in the query,
             // choose a shard at random.
             if tables.sharded(table).is_some() {
-                return Ok(Shard::Direct(round_robin::next() % schema.shards));
+                return Ok(Shard::Direct(
+                    schema.round_robin.next(Purpose::Insert) % schema.shards,
+                ));
             }
         }
 
         Ok(Shard::All)
     }
+
+    /// Get the shard of each row in the `VALUES` list.
+    ///
+    /// Returns `None` if the table isn't sharded or the shard of any row
+    /// can't be computed, e.g. the sharding key is an expression.
+    pub fn row_shards(
+        &'a self,
+        schema: &'a ShardingSchema,
+        bind: Option<&Bind>,
+    ) -> Result<Option<Vec<usize>>, Error> {
+        let tables = Tables::new(schema);
+        let columns = self.columns();
+
+        let Some(key) = self.table().and_then(|table| tables.key(table, &columns)) else {
+            return Ok(None);
+        };
+
+        let mut shards = vec![];
+
+        for tuple in self.tuples() {
+            let mut params = vec![];
+            for position in &key.positions {
+                let param = match tuple.get(*position) {
+                    Some(Value::Placeholder(number)) => {
+                        let param = (*number as usize)
+                            .checked_sub(1)
+                            .and_then(|index| bind.map(|bind| bind.parameter(index)));
+                        match param {
+                            Some(Ok(Some(param))) => Some(param),
+                            _ => return Ok(None),
+                        }
+                    }
+                    _ => None,
+                };
+                params.push(param);
+            }
+
+            let mut values = vec![];
+            for (i, (position, param)) in key.positions.iter().zip(&params).enumerate() {
+                let data_type = key.table.data_type_at(i);
+                match (tuple.get(*position), param) {
+                    (_, Some(param)) => values.push(ShardingValue::from_param(param, data_type)?),
+                    (Some(Value::Integer(int)), None) => {
+                        values.push(ShardingValue::new(*int, data_type))
+                    }
+                    (Some(Value::String(str)), None) => {
+                        values.push(ShardingValue::new(*str, data_type))
+                    }
+                    _ => return Ok(None),
+                }
+            }
+
+            let ctx = ContextBuilder::new(key.table)
+                .values(values)
+                .shards(schema.shards)
+                .round_robin(&schema.round_robin)
+                .build()?;
+
+            match ctx.apply()? {
+                Shard::Direct(shard) => shards.push(shard),
+                _ => return Ok(None),
+            }
+        }
+
+        Ok(Some(shards))
+    }
 }
 
 #[cfg(test)]
 mod test {
+    use std::collections::HashMap;
+
     use pg_query::{parse, NodeEnum};
 
     use crate::backend::ShardedTables;
-    use crate::config::ShardedTable;
+    use crate::config::{DataType, ShardedTable};
+    use crate::frontend::router::sharding::{bigint, combine, varchar};
     use crate::net::bind::Parameter;
     use crate::net::Format;
 
@@ -230,6 +450,7 @@ mod test {
                 ],
                 vec![],
             ),
+            ..Default::default()
         };
 
         match &select.node {
@@ -305,4 +526,113 @@ mod test {
             _ => panic!("not a select"),
         }
     }
+
+    #[test]
+    fn test_shard_insert_without_columns() {
+        let schema = ShardingSchema {
+            shards: 3,
+            tables: ShardedTables::new(
+                vec![ShardedTable {
+                    name: Some("sharded".into()),
+                    column: "id".into(),
+                    ..Default::default()
+                }],
+                vec![],
+            ),
+            ..Default::default()
+        };
+
+        let query = parse("INSERT INTO sharded VALUES ('test', 1)").unwrap();
+        let select = query.protobuf.stmts.first().unwrap().stmt.as_ref().unwrap();
+        let Some(NodeEnum::InsertStmt(stmt)) = &select.node else {
+            panic!("not an insert");
+        };
+        let insert = Insert::new(stmt);
+
+        // Column order not loaded yet, pick any shard.
+        assert!(matches!(
+            insert.shard(&schema, None).unwrap(),
+            Shard::Direct(_)
+        ));
+
+        schema.columns.set(HashMap::from([(
+            ("public".into(), "sharded".into()),
+            vec!["value".into(), "id".into()],
+        )]));
+
+        // Same shard as INSERT INTO sharded (id, value) VALUES (1, 'test').
+        let shard = insert.shard(&schema, None).unwrap();
+        assert!(matches!(shard, Shard::Direct(2)));
+
+        let bind = Bind::new_params(
+            "",
+            &[
+                Parameter {
+                    len: 4,
+                    data: "test".as_bytes().to_vec(),
+                },
+                Parameter {
+                    len: 1,
+                    data: "3".as_bytes().to_vec(),
+                },
+            ],
+        );
+        let shard = insert.shard(&schema, Some(&bind)).unwrap();
+        assert!(matches!(shard, Shard::Direct(1)));
+    }
+
+    #[test]
+    fn test_shard_insert_composite() {
+        let schema = ShardingSchema {
+            shards: 4,
+            tables: ShardedTables::new(
+                vec![ShardedTable {
+                    name: Some("events".into()),
+                    columns: vec!["tenant_id".into(), "region".into()],
+                    data_types: vec![DataType::Bigint, DataType::Varchar],
+                    ..Default::default()
+                }],
+                vec![],
+            ),
+            ..Default::default()
+        };
+        let expected = combine(bigint(5), varchar(b"us")) as usize % 4;
+
+        let query =
+            parse("INSERT INTO events (region, payload, tenant_id) VALUES ('us', 'data', 5)")
+                .unwrap();
+        let select = query.protobuf.stmts.first().unwrap().stmt.as_ref().unwrap();
+        let Some(NodeEnum::InsertStmt(stmt)) = &select.node else {
+            panic!("not an insert");
+        };
+        let insert = Insert::new(stmt);
+        assert_eq!(
+            insert.shard(&schema, None).unwrap(),
+            Shard::Direct(expected)
+        );
+
+        let query = parse("INSERT INTO events (tenant_id, region) VALUES ($1, $2)").unwrap();
+        let select = query.protobuf.stmts.first().unwrap().stmt.as_ref().unwrap();
+        let Some(NodeEnum::InsertStmt(stmt)) = &select.node else {
+            panic!("not an insert");
+        };
+        let insert = Insert::new(stmt);
+        let bind = Bind::new_params(
+            "",
+            &[
+                Parameter {
+                    len: 1,
+                    data: "5".as_bytes().to_vec(),
+                },
+                Parameter {
+                    len: 2,
+                    data: "us".as_bytes().to_vec(),
+                },
+            ],
+        );
+        assert_eq!(
+            insert.shard(&schema, Some(&bind)).unwrap(),
+            Shard::Direct(expected)
+        );
+    }
 }
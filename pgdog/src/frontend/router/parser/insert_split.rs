@@ -0,0 +1,500 @@
+//! Split multi-row INSERTs between shards.
+//!
+//! `INSERT INTO t VALUES (1), (2), (3)` with rows on different shards is rewritten
+//! into one INSERT per shard, with only the rows that belong there. Each shard
+//! reports how many rows it inserted and returns its own RETURNING rows, which
+//! the multi-shard connection already adds up and concatenates for the client.
+//!
+//! With the extended protocol, placeholders are renumbered in each statement
+//! (`$1`, `$2`, ...) and each shard gets only the Bind parameters its rows use.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+};
+
+use pg_query::{protobuf::*, NodeEnum};
+
+use crate::{
+    backend::databases::User,
+    frontend::{ClientRequest, PreparedStatements},
+    net::{Parse, ProtocolMessage, Query},
+};
+
+use super::Error;
+
+/// INSERT sent to one shard.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShardInsert {
+    /// Shard number.
+    pub shard: usize,
+    /// INSERT with only the rows of this shard.
+    pub query: String,
+    /// Bind parameter used for each placeholder of the query, i.e.
+    /// `$1` is `params[0]` in the original Bind message.
+    pub params: Vec<usize>,
+}
+
+/// Multi-row INSERT split between shards.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct InsertSplit {
+    /// One statement per shard, ordered by shard number.
+    inserts: Vec<ShardInsert>,
+}
+
+impl InsertSplit {
+    /// Split the INSERT into one statement per shard.
+    ///
+    /// # Arguments
+    ///
+    /// * `stmt`: INSERT statement from pg_query.
+    /// * `shards`: Shard of each row in the `VALUES` list.
+    /// * `params`: Number of parameters in the Bind message, if any.
+    ///
+    pub fn new(stmt: &InsertStmt, shards: &[usize], params: usize) -> Result<Self, Error> {
+        let mut rows: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+        for (row, shard) in shards.iter().enumerate() {
+            rows.entry(*shard).or_default().push(row);
+        }
+
+        let values = match stmt
+            .select_stmt
+            .as_deref()
+            .and_then(|node| node.node.as_ref())
+        {
+            Some(NodeEnum::SelectStmt(select)) => &select.values_lists,
+            _ => return Err(Error::EmptyQuery),
+        };
+
+        let mut used = BTreeSet::new();
+        let mut inserts = vec![];
+
+        for (shard, rows) in rows {
+            let mut stmt = stmt.clone();
+            let mut renumber = Renumber::new(params);
+
+            if let Some(NodeEnum::SelectStmt(select)) = stmt
+                .select_stmt
+                .as_deref_mut()
+                .and_then(|node| node.node.as_mut())
+            {
+                select.values_lists = rows.iter().map(|row| values[*row].clone()).collect();
+                renumber.nodes(&mut select.values_lists)?;
+            }
+
+            if let Some(on_conflict) = stmt.on_conflict_clause.as_deref_mut() {
+                renumber.nodes(&mut on_conflict.target_list)?;
+                if let Some(where_clause) = on_conflict.where_clause.as_deref_mut() {
+                    renumber.node(where_clause)?;
+                }
+            }
+            renumber.nodes(&mut stmt.returning_list)?;
+
+            let query = Node {
+                node: Some(NodeEnum::InsertStmt(Box::new(stmt))),
+            }
+            .deparse()
+            .map_err(Error::PgQuery)?;
+
+            used.extend(renumber.params.iter().copied());
+            inserts.push(ShardInsert {
+                shard,
+                query,
+                params: renumber.params,
+            });
+        }
+
+        // Parameters used elsewhere in the statement, e.g. in a CTE,
+        // would be missing from the shard statements.
+        if let Some(missing) = (0..params).find(|param| !used.contains(param)) {
+            return Err(Error::InsertSplitParameter(missing + 1));
+        }
+
+        Ok(Self { inserts })
+    }
+
+    /// Shards the INSERT is sent to, in order.
+    pub fn shards(&self) -> Vec<usize> {
+        self.inserts.iter().map(|insert| insert.shard).collect()
+    }
+
+    /// Statements for each shard, ordered by shard number.
+    pub fn inserts(&self) -> &[ShardInsert] {
+        &self.inserts
+    }
+
+    /// Create the request for each shard, ordered by shard number.
+    ///
+    /// If the client is using named prepared statements, the shard statements are added
+    /// to the global cache for the client's cluster, so servers that haven't prepared them
+    /// yet get them from there, same as any other prepared statement.
+    pub fn requests(&self, request: &ClientRequest, cluster: &Option<Arc<User>>) -> SplitRequests {
+        let bind = request.parameters().ok().flatten();
+        let parse = request.messages.iter().find_map(|message| match message {
+            ProtocolMessage::Parse(parse) => Some(parse.clone()),
+            _ => None,
+        });
+        let named = parse
+            .as_ref()
+            .map(|parse| !parse.anonymous())
+            .unwrap_or(false)
+            || bind.map(|bind| !bind.anonymous()).unwrap_or(false);
+
+        // Parameter types declared by the client.
+        let data_types = match parse {
+            Some(parse) => parse.data_types().collect(),
+            None => bind
+                .filter(|bind| !bind.anonymous())
+                .and_then(|bind| PreparedStatements::global().lock().parse(bind.statement()))
+                .map(|parse| parse.data_types().collect::<Vec<_>>())
+                .unwrap_or_default(),
+        };
+
+        let mut requests = vec![];
+        let mut statements = vec![];
+
+        for insert in &self.inserts {
+            let data_types = insert
+                .params
+                .iter()
+                .map(|param| data_types.get(*param).copied().unwrap_or(0))
+                .collect::<Vec<_>>();
+            let mut parse = Parse::with_data_types("", &insert.query, &data_types);
+
+            if named {
+                let (_, name) = PreparedStatements::global().lock().insert(&parse, cluster);
+                parse = parse.rename_fast(&name);
+                statements.push(name);
+            }
+
+            let messages = request
+                .messages
+                .iter()
+                .map(|message| match message {
+                    ProtocolMessage::Query(_) => Query::new(&insert.query).into(),
+                    ProtocolMessage::Parse(_) => parse.clone().into(),
+                    ProtocolMessage::Bind(bind) => bind.select(parse.name(), &insert.params).into(),
+                    message => message.clone(),
+                })
+                .collect::<Vec<_>>();
+
+            requests.push(ClientRequest::from(messages));
+        }
+
+        SplitRequests {
+            requests,
+            statements,
+        }
+    }
+}
+
+/// Requests for each shard of a split INSERT.
+#[derive(Debug)]
+pub struct SplitRequests {
+    /// Request for each shard, ordered by shard number.
+    pub requests: Vec<ClientRequest>,
+    /// Statements added to the global cache.
+    statements: Vec<String>,
+}
+
+impl Drop for SplitRequests {
+    fn drop(&mut self) {
+        if !self.statements.is_empty() {
+            let global = PreparedStatements::global();
+            let mut global = global.lock();
+            for name in &self.statements {
+                global.decrement(name);
+            }
+        }
+    }
+}
+
+/// Renumber placeholders in the rows of one shard.
+struct Renumber {
+    /// Number of parameters in the Bind message.
+    bind: usize,
+    /// Original parameter of each new placeholder.
+    params: Vec<usize>,
+}
+
+impl Renumber {
+    fn new(bind: usize) -> Self {
+        Self {
+            bind,
+            params: vec![],
+        }
+    }
+
+    fn nodes(&mut self, nodes: &mut [Node]) -> Result<(), Error> {
+        for node in nodes {
+            self.node(node)?;
+        }
+
+        Ok(())
+    }
+
+    fn boxed(&mut self, node: &mut Option<Box<Node>>) -> Result<(), Error> {
+        if let Some(node) = node.as_deref_mut() {
+            self.node(node)?;
+        }
+
+        Ok(())
+    }
+
+    fn node(&mut self, node: &mut Node) -> Result<(), Error> {
+        // Without parameters, there is nothing to renumber.
+        if self.bind == 0 {
+            return Ok(());
+        }
+
+        match node.node.as_mut() {
+            Some(NodeEnum::ParamRef(param)) => {
+                let original = (param.number as usize)
+                    .checked_sub(1)
+                    .filter(|original| *original < self.bind)
+                    .ok_or(Error::MissingParameter(param.number as usize))?;
+                let position = match self.params.iter().position(|p| *p == original) {
+                    Some(position) => position,
+                    None => {
+                        self.params.push(original);
+                        self.params.len() - 1
+                    }
+                };
+                param.number = position as i32 + 1;
+            }
+
+            Some(NodeEnum::List(list)) => self.nodes(&mut list.items)?,
+            Some(NodeEnum::TypeCast(cast)) => self.boxed(&mut cast.arg)?,
+            Some(NodeEnum::ResTarget(target)) => self.boxed(&mut target.val)?,
+            Some(NodeEnum::FuncCall(func)) => self.nodes(&mut func.args)?,
+            Some(NodeEnum::CoalesceExpr(expr)) => self.nodes(&mut expr.args)?,
+            Some(NodeEnum::RowExpr(expr)) => self.nodes(&mut expr.args)?,
+            Some(NodeEnum::BoolExpr(expr)) => self.nodes(&mut expr.args)?,
+            Some(NodeEnum::NullTest(test)) => self.boxed(&mut test.arg)?,
+            Some(NodeEnum::AExpr(expr)) => {
+                self.boxed(&mut expr.lexpr)?;
+                self.boxed(&mut expr.rexpr)?;
+            }
+
+            Some(NodeEnum::AConst(_))
+            | Some(NodeEnum::ColumnRef(_))
+            | Some(NodeEnum::SetToDefault(_))
+            | None => (),
+
+            // We can't tell if it uses parameters.
+            Some(_) => return Err(Error::InsertSplitExpression),
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pg_query::parse;
+
+    use crate::net::{bind::Parameter, Bind, Describe, Execute, Format, FromBytes, Sync, ToBytes};
+
+    use super::*;
+
+    fn split(query: &str, shards: &[usize], params: usize) -> Result<InsertSplit, Error> {
+        let ast = parse(query).unwrap();
+        let stmt = ast.protobuf.stmts.first().unwrap().stmt.as_ref().unwrap();
+        let Some(NodeEnum::InsertStmt(stmt)) = &stmt.node else {
+            panic!("not an insert");
+        };
+        InsertSplit::new(stmt, shards, params)
+    }
+
+    fn param(value: &str) -> Parameter {
+        Parameter {
+            len: value.len() as i32,
+            data: value.as_bytes().to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_split_values() {
+        let split = split(
+            "INSERT INTO sharded (id, value) VALUES (1, 'a'), (2, 'b'), (3, 'c') RETURNING *",
+            &[2, 0, 2],
+            0,
+        )
+        .unwrap();
+
+        assert_eq!(split.shards(), vec![0, 2]);
+        assert_eq!(
+            split.inserts()[0].query,
+            "INSERT INTO sharded (id, value) VALUES (2, 'b') RETURNING *"
+        );
+        assert_eq!(
+            split.inserts()[1].query,
+            "INSERT INTO sharded (id, value) VALUES (1, 'a'), (3, 'c') RETURNING *"
+        );
+        assert!(split
+            .inserts()
+            .iter()
+            .all(|insert| insert.params.is_empty()));
+
+        let request = ClientRequest::from(vec![ProtocolMessage::from(Query::new(
+            "INSERT INTO sharded (id, value) VALUES (1, 'a'), (2, 'b'), (3, 'c') RETURNING *",
+        ))]);
+        let requests = split.requests(&request, &None);
+        assert_eq!(requests.requests.len(), 2);
+        for (request, insert) in requests.requests.iter().zip(split.inserts()) {
+            match &request.messages[0] {
+                ProtocolMessage::Query(query) => assert_eq!(query.query(), insert.query),
+                _ => panic!("not a query"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_split_params() {
+        let split = split(
+            "INSERT INTO sharded (id, value) VALUES ($1, $2), ($3, $4::text), ($5, lower($6)) \
+            ON CONFLICT (id) DO UPDATE SET value = $7 RETURNING id",
+            &[1, 0, 1],
+            7,
+        )
+        .unwrap();
+
+        assert_eq!(split.shards(), vec![0, 1]);
+        assert_eq!(
+            split.inserts()[0].query,
+            "INSERT INTO sharded (id, value) VALUES ($1, $2::text) \
+            ON CONFLICT (id) DO UPDATE SET value = $3 RETURNING id"
+        );
+        assert_eq!(split.inserts()[0].params, vec![2, 3, 6]);
+        assert_eq!(
+            split.inserts()[1].query,
+            "INSERT INTO sharded (id, value) VALUES ($1, $2), ($3, lower($4)) \
+            ON CONFLICT (id) DO UPDATE SET value = $5 RETURNING id"
+        );
+        assert_eq!(split.inserts()[1].params, vec![0, 1, 4, 5, 6]);
+
+        let params = (1..=7).map(|i| param(&i.to_string())).collect::<Vec<_>>();
+        let request = ClientRequest::from(vec![
+            ProtocolMessage::from(Parse::new_anonymous(
+                "INSERT INTO sharded (id, value) VALUES ($1, $2), ($3, $4::text), ($5, lower($6)) \
+                ON CONFLICT (id) DO UPDATE SET value = $7 RETURNING id",
+            )),
+            Bind::new_params_codes("", &params, &[Format::Text]).into(),
+            Describe::new_portal("").into(),
+            Execute::new().into(),
+            Sync.into(),
+        ]);
+
+        let requests = split.requests(&request, &None);
+        assert_eq!(requests.requests.len(), 2);
+
+        for (request, insert) in requests.requests.iter().zip(split.inserts()) {
+            let codes = request
+                .messages
+                .iter()
+                .map(|message| message.code())
+                .collect::<String>();
+            assert_eq!(codes, "PBDES");
+
+            let ProtocolMessage::Parse(parse) = &request.messages[0] else {
+                panic!("not a parse");
+            };
+            assert!(parse.anonymous());
+            assert_eq!(parse.query(), insert.query);
+
+            let ProtocolMessage::Bind(bind) = &request.messages[1] else {
+                panic!("not a bind");
+            };
+            let bind = Bind::from_bytes(bind.to_bytes().unwrap()).unwrap();
+            assert!(bind.anonymous());
+            let values = bind
+                .params_raw()
+                .iter()
+                .map(|param| String::from_utf8(param.data.clone()).unwrap())
+                .collect::<Vec<_>>();
+            let expected = insert
+                .params
+                .iter()
+                .map(|param| (param + 1).to_string())
+                .collect::<Vec<_>>();
+            assert_eq!(values, expected);
+            assert_eq!(bind.codes(), &[Format::Text]);
+        }
+    }
+
+    #[test]
+    fn test_split_named() {
+        let query = "INSERT INTO split_named (id) VALUES ($1), ($2)";
+        let split = split(query, &[0, 1], 2).unwrap();
+
+        let params = [param("1"), param("2")];
+        let request = ClientRequest::from(vec![
+            ProtocolMessage::from(Bind::new_params_codes(
+                "__pgdog_split_test",
+                &params,
+                &[Format::Text, Format::Text],
+            )),
+            Execute::new().into(),
+            Sync.into(),
+        ]);
+
+        let names = |database: &str| {
+            let cluster = Some(Arc::new(User {
+                user: "pgdog".into(),
+                database: database.into(),
+            }));
+            split
+                .requests(&request, &cluster)
+                .requests
+                .iter()
+                .map(|request| {
+                    let ProtocolMessage::Bind(bind) = &request.messages[0] else {
+                        panic!("not a bind");
+                    };
+                    assert_eq!(bind.params_raw().len(), 1);
+                    assert_eq!(bind.codes(), &[Format::Text]);
+                    bind.statement().to_owned()
+                })
+                .collect::<Vec<_>>()
+        };
+        let pgdog = names("pgdog");
+
+        // Servers get the statements from the global cache.
+        for name in &pgdog {
+            let parse = PreparedStatements::global().lock().parse(name).unwrap();
+            assert_eq!(parse.query(), "INSERT INTO split_named (id) VALUES ($1)");
+        }
+        assert_eq!(pgdog[0], pgdog[1]);
+
+        // Statements are scoped to the client's cluster.
+        assert_eq!(names("pgdog"), pgdog);
+        assert_ne!(names("other"), pgdog);
+    }
+
+    #[test]
+    fn test_split_unsupported() {
+        let err = split(
+            "INSERT INTO sharded (id, value) VALUES ($1, (SELECT $2)), ($3, 'a')",
+            &[0, 1],
+            3,
+        )
+        .unwrap_err();
+        assert!(matches!(err, Error::InsertSplitExpression));
+
+        // Subqueries are fine without parameters.
+        assert!(split(
+            "INSERT INTO sharded (id, value) VALUES (1, (SELECT 'a')), (2, 'b')",
+            &[0, 1],
+            0,
+        )
+        .is_ok());
+
+        let err = split(
+            "WITH s AS (SELECT $3 AS v) INSERT INTO sharded (id, value) VALUES ($1, 'a'), ($2, 'b')",
+            &[0, 1],
+            3,
+        )
+        .unwrap_err();
+        assert!(matches!(err, Error::InsertSplitParameter(3)));
+    }
+}
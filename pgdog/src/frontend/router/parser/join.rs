@@ -0,0 +1,446 @@
+//! Tables joined in the FROM clause of a SELECT.
+//!
+//! Each shard can only join rows it has locally. A join between two sharded
+//! tables returns complete results only if the matching rows live on the same shard,
+//! i.e. the tables are joined on their sharding keys, or all of them are
+//! filtered down to the same shard.
+
+use pg_query::{
+    protobuf::{AExprKind, BoolExprType, JoinExpr, SelectStmt},
+    Node, NodeEnum,
+};
+
+use super::Column;
+use crate::{backend::ShardingSchema, config::ShardedTable};
+
+/// Table in the FROM clause.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Relation<'a> {
+    /// Schema name, if specified.
+    schema: Option<&'a str>,
+    /// Table name.
+    name: &'a str,
+    /// How columns refer to this table, i.e. its alias or name.
+    reference: &'a str,
+}
+
+/// Sharded table in a join.
+#[derive(Debug, Clone, Copy)]
+pub struct JoinedTable<'a, 'b> {
+    /// Alias or name of the table in the query.
+    pub reference: &'a str,
+    /// Sharding config for this table.
+    pub table: &'b ShardedTable,
+}
+
+/// Tables joined by a SELECT and the columns they are joined on.
+#[derive(Debug, Default)]
+pub struct Join<'a> {
+    relations: Vec<Relation<'a>>,
+    /// Pairs of columns compared with `=`.
+    equalities: Vec<(Column<'a>, Column<'a>)>,
+}
+
+impl<'a> Join<'a> {
+    /// Find the joined tables. Returns `None` if the statement
+    /// doesn't join anything or uses a FROM clause we don't understand,
+    /// e.g. a subquery.
+    pub fn new(stmt: &'a SelectStmt) -> Option<Self> {
+        let mut join = Self::default();
+
+        for node in &stmt.from_clause {
+            join.from(node)?;
+        }
+
+        if join.relations.len() < 2 {
+            return None;
+        }
+
+        if let Some(ref where_clause) = stmt.where_clause {
+            join.equalities(where_clause);
+        }
+
+        Some(join)
+    }
+
+    /// Group joined sharded tables that are guaranteed to have matching rows
+    /// on the same shard. Empty if no sharded tables are joined.
+    ///
+    /// Only tables with a name are considered. Tables with a composite
+    /// sharding key must be joined on all of its columns.
+    pub fn co_sharded<'b>(&self, schema: &'b ShardingSchema) -> Vec<Vec<JoinedTable<'a, 'b>>> {
+        let sharded = self
+            .relations
+            .iter()
+            .filter_map(|relation| {
+                schema
+                    .tables()
+                    .tables()
+                    .iter()
+                    .find(|table| table.name.as_deref() == Some(relation.name))
+                    .map(|table| JoinedTable {
+                        reference: relation.reference,
+                        table,
+                    })
+            })
+            .collect::<Vec<_>>();
+
+        if sharded.is_empty() {
+            return vec![];
+        }
+
+        // Union-find over tables joined on their sharding keys.
+        let mut parents = (0..sharded.len()).collect::<Vec<_>>();
+        fn root(parents: &mut [usize], mut i: usize) -> usize {
+            while parents[i] != i {
+                parents[i] = parents[parents[i]];
+                i = parents[i];
+            }
+            i
+        }
+
+        let equalities = self
+            .equalities
+            .iter()
+            .filter_map(|(left, right)| {
+                Some((
+                    self.resolve(left, schema, &sharded)?,
+                    self.resolve(right, schema, &sharded)?,
+                ))
+            })
+            .collect::<Vec<_>>();
+
+        for left in 0..sharded.len() {
+            for right in left + 1..sharded.len() {
+                if Self::joined_on_keys(&equalities, &sharded[left], &sharded[right]) {
+                    let (left, right) = (root(&mut parents, left), root(&mut parents, right));
+                    parents[left] = right;
+                }
+            }
+        }
+
+        let mut groups: Vec<(usize, Vec<JoinedTable>)> = vec![];
+        for (i, joined) in sharded.iter().enumerate() {
+            let group = root(&mut parents, i);
+            match groups.iter_mut().find(|(root, _)| *root == group) {
+                Some((_, tables)) => tables.push(*joined),
+                None => groups.push((group, vec![*joined])),
+            }
+        }
+
+        groups.into_iter().map(|(_, tables)| tables).collect()
+    }
+
+    /// Qualify a column with the table it belongs to. Unqualified columns
+    /// belong to the only table in the FROM clause that has them, using the
+    /// column cache if it's loaded or the sharding keys of the joined tables otherwise.
+    fn resolve(
+        &self,
+        column: &Column<'a>,
+        schema: &ShardingSchema,
+        sharded: &[JoinedTable<'a, '_>],
+    ) -> Option<Column<'a>> {
+        if column.table.is_some() {
+            return Some(*column);
+        }
+
+        let mut owners = self.relations.iter().filter(|relation| {
+            match schema.columns.columns(relation.schema, relation.name) {
+                Some(columns) => columns.iter().any(|name| name == column.name),
+                None => sharded.iter().any(|joined| {
+                    joined.reference == relation.reference
+                        && joined.table.key_columns().contains(&column.name)
+                }),
+            }
+        });
+
+        match (owners.next(), owners.next()) {
+            (Some(owner), None) => Some(Column {
+                table: Some(owner.reference),
+                ..*column
+            }),
+            _ => None,
+        }
+    }
+
+    /// Tables are joined on each of their sharding key columns, in key order.
+    fn joined_on_keys(
+        equalities: &[(Column, Column)],
+        left: &JoinedTable,
+        right: &JoinedTable,
+    ) -> bool {
+        let refers = |column: &Column, joined: &JoinedTable, name: &str| {
+            column.table == Some(joined.reference) && column.name == name
+        };
+
+        Self::co_located(left.table, right.table)
+            && left
+                .table
+                .key_columns()
+                .into_iter()
+                .zip(right.table.key_columns())
+                .all(|(left_key, right_key)| {
+                    equalities.iter().any(|(a, b)| {
+                        (refers(a, left, left_key) && refers(b, right, right_key))
+                            || (refers(a, right, right_key) && refers(b, left, left_key))
+                    })
+                })
+    }
+
+    /// Equal sharding keys of these tables are on the same shard.
+    fn co_located(left: &ShardedTable, right: &ShardedTable) -> bool {
+        let columns = left.key_columns().len();
+        columns == right.key_columns().len()
+            && (0..columns).all(|i| left.data_type_at(i) == right.data_type_at(i))
+            && left.hasher == right.hasher
+            && left.mapping == right.mapping
+            && left.centroids == right.centroids
+    }
+
+    /// Add tables from a FROM clause item. Returns the references
+    /// of the tables it contains.
+    fn from(&mut self, node: &'a Node) -> Option<Vec<&'a str>> {
+        match node.node {
+            Some(NodeEnum::RangeVar(ref range_var)) => {
+                let reference = range_var
+                    .alias
+                    .as_ref()
+                    .map(|alias| alias.aliasname.as_str())
+                    .unwrap_or(range_var.relname.as_str());
+                self.relations.push(Relation {
+                    schema: Some(range_var.schemaname.as_str()).filter(|schema| !schema.is_empty()),
+                    name: range_var.relname.as_str(),
+                    reference,
+                });
+                Some(vec![reference])
+            }
+
+            Some(NodeEnum::JoinExpr(ref join)) => self.join(join),
+
+            // Set-returning functions aren't sharded.
+            Some(NodeEnum::RangeFunction(_)) => Some(vec![]),
+
+            _ => None,
+        }
+    }
+
+    fn join(&mut self, join: &'a JoinExpr) -> Option<Vec<&'a str>> {
+        // Can't tell which columns a NATURAL join uses without the table schema.
+        if join.is_natural {
+            return None;
+        }
+
+        let left = self.from(join.larg.as_deref()?)?;
+        let right = self.from(join.rarg.as_deref()?)?;
+
+        for column in &join.using_clause {
+            let Some(NodeEnum::String(ref name)) = column.node else {
+                continue;
+            };
+
+            for left in &left {
+                for right in &right {
+                    self.equalities.push((
+                        Column {
+                            name: name.sval.as_str(),
+                            table: Some(*left),
+                            schema: None,
+                        },
+                        Column {
+                            name: name.sval.as_str(),
+                            table: Some(*right),
+                            schema: None,
+                        },
+                    ));
+                }
+            }
+        }
+
+        if let Some(ref quals) = join.quals {
+            self.equalities(quals);
+        }
+
+        Some(left.into_iter().chain(right).collect())
+    }
+
+    /// Find `column = column` comparisons that must all be true.
+    fn equalities(&mut self, node: &'a Node) {
+        match node.node {
+            Some(NodeEnum::BoolExpr(ref expr)) => {
+                if expr.boolop() == BoolExprType::AndExpr {
+                    for arg in &expr.args {
+                        self.equalities(arg);
+                    }
+                }
+            }
+
+            Some(NodeEnum::AExpr(ref expr)) => {
+                let equals = expr.name.first().and_then(|name| match name.node {
+                    Some(NodeEnum::String(ref op)) => Some(op.sval == "="),
+                    _ => None,
+                });
+
+                if expr.kind() == AExprKind::AexprOp && equals == Some(true) {
+                    let left = expr.lexpr.as_deref().and_then(Self::column);
+                    let right = expr.rexpr.as_deref().and_then(Self::column);
+
+                    if let (Some(left), Some(right)) = (left, right) {
+                        self.equalities.push((left, right));
+                    }
+                }
+            }
+
+            _ => (),
+        }
+    }
+
+    fn column(node: &'a Node) -> Option<Column<'a>> {
+        match node.node {
+            Some(NodeEnum::ColumnRef(_)) => Column::try_from(node).ok(),
+            Some(NodeEnum::TypeCast(ref cast)) => cast.arg.as_deref().and_then(Self::column),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::backend::ShardedTables;
+
+    fn groups(query: &str) -> Vec<Vec<std::string::String>> {
+        let schema = ShardingSchema {
+            shards: 2,
+            tables: ShardedTables::new(
+                vec![
+                    ShardedTable {
+                        name: Some("users".into()),
+                        column: "id".into(),
+                        ..Default::default()
+                    },
+                    ShardedTable {
+                        name: Some("orders".into()),
+                        column: "user_id".into(),
+                        ..Default::default()
+                    },
+                    ShardedTable {
+                        name: Some("accounts".into()),
+                        columns: vec!["tenant_id".into(), "region".into()],
+                        ..Default::default()
+                    },
+                    ShardedTable {
+                        name: Some("invoices".into()),
+                        columns: vec!["tenant_id".into(), "region".into()],
+                        ..Default::default()
+                    },
+                ],
+                vec![],
+            ),
+            ..Default::default()
+        };
+
+        let ast = pg_query::parse(query).unwrap();
+        let Some(NodeEnum::SelectStmt(ref stmt)) = ast.protobuf.stmts[0]
+            .stmt
+            .as_ref()
+            .and_then(|stmt| stmt.node.as_ref())
+        else {
+            panic!("not a select");
+        };
+
+        Join::new(stmt)
+            .map(|join| {
+                join.co_sharded(&schema)
+                    .into_iter()
+                    .map(|group| {
+                        group
+                            .into_iter()
+                            .map(|joined| joined.reference.to_string())
+                            .collect()
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn test_co_sharded() {
+        for query in [
+            "SELECT * FROM users u JOIN orders o ON u.id = o.user_id",
+            "SELECT * FROM users u JOIN orders o ON o.user_id = u.id AND o.total > 5",
+            "SELECT * FROM users u, orders o WHERE u.id = o.user_id",
+            "SELECT * FROM users u LEFT JOIN orders o ON u.id = o.user_id::bigint",
+        ] {
+            assert_eq!(groups(query), vec![vec!["u", "o"]], "{}", query);
+        }
+
+        assert_eq!(
+            groups("SELECT * FROM users a JOIN users b USING (id)"),
+            vec![vec!["a", "b"]]
+        );
+
+        // Unqualified columns belong to the only table that has them.
+        for query in [
+            "SELECT * FROM users u JOIN orders o ON id = user_id",
+            "SELECT * FROM users u JOIN orders o ON user_id = u.id",
+            "SELECT * FROM users, orders WHERE id = user_id",
+        ] {
+            let groups = groups(query);
+            assert_eq!(groups.len(), 1, "{}", query);
+            assert_eq!(groups[0].len(), 2, "{}", query);
+        }
+    }
+
+    #[test]
+    fn test_not_co_sharded() {
+        for query in [
+            "SELECT * FROM users u JOIN orders o ON u.email = o.email",
+            "SELECT * FROM users u JOIN orders o ON u.id = o.id",
+            "SELECT * FROM users u JOIN orders o ON u.id = o.user_id OR o.total > 5",
+            "SELECT * FROM users u, orders o",
+        ] {
+            assert_eq!(groups(query), vec![vec!["u"], vec!["o"]], "{}", query);
+        }
+
+        // Ambiguous without a table.
+        assert_eq!(
+            groups("SELECT * FROM users a JOIN users b ON id = id"),
+            vec![vec!["a"], vec!["b"]]
+        );
+    }
+
+    #[test]
+    fn test_co_sharded_composite() {
+        assert_eq!(
+            groups(
+                "SELECT * FROM accounts a JOIN invoices i \
+                 ON a.region = i.region AND i.tenant_id = a.tenant_id"
+            ),
+            vec![vec!["a", "i"]]
+        );
+
+        for query in [
+            "SELECT * FROM accounts a JOIN invoices i ON a.tenant_id = i.tenant_id",
+            "SELECT * FROM accounts a JOIN invoices i ON a.tenant_id = i.region AND a.region = i.tenant_id",
+            "SELECT * FROM accounts a JOIN users u ON a.tenant_id = u.id",
+        ] {
+            assert_eq!(groups(query).len(), 2, "{}", query);
+        }
+    }
+
+    #[test]
+    fn test_not_sharded_join() {
+        for query in [
+            "SELECT * FROM users",
+            "SELECT * FROM users u JOIN (SELECT * FROM orders) o ON u.email = o.email",
+            "SELECT * FROM users NATURAL JOIN orders",
+        ] {
+            assert!(groups(query).is_empty(), "{}", query);
+        }
+
+        assert_eq!(
+            groups("SELECT * FROM users u JOIN settings s ON u.email = s.email"),
+            vec![vec!["u"]]
+        );
+    }
+}
@@ -10,19 +10,25 @@ pub mod context;
 pub mod copy;
 pub mod csv;
 pub mod distinct;
+pub mod dry_run;
 pub mod error;
 pub mod function;
 pub mod insert;
+pub mod insert_split;
+pub mod join;
 pub mod key;
 pub mod limit;
 pub mod multi_tenant;
 pub mod order_by;
+pub mod policy;
 pub mod prepare;
 pub mod query;
+pub mod replication;
 pub mod rewrite;
 pub mod route;
 pub mod sequence;
 pub mod table;
+pub mod transaction_options;
 pub mod tuple;
 pub mod value;
 pub mod where_clause;
@@ -31,23 +37,28 @@ pub use aggregate::{Aggregate, AggregateFunction, AggregateTarget};
 pub use binary::BinaryStream;
 pub use cache::Cache;
 pub use column::{Column, OwnedColumn};
-pub use command::Command;
+pub use command::{Command, CommandSummary};
 pub use context::QueryParserContext;
 pub use copy::{CopyFormat, CopyParser};
 pub use csv::{CsvStream, Record};
 pub use distinct::{Distinct, DistinctBy, DistinctColumn};
 pub use error::Error;
 pub use function::Function;
-pub use function::{FunctionBehavior, LockingBehavior};
+pub use function::{FunctionBehavior, FunctionLists, LockingBehavior};
 pub use insert::Insert;
+pub use insert_split::{InsertSplit, ShardInsert, SplitRequests};
+pub use join::{Join, JoinedTable};
 pub use key::Key;
 pub use limit::{Limit, LimitClause};
 pub use order_by::OrderBy;
+pub use policy::Policy;
 pub use prepare::Prepare;
 pub use query::QueryParser;
+pub use replication::ReplicationCommand;
 pub use route::{Route, Shard};
 pub use sequence::{OwnedSequence, Sequence};
 pub use table::{OwnedTable, Table};
+pub use transaction_options::TransactionOptions;
 pub use tuple::Tuple;
 pub use value::Value;
 pub use where_clause::WhereClause;
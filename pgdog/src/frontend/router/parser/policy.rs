@@ -0,0 +1,256 @@
+//! Per-user statement and function policies.
+//!
+//! Users can be blocked from running some kinds of statements, with
+//! `blocked_statements`, and from calling some functions, with `blocked_functions`.
+//! Queries are checked after they are parsed and before they are routed.
+
+use pg_query::{protobuf::WithClause, NodeEnum, ParseResult};
+
+use super::{function::FunctionPattern, Error, Function, QueryParser};
+use crate::config::{BlockedStatement, User};
+
+/// Statements and functions blocked for a user.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Policy {
+    user: String,
+    statements: Vec<BlockedStatement>,
+    /// Patterns, as configured, and their parsed form.
+    functions: Vec<(String, FunctionPattern)>,
+}
+
+impl Policy {
+    pub fn new(user: &User) -> Self {
+        Self {
+            user: user.name.clone(),
+            statements: user.blocked_statements.clone(),
+            functions: user
+                .blocked_functions
+                .iter()
+                .map(|pattern| (pattern.clone(), FunctionPattern::new(pattern)))
+                .collect(),
+        }
+    }
+
+    /// Nothing is blocked.
+    pub fn is_empty(&self) -> bool {
+        self.statements.is_empty() && self.functions.is_empty()
+    }
+
+    /// Reject the query if any of its statements or function calls is blocked.
+    pub fn check(&self, ast: &ParseResult) -> Result<(), Error> {
+        if self.is_empty() {
+            return Ok(());
+        }
+
+        let statement = ast
+            .protobuf
+            .stmts
+            .iter()
+            .filter_map(|stmt| stmt.stmt.as_ref()?.node.as_ref())
+            .find_map(|node| self.blocked_statement(node));
+
+        if let Some(statement) = statement {
+            return Err(Error::BlockedStatement(statement, self.user.clone()));
+        }
+
+        for name in ast.call_functions() {
+            let function = match name.rsplit_once('.') {
+                Some((schema, name)) => Function {
+                    name,
+                    schema: Some(schema),
+                },
+                None => Function {
+                    name: &name,
+                    schema: None,
+                },
+            };
+
+            if let Some((pattern, _)) = self
+                .functions
+                .iter()
+                .find(|(_, pattern)| pattern.matches(&function))
+            {
+                return Err(Error::BlockedFunction(
+                    function.name.to_owned(),
+                    pattern.clone(),
+                    self.user.clone(),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Blocked kind of the statement, or of a statement it runs, if any.
+    fn blocked_statement(&self, node: &NodeEnum) -> Option<BlockedStatement> {
+        // Statements in WITH can write too.
+        let with_clause = match node {
+            NodeEnum::SelectStmt(stmt) => stmt.with_clause.as_ref(),
+            NodeEnum::InsertStmt(stmt) => stmt.with_clause.as_ref(),
+            NodeEnum::UpdateStmt(stmt) => stmt.with_clause.as_ref(),
+            NodeEnum::DeleteStmt(stmt) => stmt.with_clause.as_ref(),
+            NodeEnum::PrepareStmt(stmt) => {
+                return stmt
+                    .query
+                    .as_ref()?
+                    .node
+                    .as_ref()
+                    .and_then(|node| self.blocked_statement(node))
+            }
+            _ => None,
+        };
+
+        if let Some(statement) = with_clause.and_then(|with| self.blocked_cte(with)) {
+            return Some(statement);
+        }
+
+        let statement = match node {
+            NodeEnum::CopyStmt(_) => BlockedStatement::Copy,
+            NodeEnum::TruncateStmt(_) => BlockedStatement::Truncate,
+            NodeEnum::DeleteStmt(stmt) if stmt.where_clause.is_none() => {
+                BlockedStatement::DeleteWithoutWhere
+            }
+            NodeEnum::UpdateStmt(stmt) if stmt.where_clause.is_none() => {
+                BlockedStatement::UpdateWithoutWhere
+            }
+            node if QueryParser::write_statement(node) == Some("DDL") => BlockedStatement::Ddl,
+            _ => return None,
+        };
+
+        self.statements.contains(&statement).then_some(statement)
+    }
+
+    /// Blocked kind of a statement in the WITH clause, if any.
+    fn blocked_cte(&self, with_clause: &WithClause) -> Option<BlockedStatement> {
+        with_clause.ctes.iter().find_map(|cte| match cte.node {
+            Some(NodeEnum::CommonTableExpr(ref expr)) => expr
+                .ctequery
+                .as_ref()?
+                .node
+                .as_ref()
+                .and_then(|node| self.blocked_statement(node)),
+            _ => None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn policy(statements: &[BlockedStatement], functions: &[&str]) -> Policy {
+        Policy::new(&User {
+            name: "analyst".into(),
+            blocked_statements: statements.to_vec(),
+            blocked_functions: functions.iter().map(|name| name.to_string()).collect(),
+            ..Default::default()
+        })
+    }
+
+    fn check(policy: &Policy, query: &str) -> Result<(), Error> {
+        policy.check(&pg_query::parse(query).unwrap())
+    }
+
+    #[test]
+    fn test_blocked_statements() {
+        for (statement, blocked, allowed) in [
+            (
+                BlockedStatement::Copy,
+                "COPY users FROM STDIN",
+                "SELECT * FROM users",
+            ),
+            (
+                BlockedStatement::Copy,
+                "COPY (SELECT * FROM users) TO STDOUT",
+                "INSERT INTO users (id) VALUES (1)",
+            ),
+            (
+                BlockedStatement::Ddl,
+                "DROP TABLE users",
+                "DELETE FROM users WHERE id = 1",
+            ),
+            (
+                BlockedStatement::Ddl,
+                "ALTER TABLE users ADD COLUMN email TEXT",
+                "SELECT * FROM users",
+            ),
+            (
+                BlockedStatement::Truncate,
+                "TRUNCATE users",
+                "DELETE FROM users WHERE id = 1",
+            ),
+            (
+                BlockedStatement::DeleteWithoutWhere,
+                "DELETE FROM users",
+                "DELETE FROM users WHERE id = 1",
+            ),
+            (
+                BlockedStatement::DeleteWithoutWhere,
+                "WITH d AS (DELETE FROM users RETURNING *) SELECT * FROM d",
+                "WITH d AS (DELETE FROM users WHERE id = 1 RETURNING *) SELECT * FROM d",
+            ),
+            (
+                BlockedStatement::UpdateWithoutWhere,
+                "UPDATE users SET email = NULL",
+                "UPDATE users SET email = NULL WHERE id = 1",
+            ),
+            (
+                BlockedStatement::UpdateWithoutWhere,
+                "PREPARE u AS UPDATE users SET email = $1",
+                "PREPARE u AS UPDATE users SET email = $1 WHERE id = $2",
+            ),
+        ] {
+            let policy = policy(&[statement], &[]);
+            let err = check(&policy, blocked).unwrap_err();
+            assert!(
+                matches!(err, Error::BlockedStatement(s, ref user) if s == statement && user == "analyst"),
+                "{}",
+                blocked
+            );
+            assert!(err.to_string().contains(&statement.to_string()));
+            check(&policy, allowed).unwrap();
+
+            // Other policies don't block it.
+            check(&Policy::default(), blocked).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_blocked_functions() {
+        let policy = policy(&[], &["pg_sleep*", "dblink*", "app.purge"]);
+
+        for (query, function, pattern) in [
+            ("SELECT pg_sleep(10)", "pg_sleep", "pg_sleep*"),
+            ("SELECT PG_SLEEP_FOR('1 hour')", "pg_sleep_for", "pg_sleep*"),
+            (
+                "SELECT * FROM users WHERE id = 1 AND pg_catalog.pg_sleep(10) IS NOT NULL",
+                "pg_sleep",
+                "pg_sleep*",
+            ),
+            (
+                "SELECT * FROM public.dblink('remote', 'SELECT 1') AS t(a int)",
+                "dblink",
+                "dblink*",
+            ),
+            ("SELECT app.purge()", "purge", "app.purge"),
+        ] {
+            let err = check(&policy, query).unwrap_err();
+            assert!(
+                matches!(err, Error::BlockedFunction(ref f, ref p, _) if f == function && p == pattern),
+                "{}: {:?}",
+                query,
+                err
+            );
+        }
+
+        for query in [
+            "SELECT now()",
+            "SELECT sleep_report FROM users",
+            "SELECT purge()",
+            "SELECT other.purge()",
+            "CREATE FUNCTION pg_sleep_report() RETURNS int AS 'SELECT 1' LANGUAGE sql",
+        ] {
+            check(&policy, query).unwrap();
+        }
+    }
+}
@@ -0,0 +1,39 @@
+//! Degraded reads.
+//!
+//! With `degraded_reads` enabled, reads sent to the primary only because of
+//! the conservative read/write strategy go to replicas while the primary is banned.
+//! Statements that lock rows or call functions that write still go to the primary
+//! and fail like before.
+
+use super::*;
+
+impl QueryParser {
+    /// Send the read to replicas if the primary of one of its shards is banned.
+    pub(super) fn degraded_read(&self, route: &mut Route, context: &QueryParserContext) {
+        if !context.degraded_reads
+            || !self.write_override
+            || !self.read_statement
+            || self.plugin_output.read.is_some()
+            || route.is_read()
+        {
+            return;
+        }
+
+        let shards = context.router_context.cluster.shards();
+        let targets: Vec<_> = match route.shard() {
+            Shard::Direct(shard) => shards.get(*shard).into_iter().collect(),
+            Shard::Multi(numbers) => numbers
+                .iter()
+                .filter_map(|shard| shards.get(*shard))
+                .collect(),
+            Shard::All => shards.iter().collect(),
+        };
+
+        let banned = targets.iter().any(|shard| shard.primary_banned());
+        // Replicas have to be there to take the reads.
+        if banned && targets.iter().all(|shard| shard.has_replicas()) {
+            debug!("primary is down, sending read to a replica");
+            route.set_degraded_mut();
+        }
+    }
+}
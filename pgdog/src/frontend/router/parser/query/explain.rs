@@ -11,9 +11,9 @@ impl QueryParser {
 
         match node {
             NodeEnum::SelectStmt(ref stmt) => self.select(stmt, context),
-            NodeEnum::InsertStmt(ref stmt) => Self::insert(stmt, context),
-            NodeEnum::UpdateStmt(ref stmt) => Self::update(stmt, context),
-            NodeEnum::DeleteStmt(ref stmt) => Self::delete(stmt, context),
+            NodeEnum::InsertStmt(ref stmt) => self.insert(stmt, context),
+            NodeEnum::UpdateStmt(ref stmt) => self.update(stmt, context),
+            NodeEnum::DeleteStmt(ref stmt) => self.delete(stmt, context),
 
             _ => {
                 // For other statement types, route to all shards
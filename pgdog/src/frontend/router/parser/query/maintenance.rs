@@ -0,0 +1,24 @@
+//! Maintenance statements: `TRUNCATE`, `VACUUM` and `ANALYZE`.
+//!
+//! Sharded tables are split between shards and omnisharded tables are copied
+//! to each one, so these statements run on all shards. The client gets one
+//! `CommandComplete`, after every shard executed the statement.
+
+use super::*;
+
+impl QueryParser {
+    /// `TRUNCATE` runs on all shards.
+    pub(super) fn truncate(&self) -> Result<Command, Error> {
+        Ok(Command::Query(Route::write(Shard::All)))
+    }
+
+    /// `VACUUM` and `ANALYZE` run on all shards. Like Postgres,
+    /// `VACUUM` can't run inside a transaction, so we don't send it to one.
+    pub(super) fn vacuum(&self, stmt: &VacuumStmt) -> Result<Command, Error> {
+        if stmt.is_vacuumcmd && self.in_transaction {
+            return Err(Error::VacuumInTransaction);
+        }
+
+        Ok(Command::Query(Route::write(Shard::All)))
+    }
+}
@@ -1,28 +1,36 @@
 //! Route queries to correct shards.
-use std::collections::HashSet;
+use std::{
+    collections::HashSet,
+    panic::{catch_unwind, AssertUnwindSafe},
+};
 
 use crate::{
     backend::{databases::databases, ShardingSchema},
+    config::{CrossShardJoin, ManualQuery, ManualQueryRole, OversizedQuery, QueuePatternRouting},
     frontend::{
         router::{
             context::RouterContext,
+            history::RoutingInputs,
             parser::{rewrite::Rewrite, OrderBy, Shard},
-            round_robin,
-            sharding::{Centroids, ContextBuilder, Value as ShardingValue},
+            round_robin::{Purpose, RoundRobin},
+            sharding::{client_shard, Centroids, ContextBuilder, Value as ShardingValue},
         },
         BufferedQuery,
     },
     net::{
         messages::{Bind, Vector},
-        parameter::ParameterValue,
+        parameter::{ParameterValue, ROLE_PARAMS},
     },
     plugin::plugins,
 };
 
 use super::*;
+mod degraded;
 mod delete;
 mod explain;
+mod maintenance;
 mod plugins;
+mod read_only;
 mod select;
 mod set;
 mod shared;
@@ -32,13 +40,13 @@ mod update;
 
 use multi_tenant::MultiTenantCheck;
 use pgdog_plugin::pg_query::{
-    fingerprint,
+    fingerprint, normalize,
     protobuf::{a_const::Val, *},
     NodeEnum,
 };
 use plugins::PluginOutput;
 
-use tracing::{debug, trace};
+use tracing::{debug, error, trace, warn};
 
 /// Query parser.
 ///
@@ -62,6 +70,18 @@ pub struct QueryParser {
     shard: Shard,
     // Plugin read override.
     plugin_output: PluginOutput,
+    // The statement was found in the AST cache.
+    cache_hit: Option<bool>,
+    // The statement only reads data, regardless of where it's routed.
+    read_statement: bool,
+    // The statement is DDL, if it was parsed.
+    ddl_statement: Option<bool>,
+    // Fingerprint of the statement, if it was needed.
+    fingerprint: Option<String>,
+    // Inputs that decided the route, for the routing history.
+    inputs: RoutingInputs,
+    // Record the inputs.
+    record_inputs: bool,
 }
 
 impl Default for QueryParser {
@@ -71,6 +91,12 @@ impl Default for QueryParser {
             write_override: false,
             shard: Shard::All,
             plugin_output: PluginOutput::default(),
+            cache_hit: None,
+            read_statement: false,
+            ddl_statement: None,
+            fingerprint: None,
+            inputs: RoutingInputs::default(),
+            record_inputs: false,
         }
     }
 }
@@ -81,9 +107,51 @@ impl QueryParser {
         self.in_transaction
     }
 
+    /// Was the last statement found in the AST cache?
+    /// `None` if the statement wasn't parsed or isn't cached.
+    pub fn cache_hit(&self) -> Option<bool> {
+        self.cache_hit
+    }
+
+    /// Does the last statement only read data without locking it?
+    /// Unlike the route, this ignores the read/write split strategy.
+    pub fn read_statement(&self) -> bool {
+        self.read_statement
+    }
+
+    /// The statement is DDL, or `None` if it wasn't parsed.
+    pub fn ddl_statement(&self) -> Option<bool> {
+        self.ddl_statement
+    }
+
+    /// Fingerprint of the last statement, if routing needed it.
+    pub fn fingerprint(&self) -> Option<&str> {
+        self.fingerprint.as_deref()
+    }
+
+    /// Fingerprint the statement, unless that was done already.
+    fn compute_fingerprint(&mut self, query: &str) -> Result<&str, Error> {
+        if self.fingerprint.is_none() {
+            self.fingerprint = Some(fingerprint(query).map_err(Error::PgQuery)?.hex);
+        }
+
+        Ok(self.fingerprint.as_deref().unwrap_or_default())
+    }
+
+    /// Inputs that decided the route of the last statement.
+    pub fn inputs(&self) -> &RoutingInputs {
+        &self.inputs
+    }
+
     /// Parse a query and return a command.
     pub fn parse(&mut self, context: RouterContext) -> Result<Command, Error> {
         let mut qp_context = QueryParserContext::new(context);
+        self.cache_hit = None;
+        self.read_statement = false;
+        self.ddl_statement = None;
+        self.fingerprint = None;
+        self.inputs = RoutingInputs::default();
+        self.record_inputs = qp_context.record_inputs;
 
         let mut command = if qp_context.query().is_ok() {
             self.in_transaction = qp_context.router_context.in_transaction();
@@ -122,7 +190,7 @@ impl QueryParser {
             if use_parser { "enabled" } else { "disabled" }
         );
 
-        if !use_parser {
+        if !use_parser && !context.set_debug() && !context.set_role() && !context.show_or_set() {
             // Cluster is read-only and only has one shard.
             if context.read_only {
                 return Ok(Command::Query(Route::read(Shard::Direct(0))));
@@ -133,6 +201,17 @@ impl QueryParser {
             }
         }
 
+        // Physical replication commands aren't SQL.
+        // WAL isn't sharded, so it comes from the first shard.
+        if context.replication_mode {
+            if let Some(BufferedQuery::Query(ref query)) = context.router_context.query {
+                if let Some(command) = ReplicationCommand::parse(query.query()) {
+                    debug!("replication command: {:?}", command);
+                    return Ok(Command::Query(Route::write(Shard::Direct(0))));
+                }
+            }
+        }
+
         // e.g. Parse, Describe, Flush
         // if !context.router_context.executable {
         //     return Ok(Command::Query(
@@ -150,10 +229,22 @@ impl QueryParser {
 
         let cache = Cache::get();
 
+        // Parsing very long queries is slow, skip them.
+        if let Some(max) = context.max_query_length_for_parsing {
+            let len = context.query()?.query().len();
+            if len > max {
+                cache.skipped();
+                return self.oversized(context, len, max);
+            }
+        }
+
         // Get the AST from cache or parse the statement live.
-        let statement = match context.query()? {
+        //
+        // The parser shouldn't panic, but if it does,
+        // fail this query instead of taking down the process.
+        let statement = catch_unwind(AssertUnwindSafe(|| match context.query()? {
             // Only prepared statements (or just extended) are cached.
-            BufferedQuery::Prepared(query) => cache.parse(query.query()).map_err(Error::PgQuery)?,
+            BufferedQuery::Prepared(query) => cache.parse(query.query()).map_err(Error::PgQuery),
             // Don't cache simple queries.
             //
             // They contain parameter values, which makes the cache
@@ -162,14 +253,39 @@ impl QueryParser {
             // Make your clients use prepared statements
             // or at least send statements with placeholders using the
             // extended protocol.
-            BufferedQuery::Query(query) => cache
-                .parse_uncached(query.query())
-                .map_err(Error::PgQuery)?,
-        };
+            BufferedQuery::Query(query) => {
+                cache.parse_uncached(query.query()).map_err(Error::PgQuery)
+            }
+        }))
+        .map_err(|panic| {
+            cache.failed();
+            let reason = panic
+                .downcast_ref::<&str>()
+                .map(|reason| reason.to_string())
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "panic".into());
+            error!(
+                "query parser failed on a {} byte query: {}",
+                context
+                    .query()
+                    .map(|query| query.query().len())
+                    .unwrap_or(0),
+                reason
+            );
+            Error::ParserPanic(reason)
+        })??;
+
+        self.cache_hit = statement.cached.then_some(statement.hit);
 
         debug!("{}", context.query()?.query());
         trace!("{:#?}", statement.ast());
 
+        if context.writes_disabled {
+            self.check_read_only(statement.ast(), context)?;
+        }
+
+        context.policy.check(statement.ast())?;
+
         let rewrite = Rewrite::new(statement.ast());
         if rewrite.needs_rewrite() {
             debug!("rewrite needed");
@@ -205,11 +321,21 @@ impl QueryParser {
             .as_ref()
             .ok_or(Error::EmptyQuery)?;
 
+        self.ddl_statement = Some(
+            root.node
+                .as_ref()
+                .and_then(Self::write_statement)
+                .is_some_and(|statement| statement == "DDL"),
+        );
+
         let mut command = match root.node {
             // SET statements -> return immediately.
             Some(NodeEnum::VariableSetStmt(ref stmt)) => return self.set(stmt, context),
             // SHOW statements -> return immediately.
-            Some(NodeEnum::VariableShowStmt(ref stmt)) => return self.show(stmt, context),
+            Some(NodeEnum::VariableShowStmt(ref stmt)) => {
+                let single = statement.ast().protobuf.stmts.len() == 1;
+                return self.show(stmt, single, context);
+            }
             // DEALLOCATE statements -> return immediately.
             Some(NodeEnum::DeallocateStmt(_)) => {
                 return Ok(Command::Deallocate);
@@ -217,13 +343,13 @@ impl QueryParser {
             // SELECT statements.
             Some(NodeEnum::SelectStmt(ref stmt)) => self.select(stmt, context),
             // COPY statements.
-            Some(NodeEnum::CopyStmt(ref stmt)) => Self::copy(stmt, context),
+            Some(NodeEnum::CopyStmt(ref stmt)) => self.copy(stmt, context),
             // INSERT statements.
-            Some(NodeEnum::InsertStmt(ref stmt)) => Self::insert(stmt, context),
+            Some(NodeEnum::InsertStmt(ref stmt)) => self.insert(stmt, context),
             // UPDATE statements.
-            Some(NodeEnum::UpdateStmt(ref stmt)) => Self::update(stmt, context),
+            Some(NodeEnum::UpdateStmt(ref stmt)) => self.update(stmt, context),
             // DELETE statements.
-            Some(NodeEnum::DeleteStmt(ref stmt)) => Self::delete(stmt, context),
+            Some(NodeEnum::DeleteStmt(ref stmt)) => self.delete(stmt, context),
             // Transaction control statements,
             // e.g. BEGIN, COMMIT, etc.
             Some(NodeEnum::TransactionStmt(ref stmt)) => match self.transaction(stmt, context)? {
@@ -263,6 +389,21 @@ impl QueryParser {
 
             Some(NodeEnum::ExplainStmt(ref stmt)) => self.explain(stmt, context),
 
+            // TRUNCATE, VACUUM and ANALYZE.
+            Some(NodeEnum::TruncateStmt(_)) => self.truncate(),
+            Some(NodeEnum::VacuumStmt(ref stmt)) => self.vacuum(stmt),
+
+            // DDL can change column order, reload it when needed next.
+            Some(
+                NodeEnum::CreateStmt(_)
+                | NodeEnum::AlterTableStmt(_)
+                | NodeEnum::DropStmt(_)
+                | NodeEnum::RenameStmt(_),
+            ) => {
+                context.sharding_schema.columns.invalidate();
+                Ok(Command::Query(Route::write(None)))
+            }
+
             // All others are not handled.
             // They are sent to all shards concurrently.
             _ => Ok(Command::Query(Route::write(None))),
@@ -282,6 +423,7 @@ impl QueryParser {
         if let Shard::Direct(shard) = self.shard {
             if let Command::Query(ref mut route) = command {
                 route.set_shard_mut(shard);
+                self.inputs.comment_shard = Some(Shard::Direct(shard));
             }
         }
 
@@ -290,10 +432,12 @@ impl QueryParser {
         if let Command::Query(ref mut route) = command {
             if let Some(read) = self.plugin_output.read {
                 route.set_read_mut(read);
+                self.inputs.plugin_override = true;
             }
 
             if let Some(ref shard) = self.plugin_output.shard {
                 route.set_shard_raw_mut(shard);
+                self.inputs.plugin_override = true;
             }
         }
 
@@ -320,38 +464,121 @@ impl QueryParser {
                 // Only fingerprint the query if some manual queries are configured.
                 // Otherwise, we're wasting time parsing SQL.
                 if !databases.manual_queries().is_empty() {
-                    let fingerprint =
-                        fingerprint(context.query()?.query()).map_err(Error::PgQuery)?;
-                    debug!("fingerprint: {}", fingerprint.hex);
-                    let manual_route = databases.manual_query(&fingerprint.hex).cloned();
-
-                    // TODO: check routing logic required by config.
-                    if manual_route.is_some() {
-                        route.set_shard_mut(round_robin::next() % context.shards);
+                    let fingerprint = self.compute_fingerprint(context.query()?.query())?;
+                    debug!("fingerprint: {}", fingerprint);
+                    if let Some(manual) = databases.manual_query(fingerprint) {
+                        Self::manual_route(
+                            route,
+                            manual,
+                            context.shards,
+                            &context.sharding_schema.round_robin,
+                        );
+                        self.inputs.manual_query = true;
                     }
                 }
             }
         }
 
+        if let Command::Query(ref mut route) = command {
+            self.degraded_read(route, context);
+        }
+
         debug!("query router decision: {:#?}", command);
 
         statement.update_stats(command.route());
 
         if context.dry_run {
+            let query = context.query()?.query();
+            let normalized = normalize(query).map_err(Error::PgQuery)?;
             // Record statement in cache with normalized parameters.
             if !statement.cached {
                 cache
-                    .record_normalized(context.query()?.query(), command.route())
+                    .record_normalized(&normalized, command.route())
                     .map_err(Error::PgQuery)?;
             }
-            Ok(command.dry_run())
+
+            let computed = match command {
+                Command::Query(ref route) => Some(route.shard().clone()),
+                Command::Copy(_) => Some(Shard::All),
+                _ => None,
+            };
+            let command = command.dry_run();
+
+            if let Some(computed) = computed {
+                let fingerprint = self.compute_fingerprint(query)?;
+                dry_run::record(fingerprint, &normalized, &computed, command.route().shard());
+            }
+
+            Ok(command)
         } else {
             Ok(command)
         }
     }
 
+    /// Route a query that's too long to parse.
+    fn oversized(
+        &self,
+        context: &QueryParserContext,
+        len: usize,
+        max: usize,
+    ) -> Result<Command, Error> {
+        // Policies can't be checked without parsing.
+        if !context.policy.is_empty() {
+            return Err(Error::QueryTooLong(len, max));
+        }
+
+        match context.oversized_query {
+            OversizedQuery::Error => Err(Error::QueryTooLong(len, max)),
+            OversizedQuery::Primary => {
+                warn!(
+                    "query is {} bytes long, over max_query_length_for_parsing ({}), sending it to the primary",
+                    len, max
+                );
+                // Shard from a comment, if any. Otherwise all shards.
+                let shard = match self.shard {
+                    Shard::Direct(shard) => Shard::Direct(shard),
+                    _ => Shard::All,
+                };
+                Ok(Command::Query(Route::write(shard)))
+            }
+        }
+    }
+
+    /// Route the query using its `[[manual_queries]]` entry,
+    /// round robin between shards if it doesn't set any.
+    fn manual_route(
+        route: &mut Route,
+        query: &ManualQuery,
+        shards: usize,
+        round_robin: &RoundRobin,
+    ) {
+        match query.shard_numbers().as_slice() {
+            [] => route.set_shard_mut(round_robin.next(Purpose::ManualQuery) % shards),
+            [shard] => route.set_shard_mut(*shard),
+            numbers => route.set_shard_raw_mut(&Shard::Multi(numbers.to_vec())),
+        }
+
+        if let Some(role) = query.role {
+            route.set_read_mut(role == ManualQueryRole::Read);
+        }
+    }
+
     /// Handle COPY command.
-    fn copy(stmt: &CopyStmt, context: &QueryParserContext) -> Result<Command, Error> {
+    fn copy(&mut self, stmt: &CopyStmt, context: &QueryParserContext) -> Result<Command, Error> {
+        // COPY (SELECT ...) TO STDOUT is routed using the SELECT.
+        if let Some(NodeEnum::SelectStmt(ref select)) =
+            stmt.query.as_ref().and_then(|query| query.node.as_ref())
+        {
+            let command = self.select(select, context)?;
+            if let Command::Query(ref route) = command {
+                // TODO: merge COPY output from multiple shards.
+                if route.is_cross_shard() && context.shards > 1 {
+                    return Err(Error::CrossShardCopy);
+                }
+            }
+            return Ok(command);
+        }
+
         let parser = CopyParser::new(stmt, context.router_context.cluster)?;
         if let Some(parser) = parser {
             Ok(Command::Copy(Box::new(parser)))
@@ -367,9 +594,57 @@ impl QueryParser {
     /// * `stmt`: INSERT statement from pg_query.
     /// * `context`: Query parser context.
     ///
-    fn insert(stmt: &InsertStmt, context: &QueryParserContext) -> Result<Command, Error> {
+    fn insert(
+        &mut self,
+        stmt: &InsertStmt,
+        context: &QueryParserContext,
+    ) -> Result<Command, Error> {
         let insert = Insert::new(stmt);
-        let shard = insert.shard(&context.sharding_schema, context.router_context.bind)?;
+
+        // INSERT ... SELECT is only correct if the rows stay on the shard they're read from.
+        if context.shards > 1 {
+            if let Some(select) = insert.select() {
+                if let Some(same_key) = insert.same_sharding_key(&context.sharding_schema) {
+                    let shard = match self.select(select, context)? {
+                        Command::Query(route) => route.shard().clone(),
+                        _ => Shard::All,
+                    };
+                    self.read_statement = false;
+
+                    return match shard {
+                        Shard::Direct(shard) if same_key => {
+                            Ok(Command::Query(Route::write(Shard::Direct(shard))))
+                        }
+                        Shard::Direct(_) => Err(Error::InsertSelectShardingKey(
+                            insert
+                                .table()
+                                .map(|t| t.name)
+                                .unwrap_or_default()
+                                .to_owned(),
+                        )),
+                        _ => Err(Error::CrossShardInsertSelect),
+                    };
+                }
+            }
+        }
+
+        // Rows of a multi-row INSERT can belong to different shards.
+        if context.shards > 1 && insert.tuples().len() > 1 {
+            let bind = context.router_context.bind;
+            if let Some(shards) = insert.row_shards(&context.sharding_schema, bind)? {
+                if shards.iter().any(|shard| *shard != shards[0]) {
+                    let params = bind.map(|bind| bind.params_raw().len()).unwrap_or(0);
+                    let split = InsertSplit::new(stmt, &shards, params)?;
+                    return Ok(Command::Query(Route::write(None).set_insert_split(split)));
+                }
+            }
+        }
+
+        let shard = insert.shard_with_inputs(
+            &context.sharding_schema,
+            context.router_context.bind,
+            self.record_inputs.then_some(&mut self.inputs),
+        )?;
         Ok(Command::Query(Route::write(shard)))
     }
 }
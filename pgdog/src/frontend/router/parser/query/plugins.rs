@@ -1,5 +1,9 @@
+use std::borrow::Cow;
+
+use crate::config::config;
 use crate::frontend::router::parser::cache::CachedAst;
-use pgdog_plugin::{ReadWrite, Shard as PdShard};
+use crate::plugin::budgets;
+use pgdog_plugin::{PdSessionParameter, ReadWrite, Shard as PdShard};
 
 use super::*;
 
@@ -43,12 +47,43 @@ impl QueryParser {
         // The first plugin to returns something, wins.
         debug!("executing {} router plugins", plugins.len());
 
+        // Session parameters, e.g. `SET app.tenant_id = '42'`.
+        // They stay in memory until all plugins are done.
+        let values = context
+            .router_context
+            .params
+            .iter()
+            .map(|(name, value)| {
+                let value = match value {
+                    ParameterValue::String(value) => Cow::Borrowed(value.as_str()),
+                    ParameterValue::Tuple(values) => Cow::Owned(values.join(", ")),
+                };
+                (name.as_str(), value)
+            })
+            .collect::<Vec<_>>();
+        let session_params = values
+            .iter()
+            .map(|(name, value)| PdSessionParameter {
+                name: (*name).into(),
+                value: value.as_ref().into(),
+            })
+            .collect::<Vec<_>>();
+
         let mut context =
             context.plugin_context(&statement.ast().protobuf, &context.router_context.bind);
         context.write_override = if self.write_override || !read { 1 } else { 0 };
+        context.session_params = session_params.as_slice().into();
+
+        let (timeout, action) = {
+            let config = config();
+            (
+                config.config.general.plugin_timeout(),
+                config.config.general.plugin_slow_action,
+            )
+        };
 
-        for plugin in plugins {
-            if let Some(route) = plugin.route(context) {
+        for (plugin, budget) in plugins.iter().zip(budgets()) {
+            if let Some(route) = budget.route(plugin, context, timeout, action) {
                 match route.shard.try_into() {
                     Ok(shard) => match shard {
                         PdShard::All => self.plugin_output.shard = Some(Shard::All),
@@ -57,7 +92,12 @@ impl QueryParser {
                         }
                         PdShard::Unknown => self.plugin_output.shard = None,
                         PdShard::Blocked => {
-                            return Err(Error::BlockedByPlugin(plugin.name().to_owned()))
+                            // The plugin owns the message, copy it before calling it again.
+                            let name = plugin.name().to_owned();
+                            return Err(match route.message.to_string() {
+                                message if message.is_empty() => Error::BlockedByPlugin(name),
+                                message => Error::BlockedByPluginMessage(name, message),
+                            });
                         }
                     },
                     Err(_) => self.plugin_output.shard = None,
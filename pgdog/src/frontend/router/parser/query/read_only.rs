@@ -0,0 +1,112 @@
+//! Writes to read-only databases.
+//!
+//! Clusters where every database is configured with `read_only` only accept
+//! statements that don't change data. Writes are rejected before a connection
+//! is checked out, so they fail the same way whether or not the server is read-only.
+
+use pg_query::ParseResult;
+
+use super::*;
+
+impl QueryParser {
+    /// Reject the query if any of its statements writes.
+    pub(super) fn check_read_only(
+        &self,
+        ast: &ParseResult,
+        context: &QueryParserContext,
+    ) -> Result<(), Error> {
+        let writes = ast
+            .protobuf
+            .stmts
+            .iter()
+            .filter_map(|stmt| stmt.stmt.as_ref()?.node.as_ref())
+            .find_map(Self::write_statement);
+
+        if let Some(statement) = writes {
+            return Err(Error::ReadOnly(
+                statement,
+                context.router_context.cluster.name().to_owned(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Name of the statement if it writes, e.g. "INSERT".
+    pub(crate) fn write_statement(node: &NodeEnum) -> Option<&'static str> {
+        match node {
+            NodeEnum::InsertStmt(_) => Some("INSERT"),
+            NodeEnum::UpdateStmt(_) => Some("UPDATE"),
+            NodeEnum::DeleteStmt(_) => Some("DELETE"),
+            NodeEnum::MergeStmt(_) => Some("MERGE"),
+            NodeEnum::TruncateStmt(_) => Some("TRUNCATE"),
+            NodeEnum::CopyStmt(stmt) => stmt.is_from.then_some("COPY FROM"),
+
+            NodeEnum::SelectStmt(stmt) => {
+                if !stmt.locking_clause.is_empty() {
+                    Some("SELECT FOR UPDATE")
+                } else if stmt.into_clause.is_some() {
+                    Some("SELECT INTO")
+                } else if Self::cte_writes(stmt) {
+                    Some("WITH")
+                } else {
+                    None
+                }
+            }
+
+            // Only EXPLAIN ANALYZE executes the statement.
+            NodeEnum::ExplainStmt(stmt) => {
+                let analyze = stmt.options.iter().any(|option| {
+                    matches!(option.node, Some(NodeEnum::DefElem(ref elem)) if elem.defname == "analyze")
+                });
+                if analyze {
+                    stmt.query
+                        .as_ref()?
+                        .node
+                        .as_ref()
+                        .and_then(Self::write_statement)
+                } else {
+                    None
+                }
+            }
+
+            NodeEnum::PrepareStmt(stmt) => stmt
+                .query
+                .as_ref()?
+                .node
+                .as_ref()
+                .and_then(Self::write_statement),
+
+            NodeEnum::CreateStmt(_)
+            | NodeEnum::CreateTableAsStmt(_)
+            | NodeEnum::CreateSeqStmt(_)
+            | NodeEnum::CreateSchemaStmt(_)
+            | NodeEnum::CreateFunctionStmt(_)
+            | NodeEnum::CreateExtensionStmt(_)
+            | NodeEnum::CreateTrigStmt(_)
+            | NodeEnum::CreatePolicyStmt(_)
+            | NodeEnum::CreateEnumStmt(_)
+            | NodeEnum::CreateDomainStmt(_)
+            | NodeEnum::CompositeTypeStmt(_)
+            | NodeEnum::DefineStmt(_)
+            | NodeEnum::IndexStmt(_)
+            | NodeEnum::ViewStmt(_)
+            | NodeEnum::RuleStmt(_)
+            | NodeEnum::AlterTableStmt(_)
+            | NodeEnum::AlterSeqStmt(_)
+            | NodeEnum::AlterEnumStmt(_)
+            | NodeEnum::AlterObjectSchemaStmt(_)
+            | NodeEnum::AlterOwnerStmt(_)
+            | NodeEnum::RenameStmt(_)
+            | NodeEnum::DropStmt(_)
+            | NodeEnum::CommentStmt(_)
+            | NodeEnum::GrantStmt(_)
+            | NodeEnum::GrantRoleStmt(_)
+            | NodeEnum::RefreshMatViewStmt(_)
+            | NodeEnum::ReindexStmt(_)
+            | NodeEnum::ClusterStmt(_) => Some("DDL"),
+
+            _ => None,
+        }
+    }
+}
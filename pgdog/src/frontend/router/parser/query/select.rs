@@ -14,7 +14,12 @@ impl QueryParser {
         context: &QueryParserContext,
     ) -> Result<Command, Error> {
         let cte_writes = Self::cte_writes(stmt);
-        let mut writes = Self::functions(stmt)?;
+        let mut writes = Self::functions(stmt, context.function_lists)?;
+
+        self.read_statement = !writes.writes
+            && !cte_writes
+            && stmt.locking_clause.is_empty()
+            && writes.locking_behavior == LockingBehavior::default();
 
         // Write overwrite because of conservative read/write split.
         if self.write_override {
@@ -31,31 +36,35 @@ impl QueryParser {
             ));
         }
 
+        let cte_shards = self.cte_shards(stmt, context)?;
+
         // `SELECT NOW()`, `SELECT 1`, etc.
-        if stmt.from_clause.is_empty() {
-            return Ok(Command::Query(
-                Route::read(Some(round_robin::next() % context.shards)).set_write(writes),
-            ));
+        if stmt.from_clause.is_empty() && cte_shards.is_empty() {
+            // Large objects are stored on the first shard,
+            // unless the client picked one explicitly.
+            let shard = if writes.large_object {
+                0
+            } else {
+                context.sharding_schema.round_robin.next(Purpose::Select) % context.shards
+            };
+            return Ok(Command::Query(Route::read(Some(shard)).set_write(writes)));
         }
 
         let order_by = Self::select_sort(&stmt.sort_clause, context.router_context.bind);
-        let mut shards = HashSet::new();
         let the_table = Table::try_from(&stmt.from_clause).ok();
-        if let Some(where_clause) =
-            WhereClause::new(the_table.as_ref().map(|t| t.name), &stmt.where_clause)
-        {
-            shards = Self::where_clause(
-                &context.sharding_schema,
-                &where_clause,
-                context.router_context.bind,
-            )?;
+        let mut shards = self.select_shards(stmt, context)?;
+        // A sharded table read without a sharding key is on all shards,
+        // no matter which shards the CTEs are on.
+        if !shards.is_empty() || Self::reads_ctes_only(stmt, context) {
+            shards.extend(cte_shards);
         }
 
         // Shard by vector in ORDER BY clause.
         for order in &order_by {
             if let Some((vector, column_name)) = order.vector() {
                 for table in context.sharding_schema.tables.tables() {
-                    if &table.column == column_name
+                    // Vectors can't be part of a composite key.
+                    if table.key_columns() == [column_name.as_str()]
                         && (table.name.is_none()
                             || table.name.as_deref() == the_table.as_ref().map(|t| t.name))
                     {
@@ -77,6 +86,14 @@ impl QueryParser {
 
         let mut query = Route::select(shard, order_by, aggregates, limit, distinct);
 
+        // Job queue workers polling with `SELECT ... FOR UPDATE SKIP LOCKED`
+        // each drain their own shard.
+        if query.is_all_shards() && !stmt.locking_clause.is_empty() {
+            if let Some(shard) = Self::worker_shard(the_table.as_ref(), context) {
+                query.set_shard_mut(shard);
+            }
+        }
+
         let mut omni = false;
         if query.is_all_shards() {
             if let Some(name) = the_table.as_ref().map(|t| t.name) {
@@ -85,12 +102,234 @@ impl QueryParser {
         }
 
         if omni {
-            query.set_shard_mut(round_robin::next() % context.shards);
+            query.set_shard_mut(
+                context.sharding_schema.round_robin.next(Purpose::Select) % context.shards,
+            );
+        }
+
+        // Large object functions can't run on multiple shards.
+        if writes.large_object && query.is_cross_shard() {
+            query.set_shard_mut(0);
         }
 
         Ok(Command::Query(query.set_write(writes)))
     }
 
+    /// Shard for a locking `SELECT` without a sharding key, if the table
+    /// uses `queue_pattern_routing = "worker_sticky"`.
+    fn worker_shard(table: Option<&Table>, context: &QueryParserContext) -> Option<usize> {
+        let table = context.sharding_schema.tables.table(table?.name)?;
+        let client_id = context.router_context.client_id.as_ref()?;
+
+        if table.queue_pattern_routing == QueuePatternRouting::WorkerSticky {
+            Some(client_shard(client_id, context.shards))
+        } else {
+            None
+        }
+    }
+
+    /// Shards matching the sharding keys in the `WHERE` clause and joins.
+    fn select_shards(
+        &mut self,
+        stmt: &SelectStmt,
+        context: &QueryParserContext,
+    ) -> Result<HashSet<Shard>, Error> {
+        let mut shards = HashSet::new();
+        let where_clause = WhereClause::select(stmt);
+        if let Some(ref where_clause) = where_clause {
+            shards = self.where_clause(
+                &context.sharding_schema,
+                where_clause,
+                context.router_context.bind,
+            )?;
+        }
+
+        // Joined sharded tables must have matching rows on the same shard.
+        if context.shards > 1 {
+            if let Some(join) = Join::new(stmt) {
+                shards.extend(self.join(&join, where_clause.as_ref(), context)?);
+            }
+        }
+
+        Ok(shards)
+    }
+
+    /// Shards used by queries in the `WITH` clause.
+    ///
+    /// Read-only CTEs narrow down the shards like the `WHERE` clause does,
+    /// unless the query also reads sharded tables on all shards.
+    /// Data-modifying CTEs run on all shards they write to, even if the
+    /// rest of the query is on one shard. Recursive CTEs reference themselves
+    /// and aren't used for routing.
+    ///
+    fn cte_shards(
+        &mut self,
+        stmt: &SelectStmt,
+        context: &QueryParserContext,
+    ) -> Result<HashSet<Shard>, Error> {
+        let mut shards = HashSet::new();
+        let Some(ref with_clause) = stmt.with_clause else {
+            return Ok(shards);
+        };
+
+        if with_clause.recursive {
+            return Ok(shards);
+        }
+
+        for cte in &with_clause.ctes {
+            let Some(NodeEnum::CommonTableExpr(ref expr)) = cte.node else {
+                continue;
+            };
+            let Some(node) = expr.ctequery.as_ref().and_then(|query| query.node.as_ref()) else {
+                continue;
+            };
+
+            let command = match node {
+                NodeEnum::SelectStmt(stmt) => {
+                    let select = self.select_shards(stmt, context)?;
+                    let ctes = self.cte_shards(stmt, context)?;
+                    if select.is_empty() && !Self::reads_ctes_only(stmt, context) {
+                        shards.insert(Shard::All);
+                    }
+                    shards.extend(select);
+                    shards.extend(ctes);
+                    continue;
+                }
+                NodeEnum::InsertStmt(stmt) => self.insert(stmt, context)?,
+                NodeEnum::UpdateStmt(stmt) => self.update(stmt, context)?,
+                NodeEnum::DeleteStmt(stmt) => self.delete(stmt, context)?,
+                _ => continue,
+            };
+
+            // Multi-row INSERTs can't be split inside a CTE.
+            shards.insert(match command {
+                Command::Query(route) if route.insert_split().is_none() => route.shard().clone(),
+                _ => Shard::All,
+            });
+        }
+
+        Ok(shards)
+    }
+
+    /// The `FROM` clause only reads CTEs and tables that aren't sharded,
+    /// so the CTEs decide which shards the query runs on.
+    fn reads_ctes_only(stmt: &SelectStmt, context: &QueryParserContext) -> bool {
+        let ctes = stmt
+            .with_clause
+            .as_ref()
+            .map(|with_clause| {
+                with_clause
+                    .ctes
+                    .iter()
+                    .filter_map(|cte| match cte.node {
+                        Some(NodeEnum::CommonTableExpr(ref expr)) => Some(expr.ctename.as_str()),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+
+        stmt.from_clause
+            .iter()
+            .all(|node| Self::unsharded_source(node, &ctes, context))
+    }
+
+    /// The `FROM` clause item doesn't read sharded tables. Subqueries and
+    /// functions aren't checked, so they are assumed to read them.
+    fn unsharded_source(node: &Node, ctes: &[&str], context: &QueryParserContext) -> bool {
+        match node.node {
+            Some(NodeEnum::RangeVar(ref range)) => {
+                if range.schemaname.is_empty() && ctes.contains(&range.relname.as_str()) {
+                    return true;
+                }
+
+                // Tables sharded by column name can be any table.
+                let tables = &context.sharding_schema.tables;
+                tables.table(&range.relname).is_none()
+                    && tables.tables().iter().all(|table| table.name.is_some())
+            }
+
+            Some(NodeEnum::JoinExpr(ref join)) => [&join.larg, &join.rarg].into_iter().all(|arg| {
+                arg.as_deref()
+                    .map(|arg| Self::unsharded_source(arg, ctes, context))
+                    .unwrap_or(true)
+            }),
+
+            _ => false,
+        }
+    }
+
+    /// Route a join between sharded tables. Tables joined on their sharding keys
+    /// are on the same shard and use each other's keys. Other joins are only
+    /// correct if all tables are filtered down to the same shard.
+    fn join(
+        &mut self,
+        join: &Join,
+        where_clause: Option<&WhereClause>,
+        context: &QueryParserContext,
+    ) -> Result<HashSet<Shard>, Error> {
+        let groups = join.co_sharded(&context.sharding_schema);
+        let mut group_shards = vec![];
+        // Tables filtered by parameters we don't have values for yet,
+        // e.g. the statement is only being prepared.
+        let mut unbound = false;
+
+        for group in &groups {
+            let mut shards = HashSet::new();
+            if let Some(where_clause) = where_clause {
+                for joined in group {
+                    shards.extend(self.table_shards(
+                        &context.sharding_schema,
+                        joined.table,
+                        Some(joined.reference),
+                        where_clause,
+                        context.router_context.bind,
+                    )?);
+
+                    if context.router_context.bind.is_none() {
+                        unbound |= joined.table.key_columns().into_iter().all(|column| {
+                            where_clause
+                                .keys(Some(joined.reference), column)
+                                .iter()
+                                .any(|key| matches!(key, Key::Parameter { array: false, .. }))
+                        });
+                    }
+                }
+            }
+            group_shards.push(shards);
+        }
+
+        if group_shards.len() <= 1 {
+            return Ok(group_shards.pop().unwrap_or_default());
+        }
+
+        // Checked again once the client sends Bind.
+        if unbound {
+            return Ok(HashSet::new());
+        }
+
+        let shards = group_shards
+            .into_iter()
+            .map(Self::converge)
+            .collect::<HashSet<_>>();
+
+        if shards.len() == 1 && matches!(shards.iter().next(), Some(Shard::Direct(_))) {
+            return Ok(shards);
+        }
+
+        let (left, right) = (groups[0][0].reference, groups[1][0].reference);
+        match context.cross_shard_join {
+            CrossShardJoin::Error => Err(Error::CrossShardJoin(left.into(), right.into())),
+            CrossShardJoin::Warn => {
+                warn!(
+                    "join between \"{}\" and \"{}\" isn't on their sharding keys, results can be incomplete",
+                    left, right
+                );
+                Ok(HashSet::new())
+            }
+        }
+    }
+
     /// Handle the `ORDER BY` clause of a `SELECT` statement.
     ///
     /// # Arguments
@@ -197,11 +436,15 @@ impl QueryParser {
     /// # Arguments
     ///
     /// * `stmt`: SELECT statement from pg_query.
+    /// * `lists`: Functions configured as read-safe or writing.
     ///
-    fn functions(stmt: &SelectStmt) -> Result<FunctionBehavior, Error> {
+    fn functions(stmt: &SelectStmt, lists: &FunctionLists) -> Result<FunctionBehavior, Error> {
         for target in &stmt.target_list {
             if let Ok(func) = Function::try_from(target) {
-                return Ok(func.behavior());
+                let behavior = func.behavior_with(lists);
+                if behavior.writes {
+                    return Ok(behavior);
+                }
             }
         }
 
@@ -57,50 +57,73 @@ impl QueryParser {
                 }
             }
 
+            // Send routing metadata to the client after each statement.
+            "pgdog.debug" => {
+                let debug = match stmt.args.first().and_then(|node| node.node.as_ref()) {
+                    // RESET pgdog.debug
+                    None => false,
+
+                    Some(NodeEnum::AConst(AConst {
+                        val: Some(Val::Sval(String { sval })),
+                        ..
+                    })) => match sval.to_lowercase().as_str() {
+                        "on" | "true" | "yes" => true,
+                        "off" | "false" | "no" => false,
+                        _ => return Err(Error::SetDebug),
+                    },
+
+                    Some(NodeEnum::AConst(AConst {
+                        val: Some(Val::Ival(Integer { ival })),
+                        ..
+                    })) => *ival != 0,
+
+                    _ => return Err(Error::SetDebug),
+                };
+
+                return Ok(Command::SetDebug(debug));
+            }
+
+            // SET TRANSACTION before the first statement is merged
+            // into the pending BEGIN.
+            "TRANSACTION" if self.in_transaction && context.query()?.simple() => {
+                return Ok(Command::SetTransaction(TransactionOptions::from_nodes(
+                    &stmt.args,
+                )));
+            }
+
+            // SET ROLE and SET SESSION AUTHORIZATION change privileges of the server
+            // connection. Unless they can be tracked as client params, the connection
+            // is reset before it's used by another client.
+            name if ROLE_PARAMS.contains(&name) => {
+                if context.reject_set_role && !stmt.args.is_empty() {
+                    return Err(Error::SetRole);
+                }
+
+                if !self.in_transaction && !stmt.is_local {
+                    if let Some(value) = Self::set_value(stmt) {
+                        return Ok(Command::Set {
+                            name: name.to_string(),
+                            value,
+                        });
+                    }
+                }
+
+                return Ok(Command::Query(
+                    Route::write(Shard::All)
+                        .set_read(context.read_only)
+                        .set_changes_role(),
+                ));
+            }
+
             // TODO: Handle SET commands for updating client
             // params without touching the server.
             name => {
                 if !self.in_transaction {
-                    let mut value = vec![];
-
-                    for node in &stmt.args {
-                        if let Some(NodeEnum::AConst(AConst { val: Some(val), .. })) = &node.node {
-                            match val {
-                                Val::Sval(String { sval }) => {
-                                    value.push(sval.to_string());
-                                }
-
-                                Val::Ival(Integer { ival }) => {
-                                    value.push(ival.to_string());
-                                }
-
-                                Val::Fval(Float { fval }) => {
-                                    value.push(fval.to_string());
-                                }
-
-                                Val::Boolval(Boolean { boolval }) => {
-                                    value.push(boolval.to_string());
-                                }
-
-                                _ => (),
-                            }
-                        }
-                    }
-
-                    match value.len() {
-                        0 => (),
-                        1 => {
-                            return Ok(Command::Set {
-                                name: name.to_string(),
-                                value: ParameterValue::String(value.pop().unwrap()),
-                            })
-                        }
-                        _ => {
-                            return Ok(Command::Set {
-                                name: name.to_string(),
-                                value: ParameterValue::Tuple(value),
-                            })
-                        }
+                    if let Some(value) = Self::set_value(stmt) {
+                        return Ok(Command::Set {
+                            name: name.to_string(),
+                            value,
+                        });
                     }
                 }
             }
@@ -110,4 +133,39 @@ impl QueryParser {
             Route::write(Shard::All).set_read(context.read_only),
         ))
     }
+
+    /// Extract the value of a SET statement, if it's made of constants.
+    pub(crate) fn set_value(stmt: &VariableSetStmt) -> Option<ParameterValue> {
+        let mut value = vec![];
+
+        for node in &stmt.args {
+            if let Some(NodeEnum::AConst(AConst { val: Some(val), .. })) = &node.node {
+                match val {
+                    Val::Sval(String { sval }) => {
+                        value.push(sval.to_string());
+                    }
+
+                    Val::Ival(Integer { ival }) => {
+                        value.push(ival.to_string());
+                    }
+
+                    Val::Fval(Float { fval }) => {
+                        value.push(fval.to_string());
+                    }
+
+                    Val::Boolval(Boolean { boolval }) => {
+                        value.push(boolval.to_string());
+                    }
+
+                    _ => (),
+                }
+            }
+        }
+
+        match value.len() {
+            0 => None,
+            1 => Some(ParameterValue::String(value.pop().unwrap())),
+            _ => Some(ParameterValue::Tuple(value)),
+        }
+    }
 }
@@ -1,4 +1,5 @@
 use super::*;
+use crate::config::ShardedTable;
 
 impl QueryParser {
     /// Converge to a single route given multiple shards.
@@ -30,6 +31,7 @@ impl QueryParser {
 
     /// Handle WHERRE clause in SELECT, UPDATE an DELETE statements.
     pub(super) fn where_clause(
+        &mut self,
         sharding_schema: &ShardingSchema,
         where_clause: &WhereClause,
         params: Option<&Bind>,
@@ -37,47 +39,160 @@ impl QueryParser {
         let mut shards = HashSet::new();
         // Complexity: O(number of sharded tables * number of columns in the query)
         for table in sharding_schema.tables().tables() {
-            let table_name = table.name.as_deref();
-            let keys = where_clause.keys(table_name, &table.column);
-            for key in keys {
-                match key {
-                    Key::Constant { value, array } => {
-                        if array {
-                            shards.insert(Shard::All);
-                            break;
-                        }
+            shards.extend(self.table_shards(
+                sharding_schema,
+                table,
+                table.name.as_deref(),
+                where_clause,
+                params,
+            )?);
+        }
+
+        Ok(shards)
+    }
+
+    /// Shards matching the sharding key of one table in the WHERE clause.
+    /// The table is referred to as `table_name` in the query, e.g. by its alias.
+    pub(super) fn table_shards(
+        &mut self,
+        sharding_schema: &ShardingSchema,
+        table: &ShardedTable,
+        table_name: Option<&str>,
+        where_clause: &WhereClause,
+        params: Option<&Bind>,
+    ) -> Result<HashSet<Shard>, Error> {
+        let mut shards = HashSet::new();
 
-                        let ctx = ContextBuilder::new(table)
-                            .data(value.as_str())
-                            .shards(sharding_schema.shards)
-                            .build()?;
-                        shards.insert(ctx.apply()?);
+        if table.composite() {
+            if let Some(shard) =
+                self.composite_key(sharding_schema, table, table_name, where_clause, params)?
+            {
+                shards.insert(shard);
+            }
+            return Ok(shards);
+        }
+
+        let keys = where_clause.keys(table_name, &table.column);
+        for key in keys {
+            match key {
+                Key::Constant { value, array } => {
+                    if array {
+                        shards.insert(Shard::All);
+                        break;
                     }
 
-                    Key::Parameter { pos, array } => {
-                        // Don't hash individual values yet.
-                        // The odds are high this will go to all shards anyway.
-                        if array {
-                            shards.insert(Shard::All);
-                            break;
-                        } else if let Some(params) = params {
-                            if let Some(param) = params.parameter(pos)? {
-                                let value = ShardingValue::from_param(&param, table.data_type)?;
-                                let ctx = ContextBuilder::new(table)
-                                    .value(value)
-                                    .shards(sharding_schema.shards)
-                                    .build()?;
-                                shards.insert(ctx.apply()?);
-                            }
+                    let value = ShardingValue::new(value.as_str(), table.data_type);
+                    self.sharding_value(&value);
+                    let ctx = ContextBuilder::new(table)
+                        .value(value)
+                        .shards(sharding_schema.shards)
+                        .round_robin(&sharding_schema.round_robin)
+                        .build()?;
+                    shards.insert(ctx.apply()?);
+                }
+
+                Key::Parameter { pos, array } => {
+                    // Don't hash individual values yet.
+                    // The odds are high this will go to all shards anyway.
+                    if array {
+                        shards.insert(Shard::All);
+                        break;
+                    } else if let Some(params) = params {
+                        if let Some(param) = params.parameter(pos)? {
+                            let value = ShardingValue::from_param(&param, table.data_type)?;
+                            self.sharding_value(&value);
+                            let ctx = ContextBuilder::new(table)
+                                .value(value)
+                                .shards(sharding_schema.shards)
+                                .round_robin(&sharding_schema.round_robin)
+                                .build()?;
+                            shards.insert(ctx.apply()?);
                         }
                     }
-
-                    // Null doesn't help.
-                    Key::Null => (),
                 }
+
+                // Null doesn't help.
+                Key::Null => (),
             }
         }
 
         Ok(shards)
     }
+
+    /// Route using a composite sharding key. All key columns must be compared
+    /// to exactly one value, otherwise the key doesn't identify a shard.
+    fn composite_key(
+        &mut self,
+        sharding_schema: &ShardingSchema,
+        table: &ShardedTable,
+        table_name: Option<&str>,
+        where_clause: &WhereClause,
+        params: Option<&Bind>,
+    ) -> Result<Option<Shard>, Error> {
+        let mut keys = vec![];
+
+        for column in table.key_columns() {
+            let mut column_keys = where_clause
+                .keys(table_name, column)
+                .into_iter()
+                .filter(|key| !matches!(key, Key::Null))
+                .collect::<Vec<_>>();
+
+            match column_keys.len() {
+                // Partial key.
+                0 => return Ok(None),
+                1 => keys.push(column_keys.remove(0)),
+                _ => return Ok(Some(Shard::All)),
+            }
+        }
+
+        // Bind parameters, in key column order.
+        let mut binds = vec![];
+        for key in &keys {
+            match key {
+                Key::Constant { array: true, .. } | Key::Parameter { array: true, .. } => {
+                    return Ok(Some(Shard::All))
+                }
+                Key::Parameter { pos, .. } => match params {
+                    Some(params) => match params.parameter(*pos)? {
+                        Some(param) => binds.push(Some(param)),
+                        None => return Ok(None),
+                    },
+                    None => return Ok(None),
+                },
+                _ => binds.push(None),
+            }
+        }
+
+        let mut values = vec![];
+        for (i, (key, bind)) in keys.iter().zip(binds.iter()).enumerate() {
+            let data_type = table.data_type_at(i);
+            match (key, bind) {
+                (_, Some(param)) => values.push(ShardingValue::from_param(param, data_type)?),
+                (Key::Constant { value, .. }, None) => {
+                    values.push(ShardingValue::new(value.as_str(), data_type))
+                }
+                _ => return Ok(None),
+            }
+        }
+
+        for value in &values {
+            self.sharding_value(value);
+        }
+
+        let ctx = ContextBuilder::new(table)
+            .values(values)
+            .shards(sharding_schema.shards)
+            .round_robin(&sharding_schema.round_robin)
+            .build()?;
+
+        Ok(Some(ctx.apply()?))
+    }
+
+    /// Record a sharding key value for the routing history.
+    pub(super) fn sharding_value(&mut self, value: &ShardingValue) {
+        if self.record_inputs {
+            self.inputs.sharding_value(value);
+        }
+    }
 }
@@ -1,22 +1,68 @@
 use super::*;
-use crate::frontend::router::{parser::Shard, round_robin};
+use crate::{
+    config::PoolerMode,
+    frontend::router::{parser::Shard, round_robin::Purpose},
+};
 
 impl QueryParser {
     /// Handle SHOW command.
     pub(super) fn show(
         &mut self,
         stmt: &VariableShowStmt,
+        single: bool,
         context: &QueryParserContext,
     ) -> Result<Command, Error> {
         match stmt.name.as_str() {
             "pgdog.shards" => Ok(Command::Shards(context.shards)),
-            _ => {
-                let shard = Shard::Direct(round_robin::next() % context.shards);
+            name => {
+                if let Some(command) = self.show_local(name, single, context) {
+                    return Ok(command);
+                }
+
+                let shard = Shard::Direct(
+                    context.sharding_schema.round_robin.next(Purpose::Show) % context.shards,
+                );
                 let route = Route::write(shard).set_read(context.read_only);
                 Ok(Command::Query(route))
             }
         }
     }
+
+    /// Answer SHOW from the client's parameters and server settings
+    /// captured by the pool, if we know the answer.
+    ///
+    /// Settings can change inside transactions, e.g. with `SET LOCAL`,
+    /// so those go to the server. In session mode, the client can change them
+    /// without `SET`, e.g. with `set_config()`, so only the server knows.
+    fn show_local(
+        &self,
+        name: &str,
+        single: bool,
+        context: &QueryParserContext,
+    ) -> Option<Command> {
+        if !single
+            || self.in_transaction
+            || context.router_context.cluster.pooler_mode() != PoolerMode::Transaction
+            || !context.query().ok()?.simple()
+        {
+            return None;
+        }
+
+        let settings = context.router_context.cluster.settings()?;
+        let params = context.router_context.params;
+
+        if name == "all" {
+            Some(Command::Show {
+                settings: settings.show_all(params)?,
+                all: true,
+            })
+        } else {
+            Some(Command::Show {
+                settings: vec![settings.show(name, params)?],
+                all: false,
+            })
+        }
+    }
 }
 
 #[cfg(test)]
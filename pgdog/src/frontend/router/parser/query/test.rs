@@ -8,7 +8,7 @@ use crate::{
 
 use super::{super::Shard, *};
 use crate::backend::Cluster;
-use crate::config::ReadWriteStrategy;
+use crate::config::{BlockedStatement, ReadWriteStrategy};
 use crate::frontend::{ClientRequest, PreparedStatements, RouterContext};
 use crate::net::messages::Query;
 use crate::net::Parameters;
@@ -122,6 +122,156 @@ fn test_insert() {
     assert_eq!(route.shard(), &Shard::direct(1));
 }
 
+#[test]
+fn test_insert_multi_row() {
+    let shard = |id: i64| {
+        query!(format!(
+            "INSERT INTO sharded (id, email) VALUES ({}, 'test')",
+            id
+        ))
+        .shard()
+        .clone()
+    };
+    let on_shard = |number: usize| {
+        (1..)
+            .find(|id| shard(*id) == Shard::Direct(number))
+            .unwrap()
+    };
+    let (a, b) = (on_shard(0), on_shard(1));
+
+    // All rows on the same shard.
+    let route = query!(format!(
+        "INSERT INTO sharded (id, email) VALUES ({}, 'a'), ({}, 'b')",
+        b, b
+    ));
+    assert_eq!(route.shard(), &Shard::Direct(1));
+    assert!(route.insert_split().is_none());
+
+    let route = query!(format!(
+        "INSERT INTO sharded (id, email) VALUES ({}, 'a'), ({}, 'b'), ({}, 'c') RETURNING id",
+        b, a, b
+    ));
+    assert_eq!(route.shard(), &Shard::Multi(vec![0, 1]));
+    assert!(route.is_write());
+    let split = route.insert_split().unwrap();
+    assert_eq!(
+        split.inserts()[0].query,
+        format!(
+            "INSERT INTO sharded (id, email) VALUES ({}, 'b') RETURNING id",
+            a
+        )
+    );
+    assert_eq!(
+        split.inserts()[1].query,
+        format!(
+            "INSERT INTO sharded (id, email) VALUES ({}, 'a'), ({}, 'c') RETURNING id",
+            b, b
+        )
+    );
+
+    // Extended protocol.
+    let (a, b) = (a.to_string(), b.to_string());
+    let route = parse!(
+        "INSERT INTO sharded (id, email) VALUES ($1, $2), ($3, $4)",
+        [b.as_bytes(), "a".as_bytes(), a.as_bytes(), "b".as_bytes()]
+    );
+    assert_eq!(route.shard(), &Shard::Multi(vec![0, 1]));
+    let split = route.insert_split().unwrap();
+    assert_eq!(split.inserts()[0].params, vec![2, 3]);
+    assert_eq!(split.inserts()[1].params, vec![0, 1]);
+
+    let route = parse!(
+        "INSERT INTO sharded (id, email) VALUES ($1, $2), ($3, $4)",
+        [b.as_bytes(), "a".as_bytes(), b.as_bytes(), "b".as_bytes()]
+    );
+    assert_eq!(route.shard(), &Shard::Direct(1));
+
+    // Parameters we can't move to the shard statements.
+    let parse = Parse::named(
+        "",
+        "WITH s AS (SELECT $5::text AS email) INSERT INTO sharded (id, email) VALUES ($1, $2), ($3, $4)",
+    );
+    let params = [
+        b.as_bytes(),
+        "a".as_bytes(),
+        a.as_bytes(),
+        "b".as_bytes(),
+        "c".as_bytes(),
+    ]
+    .map(|p| Parameter {
+        len: p.len() as i32,
+        data: p.to_vec(),
+    });
+    let bind = Bind::new_params("", &params);
+    let result = QueryParser::default().parse(
+        RouterContext::new(
+            &ClientRequest::from(vec![parse.into(), bind.into()]),
+            &Cluster::new_test(),
+            &mut PreparedStatements::default(),
+            &Parameters::default(),
+            None,
+        )
+        .unwrap(),
+    );
+    assert!(matches!(result, Err(Error::InsertSplitParameter(5))));
+}
+
+#[test]
+fn test_insert_select() {
+    let expected = query!("SELECT * FROM sharded WHERE id = 11");
+
+    // Rows stay on the shard they're read from.
+    for query in [
+        "INSERT INTO sharded (id, email) SELECT id, email FROM sharded WHERE id = 11",
+        "INSERT INTO sharded (email, id) SELECT s.email, s.id FROM sharded s WHERE s.id = 11",
+        "INSERT INTO sharded (email, id) SELECT b.email, a.id FROM sharded a JOIN sharded b USING (id) WHERE b.id = 11",
+    ] {
+        let route = query!(query);
+        assert_eq!(route.shard(), expected.shard(), "{}", query);
+        assert!(route.is_write(), "{}", query);
+    }
+
+    let error = |query: &str| {
+        let mut qp = QueryParser::default();
+        let client_request = ClientRequest::from(vec![Query::new(query).into()]);
+        let cluster = Cluster::new_test();
+        let mut stmt = PreparedStatements::default();
+        let params = Parameters::default();
+        let context =
+            RouterContext::new(&client_request, &cluster, &mut stmt, &params, None).unwrap();
+        qp.parse(context).err()
+    };
+
+    // Sharding key copied from another column would move rows to another shard.
+    for query in [
+        "INSERT INTO sharded (id, email) SELECT email, id FROM sharded WHERE id = 11",
+        "INSERT INTO sharded (email, id) SELECT id, email FROM sharded WHERE id = 11",
+        "INSERT INTO sharded (id) SELECT 11 FROM sharded WHERE id = 11",
+    ] {
+        assert!(
+            matches!(error(query), Some(Error::InsertSelectShardingKey(ref table)) if table == "sharded"),
+            "{}",
+            query
+        );
+    }
+
+    // Rows read from all shards.
+    for query in [
+        "INSERT INTO sharded (id, email) SELECT id, email FROM sharded",
+        "INSERT INTO sharded (id, email) SELECT id, email FROM sharded WHERE email = 'test'",
+    ] {
+        assert!(
+            matches!(error(query), Some(Error::CrossShardInsertSelect)),
+            "{}",
+            query
+        );
+    }
+
+    // Destination isn't sharded.
+    let route = query!("INSERT INTO users (id) SELECT id FROM sharded WHERE id = 11");
+    assert_eq!(route.shard(), &Shard::All);
+}
+
 #[test]
 fn test_order_by_vector() {
     let route = query!("SELECT * FROM embeddings ORDER BY embedding <-> '[1,2,3]'");
@@ -279,11 +429,94 @@ fn test_set() {
     }
 }
 
+#[test]
+fn test_set_debug() {
+    for (query, debug) in [
+        ("SET pgdog.debug TO on", true),
+        (r#"SET "pgdog.debug" TO 'off'"#, false),
+        ("SET pgdog.debug = 1", true),
+        ("RESET pgdog.debug", false),
+    ] {
+        let (command, _) = command!(query);
+        assert!(
+            matches!(command, Command::SetDebug(value) if value == debug),
+            "{}",
+            query
+        );
+    }
+
+    let (command, _) = command!("SET application_name TO 'pgdog.debug'");
+    assert!(!matches!(command, Command::SetDebug(_)));
+
+    let cluster = Cluster::new_test();
+    let mut prep_stmts = PreparedStatements::default();
+    let params = Parameters::default();
+    let client_request: ClientRequest = vec![Query::new("SET pgdog.debug TO maybe").into()].into();
+    let router_context =
+        RouterContext::new(&client_request, &cluster, &mut prep_stmts, &params, None).unwrap();
+    assert!(matches!(
+        QueryParser::default().parse(router_context),
+        Err(Error::SetDebug)
+    ));
+}
+
+#[test]
+fn test_set_role() {
+    // Outside a transaction, tracked like any other parameter.
+    let (command, _) = command!("SET ROLE pgdog1");
+    match command {
+        Command::Set { name, value } => {
+            assert_eq!(name, "role");
+            assert_eq!(value, ParameterValue::from("pgdog1"));
+        }
+        _ => panic!("not a set"),
+    }
+
+    for query in [
+        "SET LOCAL ROLE pgdog1",
+        "RESET ROLE",
+        "RESET SESSION AUTHORIZATION",
+    ] {
+        let route = query!(query);
+        assert!(route.changes_role(), "{}", query);
+        assert!(route.is_write());
+    }
+
+    let mut qp = QueryParser::default();
+    qp.in_transaction = true;
+    for query in ["SET ROLE pgdog1", "SET SESSION AUTHORIZATION pgdog1"] {
+        match query_parser!(qp, Query::new(query), true) {
+            Command::Query(route) => assert!(route.changes_role(), "{}", query),
+            _ => panic!("should be a query"),
+        }
+    }
+
+    let cluster = Cluster::new_test();
+    let mut prep_stmts = PreparedStatements::default();
+    let params = Parameters::default();
+    for (query, rejected) in [
+        ("SET ROLE pgdog1", true),
+        ("SET SESSION AUTHORIZATION pgdog1", true),
+        ("RESET ROLE", false),
+    ] {
+        let client_request: ClientRequest = vec![Query::new(query).into()].into();
+        let router_context =
+            RouterContext::new(&client_request, &cluster, &mut prep_stmts, &params, None).unwrap();
+        let mut context = QueryParserContext::new(router_context);
+        context.reject_set_role = true;
+        let result = QueryParser::default().query(&mut context);
+        assert_eq!(matches!(result, Err(Error::SetRole)), rejected, "{}", query);
+    }
+}
+
 #[test]
 fn test_transaction() {
     let (command, mut qp) = command!("BEGIN");
     match command {
-        Command::StartTransaction(q) => assert_eq!(q.query(), "BEGIN"),
+        Command::StartTransaction { query, options } => {
+            assert_eq!(query.query(), "BEGIN");
+            assert_eq!(options, TransactionOptions::default());
+        }
         _ => panic!("not a query"),
     };
 
@@ -306,7 +539,10 @@ fn test_transaction() {
     );
     assert!(matches!(
         command,
-        Command::StartTransaction(BufferedQuery::Query(_))
+        Command::StartTransaction {
+            query: BufferedQuery::Query(_),
+            ..
+        }
     ));
     assert!(qp.in_transaction);
 
@@ -327,6 +563,45 @@ fn test_transaction() {
     }
 }
 
+#[test]
+fn test_transaction_options() {
+    let (command, mut qp) = command!("BEGIN ISOLATION LEVEL SERIALIZABLE READ ONLY");
+    match command {
+        Command::StartTransaction { options, .. } => {
+            assert_eq!(options.isolation_level, Some("serializable".into()));
+            assert_eq!(options.read_only, Some(true));
+            assert_eq!(options.deferrable, None);
+            assert_eq!(
+                options.begin(),
+                "BEGIN ISOLATION LEVEL SERIALIZABLE READ ONLY"
+            );
+        }
+        cmd => panic!("not a transaction: {:?}", cmd),
+    }
+
+    let command = query_parser!(
+        qp,
+        Query::new("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ, DEFERRABLE"),
+        true
+    );
+    match command {
+        Command::SetTransaction(options) => {
+            assert_eq!(options.isolation_level, Some("repeatable read".into()));
+            assert_eq!(options.read_only, None);
+            assert_eq!(options.deferrable, Some(true));
+        }
+        cmd => panic!("not set transaction: {:?}", cmd),
+    }
+
+    // Outside a transaction, SET TRANSACTION goes to the server.
+    let command = query_parser!(
+        QueryParser::default(),
+        Query::new("SET TRANSACTION ISOLATION LEVEL SERIALIZABLE"),
+        false
+    );
+    assert!(matches!(command, Command::Query(_)));
+}
+
 #[test]
 fn test_insert_do_update() {
     let route = query!("INSERT INTO foo (id) VALUES ($1::UUID) ON CONFLICT (id) DO UPDATE SET id = excluded.id RETURNING id");
@@ -353,6 +628,84 @@ fn test_write_functions() {
     assert!(route.lock_session());
 }
 
+#[test]
+fn test_function_lists() {
+    let queries = [
+        "SELECT nextval('seq')",
+        "SELECT refresh_cache()",
+        "SELECT now(), app.refresh_cache()",
+        "SELECT st_distance(a, b) FROM sharded",
+    ];
+
+    let mut qp = QueryParser::default();
+    let writes = queries
+        .iter()
+        .map(|query| match query_parser!(qp, Query::new(*query), false) {
+            Command::Query(route) => route.is_write(),
+            _ => panic!("should be a query"),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(writes, [true, false, false, false]);
+
+    let mut cluster = Cluster::new_test();
+    cluster.set_function_lists(FunctionLists::new(
+        &["nextval".into(), "st_*".into()],
+        &["refresh_*".into(), "st_distance".into()],
+    ));
+    let writes = queries
+        .iter()
+        .map(
+            |query| match query_parser!(qp, Query::new(*query), false, cluster.clone()) {
+                Command::Query(route) => route.is_write(),
+                _ => panic!("should be a query"),
+            },
+        )
+        .collect::<Vec<_>>();
+    assert_eq!(writes, [false, true, true, true]);
+}
+
+#[test]
+fn test_large_objects() {
+    for query in [
+        "SELECT lo_creat(-1)",
+        "SELECT lo_open($1, 131072)",
+        "SELECT loread(0, 1024)",
+        "SELECT lo_unlink(oid) FROM sharded",
+    ] {
+        let route = query!(query);
+        assert!(route.is_write(), "{}", query);
+        assert!(route.lock_session(), "{}", query);
+        assert_eq!(route.shard(), &Shard::Direct(0), "{}", query);
+    }
+
+    let route = query!("/* pgdog_shard: 1 */ SELECT lo_creat(-1)");
+    assert_eq!(route.shard(), &Shard::Direct(1));
+}
+
+#[test]
+fn test_copy_select() {
+    let route = query!("COPY (SELECT * FROM sharded WHERE id = 5) TO STDOUT");
+    assert!(route.is_read());
+    assert!(matches!(route.shard(), Shard::Direct(_)));
+
+    let expected = query!("SELECT * FROM sharded WHERE id = 5");
+    assert_eq!(route.shard(), expected.shard());
+
+    let route = query!("COPY (SELECT 1) TO STDOUT WITH (FORMAT CSV)");
+    assert!(matches!(route.shard(), Shard::Direct(_)));
+
+    let mut qp = QueryParser::default();
+    let client_request =
+        ClientRequest::from(vec![
+            Query::new("COPY (SELECT * FROM sharded) TO STDOUT").into()
+        ]);
+    let cluster = Cluster::new_test();
+    let mut stmt = PreparedStatements::default();
+    let params = Parameters::default();
+    let context = RouterContext::new(&client_request, &cluster, &mut stmt, &params, None).unwrap();
+    assert!(matches!(qp.parse(context), Err(Error::CrossShardCopy)));
+}
+
 #[test]
 fn test_write_nolock() {
     let route = query!("SELECT nextval('234')");
@@ -369,10 +722,95 @@ fn test_cte() {
     assert!(route.is_write());
 }
 
+#[test]
+fn test_cte_sharding() {
+    let expected = query!("SELECT * FROM sharded WHERE id = 11");
+    assert!(matches!(expected.shard(), Shard::Direct(_)));
+
+    // Sharding keys in read-only CTEs.
+    for query in [
+        "WITH s AS (SELECT * FROM sharded WHERE id = 11) SELECT * FROM s",
+        "WITH s AS MATERIALIZED (SELECT * FROM sharded WHERE id = 11) SELECT * FROM s",
+        "WITH s AS NOT MATERIALIZED (SELECT * FROM sharded WHERE id = 11) SELECT * FROM s",
+        "WITH s AS (WITH t AS (SELECT * FROM sharded WHERE id = 11) SELECT * FROM t) SELECT * FROM s",
+        "WITH s AS (SELECT * FROM sharded WHERE id = 11) SELECT * FROM s JOIN other o ON o.id = s.id",
+    ] {
+        let route = query!(query);
+        assert!(route.is_read(), "{}", query);
+        assert_eq!(route.shard(), expected.shard(), "{}", query);
+    }
+
+    let route = parse!(
+        "WITH s AS (SELECT * FROM sharded WHERE id = $1) SELECT * FROM s",
+        ["11".as_bytes()]
+    );
+    assert_eq!(route.shard(), expected.shard());
+
+    // Data-modifying CTEs are writes on the shard they write to.
+    for query in [
+        "WITH ins AS (INSERT INTO sharded (id, email) VALUES (11, 'test@test.com') RETURNING id) SELECT * FROM ins",
+        "WITH ins AS (INSERT INTO sharded (id) VALUES (11) RETURNING id) SELECT 1",
+        "WITH upd AS (UPDATE sharded SET email = 'test@test.com' WHERE id = 11 RETURNING *) SELECT * FROM upd",
+        "WITH del AS (DELETE FROM sharded WHERE id = 11 RETURNING *) SELECT * FROM del",
+    ] {
+        let route = query!(query);
+        assert!(route.is_write(), "{}", query);
+        assert_eq!(route.shard(), expected.shard(), "{}", query);
+    }
+
+    // Writes to all shards aren't narrowed down by the rest of the query.
+    let route =
+        query!("WITH del AS (DELETE FROM sharded RETURNING *) SELECT * FROM sharded WHERE id = 11");
+    assert!(route.is_write());
+    assert_eq!(route.shard(), &Shard::All);
+
+    // Sharded tables read outside of the CTE are on all shards.
+    for query in [
+        "WITH s AS (SELECT * FROM sharded WHERE id = 11) SELECT * FROM sharded",
+        "WITH s AS (SELECT * FROM sharded WHERE id = 11) SELECT * FROM s JOIN sharded ON sharded.id = s.id",
+        "WITH s AS (SELECT * FROM sharded WHERE id = 11), t AS (SELECT * FROM sharded) SELECT * FROM s JOIN t ON t.id = s.id",
+        "WITH s AS (SELECT * FROM sharded WHERE id = 11) SELECT * FROM (SELECT * FROM sharded) t",
+    ] {
+        let route = query!(query);
+        assert!(route.is_read(), "{}", query);
+        assert_eq!(route.shard(), &Shard::All, "{}", query);
+    }
+
+    // Recursive CTEs aren't used for routing.
+    let route = query!(
+        "WITH RECURSIVE t(n) AS (SELECT 1 UNION ALL SELECT n + 1 FROM t WHERE n < 10) SELECT * FROM t"
+    );
+    assert!(route.is_read());
+    assert_eq!(route.shard(), &Shard::All);
+}
+
+#[test]
+fn test_round_robin_per_cluster() {
+    // Clusters have their own counters, so queries to one
+    // don't skew the distribution of the other.
+    let clusters = [Cluster::new_test(), Cluster::new_test()];
+    let mut counts = [[0; 2]; 2];
+    let mut qp = QueryParser::default();
+
+    for _ in 0..1000 {
+        for (cluster, counts) in clusters.iter().zip(counts.iter_mut()) {
+            match query_parser!(qp, Query::new("SELECT 1"), false, cluster.clone()) {
+                Command::Query(route) => match route.shard() {
+                    Shard::Direct(shard) => counts[*shard] += 1,
+                    shard => panic!("expected a direct shard, got {:?}", shard),
+                },
+                _ => panic!("should be a query"),
+            }
+        }
+    }
+
+    assert_eq!(counts, [[500, 500], [500, 500]]);
+}
+
 #[test]
 fn test_function_begin() {
     let (cmd, mut qp) = command!("BEGIN");
-    assert!(matches!(cmd, Command::StartTransaction(_)));
+    assert!(matches!(cmd, Command::StartTransaction { .. }));
     assert!(qp.in_transaction);
     let cluster = Cluster::new_test();
     let mut prep_stmts = PreparedStatements::default();
@@ -493,3 +931,642 @@ fn test_any() {
 
     assert_eq!(route.shard(), &Shard::All);
 }
+
+#[test]
+fn test_composite_where_clause() {
+    use crate::backend::ShardedTables;
+    use crate::config::{DataType, ShardedTable};
+    use crate::frontend::router::sharding::{bigint, combine, varchar};
+
+    let schema = ShardingSchema {
+        shards: 4,
+        tables: ShardedTables::new(
+            vec![ShardedTable {
+                name: Some("events".into()),
+                columns: vec!["tenant_id".into(), "region".into()],
+                data_types: vec![DataType::Bigint, DataType::Varchar],
+                ..Default::default()
+            }],
+            vec![],
+        ),
+        ..Default::default()
+    };
+    let expected = Shard::Direct(combine(bigint(5), varchar(b"us")) as usize % 4);
+
+    let shards = |query: &str, bind: Option<&Bind>| {
+        let ast = pg_query::parse(query).unwrap();
+        let Some(NodeEnum::SelectStmt(stmt)) = ast.protobuf.stmts[0]
+            .stmt
+            .as_ref()
+            .and_then(|stmt| stmt.node.clone())
+        else {
+            panic!("not a select");
+        };
+        let where_clause = WhereClause::new(Some("events"), &stmt.where_clause).unwrap();
+        QueryParser::converge(
+            QueryParser::default()
+                .where_clause(&schema, &where_clause, bind)
+                .unwrap(),
+        )
+    };
+
+    assert_eq!(
+        shards(
+            "SELECT * FROM events WHERE tenant_id = 5 AND region = 'us'",
+            None
+        ),
+        expected
+    );
+    // Column order in the query doesn't matter.
+    assert_eq!(
+        shards(
+            "SELECT * FROM events WHERE region = 'us' AND tenant_id = 5",
+            None
+        ),
+        expected
+    );
+
+    let bind = Bind::new_params(
+        "",
+        &[
+            Parameter {
+                len: 1,
+                data: "5".as_bytes().to_vec(),
+            },
+            Parameter {
+                len: 2,
+                data: "us".as_bytes().to_vec(),
+            },
+        ],
+    );
+    assert_eq!(
+        shards(
+            "SELECT * FROM events WHERE tenant_id = $1 AND region = $2",
+            Some(&bind)
+        ),
+        expected
+    );
+
+    // Partial key can't be routed to one shard.
+    assert_eq!(
+        shards("SELECT * FROM events WHERE tenant_id = 5", None),
+        Shard::All
+    );
+    assert_eq!(
+        shards(
+            "SELECT * FROM events WHERE tenant_id = 5 AND region IN ('us', 'eu')",
+            None
+        ),
+        Shard::All
+    );
+}
+
+#[test]
+fn test_join() {
+    let expected = query!("SELECT * FROM sharded WHERE id = 11");
+
+    // Co-sharded tables with a sharding key go to one shard.
+    for query in [
+        "SELECT * FROM sharded a JOIN sharded b ON a.id = b.id WHERE a.id = 11",
+        "SELECT * FROM sharded a JOIN sharded b ON a.id = b.id WHERE b.id = 11",
+        "SELECT * FROM sharded a JOIN sharded b USING (id) WHERE b.id = 11",
+        "SELECT * FROM sharded a, sharded b WHERE a.id = b.id AND b.id = 11",
+        "SELECT * FROM sharded a JOIN sharded b ON a.email = b.email WHERE a.id = 11 AND b.id = 11",
+    ] {
+        let route = query!(query);
+        assert_eq!(route.shard(), expected.shard(), "{}", query);
+    }
+
+    // Co-sharded tables without a sharding key are joined on each shard.
+    let route = query!("SELECT * FROM sharded a JOIN sharded b ON a.id = b.id");
+    assert_eq!(route.shard(), &Shard::All);
+
+    // Joined on other columns, each shard only has part of the result.
+    for query in [
+        "SELECT * FROM sharded a JOIN sharded b ON a.email = b.email",
+        "SELECT * FROM sharded a JOIN sharded b ON a.email = b.email WHERE a.id = 11",
+        "SELECT * FROM sharded a JOIN sharded b ON a.id = b.id OR a.email = b.email",
+    ] {
+        let mut qp = QueryParser::default();
+        let client_request = ClientRequest::from(vec![Query::new(query).into()]);
+        let cluster = Cluster::new_test();
+        let mut stmt = PreparedStatements::default();
+        let params = Parameters::default();
+        let context =
+            RouterContext::new(&client_request, &cluster, &mut stmt, &params, None).unwrap();
+        assert!(
+            matches!(qp.parse(context), Err(Error::CrossShardJoin(ref left, ref right)) if left == "a" && right == "b"),
+            "{}",
+            query
+        );
+
+        // Unless configured to warn.
+        let context =
+            RouterContext::new(&client_request, &cluster, &mut stmt, &params, None).unwrap();
+        let mut context = QueryParserContext::new(context);
+        context.cross_shard_join = CrossShardJoin::Warn;
+        match qp.query(&mut context).unwrap() {
+            Command::Query(route) => assert_eq!(route.shard(), &Shard::All),
+            _ => panic!("should be a query"),
+        }
+    }
+
+    // Sharding key behind an alias, only in join conditions or equal to another column.
+    for query in [
+        "SELECT * FROM sharded s WHERE s.id = 11",
+        "SELECT * FROM sharded s WHERE id = 11",
+        "SELECT * FROM sharded a JOIN sharded b ON a.id = b.id AND b.id = 11",
+        "SELECT * FROM sharded s JOIN users u ON u.sharded_id = s.id AND s.id = 11",
+        "SELECT * FROM users u JOIN sharded s ON s.id = u.sharded_id WHERE u.sharded_id = 11",
+        "SELECT * FROM sharded a JOIN sharded b USING (id) JOIN users u ON u.sharded_id = b.id WHERE u.sharded_id = 11",
+    ] {
+        let route = query!(query);
+        assert_eq!(route.shard(), expected.shard(), "{}", query);
+    }
+
+    // Conditions of outer joins don't filter the preserved side.
+    let route =
+        query!("SELECT * FROM sharded s LEFT JOIN users u ON u.sharded_id = s.id AND s.id = 11");
+    assert_eq!(route.shard(), &Shard::All);
+
+    // Joins with one sharded table are fine.
+    let route = query!("SELECT * FROM sharded s JOIN users u ON s.email = u.email WHERE s.id = 11");
+    assert_eq!(route.shard(), expected.shard());
+
+    // Parameters are sharding keys: they are checked once bound.
+    let query =
+        "SELECT * FROM sharded a JOIN sharded b ON a.email = b.email WHERE a.id = $1 AND b.id = $1";
+    let route = parse!(query, ["11".as_bytes()]);
+    assert_eq!(route.shard(), expected.shard());
+    let mut qp = QueryParser::default();
+    match query_parser!(qp, Parse::new_anonymous(query), false) {
+        Command::Query(route) => assert_eq!(route.shard(), &Shard::All),
+        _ => panic!("should be a query"),
+    }
+
+    // Any join works on one shard.
+    let mut qp = QueryParser::default();
+    let command = query_parser!(
+        qp,
+        Query::new("SELECT * FROM sharded a JOIN sharded b ON a.email = b.email"),
+        false,
+        Cluster::new_test_single_shard()
+    );
+    match command {
+        Command::Query(route) => assert_eq!(route.shard(), &Shard::Direct(0)),
+        _ => panic!("should be a query"),
+    }
+}
+
+#[test]
+fn test_manual_queries() {
+    use crate::backend::databases::init;
+    use crate::config::{config, set, test::load_test, ManualQuery, ManualQueryRole};
+
+    let select = "SELECT * FROM sharded WHERE email = 'test'";
+    let update = "UPDATE sharded SET email = 'test'";
+
+    let route = |query: &str, shard, shards, role| {
+        load_test();
+        let mut config = (*config()).clone();
+        config.config.manual_queries = vec![ManualQuery {
+            fingerprint: pg_query::fingerprint(query).unwrap().hex,
+            shard,
+            shards,
+            role,
+        }];
+        set(config).unwrap();
+        init();
+
+        query!(query)
+    };
+
+    // No routing, round robin.
+    let route_select = route(select, None, None, None);
+    assert!(matches!(route_select.shard(), Shard::Direct(0 | 1)));
+    assert!(route_select.is_read());
+
+    let route_select = route(select, Some(1), None, None);
+    assert_eq!(route_select.shard(), &Shard::Direct(1));
+    assert!(route_select.is_read());
+
+    let route_select = route(select, None, Some(vec![0, 1]), None);
+    assert_eq!(route_select.shard(), &Shard::Multi(vec![0, 1]));
+    assert!(route_select.is_read());
+
+    let route_select = route(select, None, Some(vec![1]), Some(ManualQueryRole::Write));
+    assert_eq!(route_select.shard(), &Shard::Direct(1));
+    assert!(route_select.is_write());
+
+    let route_update = route(update, None, None, Some(ManualQueryRole::Read));
+    assert!(matches!(route_update.shard(), Shard::Direct(0 | 1)));
+    assert!(route_update.is_read());
+
+    let route_update = route(update, Some(0), None, Some(ManualQueryRole::Write));
+    assert_eq!(route_update.shard(), &Shard::Direct(0));
+    assert!(route_update.is_write());
+
+    let route_update = route(update, None, Some(vec![0, 1]), None);
+    assert_eq!(route_update.shard(), &Shard::Multi(vec![0, 1]));
+    assert!(route_update.is_write());
+}
+
+#[test]
+fn test_dry_run() {
+    let query = "INSERT INTO users (id) SELECT id FROM sharded WHERE id = 11";
+    let client_request = ClientRequest::from(vec![Query::new(query).into()]);
+    let cluster = Cluster::new_test();
+    let mut stmt = PreparedStatements::default();
+    let params = Parameters::default();
+    let mut context = QueryParserContext::new(
+        RouterContext::new(&client_request, &cluster, &mut stmt, &params, None).unwrap(),
+    );
+    context.dry_run = true;
+
+    let mut query_parser = QueryParser::default();
+    let command = query_parser.query(&mut context).unwrap();
+    assert_eq!(command.route().shard(), &Shard::Direct(0));
+
+    let fingerprint = pg_query::fingerprint(query).unwrap().hex;
+    assert_eq!(query_parser.fingerprint(), Some(fingerprint.as_str()));
+
+    // Parameter values aren't recorded.
+    let summary = dry_run::summary();
+    let entry = summary
+        .cross_shard()
+        .into_iter()
+        .find(|entry| entry.fingerprint == fingerprint)
+        .unwrap();
+    assert_eq!(
+        entry.query,
+        "INSERT INTO users (id) SELECT id FROM sharded WHERE id = $1"
+    );
+    assert_eq!(entry.shard, Shard::All);
+}
+
+#[test]
+fn test_max_query_length_for_parsing() {
+    use crate::config::{config, set, test::load_test, OversizedQuery};
+
+    let in_list = (0..100_000)
+        .map(|id| id.to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+    let query = format!("SELECT * FROM sharded WHERE id IN ({})", in_list);
+
+    load_test();
+    let mut config = (*config()).clone();
+    config.config.general.max_query_length_for_parsing = Some(100_000);
+    set(config.clone()).unwrap();
+
+    let skipped = Cache::stats().0.skipped;
+    let (command, qp) = command!(query.as_str());
+    let route = command.route();
+    assert_eq!(route.shard(), &Shard::All);
+    assert!(route.is_write());
+    // Not parsed.
+    assert_eq!(qp.cache_hit(), None);
+    assert!(Cache::stats().0.skipped > skipped);
+
+    // Shard comment is still used.
+    let route = query!(format!("/* pgdog_shard: 1 */ {}", query).as_str());
+    assert_eq!(route.shard(), &Shard::Direct(1));
+    assert!(route.is_write());
+
+    // Shorter queries are parsed as usual.
+    let route = query!("SELECT * FROM sharded WHERE id = 1");
+    assert!(matches!(route.shard(), Shard::Direct(_)));
+
+    config.config.general.oversized_query = OversizedQuery::Error;
+    set(config).unwrap();
+
+    let client_request = ClientRequest::from(vec![Query::new(query.as_str()).into()]);
+    let cluster = Cluster::new_test();
+    let mut stmt = PreparedStatements::default();
+    let params = Parameters::default();
+    let context = RouterContext::new(&client_request, &cluster, &mut stmt, &params, None).unwrap();
+    let err = QueryParser::default().parse(context).unwrap_err();
+    assert!(matches!(err, Error::QueryTooLong(_, 100_000)));
+}
+
+#[test]
+fn test_read_only() {
+    let cluster = Cluster::new_test_read_only();
+    assert!(cluster.writes_disabled());
+    assert!(!Cluster::new_test().writes_disabled());
+
+    let parse = |query: &str, in_transaction: bool| {
+        let client_request = ClientRequest::from(vec![Query::new(query).into()]);
+        let mut stmt = PreparedStatements::default();
+        let params = Parameters::default();
+        let transaction = in_transaction.then_some(TransactionType::ReadWrite);
+        let context =
+            RouterContext::new(&client_request, &cluster, &mut stmt, &params, transaction).unwrap();
+        QueryParser::default()
+            .parse(context)
+            .map(|command| command.clone())
+    };
+
+    for (query, statement) in [
+        ("INSERT INTO users (id) VALUES (1)", "INSERT"),
+        ("UPDATE users SET id = 2", "UPDATE"),
+        ("DELETE FROM users", "DELETE"),
+        ("TRUNCATE users", "TRUNCATE"),
+        ("COPY users FROM STDIN", "COPY FROM"),
+        ("SELECT * FROM users FOR UPDATE", "SELECT FOR UPDATE"),
+        ("SELECT * FROM users FOR SHARE", "SELECT FOR UPDATE"),
+        ("SELECT * INTO users_copy FROM users", "SELECT INTO"),
+        (
+            "WITH t AS (DELETE FROM users RETURNING *) SELECT * FROM t",
+            "WITH",
+        ),
+        (
+            "EXPLAIN ANALYZE INSERT INTO users (id) VALUES (1)",
+            "INSERT",
+        ),
+        (
+            "PREPARE ins AS INSERT INTO users (id) VALUES ($1)",
+            "INSERT",
+        ),
+        ("CREATE TABLE users (id BIGINT)", "DDL"),
+        ("ALTER TABLE users ADD COLUMN email TEXT", "DDL"),
+        ("CREATE INDEX ON users (id)", "DDL"),
+        ("DROP TABLE users", "DDL"),
+        ("SELECT 1; DELETE FROM users", "DELETE"),
+    ] {
+        for in_transaction in [false, true] {
+            let err = parse(query, in_transaction).unwrap_err();
+            assert!(
+                matches!(&err, Error::ReadOnly(kind, name) if *kind == statement && name == "pgdog"),
+                "{}: {:?}",
+                query,
+                err
+            );
+        }
+    }
+
+    for query in [
+        "SELECT * FROM users",
+        "WITH t AS (SELECT 1) SELECT * FROM t",
+        "EXPLAIN INSERT INTO users (id) VALUES (1)",
+        "COPY users TO STDOUT",
+    ] {
+        assert!(parse(query, false).is_ok(), "{}", query);
+    }
+
+    let command = parse("BEGIN", false).unwrap();
+    assert!(matches!(command, Command::StartTransaction { .. }));
+
+    // Reads in a transaction can go to replicas: it can't write anyway.
+    let command = parse("SELECT * FROM users", true).unwrap();
+    assert!(command.route().is_read());
+}
+
+#[test]
+fn test_policy() {
+    let mut cluster = Cluster::new_test_single_shard();
+    cluster.set_policy(Policy::new(&crate::config::User {
+        name: "pgdog".into(),
+        blocked_statements: vec![
+            BlockedStatement::Truncate,
+            BlockedStatement::DeleteWithoutWhere,
+        ],
+        blocked_functions: vec!["pg_sleep*".into()],
+        ..Default::default()
+    }));
+
+    let parse = |query: &str| {
+        let client_request = ClientRequest::from(vec![Query::new(query).into()]);
+        let mut stmt = PreparedStatements::default();
+        let params = Parameters::default();
+        let context =
+            RouterContext::new(&client_request, &cluster, &mut stmt, &params, None).unwrap();
+        QueryParser::default()
+            .parse(context)
+            .map(|command| command.clone())
+    };
+
+    for query in [
+        "TRUNCATE users",
+        "DELETE FROM users",
+        "SELECT pg_sleep(600)",
+        "SELECT 1; SELECT pg_sleep_for('10 minutes')",
+    ] {
+        let err = parse(query).unwrap_err();
+        assert!(
+            matches!(
+                err,
+                Error::BlockedStatement(_, ref user) | Error::BlockedFunction(_, _, ref user)
+                    if user == "pgdog"
+            ),
+            "{}: {:?}",
+            query,
+            err
+        );
+    }
+
+    for query in [
+        "DELETE FROM users WHERE id = 1",
+        "UPDATE users SET id = 2",
+        "SELECT now()",
+    ] {
+        assert!(parse(query).is_ok(), "{}", query);
+    }
+
+    // Other users aren't affected.
+    let command = query_parser!(
+        QueryParser::default(),
+        Query::new("TRUNCATE users"),
+        false,
+        Cluster::new_test_single_shard()
+    );
+    assert!(command.route().is_write());
+}
+
+#[test]
+fn test_maintenance() {
+    let cluster = Cluster::new_test();
+    let parse = |query: &str, in_transaction: bool| {
+        let client_request = ClientRequest::from(vec![Query::new(query).into()]);
+        let mut stmt = PreparedStatements::default();
+        let params = Parameters::default();
+        let transaction = in_transaction.then_some(TransactionType::ReadWrite);
+        let context =
+            RouterContext::new(&client_request, &cluster, &mut stmt, &params, transaction).unwrap();
+        QueryParser::default()
+            .parse(context)
+            .map(|command| command.clone())
+    };
+
+    for (query, in_transaction) in [
+        ("TRUNCATE sharded RESTART IDENTITY", false),
+        ("TRUNCATE sharded, sharded_omni CASCADE", true),
+        ("ANALYZE sharded", false),
+        ("ANALYZE sharded (id)", true),
+        ("ANALYZE", false),
+        ("VACUUM sharded", false),
+        ("VACUUM (ANALYZE, VERBOSE) sharded", false),
+        ("VACUUM", false),
+    ] {
+        let command = parse(query, in_transaction).unwrap();
+        let route = command.route();
+        assert_eq!(route.shard(), &Shard::All, "{}", query);
+        assert!(route.is_write(), "{}", query);
+    }
+
+    // Postgres doesn't allow VACUUM inside a transaction, including VACUUM ANALYZE.
+    for query in ["VACUUM sharded", "VACUUM ANALYZE sharded"] {
+        let err = parse(query, true).unwrap_err();
+        assert!(matches!(err, Error::VacuumInTransaction), "{}", query);
+    }
+
+    // A shard picked by the client still wins.
+    let command = parse("/* pgdog_shard: 1 */ TRUNCATE sharded", false).unwrap();
+    assert_eq!(command.route().shard(), &Shard::Direct(1));
+}
+
+#[test]
+fn test_degraded_reads() {
+    use crate::backend::pool::Error as PoolError;
+    use crate::config::{config, set, test::load_test, Role};
+
+    load_test();
+    let mut updated = (*config()).clone();
+    updated.config.general.degraded_reads = true;
+    set(updated).unwrap();
+
+    let cluster = Cluster::new_test();
+    let parse = |query: &str, in_transaction: bool| {
+        let client_request = ClientRequest::from(vec![Query::new(query).into()]);
+        let mut stmt = PreparedStatements::default();
+        let params = Parameters::default();
+        let transaction = in_transaction.then_some(TransactionType::ReadWrite);
+        let context =
+            RouterContext::new(&client_request, &cluster, &mut stmt, &params, transaction).unwrap();
+        QueryParser::default()
+            .parse(context)
+            .unwrap()
+            .route()
+            .clone()
+    };
+    let read = "/* pgdog_shard: 0 */ SELECT * FROM sharded";
+
+    // Primary is up: reads in transactions go to it.
+    let route = parse(read, true);
+    assert!(route.is_write());
+    assert!(!route.degraded());
+
+    let (_, primary) = cluster.shards()[0]
+        .pools_with_roles()
+        .into_iter()
+        .find(|(role, _)| *role == Role::Primary)
+        .unwrap();
+    primary.ban(PoolError::ManualBan);
+
+    let route = parse(read, true);
+    assert!(route.is_read());
+    assert!(route.degraded());
+    assert_eq!(route.shard(), &Shard::Direct(0));
+
+    // Reads from all shards include the shard with the banned primary.
+    assert!(parse("SELECT * FROM sharded", true).degraded());
+
+    // Other shards' primaries are up.
+    let route = parse("/* pgdog_shard: 1 */ SELECT * FROM sharded", true);
+    assert!(route.is_write());
+    assert!(!route.degraded());
+
+    // Statements that need the primary still go to it.
+    for query in [
+        "/* pgdog_shard: 0 */ SELECT * FROM sharded FOR SHARE",
+        "/* pgdog_shard: 0 */ SELECT * FROM sharded FOR UPDATE",
+        "/* pgdog_shard: 0 */ SELECT nextval('seq')",
+        "/* pgdog_shard: 0 */ INSERT INTO sharded (id) VALUES (1)",
+    ] {
+        let route = parse(query, true);
+        assert!(route.is_write(), "{}", query);
+        assert!(!route.degraded(), "{}", query);
+    }
+
+    // Outside transactions, reads go to replicas anyway.
+    let route = parse(read, false);
+    assert!(route.is_read());
+    assert!(!route.degraded());
+
+    let mut updated = (*config()).clone();
+    updated.config.general.degraded_reads = false;
+    set(updated).unwrap();
+    assert!(parse(read, true).is_write());
+}
+
+#[test]
+fn test_queue_pattern_routing() {
+    use crate::backend::ShardedTables;
+    use crate::config::{QueuePatternRouting, ShardedTable};
+    use crate::frontend::router::sharding::{bigint, client_shard};
+    use crate::net::BackendKeyData;
+
+    let mut cluster = Cluster::new_test();
+    let sharded = cluster.sharding_schema().tables.tables()[0].clone();
+    cluster.set_sharded_tables(ShardedTables::new(
+        vec![
+            sharded,
+            ShardedTable {
+                database: "pgdog".into(),
+                name: Some("jobs".into()),
+                column: "tenant_id".into(),
+                queue_pattern_routing: QueuePatternRouting::WorkerSticky,
+                ..Default::default()
+            },
+        ],
+        vec![],
+    ));
+
+    let route = |query: &str, client_id: BackendKeyData| {
+        let client_request = ClientRequest::from(vec![Query::new(query).into()]);
+        let mut stmt = PreparedStatements::default();
+        let params = Parameters::default();
+        let mut context =
+            RouterContext::new(&client_request, &cluster, &mut stmt, &params, None).unwrap();
+        context.client_id = Some(client_id);
+        match QueryParser::default().parse(context).unwrap().clone() {
+            Command::Query(route) => route,
+            command => panic!("should be a query: {:?}", command),
+        }
+    };
+
+    let poll = "SELECT * FROM jobs WHERE status = 'pending' LIMIT 10 FOR UPDATE SKIP LOCKED";
+    let mut shards = HashSet::new();
+
+    for pid in 0..50 {
+        let client_id = BackendKeyData { pid, secret: 1234 };
+        let expected = client_shard(&client_id, 2);
+
+        // The same client always gets the same shard.
+        for _ in 0..3 {
+            let route = route(poll, client_id);
+            assert_eq!(route.shard(), &Shard::Direct(expected));
+            assert!(route.is_write());
+        }
+        shards.insert(expected);
+
+        // Not locking, sharding key or no worker_sticky.
+        for query in [
+            "SELECT * FROM jobs WHERE status = 'pending' LIMIT 10",
+            "SELECT * FROM sharded WHERE value = 'pending' FOR UPDATE SKIP LOCKED",
+        ] {
+            assert_eq!(route(query, client_id).shard(), &Shard::All, "{}", query);
+        }
+        assert_eq!(
+            route(
+                "SELECT * FROM jobs WHERE tenant_id = 1 FOR UPDATE SKIP LOCKED",
+                client_id
+            )
+            .shard(),
+            &Shard::Direct(bigint(1) as usize % 2)
+        );
+    }
+
+    // Together, workers drain all shards.
+    assert_eq!(shards.len(), 2);
+}
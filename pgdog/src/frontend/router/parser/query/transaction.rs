@@ -17,7 +17,7 @@ impl QueryParser {
         // if they are using the simple protocol.
         if context.query()?.simple() {
             // Send all transactions to primary.
-            if context.rw_conservative() && !context.read_only {
+            if context.rw_conservative() && !context.read_only && !context.writes_disabled {
                 self.write_override = true;
             }
 
@@ -26,7 +26,10 @@ impl QueryParser {
                 TransactionStmtKind::TransStmtRollback => return Ok(Command::RollbackTransaction),
                 TransactionStmtKind::TransStmtBegin | TransactionStmtKind::TransStmtStart => {
                     self.in_transaction = true;
-                    return Ok(Command::StartTransaction(context.query()?.clone()));
+                    return Ok(Command::StartTransaction {
+                        query: context.query()?.clone(),
+                        options: TransactionOptions::from_nodes(&stmt.options),
+                    });
                 }
                 _ => Ok(Command::Query(Route::write(None))),
             }
@@ -2,6 +2,7 @@ use super::*;
 
 impl QueryParser {
     pub(super) fn update(
+        &mut self,
         stmt: &UpdateStmt,
         context: &QueryParserContext,
     ) -> Result<Command, Error> {
@@ -10,7 +11,7 @@ impl QueryParser {
         let where_clause = WhereClause::new(table.map(|t| t.name), &stmt.where_clause);
 
         if let Some(where_clause) = where_clause {
-            let shards = Self::where_clause(
+            let shards = self.where_clause(
                 &context.sharding_schema,
                 &where_clause,
                 context.router_context.bind,
@@ -0,0 +1,168 @@
+//! Physical replication commands, e.g. `START_REPLICATION 0/3000000 PHYSICAL`.
+//!
+//! These aren't SQL, so pg_query can't parse them. They are sent
+//! to the primary of the first shard and the WAL stream is proxied as-is.
+
+/// Replication protocol command used by physical replication clients,
+/// like `pg_receivewal` or a standby.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReplicationCommand {
+    /// `IDENTIFY_SYSTEM`
+    IdentifySystem,
+    /// `TIMELINE_HISTORY tli`
+    TimelineHistory(u32),
+    /// `READ_REPLICATION_SLOT slot_name`
+    ReadReplicationSlot(String),
+    /// `CREATE_REPLICATION_SLOT slot_name [ TEMPORARY ] PHYSICAL ...`
+    CreateReplicationSlot(String),
+    /// `START_REPLICATION [ SLOT slot_name ] [ PHYSICAL ] XXX/XXX [ TIMELINE tli ]`
+    StartReplication {
+        slot: Option<String>,
+        lsn: String,
+        timeline: Option<u32>,
+    },
+}
+
+impl ReplicationCommand {
+    /// Parse a physical replication command.
+    /// Returns `None` for SQL and logical replication commands.
+    pub fn parse(query: &str) -> Option<Self> {
+        let query = query.trim().trim_end_matches(';');
+        let tokens = query.split_whitespace().collect::<Vec<_>>();
+        let (command, args) = tokens.split_first()?;
+        let keyword = |index: usize, keyword: &str| {
+            args.get(index)
+                .map(|arg| arg.eq_ignore_ascii_case(keyword))
+                .unwrap_or(false)
+        };
+
+        match command.to_uppercase().as_str() {
+            "IDENTIFY_SYSTEM" if args.is_empty() => Some(Self::IdentifySystem),
+
+            "TIMELINE_HISTORY" if args.len() == 1 => {
+                args[0].parse().ok().map(Self::TimelineHistory)
+            }
+
+            "READ_REPLICATION_SLOT" if args.len() == 1 => {
+                Some(Self::ReadReplicationSlot(identifier(args[0])))
+            }
+
+            "CREATE_REPLICATION_SLOT" => {
+                let kind = if keyword(1, "TEMPORARY") { 2 } else { 1 };
+                if keyword(kind, "PHYSICAL") {
+                    Some(Self::CreateReplicationSlot(identifier(args.first()?)))
+                } else {
+                    None
+                }
+            }
+
+            "START_REPLICATION" => {
+                let mut position = 0;
+                let mut slot = None;
+
+                if keyword(position, "SLOT") {
+                    slot = Some(identifier(args.get(position + 1)?));
+                    position += 2;
+                }
+
+                if keyword(position, "PHYSICAL") {
+                    position += 1;
+                }
+
+                let lsn = args.get(position)?;
+                if !lsn.contains('/') {
+                    // e.g. LOGICAL
+                    return None;
+                }
+                position += 1;
+
+                let timeline = if keyword(position, "TIMELINE") {
+                    let timeline = args.get(position + 1)?.parse().ok()?;
+                    position += 2;
+                    Some(timeline)
+                } else {
+                    None
+                };
+
+                if position != args.len() {
+                    return None;
+                }
+
+                Some(Self::StartReplication {
+                    slot,
+                    lsn: lsn.to_string(),
+                    timeline,
+                })
+            }
+
+            _ => None,
+        }
+    }
+}
+
+/// Remove quotes from an identifier.
+fn identifier(name: &str) -> String {
+    name.strip_prefix('"')
+        .and_then(|name| name.strip_suffix('"'))
+        .map(|name| name.replace("\"\"", "\""))
+        .unwrap_or_else(|| name.to_lowercase())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_replication_commands() {
+        assert_eq!(
+            ReplicationCommand::parse("IDENTIFY_SYSTEM"),
+            Some(ReplicationCommand::IdentifySystem)
+        );
+        assert_eq!(
+            ReplicationCommand::parse("TIMELINE_HISTORY 2;"),
+            Some(ReplicationCommand::TimelineHistory(2))
+        );
+        assert_eq!(
+            ReplicationCommand::parse(r#"READ_REPLICATION_SLOT "Standby""#),
+            Some(ReplicationCommand::ReadReplicationSlot("Standby".into()))
+        );
+        assert_eq!(
+            ReplicationCommand::parse(
+                "CREATE_REPLICATION_SLOT standby TEMPORARY PHYSICAL RESERVE_WAL"
+            ),
+            Some(ReplicationCommand::CreateReplicationSlot("standby".into()))
+        );
+        assert_eq!(
+            ReplicationCommand::parse("START_REPLICATION 0/3000000"),
+            Some(ReplicationCommand::StartReplication {
+                slot: None,
+                lsn: "0/3000000".into(),
+                timeline: None,
+            })
+        );
+        assert_eq!(
+            ReplicationCommand::parse(
+                "START_REPLICATION SLOT standby PHYSICAL 0/3000000 TIMELINE 1"
+            ),
+            Some(ReplicationCommand::StartReplication {
+                slot: Some("standby".into()),
+                lsn: "0/3000000".into(),
+                timeline: Some(1),
+            })
+        );
+    }
+
+    #[test]
+    fn test_not_physical_replication() {
+        for query in [
+            "SELECT 1",
+            "IDENTIFY_SYSTEM 1",
+            "CREATE_REPLICATION_SLOT sub LOGICAL pgoutput",
+            "START_REPLICATION SLOT sub LOGICAL 0/0 (proto_version '1')",
+            "START_REPLICATION SLOT sub",
+            "",
+        ] {
+            assert_eq!(ReplicationCommand::parse(query), None, "{}", query);
+        }
+    }
+}
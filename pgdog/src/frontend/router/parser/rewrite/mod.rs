@@ -79,7 +79,7 @@ mod test {
         let ast = pg_query::parse("BEGIN; PREPARE test AS SELECT $1, $2, $3; PREPARE test2 AS SELECT * FROM my_table WHERE id = $1; COMMIT;").unwrap();
         let rewrite = Rewrite::new(&ast);
         assert!(rewrite.needs_rewrite());
-        let mut prepared_statements = PreparedStatements::new();
+        let mut prepared_statements = PreparedStatements::new(None);
         let queries = rewrite.rewrite(&mut prepared_statements).unwrap();
         match queries {
             Command::Rewrite(queries) => assert_eq!(queries, "BEGIN; PREPARE __pgdog_1 AS SELECT $1, $2, $3; PREPARE __pgdog_2 AS SELECT * FROM my_table WHERE id = $1; COMMIT"),
@@ -93,7 +93,7 @@ mod test {
             let ast = pg_query::parse(q).unwrap();
             let ast = Arc::new(ast);
             let rewrite = Rewrite::new(&ast)
-                .rewrite(&mut PreparedStatements::new())
+                .rewrite(&mut PreparedStatements::new(None))
                 .unwrap();
 
             assert!(matches!(rewrite, Command::Deallocate));
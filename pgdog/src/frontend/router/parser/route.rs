@@ -1,8 +1,13 @@
 use std::fmt::Display;
 
-use super::{Aggregate, DistinctBy, FunctionBehavior, Limit, LockingBehavior, OrderBy};
+use serde::{ser::SerializeStruct, Serialize, Serializer};
 
-#[derive(Debug, Clone, PartialEq, PartialOrd, Ord, Eq, Hash, Default)]
+use super::{
+    Aggregate, DistinctBy, FunctionBehavior, InsertSplit, Limit, LockingBehavior, OrderBy,
+};
+
+#[derive(Debug, Clone, PartialEq, PartialOrd, Ord, Eq, Hash, Default, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Shard {
     Direct(usize),
     Multi(Vec<usize>),
@@ -55,6 +60,9 @@ pub struct Route {
     limit: Limit,
     lock_session: bool,
     distinct: Option<DistinctBy>,
+    changes_role: bool,
+    insert_split: Option<InsertSplit>,
+    degraded: bool,
 }
 
 impl Display for Route {
@@ -68,6 +76,22 @@ impl Display for Route {
     }
 }
 
+/// Where the query went and how its results were merged,
+/// without the details of the merge.
+impl Serialize for Route {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut route = serializer.serialize_struct("Route", 7)?;
+        route.serialize_field("shard", &self.shard)?;
+        route.serialize_field("role", if self.read { "replica" } else { "primary" })?;
+        route.serialize_field("lock_session", &self.lock_session)?;
+        route.serialize_field("changes_role", &self.changes_role)?;
+        route.serialize_field("degraded", &self.degraded)?;
+        route.serialize_field("buffered", &self.should_buffer())?;
+        route.serialize_field("insert_split", &self.insert_split().is_some())?;
+        route.end()
+    }
+}
+
 impl Route {
     /// SELECT query.
     pub fn select(
@@ -178,6 +202,7 @@ impl Route {
         let FunctionBehavior {
             writes,
             locking_behavior,
+            ..
         } = write;
         self.read = !writes;
         self.lock_session = matches!(locking_behavior, LockingBehavior::Lock);
@@ -192,6 +217,42 @@ impl Route {
         self.lock_session
     }
 
+    /// Statement changes the role, which has to be reset
+    /// before the connection is used by another client.
+    pub fn set_changes_role(mut self) -> Self {
+        self.changes_role = true;
+        self
+    }
+
+    pub fn changes_role(&self) -> bool {
+        self.changes_role
+    }
+
+    /// Multi-row INSERT split between shards.
+    pub fn set_insert_split(mut self, split: InsertSplit) -> Self {
+        self.shard = Shard::Multi(split.shards());
+        self.insert_split = Some(split);
+        self
+    }
+
+    /// Statement for each shard, if the INSERT was split between them
+    /// and is still going to the same shards.
+    pub fn insert_split(&self) -> Option<&InsertSplit> {
+        self.insert_split
+            .as_ref()
+            .filter(|split| self.shard == Shard::Multi(split.shards()))
+    }
+
+    /// Read sent to a replica because the primary is down.
+    pub fn set_degraded_mut(&mut self) {
+        self.read = true;
+        self.degraded = true;
+    }
+
+    pub fn degraded(&self) -> bool {
+        self.degraded
+    }
+
     pub fn distinct(&self) -> &Option<DistinctBy> {
         &self.distinct
     }
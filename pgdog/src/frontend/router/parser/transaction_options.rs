@@ -0,0 +1,146 @@
+//! Transaction options, e.g. `BEGIN ISOLATION LEVEL SERIALIZABLE READ ONLY`.
+
+use pg_query::{
+    protobuf::{a_const::Val, AConst, Node},
+    NodeEnum,
+};
+
+/// Options passed to `BEGIN`, `START TRANSACTION` or `SET TRANSACTION`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TransactionOptions {
+    /// Isolation level, e.g. "serializable".
+    pub isolation_level: Option<String>,
+    /// READ ONLY (true) or READ WRITE (false).
+    pub read_only: Option<bool>,
+    /// DEFERRABLE (true) or NOT DEFERRABLE (false).
+    pub deferrable: Option<bool>,
+}
+
+impl TransactionOptions {
+    /// Extract options from the statement's DefElem nodes.
+    pub fn from_nodes(nodes: &[Node]) -> Self {
+        let mut options = Self::default();
+
+        for node in nodes {
+            let Some(NodeEnum::DefElem(ref elem)) = node.node else {
+                continue;
+            };
+            let Some(NodeEnum::AConst(AConst {
+                val: Some(ref val), ..
+            })) = elem.arg.as_ref().and_then(|arg| arg.node.as_ref())
+            else {
+                continue;
+            };
+
+            match (elem.defname.as_str(), val) {
+                ("transaction_isolation", Val::Sval(sval)) => {
+                    options.isolation_level = Some(sval.sval.clone())
+                }
+                ("transaction_read_only", Val::Ival(ival)) => {
+                    options.read_only = Some(ival.ival != 0)
+                }
+                ("transaction_deferrable", Val::Ival(ival)) => {
+                    options.deferrable = Some(ival.ival != 0)
+                }
+                _ => (),
+            }
+        }
+
+        options
+    }
+
+    /// Override options with the ones set in `other`.
+    pub fn merge(&mut self, other: &TransactionOptions) {
+        if other.isolation_level.is_some() {
+            self.isolation_level = other.isolation_level.clone();
+        }
+        if other.read_only.is_some() {
+            self.read_only = other.read_only;
+        }
+        if other.deferrable.is_some() {
+            self.deferrable = other.deferrable;
+        }
+    }
+
+    /// `BEGIN` statement starting a transaction with these options.
+    pub fn begin(&self) -> String {
+        let mut query = String::from("BEGIN");
+
+        if let Some(ref isolation_level) = self.isolation_level {
+            query.push_str(" ISOLATION LEVEL ");
+            query.push_str(&isolation_level.to_uppercase());
+        }
+
+        match self.read_only {
+            Some(true) => query.push_str(" READ ONLY"),
+            Some(false) => query.push_str(" READ WRITE"),
+            None => (),
+        }
+
+        match self.deferrable {
+            Some(true) => query.push_str(" DEFERRABLE"),
+            Some(false) => query.push_str(" NOT DEFERRABLE"),
+            None => (),
+        }
+
+        query
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn options(query: &str) -> TransactionOptions {
+        let ast = pg_query::parse(query).unwrap();
+        match ast.protobuf.stmts[0].stmt.as_ref().unwrap().node.as_ref() {
+            Some(NodeEnum::TransactionStmt(stmt)) => TransactionOptions::from_nodes(&stmt.options),
+            Some(NodeEnum::VariableSetStmt(stmt)) => TransactionOptions::from_nodes(&stmt.args),
+            _ => panic!("not a transaction statement"),
+        }
+    }
+
+    #[test]
+    fn test_transaction_options() {
+        for (query, begin) in [
+            ("BEGIN", "BEGIN"),
+            ("START TRANSACTION", "BEGIN"),
+            (
+                "BEGIN ISOLATION LEVEL SERIALIZABLE",
+                "BEGIN ISOLATION LEVEL SERIALIZABLE",
+            ),
+            (
+                "BEGIN ISOLATION LEVEL REPEATABLE READ READ ONLY DEFERRABLE",
+                "BEGIN ISOLATION LEVEL REPEATABLE READ READ ONLY DEFERRABLE",
+            ),
+            (
+                "START TRANSACTION READ WRITE, ISOLATION LEVEL READ COMMITTED, NOT DEFERRABLE",
+                "BEGIN ISOLATION LEVEL READ COMMITTED READ WRITE NOT DEFERRABLE",
+            ),
+            (
+                "SET TRANSACTION ISOLATION LEVEL SERIALIZABLE",
+                "BEGIN ISOLATION LEVEL SERIALIZABLE",
+            ),
+        ] {
+            let parsed = options(query);
+            assert_eq!(parsed.begin(), begin, "{}", query);
+            // Generated statement parses back into the same options.
+            assert_eq!(options(&parsed.begin()), parsed);
+        }
+    }
+
+    #[test]
+    fn test_transaction_options_merge() {
+        let mut begin = options("BEGIN READ ONLY");
+        begin.merge(&options("SET TRANSACTION ISOLATION LEVEL SERIALIZABLE"));
+        begin.merge(&options("SET TRANSACTION DEFERRABLE"));
+        assert_eq!(
+            begin.begin(),
+            "BEGIN ISOLATION LEVEL SERIALIZABLE READ ONLY DEFERRABLE"
+        );
+
+        begin.merge(&options("SET TRANSACTION READ WRITE"));
+        assert_eq!(begin.read_only, Some(false));
+        assert_eq!(begin.isolation_level, Some("serializable".into()));
+    }
+}
@@ -6,7 +6,7 @@ use pg_query::{
 };
 use std::string::String;
 
-use super::Key;
+use super::{Key, Table};
 
 #[derive(Debug)]
 pub struct Column<'a> {
@@ -31,6 +31,8 @@ enum Output<'a> {
 #[derive(Debug)]
 pub struct WhereClause<'a> {
     output: Vec<Output<'a>>,
+    /// Table aliases and the tables they refer to.
+    aliases: Vec<(&'a str, &'a str)>,
 }
 
 impl<'a> WhereClause<'a> {
@@ -46,20 +48,87 @@ impl<'a> WhereClause<'a> {
 
         let output = Self::parse(table_name, where_clause, false);
 
-        Some(Self { output })
+        Some(Self {
+            output,
+            aliases: vec![],
+        })
     }
 
+    /// Parse the `WHERE` clause of a `SELECT` and the conditions of its inner joins.
+    /// Table aliases in the `FROM` clause are resolved to table names.
+    pub fn select(stmt: &'a SelectStmt) -> Option<WhereClause<'a>> {
+        let table_name = Table::try_from(&stmt.from_clause)
+            .ok()
+            .map(|table| table.name);
+        let mut where_clause = Self {
+            output: vec![],
+            aliases: vec![],
+        };
+
+        for node in &stmt.from_clause {
+            where_clause.from(table_name, node, true);
+        }
+
+        match stmt.where_clause {
+            Some(ref node) => where_clause
+                .output
+                .extend(Self::parse(table_name, node, false)),
+            None if where_clause.output.is_empty() => return None,
+            None => (),
+        }
+
+        Some(where_clause)
+    }
+
+    /// Find the values of a sharding key, including values of columns
+    /// it's equal to, e.g. `a.id = b.id AND b.id = 5`.
     pub fn keys(&self, table_name: Option<&str>, column_name: &str) -> Vec<Key> {
+        let mut equal: Vec<&Column> = vec![];
+        let key_column = |column: &Column, equal: &[&Column]| {
+            self.column_match(column, table_name, column_name)
+                || equal
+                    .iter()
+                    .any(|other| other.table == column.table && other.name == column.name)
+        };
+
+        loop {
+            let mut found = false;
+            for (left, right) in self.equalities() {
+                for (column, other) in [(left, right), (right, left)] {
+                    if key_column(column, &equal) && !key_column(other, &equal) {
+                        equal.push(other);
+                        found = true;
+                    }
+                }
+            }
+
+            if !found {
+                break;
+            }
+        }
+
+        let matches = |column: &Column| key_column(column, &equal);
         let mut keys = vec![];
         for output in &self.output {
-            keys.extend(Self::search_for_keys(output, table_name, column_name));
+            keys.extend(Self::search_for_keys(output, &matches));
         }
         keys
     }
 
-    fn column_match(column: &Column, table: Option<&str>, name: &str) -> bool {
-        if let (Some(table), Some(other_table)) = (table, &column.table) {
-            if &table != other_table {
+    /// Columns compared to other columns with `=`.
+    fn equalities(&self) -> impl Iterator<Item = (&Column<'a>, &Column<'a>)> {
+        self.output.iter().filter_map(|output| match output {
+            Output::Filter(left, right) => match (left.as_slice(), right.as_slice()) {
+                ([Output::Column(left)], [Output::Column(right)]) => Some((left, right)),
+                _ => None,
+            },
+            _ => None,
+        })
+    }
+
+    fn column_match(&self, column: &Column, table: Option<&str>, name: &str) -> bool {
+        if let (Some(table), Some(other_table)) = (table, column.table) {
+            if table != other_table && Some(table) != self.alias(other_table) {
                 return false;
             }
         };
@@ -67,6 +136,83 @@ impl<'a> WhereClause<'a> {
         column.name == name
     }
 
+    /// Table name behind an alias.
+    fn alias(&self, reference: &str) -> Option<&'a str> {
+        self.aliases
+            .iter()
+            .find(|(alias, _)| *alias == reference)
+            .map(|(_, name)| *name)
+    }
+
+    /// Record table aliases and the conditions of inner joins, which
+    /// hold for every returned row. Returns references to the tables in `node`.
+    fn from(&mut self, table_name: Option<&'a str>, node: &'a Node, inner: bool) -> Vec<&'a str> {
+        match node.node {
+            Some(NodeEnum::RangeVar(ref range_var)) => {
+                let name = range_var.relname.as_str();
+                match range_var.alias {
+                    Some(ref alias) => {
+                        self.aliases.push((alias.aliasname.as_str(), name));
+                        vec![alias.aliasname.as_str()]
+                    }
+                    None => vec![name],
+                }
+            }
+
+            Some(NodeEnum::JoinExpr(ref join)) => {
+                // Outer joins keep rows that don't match the conditions
+                // on the preserved side.
+                let join_type = join.jointype();
+                let left_inner =
+                    inner && matches!(join_type, JoinType::JoinInner | JoinType::JoinLeft);
+                let right_inner =
+                    inner && matches!(join_type, JoinType::JoinInner | JoinType::JoinRight);
+
+                let left = join
+                    .larg
+                    .as_deref()
+                    .map(|node| self.from(table_name, node, left_inner))
+                    .unwrap_or_default();
+                let right = join
+                    .rarg
+                    .as_deref()
+                    .map(|node| self.from(table_name, node, right_inner))
+                    .unwrap_or_default();
+
+                if inner && join_type == JoinType::JoinInner {
+                    for column in &join.using_clause {
+                        let Some(name) = Self::string(Some(column)) else {
+                            continue;
+                        };
+
+                        for left in &left {
+                            for right in &right {
+                                self.output.push(Output::Filter(
+                                    vec![Output::Column(Column {
+                                        table: Some(*left),
+                                        name,
+                                    })],
+                                    vec![Output::Column(Column {
+                                        table: Some(*right),
+                                        name,
+                                    })],
+                                ));
+                            }
+                        }
+                    }
+
+                    if let Some(ref quals) = join.quals {
+                        self.output.extend(Self::parse(table_name, quals, false));
+                    }
+                }
+
+                left.into_iter().chain(right).collect()
+            }
+
+            _ => vec![],
+        }
+    }
+
     fn get_key(output: &Output) -> Option<Key> {
         match output {
             Output::Int { value, array } => Some(Key::Constant {
@@ -85,7 +231,7 @@ impl<'a> WhereClause<'a> {
         }
     }
 
-    fn search_for_keys(output: &Output, table_name: Option<&str>, column_name: &str) -> Vec<Key> {
+    fn search_for_keys(output: &Output, matches: &dyn Fn(&Column) -> bool) -> Vec<Key> {
         let mut keys = vec![];
 
         if let Output::Filter(ref left, ref right) = output {
@@ -96,7 +242,7 @@ impl<'a> WhereClause<'a> {
                 // TODO: Handle something like
                 // id = (SELECT 5) which is stupid but legal SQL.
                 (&[Output::Column(ref column)], output) => {
-                    if Self::column_match(column, table_name, column_name) {
+                    if matches(column) {
                         for output in output.iter() {
                             if let Some(key) = Self::get_key(output) {
                                 keys.push(key);
@@ -105,7 +251,7 @@ impl<'a> WhereClause<'a> {
                     }
                 }
                 (output, &[Output::Column(ref column)]) => {
-                    if Self::column_match(column, table_name, column_name) {
+                    if matches(column) {
                         for output in output.iter() {
                             if let Some(key) = Self::get_key(output) {
                                 keys.push(key);
@@ -116,18 +262,18 @@ impl<'a> WhereClause<'a> {
 
                 _ => {
                     for output in left {
-                        keys.extend(Self::search_for_keys(output, table_name, column_name));
+                        keys.extend(Self::search_for_keys(output, matches));
                     }
 
                     for output in right {
-                        keys.extend(Self::search_for_keys(output, table_name, column_name));
+                        keys.extend(Self::search_for_keys(output, matches));
                     }
                 }
             }
         }
 
         if let Output::NullCheck(c) = output {
-            if c.name == column_name && c.table == table_name {
+            if matches(c) {
                 keys.push(Key::Null);
             }
         }
@@ -366,4 +512,88 @@ mod test {
             panic!("not a select");
         }
     }
+
+    fn select_keys(query: &str, table: &str, column: &str) -> Vec<Key> {
+        let ast = parse(query).unwrap();
+        let stmt = ast.protobuf.stmts.first().cloned().unwrap().stmt.unwrap();
+
+        if let Some(NodeEnum::SelectStmt(stmt)) = stmt.node {
+            WhereClause::select(&stmt)
+                .map(|where_| where_.keys(Some(table), column))
+                .unwrap_or_default()
+        } else {
+            panic!("not a select");
+        }
+    }
+
+    #[test]
+    fn test_aliases() {
+        let five = || Key::Constant {
+            value: "5".into(),
+            array: false,
+        };
+
+        for query in [
+            "SELECT * FROM users u WHERE u.tenant_id = 5",
+            "SELECT * FROM users u WHERE tenant_id = 5",
+            "SELECT * FROM users AS u WHERE u.tenant_id = 5 AND u.id != 6",
+        ] {
+            assert_eq!(
+                select_keys(query, "users", "tenant_id"),
+                vec![five()],
+                "{}",
+                query
+            );
+            assert_eq!(
+                select_keys(query, "u", "tenant_id"),
+                vec![five()],
+                "{}",
+                query
+            );
+        }
+
+        assert!(select_keys(
+            "SELECT * FROM users u JOIN orders o ON o.user_id = u.id WHERE o.tenant_id = 5",
+            "users",
+            "tenant_id"
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn test_join_conditions() {
+        let five = || Key::Constant {
+            value: "5".into(),
+            array: false,
+        };
+
+        for query in [
+            "SELECT * FROM users u JOIN orders o ON o.tenant_id = u.tenant_id AND u.tenant_id = 5",
+            "SELECT * FROM users u JOIN orders o ON o.tenant_id = u.tenant_id WHERE u.tenant_id = 5",
+            "SELECT * FROM users u JOIN orders o USING (tenant_id) WHERE u.tenant_id = 5",
+            "SELECT * FROM users u, orders o WHERE o.tenant_id = u.tenant_id AND 5 = u.tenant_id",
+            "SELECT * FROM users u JOIN items i ON i.tenant_id = u.tenant_id JOIN orders o ON o.tenant_id = i.tenant_id WHERE u.tenant_id = 5",
+        ] {
+            assert_eq!(select_keys(query, "users", "tenant_id"), vec![five()], "{}", query);
+            assert_eq!(select_keys(query, "orders", "tenant_id"), vec![five()], "{}", query);
+            assert_eq!(select_keys(query, "o", "tenant_id"), vec![five()], "{}", query);
+        }
+
+        // Self-join.
+        let query = "SELECT * FROM users a JOIN users b ON a.id = b.id WHERE b.id = $1";
+        let param = || Key::Parameter {
+            pos: 0,
+            array: false,
+        };
+        assert_eq!(select_keys(query, "a", "id"), vec![param()]);
+        assert_eq!(select_keys(query, "users", "id"), vec![param()]);
+
+        // Outer joins keep rows that don't match.
+        for query in [
+            "SELECT * FROM users u LEFT JOIN orders o ON o.tenant_id = u.tenant_id AND u.tenant_id = 5",
+            "SELECT * FROM users u JOIN orders o ON o.tenant_id = u.tenant_id OR u.tenant_id = 5",
+        ] {
+            assert!(select_keys(query, "users", "tenant_id").is_empty(), "{}", query);
+        }
+    }
 }
@@ -1,9 +1,69 @@
+//! Round robin counters.
+//!
+//! Each cluster has its own counters, one for each purpose, so queries to one
+//! database don't skew how another one spreads its queries across shards.
+
 use once_cell::sync::Lazy;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
 
 static ROUND_ROBIN: Lazy<AtomicUsize> = Lazy::new(|| AtomicUsize::new(0));
 
 /// Get next round robin number.
+///
+/// Shared by the whole process. Use the cluster's [`RoundRobin`] instead if possible.
 pub fn next() -> usize {
     ROUND_ROBIN.fetch_add(1, Ordering::Relaxed)
 }
+
+/// What the round robin number is used for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Purpose {
+    /// Queries without tables, e.g. `SELECT 1`, and reads from omnisharded tables.
+    Select,
+    /// INSERTs into sharded tables without the sharding key.
+    Insert,
+    /// NULL sharding keys with `null_shard = "round_robin"`.
+    NullShardingKey,
+    /// Manual queries that don't specify shards.
+    ManualQuery,
+    /// SHOW commands.
+    Show,
+}
+
+impl Purpose {
+    const COUNT: usize = 5;
+}
+
+/// Round robin counters of a cluster.
+///
+/// Clones share the same counters.
+#[derive(Debug, Clone, Default)]
+pub struct RoundRobin {
+    counters: Arc<[AtomicUsize; Purpose::COUNT]>,
+}
+
+impl RoundRobin {
+    /// Get next round robin number.
+    pub fn next(&self, purpose: Purpose) -> usize {
+        self.counters[purpose as usize].fetch_add(1, Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_round_robin_purposes() {
+        let round_robin = RoundRobin::default();
+        let clone = round_robin.clone();
+
+        assert_eq!(round_robin.next(Purpose::Select), 0);
+        assert_eq!(clone.next(Purpose::Select), 1);
+        assert_eq!(round_robin.next(Purpose::Insert), 0);
+        assert_eq!(RoundRobin::default().next(Purpose::Select), 0);
+    }
+}
@@ -1,17 +1,35 @@
-use crate::frontend::router::parser::Shard;
+use crate::config::NullShard;
+use crate::frontend::router::{
+    parser::Shard,
+    round_robin::{self, Purpose, RoundRobin},
+};
 use tracing::debug;
 
-use super::{Error, Hasher, Operator, Value};
+use super::{combine, Error, Hasher, Operator, Value};
 
 #[derive(Debug)]
 pub struct Context<'a> {
     pub(super) value: Value<'a>,
+    /// Remaining values of a composite sharding key.
+    pub(super) composite: Vec<Value<'a>>,
     pub(super) operator: Operator<'a>,
     pub(super) hasher: Hasher,
+    /// Where NULL keys go.
+    pub(super) null_shard: NullShard,
+    pub(super) round_robin: Option<&'a RoundRobin>,
+    pub(super) shards: usize,
 }
 
 impl Context<'_> {
     pub fn apply(&self) -> Result<Shard, Error> {
+        if self.value.is_null() || self.composite.iter().any(|value| value.is_null()) {
+            return self.apply_null();
+        }
+
+        if !self.composite.is_empty() {
+            return self.apply_composite();
+        }
+
         match &self.operator {
             Operator::Shards(shards) => {
                 debug!("sharding using hash");
@@ -44,4 +62,45 @@ impl Context<'_> {
 
         Ok(Shard::All)
     }
+
+    /// Route a NULL sharding key.
+    fn apply_null(&self) -> Result<Shard, Error> {
+        debug!("sharding key is null, using {:?}", self.null_shard);
+
+        match self.null_shard {
+            NullShard::Error => Err(Error::NullShardingKey),
+            NullShard::RoundRobin => {
+                let next = self
+                    .round_robin
+                    .map(|counters| counters.next(Purpose::NullShardingKey))
+                    .unwrap_or_else(round_robin::next);
+                Ok(Shard::Direct(next % self.shards.max(1)))
+            }
+            NullShard::Shard(shard) => Ok(Shard::Direct(shard)),
+        }
+    }
+
+    /// Hash all key values in order and combine them.
+    /// Only hash sharding supports composite keys.
+    fn apply_composite(&self) -> Result<Shard, Error> {
+        let Operator::Shards(shards) = &self.operator else {
+            return Ok(Shard::All);
+        };
+
+        debug!("sharding using composite hash");
+
+        let mut hash = match self.value.hash(self.hasher)? {
+            Some(hash) => hash,
+            None => return Ok(Shard::All),
+        };
+
+        for value in &self.composite {
+            match value.hash(self.hasher)? {
+                Some(next) => hash = combine(hash, next),
+                None => return Ok(Shard::All),
+            }
+        }
+
+        Ok(Shard::Direct(hash as usize % *shards))
+    }
 }
@@ -1,4 +1,5 @@
-use crate::config::{DataType, Hasher as HasherConfig, ShardedTable};
+use crate::config::{DataType, Hasher as HasherConfig, NullShard, ShardedTable};
+use crate::frontend::router::round_robin::RoundRobin;
 
 use super::{Centroids, Context, Data, Error, Hasher, Lists, Operator, Ranges, Value};
 
@@ -6,6 +7,7 @@ use super::{Centroids, Context, Data, Error, Hasher, Lists, Operator, Ranges, Va
 pub struct ContextBuilder<'a> {
     data_type: DataType,
     value: Option<Value<'a>>,
+    composite: Vec<Value<'a>>,
     operator: Option<Operator<'a>>,
     centroids: Option<Centroids<'a>>,
     ranges: Option<Ranges<'a>>,
@@ -14,6 +16,9 @@ pub struct ContextBuilder<'a> {
     hasher: Hasher,
     #[allow(dead_code)]
     array: bool,
+    null_shard: NullShard,
+    round_robin: Option<&'a RoundRobin>,
+    shards: usize,
 }
 
 impl<'a> ContextBuilder<'a> {
@@ -28,6 +33,7 @@ impl<'a> ContextBuilder<'a> {
             probes: table.centroid_probes,
             operator: None,
             value: None,
+            composite: vec![],
             hasher: match table.hasher {
                 HasherConfig::Sha1 => Hasher::Sha1,
                 HasherConfig::Postgres => Hasher::Postgres,
@@ -35,6 +41,9 @@ impl<'a> ContextBuilder<'a> {
             ranges: Ranges::new(&table.mapping),
             lists: Lists::new(&table.mapping),
             array: false,
+            null_shard: table.null_shard,
+            round_robin: None,
+            shards: 0,
         }
     }
 
@@ -55,6 +64,10 @@ impl<'a> ContextBuilder<'a> {
                 array: false,
                 ranges: None,
                 lists: None,
+                composite: vec![],
+                null_shard: NullShard::default(),
+                round_robin: None,
+                shards: 0,
             })
         } else if uuid.valid() {
             Ok(Self {
@@ -67,6 +80,10 @@ impl<'a> ContextBuilder<'a> {
                 array: false,
                 ranges: None,
                 lists: None,
+                composite: vec![],
+                null_shard: NullShard::default(),
+                round_robin: None,
+                shards: 0,
             })
         } else if varchar.valid() {
             Ok(Self {
@@ -79,6 +96,10 @@ impl<'a> ContextBuilder<'a> {
                 array: false,
                 ranges: None,
                 lists: None,
+                composite: vec![],
+                null_shard: NullShard::default(),
+                round_robin: None,
+                shards: 0,
             })
         } else {
             Err(Error::IncompleteContext)
@@ -86,6 +107,7 @@ impl<'a> ContextBuilder<'a> {
     }
 
     pub fn shards(mut self, shards: usize) -> Self {
+        self.shards = shards;
         if let Some(centroids) = self.centroids.take() {
             self.operator = Some(Operator::Centroids {
                 shards,
@@ -102,6 +124,12 @@ impl<'a> ContextBuilder<'a> {
         self
     }
 
+    /// Cluster's round robin counters, used for NULL sharding keys.
+    pub fn round_robin(mut self, round_robin: &'a RoundRobin) -> Self {
+        self.round_robin = Some(round_robin);
+        self
+    }
+
     pub fn data(mut self, data: impl Into<Data<'a>>) -> Self {
         self.value = Some(Value::new(data, self.data_type));
         self
@@ -112,6 +140,14 @@ impl<'a> ContextBuilder<'a> {
         self
     }
 
+    /// Values of a composite sharding key, in key column order.
+    pub fn values(mut self, values: Vec<Value<'a>>) -> Self {
+        let mut values = values.into_iter();
+        self.value = values.next();
+        self.composite = values.collect();
+        self
+    }
+
     pub fn build(mut self) -> Result<Context<'a>, Error> {
         let operator = self.operator.take().ok_or(Error::IncompleteContext)?;
         let value = self.value.take().ok_or(Error::IncompleteContext)?;
@@ -119,7 +155,11 @@ impl<'a> ContextBuilder<'a> {
         Ok(Context {
             operator,
             value,
+            composite: self.composite,
             hasher: self.hasher,
+            null_shard: self.null_shard,
+            round_robin: self.round_robin,
+            shards: self.shards,
         })
     }
 }
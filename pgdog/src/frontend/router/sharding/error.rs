@@ -33,4 +33,7 @@ pub enum Error {
 
     #[error("range is overlapping or incorrect")]
     IncorrectRange,
+
+    #[error("sharding key is null")]
+    NullShardingKey,
 }
@@ -1,4 +1,7 @@
 use std::collections::HashMap;
+use std::sync::Arc;
+
+use arc_swap::ArcSwap;
 
 use super::{Error, Mapping, Shard, Value};
 
@@ -35,14 +38,32 @@ impl<'a> Lists<'a> {
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+/// Value to shard mapping. Clones share it, so changes
+/// made at runtime are seen by all of them.
+#[derive(Debug, Clone)]
 pub struct ListShards {
-    mapping: HashMap<FlexibleType, usize>,
+    mapping: Arc<ArcSwap<HashMap<FlexibleType, usize>>>,
+}
+
+impl PartialEq for ListShards {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.mapping, &other.mapping) || **self.load() == **other.load()
+    }
 }
 
 impl ListShards {
     pub fn is_empty(&self) -> bool {
-        self.mapping.is_empty()
+        self.load().is_empty()
+    }
+
+    /// Current mapping.
+    pub fn load(&self) -> Arc<HashMap<FlexibleType, usize>> {
+        self.mapping.load_full()
+    }
+
+    /// Replace the mapping. Readers aren't blocked.
+    pub fn store(&self, mapping: HashMap<FlexibleType, usize>) {
+        self.mapping.store(Arc::new(mapping));
     }
 
     pub fn new(mappings: &[ShardedMapping]) -> Self {
@@ -57,11 +78,13 @@ impl ListShards {
             }
         }
 
-        Self { mapping }
+        Self {
+            mapping: Arc::new(ArcSwap::from_pointee(mapping)),
+        }
     }
 
     pub fn shard(&self, value: &FlexibleType) -> Result<Shard, Error> {
-        if let Some(shard) = self.mapping.get(value) {
+        if let Some(shard) = self.mapping.load().get(value) {
             Ok(Shard::Direct(*shard))
         } else {
             Ok(Shard::All)
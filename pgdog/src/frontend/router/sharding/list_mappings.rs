@@ -0,0 +1,222 @@
+//! List mappings shared by all clusters, so they can be changed
+//! by config reloads and admin commands without a restart.
+
+use std::collections::{HashMap, HashSet};
+use std::fs::{read_to_string, rename, write};
+use std::path::Path;
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+
+use super::{ListShards, Mapping};
+use crate::config::{
+    error::Error, Config, FlexibleType, ShardedMapping, ShardedMappingKind, ShardedMappings,
+};
+
+/// Database, column and table of a mapping.
+type Key = (String, String, Option<String>);
+
+static LIST_MAPPINGS: Lazy<ListMappings> = Lazy::new(ListMappings::default);
+
+/// List mappings by database, column and table.
+#[derive(Debug, Default)]
+pub struct ListMappings {
+    lists: Mutex<HashMap<Key, ListShards>>,
+}
+
+impl ListMappings {
+    /// Get the global list mappings.
+    pub fn global() -> &'static ListMappings {
+        &LIST_MAPPINGS
+    }
+
+    /// Get the list mapping shared by all clusters, or use this one if there isn't one yet.
+    pub fn shared(&self, key: Key, list: ListShards) -> ListShards {
+        self.lists.lock().entry(key).or_insert(list).clone()
+    }
+
+    /// Replace all mappings with the ones from the config. Clusters
+    /// created from the previous config see the new values too.
+    pub fn configure(&self, config: &Config) {
+        let mut lists = self.lists.lock();
+        let mut configured = HashSet::new();
+
+        for (key, mappings) in config.sharded_mappings() {
+            if let Some(Mapping::List(list)) = Mapping::new(&mappings) {
+                match lists.get(&key) {
+                    Some(existing) => existing.store((*list.load()).clone()),
+                    None => {
+                        lists.insert(key.clone(), list);
+                    }
+                }
+                configured.insert(key);
+            }
+        }
+
+        lists.retain(|key, _| configured.contains(key));
+    }
+
+    /// Send rows with this value to the shard. Returns the mappings that changed.
+    pub fn add(&self, database: &str, column: &str, value: FlexibleType, shard: usize) -> Vec<Key> {
+        self.update(database, column, |mapping| {
+            mapping.insert(value.clone(), shard);
+        })
+    }
+
+    /// Stop sending rows with this value to a specific shard.
+    /// Returns the mappings that changed.
+    pub fn remove(&self, database: &str, column: &str, value: &FlexibleType) -> Vec<Key> {
+        self.update(database, column, |mapping| {
+            mapping.remove(value);
+        })
+    }
+
+    fn update(
+        &self,
+        database: &str,
+        column: &str,
+        f: impl Fn(&mut HashMap<FlexibleType, usize>),
+    ) -> Vec<Key> {
+        let lists = self.lists.lock();
+        let mut changed = vec![];
+
+        for (key, list) in lists.iter() {
+            if key.0 == database && key.1 == column {
+                let mut mapping = (*list.load()).clone();
+                f(&mut mapping);
+                list.store(mapping);
+                changed.push(key.clone());
+            }
+        }
+
+        changed
+    }
+
+    /// Save these mappings to the file, keeping other mappings already saved there.
+    pub fn save(&self, path: &Path, keys: &[Key]) -> Result<(), Error> {
+        let mut saved: ShardedMappings = match read_to_string(path) {
+            Ok(saved) => toml::from_str(&saved)?,
+            Err(_) => ShardedMappings::default(),
+        };
+
+        saved
+            .sharded_mappings
+            .retain(|mapping| !keys.contains(&mapping.key()));
+
+        let lists = self.lists.lock();
+        for key in keys {
+            let Some(list) = lists.get(key) else {
+                continue;
+            };
+
+            let mut shards: HashMap<usize, HashSet<FlexibleType>> = HashMap::new();
+            for (value, shard) in list.load().iter() {
+                shards.entry(*shard).or_default().insert(value.clone());
+            }
+
+            let mut shards = shards.into_iter().collect::<Vec<_>>();
+            shards.sort_by_key(|(shard, _)| *shard);
+
+            for (shard, values) in shards {
+                saved.sharded_mappings.push(ShardedMapping {
+                    database: key.0.clone(),
+                    column: key.1.clone(),
+                    table: key.2.clone(),
+                    kind: ShardedMappingKind::List,
+                    values,
+                    shard,
+                    ..Default::default()
+                });
+            }
+        }
+
+        // Don't leave a partially written file behind.
+        let tmp = path.with_extension("tmp");
+        write(&tmp, toml::to_string(&saved)?)?;
+        rename(&tmp, path)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::config::{DataType, ShardedTable};
+    use crate::frontend::router::parser::Shard;
+    use crate::frontend::router::sharding::ContextBuilder;
+
+    use super::*;
+
+    fn route(table: &ShardedTable, value: i64) -> Shard {
+        ContextBuilder::new(table)
+            .data(value)
+            .shards(2)
+            .build()
+            .unwrap()
+            .apply()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_runtime_mapping() {
+        let mut config = Config::default();
+        config.sharded_mappings = vec![ShardedMapping {
+            database: "pgdog".into(),
+            column: "tenant_id".into(),
+            kind: ShardedMappingKind::List,
+            values: HashSet::from([FlexibleType::Integer(1), FlexibleType::Integer(2)]),
+            shard: 0,
+            ..Default::default()
+        }];
+        let key: Key = ("pgdog".into(), "tenant_id".into(), None);
+
+        let mappings = ListMappings::default();
+        mappings.configure(&config);
+        let Some(Mapping::List(list)) = Mapping::new(&config.sharded_mappings) else {
+            panic!("not a list");
+        };
+
+        let table = ShardedTable {
+            database: "pgdog".into(),
+            column: "tenant_id".into(),
+            data_type: DataType::Bigint,
+            mapping: Some(Mapping::List(mappings.shared(key.clone(), list))),
+            ..Default::default()
+        };
+
+        assert_eq!(route(&table, 1), Shard::Direct(0));
+        assert_eq!(route(&table, 3), Shard::All);
+
+        // Move a tenant.
+        let changed = mappings.add("pgdog", "tenant_id", FlexibleType::Integer(1), 1);
+        assert_eq!(changed, vec![key.clone()]);
+        assert_eq!(route(&table, 1), Shard::Direct(1));
+        assert_eq!(route(&table, 2), Shard::Direct(0));
+
+        mappings.remove("pgdog", "tenant_id", &FlexibleType::Integer(2));
+        assert_eq!(route(&table, 2), Shard::All);
+
+        assert!(mappings
+            .add("pgdog", "other", FlexibleType::Integer(1), 1)
+            .is_empty());
+
+        // Reload puts back the configured values.
+        mappings.configure(&config);
+        assert_eq!(route(&table, 1), Shard::Direct(0));
+        assert_eq!(route(&table, 2), Shard::Direct(0));
+
+        // Saved changes replace the configured ones on next load.
+        let path = std::env::temp_dir().join(format!("pgdog_mappings_{}.toml", std::process::id()));
+        mappings.add("pgdog", "tenant_id", FlexibleType::Integer(3), 1);
+        mappings.save(&path, &[key.clone()]).unwrap();
+
+        config.general.sharded_mappings_path = Some(path.clone());
+        config.load_sharded_mappings().unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        mappings.configure(&config);
+        assert_eq!(route(&table, 1), Shard::Direct(0));
+        assert_eq!(route(&table, 3), Shard::Direct(1));
+        assert_eq!(config.sharded_mappings.len(), 2);
+    }
+}
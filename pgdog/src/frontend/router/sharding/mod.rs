@@ -3,7 +3,7 @@ use uuid::Uuid;
 use crate::{
     backend::ShardingSchema,
     config::{DataType, ShardedTable},
-    net::messages::{Format, FromDataType, ParameterWithFormat, Vector},
+    net::messages::{BackendKeyData, Format, FromDataType, ParameterWithFormat, Vector},
 };
 
 // pub mod context;
@@ -13,6 +13,7 @@ pub mod error;
 pub mod ffi;
 pub mod hasher;
 pub mod list;
+pub mod list_mappings;
 pub mod mapping;
 pub mod operator;
 pub mod range;
@@ -33,6 +34,7 @@ pub use vector::{Centroids, Distance};
 
 use super::parser::Shard;
 pub use list::{ListShards, Lists};
+pub use list_mappings::ListMappings;
 pub use mapping::Mapping;
 pub use range::Ranges;
 
@@ -41,6 +43,11 @@ pub fn bigint(id: i64) -> u64 {
     unsafe { ffi::hash_combine64(0, ffi::hashint8extended(id)) }
 }
 
+/// Combine hashes of a composite sharding key.
+pub fn combine(a: u64, b: u64) -> u64 {
+    unsafe { ffi::hash_combine64(a, b) }
+}
+
 /// Hash UUID.
 pub fn uuid(uuid: Uuid) -> u64 {
     unsafe {
@@ -56,6 +63,13 @@ pub fn varchar(s: &[u8]) -> u64 {
     unsafe { ffi::hash_combine64(0, ffi::hash_bytes_extended(s.as_ptr(), s.len() as i64)) }
 }
 
+/// Shard assigned to a client. It only depends on the client's
+/// BackendKeyData, so it doesn't change while the client is connected.
+pub fn client_shard(client_id: &BackendKeyData, shards: usize) -> usize {
+    let key = (i64::from(client_id.pid) << 32) | i64::from(client_id.secret as u32);
+    (bigint(key) % shards as u64) as usize
+}
+
 /// Shard a string value, parsing out a BIGINT, UUID, or vector.
 ///
 /// TODO: This is really not great, we should pass in the type oid
@@ -7,7 +7,8 @@ use crate::{
 #[derive(Debug)]
 pub struct Key<'a> {
     pub table: &'a ShardedTable,
-    pub position: usize,
+    /// Position of each sharding key column, in key order.
+    pub positions: Vec<usize>,
 }
 
 pub struct Tables<'a> {
@@ -31,38 +32,55 @@ impl<'a> Tables<'a> {
     }
 
     pub(crate) fn key(&'a self, table: Table, columns: &'a [Column]) -> Option<Key<'a>> {
+        // No column list, e.g. INSERT INTO sharded VALUES (...).
+        // Use column order from the database schema.
+        if columns.is_empty() {
+            return self.positional_key(table);
+        }
+
+        let columns = columns.iter().map(|column| column.name).collect::<Vec<_>>();
+        self.find_key(table, &columns)
+    }
+
+    fn positional_key(&self, table: Table) -> Option<Key<'a>> {
         let tables = self.schema.tables().tables();
 
-        // Check tables with name first.
-        let sharded = tables
+        // Don't load the schema for tables that can't be sharded.
+        if !tables
             .iter()
-            .filter(|table| table.name.is_some())
-            .find(|t| t.name.as_deref() == Some(table.name));
-
-        if let Some(sharded) = sharded {
-            if let Some(position) = columns.iter().position(|col| col.name == sharded.column) {
-                return Some(Key {
-                    table: sharded,
-                    position,
-                });
-            }
+            .any(|t| t.name.is_none() || t.name.as_deref() == Some(table.name))
+        {
+            return None;
         }
 
-        // Check tables without name.
-        let key: Option<(&'a ShardedTable, Option<usize>)> = tables
+        let columns = self.schema.columns.columns(table.schema, table.name)?;
+        let columns = columns
             .iter()
-            .filter(|table| table.name.is_none())
-            .map(|t| (t, columns.iter().position(|col| col.name == t.column)))
-            .find(|t| t.1.is_some());
-        if let Some(key) = key {
-            if let Some(position) = key.1 {
-                return Some(Key {
-                    table: key.0,
-                    position,
-                });
-            }
-        }
+            .map(|column| column.as_str())
+            .collect::<Vec<_>>();
+
+        self.find_key(table, &columns)
+    }
 
-        None
+    /// Find the sharded table and positions of its key columns,
+    /// checking tables with a name first. All key columns must be present.
+    fn find_key(&self, table: Table, columns: &[&str]) -> Option<Key<'a>> {
+        let tables = self.schema.tables().tables();
+
+        tables
+            .iter()
+            .filter(|t| t.name.as_deref() == Some(table.name))
+            .chain(tables.iter().filter(|t| t.name.is_none()))
+            .find_map(|sharded| {
+                sharded
+                    .key_columns()
+                    .iter()
+                    .map(|key| columns.iter().position(|column| column == key))
+                    .collect::<Option<Vec<_>>>()
+                    .map(|positions| Key {
+                        table: sharded,
+                        positions,
+                    })
+            })
     }
 }
@@ -14,6 +14,7 @@ pub enum Data<'a> {
     Text(&'a str),
     Binary(&'a [u8]),
     Integer(i64),
+    Null,
 }
 
 impl<'a> From<&'a str> for Data<'a> {
@@ -54,6 +55,19 @@ impl<'a> Value<'a> {
         }
     }
 
+    /// NULL value.
+    pub fn null(data_type: DataType) -> Self {
+        Self {
+            data_type,
+            data: Data::Null,
+        }
+    }
+
+    /// Value is NULL.
+    pub fn is_null(&self) -> bool {
+        matches!(self.data, Data::Null)
+    }
+
     pub fn from_param(
         param: &'a ParameterWithFormat<'a>,
         data_type: DataType,
@@ -72,7 +86,7 @@ impl<'a> Value<'a> {
             match self.data {
                 Data::Text(text) => Ok(Some(Vector::decode(text.as_bytes(), Format::Text)?)),
                 Data::Binary(binary) => Ok(Some(Vector::decode(binary, Format::Binary)?)),
-                Data::Integer(_) => Ok(None),
+                Data::Integer(_) | Data::Null => Ok(None),
             }
         } else {
             Ok(None)
@@ -85,16 +99,17 @@ impl<'a> Value<'a> {
                 Data::Text(text) => text.parse::<i64>().is_ok(),
                 Data::Binary(data) => [2, 4, 8].contains(&data.len()),
                 Data::Integer(_) => true,
+                Data::Null => false,
             },
             DataType::Uuid => match self.data {
                 Data::Text(text) => Uuid::from_str(text).is_ok(),
                 Data::Binary(data) => data.len() == 16,
-                Data::Integer(_) => false,
+                Data::Integer(_) | Data::Null => false,
             },
             DataType::Varchar => match self.data {
                 Data::Text(_) => true,
                 Data::Binary(data) => from_utf8(data).is_ok(),
-                Data::Integer(_) => false,
+                Data::Integer(_) | Data::Null => false,
             },
 
             _ => false,
@@ -109,6 +124,7 @@ impl<'a> Value<'a> {
         if self.data_type == DataType::Bigint {
             match self.data {
                 Data::Integer(int) => Ok(Some(int)),
+                Data::Null => Ok(None),
                 Data::Text(text) => Ok(Some(text.parse()?)),
                 Data::Binary(data) => match data.len() {
                     2 => Ok(Some(i16::from_be_bytes(data.try_into()?) as i64)),
@@ -125,7 +141,7 @@ impl<'a> Value<'a> {
     pub fn varchar(&self) -> Result<Option<&str>, Error> {
         if self.data_type == DataType::Varchar {
             match self.data {
-                Data::Integer(_) => Ok(None),
+                Data::Integer(_) | Data::Null => Ok(None),
                 Data::Text(text) => Ok(Some(text)),
                 Data::Binary(data) => Ok(Some(from_utf8(data)?)),
             }
@@ -159,19 +175,20 @@ impl<'a> Value<'a> {
                     _ => return Err(Error::IntegerSize),
                 }))),
                 Data::Integer(int) => Ok(Some(hasher.bigint(int))),
+                Data::Null => Ok(None),
             },
 
             DataType::Uuid => match self.data {
                 Data::Text(text) => Ok(Some(hasher.uuid(Uuid::from_str(text)?))),
                 Data::Binary(data) => Ok(Some(hasher.uuid(Uuid::from_bytes(data.try_into()?)))),
-                Data::Integer(_) => Ok(None),
+                Data::Integer(_) | Data::Null => Ok(None),
             },
 
             DataType::Vector => Ok(None),
             DataType::Varchar => match self.data {
                 Data::Binary(b) => Ok(Some(hasher.varchar(b))),
                 Data::Text(s) => Ok(Some(hasher.varchar(s.as_bytes()))),
-                Data::Integer(_) => Ok(None),
+                Data::Integer(_) | Data::Null => Ok(None),
             },
         }
     }
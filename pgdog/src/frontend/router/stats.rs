@@ -0,0 +1,172 @@
+//! Router errors, counted by user, database and class.
+
+use std::collections::HashMap;
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+
+use super::parser::Error as ParserError;
+use super::Error;
+use crate::backend::databases::User;
+
+static STATS: Lazy<RouterStats> = Lazy::new(RouterStats::default);
+
+/// Router error class.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorClass {
+    /// The query isn't valid SQL.
+    Parse,
+    /// The query is empty.
+    EmptyQuery,
+    /// The query doesn't filter by the tenant id.
+    MultiTenant,
+    /// The query would run on multiple shards, which isn't allowed.
+    CrossShard,
+    /// Anything else.
+    Other,
+}
+
+impl ErrorClass {
+    /// All error classes, by name.
+    pub const CLASSES: [&'static str; 5] = [
+        "parse",
+        "empty_query",
+        "multi_tenant",
+        "cross_shard",
+        "other",
+    ];
+
+    /// Classify a router error.
+    pub fn new(err: &Error) -> Self {
+        match err {
+            Error::Parser(ParserError::PgQuery(_) | ParserError::ParserPanic(_)) => Self::Parse,
+            Error::Parser(ParserError::EmptyQuery) => Self::EmptyQuery,
+            Error::Parser(ParserError::MultiTenantId) => Self::MultiTenant,
+            Error::Parser(
+                ParserError::CrossShardCopy
+                | ParserError::CrossShardJoin(..)
+                | ParserError::CrossShardInsertSelect
+                | ParserError::InsertSelectShardingKey(_),
+            ) => Self::CrossShard,
+            _ => Self::Other,
+        }
+    }
+
+    fn index(&self) -> usize {
+        match self {
+            Self::Parse => 0,
+            Self::EmptyQuery => 1,
+            Self::MultiTenant => 2,
+            Self::CrossShard => 3,
+            Self::Other => 4,
+        }
+    }
+
+    /// Error class name.
+    pub fn name(&self) -> &'static str {
+        Self::CLASSES[self.index()]
+    }
+}
+
+/// Router errors of one user and database, by class.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RouterErrors([usize; 5]);
+
+impl RouterErrors {
+    /// Number of errors of this class.
+    pub fn get(&self, class: ErrorClass) -> usize {
+        self.0[class.index()]
+    }
+
+    /// Number of errors, by class name.
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, usize)> {
+        ErrorClass::CLASSES.into_iter().zip(self.0)
+    }
+}
+
+/// Router error counters.
+#[derive(Debug, Default)]
+pub struct RouterStats {
+    errors: Mutex<HashMap<User, RouterErrors>>,
+}
+
+impl RouterStats {
+    /// Get global counters.
+    pub fn get() -> &'static RouterStats {
+        &STATS
+    }
+
+    /// Count an error returned to a client of this user and database.
+    pub fn error(&self, user: &str, database: &str, class: ErrorClass) {
+        let mut guard = self.errors.lock();
+        let key = User {
+            user: user.to_string(),
+            database: database.to_string(),
+        };
+        guard.entry(key).or_default().0[class.index()] += 1;
+    }
+
+    /// Errors of this user and database.
+    pub fn errors(&self, user: &str, database: &str) -> RouterErrors {
+        let key = User {
+            user: user.to_string(),
+            database: database.to_string(),
+        };
+        self.errors.lock().get(&key).copied().unwrap_or_default()
+    }
+
+    /// Errors of all users and databases.
+    pub fn all(&self) -> Vec<(User, RouterErrors)> {
+        self.errors
+            .lock()
+            .iter()
+            .map(|(user, errors)| (user.clone(), *errors))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_router_errors() {
+        let stats = RouterStats::default();
+        for err in [
+            Error::Parser(ParserError::PgQuery(pg_query::Error::Parse(
+                "syntax error".into(),
+            ))),
+            Error::Parser(ParserError::PgQuery(pg_query::Error::Parse(
+                "syntax error".into(),
+            ))),
+            Error::Parser(ParserError::EmptyQuery),
+            Error::Parser(ParserError::MultiTenantId),
+            Error::Parser(ParserError::CrossShardJoin("a".into(), "b".into())),
+            Error::NullBytes,
+        ] {
+            stats.error("alice", "app", ErrorClass::new(&err));
+        }
+        stats.error("bob", "app", ErrorClass::CrossShard);
+
+        let errors = stats.errors("alice", "app");
+        assert_eq!(errors.get(ErrorClass::Parse), 2);
+        assert_eq!(errors.get(ErrorClass::EmptyQuery), 1);
+        assert_eq!(errors.get(ErrorClass::MultiTenant), 1);
+        assert_eq!(errors.get(ErrorClass::CrossShard), 1);
+        assert_eq!(errors.get(ErrorClass::Other), 1);
+        assert_eq!(
+            errors.iter().collect::<Vec<_>>(),
+            [
+                ("parse", 2),
+                ("empty_query", 1),
+                ("multi_tenant", 1),
+                ("cross_shard", 1),
+                ("other", 1)
+            ]
+        );
+
+        assert_eq!(stats.errors("bob", "app").get(ErrorClass::CrossShard), 1);
+        assert_eq!(stats.errors("bob", "other"), RouterErrors::default());
+        assert_eq!(stats.all().len(), 2);
+    }
+}
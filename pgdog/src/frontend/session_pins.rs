@@ -0,0 +1,185 @@
+//! Shards used by session mode clients, remembered after they disconnect.
+//!
+//! A client that sets `pgdog.session_tag` and reconnects with the same tag,
+//! user, database and application_name is sent to the shard it used before,
+//! e.g. where it created its temporary tables.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+
+use crate::net::Parameters;
+
+/// Session parameter clients use to tag their sessions.
+pub const SESSION_TAG: &str = "pgdog.session_tag";
+
+/// Maximum number of remembered sessions.
+pub const MAX_SESSION_PINS: usize = 10_000;
+
+static PINS: Lazy<SessionPins> = Lazy::new(SessionPins::default);
+
+/// Identifies a session across reconnects.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SessionKey {
+    pub user: String,
+    pub database: String,
+    pub application_name: String,
+    pub tag: String,
+}
+
+impl SessionKey {
+    /// Get the session key from client parameters.
+    /// Returns `None` if the client didn't tag the session.
+    pub fn from_params(params: &Parameters) -> Option<Self> {
+        let tag = params.get(SESSION_TAG)?.as_str()?;
+        if tag.is_empty() {
+            return None;
+        }
+        let user = params.get_default("user", "postgres");
+
+        Some(Self {
+            user: user.to_owned(),
+            database: params.get_default("database", user).to_owned(),
+            application_name: params.get_default("application_name", "").to_owned(),
+            tag: tag.to_owned(),
+        })
+    }
+}
+
+/// Shard a session was pinned to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SessionPin {
+    pub shard: usize,
+    pub expires_at: Instant,
+}
+
+/// Sessions and the shards they were pinned to.
+#[derive(Debug, Default)]
+pub struct SessionPins {
+    pins: Mutex<HashMap<SessionKey, SessionPin>>,
+}
+
+impl SessionPins {
+    /// Get global session pins.
+    pub fn global() -> &'static SessionPins {
+        &PINS
+    }
+
+    /// Remember the shard used by a session for `ttl`.
+    pub fn remember(&self, key: SessionKey, shard: usize, ttl: Duration) {
+        let now = Instant::now();
+        let expires_at = now
+            .checked_add(ttl)
+            .unwrap_or(now + Duration::from_secs(86_400 * 365));
+        let mut pins = self.pins.lock();
+
+        if pins.len() >= MAX_SESSION_PINS && !pins.contains_key(&key) {
+            pins.retain(|_, pin| pin.expires_at > now);
+
+            // Still full, forget the session closest to expiring.
+            if pins.len() >= MAX_SESSION_PINS {
+                let oldest = pins
+                    .iter()
+                    .min_by_key(|(_, pin)| pin.expires_at)
+                    .map(|(key, _)| key.clone());
+                if let Some(oldest) = oldest {
+                    pins.remove(&oldest);
+                }
+            }
+        }
+
+        pins.insert(key, SessionPin { shard, expires_at });
+    }
+
+    /// Get the shard a session was pinned to, if it hasn't expired.
+    pub fn get(&self, key: &SessionKey) -> Option<usize> {
+        let mut pins = self.pins.lock();
+        match pins.get(key) {
+            Some(pin) if pin.expires_at > Instant::now() => Some(pin.shard),
+            Some(_) => {
+                pins.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Sessions that haven't expired.
+    pub fn pins(&self) -> Vec<(SessionKey, SessionPin)> {
+        let now = Instant::now();
+        let mut pins = self.pins.lock();
+        pins.retain(|_, pin| pin.expires_at > now);
+
+        pins.iter().map(|(key, pin)| (key.clone(), *pin)).collect()
+    }
+
+    /// Number of remembered sessions, including expired ones.
+    pub fn len(&self) -> usize {
+        self.pins.lock().len()
+    }
+
+    /// No sessions are remembered.
+    pub fn is_empty(&self) -> bool {
+        self.pins.lock().is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn key(tag: &str) -> SessionKey {
+        SessionKey {
+            user: "pgdog".into(),
+            database: "pgdog".into(),
+            application_name: "".into(),
+            tag: tag.into(),
+        }
+    }
+
+    #[test]
+    fn test_session_key() {
+        let mut params = Parameters::default();
+        params.insert("user", "alice");
+        params.insert("database", "analytics");
+        assert!(SessionKey::from_params(&params).is_none());
+
+        params.insert(SESSION_TAG, "report");
+        let key = SessionKey::from_params(&params).unwrap();
+        assert_eq!(key.user, "alice");
+        assert_eq!(key.database, "analytics");
+        assert_eq!(key.application_name, "");
+        assert_eq!(key.tag, "report");
+    }
+
+    #[test]
+    fn test_remember_and_expire() {
+        let pins = SessionPins::default();
+        pins.remember(key("a"), 1, Duration::from_secs(60));
+        pins.remember(key("b"), 2, Duration::ZERO);
+
+        assert_eq!(pins.get(&key("a")), Some(1));
+        assert_eq!(pins.get(&key("b")), None);
+        assert_eq!(pins.get(&key("c")), None);
+        assert_eq!(pins.len(), 1);
+
+        pins.remember(key("a"), 3, Duration::from_secs(60));
+        assert_eq!(pins.get(&key("a")), Some(3));
+        assert_eq!(pins.pins().len(), 1);
+    }
+
+    #[test]
+    fn test_bounded() {
+        let pins = SessionPins::default();
+        for i in 0..MAX_SESSION_PINS + 5 {
+            pins.remember(key(&i.to_string()), 0, Duration::from_secs(60 + i as u64));
+        }
+
+        assert_eq!(pins.len(), MAX_SESSION_PINS);
+        // Sessions closest to expiring were forgotten first.
+        assert_eq!(pins.get(&key("0")), None);
+        assert_eq!(pins.get(&key(&(MAX_SESSION_PINS + 4).to_string())), Some(0));
+    }
+}
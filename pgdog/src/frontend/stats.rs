@@ -3,11 +3,13 @@
 use std::time::{Duration, SystemTime};
 use tokio::time::Instant;
 
+use crate::backend::replication::publisher::Lsn;
+use crate::net::messages::replication::StatusUpdate;
 use crate::state::State;
 
-/// Client statistics.
-#[derive(Copy, Clone, Debug)]
-pub struct Stats {
+/// Counters since the client connected. They only go up.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Totals {
     /// Bytes sent over network.
     pub bytes_sent: usize,
     /// Bytes received over network.
@@ -16,21 +18,40 @@ pub struct Stats {
     pub transactions: usize,
     /// Queries served.
     pub queries: usize,
-    /// Errors.
+    /// Errors returned to the client.
     pub errors: usize,
     /// Total transaction time.
     pub transaction_time: Duration,
-    /// Last transaction time.
-    pub last_transaction_time: Duration,
     /// Total query time.
     pub query_time: Duration,
-    /// Total wait time.
+    /// Total time spent waiting for a server connection.
     pub wait_time: Duration,
+}
+
+impl std::fmt::Display for Totals {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "queries: {}, transactions: {}, errors: {}, bytes received: {}, bytes sent: {}, wait: {:.3}ms",
+            self.queries,
+            self.transactions,
+            self.errors,
+            self.bytes_received,
+            self.bytes_sent,
+            self.wait_time.as_secs_f64() * 1000.0
+        )
+    }
+}
+
+/// What the client is doing right now.
+#[derive(Copy, Clone, Debug)]
+pub struct Current {
     /// Current client state.
     pub state: State,
-    transaction_timer: Instant,
-    query_timer: Instant,
-    wait_timer: Instant,
+    /// Last transaction time.
+    pub last_transaction_time: Duration,
+    /// How long the last checkout waited for a server connection.
+    pub last_wait_time: Duration,
     /// Last time this client sent a query.
     pub last_request: SystemTime,
     /// Number of bytes used by the stream buffer, where all the messages
@@ -40,6 +61,23 @@ pub struct Stats {
     pub prepared_statements: usize,
     /// Client is locked to a particular server.
     pub locked: bool,
+    /// Last WAL position flushed by a replication client.
+    pub confirmed_lsn: Option<Lsn>,
+}
+
+/// Client statistics.
+///
+/// Updated locally by the client and published to [`super::Comms`]
+/// when its state changes.
+#[derive(Copy, Clone, Debug)]
+pub struct Stats {
+    /// Counters since the client connected.
+    pub totals: Totals,
+    /// Current state.
+    pub current: Current,
+    transaction_timer: Instant,
+    query_timer: Instant,
+    wait_timer: Instant,
 }
 
 impl Default for Stats {
@@ -52,104 +90,112 @@ impl Stats {
     pub(super) fn new() -> Self {
         let now = Instant::now();
         Self {
-            bytes_sent: 0,
-            bytes_received: 0,
-            transactions: 0,
-            queries: 0,
-            errors: 0,
-            transaction_time: Duration::from_secs(0),
-            last_transaction_time: Duration::from_secs(0),
-            query_time: Duration::from_secs(0),
-            wait_time: Duration::from_secs(0),
-            state: State::Idle,
+            totals: Totals::default(),
+            current: Current {
+                state: State::Idle,
+                last_transaction_time: Duration::from_secs(0),
+                last_wait_time: Duration::from_secs(0),
+                last_request: SystemTime::now(),
+                memory_used: 0,
+                prepared_statements: 0,
+                locked: false,
+                confirmed_lsn: None,
+            },
             transaction_timer: now,
             query_timer: now,
             wait_timer: now,
-            last_request: SystemTime::now(),
-            memory_used: 0,
-            prepared_statements: 0,
-            locked: false,
         }
     }
 
     pub(super) fn transaction(&mut self) {
-        self.last_transaction_time = self.transaction_timer.elapsed();
-        self.transactions += 1;
-        self.transaction_time += self.last_transaction_time;
-        self.state = State::Idle;
+        self.current.last_transaction_time = self.transaction_timer.elapsed();
+        self.totals.transactions += 1;
+        self.totals.transaction_time += self.current.last_transaction_time;
+        self.current.state = State::Idle;
     }
 
-    pub(super) fn error(&mut self) {
-        self.errors += 1;
-        self.state = State::Idle;
+    /// Errors returned to the client.
+    pub(super) fn errors(&mut self, errors: usize) {
+        self.totals.errors += errors;
     }
 
     pub(super) fn query(&mut self) {
         let now = Instant::now();
-        self.queries += 1;
-        self.query_time += now.duration_since(self.query_timer);
+        self.totals.queries += 1;
+        self.totals.query_time += now.duration_since(self.query_timer);
         self.query_timer = now;
     }
 
     pub(super) fn waiting(&mut self, instant: Instant) {
-        self.state = State::Waiting;
+        self.current.state = State::Waiting;
         self.wait_timer = instant;
     }
 
     /// Get wait time if waiting.
     pub fn wait_time(&self) -> Duration {
-        if self.state == State::Waiting {
+        if self.current.state == State::Waiting {
             self.wait_timer.elapsed()
         } else {
             Duration::from_secs(0)
         }
     }
 
+    /// Checkout finished, with or without a connection.
+    pub(super) fn checked_out(&mut self) {
+        let now = Instant::now();
+        self.current.last_wait_time = now.duration_since(self.wait_timer);
+        self.totals.wait_time += self.current.last_wait_time;
+    }
+
     pub(super) fn connected(&mut self) {
         let now = Instant::now();
-        self.state = State::Active;
+        self.current.state = State::Active;
         self.transaction_timer = now;
         self.query_timer = now;
-        self.wait_time = now.duration_since(self.wait_timer);
     }
 
     pub(super) fn locked(&mut self, lock: bool) {
-        self.locked = lock;
+        self.current.locked = lock;
     }
 
     pub(super) fn sent(&mut self, bytes: usize) {
-        self.bytes_sent += bytes;
+        self.totals.bytes_sent += bytes;
     }
 
     pub(super) fn memory_used(&mut self, memory: usize) {
-        self.memory_used = memory;
+        self.current.memory_used = memory;
     }
 
     pub(super) fn idle(&mut self, in_transaction: bool) {
         if in_transaction {
-            self.state = State::IdleInTransaction;
+            self.current.state = State::IdleInTransaction;
         } else {
-            self.state = State::Idle;
+            self.current.state = State::Idle;
         }
     }
 
     pub(super) fn received(&mut self, bytes: usize) {
-        self.bytes_received += bytes;
+        self.totals.bytes_received += bytes;
         // In session mode, we stay connected to the server
         // until client disconnects, so we need to reset timers every time
         // client is activated from idle state.
-        if self.state == State::Idle {
+        if self.current.state == State::Idle {
             let now = Instant::now();
             self.transaction_timer = now;
             self.query_timer = now;
-            self.last_request = SystemTime::now();
+            self.current.last_request = SystemTime::now();
         }
 
-        self.state = State::Active;
+        self.current.state = State::Active;
+    }
+
+    /// Replication client reported its position.
+    pub(super) fn status_update(&mut self, status_update: &StatusUpdate) {
+        self.current.confirmed_lsn = Some(Lsn::from_i64(status_update.last_flushed));
     }
 
     /// Number of prepared statements currently in the cache.
     pub(super) fn prepared_statements(&mut self, prepared: usize) {
-        self.prepared_statements = prepared;
+        self.current.prepared_statements = prepared;
     }
 }
@@ -1,6 +1,7 @@
 pub mod admin;
 pub mod auth;
 pub mod backend;
+pub mod bench;
 pub mod cli;
 pub mod config;
 pub mod frontend;
@@ -9,6 +10,7 @@ pub mod plugin;
 pub mod sighup;
 pub mod state;
 pub mod stats;
+pub mod telemetry;
 #[cfg(feature = "tui")]
 pub mod tui;
 pub mod util;
@@ -35,8 +37,9 @@ pub fn logger() {
         .with_default_directive(LevelFilter::INFO.into())
         .from_env_lossy();
 
-    let _ = tracing_subscriber::registry()
-        .with(format)
-        .with(filter)
-        .try_init();
+    let registry = tracing_subscriber::registry();
+    #[cfg(feature = "telemetry")]
+    let registry = registry.with(telemetry::layer());
+
+    let _ = registry.with(format.with_filter(filter)).try_init();
 }
@@ -1,14 +1,16 @@
 //! pgDog, modern PostgreSQL proxy, pooler and query router.
 
 use clap::Parser;
-use pgdog::backend::databases;
+use pgdog::admin;
 use pgdog::backend::pool::dns_cache::DnsCache;
+use pgdog::backend::{databases, users_watch};
 use pgdog::cli::{self, Commands};
 use pgdog::config::{self, config};
 use pgdog::frontend::listener::Listener;
 use pgdog::net;
 use pgdog::plugin;
 use pgdog::stats;
+use pgdog::telemetry;
 use tokio::runtime::Builder;
 use tracing::info;
 
@@ -18,10 +20,15 @@ use std::process::exit;
 #[cfg(not(target_env = "msvc"))]
 use tikv_jemallocator::Jemalloc;
 
-#[cfg(not(target_env = "msvc"))]
+#[cfg(all(not(target_env = "msvc"), not(feature = "bench")))]
 #[global_allocator]
 static GLOBAL: Jemalloc = Jemalloc;
 
+#[cfg(all(not(target_env = "msvc"), feature = "bench"))]
+#[global_allocator]
+static GLOBAL: pgdog::bench::alloc::Counting<Jemalloc> =
+    pgdog::bench::alloc::Counting::new(Jemalloc);
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args = cli::Cli::parse();
 
@@ -30,8 +37,11 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut overrides = pgdog::config::Overrides::default();
 
     match args.command {
-        Some(Commands::Fingerprint { query, path }) => {
-            pgdog::cli::fingerprint(query, path)?;
+        Some(command @ Commands::Fingerprint { .. }) => {
+            if let Err(e) = pgdog::cli::fingerprint(command) {
+                eprintln!("{}", e);
+                exit(1);
+            }
             exit(0);
         }
 
@@ -45,6 +55,37 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             exit(0);
         }
 
+        Some(Commands::ShowPools {
+            config,
+            users,
+            pool_size,
+            min_pool_size,
+            session_mode,
+            format,
+        }) => {
+            let config = config.unwrap_or(args.config);
+            let users = users.unwrap_or(args.users);
+            let config = match config::ConfigAndUsers::load(&config, &users) {
+                Ok(config) => config,
+                Err(e) => {
+                    eprintln!("Configuration error: {}", e);
+                    exit(1);
+                }
+            };
+            let overrides = pgdog::config::Overrides {
+                min_pool_size,
+                session_mode,
+                default_pool_size: pool_size,
+            };
+
+            let pools = cli::pool_settings(&config, &overrides);
+            if let Err(e) = cli::show_pools(&pools, format, std::io::stdout()) {
+                eprintln!("{}", e);
+                exit(1);
+            }
+            exit(0);
+        }
+
         Some(Commands::Run {
             pool_size,
             min_pool_size,
@@ -69,7 +110,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     // Set database from --database-url arg.
     let config = if let Some(database_urls) = args.database_url {
-        config::from_urls(&database_urls)?
+        config::from_urls(&database_urls, args.database_name.as_deref())?
     } else {
         config
     };
@@ -105,6 +146,9 @@ async fn pgdog(command: Option<Commands>) -> Result<(), Box<dyn std::error::Erro
 
     // Load databases and connect if needed.
     databases::init();
+    databases::validate_sharding_schema().await?;
+
+    telemetry::init(&config::config().config.telemetry)?;
 
     let general = &config::config().config.general;
 
@@ -116,11 +160,18 @@ async fn pgdog(command: Option<Commands>) -> Result<(), Box<dyn std::error::Erro
         tokio::spawn(async move { stats::http_server::server(openmetrics_port).await });
     }
 
+    if let Some(admin_http_port) = general.admin_http_port {
+        tokio::spawn(async move { admin::http::server(admin_http_port).await });
+    }
+
     let dns_cache_override_enabled = general.dns_ttl().is_some();
     if dns_cache_override_enabled {
         DnsCache::global().start_refresh_loop();
     }
 
+    users_watch::start();
+    databases::evict_idle();
+
     let stats_logger = stats::StatsLogger::new();
 
     if general.dry_run {
@@ -147,6 +198,15 @@ async fn pgdog(command: Option<Commands>) -> Result<(), Box<dyn std::error::Erro
                 info!("🔄 entering schema sync mode");
                 cli::schema_sync(command.clone()).await?;
             }
+
+            if let Commands::Resharding { .. } = command {
+                info!("🔄 entering resharding mode");
+                cli::resharding(command.clone()).await?;
+            }
+
+            if let Commands::Bench { .. } = command {
+                cli::bench(command.clone()).await?;
+            }
         }
     }
 
@@ -155,6 +215,7 @@ async fn pgdog(command: Option<Commands>) -> Result<(), Box<dyn std::error::Erro
 
     // Any shutdown routines go below.
     plugin::shutdown();
+    telemetry::shutdown();
 
     Ok(())
 }
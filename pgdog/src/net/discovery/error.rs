@@ -10,4 +10,10 @@ pub enum Error {
 
     #[error("{0}")]
     Io(#[from] tokio::io::Error),
+
+    #[error("unsupported message version {0}")]
+    UnsupportedVersion(u8),
+
+    #[error("message is truncated")]
+    Truncated,
 }
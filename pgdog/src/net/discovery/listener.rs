@@ -16,6 +16,12 @@ use tokio::{select, spawn};
 
 use super::{Error, Message, Payload};
 
+/// How often we broadcast our state.
+const INTERVAL: Duration = Duration::from_secs(1);
+
+/// Forget peers we haven't heard from in this long.
+const PEER_TIMEOUT: Duration = Duration::from_secs(30);
+
 /// Service discovery listener.
 #[derive(Clone, Debug)]
 pub struct Listener {
@@ -25,8 +31,18 @@ pub struct Listener {
 
 #[derive(Debug, Clone)]
 pub struct State {
+    /// Peer's unique identifier.
+    pub node_id: u64,
     /// Number of connected clients.
     pub clients: u64,
+    /// Peer's `cluster_name`.
+    pub cluster_name: Option<String>,
+    /// PgDog version.
+    pub version: String,
+    /// Databases served by the peer.
+    pub databases: Vec<String>,
+    /// Port clients connect to.
+    pub port: u16,
     /// When we received the last state update.
     pub last_message: SystemTime,
 }
@@ -76,37 +92,117 @@ impl Listener {
         socket.join_multicast_v4(address, "0.0.0.0".parse::<Ipv4Addr>().unwrap())?;
         socket.multicast_loop_v4()?; // Won't work on IPv6, but nice for debugging.
 
-        let mut buf = vec![0u8; 1024];
-        let mut interval = interval(Duration::from_secs(1));
+        self.serve(socket, SocketAddr::from((address, port))).await
+    }
+
+    /// Receive messages from peers on `socket` and send ours to `target`.
+    async fn serve(&self, socket: UdpSocket, target: SocketAddr) -> Result<Self, Error> {
+        let mut buf = vec![0u8; 4096];
+        let mut interval = interval(INTERVAL);
 
         loop {
             select! {
                 result = socket.recv_from(&mut buf) => {
                     let (len, addr) = result?;
-                    let message = Message::from_bytes(&buf[..len]).ok();
                     let now = SystemTime::now();
 
-                    if let Some(message) = message {
-                        debug!("{}: {:#?}", addr, message);
-
-                        if let Payload::Stats {
-                                clients
-                            } = message.payload {
-                            self.inner.lock().peers.insert(addr, State {
-                                clients,
-                                last_message: now,
-                            });
+                    // Messages from versions we don't understand are ignored.
+                    let message = match Message::from_bytes(&buf[..len]) {
+                        Ok(message) => message,
+                        Err(err) => {
+                            debug!("{}: ignoring message: {}", addr, err);
+                            continue;
                         }
+                    };
+
+                    // Our own message, looped back.
+                    if message.node_id == self.id {
+                        continue;
+                    }
 
+                    debug!("{}: {:#?}", addr, message);
+
+                    if let Payload::Stats {
+                        clients,
+                        cluster_name,
+                        version,
+                        databases,
+                        port,
+                    } = message.payload
+                    {
+                        self.inner.lock().peers.insert(addr, State {
+                            node_id: message.node_id,
+                            clients,
+                            cluster_name,
+                            version,
+                            databases,
+                            port,
+                            last_message: now,
+                        });
                     }
                 }
 
                 _ = interval.tick() => {
+                    self.expire(SystemTime::now());
+
                     let healthcheck = Message::stats(self.id).to_bytes()?;
-                    socket.send_to(&healthcheck, format!("{}:{}", address, port)).await?;
+                    socket.send_to(&healthcheck, target).await?;
                     debug!("healtcheck");
                 }
             }
         }
     }
+
+    /// Forget peers that stopped sending updates.
+    fn expire(&self, now: SystemTime) {
+        self.inner.lock().peers.retain(|_, state| {
+            now.duration_since(state.last_message)
+                .map(|elapsed| elapsed < PEER_TIMEOUT)
+                .unwrap_or(true)
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tokio::time::sleep;
+
+    use crate::config::{self, config};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_peers() {
+        let mut cfg = (*config()).clone();
+        cfg.config.general.cluster_name = Some("test_cluster".into());
+        config::set(cfg).unwrap();
+
+        let first = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let second = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let (first_addr, second_addr) = (first.local_addr().unwrap(), second.local_addr().unwrap());
+
+        let (a, b) = (Listener::new(), Listener::new());
+        for (listener, socket, target) in [
+            (a.clone(), first, second_addr),
+            (b.clone(), second, first_addr),
+        ] {
+            spawn(async move { listener.serve(socket, target).await });
+        }
+
+        sleep(Duration::from_millis(250)).await;
+
+        for (listener, peer, addr) in [(&a, &b, second_addr), (&b, &a, first_addr)] {
+            let peers = listener.peers();
+            assert_eq!(peers.len(), 1);
+            let state = peers.get(&addr).unwrap();
+            assert_eq!(state.node_id, peer.id);
+            assert_eq!(state.cluster_name.as_deref(), Some("test_cluster"));
+            assert_eq!(state.version, env!("CARGO_PKG_VERSION"));
+            assert_eq!(state.port, config().config.general.port);
+        }
+
+        // Peers that stopped talking are forgotten.
+        a.expire(SystemTime::now() + PEER_TIMEOUT);
+        assert!(a.peers().is_empty());
+    }
 }
@@ -1,17 +1,43 @@
-use rmp_serde::{decode, encode, Deserializer, Serializer};
+use std::collections::BTreeSet;
+
+use rmp_serde::{Deserializer, Serializer};
 use serde::{Deserialize, Serialize};
 
+use crate::backend::databases::databases;
+use crate::config::config;
 use crate::frontend::comms::comms;
 
+use super::Error;
+
+/// Version of the message encoding.
+///
+/// Messages are encoded as the version (1 byte), the length
+/// of the body (4 bytes, big endian) and the body (MessagePack).
+pub const VERSION: u8 = 2;
+
+/// Size of the version and length prefix.
+const HEADER: usize = 5;
+
 /// Message kind.
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub enum Payload {
     Healthcheck,
-    Stats { clients: u64 },
+    Stats {
+        /// Number of connected clients.
+        clients: u64,
+        /// Configured `cluster_name`.
+        cluster_name: Option<String>,
+        /// PgDog version.
+        version: String,
+        /// Databases served to clients.
+        databases: Vec<String>,
+        /// Port clients connect to.
+        port: u16,
+    },
 }
 
 /// Message sent via UDP.
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Message {
     pub node_id: u64,
     pub payload: Payload,
@@ -19,15 +45,33 @@ pub struct Message {
 
 impl Message {
     /// Convert message to bytes.
-    pub fn to_bytes(&self) -> Result<Vec<u8>, encode::Error> {
-        let mut buf = vec![];
-        self.serialize(&mut Serializer::new(&mut buf))?;
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        let mut body = vec![];
+        self.serialize(&mut Serializer::new(&mut body))?;
+
+        let mut buf = Vec::with_capacity(HEADER + body.len());
+        buf.push(VERSION);
+        buf.extend((body.len() as u32).to_be_bytes());
+        buf.extend(body);
+
         Ok(buf)
     }
 
     /// Convert bytes to message.
-    pub fn from_bytes(buf: &[u8]) -> Result<Self, decode::Error> {
-        Message::deserialize(&mut Deserializer::new(buf))
+    pub fn from_bytes(buf: &[u8]) -> Result<Self, Error> {
+        let (&version, rest) = buf.split_first().ok_or(Error::Truncated)?;
+        if version != VERSION {
+            return Err(Error::UnsupportedVersion(version));
+        }
+
+        let len = rest
+            .get(..4)
+            .and_then(|len| len.try_into().ok())
+            .map(u32::from_be_bytes)
+            .ok_or(Error::Truncated)? as usize;
+        let body = buf.get(HEADER..HEADER + len).ok_or(Error::Truncated)?;
+
+        Ok(Message::deserialize(&mut Deserializer::new(body))?)
     }
 
     /// Healthcheck message.
@@ -41,10 +85,75 @@ impl Message {
     /// Collect statistics.
     pub fn stats(node_id: u64) -> Self {
         let clients = comms().len() as u64;
+        let config = config();
+        let databases = databases()
+            .all()
+            .keys()
+            .map(|user| user.database.clone())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
 
         Self {
             node_id,
-            payload: Payload::Stats { clients },
+            payload: Payload::Stats {
+                clients,
+                cluster_name: config.config.general.cluster_name.clone(),
+                version: env!("CARGO_PKG_VERSION").to_string(),
+                databases,
+                port: config.config.general.port,
+            },
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn message() -> Message {
+        Message {
+            node_id: 1234,
+            payload: Payload::Stats {
+                clients: 5,
+                cluster_name: Some("east".into()),
+                version: "0.1.0".into(),
+                databases: vec!["pgdog".into()],
+                port: 6432,
+            },
+        }
+    }
+
+    #[test]
+    fn test_encoding() {
+        let bytes = message().to_bytes().unwrap();
+        assert_eq!(bytes[0], VERSION);
+        assert_eq!(
+            u32::from_be_bytes(bytes[1..5].try_into().unwrap()) as usize,
+            bytes.len() - HEADER
+        );
+        assert_eq!(Message::from_bytes(&bytes).unwrap(), message());
+
+        // Trailing bytes are ignored.
+        let mut padded = bytes.clone();
+        padded.extend([0, 0, 0]);
+        assert_eq!(Message::from_bytes(&padded).unwrap(), message());
+    }
+
+    #[test]
+    fn test_unsupported() {
+        let mut bytes = message().to_bytes().unwrap();
+
+        assert!(matches!(
+            Message::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(Error::Truncated)
+        ));
+        assert!(matches!(Message::from_bytes(&[]), Err(Error::Truncated)));
+
+        bytes[0] = VERSION + 1;
+        assert!(matches!(
+            Message::from_bytes(&bytes),
+            Err(Error::UnsupportedVersion(v)) if v == VERSION + 1
+        ));
+    }
+}
@@ -1,5 +1,6 @@
 //! Bind (F) message.
 use crate::net::c_string_buf_len;
+use crate::stats::memory::MemoryUsage;
 use uuid::Uuid;
 
 use super::code;
@@ -130,6 +131,30 @@ pub struct Bind {
     original: Option<Bytes>,
 }
 
+impl MemoryUsage for Bind {
+    /// Portal and statement names, and the original payload,
+    /// are counted by their length.
+    #[inline]
+    fn memory_usage(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + self.portal.len()
+            + self.statement.len()
+            + self.codes.capacity() * std::mem::size_of::<Format>()
+            + self.params.capacity() * std::mem::size_of::<Parameter>()
+            + self
+                .params
+                .iter()
+                .map(|param| param.data.capacity())
+                .sum::<usize>()
+            + self.results.capacity() * std::mem::size_of::<i16>()
+            + self
+                .original
+                .as_ref()
+                .map(|original| original.len())
+                .unwrap_or(0)
+    }
+}
+
 impl Default for Bind {
     fn default() -> Self {
         Bind {
@@ -183,6 +208,28 @@ impl Bind {
         self
     }
 
+    /// Bind some of the parameters to another prepared statement.
+    /// `params` are the positions of the parameters in this message, in their new order.
+    pub fn select(&self, name: &str, params: &[usize]) -> Self {
+        let codes = if self.codes.len() == self.params.len() {
+            params.iter().map(|param| self.codes[*param]).collect()
+        } else {
+            self.codes.clone()
+        };
+
+        Self {
+            portal: self.portal.clone(),
+            statement: Bytes::from(name.to_string() + "\0"),
+            codes,
+            params: params
+                .iter()
+                .map(|param| self.params[*param].clone())
+                .collect(),
+            results: self.results.clone(),
+            original: None,
+        }
+    }
+
     /// Is this Bind message anonymous?
     pub fn anonymous(&self) -> bool {
         self.statement.len() == 1
@@ -390,6 +437,35 @@ mod test {
         assert!(anon.anonymous());
     }
 
+    #[test]
+    fn test_bind_select() {
+        let params = ["1", "2", "3"].map(|param| Parameter {
+            len: 1,
+            data: param.as_bytes().to_vec(),
+        });
+        let bind = Bind::new_params_codes_results(
+            "test",
+            &params,
+            &[Format::Text, Format::Binary, Format::Text],
+            &[1],
+        );
+
+        let selected = bind.select("other", &[2, 1]);
+        assert_eq!(selected.statement(), "other");
+        assert_eq!(
+            selected.params_raw(),
+            &vec![params[2].clone(), params[1].clone()]
+        );
+        assert_eq!(selected.codes(), &[Format::Text, Format::Binary]);
+        let from_bytes = Bind::from_bytes(selected.to_bytes().unwrap()).unwrap();
+        assert_eq!(from_bytes.params_raw(), selected.params_raw());
+        assert_eq!(from_bytes.results, vec![1]);
+
+        // One format code applies to all parameters.
+        let bind = Bind::new_params_codes("test", &params, &[Format::Binary]);
+        assert_eq!(bind.select("", &[0]).codes(), &[Format::Binary]);
+    }
+
     #[tokio::test]
     async fn test_jsonb() {
         let mut server = test_server().await;
@@ -1,4 +1,5 @@
 use std::fmt::Display;
+use std::hash::{Hash, Hasher};
 
 use super::*;
 
@@ -9,7 +10,9 @@ use chrono::{Datelike, NaiveDate, NaiveDateTime, NaiveTime, Timelike};
 // PostgreSQL epoch is 2000-01-01 00:00:00 UTC, which is 946684800 seconds after Unix epoch
 const POSTGRES_EPOCH_MICROS: i64 = 946684800000000; // microseconds
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Hash)]
+/// Timestamps are equal, ordered and hashed by the instant they
+/// represent, so values with different offsets can be compared.
+#[derive(Debug, Copy, Clone, Default)]
 pub struct Timestamp {
     pub year: i64,
     pub month: i8,
@@ -18,11 +21,42 @@ pub struct Timestamp {
     pub minute: i8,
     pub second: i8,
     pub micros: i32,
+    /// UTC offset hours, for `timestamptz` values in text format.
     pub offset: Option<i8>,
+    /// UTC offset minutes, e.g. `30` for `+05:30`. Carries the sign
+    /// when the hours are zero, e.g. `-30` for `-00:30`.
+    pub offset_minutes: i8,
     /// Special value indicator: None for normal values, Some(true) for infinity, Some(false) for -infinity
     pub special: Option<bool>,
 }
 
+impl PartialEq for Timestamp {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == std::cmp::Ordering::Equal
+    }
+}
+
+impl Eq for Timestamp {}
+
+impl Hash for Timestamp {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match self.to_pg_epoch_micros() {
+            Ok(micros) => micros.hash(state),
+            // Invalid dates are compared without the offset.
+            Err(_) => (
+                self.year,
+                self.month,
+                self.day,
+                self.hour,
+                self.minute,
+                self.second,
+                self.micros,
+            )
+                .hash(state),
+        }
+    }
+}
+
 impl PartialOrd for Timestamp {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
         Some(self.cmp(other))
@@ -34,15 +68,20 @@ impl Ord for Timestamp {
         use std::cmp::Ordering;
 
         match (self.special, other.special) {
-            (None, None) => self
-                .year
-                .cmp(&other.year)
-                .then_with(|| self.month.cmp(&other.month))
-                .then_with(|| self.day.cmp(&other.day))
-                .then_with(|| self.hour.cmp(&other.hour))
-                .then_with(|| self.minute.cmp(&other.minute))
-                .then_with(|| self.second.cmp(&other.second))
-                .then_with(|| self.micros.cmp(&other.micros)),
+            // Compare in UTC, so values with different offsets are ordered correctly.
+            (None, None) => match (self.to_pg_epoch_micros(), other.to_pg_epoch_micros()) {
+                (Ok(a), Ok(b)) => a.cmp(&b),
+                _ => self
+                    .year
+                    .cmp(&other.year)
+                    .then_with(|| self.month.cmp(&other.month))
+                    .then_with(|| self.day.cmp(&other.day))
+                    .then_with(|| self.hour.cmp(&other.hour))
+                    .then_with(|| self.minute.cmp(&other.minute))
+                    .then_with(|| self.second.cmp(&other.second))
+                    .then_with(|| self.micros.cmp(&other.micros)),
+            },
+            (Some(a), Some(b)) if a == b => Ordering::Equal,
             (Some(false), _) => Ordering::Less,
             (_, Some(false)) => Ordering::Greater,
             (Some(true), _) => Ordering::Greater,
@@ -65,8 +104,18 @@ impl Display for Timestamp {
             self.year, self.month, self.day, self.hour, self.minute, self.second, self.micros
         )?;
 
+        // Same format Postgres uses, e.g. -08 or +05:30.
         if let Some(offset) = self.offset {
-            write!(f, "{}{}", if offset > 0 { "+" } else { "-" }, offset)?;
+            let negative = offset < 0 || self.offset_minutes < 0;
+            write!(
+                f,
+                "{}{:02}",
+                if negative { "-" } else { "+" },
+                offset.unsigned_abs()
+            )?;
+            if self.offset_minutes != 0 {
+                write!(f, ":{:02}", self.offset_minutes.unsigned_abs())?;
+            }
         }
 
         Ok(())
@@ -100,7 +149,12 @@ impl Timestamp {
         }
     }
 
-    /// Convert to microseconds since PostgreSQL epoch (2000-01-01)
+    /// UTC offset in seconds.
+    pub fn offset_seconds(&self) -> i64 {
+        self.offset.unwrap_or(0) as i64 * 3600 + self.offset_minutes as i64 * 60
+    }
+
+    /// Convert to microseconds since PostgreSQL epoch (2000-01-01) in UTC.
     /// Returns i64::MAX for infinity, i64::MIN for -infinity
     pub fn to_pg_epoch_micros(&self) -> Result<i64, Error> {
         match self.special {
@@ -121,7 +175,9 @@ impl Timestamp {
                 let dt = NaiveDateTime::new(date, time);
 
                 // Get Unix epoch microseconds and subtract PostgreSQL epoch offset
-                Ok(dt.and_utc().timestamp_micros() - POSTGRES_EPOCH_MICROS)
+                Ok(dt.and_utc().timestamp_micros()
+                    - self.offset_seconds() * 1_000_000
+                    - POSTGRES_EPOCH_MICROS)
             }
         }
     }
@@ -156,11 +212,23 @@ impl Timestamp {
             second: time.second() as i8,
             micros: (time.nanosecond() / 1000) as i32,
             offset: None,
+            offset_minutes: 0,
             special: None,
         })
     }
 }
 
+/// Parse fractional seconds. Postgres drops trailing zeros,
+/// so ".5" is 500000 microseconds.
+fn micros(fraction: &str) -> Result<i32, Error> {
+    if fraction.is_empty() || fraction.len() > 6 || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(Error::InvalidTimestamp);
+    }
+
+    let micros: i32 = fraction.parse().map_err(|_| Error::InvalidTimestamp)?;
+    Ok(micros * 10_i32.pow(6 - fraction.len() as u32))
+}
+
 impl FromDataType for Timestamp {
     fn decode(bytes: &[u8], encoding: Format) -> Result<Self, Error> {
         match encoding {
@@ -180,6 +248,12 @@ impl FromDataType for Timestamp {
                 }
 
                 if let Some(time) = time {
+                    // The offset starts with a sign, e.g. 14:51:42.5-08 or 14:51:42+05:30.
+                    let (time, offset) = match time.find(['+', '-']) {
+                        Some(pos) => (&time[..pos], Some(&time[pos..])),
+                        None => (time, None),
+                    };
+
                     let mut parts = time.split(":");
                     assign!(result, hour, parts);
                     assign!(result, minute, parts);
@@ -187,20 +261,33 @@ impl FromDataType for Timestamp {
                     if let Some(seconds) = parts.next() {
                         let mut parts = seconds.split(".");
                         assign!(result, second, parts);
-                        let micros = parts.next();
-                        if let Some(micros) = micros {
-                            let neg = micros.find('-').is_some();
-                            let mut parts = micros.split(&['-', '+']);
-                            assign!(result, micros, parts);
-                            if let Some(offset) = parts.next() {
-                                let offset: i8 = bigint(offset)?
-                                    .try_into()
-                                    .map_err(|_| Error::InvalidTimestamp)?;
-                                let offset = if neg { -offset } else { offset };
-                                result.offset = Some(offset);
-                            }
+                        if let Some(fraction) = parts.next() {
+                            result.micros = micros(fraction)?;
                         }
                     }
+
+                    if let Some(offset) = offset {
+                        let neg = offset.starts_with('-');
+                        let mut parts = offset[1..].split(":");
+                        let hours: i8 = bigint(parts.next().unwrap_or_default())?
+                            .try_into()
+                            .map_err(|_| Error::InvalidTimestamp)?;
+                        let minutes: i8 = match parts.next() {
+                            Some(minutes) => bigint(minutes)?
+                                .try_into()
+                                .map_err(|_| Error::InvalidTimestamp)?,
+                            None => 0,
+                        };
+
+                        // Offsets with seconds are only used for dates before
+                        // standard time zones existed.
+                        if parts.next().is_some() || !(0..60).contains(&minutes) {
+                            return Err(Error::InvalidTimestamp);
+                        }
+
+                        result.offset = Some(if neg { -hours } else { hours });
+                        result.offset_minutes = if neg { -minutes } else { minutes };
+                    }
                 }
 
                 // Validate ranges
@@ -330,6 +417,7 @@ mod test {
             second: 0,
             micros: 0,
             offset: None,
+            offset_minutes: 0,
             special: None,
         };
 
@@ -349,6 +437,7 @@ mod test {
             second: 56,
             micros: 789012,
             offset: None,
+            offset_minutes: 0,
             special: None,
         };
 
@@ -367,6 +456,7 @@ mod test {
             second: 45,
             micros: 123456,
             offset: None,
+            offset_minutes: 0,
             special: None,
         };
 
@@ -393,6 +483,7 @@ mod test {
             second: 0,
             micros: 0,
             offset: None,
+            offset_minutes: 0,
             special: None,
         };
 
@@ -405,6 +496,7 @@ mod test {
             second: 0,
             micros: 0,
             offset: None,
+            offset_minutes: 0,
             special: None,
         };
 
@@ -424,6 +516,7 @@ mod test {
             second: 0,
             micros: 100,
             offset: None,
+            offset_minutes: 0,
             special: None,
         };
 
@@ -436,6 +529,7 @@ mod test {
             second: 0,
             micros: 200,
             offset: None,
+            offset_minutes: 0,
             special: None,
         };
 
@@ -453,6 +547,7 @@ mod test {
             second: 0,
             micros: 0,
             offset: None,
+            offset_minutes: 0,
             special: None,
         };
         let micros = ts.to_pg_epoch_micros().unwrap();
@@ -492,6 +587,7 @@ mod test {
             second: 45,
             micros: 0,
             offset: None,
+            offset_minutes: 0,
             special: None,
         };
 
@@ -508,6 +604,7 @@ mod test {
             second: 45,
             micros: 0,
             offset: None,
+            offset_minutes: 0,
             special: None,
         };
         assert!(invalid_ts2.to_pg_epoch_micros().is_err());
@@ -522,6 +619,7 @@ mod test {
             second: 45,
             micros: 0,
             offset: None,
+            offset_minutes: 0,
             special: None,
         };
         assert!(invalid_ts3.to_pg_epoch_micros().is_err());
@@ -572,6 +670,7 @@ mod test {
             second: 0,
             micros: 0,
             offset: None,
+            offset_minutes: 0,
             special: None,
         };
 
@@ -627,6 +726,7 @@ mod test {
             second: 0,
             micros: 0,
             offset: None,
+            offset_minutes: 0,
             special: None,
         };
 
@@ -639,6 +739,7 @@ mod test {
             second: 0,
             micros: 0,
             offset: None,
+            offset_minutes: 0,
             special: None,
         };
 
@@ -648,4 +749,101 @@ mod test {
         assert!(d1 < d2);
         assert_eq!(d1.partial_cmp(&d2), Some(std::cmp::Ordering::Less));
     }
+
+    #[test]
+    fn test_offset_minutes() {
+        let ts = Timestamp::decode(b"2025-03-05 14:51:42.5+05:30", Format::Text).unwrap();
+        assert_eq!(ts.second, 42);
+        assert_eq!(ts.micros, 500000);
+        assert_eq!(ts.offset, Some(5));
+        assert_eq!(ts.offset_minutes, 30);
+        assert_eq!(ts.to_string(), "2025-03-05 14:51:42.500000+05:30");
+
+        let ts = Timestamp::decode(b"2025-03-05 14:51:42-00:30", Format::Text).unwrap();
+        assert_eq!(ts.offset, Some(0));
+        assert_eq!(ts.offset_minutes, -30);
+        assert_eq!(ts.to_string(), "2025-03-05 14:51:42.000000-00:30");
+
+        let ts = Timestamp::decode(b"2025-03-05 14:51:42.123-08", Format::Text).unwrap();
+        assert_eq!(ts.micros, 123000);
+        assert_eq!(ts.to_string(), "2025-03-05 14:51:42.123000-08");
+
+        let ts = Timestamp::decode(b"2025-03-05 14:51:42+00", Format::Text).unwrap();
+        assert_eq!(ts.to_string(), "2025-03-05 14:51:42.000000+00");
+
+        assert!(Timestamp::decode(b"2025-03-05 14:51:42+05:30:15", Format::Text).is_err());
+        assert!(Timestamp::decode(b"2025-03-05 14:51:42+05:75", Format::Text).is_err());
+    }
+
+    #[test]
+    fn test_ordering_mixed_offsets() {
+        let decode = |ts: &str| Timestamp::decode(ts.as_bytes(), Format::Text).unwrap();
+
+        // Same instant.
+        let utc = decode("2025-03-05 12:00:00+00");
+        let pst = decode("2025-03-05 04:00:00-08");
+        let ist = decode("2025-03-05 17:30:00+05:30");
+        assert_eq!(utc.cmp(&pst), std::cmp::Ordering::Equal);
+        assert_eq!(utc.cmp(&ist), std::cmp::Ordering::Equal);
+        assert_eq!(
+            utc.to_pg_epoch_micros().unwrap(),
+            ist.to_pg_epoch_micros().unwrap()
+        );
+
+        // Later wall clock, earlier instant.
+        let earlier = decode("2025-03-05 17:00:00+05:30"); // 11:30 UTC
+        let later = decode("2025-03-05 04:00:00.000001-08"); // 12:00:00.000001 UTC
+        assert!(earlier < later);
+        assert!(earlier < utc);
+        assert!(utc < later);
+
+        // Binary format is always UTC.
+        let binary =
+            Timestamp::decode(&ist.encode(Format::Binary).unwrap(), Format::Binary).unwrap();
+        assert_eq!(binary.hour, 12);
+        assert_eq!(binary.minute, 0);
+        assert_eq!(binary.cmp(&ist), std::cmp::Ordering::Equal);
+    }
+
+    #[test]
+    fn test_eq_hash_mixed_offsets() {
+        use std::collections::HashSet;
+
+        let decode = |ts: &str| Timestamp::decode(ts.as_bytes(), Format::Text).unwrap();
+
+        let utc = decode("2025-03-05 12:00:00+00");
+        let pst = decode("2025-03-05 04:00:00-08");
+        let ist = decode("2025-03-05 17:30:00+05:30");
+        assert_eq!(utc, pst);
+        assert_eq!(utc, ist);
+        assert_ne!(utc, decode("2025-03-05 12:00:00-08"));
+
+        let set = HashSet::from([utc, pst, ist]);
+        assert_eq!(set.len(), 1);
+        assert!(set.contains(&decode("2025-03-05 11:00:00-01")));
+
+        assert_eq!(Timestamp::infinity(), Timestamp::infinity());
+        assert_ne!(Timestamp::infinity(), Timestamp::neg_infinity());
+        assert_eq!(
+            HashSet::from([Timestamp::infinity(), Timestamp::infinity()]).len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_datum_timestamptz_max() {
+        use super::super::{Datum, TimestampTz};
+
+        let datums = ["2025-03-05 17:00:00+05:30", "2025-03-05 07:00:00-08"]
+            .iter()
+            .map(|ts| Datum::TimestampTz(TimestampTz::decode(ts.as_bytes(), Format::Text).unwrap()))
+            .collect::<Vec<_>>();
+
+        // 15:00 UTC is later than 11:30 UTC.
+        let max = datums
+            .iter()
+            .max_by(|a, b| a.partial_cmp(b).unwrap())
+            .unwrap();
+        assert_eq!(max, &datums[1]);
+    }
 }
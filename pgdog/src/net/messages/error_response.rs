@@ -17,6 +17,17 @@ pub struct ErrorResponse {
     pub context: Option<String>,
     pub file: Option<String>,
     pub routine: Option<String>,
+    pub severity_nonlocalized: Option<String>,
+    pub hint: Option<String>,
+    pub position: Option<String>,
+    pub internal_position: Option<String>,
+    pub internal_query: Option<String>,
+    pub schema: Option<String>,
+    pub table: Option<String>,
+    pub column: Option<String>,
+    pub data_type: Option<String>,
+    pub constraint: Option<String>,
+    pub line: Option<String>,
 }
 
 impl Default for ErrorResponse {
@@ -29,6 +40,17 @@ impl Default for ErrorResponse {
             context: None,
             file: None,
             routine: None,
+            severity_nonlocalized: None,
+            hint: None,
+            position: None,
+            internal_position: None,
+            internal_query: None,
+            schema: None,
+            table: None,
+            column: None,
+            data_type: None,
+            constraint: None,
+            line: None,
         }
     }
 }
@@ -43,10 +65,21 @@ impl ErrorResponse {
                 "password for user \"{}\" and database \"{}\" is wrong, or the database does not exist",
                 user, database
             ),
-            detail: None,
-            context: None,
-            file: None,
-            routine: None,
+            ..Default::default()
+        }
+    }
+
+    /// Client connected without TLS to a database that requires it.
+    pub fn tls_required(user: &str, database: &str) -> ErrorResponse {
+        ErrorResponse {
+            severity: "FATAL".into(),
+            code: "28000".into(),
+            message: format!(
+                "connections for user \"{}\" and database \"{}\" require TLS",
+                user, database
+            ),
+            hint: Some("connect with sslmode=require".into()),
+            ..Default::default()
         }
     }
 
@@ -56,9 +89,7 @@ impl ErrorResponse {
             code: "58000".into(),
             message: "cross-shard queries are disabled".into(),
             detail: Some("query doesn't have a sharding key".into()),
-            context: None,
-            file: None,
-            routine: None,
+            ..Default::default()
         }
     }
 
@@ -71,9 +102,45 @@ impl ErrorResponse {
                 "client_idle_timeout of {}ms expired",
                 duration.as_millis()
             )),
-            context: None,
-            file: None,
-            routine: None,
+            ..Default::default()
+        }
+    }
+
+    pub fn replication_client_timeout(duration: Duration) -> ErrorResponse {
+        ErrorResponse {
+            severity: "FATAL".into(),
+            code: "57P05".into(),
+            message: "terminating replication client due to timeout".into(),
+            detail: Some(format!(
+                "replication_client_timeout of {}ms expired",
+                duration.as_millis()
+            )),
+            ..Default::default()
+        }
+    }
+
+    /// Cross-shard query ran for longer than `cross_shard_timeout`.
+    pub fn cross_shard_timeout(duration: Duration) -> ErrorResponse {
+        ErrorResponse {
+            severity: "ERROR".into(),
+            code: "57014".into(),
+            message: "canceling cross-shard query due to cross_shard_timeout".into(),
+            detail: Some(format!(
+                "cross_shard_timeout of {}ms expired",
+                duration.as_millis()
+            )),
+            ..Default::default()
+        }
+    }
+
+    /// Cross-shard query returned more rows than `cross_shard_max_rows`.
+    pub fn cross_shard_max_rows(rows: usize) -> ErrorResponse {
+        ErrorResponse {
+            severity: "ERROR".into(),
+            code: "54000".into(),
+            message: "canceling cross-shard query due to cross_shard_max_rows".into(),
+            detail: Some(format!("query returned more than {} rows", rows)),
+            ..Default::default()
         }
     }
 
@@ -83,10 +150,27 @@ impl ErrorResponse {
             severity: "ERROR".into(),
             code: "58000".into(),
             message: "connection pool is down".into(),
-            detail: None,
-            context: None,
-            file: None,
-            routine: None,
+            ..Default::default()
+        }
+    }
+
+    /// Transaction rejected because the pool is overloaded.
+    pub fn load_shed() -> ErrorResponse {
+        ErrorResponse {
+            severity: "ERROR".into(),
+            code: "57014".into(),
+            message: "canceling transaction because the connection pool is overloaded".into(),
+            ..Default::default()
+        }
+    }
+
+    /// Client's statement_timeout expired before a connection was available.
+    pub fn statement_timeout() -> ErrorResponse {
+        ErrorResponse {
+            severity: "ERROR".into(),
+            code: "57014".into(),
+            message: "canceling statement due to statement timeout".into(),
+            ..Default::default()
         }
     }
 
@@ -96,10 +180,7 @@ impl ErrorResponse {
             severity: "FATAL".into(),
             code: "57P01".into(),
             message: "PgDog is shutting down".into(),
-            detail: None,
-            context: None,
-            file: None,
-            routine: None,
+            ..Default::default()
         }
     }
 
@@ -108,10 +189,7 @@ impl ErrorResponse {
             severity: "ERROR".into(),
             code: "42601".into(),
             message: err.into(),
-            detail: None,
-            context: None,
-            file: None,
-            routine: None,
+            ..Default::default()
         }
     }
 
@@ -121,10 +199,7 @@ impl ErrorResponse {
             severity: "ERROR".into(),
             code: "58000".into(),
             message,
-            detail: None,
-            context: None,
-            file: None,
-            routine: None,
+            ..Default::default()
         }
     }
 
@@ -138,6 +213,98 @@ impl ErrorResponse {
             ..Default::default()
         }
     }
+
+    /// Statement sent after an error in the same transaction,
+    /// before the client ended it with ROLLBACK or COMMIT.
+    pub fn in_failed_transaction() -> Self {
+        Self {
+            severity: "ERROR".into(),
+            code: "25P02".into(),
+            message:
+                "current transaction is aborted, commands ignored until end of transaction block"
+                    .into(),
+            ..Default::default()
+        }
+    }
+
+    /// Write to a read-only database.
+    pub fn read_only(message: &str) -> Self {
+        Self {
+            severity: "ERROR".into(),
+            code: "25006".into(),
+            message: message.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Statement that can't run inside a transaction block.
+    pub fn active_transaction(message: &str) -> Self {
+        Self {
+            severity: "ERROR".into(),
+            code: "25001".into(),
+            message: message.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Query blocked by a plugin.
+    pub fn blocked(message: &str) -> Self {
+        Self {
+            severity: "ERROR".into(),
+            code: "42501".into(),
+            message: message.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Read sent to a replica because the primary is down.
+    pub fn degraded_read() -> Self {
+        Self {
+            severity: "WARNING".into(),
+            code: "01000".into(),
+            message: "primary is down, read was sent to a replica and can return stale data".into(),
+            ..Default::default()
+        }
+    }
+
+    /// Routing metadata sent to clients with `pgdog.debug` enabled.
+    pub fn debug(message: &str) -> Self {
+        Self {
+            severity: "NOTICE".into(),
+            code: "00000".into(),
+            message: message.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Error severity, e.g. ERROR or FATAL.
+    pub fn severity(&self) -> &str {
+        &self.severity
+    }
+
+    /// Append a line to the CONTEXT field indicating which shard
+    /// produced this error.
+    pub fn shard_context(mut self, shard: usize) -> Self {
+        let line = format!("shard {}", shard);
+        self.context = Some(match self.context.take() {
+            Some(context) => format!("{}\n{}", context, line),
+            None => line,
+        });
+        self
+    }
+
+    /// Add an error from another shard. The same error is listed
+    /// in the context, different ones in the detail.
+    pub fn merge(mut self, other: ErrorResponse, shard: usize) -> Self {
+        if self.code != other.code || self.message != other.message {
+            let line = format!("shard {}: {}: {}", shard, other.code, other.message);
+            self.detail = Some(match self.detail.take() {
+                Some(detail) => format!("{}\n{}", detail, line),
+                None => line,
+            });
+        }
+        self.shard_context(shard)
+    }
 }
 
 impl Display for ErrorResponse {
@@ -152,22 +319,42 @@ impl Display for ErrorResponse {
 
 impl FromBytes for ErrorResponse {
     fn from_bytes(mut bytes: Bytes) -> Result<Self, Error> {
+        // NoticeResponse (B) shares the same format.
+        let code = bytes.get_u8() as char;
+        if code != 'E' && code != 'N' {
+            return Err(Error::UnexpectedMessage('E', code));
+        }
         let _len = bytes.get_i32();
 
         let mut error_response = ErrorResponse::default();
 
         while bytes.has_remaining() {
             let field = bytes.get_u8() as char;
+            if field == '\0' {
+                break;
+            }
             let value = c_string_buf(&mut bytes);
 
             match field {
                 'S' => error_response.severity = value,
+                'V' => error_response.severity_nonlocalized = Some(value),
                 'C' => error_response.code = value,
                 'M' => error_response.message = value,
                 'D' => error_response.detail = Some(value),
+                'H' => error_response.hint = Some(value),
+                'P' => error_response.position = Some(value),
+                'p' => error_response.internal_position = Some(value),
+                'q' => error_response.internal_query = Some(value),
                 'W' => error_response.context = Some(value),
+                's' => error_response.schema = Some(value),
+                't' => error_response.table = Some(value),
+                'c' => error_response.column = Some(value),
+                'd' => error_response.data_type = Some(value),
+                'n' => error_response.constraint = Some(value),
                 'F' => error_response.file = Some(value),
+                'L' => error_response.line = Some(value),
                 'R' => error_response.routine = Some(value),
+                // Unknown fields should be ignored, as per the protocol spec.
                 _ => continue,
             }
         }
@@ -184,7 +371,11 @@ impl ToBytes for ErrorResponse {
         payload.put_string(&self.severity);
 
         payload.put_u8(b'V');
-        payload.put_string(&self.severity);
+        payload.put_string(
+            self.severity_nonlocalized
+                .as_deref()
+                .unwrap_or(&self.severity),
+        );
 
         payload.put_u8(b'C');
         payload.put_string(&self.code);
@@ -192,24 +383,28 @@ impl ToBytes for ErrorResponse {
         payload.put_u8(b'M');
         payload.put_string(&self.message);
 
-        if let Some(ref detail) = self.detail {
-            payload.put_u8(b'D');
-            payload.put_string(detail);
-        }
-
-        if let Some(ref context) = self.context {
-            payload.put_u8(b'W');
-            payload.put_string(context);
-        }
+        let optional = [
+            (b'D', &self.detail),
+            (b'H', &self.hint),
+            (b'P', &self.position),
+            (b'p', &self.internal_position),
+            (b'q', &self.internal_query),
+            (b'W', &self.context),
+            (b's', &self.schema),
+            (b't', &self.table),
+            (b'c', &self.column),
+            (b'd', &self.data_type),
+            (b'n', &self.constraint),
+            (b'F', &self.file),
+            (b'L', &self.line),
+            (b'R', &self.routine),
+        ];
 
-        if let Some(ref file) = self.file {
-            payload.put_u8(b'F');
-            payload.put_string(file);
-        }
-
-        if let Some(ref routine) = self.routine {
-            payload.put_u8(b'R');
-            payload.put_string(routine);
+        for (field, value) in optional {
+            if let Some(value) = value {
+                payload.put_u8(field);
+                payload.put_string(value);
+            }
         }
 
         payload.put_u8(0);
@@ -223,3 +418,91 @@ impl Protocol for ErrorResponse {
         'E'
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn full() -> ErrorResponse {
+        ErrorResponse {
+            severity: "ERREUR".into(),
+            code: "23505".into(),
+            message: "duplicate key value violates unique constraint".into(),
+            detail: Some("Key (id)=(1) already exists.".into()),
+            context: Some("SQL function \"test\"".into()),
+            file: Some("nbtinsert.c".into()),
+            routine: Some("_bt_check_unique".into()),
+            severity_nonlocalized: Some("ERROR".into()),
+            hint: Some("Use a different id.".into()),
+            position: Some("15".into()),
+            internal_position: Some("3".into()),
+            internal_query: Some("SELECT 1".into()),
+            schema: Some("public".into()),
+            table: Some("users".into()),
+            column: Some("id".into()),
+            data_type: Some("bigint".into()),
+            constraint: Some("users_pkey".into()),
+            line: Some("666".into()),
+        }
+    }
+
+    #[test]
+    fn test_round_trip_all_fields() {
+        let error = full();
+        let bytes = error.to_bytes().unwrap();
+        let parsed = ErrorResponse::from_bytes(bytes.clone()).unwrap();
+
+        assert_eq!(parsed.severity(), "ERREUR");
+        assert_eq!(parsed.severity_nonlocalized.as_deref(), Some("ERROR"));
+        assert_eq!(parsed.code, error.code);
+        assert_eq!(parsed.message, error.message);
+        assert_eq!(parsed.detail, error.detail);
+        assert_eq!(parsed.hint, error.hint);
+        assert_eq!(parsed.position, error.position);
+        assert_eq!(parsed.internal_position, error.internal_position);
+        assert_eq!(parsed.internal_query, error.internal_query);
+        assert_eq!(parsed.context, error.context);
+        assert_eq!(parsed.schema, error.schema);
+        assert_eq!(parsed.table, error.table);
+        assert_eq!(parsed.column, error.column);
+        assert_eq!(parsed.data_type, error.data_type);
+        assert_eq!(parsed.constraint, error.constraint);
+        assert_eq!(parsed.file, error.file);
+        assert_eq!(parsed.line, error.line);
+        assert_eq!(parsed.routine, error.routine);
+
+        // Serializing again produces identical bytes.
+        assert_eq!(parsed.to_bytes().unwrap(), bytes);
+    }
+
+    #[test]
+    fn test_unknown_fields_ignored() {
+        let mut payload = Payload::named('E');
+        payload.put_u8(b'S');
+        payload.put_string("FATAL");
+        payload.put_u8(b'Z');
+        payload.put_string("future field");
+        payload.put_u8(b'C');
+        payload.put_string("57P01");
+        payload.put_u8(b'M');
+        payload.put_string("terminating");
+        payload.put_u8(0);
+
+        let error = ErrorResponse::from_bytes(payload.freeze()).unwrap();
+        assert_eq!(error.severity(), "FATAL");
+        assert_eq!(error.code, "57P01");
+        assert_eq!(error.message, "terminating");
+    }
+
+    #[test]
+    fn test_shard_context() {
+        let error = ErrorResponse::syntax("syntax error").shard_context(2);
+        assert_eq!(error.context.as_deref(), Some("shard 2"));
+
+        let error = full().shard_context(0);
+        assert_eq!(
+            error.context.as_deref(),
+            Some("SQL function \"test\"\nshard 0")
+        );
+    }
+}
@@ -2,6 +2,7 @@
 pub mod auth;
 pub mod backend_key;
 pub mod bind;
+pub mod bind_complete;
 pub mod close;
 pub mod close_complete;
 pub mod command_complete;
@@ -16,6 +17,7 @@ pub mod error_response;
 pub mod execute;
 pub mod flush;
 pub mod hello;
+pub mod no_data;
 pub mod notice_response;
 pub mod notification_response;
 pub mod parameter_description;
@@ -34,6 +36,7 @@ pub mod terminate;
 pub use auth::{Authentication, Password};
 pub use backend_key::BackendKeyData;
 pub use bind::{Bind, Format, Parameter, ParameterWithFormat};
+pub use bind_complete::BindComplete;
 pub use close::Close;
 pub use close_complete::CloseComplete;
 pub use command_complete::CommandComplete;
@@ -48,6 +51,7 @@ pub use error_response::ErrorResponse;
 pub use execute::Execute;
 pub use flush::Flush;
 pub use hello::Startup;
+pub use no_data::NoData;
 pub use notice_response::NoticeResponse;
 pub use notification_response::NotificationResponse;
 pub use parameter_description::ParameterDescription;
@@ -111,9 +115,10 @@ pub struct Message {
 }
 
 impl MemoryUsage for Message {
+    /// Messages read from a stream are the only users of their payload.
     #[inline]
     fn memory_usage(&self) -> usize {
-        std::mem::size_of::<Bytes>() + self.stream.memory_usage() + std::mem::size_of::<Source>()
+        std::mem::size_of::<Self>() + self.payload.len()
     }
 }
 
@@ -232,10 +237,6 @@ impl Message {
     pub fn source(&self) -> Source {
         self.source
     }
-
-    pub fn in_transaction(&self) -> bool {
-        self.code() == 'Z' && matches!(self.payload[5] as char, 'T' | 'E')
-    }
 }
 
 /// Check that the message we received is what we expected.
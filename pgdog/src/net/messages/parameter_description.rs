@@ -6,6 +6,13 @@ pub struct ParameterDescription {
     params: Vec<i32>,
 }
 
+impl ParameterDescription {
+    /// Statement without parameters.
+    pub fn empty() -> Self {
+        Self { params: vec![] }
+    }
+}
+
 impl FromBytes for ParameterDescription {
     fn from_bytes(mut bytes: Bytes) -> Result<Self, Error> {
         code!(bytes, 't');
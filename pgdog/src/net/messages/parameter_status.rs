@@ -6,6 +6,13 @@ use crate::net::{
     Parameter,
 };
 
+/// Parameters reported only by standbys. Clients would see them
+/// come and go depending on which server handled the startup.
+static STANDBY_PARAMS: &[&str] = &["in_hot_standby"];
+
+/// `server_version` we send if we don't know the real one.
+pub const DEFAULT_SERVER_VERSION: &str = "16.0 (PgDog)";
+
 /// ParameterStatus (B) message.
 #[derive(Debug)]
 pub struct ParameterStatus {
@@ -69,6 +76,41 @@ impl ParameterStatus {
             },
         ]
     }
+
+    /// Remove standby-only parameters and sort the rest by name,
+    /// so every client sees the same startup sequence.
+    pub fn canonical(params: Vec<ParameterStatus>) -> Vec<ParameterStatus> {
+        let mut params = params
+            .into_iter()
+            .filter(|param| !STANDBY_PARAMS.contains(&param.name.as_str()))
+            .collect::<Vec<_>>();
+        params.sort_by_key(|param| param.name.to_lowercase());
+        params
+    }
+
+    /// Parameters we can send to clients when the pool is temporarily
+    /// down and we can't get them from a server.
+    pub fn synthesized(user: &str, server_version: &str) -> Vec<ParameterStatus> {
+        Self::canonical(
+            [
+                ("application_name", ""),
+                ("client_encoding", "UTF8"),
+                ("DateStyle", "ISO, MDY"),
+                ("default_transaction_read_only", "off"),
+                ("integer_datetimes", "on"),
+                ("IntervalStyle", "postgres"),
+                ("is_superuser", "off"),
+                ("server_encoding", "UTF8"),
+                ("server_version", server_version),
+                ("session_authorization", user),
+                ("standard_conforming_strings", "on"),
+                ("TimeZone", "UTC"),
+            ]
+            .into_iter()
+            .map(ParameterStatus::from)
+            .collect(),
+        )
+    }
 }
 
 impl ToBytes for ParameterStatus {
@@ -100,3 +142,59 @@ impl Protocol for ParameterStatus {
         'S'
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn names(params: &[ParameterStatus]) -> Vec<&str> {
+        params.iter().map(|param| param.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_canonical() {
+        let params = ParameterStatus::canonical(
+            [
+                ("TimeZone", "UTC"),
+                ("in_hot_standby", "on"),
+                ("application_name", ""),
+                ("DateStyle", "ISO, MDY"),
+            ]
+            .into_iter()
+            .map(ParameterStatus::from)
+            .collect(),
+        );
+        assert_eq!(
+            names(&params),
+            ["application_name", "DateStyle", "TimeZone"]
+        );
+    }
+
+    #[test]
+    fn test_synthesized() {
+        let params = ParameterStatus::synthesized("pgdog", "16.4");
+        assert_eq!(
+            names(&params),
+            [
+                "application_name",
+                "client_encoding",
+                "DateStyle",
+                "default_transaction_read_only",
+                "integer_datetimes",
+                "IntervalStyle",
+                "is_superuser",
+                "server_encoding",
+                "server_version",
+                "session_authorization",
+                "standard_conforming_strings",
+                "TimeZone",
+            ]
+        );
+        assert!(params
+            .iter()
+            .any(|param| param.name == "session_authorization" && param.value == "pgdog"));
+        assert!(params
+            .iter()
+            .any(|param| param.name == "server_version" && param.value == "16.4"));
+    }
+}
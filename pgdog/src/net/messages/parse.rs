@@ -8,6 +8,7 @@ use std::str::from_utf8_unchecked;
 
 use super::code;
 use super::prelude::*;
+use bytes::BytesMut;
 
 /// Parse (F) message.
 #[derive(Clone, Hash, Eq, PartialEq, Default)]
@@ -58,6 +59,23 @@ impl Parse {
         }
     }
 
+    /// New prepared statement with declared parameter data types.
+    pub fn with_data_types(name: impl ToString, query: impl ToString, data_types: &[i32]) -> Self {
+        let mut types =
+            BytesMut::with_capacity(size_of::<i16>() + data_types.len() * size_of::<i32>());
+        types.put_i16(data_types.len() as i16);
+        for data_type in data_types {
+            types.put_i32(*data_type);
+        }
+
+        Self {
+            name: Bytes::from(name.to_string() + "\0"),
+            query: Bytes::from(query.to_string() + "\0"),
+            data_types: types.freeze(),
+            original: None,
+        }
+    }
+
     /// Anonymous prepared statement.
     pub fn anonymous(&self) -> bool {
         self.name.len() == 1 // Just the null byte.
@@ -197,6 +215,18 @@ mod test {
         assert_eq!(parse.len(), b.len());
     }
 
+    #[test]
+    fn test_parse_with_data_types() {
+        let parse = Parse::with_data_types("", "SELECT $1, $2", &[20, 25]);
+        assert!(parse.anonymous());
+        assert_eq!(parse.data_types().collect::<Vec<_>>(), vec![20, 25]);
+        assert_eq!(parse.to_bytes().unwrap().len(), parse.len());
+
+        let parse = Parse::from_bytes(parse.to_bytes().unwrap()).unwrap();
+        assert_eq!(parse.query(), "SELECT $1, $2");
+        assert_eq!(parse.data_types().len(), 2);
+    }
+
     #[test]
     fn test_parse_from_bytes() {
         let mut parse = Parse::named("__pgdog_1", "SELECT * FROM users");
@@ -25,6 +25,17 @@ impl StatusUpdate {
         ))
     }
 
+    /// Position flushed by the subscriber.
+    pub fn new(lsn: Lsn) -> Self {
+        Self {
+            last_applied: lsn.lsn,
+            last_flushed: lsn.lsn,
+            last_written: lsn.lsn,
+            system_clock: postgres_now(),
+            reply: 0,
+        }
+    }
+
     /// Generate a request from peer to update me now
     /// with latest lsn.
     pub fn new_reply(lsn: Lsn) -> Self {
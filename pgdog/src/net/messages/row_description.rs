@@ -106,6 +106,19 @@ impl Field {
         }
     }
 
+    /// Timestamp with time zone field.
+    pub fn timestamptz(name: &str) -> Self {
+        Self {
+            name: name.into(),
+            table_oid: 0,
+            column: 0,
+            type_oid: 1184, // PostgreSQL OID for timestamp with time zone
+            type_size: 8,
+            type_modifier: -1,
+            format: 0, // We always use text format.
+        }
+    }
+
     /// Get the column data type.
     #[inline]
     pub fn data_type(&self) -> DataType {
@@ -5,6 +5,7 @@ use std::{
     fmt::Display,
     hash::{DefaultHasher, Hash, Hasher},
     ops::{Deref, DerefMut},
+    time::Duration,
 };
 
 use once_cell::sync::Lazy;
@@ -24,6 +25,10 @@ static IMMUTABLE_PARAMS: Lazy<Vec<String>> = Lazy::new(|| {
 
 // static IMMUTABLE_PARAMS: &[&str] = &["database", "user", "client_encoding"];
 
+/// Parameters that change the user running queries,
+/// set with `SET ROLE` and `SET SESSION AUTHORIZATION`.
+pub const ROLE_PARAMS: &[&str] = &["role", "session_authorization"];
+
 /// Startup parameter.
 #[derive(Debug, Clone, PartialEq)]
 pub struct Parameter {
@@ -57,10 +62,11 @@ pub enum ParameterValue {
 impl MemoryUsage for ParameterValue {
     #[inline]
     fn memory_usage(&self) -> usize {
-        match self {
-            Self::String(v) => v.memory_usage(),
-            Self::Tuple(vals) => vals.memory_usage(),
-        }
+        std::mem::size_of::<Self>()
+            + match self {
+                Self::String(v) => v.heap_usage(),
+                Self::Tuple(vals) => vals.heap_usage(),
+            }
     }
 }
 
@@ -176,18 +182,21 @@ impl Parameters {
         self.hash == other.hash
     }
 
-    pub fn set_queries(&self) -> Vec<Query> {
-        self.params
+    /// Queries needed to change the parameters on the server from `current` to `self`.
+    /// Parameters that didn't change are skipped.
+    pub fn sync_queries(&self, current: &Self) -> Vec<Query> {
+        let reset = current
+            .params
+            .keys()
+            .filter(|name| !self.params.contains_key(*name))
+            .map(|name| Query::new(format!(r#"RESET "{}""#, name)));
+        let set = self
+            .params
             .iter()
-            .map(|(name, value)| Query::new(format!(r#"SET "{}" TO {}"#, name, value)))
-            .collect()
-    }
+            .filter(|(name, value)| current.params.get(*name) != Some(value))
+            .map(|(name, value)| Query::new(format!(r#"SET "{}" TO {}"#, name, value)));
 
-    pub fn reset_queries(&self) -> Vec<Query> {
-        self.params
-            .keys()
-            .map(|name| Query::new(format!(r#"RESET "{}""#, name)))
-            .collect()
+        reset.chain(set).collect()
     }
 
     /// Get self-declared shard number.
@@ -206,6 +215,32 @@ impl Parameters {
         }
     }
 
+    /// Client's statement_timeout, if set. Zero disables it.
+    pub fn statement_timeout(&self) -> Option<Duration> {
+        let value = self.get("statement_timeout")?.as_str()?.trim();
+        let split = value
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(value.len());
+        let (amount, unit) = value.split_at(split);
+        let amount = amount.parse::<u64>().ok()?;
+
+        let timeout = match unit.trim() {
+            "" | "ms" => Duration::from_millis(amount),
+            "us" => Duration::from_micros(amount),
+            "s" => Duration::from_secs(amount),
+            "min" => Duration::from_secs(amount * 60),
+            "h" => Duration::from_secs(amount * 60 * 60),
+            "d" => Duration::from_secs(amount * 60 * 60 * 24),
+            _ => return None,
+        };
+
+        if timeout.is_zero() {
+            None
+        } else {
+            Some(timeout)
+        }
+    }
+
     /// Get parameter value or returned an error.
     pub fn get_required(&self, name: &str) -> Result<&str, Error> {
         self.get(name)
@@ -283,4 +318,52 @@ mod test {
 
         assert!(Parameters::default().identical(&Parameters::default()));
     }
+
+    #[test]
+    fn test_sync_queries() {
+        let mut current = Parameters::default();
+        current.insert("application_name", "one");
+        current.insert("TimeZone", "UTC");
+        current.insert("statement_timeout", "1000");
+
+        let mut client = Parameters::default();
+        client.insert("application_name", "two");
+        client.insert("TimeZone", "UTC");
+
+        let queries = client
+            .sync_queries(&current)
+            .into_iter()
+            .map(|query| query.query().to_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            queries,
+            vec![
+                r#"RESET "statement_timeout""#.to_string(),
+                r#"SET "application_name" TO 'two'"#.to_string(),
+            ]
+        );
+
+        assert!(client.sync_queries(&client).is_empty());
+    }
+
+    #[test]
+    fn test_statement_timeout() {
+        use std::time::Duration;
+
+        let mut params = Parameters::default();
+        assert_eq!(params.statement_timeout(), None);
+
+        for (value, timeout) in [
+            ("500", Some(Duration::from_millis(500))),
+            ("250ms", Some(Duration::from_millis(250))),
+            ("5s", Some(Duration::from_secs(5))),
+            ("2 min", Some(Duration::from_secs(120))),
+            ("1h", Some(Duration::from_secs(3600))),
+            ("0", None),
+            ("forever", None),
+        ] {
+            params.insert("statement_timeout", value);
+            assert_eq!(params.statement_timeout(), timeout, "{}", value);
+        }
+    }
 }
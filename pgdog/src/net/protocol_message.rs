@@ -1,6 +1,8 @@
 use bytes::Buf;
 use std::io::Cursor;
 
+use crate::stats::memory::MemoryUsage;
+
 use super::{
     Bind, Close, CopyData, CopyDone, CopyFail, Describe, Execute, Flush, FromBytes, Message, Parse,
     Protocol, Query, Sync, ToBytes,
@@ -49,6 +51,19 @@ impl ProtocolMessage {
     }
 }
 
+impl MemoryUsage for ProtocolMessage {
+    /// Messages own the payload they were read from, so it's counted by its length.
+    #[inline]
+    fn memory_usage(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + match self {
+                Self::Bind(bind) => bind.heap_usage(),
+                Self::Prepare { name, statement } => name.capacity() + statement.capacity(),
+                message => message.len(),
+            }
+    }
+}
+
 impl Protocol for ProtocolMessage {
     fn code(&self) -> char {
         match self {
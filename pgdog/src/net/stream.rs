@@ -13,13 +13,14 @@ use std::pin::Pin;
 use std::task::Context;
 
 use super::messages::{ErrorResponse, Message, Protocol, ReadyForQuery, Terminate};
+use crate::stats::memory::MemoryUsage;
 
-/// A network socket.
+/// A network socket, with the capacity of its write buffer.
 #[pin_project(project = StreamProjection)]
 #[derive(Debug)]
 pub enum Stream {
-    Plain(#[pin] BufStream<TcpStream>),
-    Tls(#[pin] BufStream<tokio_rustls::TlsStream<TcpStream>>),
+    Plain(#[pin] BufStream<TcpStream>, usize),
+    Tls(#[pin] BufStream<tokio_rustls::TlsStream<TcpStream>>, usize),
     DevNull,
 }
 
@@ -31,8 +32,8 @@ impl AsyncRead for Stream {
     ) -> std::task::Poll<std::io::Result<()>> {
         let project = self.project();
         match project {
-            StreamProjection::Plain(stream) => stream.poll_read(cx, buf),
-            StreamProjection::Tls(stream) => stream.poll_read(cx, buf),
+            StreamProjection::Plain(stream, _) => stream.poll_read(cx, buf),
+            StreamProjection::Tls(stream, _) => stream.poll_read(cx, buf),
             StreamProjection::DevNull => std::task::Poll::Ready(Ok(())),
         }
     }
@@ -46,8 +47,8 @@ impl AsyncWrite for Stream {
     ) -> std::task::Poll<Result<usize, Error>> {
         let project = self.project();
         match project {
-            StreamProjection::Plain(stream) => stream.poll_write(cx, buf),
-            StreamProjection::Tls(stream) => stream.poll_write(cx, buf),
+            StreamProjection::Plain(stream, _) => stream.poll_write(cx, buf),
+            StreamProjection::Tls(stream, _) => stream.poll_write(cx, buf),
             StreamProjection::DevNull => std::task::Poll::Ready(Ok(buf.len())),
         }
     }
@@ -58,8 +59,8 @@ impl AsyncWrite for Stream {
     ) -> std::task::Poll<Result<(), Error>> {
         let project = self.project();
         match project {
-            StreamProjection::Plain(stream) => stream.poll_flush(cx),
-            StreamProjection::Tls(stream) => stream.poll_flush(cx),
+            StreamProjection::Plain(stream, _) => stream.poll_flush(cx),
+            StreamProjection::Tls(stream, _) => stream.poll_flush(cx),
             StreamProjection::DevNull => std::task::Poll::Ready(Ok(())),
         }
     }
@@ -70,35 +71,59 @@ impl AsyncWrite for Stream {
     ) -> std::task::Poll<Result<(), Error>> {
         let project = self.project();
         match project {
-            StreamProjection::Plain(stream) => stream.poll_shutdown(cx),
-            StreamProjection::Tls(stream) => stream.poll_shutdown(cx),
+            StreamProjection::Plain(stream, _) => stream.poll_shutdown(cx),
+            StreamProjection::Tls(stream, _) => stream.poll_shutdown(cx),
             StreamProjection::DevNull => std::task::Poll::Ready(Ok(())),
         }
     }
 }
 
+/// Default size of the read and write buffers.
+pub const BUFFER_SIZE: usize = 9126;
+
 impl Stream {
     /// Wrap an unencrypted TCP stream.
     pub fn plain(stream: TcpStream) -> Self {
-        Self::Plain(BufStream::with_capacity(9126, 9126, stream))
+        Self::plain_with_write_buffer(stream, BUFFER_SIZE)
+    }
+
+    /// Wrap an unencrypted TCP stream, writing to the socket only
+    /// once `write_buffer` bytes are buffered or the stream is flushed.
+    pub fn plain_with_write_buffer(stream: TcpStream, write_buffer: usize) -> Self {
+        Self::Plain(
+            BufStream::with_capacity(BUFFER_SIZE, write_buffer, stream),
+            write_buffer,
+        )
     }
 
     /// Wrap an encrypted TCP stream.
     pub fn tls(stream: tokio_rustls::TlsStream<TcpStream>) -> Self {
-        Self::Tls(BufStream::with_capacity(9126, 9126, stream))
+        Self::tls_with_write_buffer(stream, BUFFER_SIZE)
+    }
+
+    /// Wrap an encrypted TCP stream, writing to the socket only
+    /// once `write_buffer` bytes are buffered or the stream is flushed.
+    pub fn tls_with_write_buffer(
+        stream: tokio_rustls::TlsStream<TcpStream>,
+        write_buffer: usize,
+    ) -> Self {
+        Self::Tls(
+            BufStream::with_capacity(BUFFER_SIZE, write_buffer, stream),
+            write_buffer,
+        )
     }
 
     /// This is a TLS stream.
     pub fn is_tls(&self) -> bool {
-        matches!(self, Self::Tls(_))
+        matches!(self, Self::Tls(..))
     }
 
     /// Get peer address if any. We're not using UNIX sockets (yet)
     /// so the peer address should always be available.
     pub fn peer_addr(&self) -> PeerAddr {
         match self {
-            Self::Plain(stream) => stream.get_ref().peer_addr().ok().into(),
-            Self::Tls(stream) => stream.get_ref().get_ref().0.peer_addr().ok().into(),
+            Self::Plain(stream, _) => stream.get_ref().peer_addr().ok().into(),
+            Self::Tls(stream, _) => stream.get_ref().get_ref().0.peer_addr().ok().into(),
             Self::DevNull => PeerAddr { addr: None },
         }
     }
@@ -107,14 +132,36 @@ impl Stream {
     pub async fn check(&mut self) -> Result<(), crate::net::Error> {
         let mut buf = [0u8; 1];
         match self {
-            Self::Plain(plain) => plain.get_mut().peek(&mut buf).await?,
-            Self::Tls(tls) => tls.get_mut().get_mut().0.peek(&mut buf).await?,
+            Self::Plain(plain, _) => plain.get_mut().peek(&mut buf).await?,
+            Self::Tls(tls, _) => tls.get_mut().get_mut().0.peek(&mut buf).await?,
             Self::DevNull => 0,
         };
 
         Ok(())
     }
 
+    /// Resolves when the client closes the connection.
+    ///
+    /// Doesn't consume any data. If the client sent something,
+    /// it's considered connected and this never resolves.
+    ///
+    /// A client that half-closes the connection, i.e. shuts down writing but still
+    /// reads, is considered disconnected too: reading from it returns 0 bytes, just like
+    /// a closed connection. Postgres clients don't half-close, and Postgres itself
+    /// ends the session when it reads 0 bytes from the client.
+    pub async fn closed(&mut self) {
+        let mut buf = [0u8; 1];
+        let peek = match self {
+            Self::Plain(plain, _) => plain.get_mut().peek(&mut buf).await,
+            Self::Tls(tls, _) => tls.get_mut().get_mut().0.peek(&mut buf).await,
+            Self::DevNull => Ok(1),
+        };
+
+        if let Ok(1..) = peek {
+            std::future::pending::<()>().await;
+        }
+    }
+
     /// Send data via the stream.
     ///
     /// # Performance
@@ -125,8 +172,8 @@ impl Stream {
         let bytes = message.to_bytes()?;
 
         match self {
-            Stream::Plain(ref mut stream) => stream.write_all(&bytes).await?,
-            Stream::Tls(ref mut stream) => stream.write_all(&bytes).await?,
+            Stream::Plain(ref mut stream, _) => stream.write_all(&bytes).await?,
+            Stream::Tls(ref mut stream, _) => stream.write_all(&bytes).await?,
             Self::DevNull => (),
         }
 
@@ -254,12 +301,27 @@ impl Stream {
     /// Get the wrapped TCP stream back.
     pub(crate) fn take(self) -> Result<TcpStream, crate::net::Error> {
         match self {
-            Self::Plain(stream) => Ok(stream.into_inner()),
+            Self::Plain(stream, _) => Ok(stream.into_inner()),
             _ => Err(crate::net::Error::UnexpectedTlsRequest),
         }
     }
 }
 
+impl MemoryUsage for Stream {
+    /// Read and write buffers. Socket and TLS session state,
+    /// allocated by the OS and rustls, aren't counted.
+    #[inline]
+    fn memory_usage(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + match self {
+                Self::Plain(_, write_buffer) | Self::Tls(_, write_buffer) => {
+                    BUFFER_SIZE + write_buffer
+                }
+                Self::DevNull => 0,
+            }
+    }
+}
+
 /// Wrapper around SocketAddr
 /// to make it easier to debug.
 pub struct PeerAddr {
@@ -289,3 +351,37 @@ impl std::fmt::Debug for PeerAddr {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use tokio::net::TcpListener;
+    use tokio::time::timeout;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_closed() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (client, accepted) = tokio::join!(TcpStream::connect(addr), listener.accept());
+        let mut client = client.unwrap();
+        let mut stream = Stream::plain(accepted.unwrap().0);
+
+        // Client sent data, so it's still connected.
+        client.write_all(b"Q").await.unwrap();
+        assert!(timeout(Duration::from_millis(100), stream.closed())
+            .await
+            .is_err());
+
+        // Data wasn't consumed.
+        assert_eq!(stream.read_u8().await.unwrap(), b'Q');
+
+        // Half-closed, i.e. the client only shut down writing.
+        client.shutdown().await.unwrap();
+        timeout(Duration::from_secs(1), stream.closed())
+            .await
+            .unwrap();
+    }
+}
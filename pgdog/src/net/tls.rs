@@ -3,7 +3,8 @@
 use std::{path::PathBuf, sync::Arc};
 
 use crate::config::TlsVerifyMode;
-use once_cell::sync::OnceCell;
+use arc_swap::ArcSwapOption;
+use once_cell::sync::Lazy;
 use rustls::pki_types::{CertificateDer, PrivateKeyDer};
 use tokio_rustls::rustls::{
     self,
@@ -12,43 +13,36 @@ use tokio_rustls::rustls::{
     ClientConfig,
 };
 use tokio_rustls::{TlsAcceptor, TlsConnector};
-use tracing::{debug, info};
+use tracing::{debug, error, info};
 
-use crate::config::config;
+use crate::config::{config, General};
 
 use super::Error;
 
-static ACCEPTOR: OnceCell<Option<TlsAcceptor>> = OnceCell::new();
-static CONNECTOR: OnceCell<TlsConnector> = OnceCell::new();
+static ACCEPTOR: Lazy<ArcSwapOption<TlsAcceptor>> = Lazy::new(|| ArcSwapOption::from(None));
+static CONNECTOR: Lazy<ArcSwapOption<TlsConnector>> = Lazy::new(|| ArcSwapOption::from(None));
 
 /// Get preloaded TLS acceptor.
-pub fn acceptor() -> Option<&'static TlsAcceptor> {
-    if let Some(Some(acceptor)) = ACCEPTOR.get() {
-        return Some(acceptor);
-    }
-
-    None
+///
+/// The acceptor can be swapped on reload. Connections that already
+/// finished the handshake keep using the old configuration.
+pub fn acceptor() -> Option<TlsAcceptor> {
+    ACCEPTOR.load().as_deref().cloned()
 }
 
 /// Create a new TLS acceptor from the cert and key.
 ///
-/// This is not atomic, so call it on startup only.
+/// Returns `None` if the certificate or the key can't be read.
 pub fn load_acceptor(cert: &PathBuf, key: &PathBuf) -> Result<Option<TlsAcceptor>, Error> {
-    if let Some(acceptor) = ACCEPTOR.get() {
-        return Ok(acceptor.clone());
-    }
-
     let pem = if let Ok(pem) = CertificateDer::from_pem_file(cert) {
         pem
     } else {
-        let _ = ACCEPTOR.set(None);
         return Ok(None);
     };
 
     let key = if let Ok(key) = PrivateKeyDer::from_pem_file(key) {
         key
     } else {
-        let _ = ACCEPTOR.set(None);
         return Ok(None);
     };
 
@@ -58,18 +52,12 @@ pub fn load_acceptor(cert: &PathBuf, key: &PathBuf) -> Result<Option<TlsAcceptor
 
     let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(config));
 
-    info!("🔑 TLS on");
-
-    // A bit of a race, but it's not a big deal unless this is called
-    // with different certificate/secret key.
-    let _ = ACCEPTOR.set(Some(acceptor.clone()));
-
     Ok(Some(acceptor))
 }
 
-/// Create new TLS connector using the default configuration.
+/// Get TLS connector used for server connections.
 pub fn connector() -> Result<TlsConnector, Error> {
-    if let Some(connector) = CONNECTOR.get() {
+    if let Some(connector) = CONNECTOR.load().as_deref() {
         return Ok(connector.clone());
     }
 
@@ -79,7 +67,7 @@ pub fn connector() -> Result<TlsConnector, Error> {
         config.config.general.tls_server_ca_certificate.as_ref(),
     )?;
 
-    let _ = CONNECTOR.set(connector.clone());
+    CONNECTOR.store(Some(Arc::new(connector.clone())));
 
     Ok(connector)
 }
@@ -87,16 +75,57 @@ pub fn connector() -> Result<TlsConnector, Error> {
 /// Preload TLS at startup.
 pub fn load() -> Result<(), Error> {
     let config = config();
+    let (acceptor, connector) = build(&config.config.general)?;
 
-    if let Some((cert, key)) = config.config.general.tls() {
-        load_acceptor(cert, key)?;
+    if acceptor.is_some() {
+        info!("🔑 TLS on");
     }
 
-    connector()?;
+    ACCEPTOR.store(acceptor.map(Arc::new));
+    CONNECTOR.store(Some(Arc::new(connector)));
 
     Ok(())
 }
 
+/// Reload TLS certificates, e.g. after they have been rotated.
+///
+/// New connections use the new certificates, while existing ones keep their
+/// sessions. If the new certificates can't be loaded, the old ones stay active.
+pub fn reload(general: &General) {
+    match build(general) {
+        Ok((acceptor, connector)) => {
+            if general.tls().is_some() && acceptor.is_none() {
+                error!("TLS certificate or key couldn't be loaded, keeping old configuration");
+                return;
+            }
+
+            ACCEPTOR.store(acceptor.map(Arc::new));
+            CONNECTOR.store(Some(Arc::new(connector)));
+
+            info!("🔑 TLS configuration reloaded");
+        }
+
+        Err(err) => {
+            error!("TLS reload error, keeping old configuration: {}", err);
+        }
+    }
+}
+
+fn build(general: &General) -> Result<(Option<TlsAcceptor>, TlsConnector), Error> {
+    let acceptor = if let Some((cert, key)) = general.tls() {
+        load_acceptor(cert, key)?
+    } else {
+        None
+    };
+
+    let connector = connector_with_verify_mode(
+        general.tls_verify,
+        general.tls_server_ca_certificate.as_ref(),
+    )?;
+
+    Ok((acceptor, connector))
+}
+
 #[derive(Debug)]
 struct AllowAllVerifier;
 
@@ -332,6 +361,8 @@ impl ServerCertVerifier for NoHostnameVerifier {
 mod tests {
     use super::*;
     use crate::config::TlsVerifyMode;
+    use rustls::pki_types::ServerName;
+    use tokio::net::{TcpListener, TcpStream};
 
     #[tokio::test]
     async fn test_connector_with_verify_mode() {
@@ -358,6 +389,77 @@ mod tests {
         assert!(result.is_err(), "Should fail with non-existent cert file");
     }
 
+    /// Certificate the acceptor presents to a new connection.
+    async fn presented_certificate() -> CertificateDer<'static> {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let acceptor = acceptor().unwrap();
+        let connector = connector_with_verify_mode(TlsVerifyMode::Prefer, None).unwrap();
+
+        let (client, _server) = tokio::join!(
+            async {
+                let stream = TcpStream::connect(addr).await.unwrap();
+                let name = ServerName::try_from("localhost").unwrap();
+                connector.connect(name, stream).await.unwrap()
+            },
+            async {
+                let (stream, _) = listener.accept().await.unwrap();
+                acceptor.accept(stream).await.unwrap()
+            }
+        );
+
+        client.get_ref().1.peer_certificates().unwrap()[0]
+            .clone()
+            .into_owned()
+    }
+
+    #[tokio::test]
+    async fn test_reload_rotated_certificate() {
+        crate::logger();
+
+        let dir = std::env::temp_dir().join(format!("pgdog_tls_reload_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let cert = dir.join("cert.pem");
+        let key = dir.join("key.pem");
+
+        let mut general = General::default();
+        reload(&general);
+        assert!(acceptor().is_none());
+
+        // Certificate added.
+        std::fs::copy("tests/tls/cert.pem", &cert).unwrap();
+        std::fs::copy("tests/tls/key.pem", &key).unwrap();
+        general.tls_certificate = Some(cert.clone());
+        general.tls_private_key = Some(key.clone());
+        reload(&general);
+        assert_eq!(
+            presented_certificate().await,
+            CertificateDer::from_pem_file("tests/tls/cert.pem").unwrap()
+        );
+
+        // Certificate rotated.
+        std::fs::copy("tests/tls/cert2.pem", &cert).unwrap();
+        std::fs::copy("tests/tls/key2.pem", &key).unwrap();
+        reload(&general);
+        let second = ACCEPTOR.load_full().unwrap();
+        assert_eq!(
+            presented_certificate().await,
+            CertificateDer::from_pem_file("tests/tls/cert2.pem").unwrap()
+        );
+
+        // Broken certificate, old one stays active.
+        std::fs::write(&cert, "not a certificate").unwrap();
+        reload(&general);
+        let third = ACCEPTOR.load_full().unwrap();
+        assert!(Arc::ptr_eq(&second, &third));
+        assert_eq!(
+            presented_certificate().await,
+            CertificateDer::from_pem_file("tests/tls/cert2.pem").unwrap()
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
     #[tokio::test]
     async fn test_connector_with_verify_mode_good_ca_file() {
         crate::logger();
@@ -0,0 +1,258 @@
+//! Plugin time budget.
+//!
+//! Plugins are called synchronously over FFI while routing every query, so one slow
+//! plugin slows down every query. The call can't be safely interrupted: a plugin
+//! going over `plugin_timeout_ms` is never cancelled. Instead, we measure every call and
+//! if a plugin goes over budget too often, we log it and, with `plugin_slow_action = "disable"`,
+//! stop calling it until PgDog is restarted.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+use pgdog_plugin::{PdRoute, PdRouterContext, Plugin};
+use tracing::{error, warn};
+
+use crate::config::PluginSlowAction;
+
+/// How many slow calls in [`SLOW_WINDOW`] trigger `plugin_slow_action`.
+pub const SLOW_CALLS: usize = 5;
+/// Window for counting slow calls.
+pub const SLOW_WINDOW: Duration = Duration::from_secs(60);
+/// Upper bounds of the call duration histogram buckets, in ms.
+pub const BUCKETS: [f64; 8] = [0.05, 0.1, 0.5, 1.0, 5.0, 10.0, 50.0, 100.0];
+
+/// Slow calls in the current window.
+#[derive(Debug, Default)]
+struct SlowCalls {
+    started: Option<Instant>,
+    count: usize,
+}
+
+/// Plugin call stats and protection.
+#[derive(Debug)]
+pub struct Budget {
+    name: String,
+    calls: AtomicU64,
+    slow_calls: AtomicU64,
+    /// Total time spent in the plugin, in µs.
+    time: AtomicU64,
+    /// Calls by duration, the last bucket is everything above [`BUCKETS`].
+    buckets: [AtomicU64; BUCKETS.len() + 1],
+    disabled: AtomicBool,
+    window: Mutex<SlowCalls>,
+}
+
+/// Snapshot of plugin call stats.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BudgetStats {
+    pub name: String,
+    pub calls: u64,
+    pub slow_calls: u64,
+    /// Total time spent in the plugin.
+    pub time: Duration,
+    /// Cumulative call count for each of [`BUCKETS`].
+    pub buckets: Vec<u64>,
+    pub disabled: bool,
+}
+
+impl Budget {
+    /// Create budget for the plugin.
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            calls: AtomicU64::new(0),
+            slow_calls: AtomicU64::new(0),
+            time: AtomicU64::new(0),
+            buckets: Default::default(),
+            disabled: AtomicBool::new(false),
+            window: Mutex::new(SlowCalls::default()),
+        }
+    }
+
+    /// Plugin was disabled for being too slow.
+    pub fn disabled(&self) -> bool {
+        self.disabled.load(Ordering::Relaxed)
+    }
+
+    /// Call the plugin's route function and measure how long it took.
+    /// Returns `None` without calling the plugin if it's been disabled.
+    pub fn route(
+        &self,
+        plugin: &Plugin,
+        context: PdRouterContext,
+        timeout: Option<Duration>,
+        action: PluginSlowAction,
+    ) -> Option<PdRoute> {
+        if self.disabled() {
+            return None;
+        }
+
+        let start = Instant::now();
+        let route = plugin.route(context);
+        let now = Instant::now();
+        self.record(now.duration_since(start), now, timeout, action);
+
+        route
+    }
+
+    /// Record a call. Returns true if the plugin was disabled by it.
+    pub(crate) fn record(
+        &self,
+        elapsed: Duration,
+        now: Instant,
+        timeout: Option<Duration>,
+        action: PluginSlowAction,
+    ) -> bool {
+        self.calls.fetch_add(1, Ordering::Relaxed);
+        self.time
+            .fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+        let ms = elapsed.as_secs_f64() * 1000.0;
+        let bucket = BUCKETS
+            .iter()
+            .position(|le| ms <= *le)
+            .unwrap_or(BUCKETS.len());
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+
+        match timeout {
+            Some(timeout) if elapsed > timeout => (),
+            _ => return false,
+        }
+
+        self.slow_calls.fetch_add(1, Ordering::Relaxed);
+
+        let slow_calls = {
+            let mut window = self.window.lock();
+            match window.started {
+                Some(started) if now.duration_since(started) < SLOW_WINDOW => window.count += 1,
+                _ => {
+                    window.started = Some(now);
+                    window.count = 1;
+                }
+            }
+            window.count
+        };
+
+        // Log once per window, not on every slow call.
+        if slow_calls != SLOW_CALLS {
+            return false;
+        }
+
+        match action {
+            PluginSlowAction::Warn => {
+                warn!(
+                    "plugin \"{}\" went over its {:.3}ms budget {} times in {}s, last call took {:.3}ms",
+                    self.name,
+                    timeout.unwrap_or_default().as_secs_f64() * 1000.0,
+                    SLOW_CALLS,
+                    SLOW_WINDOW.as_secs(),
+                    ms,
+                );
+                false
+            }
+
+            PluginSlowAction::Disable => {
+                let disabled = !self.disabled.swap(true, Ordering::Relaxed);
+                if disabled {
+                    error!(
+                        "plugin \"{}\" went over its {:.3}ms budget {} times in {}s, disabling it until restart",
+                        self.name,
+                        timeout.unwrap_or_default().as_secs_f64() * 1000.0,
+                        SLOW_CALLS,
+                        SLOW_WINDOW.as_secs(),
+                    );
+                }
+                disabled
+            }
+        }
+    }
+
+    /// Get call stats.
+    pub fn stats(&self) -> BudgetStats {
+        let buckets = self
+            .buckets
+            .iter()
+            .scan(0, |total, bucket| {
+                *total += bucket.load(Ordering::Relaxed);
+                Some(*total)
+            })
+            .take(BUCKETS.len())
+            .collect();
+
+        BudgetStats {
+            name: self.name.clone(),
+            calls: self.calls.load(Ordering::Relaxed),
+            slow_calls: self.slow_calls.load(Ordering::Relaxed),
+            time: Duration::from_micros(self.time.load(Ordering::Relaxed)),
+            buckets,
+            disabled: self.disabled(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_budget() {
+        let budget = Budget::new("test");
+        let timeout = Some(Duration::from_millis(1));
+        let now = Instant::now();
+        let slow = Duration::from_millis(2);
+
+        budget.record(
+            Duration::from_micros(80),
+            now,
+            timeout,
+            PluginSlowAction::Disable,
+        );
+        let stats = budget.stats();
+        assert_eq!(stats.calls, 1);
+        assert_eq!(stats.slow_calls, 0);
+        assert_eq!(stats.buckets, vec![0, 1, 1, 1, 1, 1, 1, 1]);
+
+        // Slow calls spread over more than the window don't add up.
+        for i in 0..SLOW_CALLS {
+            let now = now + SLOW_WINDOW * i as u32;
+            assert!(!budget.record(slow, now, timeout, PluginSlowAction::Disable));
+        }
+        assert!(!budget.disabled());
+
+        let later = now + SLOW_WINDOW * 10;
+        for _ in 0..SLOW_CALLS - 1 {
+            assert!(!budget.record(slow, later, timeout, PluginSlowAction::Disable));
+        }
+        assert!(budget.record(slow, later, timeout, PluginSlowAction::Disable));
+        assert!(budget.disabled());
+
+        let stats = budget.stats();
+        assert_eq!(stats.calls, 1 + SLOW_CALLS as u64 * 2);
+        assert_eq!(stats.slow_calls, SLOW_CALLS as u64 * 2);
+        assert!(stats.disabled);
+    }
+
+    #[test]
+    fn test_budget_warn() {
+        let budget = Budget::new("test");
+        let timeout = Some(Duration::from_millis(1));
+        let now = Instant::now();
+
+        for _ in 0..SLOW_CALLS * 2 {
+            budget.record(
+                Duration::from_millis(2),
+                now,
+                timeout,
+                PluginSlowAction::Warn,
+            );
+        }
+        assert!(!budget.disabled());
+        assert_eq!(budget.stats().slow_calls, SLOW_CALLS as u64 * 2);
+
+        // No budget, nothing is slow.
+        let budget = Budget::new("test");
+        budget.record(Duration::from_secs(1), now, None, PluginSlowAction::Disable);
+        assert_eq!(budget.stats().slow_calls, 0);
+        assert_eq!(budget.stats().buckets[BUCKETS.len() - 1], 0);
+    }
+}
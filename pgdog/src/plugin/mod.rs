@@ -1,16 +1,28 @@
 //! pgDog plugins.
 
+pub mod budget;
+
+use std::collections::HashMap;
 use std::ops::Deref;
 
-use once_cell::sync::OnceCell;
+use once_cell::sync::{Lazy, OnceCell};
+use parking_lot::Mutex;
 use pgdog_plugin::libloading::Library;
 use pgdog_plugin::Plugin;
 use pgdog_plugin::{comp, libloading};
 use tokio::time::Instant;
 use tracing::{debug, error, info, warn};
 
+pub use budget::Budget;
+
+use crate::config::Plugin as PluginConfig;
+
 static LIBS: OnceCell<Vec<Library>> = OnceCell::new();
 pub static PLUGINS: OnceCell<Vec<Plugin>> = OnceCell::new();
+/// Call stats and time budget of each plugin, in the same order as [`PLUGINS`].
+static BUDGETS: OnceCell<Vec<Budget>> = OnceCell::new();
+/// Configuration last passed to each plugin, as JSON.
+static CONFIGS: Lazy<Mutex<HashMap<String, String>>> = Lazy::new(|| Mutex::new(HashMap::new()));
 
 /// Load plugins.
 ///
@@ -18,11 +30,16 @@ pub static PLUGINS: OnceCell<Vec<Plugin>> = OnceCell::new();
 ///
 /// This should be run before Tokio is loaded since this is not thread-safe.
 ///
-pub fn load(names: &[&str]) -> Result<(), libloading::Error> {
+pub fn load(configs: &[PluginConfig]) -> Result<(), libloading::Error> {
     if LIBS.get().is_some() {
         return Ok(());
     };
 
+    let names = configs
+        .iter()
+        .map(|plugin| plugin.name.as_str())
+        .collect::<Vec<_>>();
+
     let mut libs = vec![];
     for plugin in names.iter() {
         match Plugin::library(plugin) {
@@ -38,11 +55,19 @@ pub fn load(names: &[&str]) -> Result<(), libloading::Error> {
     let rustc_version = comp::rustc_version();
 
     let mut plugins = vec![];
+    let mut budgets = vec![];
     for (i, name) in names.iter().enumerate() {
         if let Some(lib) = LIBS.get().unwrap().get(i) {
             let now = Instant::now();
             let plugin = Plugin::load(name, lib);
 
+            // Check the FFI interface first. If it doesn't match,
+            // calling anything else in the plugin isn't safe.
+            if let Err(err) = plugin.check_abi() {
+                error!("skipping plugin \"{}\": {}", plugin.name(), err);
+                continue;
+            }
+
             // Check Rust compiler version.
             if let Some(plugin_rustc) = plugin.rustc_version() {
                 if rustc_version != plugin_rustc {
@@ -60,6 +85,26 @@ pub fn load(names: &[&str]) -> Result<(), libloading::Error> {
                 continue;
             }
 
+            debug!(
+                "plugin \"{}\" built with pg_query v{}",
+                plugin.name(),
+                plugin.pg_query_version().unwrap_or_default().deref()
+            );
+
+            // Configuration may contain secrets, don't log it.
+            if configs[i].config.is_some() {
+                let json = configs[i].config_json();
+                if let Err(err) = plugin.config(&json) {
+                    error!(
+                        "skipping plugin \"{}\": it rejected its configuration: {}",
+                        plugin.name(),
+                        err
+                    );
+                    continue;
+                }
+                CONFIGS.lock().insert(name.to_string(), json);
+            }
+
             if plugin.init() {
                 debug!("plugin \"{}\" initialized", name);
             }
@@ -72,10 +117,12 @@ pub fn load(names: &[&str]) -> Result<(), libloading::Error> {
             );
 
             plugins.push(plugin);
+            budgets.push(Budget::new(name));
         }
     }
 
     let _ = PLUGINS.set(plugins);
+    let _ = BUDGETS.set(budgets);
 
     Ok(())
 }
@@ -103,16 +150,104 @@ pub fn plugins() -> Option<&'static Vec<Plugin<'static>>> {
     PLUGINS.get()
 }
 
+/// Get call stats and time budget of all loaded plugins.
+pub fn budgets() -> &'static [Budget] {
+    BUDGETS
+        .get()
+        .map(|budgets| budgets.as_slice())
+        .unwrap_or_default()
+}
+
 /// Load plugins from config.
 pub fn load_from_config() -> Result<(), libloading::Error> {
     let config = crate::config::config();
 
-    let plugins = &config
-        .config
-        .plugins
-        .iter()
-        .map(|s| s.name.as_str())
-        .collect::<Vec<_>>();
+    load(&config.config.plugins)
+}
+
+/// Pass loaded plugins their new configuration, if it changed.
+///
+/// Plugins can't be added or removed without a restart. If a plugin
+/// rejects its new configuration, it keeps using the old one.
+pub fn reload(configs: &[PluginConfig]) {
+    let Some(plugins) = plugins() else {
+        return;
+    };
+
+    let mut loaded = CONFIGS.lock();
 
-    load(plugins)
+    for config in configs {
+        let Some(plugin) = plugins.iter().find(|plugin| plugin.name() == config.name) else {
+            continue;
+        };
+
+        reload_config(&mut loaded, config, |json| plugin.config(json));
+    }
+}
+
+/// Pass the plugin its configuration, if it changed since it was last passed.
+/// If it was removed from the config, the plugin gets `null`.
+fn reload_config(
+    loaded: &mut HashMap<String, String>,
+    config: &PluginConfig,
+    apply: impl FnOnce(&str) -> Result<(), String>,
+) {
+    let json = config.config_json();
+    let old = loaded
+        .get(&config.name)
+        .map(|json| json.as_str())
+        .unwrap_or("null");
+    if old == json {
+        return;
+    }
+
+    match apply(&json) {
+        Ok(()) => {
+            info!("plugin \"{}\" configuration reloaded", config.name);
+            loaded.insert(config.name.clone(), json);
+        }
+        Err(err) => error!(
+            "plugin \"{}\" rejected its new configuration, keeping the old one: {}",
+            config.name, err
+        ),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_reload_config() {
+        let mut loaded = HashMap::new();
+        let mut config = PluginConfig {
+            name: "test".into(),
+            config: Some(toml::from_str("write_delay = 10").unwrap()),
+        };
+
+        let mut passed = None;
+        reload_config(&mut loaded, &config, |json| {
+            passed = Some(json.to_string());
+            Ok(())
+        });
+        assert_eq!(passed.as_deref(), Some(r#"{"write_delay":10}"#));
+
+        // Unchanged, not passed again.
+        reload_config(&mut loaded, &config, |_| panic!("config didn't change"));
+
+        // Rejected, the plugin keeps the old one.
+        config.config = Some(toml::from_str("write_delay = 0").unwrap());
+        reload_config(&mut loaded, &config, |_| Err("write_delay is 0".into()));
+        assert_eq!(loaded["test"], r#"{"write_delay":10}"#);
+
+        // Removed from the config, the plugin goes back to its defaults.
+        config.config = None;
+        let mut passed = None;
+        reload_config(&mut loaded, &config, |json| {
+            passed = Some(json.to_string());
+            Ok(())
+        });
+        assert_eq!(passed.as_deref(), Some("null"));
+        assert_eq!(loaded["test"], "null");
+    }
 }
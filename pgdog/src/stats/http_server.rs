@@ -10,9 +10,34 @@ use hyper_util::rt::TokioIo;
 use tokio::net::TcpListener;
 use tracing::info;
 
-use super::{Clients, Pools, QueryCache};
+use crate::admin::http as admin_http;
+
+use super::{
+    Clients, Listener, MemoryReport, Metric, Plugins, Pools, QueryCache, ReadConsistency,
+    ReplicationSlots, Router, ShardSkew, StatsSnapshot,
+};
+
+fn stats_json() -> Response<Full<Bytes>> {
+    let snapshot = StatsSnapshot::load();
+
+    match serde_json::to_vec(&snapshot) {
+        Ok(body) => Response::builder()
+            .header(hyper::header::CONTENT_TYPE, "application/json")
+            .body(Full::new(Bytes::from(body)))
+            .unwrap_or_else(|_| Response::new(Full::new(Bytes::from("Stats unavailable")))),
+        Err(_) => Response::new(Full::new(Bytes::from("Stats unavailable"))),
+    }
+}
+
+async fn metrics(req: Request<hyper::body::Incoming>) -> Result<Response<Full<Bytes>>, Infallible> {
+    if admin_http::is_admin_path(req.uri().path()) {
+        return Ok(admin_http::handle(&req));
+    }
+
+    if req.uri().path() == "/stats.json" {
+        return Ok(stats_json());
+    }
 
-async fn metrics(_: Request<hyper::body::Incoming>) -> Result<Response<Full<Bytes>>, Infallible> {
     let clients = Clients::load();
     let pools = Pools::load();
     let query_cache: Vec<_> = QueryCache::load()
@@ -21,7 +46,62 @@ async fn metrics(_: Request<hyper::body::Incoming>) -> Result<Response<Full<Byte
         .map(|m| m.to_string())
         .collect();
     let query_cache = query_cache.join("\n");
-    let metrics_data = clients.to_string() + "\n" + &pools.to_string() + "\n" + &query_cache;
+    let plugins: Vec<_> = Plugins::load()
+        .metrics()
+        .into_iter()
+        .map(|m| m.to_string())
+        .collect();
+    let plugins = plugins.join("\n");
+    let read_consistency: Vec<_> = ReadConsistency::load()
+        .metrics()
+        .into_iter()
+        .map(|m| m.to_string())
+        .collect();
+    let read_consistency = read_consistency.join("\n");
+    let router: Vec<_> = Router::load()
+        .metrics()
+        .into_iter()
+        .map(|m| m.to_string())
+        .collect();
+    let router = router.join("\n");
+    let listener: Vec<_> = Listener::load()
+        .metrics()
+        .into_iter()
+        .map(|m| m.to_string())
+        .collect();
+    let listener = listener.join("\n");
+    let shard_skew: Vec<_> = ShardSkew::load()
+        .metrics()
+        .into_iter()
+        .map(|m| m.to_string())
+        .collect();
+    let shard_skew = shard_skew.join("\n");
+    let replication: Vec<_> = ReplicationSlots::load()
+        .metrics()
+        .into_iter()
+        .map(|m| m.to_string())
+        .collect();
+    let replication = replication.join("\n");
+    let memory = Metric::new(MemoryReport::load());
+    let metrics_data = clients.to_string()
+        + "\n"
+        + &pools.to_string()
+        + "\n"
+        + &query_cache
+        + "\n"
+        + &plugins
+        + "\n"
+        + &read_consistency
+        + "\n"
+        + &router
+        + "\n"
+        + &listener
+        + "\n"
+        + &shard_skew
+        + "\n"
+        + &replication
+        + "\n"
+        + &memory.to_string();
     let response = Response::builder()
         .header(
             hyper::header::CONTENT_TYPE,
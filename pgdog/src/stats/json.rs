@@ -0,0 +1,321 @@
+//! Stats snapshot in JSON, served at `/stats.json`.
+//!
+//! Each registry is read once: pools from the current set of databases, with
+//! each pool's state taken under its lock, and clients copied under one lock.
+//! Nothing is serialized until the snapshot is complete.
+//!
+//! Fields are only added within a [`VERSION`]. Removing or changing a field
+//! requires a new version.
+
+use std::collections::BTreeMap;
+
+use chrono::SecondsFormat;
+use serde::Serialize;
+
+use crate::backend::databases::databases;
+use crate::backend::pool::stats::Stats as PoolCounters;
+use crate::config::config;
+use crate::frontend::comms::comms;
+use crate::plugin::budgets;
+
+use super::MemoryReport;
+
+/// Snapshot format version.
+pub const VERSION: u32 = 1;
+/// Maximum number of clients included in the snapshot.
+pub const MAX_CLIENTS: usize = 1_000;
+
+/// Stats snapshot.
+#[derive(Debug, Clone, Serialize)]
+pub struct StatsSnapshot {
+    pub version: u32,
+    /// Hash of the loaded configuration, changes on reload.
+    pub config_digest: String,
+    pub pools: Vec<PoolStats>,
+    pub clients: ClientsStats,
+    /// Memory used by each category, in bytes.
+    pub memory: BTreeMap<&'static str, usize>,
+    pub plugins: Vec<PluginStats>,
+}
+
+/// Counters and gauges of one pool.
+#[derive(Debug, Clone, Serialize)]
+pub struct PoolStats {
+    pub database: String,
+    pub user: String,
+    pub host: String,
+    pub port: u16,
+    pub shard: usize,
+    pub role: String,
+    pub cl_waiting: usize,
+    pub sv_idle: usize,
+    pub sv_active: usize,
+    pub sv_locked: usize,
+    pub sv_draining: usize,
+    pub sv_total: usize,
+    pub maxwait_ms: f64,
+    pub avgwait_ms: f64,
+    pub paused: bool,
+    pub banned: bool,
+    pub online: bool,
+    pub errors: usize,
+    pub out_of_sync: usize,
+    pub stats: PoolCounters,
+}
+
+/// Connected clients.
+#[derive(Debug, Clone, Serialize)]
+pub struct ClientsStats {
+    /// All connected clients.
+    pub total: usize,
+    /// Only the first [`MAX_CLIENTS`] are listed.
+    pub truncated: bool,
+    pub clients: Vec<ClientStats>,
+}
+
+/// Summary of one client.
+#[derive(Debug, Clone, Serialize)]
+pub struct ClientStats {
+    pub id: i32,
+    pub user: String,
+    pub database: String,
+    pub addr: String,
+    pub port: u16,
+    pub state: String,
+    pub connect_time: String,
+    pub bytes_sent: usize,
+    pub bytes_received: usize,
+    pub transactions: usize,
+    pub queries: usize,
+    pub errors: usize,
+    pub memory_used: usize,
+}
+
+/// Call stats of one plugin.
+#[derive(Debug, Clone, Serialize)]
+pub struct PluginStats {
+    pub name: String,
+    pub calls: u64,
+    pub slow_calls: u64,
+    pub time_ms: f64,
+    pub disabled: bool,
+}
+
+impl StatsSnapshot {
+    /// Take a snapshot.
+    pub fn load() -> Self {
+        Self {
+            version: VERSION,
+            config_digest: config().digest.clone(),
+            pools: Self::pools(),
+            clients: Self::clients(),
+            memory: MemoryReport::load().categories().into_iter().collect(),
+            plugins: budgets()
+                .iter()
+                .map(|budget| {
+                    let stats = budget.stats();
+                    PluginStats {
+                        name: stats.name,
+                        calls: stats.calls,
+                        slow_calls: stats.slow_calls,
+                        time_ms: stats.time.as_secs_f64() * 1000.0,
+                        disabled: stats.disabled,
+                    }
+                })
+                .collect(),
+        }
+    }
+
+    fn pools() -> Vec<PoolStats> {
+        let mut pools = vec![];
+
+        for (user, cluster) in databases().all() {
+            for (shard_num, shard) in cluster.shards().iter().enumerate() {
+                for (role, pool) in shard.pools_with_roles() {
+                    let state = pool.state();
+                    pools.push(PoolStats {
+                        database: user.database.clone(),
+                        user: user.user.clone(),
+                        host: pool.addr().host.clone(),
+                        port: pool.addr().port,
+                        shard: shard_num,
+                        role: role.to_string(),
+                        cl_waiting: state.waiting,
+                        sv_idle: state.idle,
+                        sv_active: state.checked_out,
+                        sv_locked: state.locked,
+                        sv_draining: state.draining,
+                        sv_total: state.total,
+                        maxwait_ms: state.maxwait.as_secs_f64() * 1000.0,
+                        avgwait_ms: state.avgwait.as_secs_f64() * 1000.0,
+                        paused: state.paused,
+                        banned: state.banned,
+                        online: state.online,
+                        errors: state.errors,
+                        out_of_sync: state.out_of_sync,
+                        stats: state.stats,
+                    });
+                }
+            }
+        }
+
+        pools
+    }
+
+    fn clients() -> ClientsStats {
+        let (total, clients) = comms().clients_limit(MAX_CLIENTS);
+
+        let clients = clients
+            .into_iter()
+            .map(|(id, client)| {
+                let user = client.paramters.get_default("user", "postgres");
+                ClientStats {
+                    id: id.pid,
+                    user: user.to_owned(),
+                    database: client.paramters.get_default("database", user).to_owned(),
+                    addr: client.addr.ip().to_string(),
+                    port: client.addr.port(),
+                    state: client.stats.current.state.to_string(),
+                    connect_time: client
+                        .connected_at
+                        .to_rfc3339_opts(SecondsFormat::Millis, false),
+                    bytes_sent: client.stats.totals.bytes_sent,
+                    bytes_received: client.stats.totals.bytes_received,
+                    transactions: client.stats.totals.transactions,
+                    queries: client.stats.totals.queries,
+                    errors: client.stats.totals.errors,
+                    memory_used: client.stats.current.memory_used,
+                }
+            })
+            .collect();
+
+        ClientsStats {
+            total,
+            truncated: total > MAX_CLIENTS,
+            clients,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde::Deserialize;
+
+    use crate::config::test::load_test;
+
+    use super::*;
+
+    /// Format of version 1. If this test fails, the change breaks
+    /// tools reading the snapshot: bump [`VERSION`] and add the new format instead.
+    #[allow(dead_code)]
+    mod v1 {
+        use std::collections::BTreeMap;
+
+        use super::Deserialize;
+
+        #[derive(Deserialize)]
+        pub struct StatsSnapshot {
+            pub version: u32,
+            pub config_digest: String,
+            pub pools: Vec<PoolStats>,
+            pub clients: ClientsStats,
+            pub memory: BTreeMap<String, usize>,
+            pub plugins: Vec<PluginStats>,
+        }
+
+        #[derive(Deserialize)]
+        pub struct PoolStats {
+            pub database: String,
+            pub user: String,
+            pub host: String,
+            pub port: u16,
+            pub shard: usize,
+            pub role: String,
+            pub cl_waiting: usize,
+            pub sv_idle: usize,
+            pub sv_active: usize,
+            pub sv_locked: usize,
+            pub sv_total: usize,
+            pub maxwait_ms: f64,
+            pub paused: bool,
+            pub banned: bool,
+            pub online: bool,
+            pub errors: usize,
+            pub out_of_sync: usize,
+            pub stats: PoolCounters,
+        }
+
+        #[derive(Deserialize)]
+        pub struct PoolCounters {
+            pub counts: Counts,
+            pub averages: Counts,
+        }
+
+        #[derive(Deserialize)]
+        pub struct Counts {
+            pub xact_count: usize,
+            pub query_count: usize,
+            pub received: usize,
+            pub sent: usize,
+            pub xact_time: Duration,
+            pub query_time: Duration,
+            pub wait_time: Duration,
+        }
+
+        #[derive(Deserialize)]
+        pub struct Duration {
+            pub secs: u64,
+            pub nanos: u32,
+        }
+
+        #[derive(Deserialize)]
+        pub struct ClientsStats {
+            pub total: usize,
+            pub truncated: bool,
+            pub clients: Vec<ClientStats>,
+        }
+
+        #[derive(Deserialize)]
+        pub struct ClientStats {
+            pub id: i32,
+            pub user: String,
+            pub database: String,
+            pub addr: String,
+            pub port: u16,
+            pub state: String,
+            pub connect_time: String,
+            pub bytes_sent: usize,
+            pub bytes_received: usize,
+            pub transactions: usize,
+            pub queries: usize,
+            pub errors: usize,
+            pub memory_used: usize,
+        }
+
+        #[derive(Deserialize)]
+        pub struct PluginStats {
+            pub name: String,
+            pub calls: u64,
+            pub slow_calls: u64,
+            pub time_ms: f64,
+            pub disabled: bool,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stats_json_v1() {
+        load_test();
+
+        let json = serde_json::to_string(&StatsSnapshot::load()).unwrap();
+        let snapshot: v1::StatsSnapshot = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(snapshot.version, 1);
+        assert!(snapshot.config_digest.is_empty());
+        assert_eq!(snapshot.pools.len(), 1);
+        assert_eq!(snapshot.pools[0].database, "pgdog");
+        assert_eq!(snapshot.pools[0].role, "primary");
+        assert_eq!(snapshot.clients.total, snapshot.clients.clients.len());
+        assert!(!snapshot.clients.truncated);
+        assert!(snapshot.memory.contains_key("clients"));
+    }
+}
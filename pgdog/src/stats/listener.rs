@@ -0,0 +1,69 @@
+//! Client connections accepted by the listener.
+
+use crate::frontend::accept::{AcceptStats, Accepts};
+
+use super::*;
+
+/// Listener metrics.
+pub struct Listener {
+    accepts: Accepts,
+}
+
+struct ListenerMetric {
+    name: String,
+    help: String,
+    counter: bool,
+    value: MeasurementType,
+}
+
+impl Listener {
+    pub(crate) fn load() -> Self {
+        Self {
+            accepts: AcceptStats::get().stats(),
+        }
+    }
+
+    pub(crate) fn metrics(&self) -> Vec<Metric> {
+        vec![
+            Metric::new(ListenerMetric {
+                name: "total_accepted".into(),
+                help: "Total number of client connections accepted".into(),
+                counter: true,
+                value: MeasurementType::Integer(self.accepts.accepted as i64),
+            }),
+            Metric::new(ListenerMetric {
+                name: "total_accept_errors".into(),
+                help: "Total number of errors accepting client connections".into(),
+                counter: true,
+                value: MeasurementType::Integer(self.accepts.errors as i64),
+            }),
+            Metric::new(ListenerMetric {
+                name: "accepted_per_sec".into(),
+                help: "Client connections accepted per second".into(),
+                counter: false,
+                value: self.accepts.accepted_per_sec.into(),
+            }),
+        ]
+    }
+}
+
+impl OpenMetric for ListenerMetric {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn metric_type(&self) -> String {
+        if self.counter { "counter" } else { "gauge" }.into()
+    }
+
+    fn help(&self) -> Option<String> {
+        Some(self.help.clone())
+    }
+
+    fn measurements(&self) -> Vec<Measurement> {
+        vec![Measurement {
+            labels: vec![],
+            measurement: self.value.clone(),
+        }]
+    }
+}
@@ -39,8 +39,8 @@ impl Logger {
                         let (stats, len) = Cache::stats();
 
                         info!(
-                            "[query cache stats] direct: {}, multi: {}, hits: {}, misses: {}, size: {}, direct hit rate: {:.3}%",
-                            stats.direct, stats.multi, stats.hits, stats.misses, len, (stats.direct as f64 / std::cmp::max(stats.direct + stats.multi, 1) as f64 * 100.0)
+                            "[query cache stats] direct: {}, multi: {}, hits: {}, misses: {}, evictions: {}, size: {}, direct hit rate: {:.3}%",
+                            stats.direct, stats.multi, stats.hits, stats.misses, stats.evictions, len, (stats.direct as f64 / std::cmp::max(stats.direct + stats.multi, 1) as f64 * 100.0)
                         );
                     }
                     _ = me.shutdown.notified() => break,
@@ -3,8 +3,31 @@ use lru::LruCache;
 use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::hash::Hash;
 
+/// Approximate memory used by a value.
+///
+/// Counts the value itself and the heap memory it owns, including
+/// capacity that's allocated but not used yet. Memory shared with other
+/// values, e.g. through `Bytes` or `Arc`, is left to its owner. Allocator
+/// overhead isn't counted.
 pub trait MemoryUsage {
     fn memory_usage(&self) -> usize;
+
+    /// Heap memory owned by the value, i.e. without the value itself.
+    #[inline(always)]
+    fn heap_usage(&self) -> usize
+    where
+        Self: Sized,
+    {
+        self.memory_usage()
+            .saturating_sub(std::mem::size_of::<Self>())
+    }
+}
+
+/// Estimate of a hash table's allocation: a slot
+/// and a control byte for each entry it has room for.
+#[inline(always)]
+fn hash_table<E>(capacity: usize) -> usize {
+    capacity * (std::mem::size_of::<E>() + 1)
 }
 
 macro_rules! impl_memory_usage_static {
@@ -36,68 +59,141 @@ impl_memory_usage_static!(bool);
 impl MemoryUsage for String {
     #[inline(always)]
     fn memory_usage(&self) -> usize {
-        self.capacity()
+        std::mem::size_of::<Self>() + self.capacity()
     }
 }
 
 impl<V: MemoryUsage> MemoryUsage for VecDeque<V> {
     #[inline(always)]
     fn memory_usage(&self) -> usize {
-        self.iter().map(|v| v.memory_usage()).sum::<usize>()
+        std::mem::size_of::<Self>()
+            + self.capacity() * std::mem::size_of::<V>()
+            + self.iter().map(V::heap_usage).sum::<usize>()
     }
 }
 
 impl<V: MemoryUsage> MemoryUsage for Vec<V> {
     #[inline(always)]
     fn memory_usage(&self) -> usize {
-        self.iter().map(|v| v.memory_usage()).sum::<usize>()
+        std::mem::size_of::<Self>()
+            + self.capacity() * std::mem::size_of::<V>()
+            + self.iter().map(V::heap_usage).sum::<usize>()
     }
 }
 
 impl<K: MemoryUsage, V: MemoryUsage> MemoryUsage for HashMap<K, V> {
     #[inline(always)]
     fn memory_usage(&self) -> usize {
-        self.iter()
-            .map(|(k, v)| k.memory_usage() + v.memory_usage())
-            .sum::<usize>()
+        std::mem::size_of::<Self>()
+            + hash_table::<(K, V)>(self.capacity())
+            + self
+                .iter()
+                .map(|(k, v)| k.heap_usage() + v.heap_usage())
+                .sum::<usize>()
     }
 }
 
 impl<K: MemoryUsage, V: MemoryUsage> MemoryUsage for BTreeMap<K, V> {
+    /// Tree nodes aren't counted, only the entries.
     #[inline(always)]
     fn memory_usage(&self) -> usize {
-        self.iter()
-            .map(|(k, v)| k.memory_usage() + v.memory_usage())
-            .sum::<usize>()
+        std::mem::size_of::<Self>()
+            + self
+                .iter()
+                .map(|(k, v)| k.memory_usage() + v.memory_usage())
+                .sum::<usize>()
     }
 }
 
 impl<V: MemoryUsage> MemoryUsage for HashSet<V> {
     #[inline(always)]
     fn memory_usage(&self) -> usize {
-        self.iter().map(|v| v.memory_usage()).sum::<usize>()
+        std::mem::size_of::<Self>()
+            + hash_table::<V>(self.capacity())
+            + self.iter().map(V::heap_usage).sum::<usize>()
     }
 }
 
 impl<K: MemoryUsage + Hash + Eq, V: MemoryUsage + Eq> MemoryUsage for LruCache<K, V> {
+    /// Each entry is allocated separately and linked to its neighbours,
+    /// and the hash table holds a pointer to it.
     #[inline(always)]
     fn memory_usage(&self) -> usize {
-        self.iter()
-            .map(|(k, v)| k.memory_usage() + v.memory_usage())
-            .sum::<usize>()
+        let links = 2 * std::mem::size_of::<usize>();
+        std::mem::size_of::<Self>()
+            + hash_table::<(usize, usize)>(self.len())
+            + self
+                .iter()
+                .map(|(k, v)| k.memory_usage() + v.memory_usage() + links)
+                .sum::<usize>()
     }
 }
 
 impl MemoryUsage for BytesMut {
     #[inline(always)]
     fn memory_usage(&self) -> usize {
-        self.capacity()
+        std::mem::size_of::<Self>() + self.capacity()
     }
 }
 
 impl MemoryUsage for Bytes {
+    /// The buffer can be shared, so only the handle is counted.
     #[inline(always)]
     fn memory_usage(&self) -> usize {
-        0
+        std::mem::size_of::<Self>()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_capacity_counted() {
+        let mut values: Vec<String> = vec![];
+        let empty = values.memory_usage();
+
+        values.reserve(64);
+        let reserved = values.memory_usage();
+        assert!(reserved >= empty + 64 * std::mem::size_of::<String>());
+
+        values.push("a".repeat(1024));
+        assert!(values.memory_usage() >= reserved + 1024);
+
+        values.clear();
+        assert_eq!(values.memory_usage(), reserved);
+
+        values.shrink_to_fit();
+        assert_eq!(values.memory_usage(), empty);
+    }
+
+    #[test]
+    fn test_hash_map() {
+        let mut map: HashMap<String, String> = HashMap::new();
+        let empty = map.memory_usage();
+
+        for i in 0..100 {
+            map.insert(i.to_string(), "a".repeat(100));
+        }
+        let full = map.memory_usage();
+        assert!(full >= empty + 100 * 100);
+
+        map.clear();
+        let cleared = map.memory_usage();
+        assert!(cleared < full);
+        assert!(cleared > empty);
+
+        map.shrink_to_fit();
+        assert_eq!(map.memory_usage(), empty);
+    }
+
+    #[test]
+    fn test_bytes() {
+        let mut buffer = BytesMut::with_capacity(4096);
+        assert!(buffer.memory_usage() >= 4096);
+
+        buffer.extend_from_slice(&[0; 4096]);
+        let shared = buffer.split().freeze();
+        assert_eq!(shared.memory_usage(), std::mem::size_of::<Bytes>());
     }
 }
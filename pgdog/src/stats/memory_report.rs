@@ -0,0 +1,154 @@
+//! Memory usage, by category.
+
+use std::net::SocketAddr;
+
+use crate::backend::databases::databases;
+use crate::backend::pool::connection::mirror;
+use crate::frontend::comms::comms;
+use crate::frontend::router::parser::Cache;
+use crate::frontend::PreparedStatements;
+use crate::net::messages::BackendKeyData;
+use crate::stats::memory::MemoryUsage;
+
+use super::{Measurement, OpenMetric};
+
+/// How many clients to list individually.
+pub const TOP_CLIENTS: usize = 10;
+
+/// Memory used by a single client.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClientMemory {
+    pub id: BackendKeyData,
+    pub addr: SocketAddr,
+    pub bytes: usize,
+}
+
+/// Snapshot of memory used by PgDog, by category.
+///
+/// Each category is read separately and no locks are held
+/// while the report is formatted.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryReport {
+    /// All client buffers.
+    pub clients: usize,
+    /// Clients using the most memory, largest first.
+    pub top_clients: Vec<ClientMemory>,
+    /// Global prepared statements cache.
+    pub prepared_statements: usize,
+    /// Query AST cache. Parse trees aren't counted.
+    pub query_cache: usize,
+    /// Requests waiting in mirror queues.
+    pub mirror_queues: usize,
+    /// Idle server connections and pool bookkeeping.
+    pub pools: usize,
+}
+
+impl MemoryReport {
+    /// Take a snapshot.
+    pub fn load() -> Self {
+        let mut clients: Vec<_> = comms()
+            .clients_memory_usage()
+            .into_iter()
+            .map(|(id, addr, bytes)| ClientMemory { id, addr, bytes })
+            .collect();
+        let total = clients.iter().map(|client| client.bytes).sum();
+        clients.sort_by(|a, b| b.bytes.cmp(&a.bytes));
+        clients.truncate(TOP_CLIENTS);
+
+        let prepared_statements = PreparedStatements::global().lock().memory_usage();
+
+        let mut pools = 0;
+        for cluster in databases().all().values() {
+            for shard in cluster.shards() {
+                for pool in shard.pools() {
+                    pools += pool.memory_usage();
+                }
+            }
+        }
+
+        Self {
+            clients: total,
+            top_clients: clients,
+            prepared_statements,
+            query_cache: Cache::memory_usage(),
+            mirror_queues: mirror::queued_memory(),
+            pools,
+        }
+    }
+
+    /// Memory used by each category.
+    pub fn categories(&self) -> Vec<(&'static str, usize)> {
+        vec![
+            ("clients", self.clients),
+            ("prepared_statements", self.prepared_statements),
+            ("query_cache", self.query_cache),
+            ("mirror_queues", self.mirror_queues),
+            ("pools", self.pools),
+        ]
+    }
+
+    /// Memory used by all categories.
+    pub fn total(&self) -> usize {
+        self.categories().iter().map(|(_, bytes)| bytes).sum()
+    }
+}
+
+impl OpenMetric for MemoryReport {
+    fn name(&self) -> String {
+        "memory_used".into()
+    }
+
+    fn measurements(&self) -> Vec<Measurement> {
+        self.categories()
+            .into_iter()
+            .map(|(category, bytes)| Measurement {
+                labels: vec![("category".into(), category.into())],
+                measurement: bytes.into(),
+            })
+            .collect()
+    }
+
+    fn help(&self) -> Option<String> {
+        Some("Approximate amount of bytes used, by category.".into())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::stats::Metric;
+
+    use super::*;
+
+    #[test]
+    fn test_memory_report_metric() {
+        let report = MemoryReport {
+            clients: 1024,
+            top_clients: vec![],
+            prepared_statements: 512,
+            query_cache: 256,
+            mirror_queues: 0,
+            pools: 128,
+        };
+        assert_eq!(report.total(), 1920);
+
+        let metric = Metric::new(report).to_string();
+        let mut lines = metric.lines();
+        assert_eq!(lines.next().unwrap(), "# TYPE memory_used gauge");
+        assert_eq!(
+            lines.next().unwrap(),
+            "# HELP memory_used Approximate amount of bytes used, by category."
+        );
+        assert_eq!(
+            lines.next().unwrap(),
+            r#"memory_used{category="clients"} 1024"#
+        );
+        assert!(metric.contains(r#"memory_used{category="pools"} 128"#));
+    }
+
+    #[test]
+    fn test_memory_report_load() {
+        let report = MemoryReport::load();
+        assert!(report.top_clients.len() <= TOP_CLIENTS);
+        assert!(report.clients >= report.top_clients.iter().map(|c| c.bytes).sum::<usize>());
+    }
+}
@@ -1,14 +1,30 @@
 //! Statistics.
 pub mod clients;
 pub mod http_server;
+pub mod json;
+pub mod listener;
 pub mod open_metric;
 pub mod pools;
 pub use open_metric::*;
 pub mod logger;
 pub mod memory;
+pub mod memory_report;
+pub mod plugins;
 pub mod query_cache;
+pub mod read_consistency;
+pub mod replication;
+pub mod router;
+pub mod shard_skew;
 
 pub use clients::Clients;
+pub use json::StatsSnapshot;
+pub use listener::Listener;
 pub use logger::Logger as StatsLogger;
+pub use memory_report::MemoryReport;
+pub use plugins::Plugins;
 pub use pools::{PoolMetric, Pools};
 pub use query_cache::QueryCache;
+pub use read_consistency::ReadConsistency;
+pub use replication::ReplicationSlots;
+pub use router::Router;
+pub use shard_skew::ShardSkew;
@@ -19,6 +19,11 @@ pub trait OpenMetric: Send + Sync {
     fn help(&self) -> Option<String> {
         None
     }
+    /// Measurements of samples named with a suffix,
+    /// e.g. `_bucket`, `_sum` and `_count` of a histogram.
+    fn suffixed_measurements(&self) -> Vec<(&'static str, Measurement)> {
+        vec![]
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -124,6 +129,14 @@ impl std::fmt::Display for Metric {
         for measurement in self.measurements() {
             writeln!(f, "{}{}", prefix, measurement.render(&name))?;
         }
+        for (suffix, measurement) in self.suffixed_measurements() {
+            writeln!(
+                f,
+                "{}{}",
+                prefix,
+                measurement.render(&format!("{}{}", name, suffix))
+            )?;
+        }
         Ok(())
     }
 }
@@ -0,0 +1,198 @@
+//! Plugin call stats.
+
+use crate::plugin::{
+    budget::{BudgetStats, BUCKETS},
+    budgets,
+};
+
+use super::*;
+
+/// Call stats of all loaded plugins.
+pub struct Plugins {
+    stats: Vec<BudgetStats>,
+}
+
+struct PluginMetric {
+    name: String,
+    help: String,
+    gauge: bool,
+    values: Vec<(String, i64)>,
+}
+
+/// Time spent in each plugin's route function.
+struct PluginDuration {
+    stats: Vec<BudgetStats>,
+}
+
+impl Plugins {
+    pub(crate) fn load() -> Self {
+        Self {
+            stats: budgets().iter().map(|budget| budget.stats()).collect(),
+        }
+    }
+
+    pub(crate) fn metrics(&self) -> Vec<Metric> {
+        let values = |value: fn(&BudgetStats) -> i64| {
+            self.stats
+                .iter()
+                .map(|stats| (stats.name.clone(), value(stats)))
+                .collect()
+        };
+
+        vec![
+            Metric::new(PluginMetric {
+                name: "plugin_route_calls".into(),
+                help: "Number of times the plugin routed a query".into(),
+                gauge: false,
+                values: values(|stats| stats.calls as i64),
+            }),
+            Metric::new(PluginMetric {
+                name: "plugin_route_slow_calls".into(),
+                help: "Number of plugin calls that took longer than plugin_timeout_ms".into(),
+                gauge: false,
+                values: values(|stats| stats.slow_calls as i64),
+            }),
+            Metric::new(PluginMetric {
+                name: "plugin_disabled".into(),
+                help: "The plugin was disabled for being too slow".into(),
+                gauge: true,
+                values: values(|stats| stats.disabled as i64),
+            }),
+            Metric::new(PluginDuration {
+                stats: self.stats.clone(),
+            }),
+        ]
+    }
+}
+
+fn labels(plugin: &str) -> Vec<(String, String)> {
+    vec![("plugin".into(), plugin.into())]
+}
+
+impl OpenMetric for PluginMetric {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn metric_type(&self) -> String {
+        if self.gauge {
+            "gauge".into()
+        } else {
+            "counter".into()
+        }
+    }
+
+    fn help(&self) -> Option<String> {
+        Some(self.help.clone())
+    }
+
+    fn measurements(&self) -> Vec<Measurement> {
+        self.values
+            .iter()
+            .map(|(plugin, value)| Measurement {
+                labels: labels(plugin),
+                measurement: MeasurementType::Integer(*value),
+            })
+            .collect()
+    }
+}
+
+impl OpenMetric for PluginDuration {
+    fn name(&self) -> String {
+        "plugin_route_duration".into()
+    }
+
+    fn metric_type(&self) -> String {
+        "histogram".into()
+    }
+
+    fn unit(&self) -> Option<String> {
+        Some("ms".into())
+    }
+
+    fn help(&self) -> Option<String> {
+        Some("Time spent routing a query in the plugin".into())
+    }
+
+    fn measurements(&self) -> Vec<Measurement> {
+        vec![]
+    }
+
+    fn suffixed_measurements(&self) -> Vec<(&'static str, Measurement)> {
+        let mut measurements = vec![];
+
+        for stats in &self.stats {
+            for (le, count) in BUCKETS.iter().zip(&stats.buckets) {
+                let mut bucket = labels(&stats.name);
+                bucket.push(("le".into(), le.to_string()));
+                measurements.push((
+                    "_bucket",
+                    Measurement {
+                        labels: bucket,
+                        measurement: MeasurementType::Integer(*count as i64),
+                    },
+                ));
+            }
+
+            let mut bucket = labels(&stats.name);
+            bucket.push(("le".into(), "+Inf".into()));
+            measurements.push((
+                "_bucket",
+                Measurement {
+                    labels: bucket,
+                    measurement: MeasurementType::Integer(stats.calls as i64),
+                },
+            ));
+            measurements.push((
+                "_sum",
+                Measurement {
+                    labels: labels(&stats.name),
+                    measurement: MeasurementType::Float(stats.time.as_secs_f64() * 1000.0),
+                },
+            ));
+            measurements.push((
+                "_count",
+                Measurement {
+                    labels: labels(&stats.name),
+                    measurement: MeasurementType::Integer(stats.calls as i64),
+                },
+            ));
+        }
+
+        measurements
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_plugin_duration_histogram() {
+        let metric = Metric::new(PluginDuration {
+            stats: vec![BudgetStats {
+                name: "slow".into(),
+                calls: 3,
+                slow_calls: 1,
+                time: Duration::from_millis(60),
+                buckets: vec![0, 0, 0, 1, 1, 2, 2, 3],
+                disabled: false,
+            }],
+        })
+        .to_string();
+
+        // Other tests can set a namespace.
+        let has = |line: &str| metric.lines().any(|l| l.ends_with(line));
+        assert!(has("plugin_route_duration histogram"));
+        assert!(has(
+            r#"plugin_route_duration_bucket{plugin="slow",le="1"} 1"#
+        ));
+        assert!(has(
+            r#"plugin_route_duration_bucket{plugin="slow",le="+Inf"} 3"#
+        ));
+        assert!(has(r#"plugin_route_duration_sum{plugin="slow"} 60.000"#));
+        assert!(has(r#"plugin_route_duration_count{plugin="slow"} 3"#));
+    }
+}
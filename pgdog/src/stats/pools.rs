@@ -46,7 +46,10 @@ impl Pools {
         let mut cl_waiting = vec![];
         let mut sv_active = vec![];
         let mut sv_idle = vec![];
+        let mut sv_locked = vec![];
+        let mut sv_draining = vec![];
         let mut maxwait = vec![];
+        let mut avgwait = vec![];
         let mut errors = vec![];
         let mut out_of_sync = vec![];
         let mut total_xact_count = vec![];
@@ -63,6 +66,15 @@ impl Pools {
         let mut avg_query_time = vec![];
         let mut total_close = vec![];
         let mut avg_close = vec![];
+        let mut total_rollbacks = vec![];
+        let mut avg_rollbacks = vec![];
+        let mut total_prewarm = vec![];
+        let mut avg_prewarm = vec![];
+        let mut shed_p95_wait = vec![];
+        let mut shed_percent = vec![];
+        let mut total_shed = vec![];
+        let mut avg_prepared_statements = vec![];
+        let mut healthcheck_failures = vec![];
         for (user, cluster) in databases().all() {
             for (shard_num, shard) in cluster.shards().iter().enumerate() {
                 for (role, pool) in shard.pools_with_roles() {
@@ -91,11 +103,26 @@ impl Pools {
                         measurement: state.idle.into(),
                     });
 
+                    sv_locked.push(Measurement {
+                        labels: labels.clone(),
+                        measurement: state.locked.into(),
+                    });
+
+                    sv_draining.push(Measurement {
+                        labels: labels.clone(),
+                        measurement: state.draining.into(),
+                    });
+
                     maxwait.push(Measurement {
                         labels: labels.clone(),
                         measurement: state.maxwait.as_secs_f64().into(),
                     });
 
+                    avgwait.push(Measurement {
+                        labels: labels.clone(),
+                        measurement: state.avgwait.as_secs_f64().into(),
+                    });
+
                     errors.push(Measurement {
                         labels: labels.clone(),
                         measurement: state.errors.into(),
@@ -179,6 +206,57 @@ impl Pools {
                         labels: labels.clone(),
                         measurement: averages.close.into(),
                     });
+
+                    total_rollbacks.push(Measurement {
+                        labels: labels.clone(),
+                        measurement: totals.rollbacks.into(),
+                    });
+
+                    avg_rollbacks.push(Measurement {
+                        labels: labels.clone(),
+                        measurement: averages.rollbacks.into(),
+                    });
+
+                    total_prewarm.push(Measurement {
+                        labels: labels.clone(),
+                        measurement: totals.prewarm_count.into(),
+                    });
+
+                    avg_prewarm.push(Measurement {
+                        labels: labels.clone(),
+                        measurement: averages.prewarm_count.into(),
+                    });
+
+                    let load_shedding = state.load_shedding;
+
+                    shed_p95_wait.push(Measurement {
+                        labels: labels.clone(),
+                        measurement: load_shedding.p95_wait.as_millis().into(),
+                    });
+
+                    shed_percent.push(Measurement {
+                        labels: labels.clone(),
+                        measurement: load_shedding.percent.into(),
+                    });
+
+                    total_shed.push(Measurement {
+                        labels: labels.clone(),
+                        measurement: load_shedding.shed.into(),
+                    });
+
+                    avg_prepared_statements.push(Measurement {
+                        labels: labels.clone(),
+                        measurement: state.avg_prepared_statements.into(),
+                    });
+
+                    for (class, failures) in state.healthcheck_failures.iter() {
+                        let mut labels = labels.clone();
+                        labels.push(("class".into(), class.into()));
+                        healthcheck_failures.push(Measurement {
+                            labels,
+                            measurement: failures.into(),
+                        });
+                    }
                 }
             }
         }
@@ -207,6 +285,22 @@ impl Pools {
             metric_type: None,
         }));
 
+        metrics.push(Metric::new(PoolMetric {
+            name: "sv_locked".into(),
+            measurements: sv_locked,
+            help: "Servers serving clients that are locked to them until they disconnect.".into(),
+            unit: None,
+            metric_type: None,
+        }));
+
+        metrics.push(Metric::new(PoolMetric {
+            name: "sv_draining".into(),
+            measurements: sv_draining,
+            help: "Servers returned by clients and being cleaned up before check-in.".into(),
+            unit: None,
+            metric_type: None,
+        }));
+
         metrics.push(Metric::new(PoolMetric {
             name: "maxwait".into(),
             measurements: maxwait,
@@ -215,6 +309,14 @@ impl Pools {
             metric_type: None,
         }));
 
+        metrics.push(Metric::new(PoolMetric {
+            name: "avgwait".into(),
+            measurements: avgwait,
+            help: "How long clients in line have been waiting for a connection, on average.".into(),
+            unit: Some("seconds".into()),
+            metric_type: None,
+        }));
+
         metrics.push(Metric::new(PoolMetric {
             name: "errors".into(),
             measurements: errors,
@@ -343,6 +445,83 @@ impl Pools {
             metric_type: None,
         }));
 
+        metrics.push(Metric::new(PoolMetric {
+            name: "total_rollbacks".into(),
+            measurements: total_rollbacks,
+            help: "Total number of transactions rolled back because the client disconnected."
+                .into(),
+            unit: None,
+            metric_type: Some("counter".into()),
+        }));
+
+        metrics.push(Metric::new(PoolMetric {
+            name: "avg_rollbacks".into(),
+            measurements: avg_rollbacks,
+            help: "Average number of transactions rolled back because the client disconnected."
+                .into(),
+            unit: None,
+            metric_type: None,
+        }));
+
+        metrics.push(Metric::new(PoolMetric {
+            name: "total_prepared_prewarm".into(),
+            measurements: total_prewarm,
+            help:
+                "Total number of prepared statements prepared on new connections before first use."
+                    .into(),
+            unit: None,
+            metric_type: Some("counter".into()),
+        }));
+
+        metrics.push(Metric::new(PoolMetric {
+            name: "avg_prepared_prewarm".into(),
+            measurements: avg_prewarm,
+            help: "Average number of prepared statements prepared on new connections before first use."
+                .into(),
+            unit: None,
+            metric_type: None,
+        }));
+
+        metrics.push(Metric::new(PoolMetric {
+            name: "shed_p95_wait".into(),
+            measurements: shed_p95_wait,
+            help: "p95 time clients waited for a connection, used by load shedding.".into(),
+            unit: Some("milliseconds".into()),
+            metric_type: None,
+        }));
+
+        metrics.push(Metric::new(PoolMetric {
+            name: "shed_percent".into(),
+            measurements: shed_percent,
+            help: "Percentage of new transactions currently rejected by load shedding.".into(),
+            unit: None,
+            metric_type: None,
+        }));
+
+        metrics.push(Metric::new(PoolMetric {
+            name: "total_shed".into(),
+            measurements: total_shed,
+            help: "Total number of transactions rejected by load shedding.".into(),
+            unit: None,
+            metric_type: Some("counter".into()),
+        }));
+
+        metrics.push(Metric::new(PoolMetric {
+            name: "avg_prepared_statements".into(),
+            measurements: avg_prepared_statements,
+            help: "Average number of prepared statements on idle server connections.".into(),
+            unit: None,
+            metric_type: None,
+        }));
+
+        metrics.push(Metric::new(PoolMetric {
+            name: "total_healthcheck_failures".into(),
+            measurements: healthcheck_failures,
+            help: "Total number of failed healthchecks, by failure class.".into(),
+            unit: None,
+            metric_type: Some("counter".into()),
+        }));
+
         Pools { metrics }
     }
 }
@@ -1,5 +1,6 @@
 use crate::{
     frontend::{
+        prepared_statements::ClusterUsage,
         router::parser::{cache::Stats, Cache},
         PreparedStatements,
     },
@@ -13,6 +14,8 @@ pub struct QueryCacheMetric {
     help: String,
     value: usize,
     gauge: bool,
+    /// Values by user and database, reported instead of `value`.
+    by_database: Vec<((String, String), usize)>,
 }
 
 pub struct QueryCache {
@@ -20,14 +23,23 @@ pub struct QueryCache {
     len: usize,
     prepared_statements: usize,
     prepared_statements_memory: usize,
+    /// Prepared statements and memory used, by user and database.
+    prepared_statements_by_database: Vec<((String, String), ClusterUsage)>,
 }
 
 impl QueryCache {
     pub(crate) fn load() -> Self {
-        let (prepared_statements, prepared_statements_memory) = {
+        let (prepared_statements, prepared_statements_memory, prepared_statements_by_database) = {
             let global = PreparedStatements::global();
             let guard = global.lock();
-            (guard.len(), guard.memory_usage())
+            let by_database = guard
+                .usage()
+                .into_iter()
+                .filter_map(|(cluster, usage)| {
+                    cluster.map(|cluster| ((cluster.user.clone(), cluster.database.clone()), usage))
+                })
+                .collect();
+            (guard.len(), guard.memory_usage(), by_database)
         };
 
         let (stats, len) = Cache::stats();
@@ -37,6 +49,7 @@ impl QueryCache {
             len,
             prepared_statements,
             prepared_statements_memory,
+            prepared_statements_by_database,
         }
     }
 
@@ -47,42 +60,115 @@ impl QueryCache {
                 help: "Queries already present in the query cache".into(),
                 value: self.stats.hits,
                 gauge: false,
+                by_database: vec![],
             }),
             Metric::new(QueryCacheMetric {
                 name: "query_cache_misses".into(),
                 help: "New queries added to the query cache".into(),
                 value: self.stats.misses,
                 gauge: false,
+                by_database: vec![],
             }),
             Metric::new(QueryCacheMetric {
                 name: "query_cache_direct".into(),
                 help: "Queries sent directly to a single shard".into(),
                 value: self.stats.direct,
                 gauge: false,
+                by_database: vec![],
             }),
             Metric::new(QueryCacheMetric {
                 name: "query_cache_cross".into(),
                 help: "Queries sent to multiple or all shards".into(),
                 value: self.stats.multi,
                 gauge: false,
+                by_database: vec![],
+            }),
+            Metric::new(QueryCacheMetric {
+                name: "query_cache_evictions".into(),
+                help: "Queries evicted from the query cache".into(),
+                value: self.stats.evictions,
+                gauge: false,
+                by_database: vec![],
+            }),
+            Metric::new(QueryCacheMetric {
+                name: "query_cache_parse_skipped".into(),
+                help:
+                    "Queries not parsed because they are longer than max_query_length_for_parsing"
+                        .into(),
+                value: self.stats.skipped,
+                gauge: false,
+                by_database: vec![],
+            }),
+            Metric::new(QueryCacheMetric {
+                name: "query_cache_parse_failed".into(),
+                help: "Queries the query parser failed on".into(),
+                value: self.stats.failed,
+                gauge: false,
+                by_database: vec![],
+            }),
+            Metric::new(QueryCacheMetric {
+                name: "query_cache_syntax_errors".into(),
+                help: "Queries with syntax errors".into(),
+                value: self.stats.syntax_errors,
+                gauge: false,
+                by_database: vec![],
+            }),
+            Metric::new(QueryCacheMetric {
+                name: "query_cache_syntax_error_hits".into(),
+                help: "Queries with syntax errors rejected without running the query parser".into(),
+                value: self.stats.syntax_error_hits,
+                gauge: false,
+                by_database: vec![],
+            }),
+            Metric::new(QueryCacheMetric {
+                name: "query_cache_last_sweep_duration".into(),
+                help: "How long the last query cache sweep took, in microseconds".into(),
+                value: self.stats.last_sweep_duration.as_micros() as usize,
+                gauge: true,
+                by_database: vec![],
             }),
             Metric::new(QueryCacheMetric {
                 name: "query_cache_size".into(),
                 help: "Number of queries in the cache".into(),
                 value: self.len,
                 gauge: true,
+                by_database: vec![],
             }),
             Metric::new(QueryCacheMetric {
                 name: "prepared_statements".into(),
                 help: "Number of prepared statements in the cache".into(),
                 value: self.prepared_statements,
                 gauge: true,
+                by_database: vec![],
             }),
             Metric::new(QueryCacheMetric {
                 name: "prepared_statements_memory_used".into(),
                 help: "Amount of bytes used for the prepared statements cache".into(),
                 value: self.prepared_statements_memory,
                 gauge: true,
+                by_database: vec![],
+            }),
+            Metric::new(QueryCacheMetric {
+                name: "prepared_statements_by_database".into(),
+                help: "Number of prepared statements in the cache, by user and database".into(),
+                value: 0,
+                gauge: true,
+                by_database: self
+                    .prepared_statements_by_database
+                    .iter()
+                    .map(|(cluster, usage)| (cluster.clone(), usage.statements))
+                    .collect(),
+            }),
+            Metric::new(QueryCacheMetric {
+                name: "prepared_statements_memory_used_by_database".into(),
+                help: "Amount of bytes used by prepared statements, by user and database".into(),
+                value: 0,
+                gauge: true,
+                by_database: self
+                    .prepared_statements_by_database
+                    .iter()
+                    .map(|(cluster, usage)| (cluster.clone(), usage.memory_used))
+                    .collect(),
             }),
         ]
     }
@@ -106,9 +192,22 @@ impl OpenMetric for QueryCacheMetric {
     }
 
     fn measurements(&self) -> Vec<Measurement> {
-        vec![Measurement {
-            labels: vec![],
-            measurement: MeasurementType::Integer(self.value as i64),
-        }]
+        if self.by_database.is_empty() {
+            vec![Measurement {
+                labels: vec![],
+                measurement: MeasurementType::Integer(self.value as i64),
+            }]
+        } else {
+            self.by_database
+                .iter()
+                .map(|((user, database), value)| Measurement {
+                    labels: vec![
+                        ("user".into(), user.clone()),
+                        ("database".into(), database.clone()),
+                    ],
+                    measurement: MeasurementType::Integer(*value as i64),
+                })
+                .collect()
+        }
     }
 }
@@ -0,0 +1,71 @@
+//! Reads by user read consistency tier.
+
+use crate::backend::pool::{read_consistency::TierStats, ReadConsistencyStats};
+
+use super::*;
+
+/// Read counters of all tiers.
+pub struct ReadConsistency {
+    stats: Vec<(&'static str, TierStats)>,
+}
+
+struct TierMetric {
+    name: String,
+    help: String,
+    values: Vec<(&'static str, u64)>,
+}
+
+impl ReadConsistency {
+    pub(crate) fn load() -> Self {
+        Self {
+            stats: ReadConsistencyStats::get().stats(),
+        }
+    }
+
+    pub(crate) fn metrics(&self) -> Vec<Metric> {
+        let values = |value: fn(&TierStats) -> u64| {
+            self.stats
+                .iter()
+                .map(|(tier, stats)| (*tier, value(stats)))
+                .collect()
+        };
+
+        vec![
+            Metric::new(TierMetric {
+                name: "read_consistency_reads".into(),
+                help: "Number of reads routed with the user's read consistency tier".into(),
+                values: values(|stats| stats.reads),
+            }),
+            Metric::new(TierMetric {
+                name: "read_consistency_primary_fallbacks".into(),
+                help: "Number of reads sent to the primary because no replica was fresh enough"
+                    .into(),
+                values: values(|stats| stats.primary_fallbacks),
+            }),
+        ]
+    }
+}
+
+impl OpenMetric for TierMetric {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn metric_type(&self) -> String {
+        "counter".into()
+    }
+
+    fn help(&self) -> Option<String> {
+        Some(self.help.clone())
+    }
+
+    fn measurements(&self) -> Vec<Measurement> {
+        self.values
+            .iter()
+            .map(|(tier, value)| Measurement {
+                labels: vec![("tier".into(), tier.to_string())],
+                measurement: MeasurementType::Integer(*value as i64),
+            })
+            .collect()
+    }
+}
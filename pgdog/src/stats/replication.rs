@@ -0,0 +1,70 @@
+//! Replication slots streaming in this process, e.g. during resharding.
+
+use crate::backend::replication::publisher::progress::{slots, SlotStats};
+
+use super::*;
+
+/// Replication slots and their lag.
+pub struct ReplicationSlots {
+    slots: Vec<SlotStats>,
+}
+
+struct SlotMetric {
+    name: String,
+    help: String,
+    values: Vec<(String, i64)>,
+}
+
+impl ReplicationSlots {
+    pub(crate) fn load() -> Self {
+        Self { slots: slots() }
+    }
+
+    pub(crate) fn metrics(&self) -> Vec<Metric> {
+        let values = |value: fn(&SlotStats) -> i64| {
+            self.slots
+                .iter()
+                .map(|slot| (slot.slot.clone(), value(slot)))
+                .collect()
+        };
+
+        vec![
+            Metric::new(SlotMetric {
+                name: "replication_slot_lag".into(),
+                help: "Bytes of WAL the source is holding for the replication slot".into(),
+                values: values(|slot| slot.lag as i64),
+            }),
+            Metric::new(SlotMetric {
+                name: "replication_paused".into(),
+                help:
+                    "Reading from the replication slot is paused until the destination catches up"
+                        .into(),
+                values: values(|slot| slot.paused as i64),
+            }),
+        ]
+    }
+}
+
+impl OpenMetric for SlotMetric {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn metric_type(&self) -> String {
+        "gauge".into()
+    }
+
+    fn help(&self) -> Option<String> {
+        Some(self.help.clone())
+    }
+
+    fn measurements(&self) -> Vec<Measurement> {
+        self.values
+            .iter()
+            .map(|(slot, value)| Measurement {
+                labels: vec![("slot".into(), slot.clone())],
+                measurement: MeasurementType::Integer(*value),
+            })
+            .collect()
+    }
+}
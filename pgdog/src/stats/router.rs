@@ -0,0 +1,60 @@
+//! Router errors by user, database and class.
+
+use crate::backend::databases::User;
+use crate::frontend::router::stats::RouterErrors;
+use crate::frontend::router::RouterStats;
+
+use super::*;
+
+/// Router errors of all users and databases.
+pub struct Router {
+    errors: Vec<(User, RouterErrors)>,
+}
+
+struct RouterErrorsMetric {
+    errors: Vec<(User, RouterErrors)>,
+}
+
+impl Router {
+    pub(crate) fn load() -> Self {
+        Self {
+            errors: RouterStats::get().all(),
+        }
+    }
+
+    pub(crate) fn metrics(&self) -> Vec<Metric> {
+        vec![Metric::new(RouterErrorsMetric {
+            errors: self.errors.clone(),
+        })]
+    }
+}
+
+impl OpenMetric for RouterErrorsMetric {
+    fn name(&self) -> String {
+        "router_errors".into()
+    }
+
+    fn metric_type(&self) -> String {
+        "counter".into()
+    }
+
+    fn help(&self) -> Option<String> {
+        Some("Queries rejected by the query router, by user, database and error class".into())
+    }
+
+    fn measurements(&self) -> Vec<Measurement> {
+        self.errors
+            .iter()
+            .flat_map(|(user, errors)| {
+                errors.iter().map(move |(class, value)| Measurement {
+                    labels: vec![
+                        ("user".into(), user.user.clone()),
+                        ("database".into(), user.database.clone()),
+                        ("class".into(), class.into()),
+                    ],
+                    measurement: MeasurementType::Integer(value as i64),
+                })
+            })
+            .collect()
+    }
+}
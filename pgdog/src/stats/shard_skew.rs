@@ -0,0 +1,99 @@
+//! Shard skew of cross-shard queries.
+
+use crate::backend::{
+    databases::databases,
+    pool::shard_skew::{ShardSkewStats, BUCKETS},
+};
+
+use super::*;
+
+/// Skew histograms of all clusters.
+pub struct ShardSkew {
+    stats: Vec<(Vec<(String, String)>, ShardSkewStats)>,
+}
+
+impl ShardSkew {
+    pub(crate) fn load() -> Self {
+        Self {
+            stats: databases()
+                .all()
+                .iter()
+                .map(|(user, cluster)| {
+                    (
+                        vec![
+                            ("user".into(), user.user.clone()),
+                            ("database".into(), user.database.clone()),
+                        ],
+                        cluster.shard_skew().stats(),
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    pub(crate) fn metrics(self) -> Vec<Metric> {
+        vec![Metric::new(self)]
+    }
+}
+
+impl OpenMetric for ShardSkew {
+    fn name(&self) -> String {
+        "shard_skew".into()
+    }
+
+    fn metric_type(&self) -> String {
+        "histogram".into()
+    }
+
+    fn help(&self) -> Option<String> {
+        Some("Share of rows returned by the busiest shard in cross-shard queries".into())
+    }
+
+    fn measurements(&self) -> Vec<Measurement> {
+        vec![]
+    }
+
+    fn suffixed_measurements(&self) -> Vec<(&'static str, Measurement)> {
+        let mut measurements = vec![];
+
+        for (labels, stats) in &self.stats {
+            for (le, count) in BUCKETS.iter().zip(&stats.buckets) {
+                let mut bucket = labels.clone();
+                bucket.push(("le".into(), le.to_string()));
+                measurements.push((
+                    "_bucket",
+                    Measurement {
+                        labels: bucket,
+                        measurement: MeasurementType::Integer(*count as i64),
+                    },
+                ));
+            }
+
+            let mut bucket = labels.clone();
+            bucket.push(("le".into(), "+Inf".into()));
+            measurements.push((
+                "_bucket",
+                Measurement {
+                    labels: bucket,
+                    measurement: MeasurementType::Integer(stats.count as i64),
+                },
+            ));
+            measurements.push((
+                "_sum",
+                Measurement {
+                    labels: labels.clone(),
+                    measurement: MeasurementType::Float(stats.sum),
+                },
+            ));
+            measurements.push((
+                "_count",
+                Measurement {
+                    labels: labels.clone(),
+                    measurement: MeasurementType::Integer(stats.count as i64),
+                },
+            ));
+        }
+
+        measurements
+    }
+}
@@ -0,0 +1,34 @@
+//! Trace spans for the transaction lifecycle, exported with OpenTelemetry.
+//!
+//! Spans are only created when PgDog is built with the `telemetry` feature
+//! and `[telemetry]` is enabled in the config. Without the feature,
+//! [`Spans`] does nothing and compiles away.
+//!
+//! Clients can correlate PgDog spans with their own traces by passing
+//! a `traceparent` in a sqlcommenter comment, e.g.:
+//!
+//! ```sql
+//! SELECT * FROM users /*traceparent='00-5bd66ef5095369c7b0d1f8f4bd33716a-c532cb4098ac3dd2-01'*/
+//! ```
+
+pub mod trace_parent;
+
+#[cfg(feature = "telemetry")]
+pub mod otel;
+#[cfg(feature = "telemetry")]
+pub use otel::{init, layer, shutdown, Spans};
+
+#[cfg(not(feature = "telemetry"))]
+pub mod noop;
+#[cfg(not(feature = "telemetry"))]
+pub use noop::{init, shutdown, Spans};
+
+pub use trace_parent::TraceParent;
+
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("telemetry exporter: {0}")]
+    Exporter(String),
+}
@@ -0,0 +1,54 @@
+//! Telemetry without the `telemetry` feature. Does nothing.
+
+use std::fmt::Display;
+use std::time::Duration;
+
+use tracing::warn;
+
+use super::Error;
+use crate::{
+    backend::pool::Connection,
+    config::Telemetry,
+    frontend::{router::Route, ClientRequest},
+    net::Message,
+};
+
+/// Start exporting spans.
+pub fn init(config: &Telemetry) -> Result<(), Error> {
+    if config.enabled {
+        warn!("telemetry is enabled, but PgDog was built without the \"telemetry\" feature");
+    }
+
+    Ok(())
+}
+
+/// Flush spans before exiting.
+pub fn shutdown() {}
+
+/// Transaction lifecycle spans.
+#[derive(Debug, Default)]
+pub struct Spans;
+
+impl Spans {
+    #[inline(always)]
+    pub fn statement(&mut self, _request: &ClientRequest) {}
+
+    #[inline(always)]
+    pub fn checkout(&mut self) {}
+
+    #[inline(always)]
+    pub fn checked_out(
+        &mut self,
+        _route: &Route,
+        _backend: &mut Connection,
+        _wait: Duration,
+        _error: Option<&dyn Display>,
+    ) {
+    }
+
+    #[inline(always)]
+    pub fn server_message(&mut self, _message: &Message) {}
+
+    #[inline(always)]
+    pub fn transaction_done(&mut self) {}
+}
@@ -0,0 +1,274 @@
+//! OpenTelemetry span export.
+
+use std::fmt::Display;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use once_cell::sync::OnceCell;
+use opentelemetry::trace::{
+    SpanContext, SpanId, TraceContextExt, TraceFlags, TraceId, TraceState, TracerProvider,
+};
+use opentelemetry::Context;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{
+    trace::{SdkTracer, SdkTracerProvider},
+    Resource,
+};
+use tracing::{field::Empty, info, info_span, Level, Span};
+use tracing_opentelemetry::{OpenTelemetryLayer, OpenTelemetrySpanExt};
+use tracing_subscriber::{
+    filter::{Filtered, Targets},
+    layer::Layer,
+    reload, Registry,
+};
+
+use super::{Error, TraceParent};
+use crate::{
+    backend::pool::Connection,
+    config::Telemetry,
+    frontend::{router::Route, ClientRequest},
+    net::{ErrorResponse, FromBytes, Message, Protocol},
+};
+
+/// Spans are created with this target, so they don't show up in logs.
+const TARGET: &str = "pgdog::telemetry";
+
+type OtelLayer = Option<OpenTelemetryLayer<Registry, SdkTracer>>;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static HANDLE: OnceCell<reload::Handle<OtelLayer, Registry>> = OnceCell::new();
+static PROVIDER: OnceCell<SdkTracerProvider> = OnceCell::new();
+
+/// Tracing layer exporting our spans. The exporter is installed later
+/// by [`init`], once the config is loaded and Tokio is running.
+pub fn layer() -> Filtered<reload::Layer<OtelLayer, Registry>, Targets, Registry> {
+    let (layer, handle) = reload::Layer::new(None);
+    let _ = HANDLE.set(handle);
+
+    layer.with_filter(Targets::new().with_target(TARGET, Level::INFO))
+}
+
+/// Start exporting spans.
+pub fn init(config: &Telemetry) -> Result<(), Error> {
+    if !config.enabled {
+        return Ok(());
+    }
+
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_tonic()
+        .with_endpoint(&config.endpoint)
+        .build()
+        .map_err(|err| Error::Exporter(err.to_string()))?;
+
+    let provider = SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(
+            Resource::builder()
+                .with_service_name(config.service_name.clone())
+                .build(),
+        )
+        .build();
+
+    let tracer = provider.tracer("pgdog");
+    let _ = PROVIDER.set(provider);
+
+    if let Some(handle) = HANDLE.get() {
+        handle
+            .reload(Some(tracing_opentelemetry::layer().with_tracer(tracer)))
+            .map_err(|err| Error::Exporter(err.to_string()))?;
+    }
+
+    ENABLED.store(true, Ordering::Relaxed);
+    info!("exporting spans to {}", config.endpoint);
+
+    Ok(())
+}
+
+/// Flush spans before exiting.
+pub fn shutdown() {
+    ENABLED.store(false, Ordering::Relaxed);
+
+    if let Some(provider) = PROVIDER.get() {
+        let _ = provider.shutdown();
+    }
+}
+
+/// Transaction lifecycle spans.
+#[derive(Debug, Default)]
+pub struct Spans {
+    transaction: Option<Span>,
+    statement: Option<Span>,
+    checkout: Option<Span>,
+}
+
+impl Spans {
+    /// Client sent a statement. Starts the transaction span
+    /// if this is the first statement.
+    pub fn statement(&mut self, request: &ClientRequest) {
+        if !ENABLED.load(Ordering::Relaxed) {
+            return;
+        }
+
+        let query = request.query().ok().flatten();
+
+        let transaction = self.transaction.get_or_insert_with(|| {
+            let span = info_span!(
+                target: TARGET,
+                parent: None,
+                "transaction",
+                otel.name = "pgdog transaction",
+                otel.status_code = Empty,
+                otel.status_message = Empty,
+                db.system = "postgresql",
+                pgdog.shard = Empty,
+                pgdog.role = Empty,
+                server.address = Empty,
+            );
+
+            // Continue the client's trace, if it sent one.
+            if let Some(parent) = query
+                .as_ref()
+                .and_then(|query| TraceParent::from_query(query.query()))
+            {
+                span.set_parent(parent.context());
+            }
+
+            span
+        });
+
+        self.statement = Some(info_span!(
+            target: TARGET,
+            parent: &*transaction,
+            "statement",
+            otel.name = "pgdog statement",
+            otel.status_code = Empty,
+            otel.status_message = Empty,
+            db.system = "postgresql",
+            pgdog.shard = Empty,
+            pgdog.role = Empty,
+            server.address = Empty,
+        ));
+    }
+
+    /// Waiting for a connection from the pool.
+    pub fn checkout(&mut self) {
+        if let Some(ref statement) = self.statement {
+            self.checkout = Some(info_span!(
+                target: TARGET,
+                parent: statement,
+                "checkout",
+                otel.name = "pgdog checkout",
+                otel.status_code = Empty,
+                otel.status_message = Empty,
+                pgdog.wait_ms = Empty,
+            ));
+        }
+    }
+
+    /// Got a connection from the pool, or failed to.
+    pub fn checked_out(
+        &mut self,
+        route: &Route,
+        backend: &mut Connection,
+        wait: Duration,
+        error: Option<&dyn Display>,
+    ) {
+        let checkout = match self.checkout.take() {
+            Some(checkout) => checkout,
+            None => return,
+        };
+
+        checkout.record("pgdog.wait_ms", wait.as_secs_f64() * 1000.0);
+
+        let servers = backend
+            .addr()
+            .map(|addrs| {
+                addrs
+                    .into_iter()
+                    .map(|addr| format!("{}:{}", addr.host, addr.port))
+                    .collect::<Vec<_>>()
+                    .join(",")
+            })
+            .unwrap_or_default();
+        let shard = route.shard().to_string();
+        let role = if route.is_read() {
+            "replica"
+        } else {
+            "primary"
+        };
+
+        for span in [self.transaction.as_ref(), self.statement.as_ref()]
+            .into_iter()
+            .flatten()
+        {
+            span.record("pgdog.shard", shard.as_str());
+            span.record("pgdog.role", role);
+            span.record("server.address", servers.as_str());
+        }
+
+        if let Some(error) = error {
+            let message = error.to_string();
+            for span in [Some(&checkout), self.statement.as_ref()]
+                .into_iter()
+                .flatten()
+            {
+                failed(span, &message);
+            }
+        }
+    }
+
+    /// Message from the server.
+    pub fn server_message(&mut self, message: &Message) {
+        match message.code() {
+            // ErrorResponse
+            'E' => {
+                let error = ErrorResponse::from_bytes(message.payload())
+                    .map(|error| error.message)
+                    .unwrap_or_default();
+                for span in [self.transaction.as_ref(), self.statement.as_ref()]
+                    .into_iter()
+                    .flatten()
+                {
+                    failed(span, &error);
+                }
+            }
+
+            // ReadyForQuery, statement is done.
+            'Z' => self.statement = None,
+
+            _ => (),
+        }
+    }
+
+    /// Transaction finished.
+    pub fn transaction_done(&mut self) {
+        self.checkout = None;
+        self.statement = None;
+        self.transaction = None;
+    }
+}
+
+/// Set the span's status to error.
+fn failed(span: &Span, message: &str) {
+    span.record("otel.status_code", "ERROR");
+    span.record("otel.status_message", message);
+}
+
+impl TraceParent {
+    /// Context with the client's span as the remote parent.
+    fn context(&self) -> Context {
+        let flags = if self.sampled {
+            TraceFlags::SAMPLED
+        } else {
+            TraceFlags::default()
+        };
+
+        Context::new().with_remote_span_context(SpanContext::new(
+            TraceId::from_bytes(self.trace_id.to_be_bytes()),
+            SpanId::from_bytes(self.span_id.to_be_bytes()),
+            flags,
+            true,
+            TraceState::default(),
+        ))
+    }
+}
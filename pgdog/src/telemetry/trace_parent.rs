@@ -0,0 +1,92 @@
+//! W3C `traceparent` header, e.g. `00-5bd66ef5095369c7b0d1f8f4bd33716a-c532cb4098ac3dd2-01`.
+//!
+//! See <https://www.w3.org/TR/trace-context/#traceparent-header>.
+
+use crate::frontend::router::parser::comment::tags;
+
+/// Trace the client's span belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceParent {
+    pub trace_id: u128,
+    pub span_id: u64,
+    pub sampled: bool,
+}
+
+impl TraceParent {
+    /// Parse the `traceparent` value.
+    pub fn parse(value: &str) -> Option<Self> {
+        let mut parts = value.trim().split('-');
+        let version = parts.next()?;
+        let trace_id = parts.next()?;
+        let span_id = parts.next()?;
+        let flags = parts.next()?;
+
+        // Version ff is invalid; future versions may add fields.
+        if version.len() != 2 || version == "ff" || (version == "00" && parts.next().is_some()) {
+            return None;
+        }
+
+        if trace_id.len() != 32 || span_id.len() != 16 || flags.len() != 2 {
+            return None;
+        }
+
+        let trace_id = u128::from_str_radix(trace_id, 16).ok()?;
+        let span_id = u64::from_str_radix(span_id, 16).ok()?;
+        let flags = u8::from_str_radix(flags, 16).ok()?;
+
+        if trace_id == 0 || span_id == 0 {
+            return None;
+        }
+
+        Some(Self {
+            trace_id,
+            span_id,
+            sampled: flags & 1 == 1,
+        })
+    }
+
+    /// Find the `traceparent` in a sqlcommenter comment.
+    pub fn from_query(query: &str) -> Option<Self> {
+        // Skip the parser for the vast majority of queries.
+        if !query.contains("traceparent") {
+            return None;
+        }
+
+        tags(query)
+            .into_iter()
+            .find(|(key, _)| key == "traceparent")
+            .and_then(|(_, value)| Self::parse(&value))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_trace_parent() {
+        let parent =
+            TraceParent::parse("00-5bd66ef5095369c7b0d1f8f4bd33716a-c532cb4098ac3dd2-01").unwrap();
+        assert_eq!(parent.trace_id, 0x5bd66ef5095369c7b0d1f8f4bd33716a);
+        assert_eq!(parent.span_id, 0xc532cb4098ac3dd2);
+        assert!(parent.sampled);
+
+        for invalid in [
+            "",
+            "00-5bd66ef5095369c7b0d1f8f4bd33716a-c532cb4098ac3dd2",
+            "ff-5bd66ef5095369c7b0d1f8f4bd33716a-c532cb4098ac3dd2-01",
+            "00-00000000000000000000000000000000-c532cb4098ac3dd2-01",
+            "00-5bd66ef5095369c7b0d1f8f4bd33716a-0000000000000000-01",
+            "00-5bd66ef5095369c7b0d1f8f4bd33716a-c532cb4098ac3dd2-01-extra",
+            "00-xyz66ef5095369c7b0d1f8f4bd33716a-c532cb4098ac3dd2-01",
+        ] {
+            assert_eq!(TraceParent::parse(invalid), None, "{}", invalid);
+        }
+
+        let query =
+            "SELECT 1 /*traceparent='00-5bd66ef5095369c7b0d1f8f4bd33716a-c532cb4098ac3dd2-00'*/";
+        let parent = TraceParent::from_query(query).unwrap();
+        assert!(!parent.sampled);
+        assert_eq!(TraceParent::from_query("SELECT 1"), None);
+    }
+}
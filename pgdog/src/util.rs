@@ -50,6 +50,24 @@ pub fn human_duration(duration: Duration) -> String {
     }
 }
 
+/// Get a human-readable amount of bytes, e.g. 1.5 MiB.
+pub fn human_bytes(bytes: usize) -> String {
+    let units = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+
+    while value >= 1024.0 && unit < units.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, units[unit])
+    }
+}
+
 // 2000-01-01T00:00:00Z
 static POSTGRES_EPOCH: i64 = 946684800000000000;
 
@@ -75,6 +93,28 @@ pub fn escape_identifier(s: &str) -> String {
     s.replace("\"", "\"\"")
 }
 
+/// Match a name against a pattern where `*` matches any characters, e.g. `customer_*`.
+pub fn wildcard(pattern: &str, name: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let Some(mut rest) = name.strip_prefix(parts.next().unwrap_or_default()) else {
+        return false;
+    };
+
+    let parts = parts.collect::<Vec<_>>();
+    let Some((last, middle)) = parts.split_last() else {
+        return rest.is_empty();
+    };
+
+    for part in middle {
+        match rest.find(part) {
+            Some(position) => rest = &rest[position + part.len()..],
+            None => return false,
+        }
+    }
+
+    rest.ends_with(last)
+}
+
 #[cfg(test)]
 mod test {
 
@@ -88,6 +128,15 @@ mod test {
         assert_eq!(human_duration(Duration::from_millis(1000 * 3600)), "1h");
     }
 
+    #[test]
+    fn test_human_bytes() {
+        assert_eq!(human_bytes(0), "0 B");
+        assert_eq!(human_bytes(1023), "1023 B");
+        assert_eq!(human_bytes(1024), "1.0 KiB");
+        assert_eq!(human_bytes(1536), "1.5 KiB");
+        assert_eq!(human_bytes(5 * 1024 * 1024), "5.0 MiB");
+    }
+
     #[test]
     fn test_postgres_now() {
         let start = DateTime::parse_from_rfc3339("2000-01-01T00:00:00Z")
@@ -100,6 +149,25 @@ mod test {
         let _now = postgres_now();
     }
 
+    #[test]
+    fn test_wildcard() {
+        for (pattern, name, matches) in [
+            ("st_*", "st_distance", true),
+            ("st_*", "st_", true),
+            ("st_*", "xst_distance", false),
+            ("*", "anything", true),
+            ("*_hash", "crypt_hash", true),
+            ("*_hash", "crypt_hashes", false),
+            ("st_*_3d", "st_distance_3d", true),
+            ("st_*_3d", "st_3d", false),
+            ("crypt", "crypt", true),
+            ("crypt", "crypto", false),
+            ("customer_*", "Customer_1", false),
+        ] {
+            assert_eq!(wildcard(pattern, name), matches, "{} {}", pattern, name);
+        }
+    }
+
     #[test]
     fn test_escape_identifier() {
         assert_eq!(escape_identifier("simple"), "simple");
@@ -0,0 +1,9 @@
+SELECT * FROM users WHERE id = 1;
+SELECT * FROM users WHERE id = 2; SELECT 'a;b' AS value, "c;d" FROM t;
+SELECT *
+FROM orders -- comment; with a semicolon
+WHERE user_id = 5
+  AND total > 10;
+SELEC 1;
+INSERT INTO users (id) VALUES (1);
+SELECT * FROM
@@ -0,0 +1,44 @@
+//! Plugin built against an incompatible version of the FFI interface.
+//!
+//! PgDog must refuse to load it. The version can be changed to
+//! check that older, compatible versions are accepted. Used in tests only.
+//!
+
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use pgdog_plugin::PdStr;
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pgdog_rustc_version(output: *mut PdStr) {
+    let version = pgdog_plugin::comp::rustc_version();
+    unsafe {
+        *output = version;
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pgdog_plugin_version(output: *mut PdStr) {
+    let version: PdStr = env!("CARGO_PKG_VERSION").into();
+    unsafe {
+        *output = version;
+    }
+}
+
+/// ABI version reported by the plugin, if set by the test.
+static ABI_VERSION: AtomicU32 = AtomicU32::new(0);
+
+/// Pretend the plugin was built with a future version of `pgdog-plugin`,
+/// unless the test set another version.
+#[unsafe(no_mangle)]
+pub extern "C" fn pgdog_plugin_abi() -> u32 {
+    match ABI_VERSION.load(Ordering::Relaxed) {
+        0 => pgdog_plugin::comp::abi_version() + 1,
+        version => version,
+    }
+}
+
+/// Set the ABI version reported by the plugin.
+#[unsafe(no_mangle)]
+pub extern "C" fn pgdog_test_set_abi(version: u32) {
+    ABI_VERSION.store(version, Ordering::Relaxed);
+}
@@ -0,0 +1,46 @@
+use std::{env::current_exe, ops::Deref};
+
+use pgdog_plugin::{AbiError, Plugin, comp, libloading, libloading::Symbol};
+
+#[test]
+fn test_abi_version_checked() {
+    // Integration tests live in target/<profile>/deps,
+    // the shared library is in target/<profile>.
+    let exe = current_exe().unwrap();
+    let dir = exe.parent().unwrap().parent().unwrap();
+    let path = dir.join(libloading::library_filename("pgdog_abi_mismatch_plugin"));
+
+    let lib = Plugin::library(&path).unwrap();
+    let plugin = Plugin::load("pgdog_abi_mismatch_plugin", &lib);
+
+    // Version functions are safe to call.
+    assert!(plugin.rustc_version().is_some());
+    assert_eq!(plugin.version().unwrap().deref(), "0.1.0");
+    assert!(plugin.pg_query_version().is_none());
+
+    let expected = comp::abi_version();
+    assert_eq!(plugin.abi_version(), Some(expected + 1));
+    assert_eq!(
+        plugin.check_abi(),
+        Err(AbiError::Mismatch {
+            expected,
+            found: expected + 1,
+        })
+    );
+
+    let set_abi: Symbol<unsafe extern "C" fn(u32)> =
+        unsafe { lib.get(b"pgdog_test_set_abi\0") }.unwrap();
+
+    // Plugins built with ABI 3 don't return a message with the route,
+    // which defaults to empty.
+    unsafe { set_abi(3) };
+    assert_eq!(comp::min_abi_version(), 3);
+    assert_eq!(plugin.abi_version(), Some(3));
+    assert!(plugin.check_abi().is_ok());
+
+    unsafe { set_abi(2) };
+    assert_eq!(
+        plugin.check_abi(),
+        Err(AbiError::Mismatch { expected, found: 2 })
+    );
+}
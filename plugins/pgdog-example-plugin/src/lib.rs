@@ -11,8 +11,9 @@ pub mod plugin;
 use pgdog_plugin::{Context, Route, macros};
 
 // This identifies this library is a PgDog plugin and adds some
-// required methods automatically.
-macros::plugin!();
+// required methods automatically. The function, if passed, receives
+// the plugin's `config` from pgdog.toml as JSON.
+macros::plugin!(crate::plugin::configure);
 
 /// Perform any plugin initialization routines here.
 /// These are running sync on boot, and will block startup util they are finished.
@@ -31,6 +32,17 @@ fn route(context: Context) -> Route {
     crate::plugin::route_query(context).unwrap_or(Route::unknown())
 }
 
+/// If defined, this function is called with the command sent to the plugin
+/// from the admin database, e.g., `PLUGIN pgdog_example_plugin show writes`.
+///
+/// Output with tabs in the first line is shown as a table, the first line being the column names.
+/// Errors are sent to the admin client as an error.
+///
+#[macros::admin]
+fn admin(command: &str) -> Result<String, String> {
+    crate::plugin::admin(command)
+}
+
 /// Run any code before PgDog is shut down.
 ///
 /// This allows for plugins to upload stats to some external service
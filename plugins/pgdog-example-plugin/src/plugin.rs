@@ -1,12 +1,14 @@
 use std::{
     collections::HashMap,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
     time::{Duration, Instant},
 };
 
 use once_cell::sync::Lazy;
 use parking_lot::Mutex;
 use pg_query::{NodeEnum, protobuf::RangeVar};
-use pgdog_plugin::prelude::*;
+use pgdog_plugin::{config, prelude::*};
+use serde::Deserialize;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -21,6 +23,60 @@ pub enum PluginError {
 static WRITE_TIMES: Lazy<Mutex<HashMap<String, Instant>>> =
     Lazy::new(|| Mutex::new(HashMap::new()));
 
+/// Seconds after a write to a table during which reads from it go to the primary.
+static WRITE_DELAY: AtomicU64 = AtomicU64::new(5);
+
+/// Route queries to the shard of their first parameter.
+static SHARD_BY_FIRST_PARAMETER: AtomicBool = AtomicBool::new(false);
+
+/// Plugin configuration, e.g.:
+///
+/// ```toml
+/// [[plugins]]
+/// name = "pgdog_example_plugin"
+/// config = { write_delay = 10, shard_by_first_parameter = true }
+/// ```
+#[derive(Deserialize, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    #[serde(default = "Config::write_delay")]
+    write_delay: u64,
+    /// Route queries to the shard of their first parameter, assuming it's a BIGINT
+    /// sharding key. Off by default, so PgDog routes queries itself.
+    #[serde(default)]
+    shard_by_first_parameter: bool,
+}
+
+impl Config {
+    fn write_delay() -> u64 {
+        5
+    }
+
+    /// Parse the configuration. It's `null` if it was removed from `pgdog.toml`,
+    /// so we go back to the defaults.
+    fn parse(config: &str) -> Result<Self, String> {
+        let config: Option<Self> = config::parse(config).map_err(|err| err.to_string())?;
+        Ok(config.unwrap_or_default())
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            write_delay: Self::write_delay(),
+            shard_by_first_parameter: false,
+        }
+    }
+}
+
+/// Apply the configuration from `pgdog.toml`.
+pub(crate) fn configure(config: &str) -> Result<(), String> {
+    let config = Config::parse(config)?;
+    WRITE_DELAY.store(config.write_delay, Ordering::Relaxed);
+    SHARD_BY_FIRST_PARAMETER.store(config.shard_by_first_parameter, Ordering::Relaxed);
+    Ok(())
+}
+
 /// Route query to a replica or a primary, depending on when was the last time
 /// we wrote to the table.
 pub(crate) fn route_query(context: Context) -> Result<Route, PluginError> {
@@ -56,7 +112,8 @@ pub(crate) fn route_query(context: Context) -> Result<Route, PluginError> {
             if let NodeEnum::RangeVar(RangeVar { relname, .. }) = table_name {
                 // Got info on last write.
                 if let Some(last_write) = { WRITE_TIMES.lock().get(relname).cloned() }
-                    && last_write.elapsed() > Duration::from_secs(5)
+                    && last_write.elapsed()
+                        > Duration::from_secs(WRITE_DELAY.load(Ordering::Relaxed))
                     && context.has_replicas()
                 {
                     return Ok(Route::new(Shard::Unknown, ReadWrite::Read));
@@ -87,6 +144,16 @@ pub(crate) fn route_query(context: Context) -> Result<Route, PluginError> {
         _ => {}
     }
 
+    // Route to the shard picked by the application,
+    // e.g. `SET pgdog_example.shard TO '1'`.
+    if let Some(shard) = context
+        .session_param("pgdog_example.shard")
+        .and_then(|shard| shard.parse::<usize>().ok())
+        && shard < context.shards()
+    {
+        return Ok(Route::new(Shard::Direct(shard), ReadWrite::Unknown));
+    }
+
     // Get prepared statement parameters.
     let params = context.parameters();
     if params.is_empty() {
@@ -98,6 +165,16 @@ pub(crate) fn route_query(context: Context) -> Result<Route, PluginError> {
             .flatten();
         if let Some(param) = param {
             println!("Decoded parameter 0 ($1): {:?}", param);
+
+            // Use PgDog's sharding functions to find the shard
+            // for the first parameter, assuming it's a BIGINT.
+            if SHARD_BY_FIRST_PARAMETER.load(Ordering::Relaxed)
+                && context.sharded()
+                && !write_override
+                && let Some(shard) = context.shard_for_value(param, DataType::Bigint)
+            {
+                return Ok(Route::new(Shard::Direct(shard), ReadWrite::Unknown));
+            }
         }
     }
 
@@ -105,12 +182,105 @@ pub(crate) fn route_query(context: Context) -> Result<Route, PluginError> {
     Ok(Route::unknown())
 }
 
+/// Inspect and reset recorded writes, e.g., `PLUGIN pgdog_example_plugin show writes`
+/// in the admin database.
+pub(crate) fn admin(command: &str) -> Result<String, String> {
+    match command.trim().to_lowercase().as_str() {
+        "show writes" => {
+            let mut output = String::from("table\tseconds_since_write");
+            for (table, last_write) in WRITE_TIMES.lock().iter() {
+                output.push_str(&format!(
+                    "\n{}\t{:.3}",
+                    table,
+                    last_write.elapsed().as_secs_f64()
+                ));
+            }
+            Ok(output)
+        }
+        "reset writes" => {
+            WRITE_TIMES.lock().clear();
+            Ok("OK".into())
+        }
+        command => Err(format!("unknown command \"{}\"", command)),
+    }
+}
+
 #[cfg(test)]
 mod test {
-    use pgdog_plugin::{PdParameters, PdStatement};
+    use std::{os::raw::c_void, ptr::null};
+
+    use pgdog_plugin::{PdParameters, PdSessionParameter, PdSessionParameters, PdStatement, PdStr};
 
     use super::*;
 
+    /// Pretend to be PgDog and shard integers using modulo.
+    unsafe extern "C" fn shard_for_value(
+        _sharding_schema: *const c_void,
+        value: PdStr,
+        format: u8,
+        data_type: u8,
+    ) -> i64 {
+        assert_eq!(format, 0);
+        assert_eq!(data_type, u8::from(DataType::Bigint));
+        value.parse::<i64>().map(|id| id % 2).unwrap_or(-1)
+    }
+
+    #[test]
+    fn test_admin() {
+        WRITE_TIMES
+            .lock()
+            .insert("test_admin".into(), Instant::now());
+
+        let output = admin("SHOW WRITES").unwrap();
+        let mut lines = output.lines();
+        assert_eq!(lines.next(), Some("table\tseconds_since_write"));
+        assert!(lines.any(|line| line.starts_with("test_admin\t")));
+
+        assert_eq!(admin("reset writes").unwrap(), "OK");
+        assert!(WRITE_TIMES.lock().is_empty());
+
+        assert_eq!(
+            admin("drop tables").unwrap_err(),
+            "unknown command \"drop tables\""
+        );
+    }
+
+    #[test]
+    fn test_configure() {
+        assert_eq!(
+            Config::parse(r#"{"write_delay": 10}"#),
+            Ok(Config {
+                write_delay: 10,
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            Config::parse(r#"{"shard_by_first_parameter": true}"#),
+            Ok(Config {
+                shard_by_first_parameter: true,
+                ..Default::default()
+            })
+        );
+        assert_eq!(Config::parse("{}"), Ok(Config::default()));
+
+        // Configuration removed from pgdog.toml on reload.
+        assert_eq!(Config::parse("null"), Ok(Config::default()));
+
+        let error = Config::parse(r#"{"write_deley": 1}"#).unwrap_err();
+        assert!(error.contains("write_deley"), "{}", error);
+    }
+
+    #[test]
+    fn test_configure_rejected() {
+        // Rejected configuration isn't applied, and the error is returned to PgDog.
+        for json in [r#"{"write_delay": "soon"}"#, r#"{"write_deley": 1}"#, "[]"] {
+            let mut error = PdStr::default();
+            let code = unsafe { crate::pgdog_config(json.into(), &mut error) };
+            assert_eq!(code, 1, "{}", json);
+            assert!(!error.is_empty(), "{}", json);
+        }
+    }
+
     #[test]
     fn test_routing_plugin() {
         // Keep protobuf in memory.
@@ -124,6 +294,9 @@ mod test {
             write_override: 0,
             query,
             params: PdParameters::default(),
+            sharding_schema: null(),
+            shard_for_value: None,
+            session_params: PdSessionParameters::default(),
         };
         let route = route_query(context.into()).unwrap();
         let read_write: ReadWrite = route.read_write.try_into().unwrap();
@@ -132,4 +305,130 @@ mod test {
         assert_eq!(read_write, ReadWrite::Read);
         assert_eq!(shard, Shard::Unknown);
     }
+
+    #[test]
+    fn test_shard_for_value() {
+        let proto = pg_query::parse("SELECT * FROM users WHERE id = $1")
+            .unwrap()
+            .protobuf;
+        let query = unsafe { PdStatement::from_proto(&proto) };
+
+        let params = vec![Parameter {
+            len: 2,
+            data: "25".as_bytes().to_vec(),
+        }];
+        let format_codes = vec![ParameterFormat::Text];
+        let params_ffi = PdParameters {
+            num_params: params.len() as u64,
+            params: params.as_ptr() as *mut c_void,
+            num_format_codes: format_codes.len() as u64,
+            format_codes: format_codes.as_ptr() as *mut c_void,
+        };
+
+        let ffi = pgdog_plugin::PdRouterContext {
+            shards: 2,
+            has_replicas: 1,
+            has_primary: 1,
+            in_transaction: 0,
+            write_override: 0,
+            query,
+            params: params_ffi,
+            sharding_schema: null(),
+            shard_for_value: Some(shard_for_value),
+            session_params: PdSessionParameters::default(),
+        };
+        let context: Context = ffi.into();
+        assert_eq!(
+            context.shard_for_value(ParameterValue::Text("25"), DataType::Bigint),
+            Some(1)
+        );
+        assert_eq!(
+            context.shard_for_value(ParameterValue::Text("abc"), DataType::Bigint),
+            None
+        );
+
+        // PgDog routes the query, unless the plugin is configured to.
+        let route = route_query(context).unwrap();
+        let shard: Shard = route.shard.try_into().unwrap();
+        assert_eq!(shard, Shard::Unknown);
+
+        SHARD_BY_FIRST_PARAMETER.store(true, Ordering::Relaxed);
+        let route = route_query(ffi.into()).unwrap();
+        let shard: Shard = route.shard.try_into().unwrap();
+        assert_eq!(shard, Shard::Direct(1));
+
+        // Without PgDog's sharding function, the plugin can't compute the shard.
+        let context: Context = pgdog_plugin::PdRouterContext {
+            shards: 2,
+            has_replicas: 1,
+            has_primary: 1,
+            in_transaction: 0,
+            write_override: 0,
+            query,
+            params: PdParameters::default(),
+            sharding_schema: null(),
+            shard_for_value: None,
+            session_params: PdSessionParameters::default(),
+        }
+        .into();
+        assert_eq!(
+            context.shard_for_value(ParameterValue::Text("25"), DataType::Bigint),
+            None
+        );
+    }
+
+    #[test]
+    fn test_session_param() {
+        let proto = pg_query::parse("SELECT * FROM users").unwrap().protobuf;
+        let query = unsafe { PdStatement::from_proto(&proto) };
+
+        let context = |session_params: &[PdSessionParameter]| -> Context {
+            pgdog_plugin::PdRouterContext {
+                shards: 2,
+                has_replicas: 0,
+                has_primary: 1,
+                in_transaction: 0,
+                write_override: 0,
+                query,
+                params: PdParameters::default(),
+                sharding_schema: null(),
+                shard_for_value: None,
+                session_params: session_params.into(),
+            }
+            .into()
+        };
+
+        let params = [
+            PdSessionParameter {
+                name: "application_name".into(),
+                value: "test".into(),
+            },
+            PdSessionParameter {
+                name: "pgdog_example.shard".into(),
+                value: "1".into(),
+            },
+        ];
+        let ctx = context(&params);
+        assert_eq!(ctx.session_param("pgdog_example.shard"), Some("1"));
+        assert_eq!(ctx.session_param("PGDOG_EXAMPLE.SHARD"), Some("1"));
+        assert_eq!(ctx.session_param("app.tenant_id"), None);
+        assert_eq!(ctx.session_params().count(), 2);
+
+        let route = route_query(ctx).unwrap();
+        let shard: Shard = route.shard.try_into().unwrap();
+        assert_eq!(shard, Shard::Direct(1));
+
+        // Shard doesn't exist.
+        let params = [PdSessionParameter {
+            name: "pgdog_example.shard".into(),
+            value: "5".into(),
+        }];
+        let route = route_query(context(&params)).unwrap();
+        let shard: Shard = route.shard.try_into().unwrap();
+        assert_eq!(shard, Shard::Unknown);
+
+        let route = route_query(context(&[])).unwrap();
+        let shard: Shard = route.shard.try_into().unwrap();
+        assert_eq!(shard, Shard::Unknown);
+    }
 }
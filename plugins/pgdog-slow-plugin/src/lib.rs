@@ -0,0 +1,20 @@
+//! Plugin that takes too long to route queries.
+//!
+//! Used to test plugin time budgets. Used in tests only.
+//!
+
+use std::thread::sleep;
+use std::time::Duration;
+
+use pgdog_plugin::{Context, Route, macros};
+
+/// How long each routing call takes.
+pub const ROUTE_DURATION: Duration = Duration::from_millis(20);
+
+macros::plugin!();
+
+#[macros::route]
+fn route(_context: Context) -> Route {
+    sleep(ROUTE_DURATION);
+    Route::unknown()
+}
@@ -0,0 +1,67 @@
+use std::{env::current_exe, ptr::null};
+
+use pgdog::config::PluginSlowAction;
+use pgdog::plugin::{Budget, budget::SLOW_CALLS};
+use pgdog_plugin::{
+    PdParameters, PdRouterContext, PdSessionParameters, PdStatement, Plugin, libloading, pg_query,
+};
+use pgdog_slow_plugin::ROUTE_DURATION;
+
+#[test]
+fn test_slow_plugin_disabled() {
+    // Integration tests live in target/<profile>/deps,
+    // the shared library is in target/<profile>.
+    let exe = current_exe().unwrap();
+    let dir = exe.parent().unwrap().parent().unwrap();
+    let path = dir.join(libloading::library_filename("pgdog_slow_plugin"));
+
+    let lib = Plugin::library(&path).unwrap();
+    let plugin = Plugin::load("pgdog_slow_plugin", &lib);
+    assert!(plugin.check_abi().is_ok());
+
+    let proto = pg_query::parse("SELECT 1").unwrap().protobuf;
+    let context = PdRouterContext {
+        shards: 1,
+        has_replicas: 1,
+        has_primary: 1,
+        in_transaction: 0,
+        write_override: 0,
+        query: unsafe { PdStatement::from_proto(&proto) },
+        params: PdParameters::default(),
+        sharding_schema: null(),
+        shard_for_value: None,
+        session_params: PdSessionParameters::default(),
+    };
+
+    let budget = Budget::new(plugin.name());
+    let timeout = Some(ROUTE_DURATION / 4);
+
+    for _ in 0..SLOW_CALLS {
+        assert!(
+            budget
+                .route(&plugin, context, timeout, PluginSlowAction::Disable)
+                .is_some()
+        );
+    }
+
+    // Disabled plugins aren't called.
+    assert!(budget.disabled());
+    assert!(
+        budget
+            .route(&plugin, context, timeout, PluginSlowAction::Disable)
+            .is_none()
+    );
+
+    let stats = budget.stats();
+    assert_eq!(stats.calls, SLOW_CALLS as u64);
+    assert_eq!(stats.slow_calls, SLOW_CALLS as u64);
+    assert!(stats.time >= ROUTE_DURATION * SLOW_CALLS as u32);
+    assert!(stats.disabled);
+
+    // Without a budget, the plugin is only measured.
+    let budget = Budget::new(plugin.name());
+    budget.route(&plugin, context, None, PluginSlowAction::Disable);
+    assert!(!budget.disabled());
+    assert_eq!(budget.stats().slow_calls, 0);
+    assert!(budget.stats().time >= ROUTE_DURATION);
+}